@@ -0,0 +1,39 @@
+//! # Health API Module
+//!
+//! This module provides endpoints for operating the router core as a whole.
+//!
+//! ## Endpoints (Implemented)
+//!
+//! - `GET /api/v1/health` - Readiness probe with database, shared-memory and core checks (unauthenticated).
+//! - `POST /api/v1/health/shutdown` - Gracefully shuts down the router core (admin only).
+//!
+//! ## Shutdown Flow
+//!
+//! The shutdown request is forwarded to the core over the protocol server. The core
+//! replies with a drain summary, then leaves its main loop through the same cleanup
+//! path used by the Ctrl+X shortcut.
+mod shutdown;
+mod status;
+
+use actix_web::web;
+
+use super::users::{JwtAuth, RoleAuth};
+
+/// Configure health API routes
+///
+/// # Arguments
+///
+/// * `cfg` - A mutable reference to the service configuration
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/health")
+            // Probes usually can't log in, so the status check stays public
+            .service(status::init)
+            .service(
+                web::scope("/shutdown")
+                    .wrap(JwtAuth::new())
+                    .wrap(RoleAuth::admin())
+                    .service(shutdown::init),
+            ),
+    );
+}
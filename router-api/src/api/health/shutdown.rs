@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use actix_web::{post, web, HttpResponse, Responder};
+
+use crate::module::httpc::HttpC;
+
+/// Requests a graceful shutdown of the router core
+///
+/// # Endpoint
+///
+/// `POST /api/v1/health/shutdown`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// Returns the drain summary reported by the core: proxy, gateway node and gateway
+/// path counts, plus the number of log entries still queued in shared memory.
+///
+/// ## Bad Gateway (502)
+/// Returned when the core could not be reached or refused the request.
+#[post("")]
+pub async fn init(client: web::Data<Arc<HttpC>>) -> impl Responder {
+    match client.post_once_with_response("/shutdown", &[]) {
+        Ok(body) => {
+            log::info!("Core shutdown requested, drain summary: {}", body);
+            let summary = serde_json::from_str::<serde_json::Value>(&body)
+                .unwrap_or(serde_json::Value::Null);
+            HttpResponse::Ok().json(serde_json::json!({
+                "status": "shutting_down",
+                "summary": summary
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to request core shutdown: {}", e);
+            HttpResponse::BadGateway().json(
+                serde_json::json!({"error": format!("Failed to reach core: {}", e)})
+            )
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::module::{core_sync, database::get_connection, httpc::HttpC, memory_log};
+
+/// Reports overall health along with each dependency check
+///
+/// This endpoint is unauthenticated so orchestrators can use it as a readiness probe.
+///
+/// # Endpoint
+///
+/// `GET /api/v1/health`
+///
+/// # Checks
+///
+/// - `database`: SQLite is reachable and answers a trivial query
+/// - `memory_log`: the proxy and gateway shared-memory queues can be attached to
+/// - `core`: the router core answers `/status` over the protocol server
+///
+/// The state of the background push of routing data to the core is reported
+/// under `sync` (`in_sync`, `last_attempt`, `last_success`, `last_error`); it is
+/// informational and doesn't change the status code.
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// Every check passed.
+///
+/// ## Service Unavailable (503)
+/// At least one check failed; the failing components are listed in `failing`.
+#[get("")]
+pub async fn init(client: web::Data<Arc<HttpC>>) -> impl Responder {
+    let checks = [
+        ("database", check_database()),
+        ("memory_log", memory_log::check_attachable()),
+        ("core", check_core(client.as_ref())),
+    ];
+
+    let mut details = serde_json::Map::new();
+    let mut failing = Vec::new();
+    for (name, result) in checks {
+        let detail = match result {
+            Ok(()) => serde_json::json!({"status": "ok"}),
+            Err(e) => {
+                log::warn!("Health check '{}' failed: {}", name, e);
+                failing.push(name);
+                serde_json::json!({"status": "error", "error": e})
+            }
+        };
+        details.insert(name.to_string(), detail);
+    }
+
+    let sync = core_sync::status();
+    if failing.is_empty() {
+        HttpResponse::Ok().json(serde_json::json!({
+            "status": "healthy",
+            "checks": details,
+            "sync": sync
+        }))
+    } else {
+        HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "status": "unhealthy",
+            "failing": failing,
+            "checks": details,
+            "sync": sync
+        }))
+    }
+}
+
+fn check_database() -> Result<(), String> {
+    let db = get_connection().map_err(|e| e.to_string())?;
+    db.query_one("SELECT 1", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn check_core(client: &Arc<HttpC>) -> Result<(), String> {
+    client.post("/status", &[])
+}
@@ -9,10 +9,12 @@
 //!
 //! The API is organized into the following submodules:
 //!
+//! - `health`: Readiness checks and core lifecycle endpoints such as remote shutdown
 //! - `settings`: Configuration endpoints for the gateway and proxy settings
 //! - `users`: User management, authentication, and authorization
 //! - `statistics`: Performance and usage metrics collection and reporting
 //! - `sync`: Gateway and proxy node synchronization and status reporting
+//! - `openapi`: Machine-readable description of all of the above
 //!
 //! ## API Configuration
 //!
@@ -20,19 +22,22 @@
 //! Authentication is applied globally through JWT middleware, with specific permissions
 //! enforced at the individual endpoint level.
 
-mod settings;
+mod health;
+mod openapi;
+pub(crate) mod settings;
 mod statistics;
 pub mod sync;
 mod users;
 
 use actix_web::web;
-use users::init_database;
+pub use users::init_database;
 
 /// Configure and mount all API routes for the application.
 ///
 /// This function is called during application startup to register all API routes
-/// and middleware with the Actix Web service configuration. It initializes the user
-/// database and mounts all API endpoints under the `/api/v1` prefix.
+/// and middleware with the Actix Web service configuration. It mounts all API
+/// endpoints under the `/api/v1` prefix. It runs once per worker, the user
+/// database is initialized before by [`init_database`].
 ///
 /// # Arguments
 ///
@@ -46,16 +51,13 @@ use users::init_database;
 ///     .configure(api::configure)
 /// ```
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    // Initialize the users database
-    if let Err(e) = init_database() {
-        log::error!("Failed to initialize database: {}", e);
-    }
-
     cfg.service(
         web::scope("/api/v1")
             // Apply JWT authentication to all API routes
             // This middleware only verifies that the token is valid
             // Specific endpoints can enforce additional role requirements
+            .service(openapi::init)
+            .configure(health::configure)
             .configure(settings::configure)
             .configure(users::configure)
             .configure(sync::configure)
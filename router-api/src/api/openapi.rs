@@ -0,0 +1,62 @@
+//! # OpenAPI Description
+//!
+//! Serves a hand-maintained OpenAPI 3 document describing the health, users,
+//! settings, statistics and sync endpoints, their request and response bodies
+//! and the role each one requires.
+//!
+//! ## Endpoints (Implemented)
+//!
+//! - `GET /api/v1/openapi.json` - The OpenAPI document (unauthenticated).
+//!
+//! The document lives in `openapi.json` next to this file and is embedded at
+//! build time. Update it together with any route or payload change.
+
+use actix_web::{get, HttpResponse, Responder};
+
+/// The OpenAPI document, embedded at build time
+const SPEC: &str = include_str!("openapi.json");
+
+/// Returns the OpenAPI description of the REST API
+///
+/// # Endpoint
+///
+/// `GET /api/v1/openapi.json`
+///
+/// Public, so API clients and doc viewers can fetch it before logging in.
+#[get("/openapi.json")]
+pub async fn init() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(SPEC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_is_valid_json() {
+        let spec: serde_json::Value = serde_json::from_str(SPEC).unwrap();
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+
+        let paths = spec["paths"].as_object().unwrap();
+        for path in [
+            "/health",
+            "/users/login",
+            "/settings/proxy",
+            "/settings/gwnode/set",
+            "/settings/gateway/set",
+            "/statistics/default",
+            "/sync/push-all",
+        ] {
+            assert!(paths.contains_key(path), "missing {}", path);
+        }
+
+        // Every referenced schema is defined
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        for reference in SPEC.split("\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas.contains_key(name), "undefined schema {}", name);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! # Tag Permissions
+//!
+//! Proxies can carry a `tag`, e.g. the team owning them. Administrators manage
+//! everything, other users only the proxies whose tag they were given a
+//! permission for, together with the gateway nodes and gateways of those proxies:
+//!
+//! * `read` - list and fetch them
+//! * `write` - also create, change, delete and restore them and toggle maintenance
+//!
+//! Untagged proxies are for administrators only, and so is every other settings
+//! endpoint (bulk changes, import and export, reload, log level, permissions).
+//! The list endpoints leave out what the user can't read, the others answer 403.
+
+use std::collections::HashMap;
+
+use actix_web::{HttpRequest, HttpResponse};
+
+use super::permission_queries::{self, Permission, Scope};
+use super::{Gateway, GatewayNode, Proxy};
+use crate::api::users::helper::{is_admin, ClaimsFromRequest};
+use crate::module::database::DatabaseError;
+
+/// What the user of a request may do with tagged proxies
+#[derive(Debug)]
+pub struct Access {
+    /// Administrators may do everything, their permissions aren't loaded
+    admin: bool,
+    /// Scope of the user per tag
+    tags: HashMap<String, Scope>,
+}
+
+impl Access {
+    fn new(admin: bool, permissions: Vec<Permission>) -> Self {
+        Self {
+            admin,
+            tags: permissions
+                .into_iter()
+                .map(|permission| (permission.tag, permission.scope))
+                .collect(),
+        }
+    }
+
+    /// Access of the authenticated user of `req`
+    pub fn of(req: &HttpRequest) -> Result<Self, HttpResponse> {
+        let claims = req.get_claims().ok_or_else(|| {
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "Failed to get user authentication"}))
+        })?;
+        if is_admin(&claims.role) {
+            return Ok(Self::new(true, Vec::new()));
+        }
+        permission_queries::get_permissions(Some(&claims.sub))
+            .map(|permissions| Self::new(false, permissions))
+            .map_err(database_error)
+    }
+
+    /// Whether the user may `scope` the proxies tagged `tag`
+    pub fn allows(&self, tag: Option<&str>, scope: Scope) -> bool {
+        self.admin || tag.and_then(|tag| self.tags.get(tag)).is_some_and(|held| *held >= scope)
+    }
+
+    /// Refuses the request unless the user may `scope` the proxies tagged `tag`
+    pub fn check_tag(&self, tag: Option<&str>, scope: Scope) -> Result<(), HttpResponse> {
+        if self.allows(tag, scope) {
+            return Ok(());
+        }
+        let error = match tag {
+            Some(tag) => format!("You need the {} permission for tag '{}'", scope, tag),
+            None => "Only administrators can access untagged proxies".to_string(),
+        };
+        Err(HttpResponse::Forbidden().json(serde_json::json!({ "error": error })))
+    }
+
+    /// Refuses the request unless the user may `scope` the proxy `id`.
+    ///
+    /// Unknown IDs pass, the handler answers them as it does for administrators.
+    pub fn check_proxy(&self, id: &str, scope: Scope) -> Result<(), HttpResponse> {
+        self.check(|| permission_queries::tag_of_proxy(id), scope)
+    }
+
+    /// Refuses the request unless the user may `scope` the proxy of gateway node `id`
+    pub fn check_gateway_node(&self, id: &str, scope: Scope) -> Result<(), HttpResponse> {
+        self.check(|| permission_queries::tag_of_gateway_node(id), scope)
+    }
+
+    /// Refuses the request unless the user may `scope` the proxy of gateway `id`
+    pub fn check_gateway(&self, id: &str, scope: Scope) -> Result<(), HttpResponse> {
+        self.check(|| permission_queries::tag_of_gateway(id), scope)
+    }
+
+    fn check(
+        &self,
+        tag_of: impl FnOnce() -> Result<Option<Option<String>>, DatabaseError>,
+        scope: Scope,
+    ) -> Result<(), HttpResponse> {
+        if self.admin {
+            return Ok(());
+        }
+        match tag_of().map_err(database_error)? {
+            Some(tag) => self.check_tag(tag.as_deref(), scope),
+            None => Ok(()),
+        }
+    }
+
+    /// Leaves out the proxies the user can't read
+    pub fn filter_proxies(&self, proxies: &mut Vec<Proxy>) {
+        proxies.retain(|proxy| self.allows(proxy.tag.as_deref(), Scope::Read));
+    }
+
+    /// Leaves out the gateway nodes the user can't read
+    pub fn filter_gateway_nodes(&self, nodes: &mut Vec<GatewayNode>) -> Result<(), DatabaseError> {
+        if self.admin {
+            return Ok(());
+        }
+        let tags = permission_queries::proxy_tags()?;
+        nodes.retain(|node| self.allows(tags.get(&node.proxy_id).and_then(Option::as_deref), Scope::Read));
+        Ok(())
+    }
+
+    /// Leaves out the gateways the user can't read
+    pub fn filter_gateways(&self, gateways: &mut Vec<Gateway>) -> Result<(), DatabaseError> {
+        if self.admin {
+            return Ok(());
+        }
+        let tags = permission_queries::gateway_node_tags()?;
+        gateways.retain(|gateway| {
+            self.allows(tags.get(&gateway.gwnode_id).and_then(Option::as_deref), Scope::Read)
+        });
+        Ok(())
+    }
+}
+
+fn database_error(e: DatabaseError) -> HttpResponse {
+    log::error!("Failed to check permissions: {}", e);
+    HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to check permissions"}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permission(tag: &str, scope: Scope) -> Permission {
+        Permission {
+            user_id: "u1".to_string(),
+            tag: tag.to_string(),
+            scope,
+        }
+    }
+
+    #[test]
+    fn test_allows() {
+        let access = Access::new(false, vec![permission("team-a", Scope::Write), permission("team-b", Scope::Read)]);
+        assert!(access.allows(Some("team-a"), Scope::Read));
+        assert!(access.allows(Some("team-a"), Scope::Write));
+        assert!(access.allows(Some("team-b"), Scope::Read));
+        assert!(!access.allows(Some("team-b"), Scope::Write));
+        assert!(!access.allows(Some("team-c"), Scope::Read));
+        // Tags are compared exactly
+        assert!(!access.allows(Some("Team-A"), Scope::Read));
+        // Untagged proxies are for administrators only
+        assert!(!access.allows(None, Scope::Read));
+
+        let admin = Access::new(true, Vec::new());
+        assert!(admin.allows(None, Scope::Write));
+        assert!(admin.allows(Some("team-c"), Scope::Write));
+    }
+}
@@ -4,17 +4,17 @@
 //! It allows for bulk operations through a single API call, making it easier to set up and manage
 //! gateway configurations.
 
-use std::sync::{Arc, Mutex};
 
 use actix_web::{post, get, web, HttpResponse, Responder, HttpRequest};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use crate::{api::users::helper::{is_staff_or_admin, ClaimsFromRequest}, module::httpc::HttpC};
+use crate::module::netaddr;
 use super::{
-    Proxy, ProxyDomain, GatewayNode, Gateway,
+    Proxy, ProxyDomain, GatewayNode, Gateway, SplitTarget,
     proxy_queries, proxydomain_queries, gwnode_queries, gateway_queries
 };
-use crate::sync;
+use super::rule_validation;
+use super::gwnode_set::normalize_cidrs;
 
 /// Structure representing a domain in the YAML configuration
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,6 +30,9 @@ pub struct YamlDomain {
     /// TLS private key content
     #[serde(default)]
     pub tls_key: Option<String>,
+    /// Obtain and renew the certificate over ACME
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub acme: bool,
 }
 
 /// Structure representing a gateway path in the YAML configuration
@@ -37,10 +40,81 @@ pub struct YamlDomain {
 pub struct YamlPath {
     /// Priority of the path (lower number = higher priority)
     pub priority: i32,
-    /// Pattern for URL matching
+    /// Pattern for URL matching, may be empty when `strip_prefix` is set
+    #[serde(default)]
     pub pattern: String,
-    /// Target where matching requests should be routed
+    /// Target where matching requests should be routed, or `static` for an inline response
+    #[serde(default)]
     pub target: String,
+    /// Literal prefix removed before `pattern` is matched, e.g. "/api"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip_prefix: Option<String>,
+    /// Inline response served by the gateway when `target` is `static`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<YamlStaticResponse>,
+    /// Session affinity, `cookie` or `ip`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sticky: Option<String>,
+    /// Targets receiving a percentage of the path's requests
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub split: Vec<SplitTarget>,
+    /// Backend receiving a copy of the path's requests, `host:port`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror_target: Option<String>,
+}
+
+/// Inline response of a `static` gateway path, every field falls back to the core default
+/// (503, `text/html`, the default page)
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct YamlStaticResponse {
+    /// HTTP status code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Value of the Content-Type header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Response body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+/// Target value of gateway paths answered by the gateway itself
+const STATIC_TARGET: &str = "static";
+
+impl YamlPath {
+    /// Target as stored in the gateways table.
+    ///
+    /// A `static` target with a `response` is stored as `static:<json>`, the
+    /// form the core parses, so no extra columns are needed.
+    fn stored_target(&self) -> String {
+        match &self.response {
+            Some(response) if self.target == STATIC_TARGET => format!(
+                "{}:{}",
+                STATIC_TARGET,
+                serde_json::to_string(response).unwrap_or_else(|_| "{}".to_string())
+            ),
+            _ => self.target.clone(),
+        }
+    }
+
+    /// Inverse of [`YamlPath::stored_target`]
+    fn from_gateway(gateway: &Gateway) -> Self {
+        let response = gateway
+            .target
+            .strip_prefix(STATIC_TARGET)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .and_then(|json| serde_json::from_str::<YamlStaticResponse>(json).ok());
+        YamlPath {
+            priority: gateway.priority,
+            pattern: gateway.pattern.clone(),
+            target: if response.is_some() { STATIC_TARGET.to_string() } else { gateway.target.clone() },
+            strip_prefix: gateway.strip_prefix.clone(),
+            response,
+            sticky: gateway.sticky.clone(),
+            split: gateway.split.clone(),
+            mirror_target: gateway.mirror_target.clone(),
+        }
+    }
 }
 
 /// Structure representing a gateway in the YAML configuration
@@ -54,6 +128,48 @@ pub struct YamlGateway {
     pub target: String,
     /// Paths configured for this gateway
     pub path: Vec<YamlPath>,
+    /// Whether responses of this gateway are compressed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compress: bool,
+    /// Client networks allowed to use this gateway, empty allows everyone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_cidrs: Vec<String>,
+    /// Client networks refused by this gateway
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny_cidrs: Vec<String>,
+    /// Upstream connect timeout in seconds, `0` disables it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u32>,
+    /// Upstream header and idle read timeout in seconds, `0` disables it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_timeout_secs: Option<u32>,
+    /// Total response timeout in seconds, `0` disables it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_timeout_secs: Option<u32>,
+    /// Dotted JSON fields redacted from this gateway's JSON responses
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redact_fields: Vec<String>,
+    /// `mask` or `remove`, unset masks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redact_mode: Option<String>,
+    /// Rhai script deciding per request whether and where this gateway's paths route
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_script: Option<String>,
+    /// Origins whose CORS preflights the gateway answers, `*` allows any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cors_origins: Vec<String>,
+    /// Methods allowed in CORS preflights
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cors_methods: Vec<String>,
+    /// Request headers allowed in CORS preflights
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cors_headers: Vec<String>,
+    /// `Server` header sent instead of the upstream's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_header: Option<String>,
+    /// Whether the upstream's `Server` and `X-Powered-By` headers are removed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip_server_headers: bool,
 }
 
 /// Structure representing highspeed configuration in the YAML
@@ -65,6 +181,20 @@ pub struct YamlHighspeed {
     pub target: String,
 }
 
+/// TLS policy of a proxy in the YAML configuration, see [`Proxy`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct YamlTlsPolicy {
+    /// Lowest accepted TLS version, `1.2` or `1.3`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_version: Option<String>,
+    /// Colon separated accepted ciphers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ciphers: Option<String>,
+    /// Whether TLS clients without SNI are refused
+    #[serde(default)]
+    pub require_sni: bool,
+}
+
 /// Structure representing a proxy in the YAML configuration
 #[derive(Debug, Serialize, Deserialize)]
 pub struct YamlProxy {
@@ -76,6 +206,15 @@ pub struct YamlProxy {
     pub domains: Vec<YamlDomain>,
     /// Highspeed configuration
     pub highspeed: Option<YamlHighspeed>,
+    /// TLS policy, the core's secure defaults when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_policy: Option<YamlTlsPolicy>,
+    /// Target of requests no gateway rule matches, see [`Proxy`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    /// Tag users are given permissions for, see [`Proxy`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
     /// Gateways associated with this proxy
     pub gateway: Vec<YamlGateway>,
 }
@@ -87,63 +226,242 @@ pub struct YamlConfig {
     pub proxy: Vec<YamlProxy>,
 }
 
+/// Format of an uploaded configuration document
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConfigFormat {
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Detects the format from the Content-Type, falling back to the document itself.
+    ///
+    /// A body starting with `{` is taken as JSON, anything else as YAML.
+    fn detect(content_type: Option<&str>, body: &[u8]) -> Self {
+        let content_type = content_type.unwrap_or_default().to_ascii_lowercase();
+        if content_type.contains("json") {
+            return ConfigFormat::Json;
+        }
+        if content_type.contains("yaml") {
+            return ConfigFormat::Yaml;
+        }
+        match body.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'{') => ConfigFormat::Json,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ConfigFormat::Yaml => "YAML",
+            ConfigFormat::Json => "JSON",
+        }
+    }
+
+    /// Parses a configuration document, both formats share the same schema
+    fn parse(self, body: &[u8]) -> Result<YamlConfig, String> {
+        match self {
+            ConfigFormat::Yaml => serde_yaml::from_slice(body).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_slice(body).map_err(|e| e.to_string()),
+        }
+    }
+}
+
 /// Uploads a configuration file and applies it to the system
 ///
-/// This endpoint processes an uploaded YAML configuration file and creates
+/// This endpoint processes an uploaded YAML or JSON configuration file and creates
 /// the corresponding proxy, domain, gateway node, and gateway configurations.
 ///
 /// # Endpoint
 ///
 /// `POST /api/v1/auto-config`
 ///
+/// # Query Parameters
+///
+/// - `strict`: reject the upload instead of warning when rule validation finds issues
+///
 /// # Request Body
 ///
-/// The request body should be a YAML document conforming to the configuration schema.
+/// The request body should be a YAML or JSON document conforming to the configuration schema.
+/// The format is taken from the `Content-Type` (`application/json`, `application/yaml`);
+/// without one, a body starting with `{` is parsed as JSON and anything else as YAML.
 ///
 /// # Response
 ///
 /// ## Success (200 OK)
-/// Returns a summary of the created resources.
+/// Returns a summary of the created resources, plus `warnings` for rules sharing a
+/// priority on the same listener or a rule count above `GWRS_MAX_GATEWAY_RULES`.
 ///
 /// ## Bad Request (400)
-/// Returned when the YAML is invalid or configuration conflicts with existing resources.
+/// Returned when the document is invalid, configuration conflicts with existing resources,
+/// or validation found issues in strict mode.
 ///
 /// ## Forbidden (403)
-/// Returned when the user doesn't have admin or staff privileges.
+/// Returned when the user isn't an administrator.
 #[post("/auto-config")]
 pub async fn upload_config(
     req: HttpRequest,
     body: web::Bytes,
-    client: web::Data<Arc<Mutex<HttpC>>>
 ) -> impl Responder {
-    let client = client.as_ref();
-    // Extract authenticated user's claims
-    let claims = match req.get_claims() {
-        Some(claims) => claims,
-        None => {
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Failed to get user authentication"})
-            )
-        }
-    };
-    
-    // Verify user has admin or staff role
-    if !is_staff_or_admin(&claims.role) {
-        return HttpResponse::Forbidden().json(
-            serde_json::json!({"error": "Only administrators and staff can upload configurations"})
-        );
-    }
-    
-    // Parse YAML configuration
-    let config: YamlConfig = match serde_yaml::from_slice(&body) {
+    // Parse the YAML or JSON configuration
+    let content_type = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    let format = ConfigFormat::detect(content_type, &body);
+    let config: YamlConfig = match format.parse(&body) {
         Ok(config) => config,
         Err(e) => {
             return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": format!("Invalid YAML configuration: {}", e)})
+                serde_json::json!({"error": format!("Invalid {} configuration: {}", format.name(), e)})
             )
         }
     };
 
+    // Validate listen and target addresses before touching existing configuration,
+    // IPv6 literals must be bracketed (e.g. "[::1]:8080"), Unix sockets
+    // ("unix:/path/to.sock") are only served in speed mode
+    for yaml_proxy in &config.proxy {
+        let high_speed = yaml_proxy.highspeed.as_ref().map_or(false, |hs| hs.enabled);
+        let unix_listen = high_speed && netaddr::unix_socket_path(&yaml_proxy.listen).is_some();
+        if !unix_listen && netaddr::split_host_port(&yaml_proxy.listen).is_none() {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid listen address '{}' for proxy '{}'", yaml_proxy.listen, yaml_proxy.name)
+            }));
+        }
+        if let Some(policy) = &yaml_proxy.tls_policy {
+            if let Err(e) = rule_validation::normalize_tls_min_version(policy.min_version.as_deref().unwrap_or_default())
+                .and_then(|_| rule_validation::normalize_tls_ciphers(policy.ciphers.as_deref().unwrap_or_default()))
+            {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid TLS policy of proxy '{}': {}", yaml_proxy.name, e)
+                }));
+            }
+        }
+        if let Err(e) = rule_validation::normalize_fallback(yaml_proxy.fallback.as_deref().unwrap_or_default()) {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid fallback of proxy '{}': {}", yaml_proxy.name, e)
+            }));
+        }
+        if let Err(e) = rule_validation::normalize_tag(yaml_proxy.tag.as_deref().unwrap_or_default()) {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid tag of proxy '{}': {}", yaml_proxy.name, e)
+            }));
+        }
+        let tls = yaml_proxy.domains.iter().any(|domain| domain.tls || domain.acme);
+        if let Err(e) = rule_validation::check_unix_listen_tls(&yaml_proxy.listen, tls) {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid proxy '{}': {}", yaml_proxy.name, e)
+            }));
+        }
+        for yaml_domain in yaml_proxy.domains.iter().filter(|domain| domain.acme) {
+            if let Err(e) = rule_validation::acme_names(&yaml_domain.domain) {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid ACME domain '{}' of proxy '{}': {}", yaml_domain.domain, yaml_proxy.name, e)
+                }));
+            }
+        }
+        for yaml_gateway in &yaml_proxy.gateway {
+            if let Err(e) = netaddr::normalize_target(&yaml_gateway.target) {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid target address for gateway '{}': {}", yaml_gateway.name, e)
+                }));
+            }
+            for cidrs in [&yaml_gateway.allow_cidrs, &yaml_gateway.deny_cidrs] {
+                if let Err(e) = normalize_cidrs(cidrs) {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("Invalid access list of gateway '{}': {}", yaml_gateway.name, e)
+                    }));
+                }
+            }
+            if let Err(e) = rule_validation::normalize_redact_fields(&yaml_gateway.redact_fields).and_then(|_| {
+                rule_validation::normalize_redact_mode(yaml_gateway.redact_mode.as_deref().unwrap_or_default())
+            }) {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid redaction of gateway '{}': {}", yaml_gateway.name, e)
+                }));
+            }
+            if let Err(e) = rule_validation::normalize_route_script(yaml_gateway.route_script.as_deref().unwrap_or_default()) {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid route script of gateway '{}': {}", yaml_gateway.name, e)
+                }));
+            }
+            if let Err(e) = rule_validation::normalize_cors_origins(&yaml_gateway.cors_origins)
+                .and_then(|_| rule_validation::normalize_cors_methods(&yaml_gateway.cors_methods))
+                .and_then(|_| rule_validation::normalize_cors_headers(&yaml_gateway.cors_headers))
+            {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid CORS settings of gateway '{}': {}", yaml_gateway.name, e)
+                }));
+            }
+            if let Err(e) = rule_validation::normalize_server_header(yaml_gateway.server_header.as_deref().unwrap_or_default()) {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid server header of gateway '{}': {}", yaml_gateway.name, e)
+                }));
+            }
+            for yaml_path in &yaml_gateway.path {
+                if let Err(e) = rule_validation::normalize_strip_prefix(
+                    yaml_path.strip_prefix.as_deref().unwrap_or_default(),
+                ) {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("Invalid path in gateway '{}': {}", yaml_gateway.name, e)
+                    }));
+                }
+                if let Err(e) = rule_validation::normalize_sticky(yaml_path.sticky.as_deref().unwrap_or_default()) {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("Invalid path in gateway '{}': {}", yaml_gateway.name, e)
+                    }));
+                }
+                if let Err(e) = rule_validation::normalize_split(&yaml_path.split) {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("Invalid path in gateway '{}': {}", yaml_gateway.name, e)
+                    }));
+                }
+                if let Err(e) = rule_validation::normalize_mirror_target(
+                    yaml_path.mirror_target.as_deref().unwrap_or_default(),
+                ) {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("Invalid path in gateway '{}': {}", yaml_gateway.name, e)
+                    }));
+                }
+                if yaml_path.pattern.is_empty() && yaml_path.strip_prefix.is_none() {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("Path of gateway '{}' needs a pattern or a strip_prefix", yaml_gateway.name)
+                    }));
+                }
+                if yaml_path.response.is_some() && yaml_path.target != STATIC_TARGET {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!(
+                            "Path '{}' of gateway '{}' has a response but its target is not '{}'",
+                            yaml_path.pattern, yaml_gateway.name, STATIC_TARGET
+                        )
+                    }));
+                }
+                if let Some(status) = yaml_path.response.as_ref().and_then(|r| r.status) {
+                    if !(100..=599).contains(&status) {
+                        return HttpResponse::BadRequest().json(serde_json::json!({
+                            "error": format!("Invalid response status {} for path '{}'", status, yaml_path.pattern)
+                        }));
+                    }
+                }
+            }
+        }
+    }
+
+    // Check rule count and priority collisions, only rejecting in strict mode
+    let warnings = rule_validation::check_yaml_config(&config, crate::config::max_gateway_rules());
+    if !warnings.is_empty() {
+        if is_strict(req.query_string()) {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Gateway rule validation failed",
+                "warnings": warnings
+            }));
+        }
+        for warning in &warnings {
+            log::warn!("Config upload: {}", warning);
+        }
+    }
+
     // Delete all existing configurations
     // First delete all gateways
     if let Err(e) = gateway_queries::delete_all_gateways() {
@@ -182,6 +500,7 @@ pub async fn upload_config(
     for yaml_proxy in config.proxy {
         // Create proxy
         let proxy_id = Uuid::new_v4().to_string();
+        let tls_policy = yaml_proxy.tls_policy.as_ref();
         let mut proxy = Proxy {
             id: proxy_id.clone(),
             title: yaml_proxy.name.clone(),
@@ -197,6 +516,30 @@ pub async fn upload_config(
             high_speed: yaml_proxy.highspeed.as_ref().map_or(false, |hs| hs.enabled),
             high_speed_addr: None,
             high_speed_gwid: None,
+            redirect_to_https: false,
+            redirect_https_port: None,
+            deleted_at: None,
+            tcp_nodelay: true,
+            keepalive_secs: None,
+            keepalive_count: None,
+            buffer_size: None,
+            maintenance: false,
+            // Validated above
+            tls_min_version: tls_policy
+                .and_then(|policy| policy.min_version.as_deref())
+                .and_then(|version| rule_validation::normalize_tls_min_version(version).ok().flatten()),
+            tls_ciphers: tls_policy
+                .and_then(|policy| policy.ciphers.as_deref())
+                .and_then(|ciphers| rule_validation::normalize_tls_ciphers(ciphers).ok().flatten()),
+            tls_require_sni: tls_policy.map_or(false, |policy| policy.require_sni),
+            fallback: yaml_proxy
+                .fallback
+                .as_deref()
+                .and_then(|fallback| rule_validation::normalize_fallback(fallback).ok().flatten()),
+            tag: yaml_proxy
+                .tag
+                .as_deref()
+                .and_then(|tag| rule_validation::normalize_tag(tag).ok().flatten()),
         };
         
         // Save proxy
@@ -214,10 +557,11 @@ pub async fn upload_config(
             let domain = ProxyDomain {
                 id: domain_id.clone(),
                 proxy_id: Some(proxy_id.clone()),
-                tls: yaml_domain.tls,
+                tls: yaml_domain.tls || yaml_domain.acme,
                 tls_pem: yaml_domain.tls_cert.clone(),
                 tls_key: yaml_domain.tls_key.clone(),
                 sni: Some(yaml_domain.domain.clone()),
+                acme: yaml_domain.acme,
             };
             
             // Save domain
@@ -240,10 +584,28 @@ pub async fn upload_config(
                 id: gwnode_id.clone(),
                 proxy_id: proxy_id.clone(),
                 title: yaml_gateway.name.clone(),
-                alt_target: yaml_gateway.target.clone(),
+                alt_target: netaddr::normalize_target(&yaml_gateway.target).unwrap_or_else(|_| yaml_gateway.target.clone()),
                 priority: 100, // Default priority
                 domain_id,
                 domain_name: Some(yaml_gateway.domain.clone()),
+                compress: yaml_gateway.compress,
+                allow_cidrs: normalize_cidrs(&yaml_gateway.allow_cidrs).unwrap_or_default(),
+                deny_cidrs: normalize_cidrs(&yaml_gateway.deny_cidrs).unwrap_or_default(),
+                connect_timeout_secs: yaml_gateway.connect_timeout_secs,
+                header_timeout_secs: yaml_gateway.header_timeout_secs,
+                total_timeout_secs: yaml_gateway.total_timeout_secs,
+                maintenance: false,
+                redact_fields: rule_validation::normalize_redact_fields(&yaml_gateway.redact_fields).unwrap_or_default(),
+                redact_mode: rule_validation::normalize_redact_mode(yaml_gateway.redact_mode.as_deref().unwrap_or_default())
+                    .unwrap_or_default(),
+                route_script: rule_validation::normalize_route_script(yaml_gateway.route_script.as_deref().unwrap_or_default())
+                    .unwrap_or_default(),
+                cors_origins: rule_validation::normalize_cors_origins(&yaml_gateway.cors_origins).unwrap_or_default(),
+                cors_methods: rule_validation::normalize_cors_methods(&yaml_gateway.cors_methods).unwrap_or_default(),
+                cors_headers: rule_validation::normalize_cors_headers(&yaml_gateway.cors_headers).unwrap_or_default(),
+                server_header: rule_validation::normalize_server_header(yaml_gateway.server_header.as_deref().unwrap_or_default())
+                    .unwrap_or_default(),
+                strip_server_headers: yaml_gateway.strip_server_headers,
             };
             
             // Save gateway node
@@ -262,8 +624,20 @@ pub async fn upload_config(
                     id: gateway_id,
                     gwnode_id: gwnode_id.clone(),
                     pattern: yaml_path.pattern.clone(),
-                    target: yaml_path.target.clone(),
+                    target: yaml_path.stored_target(),
                     priority: yaml_path.priority,
+                    strip_prefix: rule_validation::normalize_strip_prefix(
+                        yaml_path.strip_prefix.as_deref().unwrap_or_default(),
+                    )
+                    .unwrap_or_default(),
+                    deleted_at: None,
+                    sticky: rule_validation::normalize_sticky(yaml_path.sticky.as_deref().unwrap_or_default())
+                        .unwrap_or_default(),
+                    split: rule_validation::normalize_split(&yaml_path.split).unwrap_or_default(),
+                    mirror_target: rule_validation::normalize_mirror_target(
+                        yaml_path.mirror_target.as_deref().unwrap_or_default(),
+                    )
+                    .unwrap_or_default(),
                 };
                 
                 // Save gateway
@@ -311,22 +685,6 @@ pub async fn upload_config(
         }
     }
     
-    // Add sync calls after successful configuration
-    match sync::gateway_node_tcp::sync_gateway_paths_to_registry(client).await {
-        Ok(_) => log::info!("Successfully synced gateway paths to registry"),
-        Err(e) => log::warn!("Failed to sync gateway paths to registry: {:?}. Continuing anyway.", e),
-    }
-    
-    match sync::proxy_node_tcp::sync_proxy_nodes_to_registry(client).await {
-        Ok(_) => log::info!("Successfully synced proxy nodes to registry"),
-        Err(e) => log::warn!("Failed to sync proxy nodes to registry: {:?}. Continuing anyway.", e),
-    }
-
-    match sync::gateway_node_tcp::sync_gateway_nodes_to_registry(client).await {
-        Ok(_) => log::info!("Successfully synced gateway nodes to registry"),
-        Err(e) => log::warn!("Failed to sync gateway nodes to registry: {:?}. Continuing anyway.", e),
-    }
-    
     HttpResponse::Ok().json(serde_json::json!({
         "success": true,
         "created": {
@@ -334,10 +692,19 @@ pub async fn upload_config(
             "domains": created_domains.len(),
             "gwnodes": created_gwnodes.len(),
             "gateways": created_gateways.len()
-        }
+        },
+        "warnings": warnings
     }))
 }
 
+/// Whether the query string asks for strict validation (`?strict`, `?strict=true` or `?strict=1`)
+fn is_strict(query: &str) -> bool {
+    query.split('&').any(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        key == "strict" && matches!(value, "" | "true" | "1")
+    })
+}
+
 /// Downloads the current configuration as a YAML file
 ///
 /// This endpoint exports all proxy, domain, gateway node, and gateway configurations
@@ -353,26 +720,9 @@ pub async fn upload_config(
 /// Returns a YAML document containing the full configuration.
 ///
 /// ## Forbidden (403)
-/// Returned when the user doesn't have admin or staff privileges.
+/// Returned when the user isn't an administrator.
 #[get("/auto-config")]
-pub async fn download_config(req: HttpRequest) -> impl Responder {
-    // Extract authenticated user's claims
-    let claims = match req.get_claims() {
-        Some(claims) => claims,
-        None => {
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Failed to get user authentication"})
-            )
-        }
-    };
-    
-    // Verify user has admin or staff role
-    if !is_staff_or_admin(&claims.role) {
-        return HttpResponse::Forbidden().json(
-            serde_json::json!({"error": "Only administrators and staff can download configurations"})
-        );
-    }
-    
+pub async fn download_config() -> impl Responder {
     // Retrieve all proxies
     let proxies = match proxy_queries::get_all_proxies() {
         Ok(proxies) => proxies,
@@ -403,6 +753,7 @@ pub async fn download_config(req: HttpRequest) -> impl Responder {
             tls: domain.tls,
             tls_cert: domain.tls_pem.clone(),
             tls_key: domain.tls_key.clone(),
+            acme: domain.acme,
         }).collect::<Vec<_>>();
         
         // Get gateway nodes for this proxy
@@ -429,11 +780,7 @@ pub async fn download_config(req: HttpRequest) -> impl Responder {
             };
             
             // Convert paths to YAML format
-            let yaml_paths = gateways.iter().map(|gateway| YamlPath {
-                priority: gateway.priority,
-                pattern: gateway.pattern.clone(),
-                target: gateway.target.clone(),
-            }).collect::<Vec<_>>();
+            let yaml_paths = gateways.iter().map(YamlPath::from_gateway).collect::<Vec<_>>();
             
             // Add gateway to list
             if !yaml_paths.is_empty() {
@@ -442,6 +789,20 @@ pub async fn download_config(req: HttpRequest) -> impl Responder {
                     domain: gwnode.domain_name.clone().unwrap_or_default(),
                     target: gwnode.alt_target.clone(),
                     path: yaml_paths,
+                    compress: gwnode.compress,
+                    allow_cidrs: gwnode.allow_cidrs.clone(),
+                    deny_cidrs: gwnode.deny_cidrs.clone(),
+                    connect_timeout_secs: gwnode.connect_timeout_secs,
+                    header_timeout_secs: gwnode.header_timeout_secs,
+                    total_timeout_secs: gwnode.total_timeout_secs,
+                    redact_fields: gwnode.redact_fields.clone(),
+                    redact_mode: gwnode.redact_mode.clone(),
+                    route_script: gwnode.route_script.clone(),
+                    cors_origins: gwnode.cors_origins.clone(),
+                    cors_methods: gwnode.cors_methods.clone(),
+                    cors_headers: gwnode.cors_headers.clone(),
+                    server_header: gwnode.server_header.clone(),
+                    strip_server_headers: gwnode.strip_server_headers,
                 });
             }
         }
@@ -466,12 +827,23 @@ pub async fn download_config(req: HttpRequest) -> impl Responder {
             None
         };
         
+        // The TLS policy is only written when it differs from the defaults
+        let yaml_tls_policy = (proxy.tls_min_version.is_some() || proxy.tls_ciphers.is_some() || proxy.tls_require_sni)
+            .then(|| YamlTlsPolicy {
+                min_version: proxy.tls_min_version.clone(),
+                ciphers: proxy.tls_ciphers.clone(),
+                require_sni: proxy.tls_require_sni,
+            });
+
         // Add proxy to list
         yaml_proxies.push(YamlProxy {
             name: proxy.title,
             listen: proxy.addr_listen,
             domains: yaml_domains,
             highspeed: yaml_highspeed,
+            tls_policy: yaml_tls_policy,
+            fallback: proxy.fallback,
+            tag: proxy.tag,
             gateway: yaml_gateways,
         });
     }
@@ -495,4 +867,85 @@ pub async fn download_config(req: HttpRequest) -> impl Responder {
         .content_type("application/yaml")
         .append_header(("Content-Disposition", "attachment; filename=\"gateway-config.yaml\""))
         .body(yaml_str)
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gateway(target: String) -> Gateway {
+        Gateway {
+            id: "g1".to_string(),
+            gwnode_id: "n1".to_string(),
+            pattern: "/api/*".to_string(),
+            target,
+            priority: 1,
+            strip_prefix: None,
+            deleted_at: None,
+            sticky: None,
+            split: Vec::new(),
+            mirror_target: None,
+        }
+    }
+
+    #[test]
+    fn test_static_response_round_trip() {
+        let path = YamlPath {
+            priority: 1,
+            pattern: "/api/*".to_string(),
+            target: STATIC_TARGET.to_string(),
+            strip_prefix: None,
+            sticky: None,
+            split: Vec::new(),
+            mirror_target: None,
+            response: Some(YamlStaticResponse {
+                status: Some(503),
+                content_type: Some("application/json".to_string()),
+                body: Some(r#"{"status":"maintenance"}"#.to_string()),
+            }),
+        };
+        let stored = path.stored_target();
+        assert!(stored.starts_with("static:{"));
+
+        let restored = YamlPath::from_gateway(&gateway(stored));
+        assert_eq!(restored.target, STATIC_TARGET);
+        assert_eq!(restored.response, path.response);
+    }
+
+    #[test]
+    fn test_regular_targets_are_stored_as_is() {
+        let path = YamlPath {
+            priority: 1,
+            pattern: "^/static/(.*)$".to_string(),
+            target: "/$1".to_string(),
+            strip_prefix: None,
+            response: None,
+            sticky: None,
+            split: Vec::new(),
+            mirror_target: None,
+        };
+        assert_eq!(path.stored_target(), "/$1");
+        assert_eq!(YamlPath::from_gateway(&gateway("static".to_string())).response, None);
+        assert_eq!(YamlPath::from_gateway(&gateway("/static/x".to_string())).target, "/static/x");
+    }
+
+    #[test]
+    fn test_config_format_detection() {
+        assert_eq!(ConfigFormat::detect(Some("application/json"), b"proxy: []"), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::detect(Some("application/yaml"), b"{}"), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::detect(None, b"  \n{\"proxy\": []}"), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::detect(Some("text/plain"), b"proxy: []"), ConfigFormat::Yaml);
+    }
+
+    #[test]
+    fn test_json_and_yaml_share_the_schema() {
+        let yaml = b"proxy:\n  - name: p1\n    listen: 127.0.0.1:8080\n    domains: []\n    gateway: []\n";
+        let json = br#"{"proxy": [{"name": "p1", "listen": "127.0.0.1:8080", "domains": [], "gateway": []}]}"#;
+
+        let from_yaml = ConfigFormat::Yaml.parse(yaml).unwrap();
+        let from_json = ConfigFormat::Json.parse(json).unwrap();
+        assert_eq!(from_yaml.proxy[0].listen, from_json.proxy[0].listen);
+        assert!(from_json.proxy[0].highspeed.is_none());
+        assert!(ConfigFormat::Json.parse(yaml).is_err());
+    }
+}
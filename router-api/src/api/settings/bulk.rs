@@ -0,0 +1,408 @@
+//! # Bulk Settings Endpoints
+//!
+//! Creates, updates and deletes many gateways, gateway nodes or proxies in one
+//! request, e.g. for large setups managed from the GUI.
+//!
+//! Bulk sets run every item through the same validation as the single-item
+//! handlers and are all-or-nothing: when any item is rejected nothing is saved,
+//! and the response lists per item whether it passed and why not. Valid batches
+//! are saved in a single transaction.
+//!
+//! Bulk deletes handle every existing ID in one transaction and report the IDs
+//! that did not exist. Like the single-item deletes, proxies and gateways are
+//! moved to the trash while gateway nodes are deleted for good.
+
+use std::collections::HashSet;
+
+use actix_web::{post, web, HttpResponse, Responder};
+use serde::Serialize;
+
+use super::{gateway_queries, gwnode_queries, proxy_queries};
+use super::{gateway_set, gwnode_set, proxy_set};
+use super::{Gateway, GatewayNode, Proxy};
+use crate::module::database::DatabaseError;
+
+/// Upper bound for the number of items in one bulk request
+const MAX_BULK_ITEMS: usize = 1000;
+
+/// Why a single item was rejected, shared by the single and bulk set handlers
+#[derive(Debug)]
+pub(super) enum ItemError {
+    /// The item is invalid or references something that doesn't exist (400)
+    Invalid(String),
+    /// A lookup needed to validate the item failed (500)
+    Database(String),
+}
+
+impl ItemError {
+    fn message(&self) -> &str {
+        match self {
+            ItemError::Invalid(message) | ItemError::Database(message) => message,
+        }
+    }
+
+    /// Response of a single-item handler rejecting the item
+    pub(super) fn into_response(self) -> HttpResponse {
+        match self {
+            ItemError::Invalid(error) => {
+                HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+            }
+            ItemError::Database(error) => {
+                HttpResponse::InternalServerError().json(serde_json::json!({ "error": error }))
+            }
+        }
+    }
+}
+
+/// Outcome of one item of a bulk request
+#[derive(Debug, Serialize)]
+pub struct ItemResult<T> {
+    /// Position of the item in the request
+    pub index: usize,
+    /// ID of the item, generated for new items
+    pub id: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The item as saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item: Option<T>,
+}
+
+fn check_batch_size(len: usize) -> Result<(), HttpResponse> {
+    if len == 0 || len > MAX_BULK_ITEMS {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("A bulk request needs between 1 and {} items", MAX_BULK_ITEMS)
+        })));
+    }
+    Ok(())
+}
+
+/// Validates every item, then saves the batch in one transaction if all passed.
+///
+/// `unique_keys` lists values that must not repeat within the batch (e.g. the
+/// ID), as `(label, value)` pairs.
+fn bulk_set<T, P, K, S>(items: Vec<T>, prepare: P, unique_keys: K, save: S) -> HttpResponse
+where
+    T: Serialize + Clone,
+    P: Fn(&mut T) -> Result<String, ItemError>,
+    K: Fn(&T) -> Vec<(&'static str, String)>,
+    S: FnOnce(&[T]) -> Result<(), DatabaseError>,
+{
+    let mut prepared = Vec::with_capacity(items.len());
+    let mut results = Vec::with_capacity(items.len());
+    let mut seen = HashSet::new();
+
+    for (index, mut item) in items.into_iter().enumerate() {
+        let outcome = prepare(&mut item).and_then(|id| {
+            for (label, value) in unique_keys(&item) {
+                if !seen.insert((label, value.clone())) {
+                    return Err(ItemError::Invalid(format!(
+                        "Duplicate {} '{}' in the batch",
+                        label, value
+                    )));
+                }
+            }
+            Ok(id)
+        });
+        match outcome {
+            Ok(id) => {
+                results.push(ItemResult {
+                    index,
+                    id,
+                    success: true,
+                    error: None,
+                    item: Some(item.clone()),
+                });
+                prepared.push(item);
+            }
+            Err(e) => results.push(ItemResult {
+                index,
+                id: String::new(),
+                success: false,
+                error: Some(e.message().to_string()),
+                item: None,
+            }),
+        }
+    }
+
+    let failed = results.iter().filter(|result| !result.success).count();
+    if failed > 0 {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "error": format!("{} of {} items were rejected, nothing was saved", failed, results.len()),
+            "results": results
+        }));
+    }
+
+    match save(&prepared) {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "results": results
+        })),
+        Err(e) => {
+            log::error!("Failed to save bulk batch: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "error": format!("Failed to save batch, nothing was saved: {}", e)
+            }))
+        }
+    }
+}
+
+/// Deletes or trashes the IDs in one transaction, reporting the IDs that did not exist
+fn bulk_delete<D>(ids: Vec<String>, delete: D) -> HttpResponse
+where
+    D: FnOnce(&[String]) -> Result<Vec<bool>, DatabaseError>,
+{
+    match delete(&ids) {
+        Ok(deleted) => {
+            let results = ids
+                .into_iter()
+                .zip(deleted)
+                .enumerate()
+                .map(|(index, (id, deleted))| ItemResult::<()> {
+                    index,
+                    id,
+                    success: deleted,
+                    error: (!deleted).then(|| "Not found".to_string()),
+                    item: None,
+                })
+                .collect::<Vec<_>>();
+            let deleted = results.iter().filter(|result| result.success).count();
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "deleted": deleted,
+                "results": results
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to delete bulk batch: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "error": format!("Failed to delete batch, nothing was deleted: {}", e)
+            }))
+        }
+    }
+}
+
+/// Creates or updates several gateways
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/gateway/bulk-set`
+///
+/// # Request Body
+///
+/// An array of gateways as accepted by `/gateway/set`; items without `id` are created.
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"success": true, "results": [{"index", "id", "success", "item"}]}`
+///
+/// ## Bad Request (400)
+/// Returned when any item is rejected; `results` carries the `error` of each
+/// rejected item and nothing is saved.
+#[post("/gateway/bulk-set")]
+pub async fn bulk_set_gateways(body: web::Json<Vec<Gateway>>) -> impl Responder {
+    if let Err(response) = check_batch_size(body.len()) {
+        return response;
+    }
+    bulk_set(
+        body.into_inner(),
+        |gateway| gateway_set::prepare_gateway(gateway).map(|_| gateway.id.clone()),
+        |gateway| vec![("gateway id", gateway.id.clone())],
+        gateway_queries::save_gateways,
+    )
+}
+
+/// Moves several gateways to the trash
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/gateway/bulk-delete`
+///
+/// # Request Body
+///
+/// An array of gateway IDs.
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"success": true, "deleted": n, "results": [..]}`, IDs that did not exist
+/// have `"success": false`.
+#[post("/gateway/bulk-delete")]
+pub async fn bulk_delete_gateways(body: web::Json<Vec<String>>) -> impl Responder {
+    if let Err(response) = check_batch_size(body.len()) {
+        return response;
+    }
+    bulk_delete(body.into_inner(), gateway_queries::delete_gateways_by_ids)
+}
+
+/// Creates or updates several gateway nodes
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/gwnode/bulk-set`
+///
+/// # Request Body
+///
+/// An array of gateway nodes as accepted by `/gwnode/set`; items without `id` are created.
+///
+/// # Response
+///
+/// Same as `/gateway/bulk-set`.
+#[post("/gwnode/bulk-set")]
+pub async fn bulk_set_gateway_nodes(body: web::Json<Vec<GatewayNode>>) -> impl Responder {
+    if let Err(response) = check_batch_size(body.len()) {
+        return response;
+    }
+    bulk_set(
+        body.into_inner(),
+        |node| gwnode_set::prepare_gateway_node(node).map(|_| node.id.clone()),
+        |node| vec![("gateway node id", node.id.clone())],
+        gwnode_queries::save_gateway_nodes,
+    )
+}
+
+/// Deletes several gateway nodes along with their gateways
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/gwnode/bulk-delete`
+///
+/// # Request Body
+///
+/// An array of gateway node IDs.
+///
+/// # Response
+///
+/// Same as `/gateway/bulk-delete`.
+#[post("/gwnode/bulk-delete")]
+pub async fn bulk_delete_gateway_nodes(body: web::Json<Vec<String>>) -> impl Responder {
+    if let Err(response) = check_batch_size(body.len()) {
+        return response;
+    }
+    bulk_delete(body.into_inner(), gwnode_queries::delete_gateway_nodes_by_ids)
+}
+
+/// Creates or updates several proxies
+///
+/// Domains are not part of the batch, they are managed through `/proxy`.
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/proxy/bulk-set`
+///
+/// # Request Body
+///
+/// An array of proxies as accepted in the `proxy` field of `/proxy`; items
+/// without `id` are created. Listen addresses must be unique within the batch.
+///
+/// # Response
+///
+/// Same as `/gateway/bulk-set`.
+#[post("/proxy/bulk-set")]
+pub async fn bulk_set_proxies(body: web::Json<Vec<Proxy>>) -> impl Responder {
+    if let Err(response) = check_batch_size(body.len()) {
+        return response;
+    }
+    bulk_set(
+        body.into_inner(),
+        |proxy| proxy_set::prepare_proxy(proxy).map(|_| proxy.id.clone()),
+        |proxy| {
+            vec![
+                ("proxy id", proxy.id.clone()),
+                ("listen address", proxy.addr_listen.clone()),
+            ]
+        },
+        proxy_queries::save_proxies,
+    )
+}
+
+/// Moves several proxies to the trash
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/proxy/bulk-delete`
+///
+/// # Request Body
+///
+/// An array of proxy IDs.
+///
+/// # Response
+///
+/// Same as `/gateway/bulk-delete`.
+#[post("/proxy/bulk-delete")]
+pub async fn bulk_delete_proxies(body: web::Json<Vec<String>>) -> impl Responder {
+    if let Err(response) = check_batch_size(body.len()) {
+        return response;
+    }
+    bulk_delete(body.into_inner(), proxy_queries::delete_proxies_by_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use std::cell::Cell;
+
+    async fn json_of(response: HttpResponse) -> serde_json::Value {
+        let body = to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn prepare(item: &mut (String, i32)) -> Result<String, ItemError> {
+        if item.1 < 0 {
+            return Err(ItemError::Invalid("negative".to_string()));
+        }
+        if item.0.is_empty() {
+            item.0 = format!("generated-{}", item.1);
+        }
+        Ok(item.0.clone())
+    }
+
+    #[actix_web::test]
+    async fn test_rejected_item_saves_nothing() {
+        let saved = Cell::new(false);
+        let items = vec![("a".to_string(), 1), ("b".to_string(), -1), ("a".to_string(), 2)];
+        let response = bulk_set(items, prepare, |item| vec![("id", item.0.clone())], |_| {
+            saved.set(true);
+            Ok(())
+        });
+
+        assert_eq!(response.status(), 400);
+        assert!(!saved.get());
+        let body = json_of(response).await;
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results[0]["success"], true);
+        assert_eq!(results[1]["error"], "negative");
+        assert_eq!(results[2]["error"], "Duplicate id 'a' in the batch");
+    }
+
+    #[actix_web::test]
+    async fn test_valid_batch_returns_generated_ids() {
+        let items = vec![(String::new(), 7), ("b".to_string(), 1)];
+        let response = bulk_set(items, prepare, |item| vec![("id", item.0.clone())], |items| {
+            assert_eq!(items.len(), 2);
+            Ok(())
+        });
+
+        assert_eq!(response.status(), 200);
+        let body = json_of(response).await;
+        assert_eq!(body["results"][0]["id"], "generated-7");
+        assert_eq!(body["results"][1]["id"], "b");
+    }
+
+    #[actix_web::test]
+    async fn test_delete_reports_missing_ids() {
+        let ids = vec!["a".to_string(), "missing".to_string()];
+        let response = bulk_delete(ids, |ids| Ok(ids.iter().map(|id| id != "missing").collect()));
+
+        let body = json_of(response).await;
+        assert_eq!(body["deleted"], 1);
+        assert_eq!(body["results"][1]["success"], false);
+        assert_eq!(body["results"][1]["error"], "Not found");
+    }
+}
@@ -0,0 +1,446 @@
+//! # Config Bundle Endpoints
+//!
+//! Exports the whole configuration (proxies, domains, gateway nodes and gateways,
+//! including the trash) as one versioned JSON bundle and imports it again, e.g.
+//! to move a setup between environments or to restore it after a disaster.
+//!
+//! Unlike `/auto-config`, which rebuilds a YAML description, a bundle is an exact
+//! snapshot of the database rows: IDs, priorities and generated target addresses
+//! are kept as they are.
+//!
+//! When `GWRS_BUNDLE_KEY` is set, exported bundles carry an HMAC-SHA256 signature
+//! of their `data` and imports reject bundles without a valid one. The signature
+//! covers the `data` value byte for byte as it appears in the file, so a bundle
+//! must not be reformatted between export and import.
+
+use std::collections::HashMap;
+
+use actix_web::{get, post, web, HttpResponse, Responder};
+use jsonwebtoken::{crypto, Algorithm, DecodingKey, EncodingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use super::{gateway_queries, gwnode_queries, proxy_queries, proxydomain_queries};
+use super::{Gateway, GatewayNode, Proxy, ProxyDomain};
+use crate::config;
+use crate::module::database::{get_connection, DatabaseError};
+
+/// Value of the `format` field of every bundle
+const BUNDLE_FORMAT: &str = "gwrs-config-bundle";
+
+/// Bundle layout version written on export, imports accept this version only
+///
+/// Version 1 signed a re-serialization of the parsed rows instead of the bytes of `data`.
+const BUNDLE_VERSION: u32 = 2;
+
+/// A full configuration snapshot as exported and imported
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigBundle {
+    /// Always `gwrs-config-bundle`
+    pub format: String,
+    /// Layout version of the bundle
+    pub version: u32,
+    /// When the bundle was exported (unix seconds)
+    pub exported_at: i64,
+    /// The [`BundleData`] exactly as exported, kept raw so the signature is checked
+    /// against the bytes that were signed
+    pub data: Box<RawValue>,
+    /// Base64url HMAC-SHA256 of the bytes of `data`, present when the exporting side had a key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// The database rows of a bundle, trashed proxies and gateways included
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleData {
+    pub proxies: Vec<Proxy>,
+    pub proxy_domains: Vec<ProxyDomain>,
+    pub gateway_nodes: Vec<GatewayNode>,
+    pub gateways: Vec<Gateway>,
+}
+
+/// Query parameters of the import endpoint
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    /// Delete the existing configuration before applying the bundle
+    #[serde(default)]
+    pub replace: bool,
+}
+
+/// Signs the raw `data` of a bundle with `key`
+fn sign(data: &RawValue, key: &str) -> Result<String, String> {
+    crypto::sign(data.get().as_bytes(), &EncodingKey::from_secret(key.as_bytes()), Algorithm::HS256)
+        .map_err(|e| e.to_string())
+}
+
+/// Checks the format, version and, when a key is configured, the signature of a bundle,
+/// then parses its rows
+fn validate(bundle: &ConfigBundle, key: Option<&str>) -> Result<BundleData, String> {
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(format!("Not a config bundle (format '{}')", bundle.format));
+    }
+    if bundle.version != BUNDLE_VERSION {
+        return Err(format!(
+            "Unsupported bundle version {}, expected {}",
+            bundle.version, BUNDLE_VERSION
+        ));
+    }
+
+    let Some(key) = key else {
+        if bundle.signature.is_some() {
+            log::warn!("Importing a signed bundle without {} set, signature not checked", config::ENV_BUNDLE_KEY);
+        }
+        return parse(&bundle.data);
+    };
+    let signature = bundle
+        .signature
+        .as_deref()
+        .ok_or_else(|| "Bundle is not signed".to_string())?;
+    match crypto::verify(
+        signature,
+        bundle.data.get().as_bytes(),
+        &DecodingKey::from_secret(key.as_bytes()),
+        Algorithm::HS256,
+    ) {
+        Ok(true) => parse(&bundle.data),
+        _ => Err("Bundle signature does not match".to_string()),
+    }
+}
+
+/// Parses the rows of a bundle
+fn parse(data: &RawValue) -> Result<BundleData, String> {
+    serde_json::from_str(data.get()).map_err(|e| format!("Invalid bundle data: {}", e))
+}
+
+/// Returns the first listen address used by two live proxies once the bundle is applied
+fn duplicate_listen_address(existing: &[Proxy], bundle: &[Proxy]) -> Option<String> {
+    let mut owners: HashMap<&str, &str> = HashMap::new();
+    let live_existing = existing
+        .iter()
+        .filter(|proxy| !bundle.iter().any(|imported| imported.id == proxy.id));
+    for proxy in live_existing.chain(bundle.iter()) {
+        if proxy.deleted_at.is_some() {
+            continue;
+        }
+        if let Some(owner) = owners.insert(&proxy.addr_listen, &proxy.id) {
+            if owner != proxy.id {
+                return Some(proxy.addr_listen.clone());
+            }
+        }
+    }
+    None
+}
+
+/// Reads every configuration row, trashed proxies and gateways included
+fn collect() -> Result<BundleData, DatabaseError> {
+    let mut proxies = proxy_queries::get_all_proxies()?;
+    proxies.extend(proxy_queries::get_trashed_proxies()?);
+    let mut gateways = gateway_queries::get_all_gateways()?;
+    gateways.extend(gateway_queries::get_trashed_gateways()?);
+
+    Ok(BundleData {
+        proxies,
+        proxy_domains: proxydomain_queries::get_all_proxy_domains()?,
+        gateway_nodes: gwnode_queries::get_all_gateway_nodes()?,
+        gateways,
+    })
+}
+
+/// Writes the bundle rows in one transaction, after wiping the tables if `replace` is set
+fn apply(data: &BundleData, replace: bool) -> Result<(), DatabaseError> {
+    proxy_queries::ensure_proxies_table()?;
+    proxydomain_queries::ensure_proxy_domains_table()?;
+    gwnode_queries::ensure_gateway_nodes_table()?;
+    gateway_queries::ensure_gateways_table()?;
+
+    let db = get_connection()?;
+    db.transaction(|conn| {
+        if replace {
+            // Children first, the foreign keys are enforced
+            conn.execute("DELETE FROM gateways", [])?;
+            conn.execute("DELETE FROM gateway_nodes", [])?;
+            conn.execute("DELETE FROM proxy_domains", [])?;
+            conn.execute("DELETE FROM proxies", [])?;
+        }
+        for proxy in &data.proxies {
+            proxy_queries::upsert_proxy(conn, proxy)?;
+        }
+        for domain in &data.proxy_domains {
+            let proxy_id = domain.proxy_id.as_deref().unwrap_or_default();
+            proxydomain_queries::upsert_proxy_domain(conn, domain, proxy_id)?;
+        }
+        for node in &data.gateway_nodes {
+            gwnode_queries::upsert_gateway_node(conn, node)?;
+        }
+        for gateway in &data.gateways {
+            gateway_queries::upsert_gateway(conn, gateway)?;
+        }
+        Ok(())
+    })
+}
+
+/// Exports the whole configuration as a bundle
+///
+/// # Endpoint
+///
+/// `GET /api/v1/settings/export`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// Returns the bundle as a JSON attachment:
+///
+/// ```json
+/// {
+///   "format": "gwrs-config-bundle",
+///   "version": 2,
+///   "exported_at": 1760000000,
+///   "data": {"proxies": [...], "proxy_domains": [...], "gateway_nodes": [...], "gateways": [...]},
+///   "signature": "..."
+/// }
+/// ```
+///
+/// `signature` is only present when `GWRS_BUNDLE_KEY` is set.
+///
+/// ## Forbidden (403)
+/// Returned when the user isn't an administrator.
+///
+/// ## Internal Server Error (500)
+/// Returned when the configuration could not be read.
+#[get("/export")]
+pub async fn export_config() -> impl Responder {
+    let data = match collect().map_err(|e| e.to_string()).and_then(|data| {
+        serde_json::to_string(&data)
+            .and_then(RawValue::from_string)
+            .map_err(|e| e.to_string())
+    }) {
+        Ok(data) => data,
+        Err(err) => {
+            log::error!("Failed to read configuration for export: {}", err);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Error: {}", err)
+            }));
+        }
+    };
+
+    let signature = match config::bundle_key() {
+        Some(key) => match sign(&data, &key) {
+            Ok(signature) => Some(signature),
+            Err(err) => {
+                log::error!("Failed to sign config bundle: {}", err);
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": format!("Failed to sign bundle: {}", err)
+                }));
+            }
+        },
+        None => None,
+    };
+
+    let bundle = ConfigBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        exported_at: chrono::Utc::now().timestamp(),
+        data,
+        signature,
+    };
+
+    HttpResponse::Ok()
+        .append_header(("Content-Disposition", "attachment; filename=\"gateway-config-bundle.json\""))
+        .json(bundle)
+}
+
+/// Imports a bundle produced by `/export`
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/import`
+///
+/// # Query Parameters
+///
+/// - `replace` (optional): When `true`, the existing configuration is deleted first.
+///   Otherwise rows of the bundle overwrite rows with the same ID and everything
+///   else is kept.
+///
+/// # Request Body
+///
+/// A bundle as returned by `GET /settings/export`.
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// Returns the number of imported rows per table. The background core sync
+/// pushes the imported configuration within a few seconds.
+///
+/// ## Bad Request (400)
+/// Returned when the format or version is not supported, the signature is missing
+/// or wrong while `GWRS_BUNDLE_KEY` is set (e.g. because `data` was reformatted), two live proxies would listen on the
+/// same address, or the rows don't reference each other consistently. Nothing is
+/// imported in that case.
+///
+/// ## Forbidden (403)
+/// Returned when the user isn't an administrator.
+#[post("/import")]
+pub async fn import_config(
+    query: web::Query<ImportQuery>,
+    body: web::Json<ConfigBundle>,
+) -> impl Responder {
+    let data = match validate(&body, config::bundle_key().as_deref()) {
+        Ok(data) => data,
+        Err(error) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": error }));
+        }
+    };
+
+    let existing = if query.replace {
+        Vec::new()
+    } else {
+        match proxy_queries::get_all_proxies() {
+            Ok(proxies) => proxies,
+            Err(err) => {
+                log::error!("Failed to read proxies before import: {}", err);
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": format!("Error: {}", err)
+                }));
+            }
+        }
+    };
+    if let Some(addr) = duplicate_listen_address(&existing, &data.proxies) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("More than one proxy would listen on {}", addr)
+        }));
+    }
+
+    if let Err(err) = apply(&data, query.replace) {
+        log::error!("Failed to import config bundle: {}", err);
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Failed to import bundle: {}", err)
+        }));
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "replaced": query.replace,
+        "imported": {
+            "proxies": data.proxies.len(),
+            "domains": data.proxy_domains.len(),
+            "gwnodes": data.gateway_nodes.len(),
+            "gateways": data.gateways.len()
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(id: &str, addr_listen: &str) -> Proxy {
+        Proxy {
+            id: id.to_string(),
+            title: id.to_string(),
+            addr_listen: addr_listen.to_string(),
+            addr_target: "127.0.0.1:40001".to_string(),
+            high_speed: false,
+            high_speed_addr: None,
+            high_speed_gwid: None,
+            redirect_to_https: false,
+            redirect_https_port: None,
+            deleted_at: None,
+            tcp_nodelay: true,
+            keepalive_secs: None,
+            keepalive_count: None,
+            buffer_size: None,
+            maintenance: false,
+            tls_min_version: None,
+            tls_ciphers: None,
+            tls_require_sni: false,
+            fallback: None,
+            tag: None,
+        }
+    }
+
+    fn bundle(key: Option<&str>) -> ConfigBundle {
+        let data = BundleData {
+            proxies: vec![proxy("p1", "0.0.0.0:80")],
+            proxy_domains: Vec::new(),
+            gateway_nodes: Vec::new(),
+            gateways: Vec::new(),
+        };
+        let data = RawValue::from_string(serde_json::to_string(&data).unwrap()).unwrap();
+        let signature = key.map(|key| sign(&data, key).unwrap());
+        ConfigBundle {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            exported_at: 0,
+            data,
+            signature,
+        }
+    }
+
+    #[test]
+    fn test_validate_checks_format_and_version() {
+        assert!(validate(&bundle(None), None).is_ok());
+
+        let mut newer = bundle(None);
+        newer.version = BUNDLE_VERSION + 1;
+        assert!(validate(&newer, None).unwrap_err().contains("version"));
+
+        let mut other = bundle(None);
+        other.format = "something-else".to_string();
+        assert!(validate(&other, None).is_err());
+    }
+
+    #[test]
+    fn test_validate_checks_signature_when_keyed() {
+        assert!(validate(&bundle(Some("secret")), Some("secret")).is_ok());
+        assert!(validate(&bundle(None), Some("secret")).is_err());
+        assert!(validate(&bundle(Some("other")), Some("secret")).is_err());
+
+        // The signature covers the data, not just the envelope
+        let json = serde_json::to_string(&bundle(Some("secret"))).unwrap();
+        let tampered: ConfigBundle =
+            serde_json::from_str(&json.replace("0.0.0.0:80", "0.0.0.0:8080")).unwrap();
+        assert!(validate(&tampered, Some("secret")).is_err());
+
+        // Survives a round trip through JSON
+        let parsed: ConfigBundle = serde_json::from_str(&json).unwrap();
+        let data = validate(&parsed, Some("secret")).unwrap();
+        assert_eq!(data.proxies[0].addr_listen, "0.0.0.0:80");
+    }
+
+    #[test]
+    fn test_signature_covers_the_raw_data() {
+        let json = serde_json::to_string(&bundle(Some("secret"))).unwrap();
+
+        // A field the rows don't know is dropped when parsing, it must still be signed
+        let extended = json.replace(r#""proxies":["#, r#""extra":1,"proxies":["#);
+        let parsed: ConfigBundle = serde_json::from_str(&extended).unwrap();
+        assert!(validate(&parsed, Some("secret")).is_err());
+
+        // So is a change in formatting of the signed bytes
+        let spaced = json.replace(r#""proxies":["#, r#""proxies": ["#);
+        let parsed: ConfigBundle = serde_json::from_str(&spaced).unwrap();
+        assert!(validate(&parsed, Some("secret")).is_err());
+
+        // Formatting of the envelope around `data` is not covered
+        let spaced = json.replace(r#","data":"#, r#", "data": "#);
+        let parsed: ConfigBundle = serde_json::from_str(&spaced).unwrap();
+        assert!(validate(&parsed, Some("secret")).is_ok());
+    }
+
+    #[test]
+    fn test_duplicate_listen_address() {
+        let existing = vec![proxy("a", "0.0.0.0:80"), proxy("b", "0.0.0.0:81")];
+
+        // Overwriting a proxy with its own address is fine
+        assert_eq!(duplicate_listen_address(&existing, &[proxy("a", "0.0.0.0:80")]), None);
+        // A new proxy on a taken address is not
+        assert_eq!(
+            duplicate_listen_address(&existing, &[proxy("c", "0.0.0.0:81")]),
+            Some("0.0.0.0:81".to_string())
+        );
+        // Trashed proxies don't listen
+        let mut trashed = proxy("c", "0.0.0.0:81");
+        trashed.deleted_at = Some(1);
+        assert_eq!(duplicate_listen_address(&existing, &[trashed]), None);
+    }
+}
@@ -0,0 +1,46 @@
+//! Certificate expiry of configured TLS domains.
+//!
+//! Lists the `notAfter` date of every proxy domain certificate, so expiring
+//! certificates can be renewed before clients fail the handshake. The outcome
+//! of the ACME orders of domains with `acme` set is listed next to them.
+
+use actix_web::{get, HttpResponse, Responder};
+
+use crate::config;
+use crate::module::{acme, cert_expiry};
+
+/// Returns the expiry of every proxy domain certificate
+///
+/// # Endpoint
+///
+/// `GET /api/v1/settings/proxydomain/cert-status`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"warn_days": 14, "certificates": [{"id": .., "domain": .., "expires_at": .., "days_remaining": ..}]}`,
+/// soonest expiry first. Unreadable certificates carry an `error` instead of a date.
+/// `acme` lists `{"id": .., "domain": .., "last_attempt": .., "last_success": .., "consecutive_failures": ..}`
+/// per ACME domain attempted since startup, with `last_error` while failing.
+///
+/// ## Internal Server Error (500)
+/// Returned when the proxy domains could not be read from the database.
+#[get("/proxydomain/cert-status")]
+pub async fn cert_status() -> impl Responder {
+    match cert_expiry::collect() {
+        Ok(mut certificates) => {
+            certificates.sort_by_key(|status| status.days_remaining.unwrap_or(i64::MIN));
+            HttpResponse::Ok().json(serde_json::json!({
+                "warn_days": config::cert_warn_days(),
+                "certificates": certificates,
+                "acme": acme::status(),
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to read certificate status: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to read certificate status: {}", e)
+            }))
+        }
+    }
+}
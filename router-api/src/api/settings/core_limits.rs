@@ -0,0 +1,47 @@
+//! Concurrency limits of the core's listeners.
+//!
+//! Every speed mode proxy and gateway listener counts its open connections or
+//! in-flight requests against `GWRS_PROXY_MAX_CONNECTIONS` and
+//! `GWRS_GATEWAY_MAX_REQUESTS` of the core. This endpoint shows how close each
+//! one is to its limit and how much it has turned away.
+
+use std::sync::Arc;
+
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::module::httpc::HttpC;
+
+/// Returns the concurrency limit state of every listener reported by the core
+///
+/// # Endpoint
+///
+/// `GET /api/v1/settings/limits`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `[{"kind": "proxy"|"gateway"|"mirror", "listener": .., "max": .., "in_flight": .., "rejected": ..}]`,
+/// `max` is `0` for unlimited listeners, for `mirror` it turns mirroring off.
+///
+/// ## Bad Gateway (502)
+/// Returned when the core could not be reached.
+#[get("/limits")]
+pub async fn limits(client: web::Data<Arc<HttpC>>) -> impl Responder {
+    match client.post_with_response("/status", &[]) {
+        Ok(body) => {
+            let status = serde_json::from_str::<serde_json::Value>(&body)
+                .unwrap_or(serde_json::Value::Null);
+            let limits = status
+                .get("limits")
+                .cloned()
+                .unwrap_or_else(|| serde_json::Value::Array(Vec::new()));
+            HttpResponse::Ok().json(limits)
+        }
+        Err(e) => {
+            log::error!("Failed to fetch listener limits: {}", e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Core unreachable: {}", e)
+            }))
+        }
+    }
+}
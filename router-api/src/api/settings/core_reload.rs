@@ -0,0 +1,42 @@
+//! Settings file reload of the core.
+//!
+//! Same as sending SIGHUP to router-core: its settings file is read again and
+//! the settings that can change under live traffic are applied in place.
+
+use std::sync::Arc;
+
+use actix_web::{post, web, HttpResponse, Responder};
+
+use crate::module::httpc::HttpC;
+
+/// Makes the core re-read its settings file
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/reload`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"changed": [..]}` with the names of the settings whose value changed,
+/// including those that only take effect after a restart.
+///
+/// ## Bad Gateway (502)
+/// Returned when the core could not be reached.
+#[post("/reload")]
+pub async fn reload(client: web::Data<Arc<HttpC>>) -> impl Responder {
+    match client.post_once_with_response("/command/reload", &[]) {
+        Ok(body) => {
+            log::info!("Core settings reloaded: {}", body);
+            let result = serde_json::from_str::<serde_json::Value>(&body)
+                .unwrap_or(serde_json::Value::Null);
+            HttpResponse::Ok().json(result)
+        }
+        Err(e) => {
+            log::error!("Failed to reload core settings: {}", e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Core unreachable: {}", e)
+            }))
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! # Conditional GET
+//!
+//! The settings GET endpoints answer with a weak `ETag` computed from the JSON
+//! body, plus the current config version in `X-Config-Version`. A client that
+//! sends the tag back in `If-None-Match` gets an empty `304 Not Modified` when
+//! the body would be the same, so polling the settings costs a few bytes until
+//! something actually changes.
+
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+
+use crate::module::config_version::{self, CONFIG_VERSION_HEADER};
+
+/// 64-bit FNV-1a, stable across builds so tags stay valid after an API restart
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Weak entity tag of a serialized body
+fn etag_of(body: &[u8]) -> String {
+    format!("W/\"{:016x}\"", fnv1a(body))
+}
+
+/// Whether an `If-None-Match` value names `etag`, compared weakly as RFC 9110 asks
+fn matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Responds with `body` as JSON, or `304 Not Modified` when the client already has it.
+///
+/// Both responses carry `ETag` and, when it can be read, `X-Config-Version`.
+pub fn json<T: Serialize>(req: &HttpRequest, body: &T) -> HttpResponse {
+    let body = match serde_json::to_vec(body) {
+        Ok(body) => body,
+        Err(e) => {
+            log::error!("Failed to serialize response: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to serialize response"
+            }));
+        }
+    };
+    let etag = etag_of(&body);
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| matches(value, &etag));
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response.insert_header((header::ETAG, etag));
+    match config_version::current() {
+        Ok(version) => {
+            response.insert_header((CONFIG_VERSION_HEADER, version));
+        }
+        Err(e) => log::warn!("Failed to read the config version: {}", e),
+    }
+
+    if not_modified {
+        response.finish()
+    } else {
+        response.content_type("application/json").body(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_matching() {
+        let etag = etag_of(br#"[{"id":"a"}]"#);
+        assert!(etag.starts_with("W/\"") && etag.len() == 20);
+        assert_ne!(etag, etag_of(br#"[{"id":"b"}]"#));
+
+        assert!(matches(&etag, &etag));
+        assert!(matches(etag.trim_start_matches("W/"), &etag));
+        assert!(matches(&format!("W/\"0000000000000000\", {}", etag), &etag));
+        assert!(matches("*", &etag));
+        assert!(!matches("W/\"0000000000000000\"", &etag));
+    }
+}
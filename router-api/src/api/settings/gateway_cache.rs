@@ -0,0 +1,72 @@
+//! Gateway route cache inspection and invalidation.
+//!
+//! The core caches the routing decision for every path it has seen. These
+//! endpoints expose the cache hit rate and allow a forced flush, e.g. after
+//! a backend moved and stale routes are suspected.
+
+use std::sync::Arc;
+
+use actix_web::{get, post, web, HttpResponse, Responder};
+
+use crate::module::httpc::HttpC;
+
+/// Returns route cache statistics reported by the core
+///
+/// # Endpoint
+///
+/// `GET /api/v1/settings/gateway/cache/stats`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"hits": .., "misses": .., "entries": .., "hit_rate": ..}` summed over all gateway listeners.
+///
+/// ## Bad Gateway (502)
+/// Returned when the core could not be reached.
+#[get("/gateway/cache/stats")]
+pub async fn cache_stats(client: web::Data<Arc<HttpC>>) -> impl Responder {
+    match client.post_with_response("/status", &[]) {
+        Ok(body) => {
+            let status = serde_json::from_str::<serde_json::Value>(&body)
+                .unwrap_or(serde_json::Value::Null);
+            HttpResponse::Ok().json(status.get("gateway_cache").cloned().unwrap_or_default())
+        }
+        Err(e) => {
+            log::error!("Failed to fetch gateway cache stats: {}", e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Core unreachable: {}", e)
+            }))
+        }
+    }
+}
+
+/// Clears the route cache of every gateway listener in the core
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/gateway/cache/flush`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"flushed": n}` with the number of gateway caches that were cleared.
+///
+/// ## Bad Gateway (502)
+/// Returned when the core could not be reached.
+#[post("/gateway/cache/flush")]
+pub async fn flush_cache(client: web::Data<Arc<HttpC>>) -> impl Responder {
+    match client.post_with_response("/gateway/cache/flush", &[]) {
+        Ok(body) => {
+            log::info!("Gateway route caches flushed: {}", body);
+            let result = serde_json::from_str::<serde_json::Value>(&body)
+                .unwrap_or(serde_json::Value::Null);
+            HttpResponse::Ok().json(result)
+        }
+        Err(e) => {
+            log::error!("Failed to flush gateway cache: {}", e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Core unreachable: {}", e)
+            }))
+        }
+    }
+}
@@ -2,8 +2,10 @@
 /// id is sha256 of target_path and source_path
 
 // filepath: /Users/zonblade/Project/runegram/mini-gateway-rs/router-api/src/api/settings/gateway_get.rs
-use actix_web::{get, web, HttpResponse, Responder};
-use super::gateway_queries;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use super::access::Access;
+use super::permission_queries::Scope;
+use super::{etag, gateway_queries};
 
 /// Get a gateway by ID
 ///
@@ -13,11 +15,14 @@ use super::gateway_queries;
 ///
 /// * `id` - The unique identifier of the gateway to retrieve
 #[get("/gateway/{id}")]
-pub async fn get_gateway(path: web::Path<String>) -> impl Responder {
+pub async fn get_gateway(req: HttpRequest, path: web::Path<String>) -> impl Responder {
     let id = path.into_inner();
+    if let Err(response) = Access::of(&req).and_then(|access| access.check_gateway(&id, Scope::Read)) {
+        return response;
+    }
     
     match gateway_queries::get_gateway_by_id(&id) {
-        Ok(Some(gateway)) => HttpResponse::Ok().json(gateway),
+        Ok(Some(gateway)) => etag::json(&req, &gateway),
         Ok(None) => HttpResponse::NotFound().json("Gateway not found"),
         Err(err) => {
             log::error!("Failed to get gateway: {}", err);
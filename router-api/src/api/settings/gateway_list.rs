@@ -4,18 +4,45 @@
 //! either retrieving all gateways in the system or filtering by a specific gateway node.
 //! These endpoints are read-only and do not modify any data.
 
-use actix_web::{get, web, HttpResponse, Responder};
-use super::gateway_queries;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use super::access::Access;
+use super::permission_queries::Scope;
+use super::{etag, gateway_queries};
+use super::proxy_list::ListQuery;
+use super::Gateway;
+use crate::module::database::DatabaseError;
+
+/// Appends the gateways in the trash matching `filter` when they were asked for,
+/// then leaves out those the user can't read
+fn with_trashed(
+    gateways: Result<Vec<Gateway>, DatabaseError>,
+    query: &ListQuery,
+    access: &Access,
+    filter: impl Fn(&Gateway) -> bool,
+) -> Result<Vec<Gateway>, DatabaseError> {
+    let mut gateways = gateways?;
+    if query.include_deleted {
+        gateways.extend(gateway_queries::get_trashed_gateways()?.into_iter().filter(filter));
+    }
+    access.filter_gateways(&mut gateways)?;
+    Ok(gateways)
+}
 
 /// Lists all gateway routing rules
 ///
 /// This endpoint retrieves all gateway configurations from the database and returns
 /// them as a JSON array, ordered by priority (lower number = higher priority).
+/// Gateways of proxies the user has no permission to read are left out.
 ///
 /// # Endpoint
 ///
 /// `GET /settings/gateway/list`
 ///
+/// # Query Parameters
+///
+/// * `include_deleted` - `true` to also list gateways in the trash (with a `deleted_at`
+///   timestamp) after the live ones
+///
 /// # Response
 ///
 /// ## Success (200 OK)
@@ -54,9 +81,14 @@ use super::gateway_queries;
 /// GET /settings/gateway/list
 /// ```
 #[get("/gateway/list")]
-pub async fn list_gateways() -> impl Responder {
-    match gateway_queries::get_all_gateways() {
-        Ok(gateways) => HttpResponse::Ok().json(gateways),
+pub async fn list_gateways(req: HttpRequest, query: web::Query<ListQuery>) -> impl Responder {
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+
+    match with_trashed(gateway_queries::get_all_gateways(), &query, &access, |_| true) {
+        Ok(gateways) => etag::json(&req, &gateways),
         Err(err) => {
             log::error!("Failed to list gateways: {}", err);
             HttpResponse::InternalServerError().json(format!("Error: {}", err))
@@ -77,6 +109,10 @@ pub async fn list_gateways() -> impl Responder {
 ///
 /// * `gwnode_id` - The ID of the gateway node to list gateways for
 ///
+/// # Query Parameters
+///
+/// * `include_deleted` - `true` to also list gateways in the trash, as for `list_gateways`
+///
 /// # Response
 ///
 /// ## Success (200 OK)
@@ -99,11 +135,27 @@ pub async fn list_gateways() -> impl Responder {
 /// GET /settings/gateway/list/7f9c24e5-1315-43a7-9f31-6eb9772cb46a
 /// ```
 #[get("/gateway/list/{gwnode_id}")]
-pub async fn list_gateways_by_gwnode(path: web::Path<String>) -> impl Responder {
+pub async fn list_gateways_by_gwnode(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ListQuery>,
+) -> impl Responder {
     let gwnode_id = path.into_inner();
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+    if let Err(response) = access.check_gateway_node(&gwnode_id, Scope::Read) {
+        return response;
+    }
     
-    match gateway_queries::get_gateways_by_gwnode_id(&gwnode_id) {
-        Ok(gateways) => HttpResponse::Ok().json(gateways),
+    match with_trashed(
+        gateway_queries::get_gateways_by_gwnode_id(&gwnode_id),
+        &query,
+        &access,
+        |gateway| gateway.gwnode_id == gwnode_id,
+    ) {
+        Ok(gateways) => etag::json(&req, &gateways),
         Err(err) => {
             log::error!("Failed to list gateways for gateway node {}: {}", gwnode_id, err);
             HttpResponse::InternalServerError().json(format!("Error: {}", err))
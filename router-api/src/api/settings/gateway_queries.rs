@@ -4,78 +4,96 @@
 //! Gateways are the actual routing rules that define how incoming requests are matched
 //! and forwarded based on patterns and priorities.
 //!
-//! The module handles creating the database table, querying, inserting, updating, and
+//! The module handles checking the database table, querying, inserting, updating, and
 //! deleting gateway records, as well as managing the relationship with gateway nodes.
 
 use crate::module::database::{get_connection, DatabaseError};
-use super::Gateway;
+use crate::module::migrations;
+use super::{Gateway, SplitTarget};
 use uuid::Uuid;
 
-/// Creates the gateways table in the database if it doesn't already exist
+/// Checks that the gateways table has the columns this module reads and writes
 ///
-/// This function ensures that the database schema is properly initialized before
-/// any operations are performed. It is automatically called by other functions
+/// The table is created and upgraded by `module::migrations` only. A table missing
+/// columns makes this apply the pending migrations, it is never dropped or rebuilt.
+/// It is automatically called by other functions
 /// in this module, so there's usually no need to call it directly.
 ///
 /// # Database Schema
 ///
-/// Creates a table with the following structure:
+/// The table has the following structure:
 /// - `id`: TEXT PRIMARY KEY - Unique identifier for the gateway
 /// - `gwnode_id`: TEXT NOT NULL - Reference to the associated gateway node's ID
 /// - `pattern`: TEXT NOT NULL - URL pattern for matching incoming requests
 /// - `target`: TEXT NOT NULL - Target URL where matching requests should be routed
 /// - `priority`: INTEGER NOT NULL - Priority level, with lower numbers having higher precedence
+/// - `strip_prefix`: TEXT - Optional literal path prefix removed before matching `pattern`
+///
+/// - `deleted_at`: INTEGER - When the gateway was moved to the trash (unix seconds, NULL while live)
+/// - `sticky`: TEXT - Session affinity of the path, `cookie`, `ip` or NULL
+/// - `split`: TEXT - JSON array of `{target, weight}` traffic split variants, NULL for none
+/// - `mirror_target`: TEXT - Backend receiving copies of the path's requests, NULL for none
+///
+/// Tables created before `strip_prefix`, `deleted_at`, `sticky`, `split` or `mirror_target` existed are upgraded in place by
+/// the schema migrations, which normally already ran at startup.
 ///
 /// A foreign key constraint is established to ensure referential integrity with the
 /// gateway_nodes table to ensure each gateway is associated with a valid gateway node.
 ///
 /// # Returns
 ///
-/// * `Ok(())` if the table exists or was created successfully
-/// * `Err(DatabaseError)` if there was an error creating the table
+/// * `Ok(())` if the table matches the current schema
+/// * `Err(DatabaseError)` if it doesn't, even after migrating
 ///
 /// # Errors
 ///
 /// This function will return an error if:
 /// - The database connection could not be established
-/// - The SQL statement to create the table could not be executed
+/// - The table still lacks columns once the pending migrations ran
 pub fn ensure_gateways_table() -> Result<(), DatabaseError> {
-    let db = get_connection()?;
-    
-    // Define the expected columns
-    let expected_columns = ["id", "gwnode_id", "pattern", "target", "priority"];
-    
-    // Check if the table exists with the expected columns and is not corrupted
-    if db.table_exists_with_columns("gateways", &expected_columns)? {
-        log::debug!("gateways table exists and has expected structure");
-        return Ok(());
+    migrations::ensure_table(
+        "gateways",
+        &["id", "gwnode_id", "pattern", "target", "priority", "strip_prefix", "deleted_at", "sticky", "split", "mirror_target"],
+    )
+}
+
+/// Columns read by [`gateway_from_row`], in order
+const GATEWAY_COLUMNS: &str = "id, gwnode_id, pattern, target, priority, strip_prefix, deleted_at, sticky, split, mirror_target";
+
+/// Maps a row selected with [`GATEWAY_COLUMNS`] to a `Gateway`
+fn gateway_from_row(row: &rusqlite::Row) -> rusqlite::Result<Gateway> {
+    Ok(Gateway {
+        id: row.get(0)?,
+        gwnode_id: row.get(1)?,
+        pattern: row.get(2)?,
+        target: row.get(3)?,
+        priority: row.get(4)?,
+        strip_prefix: row.get(5)?,
+        deleted_at: row.get(6)?,
+        sticky: row.get(7)?,
+        split: split_targets(row.get(8)?),
+        mirror_target: row.get(9)?,
+    })
+}
+
+/// Parses a stored `split` column, `NULL` and unreadable values mean no split
+pub(crate) fn split_targets(column: Option<String>) -> Vec<SplitTarget> {
+    column
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Stored form of a split, `NULL` without variants
+fn split_column(split: &[SplitTarget]) -> Option<String> {
+    if split.is_empty() {
+        return None;
     }
-    
-    log::info!("Creating or repairing gateways table");
-    
-    // Drop the table if it exists but is corrupted or missing columns
-    db.execute("DROP TABLE IF EXISTS gateways", [])?;
-    
-    // Create the table with the full correct structure
-    db.execute(
-        "CREATE TABLE gateways (
-            id TEXT PRIMARY KEY,
-            gwnode_id TEXT NOT NULL,
-            pattern TEXT NOT NULL,
-            target TEXT NOT NULL,
-            priority INTEGER NOT NULL,
-            FOREIGN KEY(gwnode_id) REFERENCES gateway_nodes(id)
-        )",
-        [],
-    )?;
-    
-    log::info!("Created gateways table with correct structure");
-    Ok(())
+    serde_json::to_string(split).ok()
 }
 
 /// Retrieves all gateway configurations from the database, ordered by priority
 ///
-/// This function fetches all gateway records from the database, orders them by
+/// This function fetches all gateway records that are not in the trash, orders them by
 /// priority (lower numbers first), and converts them into `Gateway` structures.
 /// It automatically ensures the database table exists before performing the query.
 ///
@@ -116,17 +134,9 @@ pub fn get_all_gateways() -> Result<Vec<Gateway>, DatabaseError> {
     
     // Query all gateways, ordered by priority
     let gateways = db.query(
-        "SELECT id, gwnode_id, pattern, target, priority FROM gateways ORDER BY priority ASC",
+        &format!("SELECT {} FROM gateways WHERE deleted_at IS NULL ORDER BY priority ASC", GATEWAY_COLUMNS),
         [],
-        |row| {
-            Ok(Gateway {
-                id: row.get(0)?,
-                gwnode_id: row.get(1)?,
-                pattern: row.get(2)?,
-                target: row.get(3)?,
-                priority: row.get(4)?,
-            })
-        },
+        gateway_from_row,
     )?;
     
     Ok(gateways)
@@ -135,7 +145,7 @@ pub fn get_all_gateways() -> Result<Vec<Gateway>, DatabaseError> {
 /// Retrieves a specific gateway configuration by its ID
 ///
 /// This function fetches a single gateway record from the database based on
-/// the provided ID. It automatically ensures the database table exists before
+/// the provided ID. Gateways in the trash are not returned. It automatically ensures the database table exists before
 /// performing the query.
 ///
 /// # Parameters
@@ -177,17 +187,9 @@ pub fn get_gateway_by_id(id: &str) -> Result<Option<Gateway>, DatabaseError> {
     
     // Query the gateway by ID
     let gateway = db.query_one(
-        "SELECT id, gwnode_id, pattern, target, priority FROM gateways WHERE id = ?1",
+        &format!("SELECT {} FROM gateways WHERE id = ?1 AND deleted_at IS NULL", GATEWAY_COLUMNS),
         [id],
-        |row| {
-            Ok(Gateway {
-                id: row.get(0)?,
-                gwnode_id: row.get(1)?,
-                pattern: row.get(2)?,
-                target: row.get(3)?,
-                priority: row.get(4)?,
-            })
-        },
+        gateway_from_row,
     )?;
     
     Ok(gateway)
@@ -195,7 +197,7 @@ pub fn get_gateway_by_id(id: &str) -> Result<Option<Gateway>, DatabaseError> {
 
 /// Retrieves all gateways associated with a specific gateway node
 ///
-/// This function fetches all gateway records that reference the specified 
+/// This function fetches all live gateway records that reference the specified 
 /// gateway node ID, ordered by priority (lower numbers first). It automatically
 /// ensures the database table exists before performing the query.
 ///
@@ -247,17 +249,12 @@ pub fn get_gateways_by_gwnode_id(gwnode_id: &str) -> Result<Vec<Gateway>, Databa
     
     // Query gateways by gateway node ID, ordered by priority
     let gateways = db.query(
-        "SELECT id, gwnode_id, pattern, target, priority FROM gateways WHERE gwnode_id = ?1 ORDER BY priority ASC",
+        &format!(
+            "SELECT {} FROM gateways WHERE gwnode_id = ?1 AND deleted_at IS NULL ORDER BY priority ASC",
+            GATEWAY_COLUMNS
+        ),
         [gwnode_id],
-        |row| {
-            Ok(Gateway {
-                id: row.get(0)?,
-                gwnode_id: row.get(1)?,
-                pattern: row.get(2)?,
-                target: row.get(3)?,
-                priority: row.get(4)?,
-            })
-        },
+        gateway_from_row,
     )?;
     
     Ok(gateways)
@@ -301,6 +298,11 @@ pub fn get_gateways_by_gwnode_id(gwnode_id: &str) -> Result<Vec<Gateway>, Databa
 ///     pattern: "/api/users/*".to_string(),
 ///     target: "http://user-service:8080".to_string(),
 ///     priority: 10,
+///     strip_prefix: None,
+///     deleted_at: None,
+///     sticky: None,
+///     split: Vec::new(),
+///     mirror_target: None,
 /// };
 ///
 /// match gateway_queries::save_gateway(&gateway) {
@@ -315,24 +317,48 @@ pub fn save_gateway(gateway: &Gateway) -> Result<(), DatabaseError> {
     ensure_gateways_table()?;
     
     // Insert or replace the gateway
-    db.execute(
-        "INSERT OR REPLACE INTO gateways (id, gwnode_id, pattern, target, priority) 
-         VALUES (?1, ?2, ?3, ?4, ?5)",
-        [
+    db.transaction(|conn| upsert_gateway(conn, gateway))?;
+    
+    Ok(())
+}
+
+/// Inserts or replaces one gateway, shared by [`save_gateway`], [`save_gateways`] and the config import
+pub(super) fn upsert_gateway(conn: &rusqlite::Connection, gateway: &Gateway) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT OR REPLACE INTO gateways (id, gwnode_id, pattern, target, priority, strip_prefix, deleted_at, sticky, split, mirror_target) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        rusqlite::params![
             &gateway.id,
             &gateway.gwnode_id,
             &gateway.pattern,
             &gateway.target,
             &gateway.priority.to_string(),
+            &gateway.strip_prefix,
+            &gateway.deleted_at,
+            &gateway.sticky,
+            split_column(&gateway.split),
+            &gateway.mirror_target,
         ],
-    )?;
-    
-    Ok(())
+    )
+}
+
+/// Saves several gateways in one transaction, either all of them or none
+pub fn save_gateways(gateways: &[Gateway]) -> Result<(), DatabaseError> {
+    ensure_gateways_table()?;
+
+    let db = get_connection()?;
+    db.transaction(|conn| {
+        for gateway in gateways {
+            upsert_gateway(conn, gateway)?;
+        }
+        Ok(())
+    })
 }
 
-/// Deletes a gateway configuration from the database by its ID
+/// Permanently deletes a gateway configuration from the database by its ID
 ///
-/// This function removes a gateway record from the database based on its ID.
+/// This function removes a gateway record from the database based on its ID,
+/// whether or not it is in the trash. Use [`trash_gateway_by_id`] for user deletes.
 /// It returns a boolean indicating whether a record was actually deleted.
 ///
 /// # Parameters
@@ -375,6 +401,110 @@ pub fn delete_gateway_by_id(id: &str) -> Result<bool, DatabaseError> {
     Ok(affected_rows > 0)
 }
 
+/// Moves a gateway to the trash
+///
+/// The gateway stops routing and is hidden from listings until it is restored
+/// with [`restore_gateway_by_id`] or purged. Returns whether a live gateway with
+/// this ID existed.
+pub fn trash_gateway_by_id(id: &str) -> Result<bool, DatabaseError> {
+    ensure_gateways_table()?;
+
+    let db = get_connection()?;
+    let affected_rows = db.execute(
+        "UPDATE gateways SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        rusqlite::params![id, chrono::Utc::now().timestamp()],
+    )?;
+
+    Ok(affected_rows > 0)
+}
+
+/// Moves several gateways to the trash in one transaction
+///
+/// Returns, per ID, whether a live gateway was moved to the trash.
+pub fn delete_gateways_by_ids(ids: &[String]) -> Result<Vec<bool>, DatabaseError> {
+    ensure_gateways_table()?;
+
+    let db = get_connection()?;
+    let now = chrono::Utc::now().timestamp();
+    db.transaction(|conn| {
+        ids.iter()
+            .map(|id| -> rusqlite::Result<bool> {
+                Ok(conn.execute(
+                    "UPDATE gateways SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+                    rusqlite::params![id, now],
+                )? > 0)
+            })
+            .collect()
+    })
+}
+
+/// Retrieves all gateways in the trash, most recently deleted first
+pub fn get_trashed_gateways() -> Result<Vec<Gateway>, DatabaseError> {
+    ensure_gateways_table()?;
+
+    let db = get_connection()?;
+    db.query(
+        &format!(
+            "SELECT {} FROM gateways WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+            GATEWAY_COLUMNS
+        ),
+        [],
+        gateway_from_row,
+    )
+}
+
+/// Retrieves a gateway in the trash by its ID
+pub fn get_trashed_gateway_by_id(id: &str) -> Result<Option<Gateway>, DatabaseError> {
+    ensure_gateways_table()?;
+
+    let db = get_connection()?;
+    db.query_one(
+        &format!("SELECT {} FROM gateways WHERE id = ?1 AND deleted_at IS NOT NULL", GATEWAY_COLUMNS),
+        [id],
+        gateway_from_row,
+    )
+}
+
+/// Takes a gateway out of the trash, returns whether it was in the trash
+pub fn restore_gateway_by_id(id: &str) -> Result<bool, DatabaseError> {
+    ensure_gateways_table()?;
+
+    let db = get_connection()?;
+    let affected_rows = db.execute(
+        "UPDATE gateways SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+        [id],
+    )?;
+
+    Ok(affected_rows > 0)
+}
+
+/// Permanently deletes the gateways moved to the trash at or before `deleted_before`
+///
+/// Returns the number of purged gateways.
+pub fn purge_trashed_gateways(deleted_before: i64) -> Result<usize, DatabaseError> {
+    ensure_gateways_table()?;
+
+    let db = get_connection()?;
+    db.execute(
+        "DELETE FROM gateways WHERE deleted_at IS NOT NULL AND deleted_at <= ?1",
+        [deleted_before],
+    )
+}
+
+/// Permanently deletes the gateways of a gateway node that are in the trash
+///
+/// Needed before the gateway node itself is deleted, as trashed gateways still
+/// reference it. Returns the number of deleted gateways.
+pub fn delete_trashed_gateways_by_gwnode_id(gwnode_id: &str) -> Result<usize, DatabaseError> {
+    ensure_gateways_table()?;
+
+    let db = get_connection()?;
+    db.execute(
+        "DELETE FROM gateways WHERE gwnode_id = ?1 AND deleted_at IS NOT NULL",
+        [gwnode_id],
+    )
+}
+
 /// Deletes all gateway configurations from the database
 ///
 /// This function removes all gateway records from the database.
@@ -5,8 +5,10 @@
 //! based on patterns and priorities.
 
 use actix_web::{post, web, HttpResponse, Responder, HttpRequest};
-use super::{Gateway, gateway_queries, gwnode_queries};
-use crate::api::users::helper::{ClaimsFromRequest, is_staff_or_admin};
+use super::{Gateway, gateway_queries, gwnode_queries, rule_validation};
+use super::bulk::ItemError;
+use super::access::Access;
+use super::permission_queries::Scope;
 
 /// Creates or updates a gateway routing rule
 ///
@@ -21,19 +23,32 @@ use crate::api::users::helper::{ClaimsFromRequest, is_staff_or_admin};
 /// # Request Body
 ///
 /// The request body should be a JSON object with the following fields:
-/// - `id` (optional): The unique identifier for the gateway. If empty, a new ID will be generated.
+/// - `id` (optional): The unique identifier for the gateway. If empty or absent, a new gateway is
+///   created with a generated ID; otherwise the gateway with this ID is updated (or created under it).
 /// - `gwnode_id`: The ID of the gateway node this gateway is associated with. Must reference an existing node.
 /// - `pattern`: Pattern for URL matching (e.g., "/api/users/*", "^/users/[0-9]+").
 /// - `target`: Target URL where matching requests should be routed.
 /// - `priority`: Priority level, with lower numbers having higher precedence.
+/// - `strip_prefix` (optional): Literal prefix removed before `pattern` is matched, e.g. "/api".
+///   With a prefix, an empty `pattern` matches everything and an empty `target` forwards the
+///   stripped path unchanged.
+/// - `sticky` (optional): `cookie` or `ip`. The path is then shared by the gateway nodes of the
+///   proxy serving the same pattern, each client staying on one of them.
+/// - `split` (optional): Variants receiving a percentage of the path's requests, e.g.
+///   `[{"target": "10.0.0.9:8080", "weight": 5}]` for a 5% canary. Weights add up to at most 100,
+///   the rest goes to the gateway node. With `sticky` each client keeps its variant.
+/// - `mirror_target` (optional): `host:port` or `http://host[:port]` receiving a copy of each
+///   proxied request of the path. The copies never delay the client and their responses are
+///   discarded.
 ///
 /// # Response
 ///
 /// ## Success (200 OK)
-/// Returns the saved gateway configuration as a JSON object, including any generated ID.
+/// Returns the saved gateway configuration as a JSON object, including any generated `id`.
 ///
 /// ## Bad Request (400)
-/// Returned when the referenced gateway node does not exist.
+/// Returned when the referenced gateway node does not exist, or `strip_prefix`, `sticky`, `split`
+/// or `mirror_target` is invalid.
 ///
 /// ## Internal Server Error (500)
 /// Returned when there is a database or server error.
@@ -85,57 +100,69 @@ pub async fn set_gateway(
     req: HttpRequest,
     req_body: web::Json<Gateway>
 ) -> impl Responder {
-    // Extract authenticated user's claims
-    let claims = match req.get_claims() {
-        Some(claims) => claims,
-        None => {
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to get user authentication"})
-            )
-        }
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
     };
-    
-    // Verify user has admin or staff role
-    if !is_staff_or_admin(&claims.role) {
-        return HttpResponse::Forbidden().json(
-            serde_json::json!({"error": "Only administrators and staff can modify gateway settings"})
-        );
-    }
-    
+
     let mut gateway = req_body.into_inner();
-    
+    // Moving a gateway to another node needs the write permission on both proxies
+    if let Err(response) = access
+        .check_gateway(&gateway.id, Scope::Write)
+        .and_then(|_| access.check_gateway_node(&gateway.gwnode_id, Scope::Write))
+    {
+        return response;
+    }
+    if let Err(e) = prepare_gateway(&mut gateway) {
+        return e.into_response();
+    }
+
+    match gateway_queries::save_gateway(&gateway) {
+        Ok(_) => HttpResponse::Ok().json(gateway),
+        Err(err) => {
+            log::error!("Failed to save gateway: {}", err);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Error: {}", err)
+            }))
+        }
+    }
+}
+
+/// Validates a gateway before it is saved, shared with the bulk endpoint
+///
+/// Normalizes `strip_prefix`, `sticky`, `split` and `mirror_target`, assigns an ID to new gateways and checks that the
+/// referenced gateway node exists.
+pub(super) fn prepare_gateway(gateway: &mut Gateway) -> Result<(), ItemError> {
+    // Saving a gateway always makes it live, the trash is only managed by the API
+    gateway.deleted_at = None;
+
+    // Prefixes are matched literally at the start of the path
+    gateway.strip_prefix = rule_validation::normalize_strip_prefix(
+        gateway.strip_prefix.as_deref().unwrap_or_default(),
+    )
+    .map_err(ItemError::Invalid)?;
+    gateway.sticky = rule_validation::normalize_sticky(gateway.sticky.as_deref().unwrap_or_default())
+        .map_err(ItemError::Invalid)?;
+    gateway.split = rule_validation::normalize_split(&gateway.split).map_err(ItemError::Invalid)?;
+    gateway.mirror_target =
+        rule_validation::normalize_mirror_target(gateway.mirror_target.as_deref().unwrap_or_default())
+            .map_err(ItemError::Invalid)?;
+
     // If no ID provided, generate a new one
     if gateway.id.is_empty() {
         gateway.id = gateway_queries::generate_gateway_id();
     }
-    
+
     // Verify that the referenced gateway node exists
     match gwnode_queries::get_gateway_node_by_id(&gateway.gwnode_id) {
-        Ok(Some(_)) => {
-            // Gateway node exists, proceed with saving the gateway
-            match gateway_queries::save_gateway(&gateway) {
-                Ok(_) => HttpResponse::Ok().json(gateway),
-                Err(err) => {
-                    log::error!("Failed to save gateway: {}", err);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": format!("Error: {}", err)
-                    }))
-                }
-            }
-        },
+        Ok(Some(_)) => Ok(()),
         Ok(None) => {
-            // Gateway node does not exist
             log::error!("Cannot create gateway: Gateway Node ID {} not found", gateway.gwnode_id);
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": format!("Gateway Node ID {} not found", gateway.gwnode_id)
-            }))
-        },
+            Err(ItemError::Invalid(format!("Gateway Node ID {} not found", gateway.gwnode_id)))
+        }
         Err(err) => {
-            // Error retrieving gateway node
             log::error!("Failed to check gateway node existence: {}", err);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Error: {}", err)
-            }))
+            Err(ItemError::Database(format!("Error: {}", err)))
         }
     }
 }
@@ -143,7 +170,9 @@ pub async fn set_gateway(
 /// Deletes a gateway routing rule
 ///
 /// This endpoint processes HTTP POST requests to delete gateway routing rules based
-/// on their unique identifier.
+/// on their unique identifier. The gateway is moved to the trash: it stops routing
+/// and is hidden from listings, and `POST /settings/gateway/{id}/restore` brings it
+/// back until it is purged after `GWRS_TRASH_RETENTION_DAYS` days (default 30).
 ///
 /// # Endpoint
 ///
@@ -157,10 +186,10 @@ pub async fn set_gateway(
 /// # Response
 ///
 /// ## Success (200 OK)
-/// Returns a success message if the gateway was found and deleted.
+/// Returns a success message if the gateway was found and moved to the trash.
 ///
 /// ## Not Found (404)
-/// Returned when no gateway with the specified ID exists.
+/// Returned when no live gateway with the specified ID exists.
 ///
 /// ## Internal Server Error (500)
 /// Returned when there is a database or server error.
@@ -180,28 +209,19 @@ pub async fn delete_gateway(
     req: HttpRequest,
     req_body: web::Json<DeleteRequest>
 ) -> impl Responder {
-    // Extract authenticated user's claims
-    let claims = match req.get_claims() {
-        Some(claims) => claims,
-        None => {
-            return HttpResponse::InternalServerError().json(
-                serde_json::json!({"error": "Failed to get user authentication"})
-            )
-        }
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
     };
-    
-    // Verify user has admin or staff role
-    if !is_staff_or_admin(&claims.role) {
-        return HttpResponse::Forbidden().json(
-            serde_json::json!({"error": "Only administrators and staff can delete gateway settings"})
-        );
-    }
-    
+
     let id = &req_body.id;
+    if let Err(response) = access.check_gateway(id, Scope::Write) {
+        return response;
+    }
     
-    match gateway_queries::delete_gateway_by_id(id) {
+    match gateway_queries::trash_gateway_by_id(id) {
         Ok(true) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Gateway deleted successfully"
+            "message": "Gateway moved to the trash"
         })),
         Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
             "error": "Gateway not found"
@@ -215,6 +235,89 @@ pub async fn delete_gateway(
     }
 }
 
+/// Restores a gateway from the trash
+///
+/// # Endpoint
+///
+/// `POST /settings/gateway/{id}/restore`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// Returns the restored gateway.
+///
+/// ## Not Found (404)
+/// Returned when the gateway is not in the trash (never existed, live, or purged).
+///
+/// ## Conflict (409)
+/// Returned when the gateway node of the gateway has been deleted since.
+///
+/// ## Internal Server Error (500)
+/// Returned when there is a database or server error.
+#[post("/gateway/{id}/restore")]
+pub async fn restore_gateway(
+    req: HttpRequest,
+    path: web::Path<String>
+) -> impl Responder {
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+
+    let id = path.into_inner();
+    if let Err(response) = access.check_gateway(&id, Scope::Write) {
+        return response;
+    }
+    
+    let mut gateway = match gateway_queries::get_trashed_gateway_by_id(&id) {
+        Ok(Some(gateway)) => gateway,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Gateway not found in the trash"
+            }))
+        }
+        Err(err) => {
+            log::error!("Failed to retrieve deleted gateway: {}", err);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Error: {}", err)
+            }));
+        }
+    };
+    
+    // Gateway nodes are deleted for good, a gateway can't come back without its node
+    match gwnode_queries::get_gateway_node_by_id(&gateway.gwnode_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Cannot restore gateway: Gateway Node ID {} no longer exists", gateway.gwnode_id)
+            }))
+        }
+        Err(err) => {
+            log::error!("Failed to check gateway node existence: {}", err);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Error: {}", err)
+            }));
+        }
+    }
+    
+    match gateway_queries::restore_gateway_by_id(&id) {
+        Ok(true) => {
+            gateway.deleted_at = None;
+            HttpResponse::Ok().json(gateway)
+        }
+        // Purged between the lookup and the restore
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Gateway not found in the trash"
+        })),
+        Err(err) => {
+            log::error!("Failed to restore gateway: {}", err);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Error: {}", err)
+            }))
+        }
+    }
+}
+
 /// Request body structure for delete operations
 ///
 /// This structure defines the JSON schema for delete request bodies.
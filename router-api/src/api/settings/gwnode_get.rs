@@ -1,6 +1,8 @@
 // filepath: /Users/zonblade/Project/runegram/mini-gateway-rs/router-api/src/api/settings/gwnode_get.rs
-use actix_web::{get, web, HttpResponse, Responder};
-use super::gwnode_queries;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use super::access::Access;
+use super::permission_queries::Scope;
+use super::{etag, gwnode_queries};
 use serde_json;
 
 /// Get a gateway node by ID
@@ -11,11 +13,14 @@ use serde_json;
 ///
 /// * `id` - The unique identifier of the gateway node to retrieve
 #[get("/gwnode/{id}")]
-pub async fn get_gateway_node(path: web::Path<String>) -> impl Responder {
+pub async fn get_gateway_node(req: HttpRequest, path: web::Path<String>) -> impl Responder {
     let id = path.into_inner();
+    if let Err(response) = Access::of(&req).and_then(|access| access.check_gateway_node(&id, Scope::Read)) {
+        return response;
+    }
     
     match gwnode_queries::get_gateway_node_by_id(&id) {
-        Ok(Some(node)) => HttpResponse::Ok().json(node),
+        Ok(Some(node)) => etag::json(&req, &node),
         Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
             "error": "Gateway node not found"
         })),
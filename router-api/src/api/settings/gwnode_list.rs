@@ -1,14 +1,24 @@
 // filepath: /Users/zonblade/Project/runegram/mini-gateway-rs/router-api/src/api/settings/gwnode_list.rs
-use actix_web::{get, web, HttpResponse, Responder};
-use super::gwnode_queries;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use super::access::Access;
+use super::permission_queries::Scope;
+use super::{etag, gwnode_queries};
 
 /// List all gateway nodes
 ///
-/// Returns a JSON array of all configured gateway nodes.
+/// Returns a JSON array of all configured gateway nodes, leaving out those of
+/// proxies the user has no permission to read.
 #[get("/gwnode/list")]
-pub async fn list_gateway_nodes() -> impl Responder {
-    match gwnode_queries::get_all_gateway_nodes() {
-        Ok(nodes) => HttpResponse::Ok().json(nodes),
+pub async fn list_gateway_nodes(req: HttpRequest) -> impl Responder {
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+
+    match gwnode_queries::get_all_gateway_nodes()
+        .and_then(|mut nodes| access.filter_gateway_nodes(&mut nodes).map(|_| nodes))
+    {
+        Ok(nodes) => etag::json(&req, &nodes),
         Err(err) => {
             log::error!("Failed to list gateway nodes: {}", err);
             HttpResponse::InternalServerError().json(format!("Error: {}", err))
@@ -24,11 +34,14 @@ pub async fn list_gateway_nodes() -> impl Responder {
 ///
 /// * `proxy_id` - The ID of the proxy to list gateway nodes for
 #[get("/gwnode/list/{proxy_id}")]
-pub async fn list_gateway_nodes_by_proxy(path: web::Path<String>) -> impl Responder {
+pub async fn list_gateway_nodes_by_proxy(req: HttpRequest, path: web::Path<String>) -> impl Responder {
     let proxy_id = path.into_inner();
+    if let Err(response) = Access::of(&req).and_then(|access| access.check_proxy(&proxy_id, Scope::Read)) {
+        return response;
+    }
     
     match gwnode_queries::get_gateway_nodes_by_proxy_id(&proxy_id) {
-        Ok(nodes) => HttpResponse::Ok().json(nodes),
+        Ok(nodes) => etag::json(&req, &nodes),
         Err(err) => {
             log::error!("Failed to list gateway nodes for proxy {}: {}", proxy_id, err);
             HttpResponse::InternalServerError().json(format!("Error: {}", err))
@@ -4,73 +4,63 @@
 //! Gateway nodes act as intermediaries between proxies and gateways, providing alternative
 //! routing paths and allowing for more complex routing scenarios.
 //!
-//! The module handles creating the database table, querying, inserting, updating, and
+//! The module handles checking the database table, querying, inserting, updating, and
 //! deleting gateway node records, as well as managing the relationship with proxies.
 
 use super::GatewayNode;
+use crate::module::migrations;
 use crate::module::database::{get_connection, DatabaseError};
 use uuid::Uuid;
 
-/// Creates the gateway_nodes table in the database if it doesn't already exist
+/// Checks that the gateway_nodes table has the columns this module reads and writes
 ///
-/// This function ensures that the database schema is properly initialized before
-/// any operations are performed. It is automatically called by other functions
+/// The table is created and upgraded by `module::migrations` only. A table missing
+/// columns makes this apply the pending migrations, it is never dropped or rebuilt.
+/// It is automatically called by other functions
 /// in this module, so there's usually no need to call it directly.
 ///
 /// # Database Schema
 ///
-/// Creates a table with the following structure:
+/// The table has the following structure:
 /// - `id`: TEXT PRIMARY KEY - Unique identifier for the gateway node
 /// - `proxy_id`: TEXT NOT NULL - Reference to the associated proxy's ID
 /// - `domain_id`: TEXT - Reference to the domain ID (can be null)
 /// - `title`: TEXT NOT NULL - Human-readable name for this gateway node
 /// - `alt_target`: TEXT NOT NULL - Alternative target URL for routing
 /// - `priority`: INTEGER NOT NULL DEFAULT 100 - Processing priority
+/// - `compress`: BOOLEAN NOT NULL DEFAULT 0 - Whether responses are gzip/br compressed by the gateway
+/// - `allow_cidrs`: TEXT - Comma separated client networks allowed to use the node's rules
+/// - `deny_cidrs`: TEXT - Comma separated client networks refused by the node's rules
+/// - `connect_timeout_secs`, `header_timeout_secs`, `total_timeout_secs`: INTEGER - Upstream timeouts (NULL for the core default)
+/// - `maintenance`: BOOLEAN NOT NULL DEFAULT 0 - Whether the gateway answers the node's rules with 503
+/// - `redact_fields`: TEXT - Comma separated JSON fields redacted from the node's responses
+/// - `redact_mode`: TEXT - `mask` or `remove`, NULL masks
+/// - `route_script`: TEXT - Rhai script deciding the node's routes per request (NULL for none)
+/// - `cors_origins`: TEXT - Comma separated origins whose CORS preflights the gateway answers
+/// - `cors_methods`: TEXT - Comma separated methods allowed in preflights
+/// - `cors_headers`: TEXT - Comma separated request headers allowed in preflights
+/// - `server_header`: TEXT - `Server` value sent instead of the upstream's (NULL keeps it)
+/// - `strip_server_headers`: BOOLEAN NOT NULL DEFAULT 0 - Whether upstream `Server` and `X-Powered-By` are removed
 ///
 /// # Returns
 ///
-/// * `Ok(())` if the table exists or was created successfully
-/// * `Err(DatabaseError)` if there was an error creating the table
+/// * `Ok(())` if the table matches the current schema
+/// * `Err(DatabaseError)` if it doesn't, even after migrating
 ///
 /// # Errors
 ///
 /// This function will return an error if:
 /// - The database connection could not be established
-/// - The SQL statement to create the table could not be executed
+/// - The table still lacks columns once the pending migrations ran
 pub fn ensure_gateway_nodes_table() -> Result<(), DatabaseError> {
-    let db = get_connection()?;
-    
-    // Define the expected columns
-    let expected_columns = ["id", "proxy_id", "domain_id", "title", "alt_target", "priority"];
-    
-    // Check if the table exists with the expected columns and is not corrupted
-    if db.table_exists_with_columns("gateway_nodes", &expected_columns)? {
-        log::debug!("gateway_nodes table exists and has expected structure");
-        return Ok(());
-    }
-    
-    log::info!("Creating or repairing gateway_nodes table");
-    
-    // Drop the table if it exists but is corrupted or missing columns
-    db.execute("DROP TABLE IF EXISTS gateway_nodes", [])?;
-    
-    // Create the table with the full correct structure
-    db.execute(
-        "CREATE TABLE gateway_nodes (
-            id TEXT PRIMARY KEY,
-            proxy_id TEXT NOT NULL,
-            domain_id TEXT,
-            title TEXT NOT NULL,
-            alt_target TEXT NOT NULL,
-            priority INTEGER NOT NULL DEFAULT 100,
-            FOREIGN KEY(proxy_id) REFERENCES proxies(id),
-            FOREIGN KEY(domain_id) REFERENCES proxy_domains(id)
-        )",
-        [],
-    )?;
-    
-    log::info!("Created gateway_nodes table with correct structure");
-    Ok(())
+    migrations::ensure_table(
+        "gateway_nodes",
+        &[
+            "id", "proxy_id", "domain_id", "title", "alt_target", "priority", "compress", "allow_cidrs", "deny_cidrs",
+            "connect_timeout_secs", "header_timeout_secs", "total_timeout_secs", "maintenance", "redact_fields", "redact_mode",
+            "route_script", "cors_origins", "cors_methods", "cors_headers", "server_header", "strip_server_headers",
+        ],
+    )
 }
 
 /// Retrieves all gateway node configurations from the database
@@ -125,7 +115,22 @@ pub fn get_all_gateway_nodes() -> Result<Vec<GatewayNode>, DatabaseError> {
             n.title, 
             n.alt_target, 
             n.priority,
-            (SELECT d.sni FROM proxy_domains d WHERE d.id = n.domain_id LIMIT 1) as domain_name
+            (SELECT d.sni FROM proxy_domains d WHERE d.id = n.domain_id LIMIT 1) as domain_name,
+            n.compress,
+            n.allow_cidrs,
+            n.deny_cidrs,
+            n.connect_timeout_secs,
+            n.header_timeout_secs,
+            n.total_timeout_secs,
+            n.maintenance,
+            n.redact_fields,
+            n.redact_mode,
+            n.route_script,
+            n.cors_origins,
+            n.cors_methods,
+            n.cors_headers,
+            n.server_header,
+            n.strip_server_headers
         FROM gateway_nodes as n",
         [],
         |row| {
@@ -137,6 +142,21 @@ pub fn get_all_gateway_nodes() -> Result<Vec<GatewayNode>, DatabaseError> {
                 alt_target: row.get(4)?,
                 priority: row.get(5)?,
                 domain_name: row.get::<_, Option<String>>(6)?,
+                compress: row.get(7)?,
+                allow_cidrs: comma_list(row.get(8)?),
+                deny_cidrs: comma_list(row.get(9)?),
+                connect_timeout_secs: row.get(10)?,
+                header_timeout_secs: row.get(11)?,
+                total_timeout_secs: row.get(12)?,
+                maintenance: row.get(13)?,
+                redact_fields: comma_list(row.get(14)?),
+                redact_mode: row.get(15)?,
+                route_script: row.get(16)?,
+                cors_origins: comma_list(row.get(17)?),
+                cors_methods: comma_list(row.get(18)?),
+                cors_headers: comma_list(row.get(19)?),
+                server_header: row.get(20)?,
+                strip_server_headers: row.get(21)?,
             })
         },
     )?;
@@ -200,7 +220,22 @@ pub fn get_gateway_node_by_id(id: &str) -> Result<Option<GatewayNode>, DatabaseE
             n.title, 
             n.alt_target, 
             n.priority,
-            (SELECT d.sni FROM proxy_domains d WHERE d.id = n.domain_id LIMIT 1) as domain_name
+            (SELECT d.sni FROM proxy_domains d WHERE d.id = n.domain_id LIMIT 1) as domain_name,
+            n.compress,
+            n.allow_cidrs,
+            n.deny_cidrs,
+            n.connect_timeout_secs,
+            n.header_timeout_secs,
+            n.total_timeout_secs,
+            n.maintenance,
+            n.redact_fields,
+            n.redact_mode,
+            n.route_script,
+            n.cors_origins,
+            n.cors_methods,
+            n.cors_headers,
+            n.server_header,
+            n.strip_server_headers
         FROM gateway_nodes as n 
         WHERE n.id = ?1",
         [id],
@@ -213,6 +248,21 @@ pub fn get_gateway_node_by_id(id: &str) -> Result<Option<GatewayNode>, DatabaseE
                 alt_target: row.get(4)?,
                 priority: row.get(5)?,
                 domain_name: row.get::<_, Option<String>>(6)?,
+                compress: row.get(7)?,
+                allow_cidrs: comma_list(row.get(8)?),
+                deny_cidrs: comma_list(row.get(9)?),
+                connect_timeout_secs: row.get(10)?,
+                header_timeout_secs: row.get(11)?,
+                total_timeout_secs: row.get(12)?,
+                maintenance: row.get(13)?,
+                redact_fields: comma_list(row.get(14)?),
+                redact_mode: row.get(15)?,
+                route_script: row.get(16)?,
+                cors_origins: comma_list(row.get(17)?),
+                cors_methods: comma_list(row.get(18)?),
+                cors_headers: comma_list(row.get(19)?),
+                server_header: row.get(20)?,
+                strip_server_headers: row.get(21)?,
             })
         },
     )?;
@@ -277,7 +327,22 @@ pub fn get_gateway_nodes_by_proxy_id(proxy_id: &str) -> Result<Vec<GatewayNode>,
             n.title, 
             n.alt_target, 
             n.priority,
-            (SELECT d.sni FROM proxy_domains d WHERE d.id = n.domain_id LIMIT 1) as domain_name
+            (SELECT d.sni FROM proxy_domains d WHERE d.id = n.domain_id LIMIT 1) as domain_name,
+            n.compress,
+            n.allow_cidrs,
+            n.deny_cidrs,
+            n.connect_timeout_secs,
+            n.header_timeout_secs,
+            n.total_timeout_secs,
+            n.maintenance,
+            n.redact_fields,
+            n.redact_mode,
+            n.route_script,
+            n.cors_origins,
+            n.cors_methods,
+            n.cors_headers,
+            n.server_header,
+            n.strip_server_headers
         FROM gateway_nodes as n
         WHERE n.proxy_id = ?1
         ORDER BY priority DESC",
@@ -291,6 +356,21 @@ pub fn get_gateway_nodes_by_proxy_id(proxy_id: &str) -> Result<Vec<GatewayNode>,
                 alt_target: row.get(4)?,
                 priority: row.get(5)?,
                 domain_name: row.get::<_, Option<String>>(6)?,
+                compress: row.get(7)?,
+                allow_cidrs: comma_list(row.get(8)?),
+                deny_cidrs: comma_list(row.get(9)?),
+                connect_timeout_secs: row.get(10)?,
+                header_timeout_secs: row.get(11)?,
+                total_timeout_secs: row.get(12)?,
+                maintenance: row.get(13)?,
+                redact_fields: comma_list(row.get(14)?),
+                redact_mode: row.get(15)?,
+                route_script: row.get(16)?,
+                cors_origins: comma_list(row.get(17)?),
+                cors_methods: comma_list(row.get(18)?),
+                cors_headers: comma_list(row.get(19)?),
+                server_header: row.get(20)?,
+                strip_server_headers: row.get(21)?,
             })
         },
     )?;
@@ -350,15 +430,42 @@ pub fn save_gateway_node(node: &GatewayNode) -> Result<(), DatabaseError> {
     ensure_gateway_nodes_table()?;
 
     // Insert or update the gateway node
-    db.execute(
-        "INSERT INTO gateway_nodes (id, proxy_id, domain_id, title, alt_target, priority)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+    db.transaction(|conn| upsert_gateway_node(conn, node))?;
+
+    Ok(())
+}
+
+/// Inserts or updates one gateway node, shared by [`save_gateway_node`], [`save_gateway_nodes`] and the config import
+///
+/// An updated node keeps its maintenance mode, only [`set_gateway_node_maintenance`]
+/// changes it. `node.maintenance` is used for new nodes.
+pub(super) fn upsert_gateway_node(conn: &rusqlite::Connection, node: &GatewayNode) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT INTO gateway_nodes (id, proxy_id, domain_id, title, alt_target, priority, compress, allow_cidrs, deny_cidrs,
+                                    connect_timeout_secs, header_timeout_secs, total_timeout_secs, maintenance,
+                                    redact_fields, redact_mode, route_script, cors_origins, cors_methods, cors_headers,
+                                    server_header, strip_server_headers)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)
          ON CONFLICT(id) DO UPDATE SET
          proxy_id = ?2,
          domain_id = ?3,
          title = ?4,
          alt_target = ?5,
-         priority = ?6",
+         priority = ?6,
+         compress = ?7,
+         allow_cidrs = ?8,
+         deny_cidrs = ?9,
+         connect_timeout_secs = ?10,
+         header_timeout_secs = ?11,
+         total_timeout_secs = ?12,
+         redact_fields = ?14,
+         redact_mode = ?15,
+         route_script = ?16,
+         cors_origins = ?17,
+         cors_methods = ?18,
+         cors_headers = ?19,
+         server_header = ?20,
+         strip_server_headers = ?21",
         rusqlite::params![
             node.id,
             node.proxy_id,
@@ -366,10 +473,47 @@ pub fn save_gateway_node(node: &GatewayNode) -> Result<(), DatabaseError> {
             node.title,
             node.alt_target,
             node.priority,
+            node.compress,
+            node.allow_cidrs.join(","),
+            node.deny_cidrs.join(","),
+            node.connect_timeout_secs,
+            node.header_timeout_secs,
+            node.total_timeout_secs,
+            node.maintenance,
+            node.redact_fields.join(","),
+            node.redact_mode,
+            node.route_script,
+            node.cors_origins.join(","),
+            node.cors_methods.join(","),
+            node.cors_headers.join(","),
+            node.server_header,
+            node.strip_server_headers,
         ],
-    )?;
+    )
+}
 
-    Ok(())
+/// Splits a stored comma separated list column, `NULL` and empty mean no entries
+pub(crate) fn comma_list(column: Option<String>) -> Vec<String> {
+    column
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Saves several gateway nodes in one transaction, either all of them or none
+pub fn save_gateway_nodes(nodes: &[GatewayNode]) -> Result<(), DatabaseError> {
+    ensure_gateway_nodes_table()?;
+
+    let db = get_connection()?;
+    db.transaction(|conn| {
+        for node in nodes {
+            upsert_gateway_node(conn, node)?;
+        }
+        Ok(())
+    })
 }
 
 /// Deletes a gateway node configuration from the database by its ID
@@ -418,6 +562,21 @@ pub fn delete_gateway_node_by_id(id: &str) -> Result<bool, DatabaseError> {
     Ok(affected_rows > 0)
 }
 
+/// Turns maintenance mode of a gateway node on or off
+///
+/// Returns whether a gateway node with this ID existed.
+pub fn set_gateway_node_maintenance(id: &str, enabled: bool) -> Result<bool, DatabaseError> {
+    ensure_gateway_nodes_table()?;
+
+    let db = get_connection()?;
+    let affected_rows = db.execute(
+        "UPDATE gateway_nodes SET maintenance = ?2 WHERE id = ?1",
+        rusqlite::params![id, enabled],
+    )?;
+
+    Ok(affected_rows > 0)
+}
+
 /// Generates a new unique identifier for a gateway node
 ///
 /// This function creates a UUID v4 (random) string that can be used as the ID
@@ -440,49 +599,22 @@ pub fn generate_gateway_node_id() -> String {
     Uuid::new_v4().to_string()
 }
 
-/// Updates gateway nodes to be unbound when their associated proxy is deleted
-///
-/// Rather than deleting gateway nodes when their associated proxy is removed,
-/// this function marks them as "unbound" by setting their proxy_id field to the
-/// special value "unbound". This preserves the gateway node configuration while
-/// indicating that it's no longer tied to a valid proxy.
+/// Deletes several gateway nodes and their gateways in one transaction
 ///
-/// # Parameters
-///
-/// * `proxy_id` - The ID of the proxy that is being deleted
-///
-/// # Returns
-///
-/// * `Ok(usize)` - The number of gateway nodes that were updated
-/// * `Err(DatabaseError)` - If there was an error updating the gateway nodes
-///
-/// # Errors
-///
-/// This function will return an error if:
-/// - The database connection could not be established
-/// - The SQL statement could not be executed
-///
-/// # Example
-///
-/// ```
-/// use router_api::api::settings::gwnode_queries;
-///
-/// let proxy_id = "550e8400-e29b-41d4-a716-446655440000";
-/// match gwnode_queries::unbind_gateway_nodes_by_proxy_id(proxy_id) {
-///     Ok(count) => println!("{} gateway nodes were marked as unbound", count),
-///     Err(err) => // eprintln!!("Error unbinding gateway nodes: {}", err),
-/// }
-/// ```
-pub fn unbind_gateway_nodes_by_proxy_id(proxy_id: &str) -> Result<usize, DatabaseError> {
-    let db = get_connection()?;
-
-    // Update all gateway nodes associated with this proxy to mark them as unbound
-    let affected_rows = db.execute(
-        "UPDATE gateway_nodes SET proxy_id = 'unbound' WHERE proxy_id = ?1",
-        [proxy_id],
-    )?;
+/// Returns, per ID, whether a gateway node was deleted.
+pub fn delete_gateway_nodes_by_ids(ids: &[String]) -> Result<Vec<bool>, DatabaseError> {
+    super::gateway_queries::ensure_gateways_table()?;
+    ensure_gateway_nodes_table()?;
 
-    Ok(affected_rows)
+    let db = get_connection()?;
+    db.transaction(|conn| {
+        ids.iter()
+            .map(|id| -> rusqlite::Result<bool> {
+                conn.execute("DELETE FROM gateways WHERE gwnode_id = ?1", [id])?;
+                Ok(conn.execute("DELETE FROM gateway_nodes WHERE id = ?1", [id])? > 0)
+            })
+            .collect()
+    })
 }
 
 /// Deletes all gateway node configurations from the database
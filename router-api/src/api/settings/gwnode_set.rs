@@ -5,9 +5,14 @@
 
 use actix_web::{post, web, HttpResponse, Responder, HttpRequest};
 use super::{GatewayNode, gwnode_queries};
+use super::access::Access;
+use super::permission_queries::Scope;
 use super::{proxy_queries, gateway_queries};
-use crate::api::users::helper::{ClaimsFromRequest, is_staff_or_admin};
 use crate::module::database::DatabaseError;
+use crate::module::netaddr;
+use super::bulk::ItemError;
+use super::rule_validation;
+use std::sync::{mpsc, LazyLock};
 
 /// Creates or updates a gateway node configuration
 ///
@@ -22,18 +27,33 @@ use crate::module::database::DatabaseError;
 /// # Request Body
 ///
 /// The request body should be a JSON object with the following fields:
-/// - `id` (optional): The unique identifier for the gateway node. If empty, a new ID will be generated.
+/// - `id` (optional): The unique identifier for the gateway node. If empty or absent, a new gateway node is
+///   created with a generated ID; otherwise the gateway node with this ID is updated (or created under it).
 /// - `proxy_id`: The ID of the proxy this gateway node is associated with. Must reference an existing proxy.
 /// - `title`: Human-readable name for this gateway node
-/// - `alt_target`: Alternative target URL for routing.
+/// - `alt_target`: Target the node routes to, `host:port`, `http://host[:port]` or `unix:/path`.
+///   Stored as `host:port`; `https://`, paths and other schemes are rejected.
+/// - `allow_cidrs` (optional): Client networks allowed to use the node's rules, e.g. `["10.0.0.0/8"]`.
+///   Empty or absent allows every client.
+/// - `deny_cidrs` (optional): Client networks answered with 403, even when also allowed.
+/// - `redact_fields` (optional): Dotted JSON fields redacted from the node's JSON responses,
+///   e.g. `["user.ssn", "items.*.token"]`.
+/// - `redact_mode` (optional): `mask` (default) or `remove`.
+/// - `route_script` (optional): Rhai script deciding the node's routes per request, at most 64 KiB.
+/// - `cors_origins` (optional): Origins whose CORS preflights the gateway answers itself, e.g.
+///   `["https://app.example.com"]` or `["*"]`. Empty or absent forwards preflights to the target.
+/// - `cors_methods` / `cors_headers` (optional): Methods and request headers allowed in preflights.
+/// - `server_header` (optional): `Server` value sent instead of the upstream's, at most 256 characters.
+/// - `strip_server_headers` (optional): Remove the upstream's `Server` and `X-Powered-By` headers.
 ///
 /// # Response
 ///
 /// ## Success (200 OK)
-/// Returns the saved gateway node configuration as a JSON object, including any generated ID.
+/// Returns the saved gateway node configuration as a JSON object, including any generated `id`.
 ///
 /// ## Bad Request (400)
-/// Returned when the referenced proxy does not exist.
+/// Returned when the referenced proxy does not exist, the target is malformed, a CIDR is invalid or
+/// a redaction setting, the route script, a CORS setting or the server header is invalid.
 ///
 /// ## Internal Server Error (500)
 /// Returned when there is a database or server error.
@@ -69,25 +89,57 @@ pub async fn set_gateway_node(
     req: HttpRequest,
     req_body: web::Json<GatewayNode>
 ) -> impl Responder {
-    // Extract authenticated user's claims
-    let claims = match req.get_claims() {
-        Some(claims) => claims,
-        None => {
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Failed to get user authentication"})
-            )
-        }
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
     };
-    
-    // Verify user has admin or staff role
-    if !is_staff_or_admin(&claims.role) {
-        return HttpResponse::Forbidden().json(
-            serde_json::json!({"error": "Only administrators and staff can modify gateway nodes"})
-        );
-    }
-    
+
     let mut node = req_body.into_inner();
-    
+    // Moving a node to another proxy needs the write permission on both
+    if let Err(response) = access
+        .check_gateway_node(&node.id, Scope::Write)
+        .and_then(|_| access.check_proxy(&node.proxy_id, Scope::Write))
+    {
+        return response;
+    }
+    let proxy_name = match prepare_gateway_node(&mut node) {
+        Ok(proxy_name) => proxy_name,
+        Err(e) => return e.into_response(),
+    };
+
+    match gwnode_queries::save_gateway_node(&node) {
+        Ok(_) => HttpResponse::Ok().json(node),
+        Err(err) => {
+            log::error!("Failed to save gateway node: {}", err);
+            let error_message = match err {
+                DatabaseError::Sqlite(sqlite_error) => {
+                    if let rusqlite::Error::SqliteFailure(err, _) = sqlite_error {
+                        if err.code == rusqlite::ffi::ErrorCode::ConstraintViolation {
+                            format!("Cannot save gateway node '{}' because of database constraints. Please check if the proxy '{}' exists and is valid.", node.title, proxy_name)
+                        } else {
+                            format!("Database error while saving gateway node '{}': {}", node.title, sqlite_error)
+                        }
+                    } else {
+                        format!("SQLite error: {}", sqlite_error)
+                    }
+                },
+                _ => format!("Failed to save gateway node '{}': {}", node.title, err)
+            };
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message,
+                "gateway_node_id": node.id
+            }))
+        }
+    }
+}
+
+/// Validates a gateway node before it is saved, shared with the bulk endpoint
+///
+/// Assigns an ID and default title to new nodes, checks the alternative target,
+/// normalizes the access lists, redaction, route script, CORS settings and server header and
+/// checks that the referenced proxy exists.
+/// Returns the proxy title.
+pub(super) fn prepare_gateway_node(node: &mut GatewayNode) -> Result<String, ItemError> {
     // If no ID provided, generate a new one
     if node.id.is_empty() {
         node.id = gwnode_queries::generate_gateway_node_id();
@@ -95,88 +147,104 @@ pub async fn set_gateway_node(
     
     // If no title provided, set a default one
     if node.title.is_empty() {
-        node.title = format!("Gateway Node {}", &node.id[..8]);
+        node.title = format!("Gateway Node {}", &node.id[..8.min(node.id.len())]);
     }
 
-    // check if ip address is with port, if not, return error
-    if !node.alt_target.contains(":") {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({"error": "Alt target must be a valid IP address with port"})
-        );
-    }
+    // IPv6 literals must be bracketed (e.g. "[::1]:8080"), Unix sockets are
+    // only reachable from speed mode proxies
+    node.alt_target = netaddr::normalize_target(&node.alt_target)
+        .map_err(|e| ItemError::Invalid(format!("Invalid alt_target: {}", e)))?;
+    probe_target(&node.title, &node.alt_target);
+
+    // Reject bad networks here, the core would otherwise skip every rule of the node
+    node.allow_cidrs = normalize_cidrs(&node.allow_cidrs)
+        .map_err(|e| ItemError::Invalid(format!("Invalid allow_cidrs entry: {}", e)))?;
+    node.deny_cidrs = normalize_cidrs(&node.deny_cidrs)
+        .map_err(|e| ItemError::Invalid(format!("Invalid deny_cidrs entry: {}", e)))?;
+    node.redact_fields = rule_validation::normalize_redact_fields(&node.redact_fields)
+        .map_err(|e| ItemError::Invalid(format!("Invalid redact_fields entry: {}", e)))?;
+    node.redact_mode = rule_validation::normalize_redact_mode(node.redact_mode.as_deref().unwrap_or_default())
+        .map_err(|e| ItemError::Invalid(format!("Invalid redact_mode: {}", e)))?;
+    node.route_script = rule_validation::normalize_route_script(node.route_script.as_deref().unwrap_or_default())
+        .map_err(|e| ItemError::Invalid(format!("Invalid route_script: {}", e)))?;
+    node.cors_origins = rule_validation::normalize_cors_origins(&node.cors_origins)
+        .map_err(|e| ItemError::Invalid(format!("Invalid cors_origins entry: {}", e)))?;
+    node.cors_methods = rule_validation::normalize_cors_methods(&node.cors_methods)
+        .map_err(|e| ItemError::Invalid(format!("Invalid cors_methods entry: {}", e)))?;
+    node.cors_headers = rule_validation::normalize_cors_headers(&node.cors_headers)
+        .map_err(|e| ItemError::Invalid(format!("Invalid cors_headers entry: {}", e)))?;
+    node.server_header = rule_validation::normalize_server_header(node.server_header.as_deref().unwrap_or_default())
+        .map_err(|e| ItemError::Invalid(format!("Invalid server_header: {}", e)))?;
 
-    // check if after : is a valid port 1 - 65535, return error if not
-    if let Some(port) = node.alt_target.split(":").nth(1) {
-        if port.parse::<u16>().is_err() {
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Alt target must be a valid IP address with port"})
-            );
-        }
-    }
-    
-    // Get proxy details for better error messages
-    let proxy_name = match proxy_queries::get_proxy_by_id(&node.proxy_id) {
-        Ok(Some(proxy)) => proxy.title,
-        Ok(None) => node.proxy_id.clone(),
-        Err(e) => {
-            log::error!("Error retrieving proxy {}: {}", node.proxy_id, e);
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({
-                    "error": format!("Failed to verify proxy existence: {}", e),
-                    "proxy_id": node.proxy_id
-                })
-            );
-        }
-    };
-    
     // Verify that the referenced proxy exists
     match proxy_queries::get_proxy_by_id(&node.proxy_id) {
-        Ok(Some(_)) => {
-            // Proxy exists, proceed with saving the gateway node
-            match gwnode_queries::save_gateway_node(&node) {
-                Ok(_) => HttpResponse::Ok().json(node),
-                Err(err) => {
-                    log::error!("Failed to save gateway node: {}", err);
-                    let error_message = match err {
-                        DatabaseError::Sqlite(sqlite_error) => {
-                            if let rusqlite::Error::SqliteFailure(err, _) = sqlite_error {
-                                if err.code == rusqlite::ffi::ErrorCode::ConstraintViolation {
-                                    format!("Cannot save gateway node '{}' because of database constraints. Please check if the proxy '{}' exists and is valid.", node.title, proxy_name)
-                                } else {
-                                    format!("Database error while saving gateway node '{}': {}", node.title, sqlite_error)
-                                }
-                            } else {
-                                format!("SQLite error: {}", sqlite_error)
-                            }
-                        },
-                        _ => format!("Failed to save gateway node '{}': {}", node.title, err)
-                    };
-                    HttpResponse::BadRequest().json(serde_json::json!({
-                        "error": error_message,
-                        "gateway_node_id": node.id
-                    }))
-                }
-            }
-        },
+        Ok(Some(proxy)) => Ok(proxy.title),
         Ok(None) => {
-            // Proxy does not exist
-            log::error!("Cannot create gateway node: Proxy '{}' not found", proxy_name);
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": format!("Cannot create gateway node: Proxy '{}' not found", proxy_name),
-                "proxy_id": node.proxy_id
-            }))
-        },
+            log::error!("Cannot create gateway node: Proxy '{}' not found", node.proxy_id);
+            Err(ItemError::Invalid(format!(
+                "Cannot create gateway node: Proxy '{}' not found",
+                node.proxy_id
+            )))
+        }
         Err(err) => {
-            // Error retrieving proxy
             log::error!("Failed to check proxy existence: {}", err);
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": format!("Failed to verify proxy existence: {}", err),
-                "proxy_id": node.proxy_id
-            }))
+            Err(ItemError::Invalid(format!("Failed to verify proxy existence: {}", err)))
         }
     }
 }
 
+/// Connect timeout of the target probe
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Probes waiting for the prober thread, more are dropped
+const PROBE_QUEUE: usize = 64;
+
+/// Queue of `(title, target)` probes, served one at a time by a single thread
+static PROBES: LazyLock<mpsc::SyncSender<(String, String)>> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::sync_channel::<(String, String)>(PROBE_QUEUE);
+    std::thread::spawn(move || {
+        use std::net::{TcpStream, ToSocketAddrs};
+        for (title, target) in receiver {
+            let reachable = target
+                .to_socket_addrs()
+                .map(|mut addrs| {
+                    addrs.any(|addr| TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).is_ok())
+                })
+                .unwrap_or(false);
+            if !reachable {
+                log::warn!(
+                    "Gateway node '{}' target {} is not reachable right now, its rules won't route until it is",
+                    title,
+                    target
+                );
+            }
+        }
+    });
+    sender
+});
+
+/// Warns in the background when a TCP target doesn't accept connections
+///
+/// Only a hint for the operator, the target may simply not be started yet.
+/// Probes are skipped while the queue is full, e.g. during a large bulk import.
+fn probe_target(title: &str, target: &str) {
+    if netaddr::unix_socket_path(target).is_some() {
+        return;
+    }
+    if PROBES.try_send((title.to_string(), target.to_string())).is_err() {
+        log::debug!("Probe queue is full, not probing {}", target);
+    }
+}
+
+/// Normalizes every entry of an access list, dropping blank ones
+pub(super) fn normalize_cidrs(cidrs: &[String]) -> Result<Vec<String>, String> {
+    cidrs
+        .iter()
+        .filter(|cidr| !cidr.trim().is_empty())
+        .map(|cidr| netaddr::normalize_cidr(cidr))
+        .collect()
+}
+
 /// Deletes a gateway node and its associated gateways
 ///
 /// This endpoint processes HTTP POST requests to delete gateway nodes. It implements
@@ -229,24 +297,15 @@ pub async fn delete_gateway_node(
     req: HttpRequest,
     req_body: web::Json<DeleteRequest>
 ) -> impl Responder {
-    // Extract authenticated user's claims
-    let claims = match req.get_claims() {
-        Some(claims) => claims,
-        None => {
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Failed to get user authentication"})
-            )
-        }
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
     };
-    
-    // Verify user has admin or staff role
-    if !is_staff_or_admin(&claims.role) {
-        return HttpResponse::Forbidden().json(
-            serde_json::json!({"error": "Only administrators and staff can delete gateway nodes"})
-        );
-    }
-    
+
     let id = &req_body.id;
+    if let Err(response) = access.check_gateway_node(id, Scope::Write) {
+        return response;
+    }
 
     // Get gateway node details for better error messages
     let node_name = match gwnode_queries::get_gateway_node_by_id(id) {
@@ -280,6 +339,15 @@ pub async fn delete_gateway_node(
                 }
             }
             
+            // Gateways in the trash still reference the node, they go with it
+            if let Err(err) = gateway_queries::delete_trashed_gateways_by_gwnode_id(id) {
+                log::error!("Failed to delete deleted gateways of node {}: {}", id, err);
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Failed to delete associated gateways for '{}': {}", node_name, err),
+                    "gateway_node_id": id
+                }));
+            }
+            
             // Now delete the gateway node itself
             match gwnode_queries::delete_gateway_node_by_id(id) {
                 Ok(true) => {
@@ -0,0 +1,402 @@
+//! Config linter.
+//!
+//! Looks for routing that can't work anymore after edits, which otherwise only
+//! shows up as requests ending on the fallback page:
+//!
+//! - `orphaned_gwnode`: gateway nodes whose proxy was deleted, `unbound` or in the trash
+//! - `dangling_gateway`: gateways referring to a gateway node that doesn't exist
+//! - `dangling_domain`: gateway nodes referring to a domain that doesn't exist or
+//!   belongs to another proxy
+//! - `unreachable_rule`: gateways no request reaches, because a catch-all rule such as
+//!   `^(.*)$` comes first on the same proxy. The core routes to the first matching rule
+//!   regardless of the host, so domain bound rules are shadowed as well. Rules with the
+//!   catch-all's own path are its failover and not reported.
+//! - `duplicate_listen`: proxies listening on the same address, or on the same port
+//!   where one of them listens on every interface
+//! - `empty_proxy`: proxies without gateway nodes that aren't in speed mode, every
+//!   request gets the fallback page
+//!
+//! Nothing is changed, the warnings are for an operator to act on.
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+
+use actix_web::{get, HttpResponse, Responder};
+use serde::Serialize;
+
+use super::{gateway_queries, gwnode_queries, proxy_queries, proxydomain_queries};
+use super::{Gateway, GatewayNode, Proxy, ProxyDomain};
+
+/// `proxy_id` of gateway nodes whose proxy was deleted
+const UNBOUND: &str = "unbound";
+
+/// Patterns matching every path
+const CATCH_ALL_PATTERNS: [&str; 6] = ["^(.*)$", "^.*$", "(.*)", ".*", "^/(.*)$", "^/.*$"];
+
+/// One problem found in the configuration
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct LintWarning {
+    /// Kind of problem, e.g. `orphaned_gwnode`
+    pub kind: &'static str,
+    /// ID of the proxy, gateway node or gateway concerned
+    pub id: String,
+    /// What is wrong, naming the items involved
+    pub message: String,
+}
+
+impl LintWarning {
+    fn new(kind: &'static str, id: &str, message: String) -> Self {
+        Self {
+            kind,
+            id: id.to_string(),
+            message,
+        }
+    }
+}
+
+/// Whether `gateway` matches every path, so no later rule of its proxy is reached.
+///
+/// A rule with `strip_prefix` only matches below the prefix, and one whose
+/// gateway node runs a routing script may pass requests on.
+fn is_catch_all(gateway: &Gateway, node: &GatewayNode) -> bool {
+    CATCH_ALL_PATTERNS.contains(&gateway.pattern.trim())
+        && gateway.strip_prefix.as_deref().map_or(true, |prefix| prefix.trim().is_empty())
+        && node.route_script.as_deref().map_or(true, |script| script.trim().is_empty())
+}
+
+/// Whether two listen addresses can't both be bound
+fn listen_conflict(a: &str, b: &str) -> bool {
+    let (a, b) = (a.trim(), b.trim());
+    if a.eq_ignore_ascii_case(b) {
+        return true;
+    }
+    match (a.parse::<SocketAddr>(), b.parse::<SocketAddr>()) {
+        (Ok(a), Ok(b)) => {
+            a.port() == b.port()
+                && a.is_ipv4() == b.is_ipv4()
+                && (a.ip() == b.ip() || a.ip().is_unspecified() || b.ip().is_unspecified())
+        }
+        _ => false,
+    }
+}
+
+/// Checks the live configuration, returning the warnings grouped by kind.
+pub fn lint(
+    proxies: &[Proxy],
+    domains: &[ProxyDomain],
+    nodes: &[GatewayNode],
+    gateways: &[Gateway],
+) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let proxies_by_id: HashMap<&str, &Proxy> = proxies.iter().map(|proxy| (proxy.id.as_str(), proxy)).collect();
+    let nodes_by_id: HashMap<&str, &GatewayNode> = nodes.iter().map(|node| (node.id.as_str(), node)).collect();
+
+    for node in nodes {
+        if node.proxy_id == UNBOUND {
+            warnings.push(LintWarning::new(
+                "orphaned_gwnode",
+                &node.id,
+                format!("Gateway node '{}' is unbound, its proxy was deleted", node.title),
+            ));
+        } else if !proxies_by_id.contains_key(node.proxy_id.as_str()) {
+            warnings.push(LintWarning::new(
+                "orphaned_gwnode",
+                &node.id,
+                format!(
+                    "Gateway node '{}' belongs to proxy '{}', which doesn't exist or is in the trash",
+                    node.title, node.proxy_id
+                ),
+            ));
+        }
+    }
+
+    for gateway in gateways {
+        if !nodes_by_id.contains_key(gateway.gwnode_id.as_str()) {
+            warnings.push(LintWarning::new(
+                "dangling_gateway",
+                &gateway.id,
+                format!(
+                    "Gateway '{}' refers to gateway node '{}', which doesn't exist",
+                    gateway.pattern, gateway.gwnode_id
+                ),
+            ));
+        }
+    }
+
+    for node in nodes {
+        let Some(domain_id) = node.domain_id.as_deref().filter(|id| !id.is_empty()) else {
+            continue;
+        };
+        match domains.iter().find(|domain| domain.id == domain_id) {
+            None => warnings.push(LintWarning::new(
+                "dangling_domain",
+                &node.id,
+                format!("Gateway node '{}' refers to domain '{}', which doesn't exist", node.title, domain_id),
+            )),
+            Some(domain) if domain.proxy_id.as_deref() != Some(node.proxy_id.as_str()) => {
+                warnings.push(LintWarning::new(
+                    "dangling_domain",
+                    &node.id,
+                    format!(
+                        "Gateway node '{}' refers to domain '{}' of another proxy",
+                        node.title,
+                        domain.sni.as_deref().unwrap_or(domain_id)
+                    ),
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+
+    for proxy in proxies {
+        // The proxy's rules in the order the core evaluates them
+        let mut rules: Vec<(&Gateway, &GatewayNode)> = gateways
+            .iter()
+            .filter_map(|gateway| nodes_by_id.get(gateway.gwnode_id.as_str()).map(|node| (gateway, *node)))
+            .filter(|(_, node)| node.proxy_id == proxy.id)
+            .collect();
+        rules.sort_by(|(a, a_node), (b, b_node)| {
+            a.priority
+                .cmp(&b.priority)
+                .then_with(|| b_node.priority.cmp(&a_node.priority))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+
+        let Some(position) = rules.iter().position(|(gateway, node)| is_catch_all(gateway, node)) else {
+            continue;
+        };
+        let (catch_all, _) = rules[position];
+        for (gateway, node) in &rules[position + 1..] {
+            if gateway.pattern.trim() == catch_all.pattern.trim() && gateway.strip_prefix == catch_all.strip_prefix {
+                continue;
+            }
+            warnings.push(LintWarning::new(
+                "unreachable_rule",
+                &gateway.id,
+                format!(
+                    "Gateway '{}' of gateway node '{}' is never reached, catch-all '{}' (priority {}) of proxy '{}' matches first",
+                    gateway.pattern, node.title, catch_all.pattern, catch_all.priority, proxy.title
+                ),
+            ));
+        }
+    }
+
+    for (index, proxy) in proxies.iter().enumerate() {
+        for other in &proxies[index + 1..] {
+            if listen_conflict(&proxy.addr_listen, &other.addr_listen) {
+                warnings.push(LintWarning::new(
+                    "duplicate_listen",
+                    &other.id,
+                    format!(
+                        "Proxies '{}' ({}) and '{}' ({}) listen on the same address",
+                        proxy.title, proxy.addr_listen, other.title, other.addr_listen
+                    ),
+                ));
+            }
+        }
+    }
+
+    let bound: HashSet<&str> = nodes.iter().map(|node| node.proxy_id.as_str()).collect();
+    for proxy in proxies {
+        if !proxy.high_speed && !bound.contains(proxy.id.as_str()) {
+            warnings.push(LintWarning::new(
+                "empty_proxy",
+                &proxy.id,
+                format!(
+                    "Proxy '{}' has no gateway nodes, every request gets the fallback page",
+                    proxy.title
+                ),
+            ));
+        }
+    }
+
+    warnings
+}
+
+/// Lists problems of the current configuration
+///
+/// # Endpoint
+///
+/// `GET /settings/lint`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"warnings": [{"kind": "unreachable_rule", "id": .., "message": ..}]}`, empty when
+/// nothing was found. See the module documentation for the kinds.
+///
+/// ## Internal Server Error (500)
+/// Returned when the configuration could not be read from the database.
+#[get("/lint")]
+pub async fn lint_config() -> impl Responder {
+    let config = proxy_queries::get_all_proxies().and_then(|proxies| {
+        Ok((
+            proxies,
+            proxydomain_queries::get_all_proxy_domains()?,
+            gwnode_queries::get_all_gateway_nodes()?,
+            gateway_queries::get_all_gateways()?,
+        ))
+    });
+    match config {
+        Ok((proxies, domains, nodes, gateways)) => HttpResponse::Ok().json(serde_json::json!({
+            "warnings": lint(&proxies, &domains, &nodes, &gateways),
+        })),
+        Err(e) => {
+            log::error!("Failed to read the configuration for linting: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to read the configuration: {}", e)
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(id: &str, addr_listen: &str) -> Proxy {
+        Proxy {
+            id: id.to_string(),
+            title: id.to_string(),
+            addr_listen: addr_listen.to_string(),
+            addr_target: "127.0.0.1:40001".to_string(),
+            high_speed: false,
+            high_speed_addr: None,
+            high_speed_gwid: None,
+            redirect_to_https: false,
+            redirect_https_port: None,
+            deleted_at: None,
+            tcp_nodelay: true,
+            keepalive_secs: None,
+            keepalive_count: None,
+            buffer_size: None,
+            maintenance: false,
+            tls_min_version: None,
+            tls_ciphers: None,
+            tls_require_sni: false,
+            fallback: None,
+            tag: None,
+        }
+    }
+
+    fn node(id: &str, proxy_id: &str, priority: i32) -> GatewayNode {
+        GatewayNode {
+            id: id.to_string(),
+            proxy_id: proxy_id.to_string(),
+            title: id.to_string(),
+            alt_target: "127.0.0.1:3000".to_string(),
+            priority,
+            domain_id: None,
+            domain_name: None,
+            compress: false,
+            allow_cidrs: Vec::new(),
+            deny_cidrs: Vec::new(),
+            connect_timeout_secs: None,
+            header_timeout_secs: None,
+            total_timeout_secs: None,
+            maintenance: false,
+            redact_fields: Vec::new(),
+            redact_mode: None,
+            route_script: None,
+            cors_origins: Vec::new(),
+            cors_methods: Vec::new(),
+            cors_headers: Vec::new(),
+            server_header: None,
+            strip_server_headers: false,
+        }
+    }
+
+    fn gateway(id: &str, gwnode_id: &str, pattern: &str, priority: i32) -> Gateway {
+        Gateway {
+            id: id.to_string(),
+            gwnode_id: gwnode_id.to_string(),
+            pattern: pattern.to_string(),
+            target: "/$1".to_string(),
+            priority,
+            strip_prefix: None,
+            deleted_at: None,
+            sticky: None,
+            split: Vec::new(),
+            mirror_target: None,
+        }
+    }
+
+    fn kinds(warnings: &[LintWarning]) -> Vec<(&'static str, &str)> {
+        warnings.iter().map(|warning| (warning.kind, warning.id.as_str())).collect()
+    }
+
+    #[test]
+    fn test_clean_config_has_no_warnings() {
+        let proxies = vec![proxy("p1", "0.0.0.0:80"), proxy("p2", "0.0.0.0:81")];
+        let nodes = vec![node("n1", "p1", 100), node("n2", "p2", 100)];
+        let gateways = vec![
+            gateway("g1", "n1", "^/api/(.*)$", 1),
+            gateway("g2", "n1", "^(.*)$", 10),
+            gateway("g3", "n2", "^(.*)$", 1),
+        ];
+        assert!(lint(&proxies, &[], &nodes, &gateways).is_empty());
+    }
+
+    #[test]
+    fn test_orphaned_and_dangling_references() {
+        let proxies = vec![proxy("p1", "0.0.0.0:80")];
+        let mut bound = node("n1", "p1", 100);
+        bound.domain_id = Some("missing".to_string());
+        let nodes = vec![bound, node("n2", UNBOUND, 100), node("n3", "trashed", 100)];
+        let gateways = vec![gateway("g1", "n1", "^/a$", 1), gateway("g2", "gone", "^/b$", 1)];
+
+        assert_eq!(
+            kinds(&lint(&proxies, &[], &nodes, &gateways)),
+            vec![
+                ("orphaned_gwnode", "n2"),
+                ("orphaned_gwnode", "n3"),
+                ("dangling_gateway", "g2"),
+                ("dangling_domain", "n1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rules_after_a_catch_all_are_unreachable() {
+        let proxies = vec![proxy("p1", "0.0.0.0:80")];
+        let nodes = vec![node("n1", "p1", 100), node("n2", "p1", 50)];
+        let gateways = vec![
+            gateway("g1", "n1", "^(.*)$", 1),
+            gateway("g2", "n1", "^/api/(.*)$", 5),
+            // Same path on another node, the catch-all's failover
+            gateway("g3", "n2", "^(.*)$", 1),
+            gateway("g4", "n2", "^/admin$", 0),
+        ];
+        assert_eq!(kinds(&lint(&proxies, &[], &nodes, &gateways)), vec![("unreachable_rule", "g2")]);
+
+        // Below a prefix or with a routing script it's not a catch-all
+        let mut prefixed = gateways.clone();
+        prefixed[0].strip_prefix = Some("/app".to_string());
+        prefixed[2].strip_prefix = Some("/app".to_string());
+        assert!(lint(&proxies, &[], &nodes, &prefixed).is_empty());
+
+        let mut scripted = nodes.clone();
+        scripted[0].route_script = Some("use_rule()".to_string());
+        scripted[1].route_script = Some("use_rule()".to_string());
+        assert!(lint(&proxies, &[], &scripted, &gateways).is_empty());
+    }
+
+    #[test]
+    fn test_listen_conflicts_and_empty_proxies() {
+        let mut speed = proxy("p3", "127.0.0.1:80");
+        speed.high_speed = true;
+        let proxies = vec![proxy("p1", "0.0.0.0:80"), proxy("p2", "0.0.0.0:81"), speed];
+        let nodes = vec![node("n1", "p1", 100)];
+
+        assert_eq!(
+            kinds(&lint(&proxies, &[], &nodes, &[])),
+            vec![("duplicate_listen", "p3"), ("empty_proxy", "p2")]
+        );
+
+        assert!(listen_conflict("0.0.0.0:80", " 0.0.0.0:80 "));
+        assert!(listen_conflict("[::]:443", "[::1]:443"));
+        assert!(!listen_conflict("127.0.0.1:80", "127.0.0.2:80"));
+        assert!(!listen_conflict("0.0.0.0:80", "[::1]:80"));
+        assert!(!listen_conflict("0.0.0.0:80", "0.0.0.0:8080"));
+    }
+}
@@ -0,0 +1,166 @@
+//! Runtime log levels of the core.
+//!
+//! The core filters its proxy, gateway and protocol log lines per component
+//! before they reach the log queues. These endpoints read and change those
+//! levels without a restart, e.g. to debug the proxy for a few minutes.
+
+use std::sync::Arc;
+
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+
+use crate::module::httpc::HttpC;
+
+const LEVELS: [&str; 5] = ["trace", "debug", "info", "warn", "error"];
+
+/// Requested component levels; omitted components keep their level
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogLevelRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+}
+
+impl LogLevelRequest {
+    /// Lower-cases every level and rejects unknown names.
+    fn normalize(mut self) -> Result<Self, String> {
+        for (component, level) in [
+            ("proxy", &mut self.proxy),
+            ("gateway", &mut self.gateway),
+            ("protocol", &mut self.protocol),
+        ] {
+            if let Some(value) = level {
+                let normalized = value.trim().to_lowercase();
+                if !LEVELS.contains(&normalized.as_str()) {
+                    return Err(format!(
+                        "Invalid {} log level '{}', expected one of: {}",
+                        component,
+                        value,
+                        LEVELS.join(", ")
+                    ));
+                }
+                *value = normalized;
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Returns the current log level of every core component
+///
+/// # Endpoint
+///
+/// `GET /api/v1/settings/log/level`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"proxy": "info", "gateway": "info", "protocol": "info"}`
+///
+/// ## Bad Gateway (502)
+/// Returned when the core could not be reached.
+#[get("/log/level")]
+pub async fn get_log_level(client: web::Data<Arc<HttpC>>) -> impl Responder {
+    match client.post_with_response("/log/level", &[]) {
+        Ok(body) => {
+            let levels = serde_json::from_str::<serde_json::Value>(&body)
+                .unwrap_or(serde_json::Value::Null);
+            HttpResponse::Ok().json(levels)
+        }
+        Err(e) => {
+            log::error!("Failed to fetch core log levels: {}", e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Core unreachable: {}", e)
+            }))
+        }
+    }
+}
+
+/// Changes the log level of one or more core components
+///
+/// # Endpoint
+///
+/// `POST /api/v1/settings/log/level`
+///
+/// # Request Body
+///
+/// `{"proxy": "debug"}` - any of `proxy`, `gateway`, `protocol`, each one of
+/// `trace`, `debug`, `info`, `warn`, `error`. The change lasts until the core restarts.
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// The levels of every component after the change.
+///
+/// ## Bad Request (400)
+/// Returned for unknown level names.
+///
+/// ## Bad Gateway (502)
+/// Returned when the core could not be reached.
+#[post("/log/level")]
+pub async fn set_log_level(
+    client: web::Data<Arc<HttpC>>,
+    req_body: web::Json<LogLevelRequest>,
+) -> impl Responder {
+    let levels = match req_body.into_inner().normalize() {
+        Ok(levels) => levels,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+        }
+    };
+
+    let payload = match serde_json::to_vec(&levels) {
+        Ok(payload) => payload,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to serialize log levels: {}", e)
+            }));
+        }
+    };
+
+    match client.post_with_response("/log/level", &payload) {
+        Ok(body) => {
+            log::info!("Core log levels updated: {}", body);
+            let levels = serde_json::from_str::<serde_json::Value>(&body)
+                .unwrap_or(serde_json::Value::Null);
+            HttpResponse::Ok().json(levels)
+        }
+        Err(e) => {
+            log::error!("Failed to update core log levels: {}", e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Core unreachable: {}", e)
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_accepts_known_levels() {
+        let request = LogLevelRequest {
+            proxy: Some(" DEBUG ".to_string()),
+            gateway: None,
+            protocol: Some("warn".to_string()),
+        };
+        let normalized = request.normalize().unwrap();
+        assert_eq!(normalized.proxy.as_deref(), Some("debug"));
+        assert_eq!(normalized.gateway, None);
+        assert_eq!(normalized.protocol.as_deref(), Some("warn"));
+    }
+
+    #[test]
+    fn test_normalize_rejects_unknown_levels() {
+        let request = LogLevelRequest {
+            proxy: None,
+            gateway: Some("loud".to_string()),
+            protocol: None,
+        };
+        assert!(request.normalize().unwrap_err().contains("gateway"));
+    }
+}
@@ -0,0 +1,121 @@
+//! Maintenance mode of proxies and gateway nodes.
+//!
+//! A proxy or gateway node in maintenance keeps its config, but the gateway
+//! answers its rules with a 503 maintenance page and a `Retry-After` header
+//! instead of forwarding. Speed mode proxies relay their connections to the
+//! core's 503 page instead of their target. The flag reaches the core with the
+//! next background sync, within a few seconds of the toggle.
+//!
+//! Saving a proxy or gateway node keeps the mode it is in, these toggles are
+//! the only way to change it.
+
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+
+use super::access::Access;
+use super::permission_queries::Scope;
+use super::{gwnode_queries, proxy_queries};
+
+/// Body of the maintenance toggles
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    /// `true` to answer with the maintenance page, `false` to forward again
+    pub enabled: bool,
+}
+
+/// Turns maintenance mode of a proxy on or off
+///
+/// Every rule of every gateway node of the proxy is answered with 503.
+///
+/// # Endpoint
+///
+/// `POST /settings/proxy/{id}/maintenance` with `{"enabled": true}`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"id": "..", "maintenance": true}`
+///
+/// ## Not Found (404)
+/// Returned when no live proxy has this ID.
+///
+/// ## Internal Server Error (500)
+/// Returned when there is a database error.
+#[post("/proxy/{id}/maintenance")]
+pub async fn set_proxy_maintenance(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<MaintenanceRequest>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if let Err(response) = Access::of(&req).and_then(|access| access.check_proxy(&id, Scope::Write)) {
+        return response;
+    }
+    match proxy_queries::set_proxy_maintenance(&id, body.enabled) {
+        Ok(true) => {
+            log::info!("Proxy {} maintenance mode {}", id, on_off(body.enabled));
+            HttpResponse::Ok().json(serde_json::json!({"id": id, "maintenance": body.enabled}))
+        }
+        Ok(false) => HttpResponse::NotFound()
+            .json(serde_json::json!({"error": format!("Proxy {} not found", id)})),
+        Err(e) => {
+            log::error!("Error setting maintenance mode of proxy {}: {}", id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to set maintenance mode: {}", e)
+            }))
+        }
+    }
+}
+
+/// Turns maintenance mode of a gateway node on or off
+///
+/// The rules of the node are answered with 503, other nodes of the same proxy
+/// keep forwarding.
+///
+/// # Endpoint
+///
+/// `POST /settings/gwnode/{id}/maintenance` with `{"enabled": true}`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"id": "..", "maintenance": true}`
+///
+/// ## Not Found (404)
+/// Returned when no gateway node has this ID.
+///
+/// ## Internal Server Error (500)
+/// Returned when there is a database error.
+#[post("/gwnode/{id}/maintenance")]
+pub async fn set_gateway_node_maintenance(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<MaintenanceRequest>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if let Err(response) = Access::of(&req).and_then(|access| access.check_gateway_node(&id, Scope::Write)) {
+        return response;
+    }
+    match gwnode_queries::set_gateway_node_maintenance(&id, body.enabled) {
+        Ok(true) => {
+            log::info!("Gateway node {} maintenance mode {}", id, on_off(body.enabled));
+            HttpResponse::Ok().json(serde_json::json!({"id": id, "maintenance": body.enabled}))
+        }
+        Ok(false) => HttpResponse::NotFound()
+            .json(serde_json::json!({"error": format!("Gateway node {} not found", id)})),
+        Err(e) => {
+            log::error!("Error setting maintenance mode of gateway node {}: {}", id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to set maintenance mode: {}", e)
+            }))
+        }
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
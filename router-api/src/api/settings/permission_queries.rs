@@ -0,0 +1,192 @@
+//! # Permission Database Operations
+//!
+//! This module provides database operations for the permissions of users on
+//! proxy tags, and the tag lookups the settings handlers check them against.
+//! A permission is removed together with its user.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::module::database::{get_connection, DatabaseError};
+use crate::module::migrations;
+
+/// What a permission allows on the proxies of a tag, `Write` includes `Read`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// List and fetch the proxies, their gateway nodes and gateways
+    Read,
+    /// Also create, change, delete and restore them
+    Write,
+}
+
+impl Scope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+        }
+    }
+
+    /// Scope stored in the `scope` column, the table only allows these two
+    fn from_column(value: &str) -> Self {
+        match value {
+            "write" => Scope::Write,
+            _ => Scope::Read,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Permission of a user on the proxies carrying a tag
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Permission {
+    /// ID of the user the permission is granted to
+    pub user_id: String,
+    /// Proxy tag the permission applies to
+    pub tag: String,
+    /// What the user may do with the proxies of the tag
+    pub scope: Scope,
+}
+
+/// Checks that the permissions table has the columns this module reads and writes
+///
+/// The table and `proxies.tag` are created by `module::migrations` only.
+///
+/// # Database Schema
+///
+/// - `user_id`: TEXT NOT NULL - User the permission is granted to, deleted with the user
+/// - `tag`: TEXT NOT NULL - Proxy tag the permission applies to
+/// - `scope`: TEXT NOT NULL - `read` or `write`
+///
+/// `(user_id, tag)` is the primary key, a user has one scope per tag.
+pub fn ensure_permissions_table() -> Result<(), DatabaseError> {
+    migrations::ensure_table("permissions", &["user_id", "tag", "scope"])?;
+    migrations::ensure_table("proxies", &["id", "tag"])
+}
+
+fn permission_from_row(row: &rusqlite::Row) -> rusqlite::Result<Permission> {
+    Ok(Permission {
+        user_id: row.get(0)?,
+        tag: row.get(1)?,
+        scope: Scope::from_column(&row.get::<_, String>(2)?),
+    })
+}
+
+/// Retrieves the permissions of one user, or of every user when `user_id` is `None`
+pub fn get_permissions(user_id: Option<&str>) -> Result<Vec<Permission>, DatabaseError> {
+    ensure_permissions_table()?;
+
+    let db = get_connection()?;
+    match user_id {
+        Some(user_id) => db.query(
+            "SELECT user_id, tag, scope FROM permissions WHERE user_id = ?1 ORDER BY tag",
+            [user_id],
+            permission_from_row,
+        ),
+        None => db.query(
+            "SELECT user_id, tag, scope FROM permissions ORDER BY user_id, tag",
+            [],
+            permission_from_row,
+        ),
+    }
+}
+
+/// Grants a permission, replacing the scope the user had on the tag
+pub fn save_permission(permission: &Permission) -> Result<(), DatabaseError> {
+    ensure_permissions_table()?;
+
+    let db = get_connection()?;
+    db.execute(
+        "INSERT OR REPLACE INTO permissions (user_id, tag, scope) VALUES (?1, ?2, ?3)",
+        rusqlite::params![&permission.user_id, &permission.tag, permission.scope.as_str()],
+    )?;
+    Ok(())
+}
+
+/// Revokes the permission of a user on a tag, returns whether there was one
+pub fn delete_permission(user_id: &str, tag: &str) -> Result<bool, DatabaseError> {
+    ensure_permissions_table()?;
+
+    let db = get_connection()?;
+    let affected_rows = db.execute(
+        "DELETE FROM permissions WHERE user_id = ?1 AND tag = ?2",
+        [user_id, tag],
+    )?;
+    Ok(affected_rows > 0)
+}
+
+/// Whether a user with this ID exists
+pub fn user_exists(user_id: &str) -> Result<bool, DatabaseError> {
+    let db = get_connection()?;
+    Ok(db
+        .query_one("SELECT id FROM users WHERE id = ?1", [user_id], |row| row.get::<_, String>(0))?
+        .is_some())
+}
+
+/// Runs a lookup returning the tag of at most one row, `None` when there is no row
+fn tag_of(sql: &str, id: &str) -> Result<Option<Option<String>>, DatabaseError> {
+    ensure_permissions_table()?;
+
+    let db = get_connection()?;
+    db.query_one(sql, [id], |row| row.get::<_, Option<String>>(0))
+}
+
+/// Tag of a proxy, live or in the trash
+///
+/// # Returns
+///
+/// `Ok(None)` when the proxy doesn't exist, `Ok(Some(None))` when it is untagged.
+pub fn tag_of_proxy(id: &str) -> Result<Option<Option<String>>, DatabaseError> {
+    tag_of("SELECT tag FROM proxies WHERE id = ?1", id)
+}
+
+/// Tag of the proxy of a gateway node, untagged for a node without a proxy
+pub fn tag_of_gateway_node(id: &str) -> Result<Option<Option<String>>, DatabaseError> {
+    tag_of(
+        "SELECT p.tag FROM gateway_nodes n LEFT JOIN proxies p ON p.id = n.proxy_id WHERE n.id = ?1",
+        id,
+    )
+}
+
+/// Tag of the proxy of a gateway's node, live or in the trash
+pub fn tag_of_gateway(id: &str) -> Result<Option<Option<String>>, DatabaseError> {
+    tag_of(
+        "SELECT p.tag FROM gateways g
+         LEFT JOIN gateway_nodes n ON n.id = g.gwnode_id
+         LEFT JOIN proxies p ON p.id = n.proxy_id
+         WHERE g.id = ?1",
+        id,
+    )
+}
+
+/// Tag of every proxy by proxy ID, trashed ones included
+pub fn proxy_tags() -> Result<HashMap<String, Option<String>>, DatabaseError> {
+    ensure_permissions_table()?;
+
+    let db = get_connection()?;
+    let tags = db.query("SELECT id, tag FROM proxies", [], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+    })?;
+    Ok(tags.into_iter().collect())
+}
+
+/// Tag of the proxy of every gateway node by node ID
+pub fn gateway_node_tags() -> Result<HashMap<String, Option<String>>, DatabaseError> {
+    ensure_permissions_table()?;
+
+    let db = get_connection()?;
+    let tags = db.query(
+        "SELECT n.id, p.tag FROM gateway_nodes n LEFT JOIN proxies p ON p.id = n.proxy_id",
+        [],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+    )?;
+    Ok(tags.into_iter().collect())
+}
@@ -0,0 +1,141 @@
+//! Permissions of users on proxy tags.
+//!
+//! A permission gives a user `read` or `write` access to the proxies carrying a
+//! tag, with their gateway nodes and gateways, see `access` for what each scope
+//! allows. Only administrators manage permissions, they don't need any themselves.
+
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+
+use super::permission_queries::{self, Permission};
+use super::rule_validation;
+
+/// Query parameters of the permission list
+#[derive(Debug, Deserialize)]
+pub struct PermissionQuery {
+    /// Only list the permissions of this user
+    pub user_id: Option<String>,
+}
+
+/// Lists the permissions of every user, or of one with `?user_id=`
+///
+/// # Endpoint
+///
+/// `GET /settings/permissions`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `[{"user_id": "..", "tag": "team-a", "scope": "write"}]`
+///
+/// ## Internal Server Error (500)
+/// Returned when there is a database error.
+#[get("/permissions")]
+pub async fn list_permissions(query: web::Query<PermissionQuery>) -> impl Responder {
+    match permission_queries::get_permissions(query.user_id.as_deref()) {
+        Ok(permissions) => HttpResponse::Ok().json(permissions),
+        Err(e) => {
+            log::error!("Failed to list permissions: {}", e);
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": format!("Failed to list permissions: {}", e)}))
+        }
+    }
+}
+
+/// Grants a user a permission on a tag, replacing the scope they had on it
+///
+/// # Endpoint
+///
+/// `POST /settings/permissions` with `{"user_id": "..", "tag": "team-a", "scope": "read"}`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// Returns the saved permission.
+///
+/// ## Bad Request (400)
+/// Returned for a blank or invalid tag.
+///
+/// ## Not Found (404)
+/// Returned when no user has this ID.
+///
+/// ## Internal Server Error (500)
+/// Returned when there is a database error.
+#[post("/permissions")]
+pub async fn set_permission(req_body: web::Json<Permission>) -> impl Responder {
+    let mut permission = req_body.into_inner();
+    permission.tag = match rule_validation::normalize_tag(&permission.tag) {
+        Ok(Some(tag)) => tag,
+        Ok(None) => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": "A permission needs a tag"}))
+        }
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+
+    match permission_queries::user_exists(&permission.user_id) {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::NotFound().json(
+                serde_json::json!({"error": format!("User {} not found", permission.user_id)}),
+            )
+        }
+        Err(e) => {
+            log::error!("Error checking user {}: {}", permission.user_id, e);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": format!("Failed to check user: {}", e)}));
+        }
+    }
+
+    match permission_queries::save_permission(&permission) {
+        Ok(()) => {
+            log::info!(
+                "User {} granted {} on tag '{}'",
+                permission.user_id,
+                permission.scope,
+                permission.tag
+            );
+            HttpResponse::Ok().json(permission)
+        }
+        Err(e) => {
+            log::error!("Error saving permission of user {}: {}", permission.user_id, e);
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": format!("Failed to save permission: {}", e)}))
+        }
+    }
+}
+
+/// Revokes the permission of a user on a tag
+///
+/// # Endpoint
+///
+/// `DELETE /settings/permissions/{user_id}/{tag}`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `{"message": "Permission revoked"}`
+///
+/// ## Not Found (404)
+/// Returned when the user has no permission on the tag.
+///
+/// ## Internal Server Error (500)
+/// Returned when there is a database error.
+#[delete("/permissions/{user_id}/{tag}")]
+pub async fn delete_permission(path: web::Path<(String, String)>) -> impl Responder {
+    let (user_id, tag) = path.into_inner();
+    match permission_queries::delete_permission(&user_id, &tag) {
+        Ok(true) => {
+            log::info!("User {} lost the permission on tag '{}'", user_id, tag);
+            HttpResponse::Ok().json(serde_json::json!({"message": "Permission revoked"}))
+        }
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("User {} has no permission on tag '{}'", user_id, tag)
+        })),
+        Err(e) => {
+            log::error!("Error revoking permission of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": format!("Failed to revoke permission: {}", e)}))
+        }
+    }
+}
@@ -1,5 +1,7 @@
-use super::{proxy_queries, proxydomain_queries};
-use actix_web::{get, web, HttpResponse, Responder};
+use super::access::Access;
+use super::permission_queries::Scope;
+use super::{etag, proxy_queries, proxydomain_queries};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
 use serde_json::json;
 
 /// Get a proxy by ID
@@ -7,8 +9,11 @@ use serde_json::json;
 /// This endpoint returns a specific proxy configuration by its ID,
 /// along with all associated proxy domains.
 #[get("/proxy/{id}")]
-pub async fn get_proxy(path: web::Path<String>) -> impl Responder {
+pub async fn get_proxy(req: HttpRequest, path: web::Path<String>) -> impl Responder {
     let id = path.into_inner();
+    if let Err(response) = Access::of(&req).and_then(|access| access.check_proxy(&id, Scope::Read)) {
+        return response;
+    }
 
     match proxy_queries::get_proxy_by_id(&id) {
         Ok(Some(proxy)) => {
@@ -16,7 +21,7 @@ pub async fn get_proxy(path: web::Path<String>) -> impl Responder {
             match proxydomain_queries::get_proxy_domains_by_proxy_id(&id) {
                 Ok(domains) => {
                     // Return combined proxy and domains
-                    HttpResponse::Ok().json(json!({
+                    etag::json(&req, &json!({
                         "proxy": proxy,
                         "domains": domains
                     }))
@@ -24,7 +29,7 @@ pub async fn get_proxy(path: web::Path<String>) -> impl Responder {
                 Err(e) => {
                     log::error!("Error fetching domains for proxy {}: {}", id, e);
                     // Return proxy with empty domains and warning
-                    HttpResponse::Ok().json(json!({
+                    etag::json(&req, &json!({
                         "proxy": proxy,
                         "domains": [],
                         "warning": "Could not fetch associated domains"
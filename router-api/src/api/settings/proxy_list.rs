@@ -1,15 +1,43 @@
-use super::{proxy_queries, proxydomain_queries};
-use actix_web::{get, HttpResponse, Responder};
+use super::access::Access;
+use super::{etag, proxy_queries, proxydomain_queries};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
 use serde_json::json;
 
+/// Query parameters of the list endpoints
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    /// Also list items in the trash, they carry a `deleted_at` timestamp
+    #[serde(default)]
+    pub include_deleted: bool,
+}
+
 /// List all proxies in the system
 ///
 /// This endpoint returns a list of all configured proxies
 /// along with their associated domains (simplified to ID, SNI and TLS status only).
+/// Proxies in the trash are only listed with `?include_deleted=true`, after the live ones.
+/// Proxies the user has no permission to read are left out.
 #[get("/proxies")]
-pub async fn list_proxies() -> impl Responder {
-    match proxy_queries::get_all_proxies() {
-        Ok(proxies) => {
+pub async fn list_proxies(req: HttpRequest, query: web::Query<ListQuery>) -> impl Responder {
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
+
+    let proxies = if query.include_deleted {
+        proxy_queries::get_all_proxies().and_then(|mut proxies| {
+            proxies.extend(proxy_queries::get_trashed_proxies()?);
+            Ok(proxies)
+        })
+    } else {
+        proxy_queries::get_all_proxies()
+    };
+
+    match proxies {
+        Ok(mut proxies) => {
+            access.filter_proxies(&mut proxies);
+
             // Create a vector to hold combined proxy+domains results
             let mut result = Vec::new();
             
@@ -44,7 +72,7 @@ pub async fn list_proxies() -> impl Responder {
                 }
             }
             
-            HttpResponse::Ok().json(result)
+            etag::json(&req, &result)
         },
         Err(e) => {
             log::error!("Failed to list proxies: {}", e);
@@ -1,176 +1,108 @@
 //! # Proxy Database Operations
 //!
 //! This module provides database operations for managing proxy configurations.
-//! It handles creating the database table, querying, inserting, updating, and
+//! It handles checking the database table, querying, inserting, updating, and
 //! deleting proxy records.
 
 use super::Proxy;
 use crate::module::database::{get_connection, DatabaseError};
+use crate::module::migrations;
 use rand::Rng;
 use std::net::TcpListener;
 use uuid;
 
-/// Creates the proxies table in the database if it doesn't already exist
+/// Checks that the proxies table has the columns this module reads and writes
 ///
-/// This function ensures that the database schema is properly initialized before
-/// any operations are performed. It is automatically called by other functions
+/// The table is created and upgraded by `module::migrations` only. A table missing
+/// columns makes this apply the pending migrations, it is never dropped or rebuilt.
+/// It is automatically called by other functions
 /// in this module, so there's usually no need to call it directly.
 ///
 /// # Database Schema
 ///
-/// Creates a table with the following structure:
+/// The table has the following structure:
 /// - `id`: TEXT PRIMARY KEY - Unique identifier for the proxy
 /// - `title`: TEXT NOT NULL - Human-readable name for the proxy
 /// - `addr_listen`: TEXT NOT NULL - Address where the proxy listens for connections
 /// - `addr_target`: TEXT NOT NULL - Target address where requests are forwarded
 /// - `high_speed`: BOOLEAN NOT NULL DEFAULT 0 - Whether speed mode is enabled
 /// - `high_speed_addr`: TEXT - Specific address to use for speed mode
+/// - `redirect_to_https`: BOOLEAN NOT NULL DEFAULT 0 - Whether plain HTTP is redirected to HTTPS
+/// - `redirect_https_port`: INTEGER - HTTPS port used in redirects (NULL for 443)
+/// - `deleted_at`: INTEGER - When the proxy was moved to the trash (unix seconds, NULL while live)
+/// - `tcp_nodelay`: BOOLEAN NOT NULL DEFAULT 1 - Whether Nagle's algorithm is disabled on the proxied sockets
+/// - `keepalive_secs`: INTEGER - TCP keepalive idle time and probe interval (NULL disables keepalive)
+/// - `keepalive_count`: INTEGER - Unanswered keepalive probes before the peer is dropped (NULL for the OS default)
+/// - `buffer_size`: INTEGER - Speed mode relay buffer per direction in bytes (NULL for the core default)
+/// - `maintenance`: BOOLEAN NOT NULL DEFAULT 0 - Whether the gateway answers the proxy's rules with 503
+/// - `tls_min_version`: TEXT - Lowest accepted TLS version, `1.2` or `1.3` (NULL for 1.2)
+/// - `tls_ciphers`: TEXT - Colon separated accepted ciphers (NULL for the core default)
+/// - `tls_require_sni`: BOOLEAN NOT NULL DEFAULT 0 - Whether TLS clients without SNI are refused
+/// - `fallback`: TEXT - Target of requests no gateway rule matches (NULL for the core default)
+/// - `tag`: TEXT - Tag users are given permissions for (NULL for administrators only)
 ///
 /// # Returns
 ///
-/// * `Ok(())` if the table exists or was created successfully
-/// * `Err(DatabaseError)` if there was an error creating the table
+/// * `Ok(())` if the table matches the current schema
+/// * `Err(DatabaseError)` if it doesn't, even after migrating
 ///
 /// # Errors
 ///
 /// This function will return an error if:
 /// - The database connection could not be established
-/// - The SQL statement to create the table could not be executed
+/// - The table still lacks columns once the pending migrations ran
 pub fn ensure_proxies_table() -> Result<(), DatabaseError> {
-    let db = get_connection()?;
-    
-    // Define the expected columns for proxies table
-    let expected_columns = ["id", "title", "addr_listen", "addr_target", "high_speed", "high_speed_addr", "high_speed_gwid"];
-    
-    // Check if the table exists with the expected columns and is not corrupted
-    let proxies_table_valid = db.table_exists_with_columns("proxies", &expected_columns)?;
-    
-    // Define the expected columns for proxy_domains table
-    let expected_domain_columns = ["id", "proxy_id", "tls", "tls_pem", "tls_key", "sni"];
-    
-    // Check if the proxy_domains table exists with the expected columns and is not corrupted
-    let proxy_domains_table_valid = db.table_exists_with_columns("proxy_domains", &expected_domain_columns)?;
-    
-    if proxies_table_valid && proxy_domains_table_valid {
-        log::debug!("proxies and proxy_domains tables exist and have expected structure");
-        return Ok(());
-    }
-    
-    log::info!("Creating or repairing proxies and/or proxy_domains tables");
-    
-    // Handle proxies table
-    if !proxies_table_valid {
-        // Check if an old version of the proxies table exists (with tls column)
-        let old_table_exists = db.query(
-            "SELECT tls FROM proxies LIMIT 1",
-            [],
-            |_| Ok(true)
-        ).is_ok();
-        
-        if old_table_exists {
-            log::info!("Migrating proxies table to new structure...");
-            
-            // Create temporary table with new structure
-            db.execute(
-                "CREATE TABLE proxies_new (
-                    id TEXT PRIMARY KEY,
-                    title TEXT NOT NULL,
-                    addr_listen TEXT NOT NULL,
-                    addr_target TEXT NOT NULL,
-                    high_speed BOOLEAN NOT NULL DEFAULT 0,
-                    high_speed_addr TEXT,
-                    high_speed_gwid TEXT
-                )",
-                [],
-            )?;
-            
-            // Copy data from old table to new table, dropping TLS-related fields
-            db.execute(
-                "INSERT INTO proxies_new (id, title, addr_listen, addr_target, high_speed, high_speed_addr)
-                SELECT id, title, addr_listen, addr_target, high_speed, high_speed_addr FROM proxies",
-                [],
-            )?;
-            
-            // Migrate TLS data to proxy_domains table if it exists
-            if !proxy_domains_table_valid {
-                // Create proxy_domains table first if it doesn't exist
-                db.execute("DROP TABLE IF EXISTS proxy_domains", [])?;
-                
-                db.execute(
-                    "CREATE TABLE proxy_domains (
-                        id TEXT PRIMARY KEY,
-                        proxy_id TEXT NOT NULL,
-                        tls BOOLEAN NOT NULL DEFAULT 0,
-                        tls_pem TEXT,
-                        tls_key TEXT,
-                        sni TEXT
-                    )",
-                    [],
-                )?;
-                
-                // Migrate TLS data to proxy_domains table
-                db.execute(
-                    "INSERT INTO proxy_domains (id, proxy_id, tls, tls_pem, tls_key, sni)
-                    SELECT hex(randomblob(16)), id, tls, tls_pem, tls_key, sni
-                    FROM proxies WHERE tls = 1",
-                    [],
-                )?;
-                
-                log::info!("Created proxy_domains table with correct structure");
-            }
-            
-            // Rename tables to complete migration
-            db.execute("DROP TABLE proxies", [])?;
-            db.execute("ALTER TABLE proxies_new RENAME TO proxies", [])?;
-            
-            log::info!("Migration completed successfully.");
-        } else {
-            // Drop the table if it exists but is corrupted or missing columns
-            db.execute("DROP TABLE IF EXISTS proxies", [])?;
-            
-            // Create the table with the full correct structure
-            db.execute(
-                "CREATE TABLE proxies (
-                    id TEXT PRIMARY KEY,
-                    title TEXT NOT NULL,
-                    addr_listen TEXT NOT NULL,
-                    addr_target TEXT NOT NULL,
-                    high_speed BOOLEAN NOT NULL DEFAULT 0,
-                    high_speed_addr TEXT,
-                    high_speed_gwid TEXT
-                )",
-                [],
-            )?;
-            
-            log::info!("Created proxies table with correct structure");
-        }
-    }
-    
-    // Handle proxy_domains table separately if needed
-    if !proxy_domains_table_valid {
-        db.execute("DROP TABLE IF EXISTS proxy_domains", [])?;
-        
-        db.execute(
-            "CREATE TABLE proxy_domains (
-                id TEXT PRIMARY KEY,
-                proxy_id TEXT NOT NULL,
-                tls BOOLEAN NOT NULL DEFAULT 0,
-                tls_pem TEXT,
-                tls_key TEXT,
-                sni TEXT
-            )",
-            [],
-        )?;
-        
-        log::info!("Created proxy_domains table with correct structure");
-    }
+    migrations::ensure_table(
+        "proxies",
+        &[
+            "id", "title", "addr_listen", "addr_target", "high_speed", "high_speed_addr", "high_speed_gwid",
+            "redirect_to_https", "redirect_https_port", "deleted_at",
+            "tcp_nodelay", "keepalive_secs", "keepalive_count", "buffer_size", "maintenance",
+            "tls_min_version", "tls_ciphers", "tls_require_sni", "fallback", "tag",
+        ],
+    )
+}
 
-    Ok(())
+/// Columns read by [`proxy_from_row`], in order
+const PROXY_COLUMNS: &str = "id, title, addr_listen, addr_target, high_speed, high_speed_addr, high_speed_gwid, redirect_to_https, redirect_https_port, deleted_at, tcp_nodelay, keepalive_secs, keepalive_count, buffer_size, maintenance, tls_min_version, tls_ciphers, tls_require_sni, fallback, tag";
+
+/// Maps a row selected with [`PROXY_COLUMNS`] to a `Proxy`
+fn proxy_from_row(row: &rusqlite::Row) -> rusqlite::Result<Proxy> {
+    Ok(Proxy {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        addr_listen: row.get(2)?,
+        addr_target: row.get(3)?,
+        high_speed: row.get(4)?,
+        high_speed_addr: match row.get::<_, String>(5) {
+            Ok(s) if s == "\u{0000}" => None,
+            Ok(s) => Some(s),
+            Err(_) => None,
+        },
+        high_speed_gwid: match row.get::<_, String>(6) {
+            Ok(s) if s == "\u{0000}" => None,
+            Ok(s) => Some(s),
+            Err(_) => None,
+        },
+        redirect_to_https: row.get(7)?,
+        redirect_https_port: row.get::<_, Option<u16>>(8).unwrap_or(None),
+        deleted_at: row.get(9)?,
+        tcp_nodelay: row.get(10)?,
+        keepalive_secs: row.get(11)?,
+        keepalive_count: row.get(12)?,
+        buffer_size: row.get(13)?,
+        maintenance: row.get(14)?,
+        tls_min_version: row.get(15)?,
+        tls_ciphers: row.get(16)?,
+        tls_require_sni: row.get(17)?,
+        fallback: row.get(18)?,
+        tag: row.get(19)?,
+    })
 }
 
 /// Retrieves all proxy configurations from the database
 ///
-/// This function fetches all proxy records from the database and converts
+/// This function fetches all proxy records that are not in the trash and converts
 /// them into `Proxy` structures. It automatically ensures the database table
 /// exists before performing the query.
 ///
@@ -210,27 +142,9 @@ pub fn get_all_proxies() -> Result<Vec<Proxy>, DatabaseError> {
 
     // Query all proxies
     let proxies = db.query(
-        "SELECT id, title, addr_listen, addr_target, high_speed, high_speed_addr, high_speed_gwid FROM proxies",
+        &format!("SELECT {} FROM proxies WHERE deleted_at IS NULL", PROXY_COLUMNS),
         [],
-        |row| {
-            Ok(Proxy {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                addr_listen: row.get(2)?,
-                addr_target: row.get(3)?,
-                high_speed: row.get(4)?,
-                high_speed_addr: match row.get::<_, String>(5) {
-                    Ok(s) if s == "\u{0000}" => None,
-                    Ok(s) => Some(s),
-                    Err(_) => None,
-                },
-                high_speed_gwid: match row.get::<_, String>(6) {
-                    Ok(s) if s == "\u{0000}" => None,
-                    Ok(s) => Some(s),
-                    Err(_) => None,
-                },
-            })
-        },
+        proxy_from_row,
     )?;
 
     Ok(proxies)
@@ -239,7 +153,7 @@ pub fn get_all_proxies() -> Result<Vec<Proxy>, DatabaseError> {
 /// Retrieves a specific proxy configuration by its ID
 ///
 /// This function fetches a single proxy record from the database based on
-/// the provided ID. It automatically ensures the database table exists before
+/// the provided ID. Proxies in the trash are not returned. It automatically ensures the database table exists before
 /// performing the query.
 ///
 /// # Parameters
@@ -280,27 +194,9 @@ pub fn get_proxy_by_id(id: &str) -> Result<Option<Proxy>, DatabaseError> {
 
     // Query the proxy by ID
     let proxy = db.query_one(
-        "SELECT id, title, addr_listen, addr_target, high_speed, high_speed_addr, high_speed_gwid FROM proxies WHERE id = ?1",
+        &format!("SELECT {} FROM proxies WHERE id = ?1 AND deleted_at IS NULL", PROXY_COLUMNS),
         [id],
-        |row| {
-            Ok(Proxy {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                addr_listen: row.get(2)?,
-                addr_target: row.get(3)?,
-                high_speed: row.get(4)?,
-                high_speed_addr: match row.get::<_, String>(5) {
-                    Ok(s) if s == "\u{0000}" => None,
-                    Ok(s) => Some(s),
-                    Err(_) => None,
-                },
-                high_speed_gwid: match row.get::<_, String>(6) {
-                    Ok(s) if s == "\u{0000}" => None,
-                    Ok(s) => Some(s),
-                    Err(_) => None,
-                },
-            })
-        },
+        proxy_from_row,
     )?;
 
     Ok(proxy)
@@ -341,10 +237,22 @@ pub fn save_proxy(proxy: &Proxy) -> Result<(), DatabaseError> {
     // Get a fresh database connection for this operation
     let db = get_connection()?;
     
-    // Insert or replace the proxy with a simple execute operation
-    db.execute(
-        "INSERT OR REPLACE INTO proxies (id, title, addr_listen, addr_target, high_speed, high_speed_addr, high_speed_gwid) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    // Insert or replace the proxy in its own transaction
+    db.transaction(|conn| upsert_proxy(conn, proxy))?;
+    
+    // Connection is closed automatically when db goes out of scope
+    Ok(())
+}
+
+/// Inserts or replaces one proxy, shared by [`save_proxy`], [`save_proxies`] and the config import
+///
+/// A stored proxy keeps its maintenance mode, only [`set_proxy_maintenance`]
+/// changes it. `proxy.maintenance` is used for new proxies.
+pub(super) fn upsert_proxy(conn: &rusqlite::Connection, proxy: &Proxy) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT OR REPLACE INTO proxies (id, title, addr_listen, addr_target, high_speed, high_speed_addr, high_speed_gwid, redirect_to_https, redirect_https_port, deleted_at, tcp_nodelay, keepalive_secs, keepalive_count, buffer_size, maintenance, tls_min_version, tls_ciphers, tls_require_sni, fallback, tag) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14,
+                 COALESCE((SELECT maintenance FROM proxies WHERE id = ?1), ?15), ?16, ?17, ?18, ?19, ?20)",
         rusqlite::params![
             &proxy.id,
             &proxy.title,
@@ -353,16 +261,40 @@ pub fn save_proxy(proxy: &Proxy) -> Result<(), DatabaseError> {
             &(if proxy.high_speed { 1 } else { 0 }),
             &proxy.high_speed_addr.clone().unwrap_or("\u{0000}".to_string()),
             &proxy.high_speed_gwid.clone().unwrap_or("\u{0000}".to_string()),
+            &(if proxy.redirect_to_https { 1 } else { 0 }),
+            &proxy.redirect_https_port,
+            &proxy.deleted_at,
+            &proxy.tcp_nodelay,
+            &proxy.keepalive_secs,
+            &proxy.keepalive_count,
+            &proxy.buffer_size,
+            &proxy.maintenance,
+            &proxy.tls_min_version,
+            &proxy.tls_ciphers,
+            &proxy.tls_require_sni,
+            &proxy.fallback,
+            &proxy.tag,
         ],
-    )?;
-    
-    // Connection is closed automatically when db goes out of scope
-    Ok(())
+    )
 }
 
-/// Deletes a proxy configuration from the database by its ID
+/// Saves several proxies in one transaction, either all of them or none
+pub fn save_proxies(proxies: &[Proxy]) -> Result<(), DatabaseError> {
+    ensure_proxies_table()?;
+
+    let db = get_connection()?;
+    db.transaction(|conn| {
+        for proxy in proxies {
+            upsert_proxy(conn, proxy)?;
+        }
+        Ok(())
+    })
+}
+
+/// Permanently deletes a proxy configuration from the database by its ID
 ///
-/// This function removes a proxy record from the database based on its ID.
+/// This function removes a proxy record from the database based on its ID,
+/// whether or not it is in the trash. Use [`trash_proxy_by_id`] for user deletes.
 /// It returns a boolean indicating whether a record was actually deleted.
 ///
 /// # Parameters
@@ -402,6 +334,134 @@ pub fn delete_proxy_by_id(id: &str) -> Result<bool, DatabaseError> {
     Ok(affected_rows > 0)
 }
 
+/// Moves a proxy to the trash
+///
+/// The proxy stops being served and is hidden from listings, but its domains and
+/// gateway nodes are kept so [`restore_proxy_by_id`] brings it back unchanged.
+/// Returns whether a live proxy with this ID existed.
+pub fn trash_proxy_by_id(id: &str) -> Result<bool, DatabaseError> {
+    ensure_proxies_table()?;
+
+    let db = get_connection()?;
+    let affected_rows = db.execute(
+        "UPDATE proxies SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        rusqlite::params![id, chrono::Utc::now().timestamp()],
+    )?;
+
+    Ok(affected_rows > 0)
+}
+
+/// Turns maintenance mode of a live proxy on or off
+///
+/// Returns whether a live proxy with this ID existed.
+pub fn set_proxy_maintenance(id: &str, enabled: bool) -> Result<bool, DatabaseError> {
+    ensure_proxies_table()?;
+
+    let db = get_connection()?;
+    let affected_rows = db.execute(
+        "UPDATE proxies SET maintenance = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        rusqlite::params![id, enabled],
+    )?;
+
+    Ok(affected_rows > 0)
+}
+
+/// Moves several proxies to the trash in one transaction
+///
+/// Returns, per ID, whether a live proxy was moved to the trash.
+pub fn delete_proxies_by_ids(ids: &[String]) -> Result<Vec<bool>, DatabaseError> {
+    ensure_proxies_table()?;
+
+    let db = get_connection()?;
+    let now = chrono::Utc::now().timestamp();
+    db.transaction(|conn| {
+        ids.iter()
+            .map(|id| -> rusqlite::Result<bool> {
+                Ok(conn.execute(
+                    "UPDATE proxies SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+                    rusqlite::params![id, now],
+                )? > 0)
+            })
+            .collect()
+    })
+}
+
+/// Retrieves all proxies in the trash, most recently deleted first
+pub fn get_trashed_proxies() -> Result<Vec<Proxy>, DatabaseError> {
+    ensure_proxies_table()?;
+
+    let db = get_connection()?;
+    db.query(
+        &format!(
+            "SELECT {} FROM proxies WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+            PROXY_COLUMNS
+        ),
+        [],
+        proxy_from_row,
+    )
+}
+
+/// Retrieves a proxy in the trash by its ID
+pub fn get_trashed_proxy_by_id(id: &str) -> Result<Option<Proxy>, DatabaseError> {
+    ensure_proxies_table()?;
+
+    let db = get_connection()?;
+    db.query_one(
+        &format!("SELECT {} FROM proxies WHERE id = ?1 AND deleted_at IS NOT NULL", PROXY_COLUMNS),
+        [id],
+        proxy_from_row,
+    )
+}
+
+/// Takes a proxy out of the trash, returns whether it was in the trash
+pub fn restore_proxy_by_id(id: &str) -> Result<bool, DatabaseError> {
+    ensure_proxies_table()?;
+
+    let db = get_connection()?;
+    let affected_rows = db.execute(
+        "UPDATE proxies SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+        [id],
+    )?;
+
+    Ok(affected_rows > 0)
+}
+
+/// Permanently deletes the proxies moved to the trash at or before `deleted_before`
+///
+/// Like a delete before the trash existed, the domains of each proxy are removed
+/// and its gateway nodes unbound. Each proxy is purged in its own transaction, a
+/// proxy that is still referenced stays in the trash and is logged. Returns the
+/// number of purged proxies.
+pub fn purge_trashed_proxies(deleted_before: i64) -> Result<usize, DatabaseError> {
+    ensure_proxies_table()?;
+    super::proxydomain_queries::ensure_proxy_domains_table()?;
+    super::gwnode_queries::ensure_gateway_nodes_table()?;
+
+    let db = get_connection()?;
+    let ids = db.query(
+        "SELECT id FROM proxies WHERE deleted_at IS NOT NULL AND deleted_at <= ?1",
+        [deleted_before],
+        |row| row.get::<_, String>(0),
+    )?;
+
+    let mut purged = 0;
+    for id in &ids {
+        let result = db.transaction(|conn| {
+            conn.execute("DELETE FROM proxy_domains WHERE proxy_id = ?1", [id])?;
+            conn.execute(
+                "UPDATE gateway_nodes SET proxy_id = 'unbound' WHERE proxy_id = ?1",
+                [id],
+            )?;
+            conn.execute("DELETE FROM proxies WHERE id = ?1", [id])
+        });
+        match result {
+            Ok(_) => purged += 1,
+            Err(e) => log::warn!("Keeping deleted proxy {} in the trash: {}", id, e),
+        }
+    }
+    Ok(purged)
+}
+
 /// Deletes all proxy configurations from the database
 ///
 /// This function removes all proxy records from the database.
@@ -477,7 +537,8 @@ pub fn generate_target_address() -> Result<String, String> {
 
 /// Checks if there are multiple proxies using the same listen address
 ///
-/// This function counts how many proxies are configured to listen on a given address.
+/// This function counts how many live proxies are configured to listen on a given address,
+/// proxies in the trash don't hold on to their address.
 /// It's used to enforce constraints for high-speed mode, which requires that each
 /// listen address is unique across all proxies.
 ///
@@ -509,11 +570,11 @@ pub fn has_duplicate_listen_address(listen_addr: &str, exclude_id: Option<&str>)
     
     if let Some(id) = exclude_id {
         // Count proxies with the same listen address, excluding the specified proxy
-        let sql = "SELECT COUNT(*) FROM proxies WHERE addr_listen = ? AND id != ?";
+        let sql = "SELECT COUNT(*) FROM proxies WHERE addr_listen = ? AND id != ? AND deleted_at IS NULL";
         count = db.query_one(sql, [listen_addr, id], |row| row.get(0))?.unwrap_or(0);
     } else {
         // Count all proxies with the given listen address
-        let sql = "SELECT COUNT(*) FROM proxies WHERE addr_listen = ?";
+        let sql = "SELECT COUNT(*) FROM proxies WHERE addr_listen = ? AND deleted_at IS NULL";
         count = db.query_one(sql, [listen_addr], |row| row.get(0))?.unwrap_or(0);
     }
     
@@ -4,10 +4,14 @@
 //! Proxies are the foundation of the gateway system, listening on specific addresses and forwarding
 //! traffic to target destinations.
 
+use super::access::Access;
 use super::gwnode_queries;
+use super::permission_queries::Scope;
+use super::rule_validation;
 use super::{proxy_queries, proxydomain_queries, Proxy, ProxyDomain};
-use crate::api::users::helper::{is_staff_or_admin, ClaimsFromRequest};
+use super::bulk::ItemError;
 use crate::module::database::DatabaseError;
+use crate::module::netaddr;
 use actix_web::{delete, post, web, HttpRequest, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -37,13 +41,30 @@ pub struct ProxyInputObject {
 /// # Request Body
 ///
 /// The request body should be a JSON object with the following fields:
-/// - `id` (optional): The unique identifier for the proxy. If empty, a new UUID will be generated.
+/// - `id` (optional): The unique identifier for the proxy. If empty or absent, a new proxy is
+///   created with a generated UUID; otherwise the proxy with this ID is updated (or created under it).
 /// - `title`: Human-readable name for the proxy.
-/// - `addr_listen`: Address where the proxy listens for connections (format: "ip:port").
+/// - `addr_listen`: Address where the proxy listens for connections (format: "ip:port"), or
+///   `unix:/path/to.sock` for a Unix domain socket in speed mode.
 /// - `high_speed` (optional): Whether speed mode is enabled for faster proxying (default: false).
 /// - `high_speed_addr` (optional): Specific address to use for speed mode.
-///
-/// Note: TLS configuration has been moved to the ProxyDomain entity.
+/// - `redirect_to_https` (optional): Answer plain HTTP with a 301 to HTTPS instead of forwarding (default: false).
+/// - `redirect_https_port` (optional): HTTPS port used in redirects (default: 443).
+/// - `tcp_nodelay` (optional): Disable Nagle's algorithm on the proxied sockets (default: true).
+/// - `keepalive_secs` (optional): TCP keepalive idle time and probe interval in seconds (1-32767),
+///   keepalive is off when absent.
+/// - `keepalive_count` (optional): Unanswered keepalive probes before the peer is dropped (1-127).
+/// - `tls_min_version` (optional): Lowest TLS version accepted, `1.2` or `1.3` (default: 1.2).
+/// - `tls_ciphers` (optional): Colon or comma separated cipher names, `TLS_*` names for TLS 1.3;
+///   saved colon separated.
+/// - `tls_require_sni` (optional): Refuse TLS clients that don't send SNI (default: false).
+/// - `fallback` (optional): Target of requests no gateway rule matches, `404`, `500`, a `static`
+///   target or a catch-all `host:port` (default: the core's `GWRS_GATEWAY_FALLBACK`, else 404).
+/// - `tag` (optional): Owner of the proxy, letters, digits, `.`, `_` and `-`. Users other than
+///   administrators need the `write` permission for the tag, and for the stored tag when updating.
+///
+/// Note: TLS configuration has been moved to the ProxyDomain entity. Domains with `acme` set get
+/// their certificate from the ACME CA, they keep the issued one when saved without `tls_pem`.
 ///
 /// Note: The `addr_target` field does not need to be provided in the request as it is
 /// automatically generated with an available port on 127.0.0.1.
@@ -51,8 +72,8 @@ pub struct ProxyInputObject {
 /// # Response
 ///
 /// ## Success (200 OK)
-/// Returns the complete saved proxy configuration as a JSON object, including
-/// the generated ID and target address.
+/// Returns `{"proxy": .., "domains": [..]}` with the saved proxy, including the
+/// generated `id` and target address, and the saved domains with their `id`s.
 ///
 /// ## Internal Server Error (500)
 /// Returned when there is a database error, port allocation failure, or other server error.
@@ -96,294 +117,405 @@ pub struct ProxyInputObject {
 /// ```
 #[post("/proxy")]
 pub async fn set_proxy(req: HttpRequest, input: web::Json<ProxyInputObject>) -> impl Responder {
-    // Extract authenticated user's claims
-    let claims = match req.get_claims() {
-        Some(claims) => claims,
-        None => {
-            return HttpResponse::BadRequest()
-                .json(serde_json::json!({"error": "Failed to get user authentication"}))
-        }
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
     };
 
-    // Verify user has admin or staff role
-    if !is_staff_or_admin(&claims.role) {
-        return HttpResponse::Forbidden().json(
-            serde_json::json!({"error": "Only administrators and staff can modify proxy settings"}),
-        );
-    }
-
     let mut proxy = input.proxy.clone();
-    let is_new_proxy = proxy.id.is_empty();
-
-    // Generate an ID if none was provided
-    if is_new_proxy {
-        proxy.id = Uuid::new_v4().to_string();
+    // Both the stored and the new tag must be writable, a proxy can't be moved to another team
+    if let Err(response) = access.check_proxy(&proxy.id, Scope::Write) {
+        return response;
     }
-
-    // check if proxy.addr_listen is a valid ip address with port
-    if !proxy.addr_listen.contains(":") {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({"error": "Addr listen must be a valid IP address with port"}),
-        );
+    let is_new_proxy = match prepare_proxy(&mut proxy) {
+        Ok(is_new_proxy) => is_new_proxy,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(response) = access.check_tag(proxy.tag.as_deref(), Scope::Write) {
+        return response;
     }
 
-    // check if after : is a valid port 1 - 65535, return error if not
-    if let Some(port) = proxy.addr_listen.split(":").nth(1) {
-        if port.parse::<u16>().is_err() {
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Addr listen must be a valid IP address with port"}),
-            );
-        }
-    }
+    // Store the proxy ID for potential cleanup if domain save fails
+    let proxy_id = proxy.id.clone();
 
-    // Check for duplicate listen address - this check applies to all proxies regardless of mode
-    match proxy_queries::has_duplicate_listen_address(&proxy.addr_listen, Some(&proxy.id)) {
-        Ok(has_duplicate) => {
-            if has_duplicate {
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Cannot create/update proxy because there is already another proxy with the same listen address. Each proxy must have a unique listen address."
-                }));
+    // Step 1: Save the proxy without verification
+    if let Err(e) = proxy_queries::save_proxy(&proxy) {
+        log::error!("Error saving proxy {}: {}", proxy.id, e);
+        let error_message = match e {
+            DatabaseError::Sqlite(sqlite_error) => {
+                if let rusqlite::Error::SqliteFailure(err, _) = sqlite_error {
+                    if err.code == rusqlite::ffi::ErrorCode::ConstraintViolation {
+                        format!("Database constraint violation while saving proxy {}. Please check if the proxy ID is valid.", proxy.id)
+                    } else {
+                        format!(
+                            "Database error while saving proxy {}: {}",
+                            proxy.id, sqlite_error
+                        )
+                    }
+                } else {
+                    format!("SQLite error: {}", sqlite_error)
+                }
             }
-        }
-        Err(e) => {
-            log::error!("Error checking for duplicate listen addresses: {}", e);
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Failed to check for duplicate listen addresses"
-            }));
-        }
+            _ => format!("Failed to save proxy {}: {}", proxy.id, e),
+        };
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": error_message
+        }));
     }
 
-    // Store the proxy ID for potential cleanup if domain save fails
-    let proxy_id = proxy.id.clone();
+    log::debug!("Proxy {} saved successfully", proxy.id);
 
-    // Generate a target address with random available port
-    match proxy_queries::generate_target_address() {
-        Ok(addr) => {
-            proxy.addr_target = addr;
-
-            // Handle high-speed mode configuration
-            if proxy.high_speed {
-                // If high_speed_gwid is provided, look up its alt_target to set as high_speed_addr
-                if let Some(gwid) = &proxy.high_speed_gwid {
-                    if !gwid.is_empty() {
-                        match gwnode_queries::get_gateway_node_by_id(gwid) {
-                            Ok(Some(gwnode)) => {
-                                proxy.high_speed_addr = Some(gwnode.alt_target.clone());
-                            }
-                            Ok(None) => {
-                                log::warn!("Gateway node {} not found for high_speed_gwid", gwid);
-                                return HttpResponse::BadRequest().json(serde_json::json!({
-                                    "error": "The specified gateway node for high-speed mode was not found"
-                                }));
-                            }
-                            Err(e) => {
-                                log::error!("Error retrieving gateway node {}: {}", gwid, e);
-                                return HttpResponse::BadRequest().json(serde_json::json!({
-                                    "error": "Failed to retrieve gateway node for high-speed mode"
-                                }));
-                            }
+    // Step 2: Process domains if provided
+    let mut saved_domain_ids = Vec::new(); // Track successfully saved domains for potential cleanup
+
+    if let Some(incoming_domains) = &input.domains {
+        let mut incoming_domains = incoming_domains.clone();
+
+        // Check for duplicate domain names in the incoming domains
+        let mut seen_domain_names = std::collections::HashSet::new();
+
+        for domain in incoming_domains.iter_mut() {
+            if let Some(domain_name) = &domain.sni {
+                // A domain may list several names, including wildcards
+                let names = match rule_validation::normalize_sni(domain_name) {
+                    Ok(names) => names,
+                    Err(e) => {
+                        if is_new_proxy {
+                            cleanup_proxy_and_domains(&proxy_id, &saved_domain_ids);
+                        }
+                        return HttpResponse::BadRequest().json(serde_json::json!({
+                            "error": e
+                        }));
+                    }
+                };
+
+                for name in &names {
+                    // Check if this domain name has been seen before
+                    if !seen_domain_names.insert(name.clone()) {
+                        // Cleanup the proxy we just created if this is a new proxy
+                        if is_new_proxy {
+                            cleanup_proxy_and_domains(&proxy_id, &saved_domain_ids);
                         }
+
+                        return HttpResponse::BadRequest().json(serde_json::json!({
+                            "error": format!("Duplicate domain name '{}' found in the request. Each domain name must be unique.", name)
+                        }));
                     }
                 }
 
-                // If high_speed is enabled but high_speed_addr is still empty, set it to the same as addr_target
-                if proxy.high_speed_addr.is_none()
-                    || proxy.high_speed_addr.as_ref().unwrap().is_empty()
-                {
-                    proxy.high_speed_addr = Some(proxy.addr_target.clone());
+                if !names.is_empty() {
+                    domain.sni = Some(names.join(", "));
                 }
+            }
+
+            // The CA validates every name over plain HTTP, see `module::acme`
+            if domain.acme {
+                if let Err(e) = rule_validation::acme_names(domain.sni.as_deref().unwrap_or_default()) {
+                    if is_new_proxy {
+                        cleanup_proxy_and_domains(&proxy_id, &saved_domain_ids);
+                    }
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("Invalid ACME domain: {}", e)
+                    }));
+                }
+                domain.tls = true;
+            }
+
+            if let Err(e) = rule_validation::check_unix_listen_tls(&proxy.addr_listen, domain.tls) {
+                if is_new_proxy {
+                    cleanup_proxy_and_domains(&proxy_id, &saved_domain_ids);
+                }
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": e
+                }));
+            }
+        }
+
+        // Fetch existing domains for this proxy to identify domains to remove later
+        let existing_domains =
+            match proxydomain_queries::get_proxy_domains_by_proxy_id(&proxy.id) {
+                Ok(domains) => domains,
+                Err(e) => {
+                    log::warn!("Warning: Failed to fetch existing domains: {}", e);
+                    // Continue anyway, we just won't be able to remove old domains
+                    Vec::new()
+                }
+            };
+
+        let mut existing_domain_ids_to_keep = Vec::new();
+
+        // Process each domain individually
+        for mut domain in incoming_domains.clone() {
+            // Ensure domain is associated with this proxy
+            domain.proxy_id = Some(proxy.id.clone());
+
+            // Generate domain ID if not provided (empty string)
+            if domain.id.is_empty() {
+                domain.id = proxydomain_queries::generate_proxy_domain_id();
             } else {
-                // If high_speed is disabled, set high_speed_addr and high_speed_gwid to None
-                proxy.high_speed_addr = None;
-                proxy.high_speed_gwid = None;
+                // If domain has an ID, it exists, so add it to the keep list
+                existing_domain_ids_to_keep.push(domain.id.clone());
             }
 
-            // Step 1: Save the proxy without verification
-            if let Err(e) = proxy_queries::save_proxy(&proxy) {
-                log::error!("Error saving proxy {}: {}", proxy.id, e);
+            // An ACME domain saved without its certificate keeps the one issued last
+            if domain.acme && domain.tls_pem.as_deref().map_or(true, |pem| pem.trim().is_empty()) {
+                if let Some(existing) = existing_domains.iter().find(|existing| existing.id == domain.id && existing.acme) {
+                    domain.tls_pem = existing.tls_pem.clone();
+                    domain.tls_key = existing.tls_key.clone();
+                }
+            }
+
+            // Log domain data before saving
+            log::debug!(
+                "Saving proxy domain: id={}, proxy_id={:?}",
+                domain.id,
+                domain.proxy_id
+            );
+
+            // Save the domain with proper error handling
+            if let Err(e) = proxydomain_queries::save_proxy_domain(&domain) {
+                log::error!("Error saving proxy domain {}: {}", domain.id, e);
+
+                // Cleanup the proxy and successfully saved domains if this is a new proxy
+                if is_new_proxy {
+                    cleanup_proxy_and_domains(&proxy_id, &saved_domain_ids);
+                }
+
+                // Return a detailed error message
                 let error_message = match e {
                     DatabaseError::Sqlite(sqlite_error) => {
                         if let rusqlite::Error::SqliteFailure(err, _) = sqlite_error {
                             if err.code == rusqlite::ffi::ErrorCode::ConstraintViolation {
-                                format!("Database constraint violation while saving proxy {}. Please check if the proxy ID is valid.", proxy.id)
+                                format!("Foreign key constraint failed for domain {}. Make sure the proxy_id is valid.", domain.id)
                             } else {
                                 format!(
-                                    "Database error while saving proxy {}: {}",
-                                    proxy.id, sqlite_error
+                                    "Database error while saving domain {}: {}",
+                                    domain.id, sqlite_error
                                 )
                             }
                         } else {
                             format!("SQLite error: {}", sqlite_error)
                         }
                     }
-                    _ => format!("Failed to save proxy {}: {}", proxy.id, e),
+                    _ => format!("Failed to save proxy domain {}: {}", domain.id, e),
                 };
+
                 return HttpResponse::BadRequest().json(serde_json::json!({
                     "error": error_message
                 }));
             }
 
-            log::debug!("Proxy {} saved successfully", proxy.id);
-
-            // Step 2: Process domains if provided
-            let mut saved_domain_ids = Vec::new(); // Track successfully saved domains for potential cleanup
-
-            if let Some(incoming_domains) = &input.domains {
-                // Check for duplicate domain names in the incoming domains
-                let mut seen_domain_names = std::collections::HashSet::new();
-
-                for domain in incoming_domains.iter() {
-                    if let Some(domain_name) = &domain.sni {
-                        if !domain_name.is_empty() {
-                            // Check if this domain name has been seen before
-                            if !seen_domain_names.insert(domain_name.clone()) {
-                                // Cleanup the proxy we just created if this is a new proxy
-                                if is_new_proxy {
-                                    cleanup_proxy_and_domains(&proxy_id, &saved_domain_ids);
-                                }
+            // Add to the list of successfully saved domains
+            saved_domain_ids.push(domain.id.clone());
+        }
 
-                                return HttpResponse::BadRequest().json(serde_json::json!({
-                                    "error": format!("Duplicate domain name '{}' found in the request. Each domain name must be unique.", domain_name)
-                                }));
-                            }
+        // Delete domains that exist in the database but are not in the incoming data
+        if !is_new_proxy {
+            // Only for existing proxies
+            let mut deleted_count = 0;
+            for existing_domain in existing_domains {
+                if !existing_domain_ids_to_keep.contains(&existing_domain.id) {
+                    match proxydomain_queries::delete_proxy_domain_by_id(
+                        &existing_domain.id,
+                    ) {
+                        Ok(_) => {
+                            deleted_count += 1;
+                            log::debug!(
+                                "Deleted proxy domain {} as it was removed from frontend",
+                                existing_domain.id
+                            );
+                        }
+                        Err(e) => {
+                            log::error!(
+                                "Error deleting removed proxy domain {}: {}",
+                                existing_domain.id,
+                                e
+                            );
+                            // Continue processing other domains despite this error
                         }
                     }
                 }
+            }
 
-                // Fetch existing domains for this proxy to identify domains to remove later
-                let existing_domains =
-                    match proxydomain_queries::get_proxy_domains_by_proxy_id(&proxy.id) {
-                        Ok(domains) => domains,
-                        Err(e) => {
-                            log::warn!("Warning: Failed to fetch existing domains: {}", e);
-                            // Continue anyway, we just won't be able to remove old domains
-                            Vec::new()
-                        }
-                    };
+            if deleted_count > 0 {
+                log::info!(
+                    "Deleted {} proxy domains that were removed from the frontend",
+                    deleted_count
+                );
+            }
+        }
+    }
 
-                let mut existing_domain_ids_to_keep = Vec::new();
+    // Step 3: Fetch all domains for this proxy to include in response
+    let domains = match proxydomain_queries::get_proxy_domains_by_proxy_id(&proxy.id) {
+        Ok(domains) => domains,
+        Err(e) => {
+            log::error!("Error fetching proxy domains for {}: {}", proxy.id, e);
+            // Return success but with empty domains list and a warning
+            return HttpResponse::Ok().json(serde_json::json!({
+                "proxy": proxy,
+                "domains": Vec::<ProxyDomain>::new(),
+                "warning": "Proxy saved but could not fetch associated domains"
+            }));
+        }
+    };
 
-                // Process each domain individually
-                for mut domain in incoming_domains.clone() {
-                    // Ensure domain is associated with this proxy
-                    domain.proxy_id = Some(proxy.id.clone());
+    // Return the complete proxy with its domains
+    HttpResponse::Ok().json(serde_json::json!({
+        "proxy": proxy,
+        "domains": domains
+    }))
+}
 
-                    // Generate domain ID if not provided (empty string)
-                    if domain.id.is_empty() {
-                        domain.id = proxydomain_queries::generate_proxy_domain_id();
-                    } else {
-                        // If domain has an ID, it exists, so add it to the keep list
-                        existing_domain_ids_to_keep.push(domain.id.clone());
-                    }
+/// Validates a proxy before it is saved, shared with the bulk endpoint
+///
+/// Assigns an ID to new proxies, checks the listen address and redirect settings,
+/// rejects duplicate listen addresses, generates the target address and resolves
+/// the speed mode address. Returns whether the proxy is new.
+pub(super) fn prepare_proxy(proxy: &mut Proxy) -> Result<bool, ItemError> {
+    let is_new_proxy = proxy.id.is_empty();
 
-                    // Log domain data before saving
-                    log::debug!(
-                        "Saving proxy domain: id={}, proxy_id={:?}",
-                        domain.id,
-                        domain.proxy_id
-                    );
+    // Saving a proxy always makes it live, the trash is only managed by the API
+    proxy.deleted_at = None;
 
-                    // Save the domain with proper error handling
-                    if let Err(e) = proxydomain_queries::save_proxy_domain(&domain) {
-                        log::error!("Error saving proxy domain {}: {}", domain.id, e);
+    // Generate an ID if none was provided
+    if is_new_proxy {
+        proxy.id = Uuid::new_v4().to_string();
+    }
 
-                        // Cleanup the proxy and successfully saved domains if this is a new proxy
-                        if is_new_proxy {
-                            cleanup_proxy_and_domains(&proxy_id, &saved_domain_ids);
-                        }
+    // check if proxy.addr_listen is a valid ip address with port,
+    // IPv6 literals must be bracketed (e.g. "[::1]:8080")
+    if netaddr::unix_socket_path(&proxy.addr_listen).is_some() {
+        // Only the speed mode listener is served by the core's stream proxy,
+        // gateway and redirect listeners bind TCP
+        if !proxy.high_speed {
+            return Err(ItemError::Invalid(
+                "A Unix socket listen address needs high_speed".to_string(),
+            ));
+        }
+    } else if netaddr::split_host_port(&proxy.addr_listen).is_none() {
+        return Err(ItemError::Invalid(
+            "Addr listen must be a valid IP address with port or a unix:/path socket".to_string(),
+        ));
+    }
 
-                        // Return a detailed error message
-                        let error_message = match e {
-                            DatabaseError::Sqlite(sqlite_error) => {
-                                if let rusqlite::Error::SqliteFailure(err, _) = sqlite_error {
-                                    if err.code == rusqlite::ffi::ErrorCode::ConstraintViolation {
-                                        format!("Foreign key constraint failed for domain {}. Make sure the proxy_id is valid.", domain.id)
-                                    } else {
-                                        format!(
-                                            "Database error while saving domain {}: {}",
-                                            domain.id, sqlite_error
-                                        )
-                                    }
-                                } else {
-                                    format!("SQLite error: {}", sqlite_error)
-                                }
-                            }
-                            _ => format!("Failed to save proxy domain {}: {}", domain.id, e),
-                        };
+    // A redirect listener answers every request itself, so it can't also forward in speed mode
+    if proxy.redirect_to_https {
+        if proxy.high_speed {
+            return Err(ItemError::Invalid(
+                "A proxy can't use both redirect_to_https and high_speed".to_string(),
+            ));
+        }
+        if proxy.redirect_https_port == Some(0) {
+            return Err(ItemError::Invalid(
+                "Redirect HTTPS port must be between 1 and 65535".to_string(),
+            ));
+        }
+    } else {
+        proxy.redirect_https_port = None;
+    }
 
-                        return HttpResponse::BadRequest().json(serde_json::json!({
-                            "error": error_message
-                        }));
-                    }
+    // Limits of TCP_KEEPINTVL and TCP_KEEPCNT on Linux
+    if let Some(secs) = proxy.keepalive_secs {
+        if !(1..=32767).contains(&secs) {
+            return Err(ItemError::Invalid(
+                "Keepalive interval must be between 1 and 32767 seconds".to_string(),
+            ));
+        }
+    }
+    match proxy.keepalive_count {
+        Some(count) if !(1..=127).contains(&count) => {
+            return Err(ItemError::Invalid(
+                "Keepalive count must be between 1 and 127".to_string(),
+            ));
+        }
+        Some(_) if proxy.keepalive_secs.is_none() => {
+            return Err(ItemError::Invalid(
+                "Keepalive count needs keepalive_secs to be set".to_string(),
+            ));
+        }
+        _ => {}
+    }
 
-                    // Add to the list of successfully saved domains
-                    saved_domain_ids.push(domain.id.clone());
-                }
+    // Cipher names OpenSSL doesn't know are reported by the core when the proxy is synced,
+    // so are buffer sizes outside its range, which it clamps
+    proxy.tls_min_version =
+        rule_validation::normalize_tls_min_version(proxy.tls_min_version.as_deref().unwrap_or_default())
+            .map_err(ItemError::Invalid)?;
+    proxy.tls_ciphers = rule_validation::normalize_tls_ciphers(proxy.tls_ciphers.as_deref().unwrap_or_default())
+        .map_err(ItemError::Invalid)?;
+    proxy.fallback = rule_validation::normalize_fallback(proxy.fallback.as_deref().unwrap_or_default())
+        .map_err(ItemError::Invalid)?;
+    proxy.tag = rule_validation::normalize_tag(proxy.tag.as_deref().unwrap_or_default())
+        .map_err(ItemError::Invalid)?;
 
-                // Delete domains that exist in the database but are not in the incoming data
-                if !is_new_proxy {
-                    // Only for existing proxies
-                    let mut deleted_count = 0;
-                    for existing_domain in existing_domains {
-                        if !existing_domain_ids_to_keep.contains(&existing_domain.id) {
-                            match proxydomain_queries::delete_proxy_domain_by_id(
-                                &existing_domain.id,
-                            ) {
-                                Ok(_) => {
-                                    deleted_count += 1;
-                                    log::debug!(
-                                        "Deleted proxy domain {} as it was removed from frontend",
-                                        existing_domain.id
-                                    );
-                                }
-                                Err(e) => {
-                                    log::error!(
-                                        "Error deleting removed proxy domain {}: {}",
-                                        existing_domain.id,
-                                        e
-                                    );
-                                    // Continue processing other domains despite this error
-                                }
-                            }
-                        }
-                    }
+    // Check for duplicate listen address - this check applies to all proxies regardless of mode
+    match proxy_queries::has_duplicate_listen_address(&proxy.addr_listen, Some(&proxy.id)) {
+        Ok(true) => {
+            return Err(ItemError::Invalid(
+                "Cannot create/update proxy because there is already another proxy with the same listen address. Each proxy must have a unique listen address.".to_string(),
+            ));
+        }
+        Ok(false) => {}
+        Err(e) => {
+            log::error!("Error checking for duplicate listen addresses: {}", e);
+            return Err(ItemError::Invalid(
+                "Failed to check for duplicate listen addresses".to_string(),
+            ));
+        }
+    }
 
-                    if deleted_count > 0 {
-                        log::info!(
-                            "Deleted {} proxy domains that were removed from the frontend",
-                            deleted_count
-                        );
+    // Generate a target address with random available port
+    proxy.addr_target = proxy_queries::generate_target_address().map_err(|e| {
+        log::error!("Error generating target address: {}", e);
+        ItemError::Invalid("Failed to generate target address".to_string())
+    })?;
+
+    // Handle high-speed mode configuration
+    if proxy.high_speed {
+        // If high_speed_gwid is provided, look up its alt_target to set as high_speed_addr
+        if let Some(gwid) = proxy.high_speed_gwid.clone().filter(|gwid| !gwid.is_empty()) {
+            match gwnode_queries::get_gateway_node_by_id(&gwid) {
+                Ok(Some(gwnode)) => {
+                    // The speed mode listener serves the TLS of the gateway node's domain
+                    if let Some(domain_id) = gwnode.domain_id.as_deref().filter(|id| !id.is_empty()) {
+                        let tls = proxydomain_queries::get_proxy_domain_by_id(domain_id)
+                            .map_err(|e| {
+                                log::error!("Error retrieving domain {}: {}", domain_id, e);
+                                ItemError::Invalid("Failed to retrieve the gateway node's domain".to_string())
+                            })?
+                            .map_or(false, |domain| domain.tls);
+                        rule_validation::check_unix_listen_tls(&proxy.addr_listen, tls)
+                            .map_err(ItemError::Invalid)?;
                     }
+                    proxy.high_speed_addr = Some(gwnode.alt_target.clone());
+                }
+                Ok(None) => {
+                    log::warn!("Gateway node {} not found for high_speed_gwid", gwid);
+                    return Err(ItemError::Invalid(
+                        "The specified gateway node for high-speed mode was not found".to_string(),
+                    ));
                 }
-            }
-
-            // Step 3: Fetch all domains for this proxy to include in response
-            let domains = match proxydomain_queries::get_proxy_domains_by_proxy_id(&proxy.id) {
-                Ok(domains) => domains,
                 Err(e) => {
-                    log::error!("Error fetching proxy domains for {}: {}", proxy.id, e);
-                    // Return success but with empty domains list and a warning
-                    return HttpResponse::Ok().json(serde_json::json!({
-                        "proxy": proxy,
-                        "domains": Vec::<ProxyDomain>::new(),
-                        "warning": "Proxy saved but could not fetch associated domains"
-                    }));
+                    log::error!("Error retrieving gateway node {}: {}", gwid, e);
+                    return Err(ItemError::Invalid(
+                        "Failed to retrieve gateway node for high-speed mode".to_string(),
+                    ));
                 }
-            };
-
-            // Return the complete proxy with its domains
-            HttpResponse::Ok().json(serde_json::json!({
-                "proxy": proxy,
-                "domains": domains
-            }))
+            }
         }
-        Err(e) => {
-            log::error!("Error generating target address: {}", e);
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Failed to generate target address"
-            }));
+
+        // If high_speed is enabled but high_speed_addr is still empty, set it to the same as addr_target
+        if proxy.high_speed_addr.as_deref().map_or(true, str::is_empty) {
+            proxy.high_speed_addr = Some(proxy.addr_target.clone());
         }
+    } else {
+        // If high_speed is disabled, set high_speed_addr and high_speed_gwid to None
+        proxy.high_speed_addr = None;
+        proxy.high_speed_gwid = None;
     }
+
+    Ok(is_new_proxy)
 }
 
 /// Helper function to clean up a proxy and all its domains when an error occurs
@@ -401,12 +533,16 @@ fn cleanup_proxy_and_domains(proxy_id: &str, domain_ids: &[String]) {
     }
 }
 
-/// Deletes a proxy configuration by ID
+/// Moves a proxy to the trash by ID
+///
+/// The proxy stops being served and is hidden from listings, but nothing is
+/// destroyed: its domains and gateway nodes are kept as they are so that
+/// `POST /settings/proxy/{id}/restore` brings it back unchanged.
 ///
-/// This endpoint processes HTTP DELETE requests to remove proxy configurations.
-/// Rather than cascading deletion, it implements a "soft unbinding" approach
-/// for associated  proxy nodes by marking them as "unbound" but preserving
-/// their configuration.
+/// Proxies stay in the trash for `GWRS_TRASH_RETENTION_DAYS` days (default 30).
+/// After that they are purged, which removes their domains and marks their
+/// gateway nodes as "unbound" (their configuration is preserved so they can be
+/// reassigned to a different proxy later).
 ///
 /// # Endpoint
 ///
@@ -419,24 +555,13 @@ fn cleanup_proxy_and_domains(proxy_id: &str, domain_ids: &[String]) {
 /// # Response
 ///
 /// ## Success (200 OK)
-/// Returns a success message indicating the proxy was deleted, along with
-/// information about how many  proxy nodes were unbound in the process.
+/// Returns a message indicating the proxy was moved to the trash.
 ///
 /// ## Not Found (404)
-/// Returned when no proxy with the specified ID exists.
+/// Returned when no live proxy with the specified ID exists.
 ///
 /// ## Internal Server Error (500)
-/// Returned when there is a database or server error during the deletion or unbinding process.
-///
-/// # Gateway Node Handling
-///
-/// This endpoint implements a two-step process:
-/// 1. First, it finds all  proxy nodes associated with the proxy and marks them as "unbound"
-///    (sets their proxy_id to "unbound") rather than deleting them
-/// 2. Then, it deletes the proxy itself
-///
-/// This approach preserves gateway node configurations even when their associated proxy
-/// is removed, allowing them to be reassigned to different proxies later.
+/// Returned when there is a database or server error.
 ///
 /// # Example
 ///
@@ -445,121 +570,117 @@ fn cleanup_proxy_and_domains(proxy_id: &str, domain_ids: &[String]) {
 /// ```
 #[delete("/proxy/{id}")]
 pub async fn delete_proxy(req: HttpRequest, path: web::Path<String>) -> impl Responder {
-    // Extract authenticated user's claims
-    let claims = match req.get_claims() {
-        Some(claims) => claims,
-        None => {
-            return HttpResponse::BadRequest()
-                .json(serde_json::json!({"error": "Failed to get user authentication"}))
-        }
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
     };
 
-    // Verify user has admin or staff role
-    if !is_staff_or_admin(&claims.role) {
-        return HttpResponse::Forbidden().json(
-            serde_json::json!({"error": "Only administrators and staff can delete proxy settings"}),
-        );
-    }
-
     let id = path.into_inner();
+    if let Err(response) = access.check_proxy(&id, Scope::Write) {
+        return response;
+    }
 
-    // Get proxy details for better error messages
+    // Get proxy details for better messages
     let proxy_name = match proxy_queries::get_proxy_by_id(&id) {
         Ok(Some(proxy)) => proxy.title,
         _ => id.clone(), // Fallback to ID if proxy not found
     };
 
-    // First delete any proxy domains associated with this proxy
-    let domains_deleted = match proxydomain_queries::delete_proxy_domains_by_proxy_id(&id) {
-        Ok(count) => count,
+    match proxy_queries::trash_proxy_by_id(&id) {
+        Ok(true) => HttpResponse::Ok().body(format!(
+            "Proxy '{}' moved to the trash. Restore it with POST /settings/proxy/{}/restore.",
+            proxy_name, id
+        )),
+        Ok(false) => HttpResponse::NotFound().body(format!("Proxy '{}' not found", proxy_name)),
         Err(e) => {
-            log::error!("Error deleting proxy domains for proxy {}: {}", id, e);
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": format!("
-                    Failed to delete proxy domains for '{}', 
-                    there are gateway node configurations associated with this proxy. 
-                    Please remove or modify to different proxy for all gateway node 
-                    configurations before deleting the proxy.", proxy_name)
-            }));
+            log::error!("Error deleting proxy {}: {}", id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to delete proxy '{}': {}", proxy_name, e)
+            }))
         }
-    };
+    }
+}
 
-    // Then unbind any  proxy nodes associated with this proxy
-    match gwnode_queries::unbind_gateway_nodes_by_proxy_id(&id) {
-        Ok(unbound_count) => {
-            // Now delete the proxy
-            match proxy_queries::delete_proxy_by_id(&id) {
-                Ok(deleted) => {
-                    if deleted {
-                        let mut message = format!("Proxy '{}' deleted.", proxy_name);
-
-                        if domains_deleted > 0 {
-                            message.push_str(&format!(
-                                " {} proxy domains were removed.",
-                                domains_deleted
-                            ));
-                        }
+/// Restores a proxy from the trash
+///
+/// The proxy comes back with the domains and gateway nodes it had when it was
+/// deleted and is served again on the next sync.
+///
+/// # Endpoint
+///
+/// `POST /settings/proxy/{id}/restore`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// Returns the restored proxy as `{"proxy": {...}}`.
+///
+/// ## Not Found (404)
+/// Returned when the proxy is not in the trash (never existed, live, or purged).
+///
+/// ## Conflict (409)
+/// Returned when another proxy took the listen address in the meantime.
+///
+/// ## Internal Server Error (500)
+/// Returned when there is a database or server error.
+#[post("/proxy/{id}/restore")]
+pub async fn restore_proxy(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    let access = match Access::of(&req) {
+        Ok(access) => access,
+        Err(response) => return response,
+    };
 
-                        if unbound_count > 0 {
-                            message.push_str(&format!(
-                                " {}  proxy nodes were unbound.",
-                                unbound_count
-                            ));
-                        }
+    let id = path.into_inner();
+    if let Err(response) = access.check_proxy(&id, Scope::Write) {
+        return response;
+    }
 
-                        HttpResponse::Ok().body(message)
-                    } else {
-                        HttpResponse::NotFound().body(format!("Proxy '{}' not found", proxy_name))
-                    }
-                }
-                Err(e) => {
-                    log::error!("Error deleting proxy {}: {}", id, e);
-                    let error_message = match e {
-                        DatabaseError::Sqlite(sqlite_error) => {
-                            if let rusqlite::Error::SqliteFailure(err, _) = sqlite_error {
-                                if err.code == rusqlite::ffi::ErrorCode::ConstraintViolation {
-                                    format!("Cannot delete proxy '{}' because it is still referenced by other entities. Please unbind all  proxy nodes first.", proxy_name)
-                                } else {
-                                    format!(
-                                        "Database error while deleting proxy '{}': {}",
-                                        proxy_name, sqlite_error
-                                    )
-                                }
-                            } else {
-                                format!("SQLite error: {}", sqlite_error)
-                            }
-                        }
-                        _ => format!("Failed to delete proxy '{}': {}", proxy_name, e),
-                    };
-                    HttpResponse::BadRequest().json(serde_json::json!({
-                        "error": error_message
-                    }))
-                }
-            }
+    let mut proxy = match proxy_queries::get_trashed_proxy_by_id(&id) {
+        Ok(Some(proxy)) => proxy,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(
+                serde_json::json!({"error": format!("Proxy {} not found in the trash", id)}),
+            )
         }
         Err(e) => {
-            log::error!("Error unbinding  proxy nodes for proxy {}: {}", id, e);
-            let error_message = match e {
-                DatabaseError::Sqlite(sqlite_error) => {
-                    if let rusqlite::Error::SqliteFailure(err, _) = sqlite_error {
-                        if err.code == rusqlite::ffi::ErrorCode::ConstraintViolation {
-                            format!("Cannot  unbind proxy nodes for proxy '{}' because of existing  proxy nodes. Please remove or modify to different proxy for all gateway node configurations before deleting the proxy.", proxy_name)
-                        } else {
-                            format!(
-                                "Database error while unbinding  proxy nodes for '{}': {}",
-                                proxy_name, sqlite_error
-                            )
-                        }
-                    } else {
-                        format!("SQLite error: {}", sqlite_error)
-                    }
-                }
-                _ => format!("Failed to  unbind proxy nodes for '{}': {}", proxy_name, e),
-            };
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": error_message
+            log::error!("Error retrieving deleted proxy {}: {}", id, e);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": format!("Failed to retrieve proxy: {}", e)}));
+        }
+    };
+
+    // Listen addresses of deleted proxies are free for reuse, so it may be taken now
+    match proxy_queries::has_duplicate_listen_address(&proxy.addr_listen, Some(&id)) {
+        Ok(false) => {}
+        Ok(true) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!(
+                    "Cannot restore proxy '{}' because another proxy now listens on {}",
+                    proxy.title, proxy.addr_listen
+                )
             }))
         }
+        Err(e) => {
+            log::error!("Error checking for duplicate listen addresses: {}", e);
+            return HttpResponse::InternalServerError().json(
+                serde_json::json!({"error": "Failed to check for duplicate listen addresses"}),
+            );
+        }
+    }
+
+    match proxy_queries::restore_proxy_by_id(&id) {
+        Ok(true) => {
+            proxy.deleted_at = None;
+            HttpResponse::Ok().json(serde_json::json!({ "proxy": proxy }))
+        }
+        // Purged between the lookup and the restore
+        Ok(false) => HttpResponse::NotFound()
+            .json(serde_json::json!({"error": format!("Proxy {} not found in the trash", id)})),
+        Err(e) => {
+            log::error!("Error restoring proxy {}: {}", id, e);
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": format!("Failed to restore proxy: {}", e)}))
+        }
     }
 }
 
@@ -592,3 +713,186 @@ async fn can_use_high_speed(proxy: &Proxy) -> Result<bool, DatabaseError> {
 
     Ok(!has_duplicate)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::settings::gwnode_set;
+    use crate::api::settings::permission_queries::{self, Permission};
+    use crate::api::users::helper::auth_token::Claims;
+    use crate::module::database::get_connection;
+    use actix_web::{dev::Service, test, App, HttpMessage};
+
+    fn admin_claims() -> Claims {
+        Claims {
+            sub: "test-admin".to_string(),
+            username: "admin".to_string(),
+            role: "admin".to_string(),
+            exp: u64::MAX,
+            iat: 0,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_created_proxy_id_is_usable_for_gwnode() {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(admin_claims());
+                    srv.call(req)
+                })
+                .service(set_proxy)
+                .service(gwnode_set::set_gateway_node),
+        )
+        .await;
+
+        // No id: the proxy is created and its generated id returned
+        let listen = format!("127.0.0.1:{}", rand::random::<u16>() % 10000 + 50000);
+        let req = test::TestRequest::post()
+            .uri("/proxy")
+            .set_json(serde_json::json!({
+                "proxy": {"title": "id round trip", "addr_listen": listen}
+            }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let proxy_id = body["proxy"]["id"].as_str().unwrap().to_string();
+        assert!(Uuid::parse_str(&proxy_id).is_ok());
+
+        let req = test::TestRequest::post()
+            .uri("/gwnode/set")
+            .set_json(serde_json::json!({
+                "proxy_id": proxy_id,
+                "title": "node",
+                "alt_target": "127.0.0.1:8080",
+                "domain_id": null,
+                "domain_name": null
+            }))
+            .to_request();
+        let node: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let node_id = node["id"].as_str().unwrap();
+        assert!(!node_id.is_empty());
+        assert_eq!(node["proxy_id"], proxy_id.as_str());
+
+        gwnode_queries::delete_gateway_node_by_id(node_id).unwrap();
+        proxy_queries::delete_proxy_by_id(&proxy_id).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_deleted_proxy_can_be_restored() {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(admin_claims());
+                    srv.call(req)
+                })
+                .service(set_proxy)
+                .service(delete_proxy)
+                .service(restore_proxy),
+        )
+        .await;
+
+        let listen = format!("127.0.0.1:{}", rand::random::<u16>() % 10000 + 50000);
+        let req = test::TestRequest::post()
+            .uri("/proxy")
+            .set_json(serde_json::json!({
+                "proxy": {"title": "trash round trip", "addr_listen": listen}
+            }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let proxy_id = body["proxy"]["id"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/proxy/{}", proxy_id))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        assert!(proxy_queries::get_proxy_by_id(&proxy_id).unwrap().is_none());
+        assert!(proxy_queries::get_trashed_proxy_by_id(&proxy_id).unwrap().is_some());
+        assert!(!proxy_queries::has_duplicate_listen_address(&listen, None).unwrap());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/proxy/{}/restore", proxy_id))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        assert!(proxy_queries::get_proxy_by_id(&proxy_id).unwrap().is_some());
+
+        // Restoring a live proxy finds nothing in the trash
+        let req = test::TestRequest::post()
+            .uri(&format!("/proxy/{}/restore", proxy_id))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+        proxy_queries::delete_proxy_by_id(&proxy_id).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_proxy_writes_need_the_tag_permission() {
+        permission_queries::ensure_permissions_table().unwrap();
+        let user_id = format!("test-staff-{}", Uuid::new_v4());
+        get_connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO users (id, username, email, password_hash, role) VALUES (?1, ?1, ?1, 'x', 'staff')",
+                [&user_id],
+            )
+            .unwrap();
+        for (tag, scope) in [("team-a", Scope::Write), ("team-b", Scope::Read)] {
+            permission_queries::save_permission(&Permission {
+                user_id: user_id.clone(),
+                tag: tag.to_string(),
+                scope,
+            })
+            .unwrap();
+        }
+
+        let claims = Claims {
+            sub: user_id.clone(),
+            username: user_id.clone(),
+            role: "staff".to_string(),
+            exp: u64::MAX,
+            iat: 0,
+        };
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut().insert(claims.clone());
+                    srv.call(req)
+                })
+                .service(set_proxy)
+                .service(delete_proxy),
+        )
+        .await;
+        let save = |id: &str, tag: Option<&str>| {
+            let listen = format!("127.0.0.1:{}", rand::random::<u16>() % 10000 + 50000);
+            test::TestRequest::post()
+                .uri("/proxy")
+                .set_json(serde_json::json!({
+                    "proxy": {"id": id, "title": "tagged", "addr_listen": listen, "tag": tag}
+                }))
+                .to_request()
+        };
+
+        let body: serde_json::Value = test::call_and_read_body_json(&app, save("", Some(" team-a "))).await;
+        let proxy_id = body["proxy"]["id"].as_str().unwrap().to_string();
+        assert_eq!(body["proxy"]["tag"], "team-a");
+
+        // Read only, untagged and unknown tags are refused, so is moving the proxy away
+        assert_eq!(test::call_service(&app, save("", Some("team-b"))).await.status(), 403);
+        assert_eq!(test::call_service(&app, save("", None)).await.status(), 403);
+        assert_eq!(test::call_service(&app, save("", Some("team-c"))).await.status(), 403);
+        assert_eq!(test::call_service(&app, save(&proxy_id, Some("team-b"))).await.status(), 403);
+        assert_eq!(proxy_queries::get_proxy_by_id(&proxy_id).unwrap().unwrap().tag.as_deref(), Some("team-a"));
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/proxy/{}", proxy_id))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+        proxy_queries::delete_proxy_by_id(&proxy_id).unwrap();
+        // The permissions go with the user
+        get_connection()
+            .unwrap()
+            .execute("DELETE FROM users WHERE id = ?1", [&user_id])
+            .unwrap();
+        assert!(permission_queries::get_permissions(Some(&user_id)).unwrap().is_empty());
+    }
+}
@@ -1,71 +1,47 @@
 //! # Proxy Domain Database Operations
 //!
 //! This module provides database operations for managing proxy domain configurations.
-//! It handles creating the database table, querying, inserting, updating, and
+//! It handles checking the database table, querying, inserting, updating, and
 //! deleting proxy domain records.
 
+use crate::module::migrations;
 use crate::module::database::{get_connection, DatabaseError};
 use super::ProxyDomain;
 use uuid::Uuid;
 
-/// Creates the proxy_domains table in the database if it doesn't already exist
+/// Checks that the proxy_domains table has the columns this module reads and writes
 ///
-/// This function ensures that the database schema is properly initialized before
-/// any operations are performed. It is automatically called by other functions
+/// The table is created and upgraded by `module::migrations` only. A table missing
+/// columns makes this apply the pending migrations, it is never dropped or rebuilt.
+/// It is automatically called by other functions
 /// in this module, so there's usually no need to call it directly.
 ///
 /// # Database Schema
 ///
-/// Creates a table with the following structure:
+/// The table has the following structure:
 /// - `id`: TEXT PRIMARY KEY - Unique identifier for the proxy domain
 /// - `proxy_id`: TEXT NOT NULL - Reference to the proxy this domain is associated with
 /// - `tls`: BOOLEAN NOT NULL DEFAULT 0 - Whether TLS is enabled
 /// - `tls_pem`: TEXT - PEM certificate content
 /// - `tls_key`: TEXT - Private key content
 /// - `sni`: TEXT - Server Name Indication value
+/// - `acme`: BOOLEAN NOT NULL DEFAULT 0 - Whether the certificate is obtained over ACME
 ///
 /// # Returns
 ///
-/// * `Ok(())` if the table exists or was created successfully
-/// * `Err(DatabaseError)` if there was an error creating the table
+/// * `Ok(())` if the table matches the current schema
+/// * `Err(DatabaseError)` if it doesn't, even after migrating
 ///
 /// # Errors
 ///
 /// This function will return an error if:
 /// - The database connection could not be established
-/// - The SQL statement to create the table could not be executed
+/// - The table still lacks columns once the pending migrations ran
 pub fn ensure_proxy_domains_table() -> Result<(), DatabaseError> {
-    let db = get_connection()?;
-    
-    // Define the expected columns
-    let expected_columns = ["id", "proxy_id", "tls", "tls_pem", "tls_key", "sni"];
-    
-    // Check if the table exists with the expected columns and is not corrupted
-    if db.table_exists_with_columns("proxy_domains", &expected_columns)? {
-        log::debug!("proxy_domains table exists and has expected structure");
-        return Ok(());
-    }
-    
-    log::info!("Creating or repairing proxy_domains table");
-    
-    // Drop the table if it exists but is corrupted or missing columns
-    db.execute("DROP TABLE IF EXISTS proxy_domains", [])?;
-    
-    // Create the table with the full correct structure
-    db.execute(
-        "CREATE TABLE proxy_domains (
-            id TEXT PRIMARY KEY,
-            proxy_id TEXT NOT NULL,
-            tls BOOLEAN NOT NULL DEFAULT 0,
-            tls_pem TEXT,
-            tls_key TEXT,
-            sni TEXT
-        )",
-        [],
-    )?;
-    
-    log::info!("Created proxy_domains table with correct structure");
-    Ok(())
+    migrations::ensure_table(
+        "proxy_domains",
+        &["id", "proxy_id", "tls", "tls_pem", "tls_key", "sni", "acme"],
+    )
 }
 
 /// Generates a unique ID for a new proxy domain
@@ -98,7 +74,6 @@ pub fn generate_proxy_domain_id() -> String {
 /// - The table does not exist and could not be created
 /// - The SQL query could not be executed
 /// - There was an error mapping the database rows to `ProxyDomain` structures
-#[allow(dead_code)]
 pub fn get_all_proxy_domains() -> Result<Vec<ProxyDomain>, DatabaseError> {
     let db = get_connection()?;
 
@@ -107,7 +82,7 @@ pub fn get_all_proxy_domains() -> Result<Vec<ProxyDomain>, DatabaseError> {
 
     // Query all proxy domains
     let domains = db.query(
-        "SELECT id, proxy_id, tls, tls_pem, tls_key, sni FROM proxy_domains",
+        "SELECT id, proxy_id, tls, tls_pem, tls_key, sni, acme FROM proxy_domains",
         [],
         |row| {
             Ok(ProxyDomain {
@@ -117,6 +92,7 @@ pub fn get_all_proxy_domains() -> Result<Vec<ProxyDomain>, DatabaseError> {
                 tls_pem: row.get(3)?,
                 tls_key: row.get(4)?,
                 sni: row.get(5)?,
+                acme: row.get(6)?,
             })
         },
     )?;
@@ -156,7 +132,7 @@ pub fn get_proxy_domain_by_id(id: &str) -> Result<Option<ProxyDomain>, DatabaseE
 
     // Query the proxy domain by ID
     let domain = db.query_one(
-        "SELECT id, proxy_id, tls, tls_pem, tls_key, sni FROM proxy_domains WHERE id = ?1",
+        "SELECT id, proxy_id, tls, tls_pem, tls_key, sni, acme FROM proxy_domains WHERE id = ?1",
         [id],
         |row| {
             Ok(ProxyDomain {
@@ -166,6 +142,7 @@ pub fn get_proxy_domain_by_id(id: &str) -> Result<Option<ProxyDomain>, DatabaseE
                 tls_pem: row.get(3)?,
                 tls_key: row.get(4)?,
                 sni: row.get(5)?,
+                acme: row.get(6)?,
             })
         },
     )?;
@@ -203,7 +180,7 @@ pub fn get_proxy_domains_by_proxy_id(proxy_id: &str) -> Result<Vec<ProxyDomain>,
     
     // Query proxy domains by proxy ID
     let domains = db.query(
-        "SELECT id, proxy_id, tls, tls_pem, tls_key, sni FROM proxy_domains WHERE proxy_id = ?1",
+        "SELECT id, proxy_id, tls, tls_pem, tls_key, sni, acme FROM proxy_domains WHERE proxy_id = ?1",
         [proxy_id],
         |row| {
             Ok(ProxyDomain {
@@ -213,6 +190,7 @@ pub fn get_proxy_domains_by_proxy_id(proxy_id: &str) -> Result<Vec<ProxyDomain>,
                 tls_pem: row.get(3)?,
                 tls_key: row.get(4)?,
                 sni: row.get(5)?,
+                acme: row.get(6)?,
             })
         },
     )?;
@@ -261,23 +239,53 @@ pub fn save_proxy_domain(domain: &ProxyDomain) -> Result<(), DatabaseError> {
                domain.id, proxy_id, domain.sni);
     
     // Insert or replace the proxy domain with validated proxy_id and proper NULL handling
-    db.execute(
-        "INSERT OR REPLACE INTO proxy_domains (id, proxy_id, tls, tls_pem, tls_key, sni) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    db.transaction(|conn| upsert_proxy_domain(conn, domain, &proxy_id)).map_err(|e| {
+        log::error!("Database error when saving domain {}: {}", domain.id, e);
+        e
+    })?;
+
+    Ok(())
+}
+
+/// Inserts or replaces one proxy domain, shared by [`save_proxy_domain`] and the config import
+pub(super) fn upsert_proxy_domain(
+    conn: &rusqlite::Connection,
+    domain: &ProxyDomain,
+    proxy_id: &str,
+) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT OR REPLACE INTO proxy_domains (id, proxy_id, tls, tls_pem, tls_key, sni, acme) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
         rusqlite::params![
             &domain.id,
-            &proxy_id,
+            proxy_id,
             &(if domain.tls { 1 } else { 0 }),
             &domain.tls_pem,
             &domain.tls_key,
             &domain.sni,
+            &domain.acme,
         ],
-    ).map_err(|e| {
-        log::error!("Database error when saving domain {}: {}", domain.id, e);
-        DatabaseError::from(e)
-    })?;
+    )
+}
 
-    Ok(())
+/// Stores a certificate obtained over ACME in its domain, leaving the other settings as they are
+///
+/// # Returns
+///
+/// * `Ok(true)` - If the domain still exists with `acme` set and was updated
+/// * `Ok(false)` - If it was deleted or switched to a manual certificate meanwhile
+pub fn save_acme_certificate(id: &str, tls_pem: &str, tls_key: &str) -> Result<bool, DatabaseError> {
+    let db = get_connection()?;
+
+    // Ensure the table exists
+    ensure_proxy_domains_table()?;
+
+    let affected_rows = db.execute(
+        "UPDATE proxy_domains SET tls = 1, tls_pem = ?2, tls_key = ?3 WHERE id = ?1 AND acme = 1",
+        [id, tls_pem, tls_key],
+    )?;
+
+    Ok(affected_rows > 0)
 }
 
 /// Deletes a proxy domain configuration from the database by its ID
@@ -313,41 +321,6 @@ pub fn delete_proxy_domain_by_id(id: &str) -> Result<bool, DatabaseError> {
     Ok(affected_rows > 0)
 }
 
-/// Deletes all proxy domains associated with a specific proxy
-///
-/// This function removes all proxy domain records from the database that are
-/// associated with the given proxy ID. It returns the number of records deleted.
-///
-/// # Parameters
-///
-/// * `proxy_id` - The ID of the proxy whose domains should be deleted
-///
-/// # Returns
-///
-/// * `Ok(usize)` - The number of proxy domains that were deleted
-/// * `Err(DatabaseError)` - If there was an error deleting the proxy domains
-///
-/// # Errors
-///
-/// This function will return an error if:
-/// - The database connection could not be established
-/// - The table does not exist and could not be created
-/// - The SQL statement could not be executed
-pub fn delete_proxy_domains_by_proxy_id(proxy_id: &str) -> Result<usize, DatabaseError> {
-    let db = get_connection()?;
-    
-    // Ensure the table exists
-    ensure_proxy_domains_table()?;
-    
-    // Delete all proxy domains associated with this proxy
-    let affected_rows = db.execute(
-        "DELETE FROM proxy_domains WHERE proxy_id = ?1",
-        [proxy_id],
-    )?;
-    
-    Ok(affected_rows)
-}
-
 /// Deletes all proxy domain configurations from the database
 ///
 /// This function removes all proxy domain records from the database.
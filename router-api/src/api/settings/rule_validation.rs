@@ -0,0 +1,715 @@
+//! Config-time checks for gateway path rules.
+//!
+//! The core evaluates rules per listener in priority order. Rules sharing a
+//! priority on the same listener are ordered by rule id, which is reproducible
+//! but rarely what the author intended, so they are reported here. Very large
+//! rule sets are flagged as well since every cache miss walks the list.
+
+use std::collections::HashMap;
+
+use super::auto_config::YamlConfig;
+use super::SplitTarget;
+use crate::module::netaddr;
+
+/// A gateway path rule as seen by the validator
+pub struct RuleRef<'a> {
+    /// Listener the rule is evaluated on (the proxy listen address)
+    pub listener: &'a str,
+    /// Rule priority, lower values are evaluated first
+    pub priority: i32,
+    /// URL pattern of the rule
+    pub pattern: &'a str,
+    /// Gateway node the rule belongs to, used in messages
+    pub owner: &'a str,
+}
+
+/// Checks rules against the rule ceiling and for priority collisions.
+///
+/// # Returns
+///
+/// Human readable warnings, empty when the rule set is clean.
+pub fn check_rules(rules: &[RuleRef], max_rules: usize) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if rules.len() > max_rules {
+        warnings.push(format!(
+            "{} gateway rules exceed the configured maximum of {}",
+            rules.len(),
+            max_rules
+        ));
+    }
+
+    // Group by (listener, priority), keeping first-seen order for stable messages
+    let mut groups: Vec<((&str, i32), Vec<&RuleRef>)> = Vec::new();
+    let mut index: HashMap<(&str, i32), usize> = HashMap::new();
+    for rule in rules {
+        let key = (rule.listener, rule.priority);
+        match index.get(&key) {
+            Some(&i) => groups[i].1.push(rule),
+            None => {
+                index.insert(key, groups.len());
+                groups.push((key, vec![rule]));
+            }
+        }
+    }
+
+    for ((listener, priority), group) in groups {
+        if group.len() < 2 {
+            continue;
+        }
+        let patterns = group
+            .iter()
+            .map(|rule| format!("'{}' ({})", rule.pattern, rule.owner))
+            .collect::<Vec<_>>()
+            .join(", ");
+        warnings.push(format!(
+            "{} rules on listener {} share priority {}: {}; they are evaluated in rule id order",
+            group.len(),
+            listener,
+            priority,
+            patterns
+        ));
+    }
+
+    warnings
+}
+
+/// Runs [`check_rules`] over every path of an uploaded configuration.
+pub fn check_yaml_config(config: &YamlConfig, max_rules: usize) -> Vec<String> {
+    let rules: Vec<RuleRef> = config
+        .proxy
+        .iter()
+        .flat_map(|proxy| {
+            proxy.gateway.iter().flat_map(move |gateway| {
+                gateway.path.iter().map(move |path| RuleRef {
+                    listener: &proxy.listen,
+                    priority: path.priority,
+                    pattern: &path.pattern,
+                    owner: &gateway.name,
+                })
+            })
+        })
+        .collect();
+
+    check_rules(&rules, max_rules)
+}
+
+/// Validates and normalizes a `strip_prefix` value.
+///
+/// The prefix is matched literally at the start of the path, so it must be an
+/// absolute path without wildcards, query or fragment. A trailing `/` is dropped
+/// so `/api` and `/api/` behave the same.
+///
+/// # Returns
+///
+/// `Ok(None)` for an empty prefix or `/`, which strip nothing.
+pub fn normalize_strip_prefix(prefix: &str) -> Result<Option<String>, String> {
+    let prefix = prefix.trim();
+    if prefix.is_empty() {
+        return Ok(None);
+    }
+    if !prefix.starts_with('/') {
+        return Err(format!("strip_prefix '{}' must start with '/'", prefix));
+    }
+    if let Some(c) = prefix
+        .chars()
+        .find(|c| c.is_whitespace() || matches!(c, '?' | '#' | '*'))
+    {
+        return Err(format!("strip_prefix '{}' must not contain '{}'", prefix, c));
+    }
+    let prefix = prefix.trim_end_matches('/');
+    Ok((!prefix.is_empty()).then(|| prefix.to_string()))
+}
+
+/// Validates and normalizes a `sticky` value.
+///
+/// # Returns
+///
+/// `Ok(None)` for an empty value, the path is not sticky then.
+pub fn normalize_sticky(sticky: &str) -> Result<Option<String>, String> {
+    match sticky.trim().to_ascii_lowercase().as_str() {
+        "" => Ok(None),
+        mode @ ("cookie" | "ip") => Ok(Some(mode.to_string())),
+        _ => Err(format!("sticky '{}' must be 'cookie' or 'ip'", sticky.trim())),
+    }
+}
+
+/// Validates and normalizes the `split` of a gateway.
+///
+/// Targets take the `host:port` and `http://` forms of gateway node targets,
+/// weights are percentages adding up to at most 100. Entries weighing `0`
+/// are kept, the core leaves them out until they get a share.
+///
+/// # Returns
+///
+/// The entries with normalized targets, in their original order.
+pub fn normalize_split(split: &[SplitTarget]) -> Result<Vec<SplitTarget>, String> {
+    let mut normalized: Vec<SplitTarget> = Vec::new();
+    for entry in split {
+        let target = netaddr::normalize_target(&entry.target)?;
+        if netaddr::unix_socket_path(&target).is_some() {
+            return Err(format!("split target '{}' must be host:port", target));
+        }
+        if normalized.iter().any(|known| known.target == target) {
+            return Err(format!("split target '{}' is listed twice", target));
+        }
+        if entry.weight > 100 {
+            return Err(format!("split weight {} of '{}' is above 100 percent", entry.weight, target));
+        }
+        normalized.push(SplitTarget { target, weight: entry.weight });
+    }
+    let total: u32 = normalized.iter().map(|entry| entry.weight).sum();
+    if total > 100 {
+        return Err(format!("split weights add up to {}, at most 100 percent", total));
+    }
+    Ok(normalized)
+}
+
+/// Validates and normalizes the `mirror_target` of a gateway.
+///
+/// Takes the `host:port` and `http://` forms of gateway node targets, the
+/// core sends its copies over TCP only.
+///
+/// # Returns
+///
+/// `Ok(None)` for an empty value, the path isn't mirrored then.
+pub fn normalize_mirror_target(target: &str) -> Result<Option<String>, String> {
+    if target.trim().is_empty() {
+        return Ok(None);
+    }
+    let target = netaddr::normalize_target(target)?;
+    if netaddr::unix_socket_path(&target).is_some() {
+        return Err(format!("mirror target '{}' must be host:port", target));
+    }
+    Ok(Some(target))
+}
+
+/// Validates and normalizes the `redact_fields` of a gateway node.
+///
+/// Each field is a dotted JSON path like `user.ssn`, `*` matching any key.
+/// Fields are stored comma separated, so they must not contain a comma.
+///
+/// # Returns
+///
+/// The distinct fields in their original order, blank entries dropped.
+pub fn normalize_redact_fields(fields: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for field in fields {
+        let field = field.trim();
+        if field.is_empty() || normalized.iter().any(|known| known == field) {
+            continue;
+        }
+        if field.contains(',') {
+            return Err(format!("redact field '{}' must not contain ','", field));
+        }
+        if field.split('.').any(str::is_empty) {
+            return Err(format!("redact field '{}' has an empty path segment", field));
+        }
+        normalized.push(field.to_string());
+    }
+    Ok(normalized)
+}
+
+/// Validates and normalizes the `redact_mode` of a gateway node.
+///
+/// # Returns
+///
+/// `Ok(None)` for an empty value, matching fields are masked then.
+pub fn normalize_redact_mode(mode: &str) -> Result<Option<String>, String> {
+    match mode.trim().to_ascii_lowercase().as_str() {
+        "" => Ok(None),
+        mode @ ("mask" | "remove") => Ok(Some(mode.to_string())),
+        _ => Err(format!("redact_mode '{}' must be 'mask' or 'remove'", mode.trim())),
+    }
+}
+
+/// Largest `route_script` accepted, in bytes
+pub const MAX_ROUTE_SCRIPT_SIZE: usize = 64 * 1024;
+
+/// Validates the `route_script` of a gateway node.
+///
+/// The script must compile as Rhai without `eval`, which the core refuses.
+/// Run limits are enforced by the core per request.
+///
+/// # Returns
+///
+/// `Ok(None)` for a blank script, the node's rules are then used as configured.
+pub fn normalize_route_script(script: &str) -> Result<Option<String>, String> {
+    if script.trim().is_empty() {
+        return Ok(None);
+    }
+    if script.len() > MAX_ROUTE_SCRIPT_SIZE {
+        return Err(format!("route_script exceeds {} bytes", MAX_ROUTE_SCRIPT_SIZE));
+    }
+    let mut engine = rhai::Engine::new();
+    engine.disable_symbol("eval");
+    engine
+        .compile(script)
+        .map_err(|e| format!("route_script doesn't compile: {}", e))?;
+    Ok(Some(script.to_string()))
+}
+
+/// Validates and normalizes the `cors_origins` of a gateway node.
+///
+/// Origins are `scheme://host[:port]` over http or https, compared without case
+/// by browsers, or `*` for any origin.
+///
+/// # Returns
+///
+/// The distinct origins lower-cased in their original order, blank entries dropped.
+pub fn normalize_cors_origins(origins: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for origin in origins {
+        let origin = origin.trim().trim_end_matches('/').to_ascii_lowercase();
+        if origin.is_empty() || normalized.contains(&origin) {
+            continue;
+        }
+        if origin != "*" {
+            let host = origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"))
+                .ok_or_else(|| format!("cors origin '{}' must start with http:// or https://", origin))?;
+            if host.is_empty() || host.contains(['/', '@', ',']) || host.contains(char::is_whitespace) {
+                return Err(format!("cors origin '{}' must be scheme://host[:port]", origin));
+            }
+        }
+        normalized.push(origin);
+    }
+    Ok(normalized)
+}
+
+/// Validates and normalizes the `cors_methods` of a gateway node.
+///
+/// # Returns
+///
+/// The distinct methods upper-cased in their original order, blank entries dropped.
+pub fn normalize_cors_methods(methods: &[String]) -> Result<Vec<String>, String> {
+    normalize_tokens("cors method", methods, str::to_ascii_uppercase)
+}
+
+/// Validates and normalizes the `cors_headers` of a gateway node.
+///
+/// # Returns
+///
+/// The distinct header names lower-cased in their original order, blank entries dropped.
+pub fn normalize_cors_headers(headers: &[String]) -> Result<Vec<String>, String> {
+    normalize_tokens("cors header", headers, str::to_ascii_lowercase)
+}
+
+/// Longest `server_header` accepted, the core refuses longer values
+pub const MAX_SERVER_HEADER_LEN: usize = 256;
+
+/// Validates the `server_header` of a gateway node.
+///
+/// # Returns
+///
+/// `Ok(None)` for a blank value, the upstream's `Server` header is kept then.
+pub fn normalize_server_header(server: &str) -> Result<Option<String>, String> {
+    let server = server.trim();
+    if server.is_empty() {
+        return Ok(None);
+    }
+    if server.len() > MAX_SERVER_HEADER_LEN {
+        return Err(format!("server_header exceeds {} characters", MAX_SERVER_HEADER_LEN));
+    }
+    if !server.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        return Err(format!("server_header '{}' must be printable ASCII", server.escape_debug()));
+    }
+    Ok(Some(server.to_string()))
+}
+
+/// Distinct HTTP tokens, as method and header names are written, in their original order
+fn normalize_tokens(kind: &str, values: &[String], case: fn(&str) -> String) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for value in values {
+        let value = case(value.trim());
+        if value.is_empty() || normalized.contains(&value) {
+            continue;
+        }
+        if !value.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)) {
+            return Err(format!("{} '{}' is not a valid name", kind, value));
+        }
+        normalized.push(value);
+    }
+    Ok(normalized)
+}
+
+/// Validates and normalizes an SNI value listing one or more host names.
+///
+/// Names are separated by commas or whitespace and lower-cased. A wildcard is
+/// only allowed as the whole leftmost label of a name with at least two more
+/// labels (`*.example.com`), matching how the core selects certificates.
+/// IP literals are accepted as they are.
+///
+/// # Returns
+///
+/// The distinct names in their original order, empty for a blank value.
+pub fn normalize_sni(value: &str) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in value.split(|c: char| c == ',' || c.is_whitespace()) {
+        let name = name.trim().to_ascii_lowercase();
+        if name.is_empty() || names.contains(&name) {
+            continue;
+        }
+
+        let literal = name.trim_start_matches('[').trim_end_matches(']');
+        if literal.parse::<std::net::IpAddr>().is_err() {
+            let host = match name.strip_prefix("*.") {
+                Some(rest) if rest.contains('.') => rest,
+                Some(_) => {
+                    return Err(format!(
+                        "wildcard domain '{}' must have at least two labels after '*.'",
+                        name
+                    ))
+                }
+                None => name.as_str(),
+            };
+            let valid = host.split('.').all(|label| {
+                !label.is_empty()
+                    && label.len() <= 63
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            });
+            if !valid {
+                return Err(format!("invalid domain name '{}'", name));
+            }
+        }
+
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// The names an ACME certificate of a domain is ordered for.
+///
+/// HTTP-01 challenges can only prove control of a single host name, so the SNI
+/// value must list at least one name and neither wildcards nor IP literals.
+pub fn acme_names(sni: &str) -> Result<Vec<String>, String> {
+    let names = normalize_sni(sni)?;
+    if names.is_empty() {
+        return Err("an ACME certificate needs at least one domain name".to_string());
+    }
+    // Wildcards, IP literals and single label names like `localhost`
+    let issuable = |name: &&String| {
+        !name.starts_with('*')
+            && !name.starts_with('[')
+            && name.contains('.')
+            && name.parse::<std::net::IpAddr>().is_err()
+    };
+    if let Some(name) = names.iter().find(|name| !issuable(name)) {
+        return Err(format!("ACME certificates can't be issued for '{}'", name));
+    }
+    Ok(names)
+}
+
+/// Validates the `tls_min_version` of a proxy, `1.2` or `1.3`, optionally written `TLSv1.2`.
+///
+/// # Returns
+///
+/// `Ok(None)` for a blank value, the core then accepts TLS 1.2 and newer.
+pub fn normalize_tls_min_version(version: &str) -> Result<Option<String>, String> {
+    let version = version.trim();
+    if version.is_empty() {
+        return Ok(None);
+    }
+    let number = version
+        .get(..4)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("tlsv"))
+        .map_or(version, |_| &version[4..]);
+    match number {
+        "1.2" | "1.3" => Ok(Some(number.to_string())),
+        _ => Err(format!("tls_min_version '{}' must be 1.2 or 1.3", version)),
+    }
+}
+
+/// Validates the `tls_ciphers` of a proxy, cipher names separated by colons or commas.
+///
+/// Names are only checked for their characters, cipher strings like `ALL:!aNULL`
+/// are refused. Whether OpenSSL knows them is checked by the core.
+///
+/// # Returns
+///
+/// The names joined with colons, `Ok(None)` for a blank value.
+pub fn normalize_tls_ciphers(ciphers: &str) -> Result<Option<String>, String> {
+    let names: Vec<&str> = ciphers
+        .split([':', ','])
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if let Some(name) = names
+        .iter()
+        .find(|name| !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+    {
+        return Err(format!("invalid TLS cipher name '{}'", name));
+    }
+    Ok((!names.is_empty()).then(|| names.join(":")))
+}
+
+/// Validates the `fallback` of a proxy, the target of requests no gateway rule matches.
+///
+/// Accepts `404`, `500`, a `static` or `static:{..}` target like a gateway rule's,
+/// or the `host:port` of a catch-all backend. The core resolves a backend as a
+/// network address, so Unix sockets are refused.
+///
+/// # Returns
+///
+/// The normalized target, `Ok(None)` for a blank value.
+pub fn normalize_fallback(target: &str) -> Result<Option<String>, String> {
+    let target = target.trim();
+    match target {
+        "" => return Ok(None),
+        "404" | "500" | "static" => return Ok(Some(target.to_string())),
+        _ => {}
+    }
+    if let Some(spec) = target.strip_prefix("static:") {
+        return serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(spec)
+            .map(|_| Some(target.to_string()))
+            .map_err(|e| format!("invalid static fallback '{}': {}", target, e));
+    }
+    if netaddr::unix_socket_path(target).is_some() {
+        return Err(format!("fallback '{}' must not be a Unix socket", target));
+    }
+    netaddr::normalize_target(target).map(Some)
+}
+
+/// Longest accepted proxy tag
+const MAX_TAG_LEN: usize = 64;
+
+/// Validates the `tag` of a proxy, the name permissions are granted for.
+///
+/// Tags are compared exactly, so they are limited to letters, digits, `.`, `_`
+/// and `-` and kept as written apart from surrounding whitespace.
+///
+/// # Returns
+///
+/// The trimmed tag, `Ok(None)` for a blank value.
+pub fn normalize_tag(tag: &str) -> Result<Option<String>, String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Ok(None);
+    }
+    if tag.len() > MAX_TAG_LEN {
+        return Err(format!("tag '{}' is longer than {} characters", tag, MAX_TAG_LEN));
+    }
+    if !tag.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')) {
+        return Err(format!(
+            "invalid tag '{}', only letters, digits, '.', '_' and '-' are allowed",
+            tag
+        ));
+    }
+    Ok(Some(tag.to_string()))
+}
+
+/// Refuses TLS on a proxy listening on a Unix socket.
+///
+/// The core only terminates TLS on TCP listeners, such a proxy would be served
+/// in plain text.
+pub fn check_unix_listen_tls(addr_listen: &str, tls: bool) -> Result<(), String> {
+    if tls && netaddr::unix_socket_path(addr_listen).is_some() {
+        return Err(format!(
+            "TLS is not supported on the Unix socket listen address '{}'",
+            addr_listen
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule<'a>(listener: &'a str, priority: i32, pattern: &'a str) -> RuleRef<'a> {
+        RuleRef { listener, priority, pattern, owner: "gw" }
+    }
+
+    #[test]
+    fn test_clean_rules_have_no_warnings() {
+        let rules = [rule("0.0.0.0:80", 1, "^/a"), rule("0.0.0.0:80", 2, "^/b"), rule("0.0.0.0:443", 1, "^/a")];
+        assert!(check_rules(&rules, 10).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_priority_on_same_listener() {
+        let rules = [rule("0.0.0.0:80", 1, "^/a"), rule("0.0.0.0:80", 1, "^/(.*)$")];
+        let warnings = check_rules(&rules, 10);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("share priority 1"));
+    }
+
+    #[test]
+    fn test_normalize_strip_prefix() {
+        assert_eq!(normalize_strip_prefix("/api"), Ok(Some("/api".to_string())));
+        assert_eq!(normalize_strip_prefix(" /api/v1/ "), Ok(Some("/api/v1".to_string())));
+        assert_eq!(normalize_strip_prefix(""), Ok(None));
+        assert_eq!(normalize_strip_prefix("/"), Ok(None));
+        assert!(normalize_strip_prefix("api").is_err());
+        assert!(normalize_strip_prefix("/api/*").is_err());
+        assert!(normalize_strip_prefix("/api?x=1").is_err());
+    }
+
+    #[test]
+    fn test_normalize_sticky() {
+        assert_eq!(normalize_sticky("cookie"), Ok(Some("cookie".to_string())));
+        assert_eq!(normalize_sticky(" IP "), Ok(Some("ip".to_string())));
+        assert_eq!(normalize_sticky(""), Ok(None));
+        assert!(normalize_sticky("least_conn").is_err());
+    }
+
+    #[test]
+    fn test_normalize_split() {
+        let entry = |target: &str, weight: u32| SplitTarget { target: target.to_string(), weight };
+        assert_eq!(normalize_split(&[]), Ok(Vec::new()));
+        assert_eq!(
+            normalize_split(&[entry("http://Canary", 5), entry("10.0.0.9:8080", 0)]),
+            Ok(vec![entry("canary:80", 5), entry("10.0.0.9:8080", 0)])
+        );
+        assert!(normalize_split(&[entry("10.0.0.9:8080", 60), entry("10.0.0.10:8080", 41)]).is_err());
+        assert!(normalize_split(&[entry("canary:80", 5), entry("http://canary", 5)]).is_err());
+        assert!(normalize_split(&[entry("unix:/run/canary.sock", 5)]).is_err());
+        assert!(normalize_split(&[entry("https://canary", 5)]).is_err());
+    }
+
+    #[test]
+    fn test_normalize_mirror_target() {
+        assert_eq!(normalize_mirror_target(" "), Ok(None));
+        assert_eq!(normalize_mirror_target("http://Shadow"), Ok(Some("shadow:80".to_string())));
+        assert_eq!(normalize_mirror_target("10.0.0.9:8080"), Ok(Some("10.0.0.9:8080".to_string())));
+        assert!(normalize_mirror_target("unix:/run/shadow.sock").is_err());
+        assert!(normalize_mirror_target("https://shadow").is_err());
+    }
+
+    #[test]
+    fn test_normalize_redaction() {
+        let fields = ["user.ssn", " auth.*.token ", "", "user.ssn"].map(str::to_string);
+        assert_eq!(
+            normalize_redact_fields(&fields),
+            Ok(vec!["user.ssn".to_string(), "auth.*.token".to_string()])
+        );
+        assert!(normalize_redact_fields(&["user..ssn".to_string()]).is_err());
+        assert!(normalize_redact_fields(&["ssn,token".to_string()]).is_err());
+        assert_eq!(normalize_redact_mode(" Remove"), Ok(Some("remove".to_string())));
+        assert_eq!(normalize_redact_mode(""), Ok(None));
+        assert!(normalize_redact_mode("hash").is_err());
+    }
+
+    #[test]
+    fn test_normalize_route_script() {
+        assert_eq!(normalize_route_script(" \n"), Ok(None));
+        let script = "if request.method == \"DELETE\" { false }";
+        assert_eq!(normalize_route_script(script), Ok(Some(script.to_string())));
+        assert!(normalize_route_script("if (").is_err());
+        assert!(normalize_route_script("eval(\"true\")").is_err());
+        assert!(normalize_route_script(&"1;".repeat(MAX_ROUTE_SCRIPT_SIZE)).is_err());
+    }
+
+    #[test]
+    fn test_normalize_cors() {
+        let list = |values: &[&str]| values.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            normalize_cors_origins(&list(&[" https://App.example.com/ ", "", "https://app.example.com", "*"])),
+            Ok(list(&["https://app.example.com", "*"]))
+        );
+        assert_eq!(normalize_cors_origins(&list(&["http://localhost:3000"])), Ok(list(&["http://localhost:3000"])));
+        assert!(normalize_cors_origins(&list(&["app.example.com"])).is_err());
+        assert!(normalize_cors_origins(&list(&["https://app.example.com/login"])).is_err());
+        assert!(normalize_cors_origins(&list(&["https://"])).is_err());
+
+        assert_eq!(normalize_cors_methods(&list(&["get", " PUT", "GET"])), Ok(list(&["GET", "PUT"])));
+        assert!(normalize_cors_methods(&list(&["GE T"])).is_err());
+        assert_eq!(
+            normalize_cors_headers(&list(&["Authorization", "x-request-id", "authorization"])),
+            Ok(list(&["authorization", "x-request-id"]))
+        );
+        assert!(normalize_cors_headers(&list(&["x,token"])).is_err());
+    }
+
+    #[test]
+    fn test_normalize_server_header() {
+        assert_eq!(normalize_server_header("  "), Ok(None));
+        assert_eq!(normalize_server_header(" edge/1.0 (hardened) "), Ok(Some("edge/1.0 (hardened)".to_string())));
+        assert!(normalize_server_header("edge\r\nX-Injected: 1").is_err());
+        assert!(normalize_server_header("édge").is_err());
+        assert!(normalize_server_header(&"x".repeat(MAX_SERVER_HEADER_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_normalize_sni() {
+        assert_eq!(
+            normalize_sni("Example.com, *.example.com  api.example.com,example.com"),
+            Ok(vec![
+                "example.com".to_string(),
+                "*.example.com".to_string(),
+                "api.example.com".to_string(),
+            ])
+        );
+        assert_eq!(normalize_sni(" "), Ok(vec![]));
+        assert_eq!(normalize_sni("[::1]"), Ok(vec!["[::1]".to_string()]));
+        assert!(normalize_sni("*.com").is_err());
+        assert!(normalize_sni("a.*.example.com").is_err());
+        assert!(normalize_sni("a*.example.com").is_err());
+        assert!(normalize_sni("bad..example.com").is_err());
+    }
+
+    #[test]
+    fn test_acme_names() {
+        assert_eq!(
+            acme_names("Example.com, www.example.com"),
+            Ok(vec!["example.com".to_string(), "www.example.com".to_string()])
+        );
+        assert!(acme_names(" ").is_err());
+        assert!(acme_names("*.example.com").is_err());
+        assert!(acme_names("example.com, 203.0.113.7").is_err());
+        assert!(acme_names("[::1]").is_err());
+        assert!(acme_names("localhost").is_err());
+    }
+
+    #[test]
+    fn test_normalize_tls_policy() {
+        assert_eq!(normalize_tls_min_version(" "), Ok(None));
+        assert_eq!(normalize_tls_min_version("TLSv1.3"), Ok(Some("1.3".to_string())));
+        assert!(normalize_tls_min_version("1.1").is_err());
+
+        assert_eq!(normalize_tls_ciphers(""), Ok(None));
+        assert_eq!(
+            normalize_tls_ciphers("ECDHE-RSA-AES128-GCM-SHA256, TLS_AES_128_GCM_SHA256"),
+            Ok(Some("ECDHE-RSA-AES128-GCM-SHA256:TLS_AES_128_GCM_SHA256".to_string()))
+        );
+        assert!(normalize_tls_ciphers("ALL:!aNULL").is_err());
+    }
+
+    #[test]
+    fn test_normalize_fallback() {
+        assert_eq!(normalize_fallback(" "), Ok(None));
+        assert_eq!(normalize_fallback("500"), Ok(Some("500".to_string())));
+        assert_eq!(normalize_fallback("http://Backend"), Ok(Some("backend:80".to_string())));
+        assert!(normalize_fallback(r#"static:{"status":503}"#).is_ok());
+        assert!(normalize_fallback("static:{not json").is_err());
+        assert!(normalize_fallback("unix:/run/backend.sock").is_err());
+    }
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag("  "), Ok(None));
+        assert_eq!(normalize_tag(" team-a "), Ok(Some("team-a".to_string())));
+        assert_eq!(normalize_tag("Billing_v2.eu"), Ok(Some("Billing_v2.eu".to_string())));
+        assert!(normalize_tag("team a").is_err());
+        assert!(normalize_tag("team/a").is_err());
+        assert!(normalize_tag(&"a".repeat(MAX_TAG_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_unix_listen_tls() {
+        assert!(check_unix_listen_tls("unix:/run/gw.sock", false).is_ok());
+        assert!(check_unix_listen_tls("0.0.0.0:443", true).is_ok());
+        assert!(check_unix_listen_tls("unix:/run/gw.sock", true).is_err());
+    }
+
+    #[test]
+    fn test_rule_ceiling() {
+        let rules = [rule("0.0.0.0:80", 1, "^/a"), rule("0.0.0.0:80", 2, "^/b")];
+        let warnings = check_rules(&rules, 1);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("exceed the configured maximum of 1"));
+    }
+}
@@ -0,0 +1,36 @@
+use actix_web::{get, HttpRequest, HttpResponse, Responder};
+
+use super::etag;
+use crate::module::config_version;
+
+/// Current config version
+///
+/// Returns the counter bumped by every write to the proxies, proxy domains,
+/// gateway nodes and gateways, so a client can poll this instead of the lists
+/// and only refetch when the number moved.
+///
+/// # Endpoint
+///
+/// `GET /settings/version`
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// ```json
+/// { "version": 42 }
+/// ```
+///
+/// ## Internal Server Error (500)
+/// Returned when the version can't be read from the database.
+#[get("/version")]
+pub async fn get_config_version(req: HttpRequest) -> impl Responder {
+    match config_version::current() {
+        Ok(version) => etag::json(&req, &serde_json::json!({ "version": version })),
+        Err(e) => {
+            log::error!("Failed to read the config version: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to read the config version"
+            }))
+        }
+    }
+}
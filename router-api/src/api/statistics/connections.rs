@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Deserialize;
+
+use crate::module::httpc::HttpC;
+
+#[derive(Deserialize)]
+struct Params {
+    /// Listen address of the proxy, e.g. `0.0.0.0:8443`
+    proxy: Option<String>,
+    /// Target address of the proxy
+    target: Option<String>,
+}
+
+/// Connections the speed mode proxies of the core are relaying right now
+///
+/// # Endpoint
+///
+/// `GET /api/v1/statistics/connections?proxy=..&target=..`, both filters optional
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// `[{"id": .., "proxy": .., "target": .., "client": .., "protocol": "tcp"|"tls"|"ws"|"wss",
+/// "bytes_in": .., "bytes_out": .., "age_secs": ..}]`, oldest first. `client` is
+/// `null` on Unix socket listeners.
+///
+/// ## Bad Gateway (502)
+/// Returned when the core could not be reached.
+#[get("")]
+pub async fn init(client: web::Data<Arc<HttpC>>, query: web::Query<Params>) -> impl Responder {
+    let body = match client.post_with_response("/connections", &[]) {
+        Ok(body) => body,
+        Err(e) => {
+            log::error!("Failed to fetch active connections: {}", e);
+            return HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Core unreachable: {}", e)
+            }));
+        }
+    };
+
+    let mut connections = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| serde_json::from_value::<Vec<serde_json::Value>>(body["connections"].clone()).ok())
+        .unwrap_or_default();
+    let filters = [("proxy", &query.proxy), ("target", &query.target)];
+    for (field, wanted) in filters {
+        if let Some(wanted) = wanted {
+            connections.retain(|connection| connection[field].as_str() == Some(wanted.as_str()));
+        }
+    }
+
+    HttpResponse::Ok().json(connections)
+}
@@ -6,34 +6,31 @@ use crate::module::temporary_log::{tlog_gateway, tlog_proxy, BytesMetric};
 #[derive(Deserialize)]
 struct Params {
     target: Option<String>,
+    metric: Option<String>,
 }
 
+/// Average bytes per record in 15 second intervals, `?metric=in|out|total` (default `total`)
 #[get("/bytes")]
 pub async fn init(query: web::Query<Params>) -> impl Responder {
-    let end = chrono::Utc::now();
-    // start 30 minutes before
-    let start = end - chrono::Duration::minutes(120);
-
-    let result = {
-        match &query.target {
-            Some(str) => {
-                match str.as_str() {
-                    "proxy" => tlog_proxy::get_bytes_io_frame(start, end, BytesMetric::BytesTotal),
-                    "domain" => tlog_gateway::get_bytes_io_frame(start, end, BytesMetric::BytesTotal),
-                    _ => tlog_gateway::get_bytes_io_frame(start, end, BytesMetric::BytesTotal)
-                }
+    let metric = match query.metric.as_deref() {
+        None => BytesMetric::BytesTotal,
+        Some(value) => match BytesMetric::parse(value) {
+            Some(metric) => metric,
+            None => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "Invalid metric, expected in, out or total"
+                }));
             }
-            None => tlog_gateway::get_bytes_io_frame(start, end, BytesMetric::BytesTotal),
-        }
+        },
     };
 
-    let result = match result {
-        Ok(data) => data,
-        Err(e) => {
-            log::error!("Error fetching domains for proxy {}", e);
-            vec![]
-        }
+    let end = chrono::Utc::now();
+    let start = end - chrono::Duration::minutes(120);
+
+    let result = match query.target.as_deref() {
+        Some("proxy") => tlog_proxy::get_bytes_io_frame(start, end, metric),
+        _ => tlog_gateway::get_bytes_io_frame(start, end, metric),
     };
 
-    HttpResponse::Ok().json(result)
+    super::timeframes(result)
 }
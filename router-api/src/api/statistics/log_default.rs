@@ -1,4 +1,4 @@
-use actix_web::{get, web, HttpResponse, Responder};
+use actix_web::{get, web, Responder};
 use chrono::{Duration, Utc};
 use serde::Deserialize;
 
@@ -28,13 +28,5 @@ pub async fn init(query: web::Query<Params>) -> impl Responder {
         }
     };
 
-    let result = match result {
-        Ok(data) => data,
-        Err(e) => {
-            log::error!("Error fetching domains for proxy {}", e);
-            vec![]
-        }
-    };
-
-    HttpResponse::Ok().json(result)
+    super::timeframes(result)
 }
@@ -0,0 +1,32 @@
+use actix_web::{get, web, Responder};
+use serde::Deserialize;
+
+use crate::config;
+use crate::module::memory_log;
+use crate::module::temporary_log::{tlog_gateway, tlog_proxy};
+
+#[derive(Deserialize)]
+struct Params {
+    target: Option<String>,
+    /// Lowest level counted, `GWRS_LOG_MIN_LEVEL` when absent or unknown
+    min_level: Option<String>,
+}
+
+/// Per-level record counts in 15 second intervals, e.g. to chart errors against info
+#[get("/levels")]
+pub async fn init(query: web::Query<Params>) -> impl Responder {
+    let end = chrono::Utc::now();
+    let start = end - chrono::Duration::minutes(120);
+    let min_level = query
+        .min_level
+        .as_deref()
+        .and_then(memory_log::parse_level)
+        .unwrap_or_else(config::log_min_level);
+
+    let result = match query.target.as_deref() {
+        Some("proxy") => tlog_proxy::get_level_counts(start, end, min_level),
+        _ => tlog_gateway::get_level_counts(start, end, min_level),
+    };
+
+    super::timeframes(result)
+}
@@ -0,0 +1,14 @@
+use actix_web::{get, HttpResponse, Responder};
+
+use crate::module::memory_log::ring_status;
+
+/// Fill level and dropped entries of the proxy and gateway log queues, a
+/// growing `dropped` count means the statistics are missing entries
+#[get("/rings")]
+pub async fn init() -> impl Responder {
+    let (proxy, gateway) = ring_status();
+    HttpResponse::Ok().json(serde_json::json!({
+        "proxy": proxy,
+        "gateway": gateway,
+    }))
+}
@@ -40,13 +40,5 @@ pub async fn init(path: web::Path<String>, query: web::Query<Params>) -> impl Re
         }
     };
 
-    let result = match result {
-        Ok(data) => data,
-        Err(e) => {
-            log::error!("Error fetching domains for proxy {}", e);
-            vec![]
-        }
-    };
-
-    HttpResponse::Ok().json(result)
+    super::timeframes(result)
 }
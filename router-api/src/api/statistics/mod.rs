@@ -12,20 +12,34 @@
 //! 
 //! - `GET /api/v1/statistics/default` - Returns default gateway statistics for the last 120 minutes.
 //! - `GET /api/v1/statistics/status/{status}` - Returns gateway statistics filtered by HTTP status code for the last 120 minutes.
-//! - `GET /api/v1/statistics/bytes` - Returns total bytes in/out for the last 120 minutes.
+//! - `GET /api/v1/statistics/bytes` - Returns bytes in/out for the last 120 minutes.
+//! - `GET /api/v1/statistics/levels` - Returns record counts per log level for the last 120 minutes.
+//! - `GET /api/v1/statistics/rings` - Returns the fill level and dropped entries of the log queues.
+//! - `GET /api/v1/statistics/connections` - Lists the connections the proxies are relaying right now (admin).
 //! 
 //! ### Query Parameters
 //! 
-//! All endpoints accept the following optional query parameter:
+//! All time series endpoints accept the following optional query parameter:
 //! 
 //! - `target`: string, optional. Determines the data source:
 //!     - `domain` (default): Returns statistics for gateway domains.
 //!     - `proxy`: Returns statistics for proxies.
+//!
+//! The bytes endpoint also accepts `metric`: `in`, `out` or `total` (default),
+//! anything else is rejected with 400. The connections endpoint instead takes
+//! `proxy` and `target`, matched exactly against the listen and target address.
+//! 
+//! ## Partial Data
+//! 
+//! When archived log segments are corrupted the readable records are still
+//! reported, and the response carries `X-Log-Entries-Skipped` with the number of
+//! corrupted regions left out. The header is absent when nothing was skipped.
 //! 
 //! ## Authorization
 //! 
 //! Statistics endpoints may require authentication and are typically restricted to users
 //! with admin or staff roles, as they provide sensitive operational data about the system.
+//! The connections endpoint exposes client addresses and always requires an admin.
 //! 
 //! ## Data Collection
 //! 
@@ -37,13 +51,36 @@
 //! 4. Provide recent views (last 120 minutes)
 // mod logs;
 // mod logs_broadcast;
+mod connections;
 mod log_default;
 mod log_bytesio;
+mod log_level;
+mod log_ring;
 mod log_status_code;
 
-use actix_web::web;
+use actix_web::{web, HttpResponse};
+use super::users::{JwtAuth, RoleAuth};
+use serde::Serialize;
 // use logs_broadcast::LogsBroadcaster;
 
+use crate::module::temporary_log::{LogStoreError, Queried, SKIPPED_HEADER};
+
+/// Responds with the intervals of a statistics query, flagging skipped entries.
+///
+/// A failed query answers with an empty list, like an empty store.
+fn timeframes<T: Serialize>(result: Result<Queried<Vec<T>>, LogStoreError>) -> HttpResponse {
+    match result {
+        Ok(Queried { data, skipped }) if skipped > 0 => HttpResponse::Ok()
+            .insert_header((SKIPPED_HEADER, skipped.to_string()))
+            .json(data),
+        Ok(Queried { data, .. }) => HttpResponse::Ok().json(data),
+        Err(e) => {
+            log::error!("Error fetching statistics: {}", e);
+            HttpResponse::Ok().json(Vec::<T>::new())
+        }
+    }
+}
+
 /// Configure statistics API routes
 /// 
 /// This function will set up the routes for statistics endpoints when implemented.
@@ -66,6 +103,14 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .service(log_default::init)
             .service(log_status_code::init)
             .service(log_bytesio::init)
+            .service(log_level::init)
+            .service(log_ring::init)
+            .service(
+                web::scope("/connections")
+                    .wrap(JwtAuth::new())
+                    .wrap(RoleAuth::admin())
+                    .service(connections::init),
+            )
     //         .route("/gateways/{id}", web::get().to(handlers::get_gateway_stats))
     //         .route("/proxies/{id}", web::get().to(handlers::get_proxy_stats))
     //         .route("/traffic", web::get().to(handlers::get_traffic_stats))
@@ -1,4 +1,4 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use actix_web::{post, web, HttpResponse};
 use serde::{Deserialize, Serialize};
 
@@ -23,7 +23,7 @@ pub struct GatewayNode {
 }
 
 #[post("/gateway")]
-pub async fn gateway(client: web::Data<Arc<Mutex<HttpC>>>) -> HttpResponse {
+pub async fn gateway(client: web::Data<Arc<HttpC>>) -> HttpResponse {
     
     let result = match sync_gateway_nodes_to_registry(client.as_ref()).await {
         Ok(data) => {
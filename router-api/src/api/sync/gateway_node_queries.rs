@@ -1,4 +1,4 @@
-use crate::api::settings::{gateway_queries, gwnode_queries, proxy_queries, proxydomain_queries};
+use crate::api::settings::{gateway_queries, gwnode_queries, proxy_queries, proxydomain_queries, SplitTarget};
 use crate::module::database::{get_connection, DatabaseError};
 use serde::{Deserialize, Serialize};
 
@@ -10,6 +10,10 @@ pub struct QGatewayNode {
     pub addr_target: String,       // from proxy table
     pub addr_bind: String,          // from proxy table (proxy.addr_target)
     pub tls: Vec<QGatewayNodeSNI>,
+    pub tls_min_version: Option<String>, // from proxy table
+    pub tls_ciphers: Option<String>,     // from proxy table
+    pub tls_require_sni: bool,           // from proxy table
+    pub fallback: Option<String>,        // from proxy table
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +46,10 @@ pub struct QGatewayNodeSNI {
 ///   pattern TEXT NOT NULL,
 ///   target TEXT NOT NULL,
 ///   priority INTEGER NOT NULL,
+///   strip_prefix TEXT,
+///   sticky TEXT,
+///   split TEXT,
+///   mirror_target TEXT,
 ///   FOREIGN KEY (gwnode_id) REFERENCES gateway_nodes (id)
 /// )
 /// ```
@@ -52,7 +60,8 @@ pub struct QGatewayNodeSNI {
 ///   tls BOOLEAN NOT NULL DEFAULT 0,
 ///   tls_pem TEXT,
 ///   tls_key TEXT,
-///   sni TEXT
+///   sni TEXT,
+///   acme BOOLEAN NOT NULL DEFAULT 0
 /// )
 ///```
 /// ```sql
@@ -62,7 +71,11 @@ pub struct QGatewayNodeSNI {
 ///   addr_listen TEXT NOT NULL,
 ///   addr_target TEXT NOT NULL,
 ///   high_speed BOOLEAN NOT NULL DEFAULT 0,
-///   high_speed_addr TEXT
+///   high_speed_addr TEXT,
+///   tls_min_version TEXT,
+///   tls_ciphers TEXT,
+///   tls_require_sni BOOLEAN NOT NULL DEFAULT 0,
+///   fallback TEXT
 /// )
 /// ```
 /// 
@@ -81,13 +94,19 @@ pub fn get_all_gateway_nodes() -> Result<Vec<QGatewayNode>, DatabaseError> {
         SELECT DISTINCT 
             p.addr_listen,
             p.addr_target AS addr_bind,
-            gn.alt_target AS alt_target
+            gn.alt_target AS alt_target,
+            p.tls_min_version,
+            p.tls_ciphers,
+            p.tls_require_sni,
+            p.fallback
         FROM 
             gateway_nodes gn
         JOIN 
             proxies p ON gn.proxy_id = p.id
         WHERE
             p.high_speed = 0
+            AND p.redirect_to_https = 0
+            AND p.deleted_at IS NULL
     ";
 
     let listening_addresses = db.query(addr_query, [], |row| {
@@ -95,13 +114,17 @@ pub fn get_all_gateway_nodes() -> Result<Vec<QGatewayNode>, DatabaseError> {
             row.get::<_, String>(0)?, // addr_listen
             row.get::<_, String>(1)?, // addr_target (addr_bind)
             row.get::<_, String>(2)?, // addr_target
+            row.get::<_, Option<String>>(3)?, // tls_min_version
+            row.get::<_, Option<String>>(4)?, // tls_ciphers
+            row.get::<_, bool>(5)?, // tls_require_sni
+            row.get::<_, Option<String>>(6)?, // fallback
         ))
     })?;
 
     let mut gateway_nodes = Vec::new();
     
     // For each unique listening address
-    for (addr_listen, addr_bind, addr_target) in listening_addresses {
+    for (addr_listen, addr_bind, addr_target, tls_min_version, tls_ciphers, tls_require_sni, fallback) in listening_addresses {
         // Find all gateway nodes using this listening address
         let nodes_query = "
             SELECT 
@@ -112,13 +135,15 @@ pub fn get_all_gateway_nodes() -> Result<Vec<QGatewayNode>, DatabaseError> {
                 proxies p ON gn.proxy_id = p.id
             WHERE 
                 p.addr_listen = ?
+                AND p.deleted_at IS NULL
         ";
 
         let node_ids = db.query(nodes_query, [&addr_listen], |row| {
             row.get::<_, String>(0)
         })?;
 
-        // Collect all TLS configurations for all nodes with this listening address
+        // Collect all TLS configurations for all nodes with this listening address,
+        // ACME domains waiting for their first certificate are left out
         let mut tls_configs = Vec::new();
         let mut seen_snis = std::collections::HashSet::new();
 
@@ -135,6 +160,7 @@ pub fn get_all_gateway_nodes() -> Result<Vec<QGatewayNode>, DatabaseError> {
                     gateway_nodes gn ON pd.id = gn.domain_id
                 WHERE 
                     gn.id = ?
+                    AND NOT (IFNULL(pd.acme, 0) = 1 AND IFNULL(pd.tls_pem, '') = '')
                 UNION
                 SELECT 
                     IFNULL(pd.tls, 0) AS tls,
@@ -148,6 +174,7 @@ pub fn get_all_gateway_nodes() -> Result<Vec<QGatewayNode>, DatabaseError> {
                 WHERE 
                     gn.id = ? 
                     AND (gn.domain_id IS NULL OR pd.id != gn.domain_id)
+                    AND NOT (IFNULL(pd.acme, 0) = 1 AND IFNULL(pd.tls_pem, '') = '')
             ";
 
             let node_tls_configs = db.query(tls_query, [&node_id, &node_id], |row| {
@@ -176,6 +203,10 @@ pub fn get_all_gateway_nodes() -> Result<Vec<QGatewayNode>, DatabaseError> {
             addr_target,
             addr_bind,    // Added addr_bind from proxy.addr_target
             tls: tls_configs,
+            tls_min_version,
+            tls_ciphers,
+            tls_require_sni,
+            fallback,
         });
     }
 
@@ -185,13 +216,34 @@ pub fn get_all_gateway_nodes() -> Result<Vec<QGatewayNode>, DatabaseError> {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QGatewayPath {
+    pub id: String,          // from gateway table, tie-break for equal priorities
     pub priority: u8,        // from gateway table
+    pub node_priority: i32,  // from gateway node table, failover order between equal priorities
     pub tls: bool,          // from proxy_domain table
     pub sni: Option<String>, // from proxy_domain table
     pub addr_bind: String, // from proxy table
     pub addr_target: String, // from gateway node table
     pub path_listen: String, // from gateway table
     pub path_target: String, // from gateway table
+    pub strip_prefix: Option<String>, // from gateway table
+    pub compress: bool,      // from gateway node table
+    pub allow_cidrs: Vec<String>, // from gateway node table
+    pub deny_cidrs: Vec<String>,  // from gateway node table
+    pub connect_timeout_secs: Option<u32>, // from gateway node table, core default when unset
+    pub header_timeout_secs: Option<u32>,  // from gateway node table, core default when unset
+    pub total_timeout_secs: Option<u32>,   // from gateway node table, core default when unset
+    pub maintenance: bool,   // from proxy or gateway node table, either one turns it on
+    pub sticky: Option<String>, // from gateway table
+    pub redact_fields: Vec<String>, // from gateway node table
+    pub redact_mode: Option<String>, // from gateway node table
+    pub route_script: Option<String>, // from gateway node table
+    pub cors_origins: Vec<String>, // from gateway node table
+    pub cors_methods: Vec<String>, // from gateway node table
+    pub cors_headers: Vec<String>, // from gateway node table
+    pub server_header: Option<String>, // from gateway node table
+    pub strip_server_headers: bool, // from gateway node table
+    pub split: Vec<SplitTarget>, // from gateway table
+    pub mirror_target: Option<String>, // from gateway table
 }
 /// sync all path
 /// 
@@ -204,6 +256,21 @@ pub struct QGatewayPath {
 ///   title TEXT NOT NULL,
 ///   alt_target TEXT NOT NULL,
 ///   priority INTEGER NOT NULL DEFAULT 100,
+///   compress BOOLEAN NOT NULL DEFAULT 0,
+///   allow_cidrs TEXT,
+///   deny_cidrs TEXT,
+///   connect_timeout_secs INTEGER,
+///   header_timeout_secs INTEGER,
+///   total_timeout_secs INTEGER,
+///   maintenance BOOLEAN NOT NULL DEFAULT 0,
+///   redact_fields TEXT,
+///   redact_mode TEXT,
+///   route_script TEXT,
+///   cors_origins TEXT,
+///   cors_methods TEXT,
+///   cors_headers TEXT,
+///   server_header TEXT,
+///   strip_server_headers BOOLEAN NOT NULL DEFAULT 0,
 ///   FOREIGN KEY (proxy_id) REFERENCES proxies (id),
 ///   FOREIGN KEY (domain_id) REFERENCES proxy_domains (id)
 /// )
@@ -215,6 +282,10 @@ pub struct QGatewayPath {
 ///   pattern TEXT NOT NULL,
 ///   target TEXT NOT NULL,
 ///   priority INTEGER NOT NULL,
+///   strip_prefix TEXT,
+///   sticky TEXT,
+///   split TEXT,
+///   mirror_target TEXT,
 ///   FOREIGN KEY (gwnode_id) REFERENCES gateway_nodes (id)
 /// )
 /// ```
@@ -225,7 +296,8 @@ pub struct QGatewayPath {
 ///   tls BOOLEAN NOT NULL DEFAULT 0,
 ///   tls_pem TEXT,
 ///   tls_key TEXT,
-///   sni TEXT
+///   sni TEXT,
+///   acme BOOLEAN NOT NULL DEFAULT 0
 /// )
 ///```
 /// ```sql
@@ -250,27 +322,70 @@ pub fn get_all_gateway_paths() -> Result<Vec<QGatewayPath>, DatabaseError> {
 
     let query = "SELECT 
         g.priority,
+        g.id,
         pd.sni,
         p.addr_target AS addr_bind,
         gn.alt_target AS addr_target,
         g.pattern AS path_listen,
         g.target AS path_target,
-        IFNULL(pd.tls, 0) AS tls
+        IFNULL(pd.tls, 0) AS tls,
+        g.strip_prefix,
+        gn.compress,
+        gn.allow_cidrs,
+        gn.deny_cidrs,
+        gn.priority AS node_priority,
+        gn.connect_timeout_secs,
+        gn.header_timeout_secs,
+        gn.total_timeout_secs,
+        (p.maintenance OR gn.maintenance) AS maintenance,
+        g.sticky,
+        gn.redact_fields,
+        gn.redact_mode,
+        gn.route_script,
+        g.split,
+        gn.cors_origins,
+        gn.cors_methods,
+        gn.cors_headers,
+        gn.server_header,
+        gn.strip_server_headers,
+        g.mirror_target
     FROM gateways g
     JOIN gateway_nodes gn ON g.gwnode_id = gn.id
     JOIN proxies p ON gn.proxy_id = p.id
     LEFT JOIN proxy_domains pd ON gn.domain_id = pd.id
-    ORDER BY g.priority DESC";
+    WHERE g.deleted_at IS NULL AND p.deleted_at IS NULL
+    ORDER BY g.priority, gn.priority DESC, g.id";
 
     let rows = db.query(query, [], |row| {
         Ok(QGatewayPath {
             priority: row.get(0)?,
-            sni: row.get::<_, Option<String>>(1)?,
-            addr_bind: row.get(2)?,
-            addr_target: row.get(3)?,
-            path_listen: row.get(4)?,
-            path_target: row.get(5)?,
-            tls: row.get(6)?,
+            id: row.get(1)?,
+            sni: row.get::<_, Option<String>>(2)?,
+            addr_bind: row.get(3)?,
+            addr_target: row.get(4)?,
+            path_listen: row.get(5)?,
+            path_target: row.get(6)?,
+            tls: row.get(7)?,
+            strip_prefix: row.get(8)?,
+            compress: row.get(9)?,
+            allow_cidrs: gwnode_queries::comma_list(row.get(10)?),
+            deny_cidrs: gwnode_queries::comma_list(row.get(11)?),
+            node_priority: row.get(12)?,
+            connect_timeout_secs: row.get(13)?,
+            header_timeout_secs: row.get(14)?,
+            total_timeout_secs: row.get(15)?,
+            maintenance: row.get(16)?,
+            sticky: row.get(17)?,
+            redact_fields: gwnode_queries::comma_list(row.get(18)?),
+            redact_mode: row.get(19)?,
+            route_script: row.get(20)?,
+            split: gateway_queries::split_targets(row.get(21)?),
+            cors_origins: gwnode_queries::comma_list(row.get(22)?),
+            cors_methods: gwnode_queries::comma_list(row.get(23)?),
+            cors_headers: gwnode_queries::comma_list(row.get(24)?),
+            server_header: row.get(25)?,
+            strip_server_headers: row.get(26)?,
+            mirror_target: row.get(27)?,
         })
     })?;
     
@@ -1,5 +1,5 @@
 
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use super::gateway_node_queries;
 use crate::{
@@ -8,7 +8,7 @@ use crate::{
 };
 use log::{error, info, warn};
 
-pub async fn sync_gateway_nodes_to_registry(client: &Arc<Mutex<HttpC>>) -> Result<HTTPCResponse, HTTPCResponse> {
+pub async fn sync_gateway_nodes_to_registry(client: &Arc<HttpC>) -> Result<HTTPCResponse, HTTPCResponse> {
     log::info!("Syncing gateway nodes to registry...");
 
     let gateway_nodes = match gateway_node_queries::get_all_gateway_nodes() {
@@ -18,6 +18,7 @@ pub async fn sync_gateway_nodes_to_registry(client: &Arc<Mutex<HttpC>>) -> Resul
             return Err(HTTPCResponse{
                 status: "error".to_string(),
                 message: format!("Database error: {}", e),
+                count: 0,
             });
         }
     };
@@ -37,30 +38,28 @@ pub async fn sync_gateway_nodes_to_registry(client: &Arc<Mutex<HttpC>>) -> Resul
             return Err(HTTPCResponse{
                 status: "error".to_string(),
                 message: format!("Serialization error: {}", e),
+                count: 0,
             });
         }
     };
 
-    let _ = match client.lock() {
-        Ok(client)=>{
-            let _ = client.post_text("/gateway/node", &payload_str);
-            info!("Successfully sent proxy nodes to registry");
-        },
-        Err(e)=>{
-            error!("Failed to lock HTTP client: {}", e);
-            return Err(HTTPCResponse{
-                status: "error".to_string(),
-                message: format!("Client lock error: {}", e),
-            });
-        }
-    };
+    if let Err(e) = client.post_text("/gateway/node", &payload_str) {
+        error!("Failed to send gateway nodes to registry: {}", e);
+        return Err(HTTPCResponse{
+            status: "error".to_string(),
+            message: format!("Core error: {}", e),
+            count: 0,
+        });
+    }
+    info!("Successfully sent proxy nodes to registry");
     Ok(HTTPCResponse {
         status: "success".to_string(),
         message: format!("Successfully synced gateway nodes"),
+        count: gateway_nodes.len(),
     })
 }
 
-pub async fn sync_gateway_paths_to_registry(client: &Arc<Mutex<HttpC>>) -> Result<HTTPCResponse, HTTPCResponse> {
+pub async fn sync_gateway_paths_to_registry(client: &Arc<HttpC>) -> Result<HTTPCResponse, HTTPCResponse> {
     // Get the gateway nodes from the database using our JOIN query
     let gateway_path = match gateway_node_queries::get_all_gateway_paths() {
         Ok(nodes) => nodes,
@@ -69,6 +68,7 @@ pub async fn sync_gateway_paths_to_registry(client: &Arc<Mutex<HttpC>>) -> Resul
             return Err(HTTPCResponse{
                 status: "error".to_string(),
                 message: format!("Database error: {}", e),
+                count: 0,
             });
         }
     };
@@ -88,26 +88,24 @@ pub async fn sync_gateway_paths_to_registry(client: &Arc<Mutex<HttpC>>) -> Resul
             return Err(HTTPCResponse{
                 status: "error".to_string(),
                 message: format!("Serialization error: {}", e),
+                count: 0,
             });
         }
     };
 
-    let _ = match client.lock() {
-        Ok(client)=>{
-            let _ = client.post_text("/gateway/path", &payload_str);
-            info!("Successfully sent proxy nodes to registry");
-        },
-        Err(e)=>{
-            error!("Failed to lock HTTP client: {}", e);
-            return Err(HTTPCResponse{
-                status: "error".to_string(),
-                message: format!("Client lock error: {}", e),
-            });
-        }
-    };
+    if let Err(e) = client.post_text("/gateway/path", &payload_str) {
+        error!("Failed to send gateway paths to registry: {}", e);
+        return Err(HTTPCResponse{
+            status: "error".to_string(),
+            message: format!("Core error: {}", e),
+            count: 0,
+        });
+    }
+    info!("Successfully sent proxy nodes to registry");
 
     Ok(HTTPCResponse {
         status: "success".to_string(),
         message: format!("Successfully synced gateway paths"),
+        count: gateway_path.len(),
     })
 }
\ No newline at end of file
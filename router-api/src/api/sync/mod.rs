@@ -15,6 +15,12 @@
 //! - **State Reconciliation**: Ensure system-wide consistency
 //! - **Automatic Recovery**: Detect and recover from node failures
 //!
+//! ## Endpoints (Implemented)
+//!
+//! - `POST /api/v1/sync/gateway` - Push gateway nodes and paths to the core (staff)
+//! - `POST /api/v1/sync/proxy` - Push proxy nodes to the core (staff)
+//! - `POST /api/v1/sync/push-all` - Push all routing data to the core and report the counts (admin)
+//!
 //! ## Endpoints (Planned)
 //!
 //! - `POST /api/v1/sync/register` - Register a new gateway or proxy node
@@ -38,9 +44,10 @@
 //! - Stateful recovery after node restarts
 //! - Versioned configuration to prevent inconsistencies
 mod gateway_node;
-mod gateway_node_queries;
+pub(crate) mod gateway_node_queries;
 mod proxy_node;
-mod proxy_node_queries;
+pub(crate) mod proxy_node_queries;
+mod push_all;
 
 pub mod gateway_node_tcp;
 pub mod proxy_node_tcp;
@@ -54,6 +61,9 @@ use super::users::{JwtAuth, RoleAuth};
 pub struct HTTPCResponse {
     pub status: String,
     pub message: String,
+    /// Number of items pushed to the core, 0 on failure
+    #[serde(default)]
+    pub count: usize,
 }
 
 /// Configure synchronization API routes
@@ -70,6 +80,12 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .wrap(JwtAuth::new())
             .wrap(RoleAuth::staff())
             .service(gateway_node::gateway)
-            .service(proxy_node::gateway),
+            .service(proxy_node::gateway)
+            // Recovery after a core restart, admins only
+            .service(
+                web::scope("/push-all")
+                    .wrap(RoleAuth::admin())
+                    .service(push_all::init),
+            ),
     );
 }
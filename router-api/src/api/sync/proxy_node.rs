@@ -5,7 +5,7 @@
 //! Proxy Nodes are serialized and sent to the registry service to sync
 //! configuration across the distributed system.
 
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use actix_web::{post, web, HttpResponse};
 use serde::{Deserialize, Serialize};
@@ -75,7 +75,7 @@ pub struct ProxyDomain {
 }
 
 #[post("/proxy")]
-pub async fn gateway(client: web::Data<Arc<Mutex<HttpC>>>) -> HttpResponse {
+pub async fn gateway(client: web::Data<Arc<HttpC>>) -> HttpResponse {
     let result = sync_proxy_nodes_to_registry(client.as_ref()).await;
 
     match result {
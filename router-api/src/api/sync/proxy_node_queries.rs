@@ -11,11 +11,20 @@ pub struct QProxyNode {
     pub tls_key: Option<String>,        // from proxy table
     pub addr_listen: String,            // from proxy table
     pub addr_target: String,            // from proxy table
-    pub high_speed: bool,               // always true
+    pub high_speed: bool,               // from proxy table
     pub high_speed_addr: Option<String>,// always Some
-    pub buffer_size: Option<usize>,     // always None, because unused now
+    pub buffer_size: Option<usize>,     // from proxy table
     pub timeout_secs: Option<u64>,      // always None, because unused now
     pub adaptive_buffer: bool,          // always false, because unused now
+    pub redirect_to_https: bool,        // from proxy table
+    pub redirect_https_port: Option<u16>, // from proxy table
+    pub tcp_nodelay: bool,              // from proxy table
+    pub keepalive_secs: Option<u32>,    // from proxy table
+    pub keepalive_count: Option<u32>,   // from proxy table
+    pub tls_min_version: Option<String>,// from proxy table
+    pub tls_ciphers: Option<String>,    // from proxy table
+    pub tls_require_sni: bool,          // from proxy table
+    pub maintenance: bool,              // from proxy table
 }
 
 
@@ -29,7 +38,8 @@ pub struct QProxyNode {
 ///   tls BOOLEAN NOT NULL DEFAULT 0,
 ///   tls_pem TEXT,
 ///   tls_key TEXT,
-///   sni TEXT
+///   sni TEXT,
+///   acme BOOLEAN NOT NULL DEFAULT 0
 /// )
 ///```
 /// ```sql
@@ -41,6 +51,16 @@ pub struct QProxyNode {
 ///   high_speed BOOLEAN NOT NULL DEFAULT 0,
 ///   high_speed_addr TEXT,
 ///   high_speed_gwid TEXT,
+///   redirect_to_https BOOLEAN NOT NULL DEFAULT 0,
+///   redirect_https_port INTEGER,
+///   tcp_nodelay BOOLEAN NOT NULL DEFAULT 1,
+///   keepalive_secs INTEGER,
+///   keepalive_count INTEGER,
+///   buffer_size INTEGER,
+///   tls_min_version TEXT,
+///   tls_ciphers TEXT,
+///   tls_require_sni BOOLEAN NOT NULL DEFAULT 0,
+///   maintenance BOOLEAN NOT NULL DEFAULT 0,
 /// )
 /// ```
 pub fn get_all_proxy_nodes() -> Result<Vec<QProxyNode>, DatabaseError> {
@@ -51,8 +71,9 @@ pub fn get_all_proxy_nodes() -> Result<Vec<QProxyNode>, DatabaseError> {
     proxydomain_queries::ensure_proxy_domains_table()?;
     gwnode_queries::ensure_gateway_nodes_table()?;
     
-    // Query to retrieve proxy nodes with TLS information via gateway_nodes
-    // Filtering for proxies where high_speed is enabled (true/1)
+    // Query to retrieve proxy nodes with TLS information via gateway_nodes,
+    // speed mode listeners of ACME domains waiting for their first certificate are left out
+    // Filtering for proxies where high_speed or redirect_to_https is enabled (true/1)
     let query = "
         SELECT 
             COALESCE(pd.tls, 0) AS tls,
@@ -61,11 +82,20 @@ pub fn get_all_proxy_nodes() -> Result<Vec<QProxyNode>, DatabaseError> {
             pd.tls_key,
             p.addr_listen,
             p.addr_target,
-            1 AS high_speed,
+            p.high_speed,
             p.high_speed_addr,
-            NULL AS buffer_size,
+            p.buffer_size,
             NULL AS timeout_secs,
-            0 AS adaptive_buffer
+            0 AS adaptive_buffer,
+            p.redirect_to_https,
+            p.redirect_https_port,
+            p.tcp_nodelay,
+            p.keepalive_secs,
+            p.keepalive_count,
+            p.tls_min_version,
+            p.tls_ciphers,
+            p.tls_require_sni,
+            p.maintenance
         FROM 
             proxies p
         LEFT JOIN 
@@ -73,7 +103,9 @@ pub fn get_all_proxy_nodes() -> Result<Vec<QProxyNode>, DatabaseError> {
         LEFT JOIN 
             proxy_domains pd ON gn.domain_id = pd.id
         WHERE
-            p.high_speed = 1
+            (p.high_speed = 1 OR p.redirect_to_https = 1)
+            AND p.deleted_at IS NULL
+            AND NOT (p.high_speed = 1 AND IFNULL(pd.acme, 0) = 1 AND IFNULL(pd.tls_pem, '') = '')
     ";
     
     let proxy_nodes = db.query(query, [], |row| {
@@ -89,6 +121,15 @@ pub fn get_all_proxy_nodes() -> Result<Vec<QProxyNode>, DatabaseError> {
             buffer_size: row.get(8)?,
             timeout_secs: row.get(9)?,
             adaptive_buffer: row.get(10)?,
+            redirect_to_https: row.get(11)?,
+            redirect_https_port: row.get(12)?,
+            tcp_nodelay: row.get(13)?,
+            keepalive_secs: row.get(14)?,
+            keepalive_count: row.get(15)?,
+            tls_min_version: row.get(16)?,
+            tls_ciphers: row.get(17)?,
+            tls_require_sni: row.get(18)?,
+            maintenance: row.get(19)?,
         })
     })?;
     
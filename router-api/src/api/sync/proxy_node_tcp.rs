@@ -1,12 +1,12 @@
 
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use crate::module::httpc::HttpC;
 
 use super::{proxy_node_queries, HTTPCResponse};
 use log::{error, info, warn};
 
-pub async fn sync_proxy_nodes_to_registry(client: &Arc<Mutex<HttpC>>) -> Result<HTTPCResponse, HTTPCResponse> {
+pub async fn sync_proxy_nodes_to_registry(client: &Arc<HttpC>) -> Result<HTTPCResponse, HTTPCResponse> {
     log::info!("Syncing proxy nodes to registry...");
 
     // Get the proxy nodes from the database using our JOIN query
@@ -17,6 +17,7 @@ pub async fn sync_proxy_nodes_to_registry(client: &Arc<Mutex<HttpC>>) -> Result<
             return Err(HTTPCResponse{
                 status: "error".to_string(),
                 message: format!("Database error: {}", e),
+                count: 0,
             });
         }
     };
@@ -36,26 +37,24 @@ pub async fn sync_proxy_nodes_to_registry(client: &Arc<Mutex<HttpC>>) -> Result<
             return Err(HTTPCResponse{
                 status: "error".to_string(),
                 message: format!("Serialization error: {}", e),
+                count: 0,
             });
         }
     };
 
-    let _ = match client.lock() {
-        Ok(client)=>{
-            let _ = client.post_text("/proxy/node", &payload_str);
-            info!("Successfully sent proxy nodes to registry");
-        },
-        Err(e)=>{
-            error!("Failed to lock HTTP client: {}", e);
-            return Err(HTTPCResponse{
-                status: "error".to_string(),
-                message: format!("Client lock error: {}", e),
-            });
-        }
-    };
+    if let Err(e) = client.post_text("/proxy/node", &payload_str) {
+        error!("Failed to send proxy nodes to registry: {}", e);
+        return Err(HTTPCResponse{
+            status: "error".to_string(),
+            message: format!("Core error: {}", e),
+            count: 0,
+        });
+    }
+    info!("Successfully sent proxy nodes to registry");
 
     Ok(HTTPCResponse {
         status: "success".to_string(),
         message: format!("Successfully sync proxy nodes"),
+        count: proxy_nodes.len(),
     })
 }
\ No newline at end of file
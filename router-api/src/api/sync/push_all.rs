@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use actix_web::{post, web, HttpResponse};
+
+use crate::module::{core_sync, httpc::HttpC};
+
+/// Pushes every proxy node, gateway node and gateway path to the core now
+///
+/// Meant for recovering a core that restarted on its own and lost its routing,
+/// the background sync only pushes again when the database changes.
+///
+/// # Endpoint
+///
+/// `POST /api/v1/sync/push-all` (admin only)
+///
+/// # Response
+///
+/// ## Success (200 OK)
+/// ```json
+/// {"status": "success", "proxy_nodes": 3, "gateway_nodes": 2, "gateway_paths": 7, "errors": []}
+/// ```
+///
+/// ## Bad Gateway (502)
+/// At least one part failed; the counts of the parts that were pushed are still
+/// reported and `errors` lists the failures.
+#[post("")]
+pub async fn init(client: web::Data<Arc<HttpC>>) -> HttpResponse {
+    let report = core_sync::sync_all(client.as_ref()).await;
+
+    if report.is_ok() {
+        log::info!(
+            "Pushed {} proxy nodes, {} gateway nodes and {} gateway paths to the core",
+            report.proxy_nodes,
+            report.gateway_nodes,
+            report.gateway_paths
+        );
+        HttpResponse::Ok().json(serde_json::json!({
+            "status": "success",
+            "proxy_nodes": report.proxy_nodes,
+            "gateway_nodes": report.gateway_nodes,
+            "gateway_paths": report.gateway_paths,
+            "errors": report.errors
+        }))
+    } else {
+        log::error!("Failed to push routing data to the core: {}", report.errors.join("; "));
+        HttpResponse::BadGateway().json(serde_json::json!({
+            "status": "error",
+            "proxy_nodes": report.proxy_nodes,
+            "gateway_nodes": report.gateway_nodes,
+            "gateway_paths": report.gateway_paths,
+            "errors": report.errors
+        }))
+    }
+}
@@ -11,6 +11,14 @@ use actix_web::{
 
 use futures_util::future::LocalBoxFuture;
 use crate::api::users::helper::auth_token::{self, Claims, AuthConfig};
+use crate::module::api_tls::ClientCert;
+
+/// Caps the role of the claims at the role of the connection's client certificate
+fn cap_to_client_cert(req: &ServiceRequest, claims: &mut Claims) {
+    if let Some(cap) = req.conn_data::<ClientCert>().and_then(|cert| cert.role.as_deref()) {
+        claims.role = auth_token::cap_role(&claims.role, cap);
+    }
+}
 
 // New JWT-based authentication middleware
 pub struct JwtAuth {
@@ -95,10 +103,11 @@ where
             let token = &auth_header[7..];
 
             // Validate JWT token
-            let claims = match auth_token::validate_token(token, &auth_config) {
+            let mut claims = match auth_token::validate_token(token, &auth_config) {
                 Ok(claims) => claims,
                 Err(_) => return Err(ErrorUnauthorized("Invalid or expired token")),
             };
+            cap_to_client_cert(&req, &mut claims);
 
             // Store claims in request extensions for access in handlers
             req.extensions_mut().insert(claims);
@@ -214,10 +223,11 @@ where
             let token = &auth_header[7..];
 
             // Validate JWT token
-            let claims = match auth_token::validate_token(token, &auth_config) {
+            let mut claims = match auth_token::validate_token(token, &auth_config) {
                 Ok(claims) => claims,
                 Err(_) => return Err(ErrorUnauthorized("Invalid or expired token")),
             };
+            cap_to_client_cert(&req, &mut claims);
 
             // Check if user has required role
             let has_required_role = match required_role.as_str() {
@@ -354,10 +364,11 @@ where
             let token = &auth_header[7..];
 
             // Validate JWT token
-            let claims = match auth_token::validate_token(token, &auth_config) {
+            let mut claims = match auth_token::validate_token(token, &auth_config) {
                 Ok(claims) => claims,
                 Err(_) => return Err(ErrorUnauthorized("Invalid or expired token")),
             };
+            cap_to_client_cert(&req, &mut claims);
 
             // Extract user_id from path
             let path = req.match_info();
@@ -262,6 +262,32 @@ pub fn is_user_or_above(_role: &str) -> bool {
     true // Everyone has at least user level privileges if they have a valid token
 }
 
+/// Caps a role at another one
+///
+/// Used for requests over a client certificate that is mapped to a role, the
+/// user then acts with the lower of their own role and the certificate's.
+///
+/// # Parameters
+///
+/// * `role` - The role of the user
+/// * `cap` - The highest role allowed
+///
+/// # Returns
+///
+/// The lower of both roles
+pub fn cap_role(role: &str, cap: &str) -> String {
+    let rank = |role: &str| match role {
+        _ if is_admin(role) => 2,
+        _ if is_staff_or_admin(role) => 1,
+        _ => 0,
+    };
+    if rank(cap) < rank(role) {
+        cap.to_string()
+    } else {
+        role.to_string()
+    }
+}
+
 /// Checks if user with given ID and role can modify another user with target_id
 ///
 /// This implements the permission logic for user modification:
@@ -291,4 +317,17 @@ pub fn can_modify_user(user_id: &str, user_role: &str, target_id: &str) -> bool
     
     // Regular users can only modify themselves
     user_id == target_id
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cap_role() {
+        assert_eq!(cap_role("admin", "user"), "user");
+        assert_eq!(cap_role("admin", "staff"), "staff");
+        assert_eq!(cap_role("staff", "admin"), "staff");
+        assert_eq!(cap_role("user", "admin"), "user");
+    }
+}
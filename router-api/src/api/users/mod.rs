@@ -22,6 +22,9 @@
 //! - **Staff**: Extended privileges for managing regular users and some settings
 //! - **User**: Basic access to own profile and public resources
 //!
+//! Below admin, access to proxies and their gateway nodes and gateways is given
+//! per proxy tag, see the permissions of the settings module.
+//!
 //! ## Security Features
 //!
 //! - Token-based authentication with configurable expiration
@@ -29,17 +32,25 @@
 //! - Role-based middleware for securing endpoints
 //! - Self-check middleware to ensure users can only modify their own data
 //!
-//! ## Default Administrator Account
+//! ## First Administrator Account
 //!
-//! The module automatically creates a default administrator account if no users
-//! exist in the database. This ensures that there's always an admin user for
-//! initial system setup.
+//! The module creates an administrator account at startup if no users exist in
+//! the database, so there's always an admin user for initial system setup. Its
+//! username is `GWRS_ADMIN_USER` (`admin` when unset) and its password
+//! `GWRS_ADMIN_PASS`, or the hash in `GWRS_ADMIN_PASS_HASH`. Without either a
+//! random password is generated and printed once to stderr, there is no
+//! built-in default password. Databases still holding the `adminpassword`
+//! account of earlier versions get a warning on every boot.
 
 mod handlers;
 pub mod helper;
 mod models;
 
 use actix_web::web;
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::config;
+use models::User;
 // Re-export auth helpers for use in other modules
 pub use helper::{JwtAuth, RoleAuth, UserSelfCheck};
 
@@ -80,18 +91,41 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
     );
 }
 
-/// Initializes the users database table and creates a default admin user if needed
+/// Password hash of the administrator earlier versions created with `adminpassword`
+const LEGACY_DEFAULT_HASH: &str = "hashed_adminpassword";
+
+/// Length of a generated first administrator password
+const GENERATED_PASSWORD_LEN: usize = 24;
+
+/// Password hash of the first administrator, and the password when it was generated.
 ///
-/// This function:
-/// 1. Creates the users table if it doesn't exist
-/// 2. Checks if any users exist in the database
-/// 3. If no users exist, creates a default administrator account
+/// `GWRS_ADMIN_PASS_HASH` takes precedence over `GWRS_ADMIN_PASS`, without
+/// either a random password is generated.
+fn first_admin_password(pass_hash: Option<String>, pass: Option<String>) -> (String, Option<String>) {
+    if let Some(hash) = pass_hash {
+        return (hash, None);
+    }
+    if let Some(pass) = pass {
+        return (User::hash_password(&pass), None);
+    }
+    let generated: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(GENERATED_PASSWORD_LEN)
+        .map(char::from)
+        .collect();
+    (User::hash_password(&generated), Some(generated))
+}
+
+/// Creates the first administrator if no users exist
 ///
-/// The default admin account has these credentials:
-/// - Username: admin
-/// - Password: adminpassword
-/// - Email: admin@example.com
-/// - Role: admin
+/// The users table itself is created by the schema migrations at startup
+/// (see `module::migrations`). This function, called once at startup:
+/// 1. Checks if any users exist in the database
+/// 2. If no users exist, creates an administrator account with the
+///    `GWRS_ADMIN_USER` username, email `admin@example.com` and the password
+///    of `GWRS_ADMIN_PASS_HASH` or `GWRS_ADMIN_PASS`, or a random one printed
+///    once to stderr
+/// 3. Warns about accounts still using the former default password `adminpassword`
 ///
 /// # Returns
 ///
@@ -103,40 +137,75 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
 pub fn init_database() -> Result<(), crate::module::database::DatabaseError> {
     let db = crate::module::database::get_connection()?;
 
-    // Create users table if not exists with role field
-    db.execute(
-        "CREATE TABLE IF NOT EXISTS users (
-            id TEXT PRIMARY KEY,
-            username TEXT NOT NULL UNIQUE,
-            email TEXT NOT NULL UNIQUE,
-            password_hash TEXT NOT NULL,
-            role TEXT NOT NULL CHECK(role IN ('admin', 'staff', 'user')),
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )",
-        [],
-    )?;
-
-    // Create a default admin user if no users exist
+    // Create the first admin user if no users exist
     let user_count: i64 = db
         .query_one("SELECT COUNT(*) FROM users", [], |row| row.get::<_, i64>(0))?
         .unwrap_or(0);
 
     if user_count == 0 {
-        // Create a default admin user
+        let username = config::admin_user();
+        let (password_hash, generated) =
+            first_admin_password(config::admin_pass_hash(), config::admin_pass());
+        let id = uuid::Uuid::new_v4().to_string();
         db.execute(
             "INSERT INTO users (id, username, email, password_hash, role) VALUES (?, ?, ?, ?, ?)",
-            [
-                &uuid::Uuid::new_v4().to_string(),
-                "admin",
-                "admin@example.com",
-                "hashed_adminpassword", // In a real app, use proper password hashing
-                "admin",
-            ],
+            [id.as_str(), username.as_str(), "admin@example.com", password_hash.as_str(), "admin"],
         )?;
 
-        log::debug!("Created default admin user (username: admin, password: adminpassword)");
+        match generated {
+            // Shown this once only, it isn't stored anywhere but as its hash
+            Some(password) => {
+                log::warn!(
+                    "Created admin user '{}' with a generated password, printed to stderr. Set {} or {} to choose it.",
+                    username,
+                    config::ENV_ADMIN_PASS,
+                    config::ENV_ADMIN_PASS_HASH
+                );
+                eprintln!("==================================================================");
+                eprintln!(" First boot: created the administrator account");
+                eprintln!("   username: {}", username);
+                eprintln!("   password: {}", password);
+                eprintln!(" This password is not shown again, change it after logging in.");
+                eprintln!("==================================================================");
+            }
+            None => log::info!("Created admin user '{}' with the configured password", username),
+        }
+    }
+
+    let defaults = db.query(
+        "SELECT username FROM users WHERE password_hash = ?",
+        [LEGACY_DEFAULT_HASH],
+        |row| row.get::<_, String>(0),
+    )?;
+    for username in defaults {
+        log::warn!(
+            "!!! User '{}' still has the default password 'adminpassword', anyone can log in as it. Change it now !!!",
+            username
+        );
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_admin_password() {
+        // A pre-hashed password is stored as it is and wins over a plain one
+        let (hash, generated) = first_admin_password(Some("hashed_s3cret".to_string()), Some("other".to_string()));
+        assert_eq!((hash.as_str(), generated), ("hashed_s3cret", None));
+
+        let (hash, generated) = first_admin_password(None, Some("s3cret".to_string()));
+        assert_eq!((hash, generated), (User::hash_password("s3cret"), None));
+
+        // Without either a random password is generated, never the former default
+        let (hash, generated) = first_admin_password(None, None);
+        let generated = generated.expect("password generated");
+        assert_eq!(generated.len(), GENERATED_PASSWORD_LEN);
+        assert!(generated.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(hash, User::hash_password(&generated));
+        assert_ne!(hash, LEGACY_DEFAULT_HASH);
+    }
+}
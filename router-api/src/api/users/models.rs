@@ -150,12 +150,19 @@ impl User {
             id: Uuid::new_v4().to_string(),
             username,
             email,
-            password_hash: format!("hashed_{}", password), // Simulated hash
+            password_hash: Self::hash_password(&password),
             role,
             created_at: None,
             updated_at: None,
         }
     }
+
+    /// Hash of a plaintext password as it is stored in `password_hash`
+    ///
+    /// Simulated like the rest of the password handling, see [`User::new`].
+    pub fn hash_password(password: &str) -> String {
+        format!("hashed_{}", password) // Simulated hash
+    }
 }
 
 /// Request DTO for user creation
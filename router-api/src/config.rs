@@ -1,8 +1,13 @@
+use actix_cors::Cors;
+use actix_web::http::header;
 use mini_config::Configure;
+use router_common::env_list;
+use serde_json::json;
 use std::sync::{Arc, RwLock};
 use std::sync::Once;
+use std::path::PathBuf;
 
-use crate::module::temporary_log;
+use crate::module::{config_version, memory_log, temporary_log};
 
 #[derive(Debug, Clone, Configure)]
 pub enum Api {
@@ -46,8 +51,490 @@ pub fn append_config(_key: &str, _value: &str) {
     // }
 }
 
+/// Environment variable holding a comma separated list of allowed CORS origins
+pub const ENV_CORS_ORIGINS: &str = "GWRS_CORS_ORIGINS";
+/// Environment variable holding a comma separated list of allowed CORS methods
+pub const ENV_CORS_METHODS: &str = "GWRS_CORS_METHODS";
+/// Environment variable holding a comma separated list of allowed CORS headers
+pub const ENV_CORS_HEADERS: &str = "GWRS_CORS_HEADERS";
+
+/// Methods used by the API routes, including PUT for user updates.
+/// OPTIONS is answered by the CORS middleware itself for preflight requests.
+const DEFAULT_CORS_METHODS: [&str; 5] = ["GET", "POST", "PUT", "DELETE", "OPTIONS"];
+
+/// Request headers allowed when `GWRS_CORS_HEADERS` is unset
+const DEFAULT_CORS_HEADERS: [&str; 4] = ["authorization", "accept", "content-type", "if-none-match"];
+
+/// Cross-origin settings for the API server.
+///
+/// When no origins are configured (or `*` is given) any origin is allowed,
+/// which keeps local development working out of the box. Set
+/// `GWRS_CORS_ORIGINS` in production to restrict it.
+#[derive(Debug, Clone, Default)]
+pub struct CorsConfig {
+    /// Allowed origins, empty means any origin
+    pub origins: Vec<String>,
+    /// Allowed methods, empty means the default method set
+    pub methods: Vec<String>,
+    /// Allowed request headers, empty means the default header set
+    pub headers: Vec<String>,
+}
+
+impl CorsConfig {
+    /// Reads the CORS settings from the `GWRS_CORS_*` environment variables.
+    pub fn from_env() -> Self {
+        Self {
+            origins: env_list(ENV_CORS_ORIGINS),
+            methods: env_list(ENV_CORS_METHODS),
+            headers: env_list(ENV_CORS_HEADERS),
+        }
+    }
+
+    /// Builds the actix CORS middleware from these settings.
+    pub fn build(&self) -> Cors {
+        let mut cors = Cors::default();
+
+        if self.origins.is_empty() || self.origins.iter().any(|o| o == "*") {
+            cors = cors.allow_any_origin();
+        } else {
+            for origin in &self.origins {
+                cors = cors.allowed_origin(origin);
+            }
+        }
+
+        cors = if self.methods.is_empty() {
+            cors.allowed_methods(DEFAULT_CORS_METHODS)
+        } else {
+            cors.allowed_methods(self.methods.iter().map(String::as_str))
+        };
+
+        cors = if self.headers.is_empty() {
+            cors.allowed_headers(DEFAULT_CORS_HEADERS)
+        } else {
+            cors.allowed_headers(self.headers.iter().map(String::as_str))
+        };
+
+        // Lets the GUI read the tags it revalidates the settings with, and
+        // whether statistics are partial
+        cors.expose_headers(vec![
+            header::ETAG,
+            header::HeaderName::from_static(config_version::CONFIG_VERSION_HEADER),
+            header::HeaderName::from_static(temporary_log::SKIPPED_HEADER),
+        ])
+        .supports_credentials()
+        .max_age(3600)
+    }
+}
+
+/// Environment variable overriding the number of HTTP worker threads
+pub const ENV_WORKERS: &str = "ROUTER_API_WORKERS";
+
+/// Parses a worker count, rejecting zero and non-numeric values.
+pub fn parse_workers(value: &str) -> Result<usize, String> {
+    match value.trim().parse::<usize>() {
+        Ok(0) => Err("worker count must be at least 1".to_string()),
+        Ok(workers) => Ok(workers),
+        Err(_) => Err(format!("invalid worker count: {}", value)),
+    }
+}
+
+/// Resolves the number of HTTP worker threads.
+///
+/// The `--workers` argument takes precedence over `ROUTER_API_WORKERS`,
+/// and both fall back to the number of logical CPUs.
+pub fn workers(cli: Option<usize>) -> Result<usize, String> {
+    if let Some(workers) = cli {
+        return Ok(workers);
+    }
+
+    match std::env::var(ENV_WORKERS) {
+        Ok(value) => parse_workers(&value).map_err(|e| format!("{}: {}", ENV_WORKERS, e)),
+        Err(_) => Ok(std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)),
+    }
+}
+
+/// Environment variable overriding the gateway rule ceiling checked at upload time
+pub const ENV_MAX_GATEWAY_RULES: &str = "GWRS_MAX_GATEWAY_RULES";
+
+/// Gateway rule count above which uploads are flagged
+const DEFAULT_MAX_GATEWAY_RULES: usize = 1000;
+
+/// Returns the configured gateway rule ceiling.
+pub fn max_gateway_rules() -> usize {
+    std::env::var(ENV_MAX_GATEWAY_RULES)
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_GATEWAY_RULES)
+}
+
+/// Environment variable setting the lowest log level (`trace`..`error`) the level
+/// statistics count by default; every entry is still stored
+pub const ENV_LOG_MIN_LEVEL: &str = "GWRS_LOG_MIN_LEVEL";
+
+/// Returns the configured minimum log level, keeping everything by default.
+pub fn log_min_level() -> u8 {
+    std::env::var(ENV_LOG_MIN_LEVEL)
+        .ok()
+        .and_then(|value| memory_log::parse_level(&value))
+        .unwrap_or(0)
+}
+
+/// Environment variable setting how many connections each SQLite database pool keeps open
+pub const ENV_DB_POOL_SIZE: &str = "GWRS_DB_POOL_SIZE";
+
+/// Connections per database pool by default
+const DEFAULT_DB_POOL_SIZE: usize = 8;
+
+/// Returns the configured connection pool size, at least 1.
+pub fn db_pool_size() -> usize {
+    std::env::var(ENV_DB_POOL_SIZE)
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|size| *size >= 1)
+        .unwrap_or(DEFAULT_DB_POOL_SIZE)
+}
+
+/// Environment variable setting how many days before expiry a certificate is warned about
+pub const ENV_CERT_WARN_DAYS: &str = "GWRS_CERT_WARN_DAYS";
+
+/// Days before expiry a certificate is warned about by default
+const DEFAULT_CERT_WARN_DAYS: i64 = 14;
+
+/// Returns the configured certificate expiry warning threshold in days.
+pub fn cert_warn_days() -> i64 {
+    std::env::var(ENV_CERT_WARN_DAYS)
+        .ok()
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_CERT_WARN_DAYS)
+}
+
+/// Environment variable with the directory URL of the ACME CA certificates of `acme` domains are ordered from
+pub const ENV_ACME_DIRECTORY: &str = "GWRS_ACME_DIRECTORY";
+
+/// Let's Encrypt production directory
+pub const DEFAULT_ACME_DIRECTORY: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// Returns the configured ACME directory URL, Let's Encrypt by default.
+pub fn acme_directory() -> String {
+    std::env::var(ENV_ACME_DIRECTORY)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| DEFAULT_ACME_DIRECTORY.to_string())
+}
+
+/// Environment variable with the contact email of the ACME account, told about expiring certificates
+pub const ENV_ACME_EMAIL: &str = "GWRS_ACME_EMAIL";
+
+/// Returns the configured ACME contact email, the account has no contact without one.
+pub fn acme_email() -> Option<String> {
+    std::env::var(ENV_ACME_EMAIL)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|email| !email.is_empty())
+}
+
+/// Environment variable agreeing to the terms of service of the ACME CA, without it no certificates are ordered
+pub const ENV_ACME_ACCEPT_TERMS: &str = "GWRS_ACME_ACCEPT_TERMS";
+
+/// Whether the operator agreed to the terms of service of the ACME CA.
+///
+/// Off by default, the ACME renewer only starts with `1`, `true`, `yes` or `on`.
+pub fn acme_accept_terms() -> bool {
+    std::env::var(ENV_ACME_ACCEPT_TERMS).map_or(false, |value| {
+        matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on")
+    })
+}
+
+/// Environment variable setting how many days before expiry an ACME certificate is renewed
+pub const ENV_ACME_RENEW_DAYS: &str = "GWRS_ACME_RENEW_DAYS";
+
+/// Days before expiry an ACME certificate is renewed by default, a third of Let's Encrypt's 90
+const DEFAULT_ACME_RENEW_DAYS: i64 = 30;
+
+/// Returns the configured ACME renewal threshold in days.
+pub fn acme_renew_days() -> i64 {
+    std::env::var(ENV_ACME_RENEW_DAYS)
+        .ok()
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .filter(|days| *days >= 1)
+        .unwrap_or(DEFAULT_ACME_RENEW_DAYS)
+}
+
+/// Environment variable setting how many days deleted proxies and gateways stay in the trash
+pub const ENV_TRASH_RETENTION_DAYS: &str = "GWRS_TRASH_RETENTION_DAYS";
+
+/// Days deleted items are kept by default
+const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+
+/// Returns the configured trash retention in days, 0 keeps deleted items forever.
+pub fn trash_retention_days() -> i64 {
+    std::env::var(ENV_TRASH_RETENTION_DAYS)
+        .ok()
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS)
+}
+
+/// Environment variable holding the shared key config bundles are signed with
+pub const ENV_BUNDLE_KEY: &str = "GWRS_BUNDLE_KEY";
+
+/// Returns the configured bundle signing key, bundles are unsigned without one.
+pub fn bundle_key() -> Option<String> {
+    std::env::var(ENV_BUNDLE_KEY)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
+/// Environment variable setting the directory log segments and consumer checkpoints are kept in
+pub const ENV_LOG_ARCHIVE_DIR: &str = "GWRS_LOG_ARCHIVE_DIR";
+
+/// Returns the configured log archive directory.
+///
+/// Defaults to `gwrs/logment` under the user's local data directory
+/// (`~/.local/share` on Linux), or `/tmp/gwrs/logment` when there is none.
+pub fn log_archive_dir() -> PathBuf {
+    std::env::var(ENV_LOG_ARCHIVE_DIR)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::data_local_dir().map(|dir| dir.join("gwrs").join("logment")))
+        .unwrap_or_else(|| PathBuf::from("/tmp/gwrs/logment"))
+}
+
+/// Environment variable setting how many minutes of archived log segments are merged into one file
+pub const ENV_LOG_COMPACTION_MINUTES: &str = "GWRS_LOG_COMPACTION_MINUTES";
+
+/// Minutes of segments per compacted file by default
+const DEFAULT_LOG_COMPACTION_MINUTES: i64 = 5;
+
+/// Returns the configured compaction window in minutes, 0 disables compaction.
+pub fn log_compaction_minutes() -> i64 {
+    std::env::var(ENV_LOG_COMPACTION_MINUTES)
+        .ok()
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .filter(|minutes| *minutes >= 0)
+        .unwrap_or(DEFAULT_LOG_COMPACTION_MINUTES)
+}
+
+/// Environment variable with the `host:port` of the router-core protocol server
+pub const ENV_CORE_ADDRESS: &str = "GWRS_CORE_ADDRESS";
+
+/// Protocol server address of a router-core on the same host
+const DEFAULT_CORE_ADDRESS: &str = "127.0.0.1:30099";
+
+/// Returns the address of the router-core protocol server.
+///
+/// Set it to the core's `GWRS_PROTTP_ADDRESS` as seen from the API, e.g.
+/// `router-core:30099` when the two run in separate containers.
+pub fn core_address() -> String {
+    std::env::var(ENV_CORE_ADDRESS)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|address| !address.is_empty())
+        .unwrap_or_else(|| DEFAULT_CORE_ADDRESS.to_string())
+}
+
+/// Environment variable with the bearer token of the router-core protocol server
+pub const ENV_CORE_TOKEN: &str = "GWRS_CORE_TOKEN";
+
+/// Returns the token sent to router-core, the core's `GWRS_PROTTP_TOKEN`.
+pub fn core_token() -> Option<String> {
+    std::env::var(ENV_CORE_TOKEN)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// Environment variable with the username of the administrator created on first boot
+pub const ENV_ADMIN_USER: &str = "GWRS_ADMIN_USER";
+/// Environment variable with the password of the administrator created on first boot
+pub const ENV_ADMIN_PASS: &str = "GWRS_ADMIN_PASS";
+/// Environment variable with a password hash of the first administrator, instead of [`ENV_ADMIN_PASS`]
+pub const ENV_ADMIN_PASS_HASH: &str = "GWRS_ADMIN_PASS_HASH";
+
+/// Username of the first administrator when `GWRS_ADMIN_USER` is unset
+const DEFAULT_ADMIN_USER: &str = "admin";
+
+/// Returns the username of the administrator created on first boot.
+pub fn admin_user() -> String {
+    std::env::var(ENV_ADMIN_USER)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|user| !user.is_empty())
+        .unwrap_or_else(|| DEFAULT_ADMIN_USER.to_string())
+}
+
+/// Returns the password of the first administrator, `None` when unset or empty.
+pub fn admin_pass() -> Option<String> {
+    std::env::var(ENV_ADMIN_PASS)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|pass| !pass.is_empty())
+}
+
+/// Returns the pre-hashed password of the first administrator, `None` when unset or empty.
+pub fn admin_pass_hash() -> Option<String> {
+    std::env::var(ENV_ADMIN_PASS_HASH)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+}
+
+/// Environment variable with the PEM certificate chain the API is served with over TLS
+pub const ENV_API_TLS_CERT: &str = "GWRS_API_TLS_CERT";
+/// Environment variable with the PEM private key of [`ENV_API_TLS_CERT`]
+pub const ENV_API_TLS_KEY: &str = "GWRS_API_TLS_KEY";
+/// Environment variable with the PEM CA bundle client certificates must chain to
+pub const ENV_API_CLIENT_CA: &str = "GWRS_API_CLIENT_CA";
+/// Environment variable mapping client certificate common names to the highest
+/// role they may act as, e.g. `ops-laptop=admin,grafana=user,*=staff`
+pub const ENV_API_CLIENT_ROLES: &str = "GWRS_API_CLIENT_ROLES";
+
+/// Roles a client certificate can be mapped to
+const CLIENT_ROLES: [&str; 3] = ["admin", "staff", "user"];
+
+/// TLS settings of the API server.
+///
+/// Without a certificate the API is served over plain HTTP. With a client CA,
+/// only clients presenting a certificate issued by it complete the handshake,
+/// and JWT still decides who they are. Client roles cap the JWT role of
+/// requests by the common name of their certificate.
+#[derive(Debug, Clone, Default)]
+pub struct ApiTlsConfig {
+    /// Server certificate chain, TLS is off without one
+    pub cert: Option<PathBuf>,
+    /// Server private key
+    pub key: Option<PathBuf>,
+    /// CA bundle of client certificates, client certificates are required with one
+    pub client_ca: Option<PathBuf>,
+    /// Highest role per certificate common name, `*` matching any other name
+    pub client_roles: Vec<(String, String)>,
+}
+
+impl ApiTlsConfig {
+    /// Reads the TLS settings from the `GWRS_API_*` environment variables.
+    pub fn from_env() -> Result<Self, String> {
+        let path = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        };
+        let config = Self {
+            cert: path(ENV_API_TLS_CERT),
+            key: path(ENV_API_TLS_KEY),
+            client_ca: path(ENV_API_CLIENT_CA),
+            client_roles: parse_client_roles(&std::env::var(ENV_API_CLIENT_ROLES).unwrap_or_default())
+                .map_err(|e| format!("{}: {}", ENV_API_CLIENT_ROLES, e))?,
+        };
+
+        if config.cert.is_some() != config.key.is_some() {
+            return Err(format!("{} and {} must be set together", ENV_API_TLS_CERT, ENV_API_TLS_KEY));
+        }
+        if config.cert.is_none() && config.client_ca.is_some() {
+            return Err(format!("{} needs {} and {}", ENV_API_CLIENT_CA, ENV_API_TLS_CERT, ENV_API_TLS_KEY));
+        }
+        if config.client_ca.is_none() && !config.client_roles.is_empty() {
+            return Err(format!("{} needs {}", ENV_API_CLIENT_ROLES, ENV_API_CLIENT_CA));
+        }
+        Ok(config)
+    }
+
+    /// Highest role a client certificate with this common name may act as.
+    pub fn role_for(&self, common_name: Option<&str>) -> Option<&str> {
+        common_name
+            .and_then(|name| self.client_roles.iter().find(|(cn, _)| cn == name))
+            .or_else(|| self.client_roles.iter().find(|(cn, _)| cn == "*"))
+            .map(|(_, role)| role.as_str())
+    }
+}
+
+/// Parses `cn=role` pairs separated by commas.
+pub fn parse_client_roles(value: &str) -> Result<Vec<(String, String)>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (cn, role) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected cn=role, got '{}'", pair))?;
+            let (cn, role) = (cn.trim(), role.trim().to_ascii_lowercase());
+            if cn.is_empty() {
+                return Err(format!("empty common name in '{}'", pair));
+            }
+            if !CLIENT_ROLES.contains(&role.as_str()) {
+                return Err(format!("unknown role '{}', expected admin, staff or user", role));
+            }
+            Ok((cn.to_string(), role))
+        })
+        .collect()
+}
+
+/// The settings the API runs with as JSON, after the command line, the
+/// environment and the defaults were merged.
+///
+/// Secrets are never included, the core token, the bundle key and the first
+/// administrator's password only show whether they are set.
+pub fn effective(bind_address: &str, workers: usize, tls: &ApiTlsConfig) -> serde_json::Value {
+    let cors = CorsConfig::from_env();
+    let or_default = |values: Vec<String>, default: Vec<String>| if values.is_empty() { default } else { values };
+    json!({
+        "service": "router-api",
+        "version": env!("CARGO_PKG_VERSION"),
+        "bind_address": bind_address,
+        "workers": workers,
+        "tls": {
+            "enabled": tls.cert.is_some(),
+            "cert": tls.cert,
+            "key": tls.key,
+            "client_ca": tls.client_ca,
+            "client_roles": tls.client_roles.iter().map(|(cn, role)| format!("{}={}", cn, role)).collect::<Vec<_>>(),
+        },
+        "core": {
+            "address": core_address(),
+            "token_set": core_token().is_some(),
+        },
+        "admin": {
+            "user": admin_user(),
+            "password_set": admin_pass().is_some() || admin_pass_hash().is_some(),
+        },
+        "cors": {
+            "origins": or_default(cors.origins, vec!["*".to_string()]),
+            "methods": or_default(cors.methods, DEFAULT_CORS_METHODS.map(str::to_string).to_vec()),
+            "headers": or_default(cors.headers, DEFAULT_CORS_HEADERS.map(str::to_string).to_vec()),
+        },
+        "database": {
+            "pool_size": db_pool_size(),
+        },
+        "log": {
+            "min_level": memory_log::level_name(log_min_level()),
+            "archive_dir": log_archive_dir(),
+            "compaction_minutes": log_compaction_minutes(),
+        },
+        "max_gateway_rules": max_gateway_rules(),
+        "cert_warn_days": cert_warn_days(),
+        "acme": {
+            "accept_terms": acme_accept_terms(),
+            "directory": acme_directory(),
+            "email": acme_email(),
+            "renew_days": acme_renew_days(),
+        },
+        "trash_retention_days": trash_retention_days(),
+        "bundle_key_set": bundle_key().is_some(),
+    })
+}
+
 pub fn init(){
-    Api::TCPAddress.set("127.0.0.1:30099");
+    let core_address = core_address();
+    Api::TCPAddress.set(&core_address);
     
     // Initialize the global config only once
     INIT.call_once(|| {
@@ -61,7 +548,125 @@ pub fn init(){
     });
     
     // Add initial values
-    append_config("tcp_address", "127.0.0.1:30099");
+    append_config("tcp_address", &core_address);
 
     temporary_log::init();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::{Method, StatusCode}, test, web, App, HttpResponse};
+
+    /// Issues a browser-style preflight for a PUT on the user update route.
+    async fn preflight_put(cors: Cors, origin: &str) -> actix_web::dev::ServiceResponse {
+        let app = test::init_service(
+            App::new()
+                .wrap(cors)
+                .route("/api/v1/users/{user_id}", web::put().to(|| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+
+        let req = test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/api/v1/users/some-user-id")
+            .insert_header((header::ORIGIN, origin))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "PUT"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization, content-type"))
+            .to_request();
+
+        test::call_service(&app, req).await
+    }
+
+    #[actix_web::test]
+    async fn test_default_preflight_allows_put() {
+        let resp = preflight_put(CorsConfig::default().build(), "http://localhost:24041").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let allowed = resp
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_METHODS)
+            .expect("preflight response should list allowed methods")
+            .to_str()
+            .unwrap();
+        assert!(allowed.contains("PUT"));
+        assert!(allowed.contains("DELETE"));
+    }
+
+    #[actix_web::test]
+    async fn test_restricted_origin_preflight() {
+        let config = CorsConfig {
+            origins: vec!["https://gw.example.com".to_string()],
+            ..Default::default()
+        };
+
+        let resp = preflight_put(config.build(), "https://gw.example.com").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = preflight_put(config.build(), "https://evil.example.com").await;
+        assert_ne!(resp.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_parse_workers() {
+        assert_eq!(parse_workers("4"), Ok(4));
+        assert_eq!(parse_workers(" 16 "), Ok(16));
+        assert!(parse_workers("0").is_err());
+        assert!(parse_workers("-1").is_err());
+        assert!(parse_workers("many").is_err());
+    }
+
+    #[test]
+    fn test_cli_workers_take_precedence() {
+        assert_eq!(workers(Some(3)), Ok(3));
+    }
+
+    #[test]
+    fn test_effective_config() {
+        let tls = ApiTlsConfig {
+            cert: Some(PathBuf::from("/etc/gwrs/api.pem")),
+            key: Some(PathBuf::from("/etc/gwrs/api.key")),
+            ..Default::default()
+        };
+        let effective = effective("0.0.0.0:24042", 4, &tls);
+        assert_eq!(effective["bind_address"], "0.0.0.0:24042");
+        assert_eq!(effective["workers"], 4);
+        assert_eq!(effective["tls"]["enabled"], true);
+        assert_eq!(effective["tls"]["cert"], "/etc/gwrs/api.pem");
+        assert!(effective["core"]["token_set"].is_boolean());
+        assert!(effective["core"].get("token").is_none());
+        assert!(effective.get("bundle_key").is_none());
+        assert!(effective["admin"]["password_set"].is_boolean());
+        assert!(effective["admin"].get("password").is_none());
+    }
+
+    #[test]
+    fn test_parse_client_roles() {
+        assert_eq!(parse_client_roles(""), Ok(vec![]));
+        assert_eq!(
+            parse_client_roles(" ops-laptop=Admin, grafana agent=user "),
+            Ok(vec![
+                ("ops-laptop".to_string(), "admin".to_string()),
+                ("grafana agent".to_string(), "user".to_string()),
+            ])
+        );
+        assert!(parse_client_roles("ops-laptop").is_err());
+        assert!(parse_client_roles("=admin").is_err());
+        assert!(parse_client_roles("ops-laptop=root").is_err());
+    }
+
+    #[test]
+    fn test_client_role_lookup() {
+        let mut config = ApiTlsConfig {
+            client_roles: parse_client_roles("ops-laptop=admin,grafana=user").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(config.role_for(Some("ops-laptop")), Some("admin"));
+        assert_eq!(config.role_for(Some("ci")), None);
+        assert_eq!(config.role_for(None), None);
+
+        config.client_roles.push(("*".to_string(), "staff".to_string()));
+        assert_eq!(config.role_for(Some("grafana")), Some("user"));
+        assert_eq!(config.role_for(Some("ci")), Some("staff"));
+    }
+}
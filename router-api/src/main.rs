@@ -9,9 +9,13 @@
 //! The API server is built with the following components:
 //! - **Actix Web**: High-performance HTTP server framework for handling REST requests
 //! - **SQLite Database**: Persistent storage for configuration, user data, and routing rules
-//! - **Thread-safe Client**: Arc<Mutex<Client>> for managing shared state between requests
-//! - **CORS Support**: Configurable cross-origin request security
+//! - **Pooled Client**: Arc<HttpC> backed by a small pool of reusable connections to the core,
+//!   shared by every worker thread (see Concurrency below)
+//! - **CORS Support**: Cross-origin policy configurable via `GWRS_CORS_ORIGINS`, `GWRS_CORS_METHODS`
+//!   and `GWRS_CORS_HEADERS`
 //! - **JWT Authentication**: Role-based access control (admin, staff, user)
+//! - **Request IDs**: `X-Request-Id` assigned per request (or taken from the client),
+//!   forwarded to the core and logged in the `COMMENT` field of `[GWX]`/`[PXY]` lines
 //! - **Registry Synchronization**: Automatic sync of proxy and gateway nodes with central registry
 //!
 //! ## API Endpoints
@@ -38,21 +42,65 @@
 //! - Configuration files in the working directory
 //! - Default values for development environments
 //!
+//! ## Concurrency
+//!
+//! The HTTP server runs one worker thread per logical CPU by default. This can be changed
+//! with `--workers` or the `ROUTER_API_WORKERS` environment variable (`--workers` wins).
+//!
+//! All workers share a single Arc<HttpC> for talking to router-core. The client keeps a
+//! bounded pool of keep-alive connections, so handlers that reach the core (sync, config
+//! pushes, shutdown) check out their own connection instead of waiting on each other.
+//! Idle connections are health-checked before reuse and opened on demand when the pool
+//! is empty, so concurrent core calls scale with the worker count.
+//!
+//! ## Log Archive
+//!
+//! Log segments and consumer checkpoints are kept in `GWRS_LOG_ARCHIVE_DIR`, by default
+//! `gwrs/logment` under the local data directory. The server refuses to start when the
+//! directory can't be written. If the disk fills up later, logs are kept in memory only
+//! until segments can be written again.
+//!
+//! Sealed one minute segments are merged in the background into blocks of
+//! `GWRS_LOG_COMPACTION_MINUTES` (5 by default, 0 disables it), so range queries open
+//! fewer files.
+//!
+//! ## First Boot
+//!
+//! On an empty database an administrator is created with the username `GWRS_ADMIN_USER`
+//! (`admin` by default) and the password `GWRS_ADMIN_PASS`, or a pre-hashed one in
+//! `GWRS_ADMIN_PASS_HASH`. With neither set a random password is generated and printed
+//! once to stderr. The variables are only read while no user exists.
+//!
 //! ## Network
 //!
 //! By default, the service listens on port 24042 on all network interfaces (0.0.0.0).
 //! This can be configured through environment variables or config files.
+//!
+//! router-core is reached at `GWRS_CORE_ADDRESS`, `127.0.0.1:30099` by default. When
+//! the core runs in another container, point it at the core's `GWRS_PROTTP_ADDRESS`
+//! and set `GWRS_CORE_TOKEN` to the core's `GWRS_PROTTP_TOKEN`.
+//!
+//! ## TLS and Client Certificates
+//!
+//! The API is served over plain HTTP unless `GWRS_API_TLS_CERT` and `GWRS_API_TLS_KEY`
+//! point at a PEM certificate chain and its private key. With `GWRS_API_CLIENT_CA` set
+//! to a PEM CA bundle as well, the API requires mutual TLS: clients without a certificate
+//! issued by that CA fail the handshake and never reach a handler.
+//!
+//! The certificate only gates the transport, requests still log in and send their JWT.
+//! `GWRS_API_CLIENT_ROLES` optionally maps certificate common names to the highest role
+//! their requests may act as, e.g. `ops-laptop=admin,grafana=user,*=staff` with `*`
+//! matching every other name. Requests over a mapped certificate act with the lower of
+//! their JWT role and the certificate's. Handlers find the certificate's subject with
+//! `req.conn_data::<module::api_tls::ClientCert>()`.
 
 mod api;
 mod config;
 mod module;
 
-use actix_cors::Cors;
-use actix_web::http::header;
 use actix_web::{middleware, web, App, HttpServer};
-use api::sync;
 use module::memory_log;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use crate::config::Api;
 
@@ -67,6 +115,9 @@ use crate::config::Api;
 /// 6. Configuring API routes for all endpoint categories
 /// 7. Starting the HTTP server with worker threads for concurrent request handling
 ///
+/// `--print-config` prints the effective configuration as JSON and exits before
+/// anything is started, see `config::effective`. A normal start logs it once.
+///
 /// # Network Configuration
 ///
 /// The server binds to 0.0.0.0:24042 by default, making it accessible from any network interface.
@@ -74,13 +125,15 @@ use crate::config::Api;
 ///
 /// # Performance
 ///
-/// The server uses 2 worker threads by default to handle concurrent requests efficiently.
-/// This value can be adjusted based on available system resources and expected load.
+/// The server uses one worker thread per logical CPU by default. This can be adjusted with
+/// `--workers` or `ROUTER_API_WORKERS`, and must be at least 1. Workers share the core
+/// client, which hands each request its own pooled connection.
 ///
 /// # Synchronization
 ///
-/// During startup, the server synchronizes proxy and gateway node configurations with
-/// the central registry to ensure consistency across the routing system.
+/// A background thread pushes proxy and gateway node configurations to the core,
+/// retrying with backoff until the core is reachable and again whenever the
+/// database changes (see `module::core_sync`).
 ///
 /// # Returns
 ///
@@ -91,22 +144,12 @@ use crate::config::Api;
 ///
 /// This function may return errors in the following situations:
 /// - Configuration loading failures
-/// - Registry synchronization issues
 /// - Network binding failures (e.g., port already in use)
 /// - Critical runtime errors during server execution
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    {
-        std::env::set_var("RUST_LOG", "info");
-        env_logger::init();
-        config::init();
-    }
-
-
-    {
-        log::info!("Starting memory log spawner...");
-        memory_log::spawner::spawn_all();
-    }
+    std::env::set_var("RUST_LOG", "info");
+    env_logger::init();
 
     // Parse command line arguments using clap
     let matches = clap::Command::new("Router API")
@@ -128,83 +171,184 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .default_value("24042")
                 .value_parser(clap::value_parser!(u16)),
         )
+        .arg(
+            clap::Arg::new("workers")
+                .long("workers")
+                .help("Number of HTTP worker threads [env: ROUTER_API_WORKERS] [default: logical CPUs]")
+                .value_name("WORKERS")
+                .value_parser(config::parse_workers),
+        )
+        .arg(
+            clap::Arg::new("print-config")
+                .long("print-config")
+                .help("Print the effective configuration as JSON and exit")
+                .action(clap::ArgAction::SetTrue),
+        )
         .get_matches();
 
     // Extract values with fallbacks
     let ip = matches.get_one::<String>("ip").unwrap();
     let port = matches.get_one::<u16>("port").unwrap();
-    let bind_address = format!("{}:{}", ip, port);
+    let bind_address = module::netaddr::join_host_port(ip, *port);
+    let workers = match config::workers(matches.get_one::<usize>("workers").copied()) {
+        Ok(workers) => workers,
+        Err(e) => {
+            log::error!("Invalid worker configuration: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    let tls = match config::ApiTlsConfig::from_env() {
+        Ok(tls) => tls,
+        Err(e) => {
+            log::error!("Invalid TLS configuration: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    let effective_config = config::effective(&bind_address, workers, &tls);
+    if matches.get_flag("print-config") {
+        println!("{}", serde_json::to_string_pretty(&effective_config)?);
+        return Ok(());
+    }
+
+    {
+        log::info!("Starting router-api {}...", env!("CARGO_PKG_VERSION"));
+        log::info!("Effective configuration: {}", effective_config);
+        let archive_dir = config::log_archive_dir();
+        module::temporary_log::prepare_archive_dir(&archive_dir).map_err(|e| {
+            format!("Log archive directory {} is not writable: {}", archive_dir.display(), e)
+        })?;
+        config::init();
+    }
+
+    {
+        log::info!("Applying database migrations...");
+        module::migrations::run()?;
+    }
+
+    {
+        // Once, before the workers start, so a generated admin password is only made and shown once
+        log::info!("Initializing users...");
+        api::init_database()?;
+    }
+
+
+    {
+        log::info!("Starting memory log spawner...");
+        memory_log::spawner::spawn_all();
+    }
+
+    {
+        log::info!("Starting certificate expiry monitor...");
+        module::cert_expiry::spawn_monitor();
+    }
+
+    {
+        log::info!("Starting trash purger...");
+        module::trash_purge::spawn_purger();
+    }
+
+    let tls_server = match module::api_tls::server_config(&tls) {
+        Ok(tls_server) => tls_server,
+        Err(e) => {
+            log::error!("Failed to load the API certificates: {}", e);
+            return Err(e.into());
+        }
+    };
+    let tls = Arc::new(tls);
 
     log::info!("Starting API server on {}...", bind_address);
 
-    // Create a thread-safe client wrapped in Arc<Mutex<>> to safely share
+    // Create a pooled client wrapped in Arc<> to safely share
     // across multiple threads and request handlers
     let (u_address, u_port) = {
         let address = Api::TCPAddress.get_str();
-        let parts: Vec<&str> = address.split(':').collect();
-        if parts.len() != 2 {
-            log::error!("Invalid TCP address format: {}", address);
-            return Err("Invalid TCP address format".into());
+        match module::netaddr::split_host_port(&address) {
+            Some((host, port)) => (host.to_string(), port),
+            None => {
+                log::error!(
+                    "Invalid {}='{}', expected host:port",
+                    config::ENV_CORE_ADDRESS,
+                    address
+                );
+                return Err("Invalid core address".into());
+            }
         }
-        (parts[0].to_string(), parts[1].parse::<u16>().unwrap_or(24042))
     };
 
-    let client = module::httpc::HttpC::new(&u_address, u_port);
-    let client = Arc::new(Mutex::new(client));
+    let client = module::httpc::HttpC::new(&u_address, u_port).with_token(config::core_token());
+    let client = Arc::new(client);
 
     log::info!("Initializing sync...");
     {
-        // Try to sync with registry but don't fail startup if it doesn't work
-        match sync::proxy_node_tcp::sync_proxy_nodes_to_registry(&client).await {
-            Ok(_) => log::info!("Successfully synced proxy nodes to registry"),
-            Err(e) => log::warn!("Failed to sync proxy nodes to registry: {:?}. Continuing startup anyway.", e),
-        }
-        
-        match sync::gateway_node_tcp::sync_gateway_nodes_to_registry(&client).await {
-            Ok(_) => log::info!("Successfully synced gateway nodes to registry"),
-            Err(e) => log::warn!("Failed to sync gateway nodes to registry: {:?}. Continuing startup anyway.", e),
-        }
+        // Retries in the background until the core is reachable, startup doesn't wait for it
+        module::core_sync::spawn_syncer(client.clone());
+    }
 
-        match sync::gateway_node_tcp::sync_gateway_paths_to_registry(&client).await {
-            Ok(_) => log::info!("Successfully synced gateway paths to registry"),
-            Err(e) => log::warn!("Failed to sync gateway paths to registry: {:?}. Continuing startup anyway.", e),
-        }
+    if config::acme_accept_terms() {
+        log::info!("Starting ACME renewer...");
+        module::acme::spawn_renewer(client.clone());
+    } else {
+        log::info!(
+            "ACME renewer not started, set {} to agree to the terms of service of {}",
+            config::ENV_ACME_ACCEPT_TERMS,
+            config::acme_directory()
+        );
+    }
+
+    let cors_config = config::CorsConfig::from_env();
+    if cors_config.origins.is_empty() {
+        log::warn!(
+            "{} is not set, allowing any origin. Restrict it in production.",
+            config::ENV_CORS_ORIGINS
+        );
     }
 
     // Configure and start actix-web server
-    log::info!("Starting HTTP server on {}...", bind_address);
-    HttpServer::new(move || {
-        // Configure CORS with permissive settings for development
-        // In production, this should be restricted to specific origins
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allowed_methods(vec!["GET", "POST", "DELETE"])
-            .allowed_headers(vec![
-                header::AUTHORIZATION,
-                header::ACCEPT,
-                header::CONTENT_TYPE,
-            ])
-            .supports_credentials()
-            .max_age(3600);
+    log::info!("Starting HTTP server on {} with {} workers...", bind_address, workers);
+    let server = HttpServer::new(move || {
+        // Configure CORS from GWRS_CORS_* environment variables,
+        // any origin is allowed when GWRS_CORS_ORIGINS is unset
+        let cors = cors_config.build();
 
         App::new()
             // Add client as app data to make it accessible in route handlers
             // via dependency injection
             .app_data(web::Data::new(client.clone()))
-            // Enable logger middleware for request/response logging
-            .wrap(middleware::Logger::default())
+            // Assign an X-Request-Id to every request and forward it to the core
+            .wrap(module::request_id::RequestId)
+            // Enable logger middleware for request/response logging,
+            // including the request ID for correlation with core logs
+            .wrap(middleware::Logger::new(
+                r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T request_id=%{x-request-id}o"#,
+            ))
             // Enable CORS middleware with the configured settings
             .wrap(cors)
             // Configure routes using the function defined in the api module
             .configure(api::configure)
     })
-    // Bind server to the specified address and port
-    .bind(&bind_address)?
-    // Set number of worker threads to 2 for handling concurrent requests
-    .workers(1)
-    // Start the HTTP server and keep it running until terminated
-    .run()
-    .await?;
+    // Keep the client certificate of TLS connections for the auth middleware
+    .on_connect(module::api_tls::on_connect(tls.clone()));
+
+    // Bind server to the specified address and port, over TLS when configured
+    let server = match tls_server {
+        Some(tls_server) => {
+            log::info!(
+                "Serving the API over TLS{}",
+                if tls.client_ca.is_some() { ", client certificates required" } else { "" }
+            );
+            server.bind_rustls_0_23(&bind_address, tls_server)?
+        }
+        None => server.bind(&bind_address)?,
+    };
+
+    server
+        // Set number of worker threads from --workers, ROUTER_API_WORKERS or the CPU count
+        .workers(workers)
+        // Start the HTTP server and keep it running until terminated
+        .run()
+        .await?;
 
     Ok(())
 }
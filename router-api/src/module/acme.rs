@@ -0,0 +1,452 @@
+//! # ACME Certificates
+//!
+//! Obtains and renews the certificates of proxy domains with `acme` set from an
+//! ACME CA, Let's Encrypt unless `GWRS_ACME_DIRECTORY` names another, proving
+//! control of each name with an HTTP-01 challenge:
+//!
+//! 1. An order is placed for the SNI names of the domain.
+//! 2. The key authorization of every challenge is published to the core with
+//!    the `acme-challenge` command. The core answers
+//!    `/.well-known/acme-challenge/<token>` on its gateway and HTTPS redirect
+//!    listeners, so port 80 of every name has to reach one of them.
+//! 3. Once the CA validated all names, the order is finalized with a fresh key
+//!    and the issued chain and key are stored in the domain record. Core sync
+//!    pushes the changed domain and the core swaps the certificate into its
+//!    running gateway listeners, open connections are kept. A push changing
+//!    nothing but certificates doesn't restart the listeners, except those of
+//!    speed mode TLS proxies, which load their certificate on start only.
+//!
+//! Registering the account agrees to the CA's terms of service, so nothing is
+//! ordered unless `GWRS_ACME_ACCEPT_TERMS` is set; `main` only starts the
+//! renewal thread then.
+//!
+//! A background thread looks for domains without a certificate, with one for
+//! other names or within `GWRS_ACME_RENEW_DAYS` (default 30) of expiry. A
+//! failed attempt is logged as an error and retried after 15 minutes, doubling
+//! up to a day. The outcome per domain is listed by the certificate status
+//! endpoint, next to the expiry warnings of `cert_expiry`.
+//!
+//! The account is registered on first use, with `GWRS_ACME_EMAIL` as contact
+//! when set, and its key is kept in `acme_accounts`, one per directory.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount, NewOrder,
+    Order, OrderStatus,
+};
+use rcgen::{CertificateParams, DistinguishedName, KeyPair};
+use serde::Serialize;
+use x509_parser::extensions::GeneralName;
+use x509_parser::pem::Pem;
+
+use crate::api::settings::{proxydomain_queries, rule_validation, ProxyDomain};
+use crate::config;
+use crate::module::cert_expiry;
+use crate::module::database::{get_connection, DatabaseError};
+use crate::module::httpc::HttpC;
+
+/// Interval between two checks of the renewal thread
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Delay before the first retry of a failed order
+const INITIAL_RETRY: Duration = Duration::from_secs(15 * 60);
+/// Longest delay between two attempts of a failing domain
+const MAX_RETRY: Duration = Duration::from_secs(24 * 60 * 60);
+/// Interval between two polls of an order's state
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// Polls before an order is given up, two minutes
+const MAX_POLLS: u32 = 40;
+
+/// Outcome of the certificate orders of one domain
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AcmeStatus {
+    /// ID of the proxy domain
+    pub id: String,
+    /// Names the certificate is ordered for
+    pub domain: String,
+    /// Unix timestamp of the last order
+    pub last_attempt: Option<i64>,
+    /// Unix timestamp of the last issued certificate
+    pub last_success: Option<i64>,
+    /// Why the last order failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Failed orders since the last issued certificate
+    pub consecutive_failures: u32,
+}
+
+static STATUS: Mutex<Vec<AcmeStatus>> = Mutex::new(Vec::new());
+
+/// Returns the order outcome of every ACME domain attempted since startup.
+pub fn status() -> Vec<AcmeStatus> {
+    STATUS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Records the outcome of an order, returning the consecutive failures of the domain.
+fn record(domain: &ProxyDomain, names: &str, outcome: &Result<(), String>, now: i64) -> u32 {
+    let mut statuses = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    let index = match statuses.iter().position(|status| status.id == domain.id) {
+        Some(index) => index,
+        None => {
+            statuses.push(AcmeStatus {
+                id: domain.id.clone(),
+                ..Default::default()
+            });
+            statuses.len() - 1
+        }
+    };
+    let status = &mut statuses[index];
+    status.domain = names.to_string();
+    status.last_attempt = Some(now);
+    match outcome {
+        Ok(()) => {
+            status.last_success = Some(now);
+            status.last_error = None;
+            status.consecutive_failures = 0;
+        }
+        Err(e) => {
+            status.last_error = Some(e.clone());
+            status.consecutive_failures += 1;
+        }
+    }
+    status.consecutive_failures
+}
+
+/// Delay after `failures` consecutive failed orders
+fn retry_delay(failures: u32) -> Duration {
+    let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+    INITIAL_RETRY.saturating_mul(factor).min(MAX_RETRY)
+}
+
+/// Whether a failing domain is still waiting for its next attempt at `now`.
+fn backing_off(id: &str, now: i64) -> bool {
+    let statuses = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    statuses
+        .iter()
+        .find(|status| status.id == id && status.consecutive_failures > 0)
+        .and_then(|status| status.last_attempt.map(|attempt| (attempt, status.consecutive_failures)))
+        .is_some_and(|(attempt, failures)| now < attempt + retry_delay(failures).as_secs() as i64)
+}
+
+/// DNS names of the first certificate in a PEM chain.
+fn leaf_names(pem: &str) -> Result<Vec<String>, String> {
+    for block in Pem::iter_from_buffer(pem.as_bytes()) {
+        let block = block.map_err(|e| format!("invalid PEM: {}", e))?;
+        if block.label != "CERTIFICATE" {
+            continue;
+        }
+        let cert = block
+            .parse_x509()
+            .map_err(|e| format!("invalid certificate: {}", e))?;
+        let names = cert
+            .subject_alternative_name()
+            .map_err(|e| format!("invalid subject alternative names: {}", e))?
+            .map(|san| {
+                san.value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match name {
+                        GeneralName::DNSName(name) => Some(name.to_ascii_lowercase()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        return Ok(names);
+    }
+    Err("no certificate found in PEM".to_string())
+}
+
+/// Whether the domain needs a new certificate for `names` at `now`.
+///
+/// That is without a certificate, with one that can't be read or lacks one of
+/// the names, and within `renew_days` of expiry.
+fn due(domain: &ProxyDomain, names: &[String], renew_days: i64, now: i64) -> bool {
+    let pem = match domain.tls_pem.as_deref().filter(|pem| !pem.trim().is_empty()) {
+        Some(pem) => pem,
+        None => return true,
+    };
+    let covered = leaf_names(pem).is_ok_and(|issued| names.iter().all(|name| issued.contains(name)));
+    let expiring = cert_expiry::leaf_not_after(pem)
+        .map_or(true, |not_after| cert_expiry::days_remaining(not_after, now) < renew_days);
+    !covered || expiring
+}
+
+/// Stored credentials of the account at `directory`.
+fn load_credentials(directory: &str) -> Result<Option<String>, DatabaseError> {
+    get_connection()?.query_one(
+        "SELECT credentials FROM acme_accounts WHERE directory = ?1",
+        [directory],
+        |row| row.get(0),
+    )
+}
+
+fn save_credentials(directory: &str, credentials: &str) -> Result<(), DatabaseError> {
+    get_connection()?.execute(
+        "INSERT OR REPLACE INTO acme_accounts (directory, credentials) VALUES (?1, ?2)",
+        [directory, credentials],
+    )?;
+    Ok(())
+}
+
+/// The account at `directory`, registered on first use.
+async fn account(directory: &str) -> Result<Account, String> {
+    if let Some(credentials) = load_credentials(directory).map_err(|e| e.to_string())? {
+        let credentials = serde_json::from_str::<AccountCredentials>(&credentials)
+            .map_err(|e| format!("stored ACME account is invalid: {}", e))?;
+        return Account::from_credentials(credentials)
+            .await
+            .map_err(|e| format!("ACME account could not be loaded: {}", e));
+    }
+
+    let contact = config::acme_email().map(|email| format!("mailto:{}", email));
+    let contact: Vec<&str> = contact.iter().map(String::as_str).collect();
+    let (account, credentials) = Account::create(
+        &NewAccount {
+            contact: &contact,
+            // Only reached with GWRS_ACME_ACCEPT_TERMS set
+            terms_of_service_agreed: true,
+            only_return_existing: false,
+        },
+        directory,
+        None,
+    )
+    .await
+    .map_err(|e| format!("ACME account could not be registered at {}: {}", directory, e))?;
+
+    let credentials = serde_json::to_string(&credentials).map_err(|e| e.to_string())?;
+    save_credentials(directory, &credentials).map_err(|e| e.to_string())?;
+    log::info!("Registered an ACME account at {}", directory);
+    Ok(account)
+}
+
+/// Publishes a challenge to the core, or removes it without a key authorization.
+fn publish_challenge(client: &HttpC, token: &str, key_authorization: Option<&str>) -> Result<(), String> {
+    let body = serde_json::json!({ "token": token, "key_authorization": key_authorization });
+    client
+        .post_json("/command/acme-challenge", &body.to_string())
+        .map_err(|e| format!("core could not publish the challenge: {}", e))
+}
+
+/// Publishes the HTTP-01 challenges of an order and waits until the CA validated them.
+///
+/// The tokens published are added to `published`, also when validation fails.
+async fn validate(order: &mut Order, client: &HttpC, published: &mut Vec<String>) -> Result<(), String> {
+    let authorizations = order
+        .authorizations()
+        .await
+        .map_err(|e| format!("authorizations could not be read: {}", e))?;
+    for authorization in &authorizations {
+        match authorization.status {
+            AuthorizationStatus::Pending => {}
+            AuthorizationStatus::Valid => continue,
+            status => {
+                return Err(format!(
+                    "authorization of {:?} is {:?}",
+                    authorization.identifier, status
+                ))
+            }
+        }
+        let challenge = authorization
+            .challenges
+            .iter()
+            .find(|challenge| challenge.r#type == ChallengeType::Http01)
+            .ok_or_else(|| format!("no HTTP-01 challenge offered for {:?}", authorization.identifier))?;
+
+        let key_authorization = order.key_authorization(challenge);
+        publish_challenge(client, &challenge.token, Some(key_authorization.as_str()))?;
+        published.push(challenge.token.clone());
+        order
+            .set_challenge_ready(&challenge.url)
+            .await
+            .map_err(|e| format!("challenge could not be started: {}", e))?;
+    }
+
+    for _ in 0..MAX_POLLS {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let state = order
+            .refresh()
+            .await
+            .map_err(|e| format!("order state could not be read: {}", e))?;
+        match state.status {
+            OrderStatus::Ready => return Ok(()),
+            OrderStatus::Invalid => {
+                let reason = state
+                    .error
+                    .as_ref()
+                    .and_then(|problem| problem.detail.clone())
+                    .unwrap_or_else(|| "a challenge failed, is port 80 of every name served by the gateway?".to_string());
+                return Err(format!("CA refused the order: {}", reason));
+            }
+            _ => {}
+        }
+    }
+    Err("CA did not validate the challenges in time".to_string())
+}
+
+/// Orders a certificate for `names`, returning its PEM chain and private key.
+async fn obtain(directory: &str, client: &HttpC, names: &[String]) -> Result<(String, String), String> {
+    let account = account(directory).await?;
+    let identifiers: Vec<Identifier> = names.iter().map(|name| Identifier::Dns(name.clone())).collect();
+    let mut order = account
+        .new_order(&NewOrder {
+            identifiers: &identifiers,
+        })
+        .await
+        .map_err(|e| format!("order could not be placed: {}", e))?;
+
+    // Challenges are answered until the order is decided, not longer
+    let mut published = Vec::new();
+    let validated = validate(&mut order, client, &mut published).await;
+    for token in &published {
+        if let Err(e) = publish_challenge(client, token, None) {
+            log::warn!("ACME challenge {} stays published: {}", token, e);
+        }
+    }
+    validated?;
+
+    let key = KeyPair::generate().map_err(|e| format!("key could not be generated: {}", e))?;
+    let mut params =
+        CertificateParams::new(names.to_vec()).map_err(|e| format!("invalid certificate names: {}", e))?;
+    params.distinguished_name = DistinguishedName::new();
+    let csr = params
+        .serialize_request(&key)
+        .map_err(|e| format!("CSR could not be created: {}", e))?;
+    order
+        .finalize(csr.der())
+        .await
+        .map_err(|e| format!("order could not be finalized: {}", e))?;
+
+    for _ in 0..MAX_POLLS {
+        let chain = order
+            .certificate()
+            .await
+            .map_err(|e| format!("certificate could not be downloaded: {}", e))?;
+        if let Some(chain) = chain {
+            return Ok((chain, key.serialize_pem()));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    Err("CA did not issue the certificate in time".to_string())
+}
+
+/// Obtains a certificate for every ACME domain that is due and not backing off.
+fn renew_due(runtime: &tokio::runtime::Runtime, client: &HttpC) {
+    let domains = match proxydomain_queries::get_all_proxy_domains() {
+        Ok(domains) => domains,
+        Err(e) => {
+            log::error!("Failed to read proxy domains for ACME renewal: {}", e);
+            return;
+        }
+    };
+    let directory = config::acme_directory();
+    let renew_days = config::acme_renew_days();
+
+    for domain in domains.iter().filter(|domain| domain.acme) {
+        let now = Utc::now().timestamp();
+        let sni = domain.sni.clone().unwrap_or_default();
+        let outcome = match rule_validation::acme_names(&sni) {
+            Ok(names) if !due(domain, &names, renew_days, now) => continue,
+            _ if backing_off(&domain.id, now) => continue,
+            Ok(names) => {
+                log::info!("Ordering an ACME certificate for {}", names.join(", "));
+                runtime
+                    .block_on(obtain(&directory, client, &names))
+                    .and_then(|(chain, key)| {
+                        proxydomain_queries::save_acme_certificate(&domain.id, &chain, &key)
+                            .map(|_| ())
+                            .map_err(|e| format!("certificate could not be stored: {}", e))
+                    })
+            }
+            Err(e) => Err(e),
+        };
+
+        let failures = record(domain, &sni, &outcome, now);
+        match &outcome {
+            Ok(()) => log::info!("Stored a new ACME certificate for '{}'", sni),
+            Err(e) => log::error!(
+                "ACME certificate of domain '{}' could not be obtained ({} failures in a row): {}. Retrying in {} minutes",
+                sni,
+                failures,
+                e,
+                retry_delay(failures).as_secs() / 60
+            ),
+        }
+    }
+
+    // Forget domains that were deleted or switched to a manual certificate
+    STATUS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|status| domains.iter().any(|domain| domain.acme && domain.id == status.id));
+}
+
+/// Starts the background thread obtaining and renewing ACME certificates.
+pub fn spawn_renewer(client: Arc<HttpC>) {
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                log::error!("ACME renewal could not start: {}", e);
+                return;
+            }
+        };
+        log::info!(
+            "ACME renewal started with {}, renewing {} days ahead",
+            config::acme_directory(),
+            config::acme_renew_days()
+        );
+        loop {
+            renew_due(&runtime, &client);
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain(id: &str, tls_pem: Option<&str>) -> ProxyDomain {
+        ProxyDomain {
+            id: id.to_string(),
+            proxy_id: None,
+            tls: true,
+            tls_pem: tls_pem.map(str::to_string),
+            tls_key: None,
+            sni: Some("example.com".to_string()),
+            acme: true,
+        }
+    }
+
+    #[test]
+    fn test_domains_without_a_readable_certificate_are_due() {
+        let names = vec!["example.com".to_string()];
+        assert!(due(&domain("a", None), &names, 30, 0));
+        assert!(due(&domain("a", Some(" ")), &names, 30, 0));
+        assert!(due(&domain("a", Some("not a certificate")), &names, 30, 0));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), INITIAL_RETRY);
+        assert_eq!(retry_delay(2), INITIAL_RETRY * 2);
+        assert_eq!(retry_delay(8), MAX_RETRY);
+        assert_eq!(retry_delay(100), MAX_RETRY);
+    }
+
+    #[test]
+    fn test_failed_domains_back_off() {
+        let failing = domain("acme-test-failing", None);
+        let now = 1_700_000_000;
+        record(&failing, "example.com", &Err("timeout".to_string()), now);
+        assert!(backing_off(&failing.id, now + 60));
+        assert!(!backing_off(&failing.id, now + INITIAL_RETRY.as_secs() as i64));
+
+        record(&failing, "example.com", &Ok(()), now);
+        assert!(!backing_off(&failing.id, now + 60));
+        assert!(!backing_off("acme-test-unknown", now));
+    }
+}
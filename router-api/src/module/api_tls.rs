@@ -0,0 +1,132 @@
+//! # API TLS and Client Certificates
+//!
+//! Builds the rustls configuration of the API server from [`ApiTlsConfig`].
+//! With a client CA the handshake requires a certificate chaining to it, so
+//! clients without one never reach a handler. The leaf certificate of every
+//! such connection is parsed once and kept as a [`ClientCert`] in the
+//! connection data, where the auth middleware and handlers find it with
+//! `req.conn_data::<ClientCert>()`.
+//!
+//! The certificate gates the transport, the JWT still identifies the user. A
+//! role mapped to the certificate's common name caps the JWT role, so a token
+//! of an admin used from a certificate mapped to `user` only acts as `user`.
+
+use std::any::Any;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+
+use crate::config::ApiTlsConfig;
+
+/// Client certificate of a TLS connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCert {
+    /// Distinguished name of the subject, e.g. `CN=ops-laptop, O=Example`
+    pub subject: String,
+    /// Common name of the subject
+    pub common_name: Option<String>,
+    /// Highest role requests over this connection may act as, uncapped when `None`
+    pub role: Option<String>,
+}
+
+impl ClientCert {
+    /// Reads subject and common name of a DER certificate and looks up its role.
+    pub fn parse(der: &[u8], tls: &ApiTlsConfig) -> Result<Self, String> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| format!("invalid client certificate: {}", e))?;
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string);
+        Ok(Self {
+            subject: cert.subject().to_string(),
+            role: tls.role_for(common_name.as_deref()).map(str::to_string),
+            common_name,
+        })
+    }
+}
+
+/// Builds the server configuration, `Ok(None)` when TLS is off.
+pub fn server_config(tls: &ApiTlsConfig) -> Result<Option<ServerConfig>, String> {
+    let (Some(cert), Some(key)) = (&tls.cert, &tls.key) else {
+        return Ok(None);
+    };
+    let chain = read_certs(cert)?;
+    let key = read_key(key)?;
+
+    let builder = ServerConfig::builder();
+    let builder = match &tls.client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for ca in read_certs(client_ca)? {
+                roots
+                    .add(ca)
+                    .map_err(|e| format!("invalid CA certificate in {}: {}", client_ca.display(), e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .map_err(|e| format!("invalid client CA {}: {}", client_ca.display(), e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    builder
+        .with_single_cert(chain, key)
+        .map(Some)
+        .map_err(|e| format!("certificate {} doesn't match its key: {}", cert.display(), e))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid PEM in {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("invalid PEM in {}: {}", path.display(), e))?
+        .ok_or_else(|| format!("no private key found in {}", path.display()))
+}
+
+/// Connection callback of the server, stores the client certificate of TLS connections.
+pub fn on_connect(tls: Arc<ApiTlsConfig>) -> impl Fn(&dyn Any, &mut Extensions) + Send + Sync + 'static {
+    move |connection: &dyn Any, data: &mut Extensions| {
+        let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+            return;
+        };
+        let Some(leaf) = stream.get_ref().1.peer_certificates().and_then(|chain| chain.first()) else {
+            return;
+        };
+        match ClientCert::parse(leaf, &tls) {
+            Ok(cert) => {
+                log::debug!("Client certificate {} connected", cert.subject);
+                data.insert(cert);
+            }
+            Err(e) => {
+                // Verified but unreadable, it gets the role of an unmapped name
+                log::warn!("Failed to read a verified client certificate: {}", e);
+                data.insert(ClientCert {
+                    subject: String::new(),
+                    common_name: None,
+                    role: tls.role_for(None).map(str::to_string),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! # Certificate Expiry Monitoring
+//!
+//! Reads the `notAfter` date of every manually configured `ProxyDomain`
+//! certificate, so operators see which certificates are about to expire before
+//! clients start failing the handshake.
+//!
+//! Only the leaf (first) certificate of a PEM chain is considered. A background
+//! thread re-checks all domains every few hours and logs a warning for each
+//! certificate within `GWRS_CERT_WARN_DAYS` of expiry (default 14).
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use x509_parser::pem::Pem;
+
+use crate::api::settings::{proxydomain_queries, ProxyDomain};
+use crate::config;
+
+/// Interval between two checks of the monitor thread
+const CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Expiry information of one domain certificate
+#[derive(Debug, Clone, Serialize)]
+pub struct CertStatus {
+    /// ID of the proxy domain
+    pub id: String,
+    /// SNI names of the domain
+    pub domain: String,
+    /// `notAfter` of the leaf certificate (RFC 3339)
+    pub expires_at: Option<String>,
+    /// Whole days until expiry, negative once expired
+    pub days_remaining: Option<i64>,
+    /// Why the certificate could not be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Returns the `notAfter` timestamp (seconds) of the first certificate in a PEM chain.
+pub fn leaf_not_after(pem: &str) -> Result<i64, String> {
+    for block in Pem::iter_from_buffer(pem.as_bytes()) {
+        let block = block.map_err(|e| format!("invalid PEM: {}", e))?;
+        if block.label != "CERTIFICATE" {
+            continue;
+        }
+        let cert = block
+            .parse_x509()
+            .map_err(|e| format!("invalid certificate: {}", e))?;
+        return Ok(cert.validity().not_after.timestamp());
+    }
+    Err("no certificate found in PEM".to_string())
+}
+
+/// Whole days from `now` until `not_after`, rounded down.
+pub fn days_remaining(not_after: i64, now: i64) -> i64 {
+    (not_after - now).div_euclid(SECONDS_PER_DAY)
+}
+
+fn status_of(domain: &ProxyDomain, now: i64) -> CertStatus {
+    let mut status = CertStatus {
+        id: domain.id.clone(),
+        domain: domain.sni.clone().unwrap_or_default(),
+        expires_at: None,
+        days_remaining: None,
+        error: None,
+    };
+
+    match leaf_not_after(domain.tls_pem.as_deref().unwrap_or_default()) {
+        Ok(not_after) => {
+            status.expires_at = DateTime::<Utc>::from_timestamp(not_after, 0)
+                .map(|expires_at| expires_at.to_rfc3339());
+            status.days_remaining = Some(days_remaining(not_after, now));
+        }
+        Err(e) => status.error = Some(e),
+    }
+    status
+}
+
+/// Reads the expiry of every TLS domain with a configured certificate.
+pub fn collect() -> Result<Vec<CertStatus>, String> {
+    let domains = proxydomain_queries::get_all_proxy_domains().map_err(|e| e.to_string())?;
+    let now = Utc::now().timestamp();
+
+    Ok(domains
+        .iter()
+        .filter(|domain| {
+            domain.tls && domain.tls_pem.as_deref().is_some_and(|pem| !pem.trim().is_empty())
+        })
+        .map(|domain| status_of(domain, now))
+        .collect())
+}
+
+/// Logs every certificate that is unreadable, expired or within `warn_days` of expiry.
+fn check(warn_days: i64) {
+    let statuses = match collect() {
+        Ok(statuses) => statuses,
+        Err(e) => {
+            log::error!("Failed to check certificate expiry: {}", e);
+            return;
+        }
+    };
+
+    for status in statuses {
+        match (status.days_remaining, &status.error) {
+            (_, Some(e)) => {
+                log::warn!("Certificate of domain '{}' could not be read: {}", status.domain, e);
+            }
+            (Some(days), None) if days < 0 => {
+                log::error!(
+                    "Certificate of domain '{}' expired on {}",
+                    status.domain,
+                    status.expires_at.unwrap_or_default()
+                );
+            }
+            (Some(days), None) if days <= warn_days => {
+                log::warn!(
+                    "Certificate of domain '{}' expires in {} days ({})",
+                    status.domain,
+                    days,
+                    status.expires_at.unwrap_or_default()
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Starts the background thread checking certificate expiry.
+pub fn spawn_monitor() {
+    let warn_days = config::cert_warn_days();
+    std::thread::spawn(move || {
+        log::info!(
+            "Certificate expiry monitor started, warning {} days ahead",
+            warn_days
+        );
+        loop {
+            check(warn_days);
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Self-signed, notAfter 2030-01-01T00:00:00Z
+    const CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBgTCCASegAwIBAgIUbXSK3hs//x7qRASOROqLO/r0ZC0wCgYIKoZIzj0EAwIw
+FjEUMBIGA1UEAwwLZXhhbXBsZS5jb20wHhcNMjUwMTAxMDAwMDAwWhcNMzAwMTAx
+MDAwMDAwWjAWMRQwEgYDVQQDDAtleGFtcGxlLmNvbTBZMBMGByqGSM49AgEGCCqG
+SM49AwEHA0IABGBHfQ4IMOdZlRO+6bYpBxX/90kep+kAwvoKxm2WbS/IMwRt4x49
+3ae29cE0/oDp8ukwYa9XNwQfTekgXkN5igOjUzBRMB0GA1UdDgQWBBR2g0SL2CNR
+i6rc6VC5b/CXAPplIzAfBgNVHSMEGDAWgBR2g0SL2CNRi6rc6VC5b/CXAPplIzAP
+BgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0gAMEUCIQDT16wDGN986zrJV5v4
+fRJSswJqPZg6ab3sQR7ERXLxfAIgH+kudFh9lYWpEyfXaDfQlzY0K3nOyy+PR7fn
+8QJJJ2Y=
+-----END CERTIFICATE-----
+";
+
+    const NOT_AFTER: i64 = 1_893_456_000;
+
+    #[test]
+    fn test_leaf_not_after() {
+        assert_eq!(leaf_not_after(CERT), Ok(NOT_AFTER));
+        assert!(leaf_not_after("").is_err());
+        assert!(leaf_not_after("-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n").is_err());
+    }
+
+    #[test]
+    fn test_days_remaining_rounds_down() {
+        assert_eq!(days_remaining(NOT_AFTER, NOT_AFTER - 10 * SECONDS_PER_DAY), 10);
+        assert_eq!(days_remaining(NOT_AFTER, NOT_AFTER - 10 * SECONDS_PER_DAY + 1), 9);
+        assert_eq!(days_remaining(NOT_AFTER, NOT_AFTER + 1), -1);
+    }
+
+    #[test]
+    fn test_status_of_domain() {
+        let domain = ProxyDomain {
+            id: "d1".to_string(),
+            proxy_id: None,
+            tls: true,
+            tls_pem: Some(CERT.to_string()),
+            tls_key: None,
+            sni: Some("example.com".to_string()),
+            acme: false,
+        };
+        let status = status_of(&domain, NOT_AFTER - 3 * SECONDS_PER_DAY);
+        assert_eq!(status.domain, "example.com");
+        assert_eq!(status.days_remaining, Some(3));
+        assert_eq!(status.expires_at.as_deref(), Some("2030-01-01T00:00:00+00:00"));
+        assert!(status.error.is_none());
+    }
+}
@@ -0,0 +1,26 @@
+//! # Config Version
+//!
+//! A counter in the `config_version` table that grows with every write to the
+//! proxies, proxy domains, gateway nodes and gateways. SQLite triggers bump it,
+//! so every write path (single and bulk edits, imports, auto-config and the
+//! trash purge) is counted without the handlers having to remember it.
+//!
+//! The settings GET endpoints report it in the `X-Config-Version` header and
+//! `GET /api/v1/settings/version` returns it alone, so a client can tell
+//! whether anything changed since it last fetched with a single cheap call.
+//! The counter survives restarts and never goes back.
+
+use crate::module::database::{get_connection, DatabaseResult};
+
+/// Response header carrying the current config version
+pub const CONFIG_VERSION_HEADER: &str = "x-config-version";
+
+/// Returns the current config version, `0` before the first write.
+pub fn current() -> DatabaseResult<u64> {
+    let version = get_connection()?.query_one(
+        "SELECT version FROM config_version WHERE id = 1",
+        [],
+        |row| row.get::<_, i64>(0),
+    )?;
+    Ok(version.unwrap_or(0).max(0) as u64)
+}
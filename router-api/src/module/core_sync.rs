@@ -0,0 +1,259 @@
+//! # Core Synchronization
+//!
+//! Pushes the proxy nodes, gateway nodes and gateway paths stored in the
+//! database to the router core. A background thread keeps the core converged
+//! with the database:
+//!
+//! - until a push succeeds it retries with exponential backoff (1s up to 60s),
+//!   so a core that was down when the API started picks up its routing once
+//!   it is reachable
+//! - afterwards it compares a fingerprint of each part every few seconds and
+//!   pushes again only the parts whose rows changed
+//!
+//! Settings handlers only write the database; this thread is what applies their
+//! changes to the core, within one poll interval.
+//!
+//! The outcome of the last attempt is kept for the health endpoint.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::api::sync::{
+    gateway_node_queries, gateway_node_tcp, proxy_node_queries, proxy_node_tcp,
+};
+use crate::module::httpc::HttpC;
+
+/// Delay before the first retry of a failed push
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest delay between two retries
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Interval between two checks of the database for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// State of the last push to the core
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncStatus {
+    /// Whether the last push succeeded
+    pub in_sync: bool,
+    /// Unix timestamp of the last push attempt
+    pub last_attempt: Option<i64>,
+    /// Unix timestamp of the last successful push
+    pub last_success: Option<i64>,
+    /// Why the last push failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Failed attempts since the last successful push
+    pub consecutive_failures: u32,
+}
+
+/// Outcome of one push of all routing data
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    /// Proxy nodes pushed
+    pub proxy_nodes: usize,
+    /// Gateway nodes pushed
+    pub gateway_nodes: usize,
+    /// Gateway paths pushed
+    pub gateway_paths: usize,
+    /// Parts that failed, with the reason
+    pub errors: Vec<String>,
+}
+
+impl SyncReport {
+    /// Whether every part was pushed
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+static STATUS: Mutex<SyncStatus> = Mutex::new(SyncStatus {
+    in_sync: false,
+    last_attempt: None,
+    last_success: None,
+    last_error: None,
+    consecutive_failures: 0,
+});
+
+/// Returns the state of the last push to the core.
+pub fn status() -> SyncStatus {
+    STATUS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn record(report: &SyncReport) {
+    let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Utc::now().timestamp();
+    status.last_attempt = Some(now);
+    if report.is_ok() {
+        status.in_sync = true;
+        status.last_success = Some(now);
+        status.last_error = None;
+        status.consecutive_failures = 0;
+    } else {
+        status.in_sync = false;
+        status.last_error = Some(report.errors.join("; "));
+        status.consecutive_failures += 1;
+    }
+}
+
+/// One of the routing tables pushed to the core on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part {
+    ProxyNodes,
+    GatewayNodes,
+    GatewayPaths,
+}
+
+const PARTS: [Part; 3] = [Part::ProxyNodes, Part::GatewayNodes, Part::GatewayPaths];
+
+impl Part {
+    /// Fingerprint of the rows the core receives for this part, changes with any edit.
+    fn fingerprint(self) -> Result<u64, String> {
+        let json = match self {
+            Part::ProxyNodes => proxy_node_queries::get_all_proxy_nodes()
+                .map_err(|e| e.to_string())
+                .and_then(|rows| serde_json::to_string(&rows).map_err(|e| e.to_string())),
+            Part::GatewayNodes => gateway_node_queries::get_all_gateway_nodes()
+                .map_err(|e| e.to_string())
+                .and_then(|rows| serde_json::to_string(&rows).map_err(|e| e.to_string())),
+            Part::GatewayPaths => gateway_node_queries::get_all_gateway_paths()
+                .map_err(|e| e.to_string())
+                .and_then(|rows| serde_json::to_string(&rows).map_err(|e| e.to_string())),
+        }?;
+
+        let mut hasher = DefaultHasher::new();
+        json.hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    /// Pushes this part to the core and adds the outcome to `report`.
+    async fn push(self, client: &Arc<HttpC>, report: &mut SyncReport) -> bool {
+        let result = match self {
+            Part::ProxyNodes => proxy_node_tcp::sync_proxy_nodes_to_registry(client)
+                .await
+                .map(|data| report.proxy_nodes = data.count),
+            Part::GatewayNodes => gateway_node_tcp::sync_gateway_nodes_to_registry(client)
+                .await
+                .map(|data| report.gateway_nodes = data.count),
+            Part::GatewayPaths => gateway_node_tcp::sync_gateway_paths_to_registry(client)
+                .await
+                .map(|data| report.gateway_paths = data.count),
+        };
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                report.errors.push(format!("{}: {}", self.name(), e.message));
+                false
+            }
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Part::ProxyNodes => "proxy nodes",
+            Part::GatewayNodes => "gateway nodes",
+            Part::GatewayPaths => "gateway paths",
+        }
+    }
+}
+
+/// Pushes proxy nodes, gateway nodes and gateway paths to the core and records the outcome.
+///
+/// Every part is attempted even when an earlier one fails.
+pub async fn sync_all(client: &Arc<HttpC>) -> SyncReport {
+    let mut report = SyncReport::default();
+    for part in PARTS {
+        part.push(client, &mut report).await;
+    }
+    record(&report);
+    report
+}
+
+/// Delay after `failures` consecutive failed pushes
+fn backoff(failures: u32) -> Duration {
+    let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+    INITIAL_BACKOFF.saturating_mul(factor).min(MAX_BACKOFF)
+}
+
+/// Starts the background thread keeping the core in sync with the database.
+///
+/// Each part is fingerprinted on its own and only the parts that changed are
+/// pushed, so an edit restarts the core listeners once rather than once per part.
+pub fn spawn_syncer(client: Arc<HttpC>) {
+    std::thread::spawn(move || {
+        log::info!("Core sync started");
+        // Fingerprint of the data the core acknowledged last, per part
+        let mut pushed: [Option<u64>; 3] = [None; 3];
+        loop {
+            let mut current = [0u64; 3];
+            let mut failed = None;
+            for (i, part) in PARTS.into_iter().enumerate() {
+                match part.fingerprint() {
+                    Ok(fingerprint) => current[i] = fingerprint,
+                    Err(e) => {
+                        failed = Some(e);
+                        break;
+                    }
+                }
+            }
+            if let Some(e) = failed {
+                log::error!("Failed to read routing data for core sync: {}", e);
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+
+            let changed: Vec<usize> = (0..PARTS.len())
+                .filter(|&i| pushed[i] != Some(current[i]))
+                .collect();
+            if changed.is_empty() {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+
+            let first_sync = pushed.iter().all(Option::is_none);
+            let mut report = SyncReport::default();
+            for &i in &changed {
+                if futures::executor::block_on(PARTS[i].push(&client, &mut report)) {
+                    pushed[i] = Some(current[i]);
+                }
+            }
+            record(&report);
+
+            if report.is_ok() {
+                if first_sync {
+                    log::info!("Successfully synced routing data to the core");
+                } else {
+                    let names: Vec<&str> = changed.iter().map(|&i| PARTS[i].name()).collect();
+                    log::info!("Routing data changed, pushed {} to the core", names.join(", "));
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            } else {
+                let delay = backoff(status().consecutive_failures);
+                log::warn!(
+                    "Failed to sync routing data to the core: {}. Retrying in {:?}",
+                    report.errors.join("; "),
+                    delay
+                );
+                std::thread::sleep(delay);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(8));
+        assert_eq!(backoff(7), MAX_BACKOFF);
+        assert_eq!(backoff(100), MAX_BACKOFF);
+    }
+}
@@ -49,8 +49,12 @@
 /// }
 /// ```
 use rusqlite::{Connection, Result as SqliteResult};
+use std::collections::HashMap;
 use std::fs;
+use std::ops::{Deref, DerefMut};
 use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 /// Errors that can occur during database operations.
@@ -72,13 +76,6 @@ pub enum DatabaseError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     
-    /// Error when attempting to access a database connection that has not been initialized.
-    ///
-    /// This typically occurs when there is a mutex poisoning or other threading issues.
-    #[error("Database connection not initialized")]
-    #[allow(dead_code)]
-    NotInitialized,
-    
     /// Custom error with a specific message.
     ///
     /// This is useful for domain-specific errors that are not directly
@@ -110,16 +107,172 @@ impl DatabaseError {
 /// for all database operations that includes the appropriate error type.
 pub type DatabaseResult<T> = Result<T, DatabaseError>;
 
+/// How long an operation waits for a free pooled connection before failing
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Idle connections and the number of connections currently open
+struct PoolState {
+    idle: Vec<Connection>,
+    open: usize,
+}
+
+/// A fixed-size pool of connections to one database file.
+///
+/// Connections are opened lazily, up to `size`, and go back to the pool when
+/// their [`PooledConnection`] is dropped. Every connection uses WAL journaling,
+/// so readers don't block the writer (and vice versa) and read handlers run
+/// concurrently on separate connections; writers still take turns, waiting up
+/// to the busy timeout for each other.
+pub struct Pool {
+    path: String,
+    size: usize,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+impl Pool {
+    fn new(path: String, size: usize) -> Self {
+        Self {
+            path,
+            size: size.max(1),
+            state: Mutex::new(PoolState { idle: Vec::new(), open: 0 }),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Opens a new connection and sets up the pragmas every connection needs.
+    fn open(&self) -> SqliteResult<Connection> {
+        let conn = Connection::open(&self.path)?;
+
+        // Configure SQLite for better reliability and concurrent readers
+        conn.execute_batch("
+            PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            PRAGMA busy_timeout = 1000;
+            PRAGMA foreign_keys = ON;
+        ")?;
+
+        Ok(conn)
+    }
+
+    /// Pool bookkeeping, recovered if a panicking thread poisoned the lock.
+    ///
+    /// The lock is never held across a query and every change under it is a
+    /// single push, pop or count update, so the state is consistent after a panic.
+    fn state(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes a connection from the pool, opening one if the pool isn't full yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no connection became free within five seconds, or if
+    /// a new connection could not be opened.
+    pub fn get(self: &Arc<Self>) -> DatabaseResult<PooledConnection> {
+        let deadline = Instant::now() + CHECKOUT_TIMEOUT;
+        let mut state = self.state();
+
+        loop {
+            if let Some(conn) = state.idle.pop() {
+                return Ok(PooledConnection { pool: Arc::clone(self), conn: Some(conn) });
+            }
+
+            if state.open < self.size {
+                state.open += 1;
+                drop(state);
+                return match self.open() {
+                    Ok(conn) => Ok(PooledConnection { pool: Arc::clone(self), conn: Some(conn) }),
+                    Err(e) => {
+                        self.forget_one();
+                        Err(e.into())
+                    }
+                };
+            }
+
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return Err(DatabaseError::from_msg(format!(
+                    "Timed out waiting for a connection to {}",
+                    self.path
+                )));
+            }
+            state = self
+                .available
+                .wait_timeout(state, timeout)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+    }
+
+    /// Frees the slot of a connection that is closed instead of returned.
+    fn forget_one(&self) {
+        self.state().open -= 1;
+        self.available.notify_one();
+    }
+}
+
+/// A connection borrowed from a [`Pool`], returned to it on drop.
+///
+/// A connection dropped in the middle of a transaction is closed instead, so
+/// the transaction is rolled back and never leaks into the next borrower.
+pub struct PooledConnection {
+    pool: Arc<Pool>,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection is present until drop")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("connection is present until drop")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(conn) = self.conn.take() else { return };
+
+        if !conn.is_autocommit() {
+            drop(conn);
+            self.pool.forget_one();
+            return;
+        }
+
+        self.pool.state().idle.push(conn);
+        self.pool.available.notify_one();
+    }
+}
+
+/// Returns the shared pool of the database file at `path`, creating it on first use.
+fn pool_for(path: String) -> Arc<Pool> {
+    static POOLS: OnceLock<Mutex<HashMap<String, Arc<Pool>>>> = OnceLock::new();
+
+    let mut pools = POOLS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    Arc::clone(
+        pools
+            .entry(path.clone())
+            .or_insert_with(|| Arc::new(Pool::new(path, crate::config::db_pool_size()))),
+    )
+}
+
 /// A simplified database wrapper for SQLite operations.
 ///
-/// This implementation creates a new connection for each operation and
-/// closes it immediately afterward, avoiding any potential locking issues
-/// at the cost of a slight performance overhead. This is ideal for
-/// low-concurrency applications where database locking is more problematic
-/// than the overhead of creating new connections.
+/// Every operation borrows a connection from the pool of the database file and
+/// returns it afterwards. `Database` values are cheap handles, all handles to
+/// the same file share one pool (sized by `GWRS_DB_POOL_SIZE`, default 8).
 pub struct Database {
-    /// Path to the SQLite database file
-    db_path: String,
+    /// Pool of connections to the SQLite database file
+    pool: Arc<Pool>,
 }
 
 #[allow(dead_code)]
@@ -141,7 +294,7 @@ impl Database {
         
         let db_path = db_dir.join("core.sqlite").to_string_lossy().to_string();
         
-        Ok(Self { db_path })
+        Ok(Self::open(db_path))
     }
     
     /// Creates a new database connection to the logging database.
@@ -161,35 +314,30 @@ impl Database {
         
         let db_path = db_dir.join("core_logging.sqlite").to_string_lossy().to_string();
         
-        Ok(Self { db_path })
+        Ok(Self::open(db_path))
+    }
+
+    /// Creates a handle to the database file at `db_path`.
+    fn open(db_path: String) -> Self {
+        Self { pool: pool_for(db_path) }
     }
     
-    /// Creates a new connection to the database.
+    /// Borrows a connection from the pool.
     ///
-    /// This function is used internally to open a fresh connection for each operation.
-    /// It also sets up necessary SQLite pragmas for better reliability.
+    /// Use it to keep one connection across several statements, e.g. a read
+    /// transaction; the other methods borrow a connection per call.
     ///
     /// # Returns
     ///
-    /// A `DatabaseResult` containing either the new Connection or an error
-    fn connect(&self) -> DatabaseResult<Connection> {
-        let conn = Connection::open(&self.db_path)?;
-        
-        // Configure SQLite for better reliability
-        conn.execute_batch("
-            PRAGMA journal_mode = WAL;
-            PRAGMA synchronous = NORMAL;
-            PRAGMA busy_timeout = 1000;
-            PRAGMA foreign_keys = ON;
-        ")?;
-        
-        Ok(conn)
+    /// A `DatabaseResult` containing the pooled connection or an error
+    pub fn connection(&self) -> DatabaseResult<PooledConnection> {
+        self.pool.get()
     }
     
     /// Executes a raw SQL query with optional parameters.
     ///
-    /// This method creates a new connection, executes the statement, and then
-    /// automatically closes the connection.
+    /// This method borrows a connection from the pool, executes the statement, and then
+    /// returns the connection to the pool.
     ///
     /// # Parameters
     ///
@@ -241,7 +389,7 @@ impl Database {
     where
         P: rusqlite::Params,
     {
-        let conn = self.connect()?;
+        let conn = self.connection()?;
         let result = conn.execute(sql, params)?;
         Ok(result)
     }
@@ -316,7 +464,7 @@ impl Database {
         F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
         P: rusqlite::Params,
     {
-        let conn = self.connect()?;
+        let conn = self.connection()?;
         let mut stmt = conn.prepare(sql)?;
         let rows = stmt.query_map(params, f)?;
         
@@ -404,7 +552,7 @@ impl Database {
         F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
         P: rusqlite::Params,
     {
-        let conn = self.connect()?;
+        let conn = self.connection()?;
         let mut stmt = conn.prepare(sql)?;
         let mut rows = stmt.query_map(params, f)?;
         
@@ -491,7 +639,7 @@ impl Database {
     where
         F: FnOnce(&Connection) -> SqliteResult<T>,
     {
-        let mut conn = self.connect()?;
+        let mut conn = self.connection()?;
         let tx = conn.transaction()?;
         let result = f(&tx)?;
         tx.commit()?;
@@ -527,7 +675,7 @@ impl Database {
         table_name: &str, 
         expected_columns: &[&str]
     ) -> DatabaseResult<bool> {
-        let conn = self.connect()?;
+        let conn = self.connection()?;
         
         // First check if the table exists
         let table_exists: bool = conn
@@ -574,6 +722,112 @@ impl Database {
     }
 }
 
+/// One step of the schema migrations applied by [`Database::migrate`].
+///
+/// Steps are applied in order of `version`, each in its own transaction together
+/// with recording the new version, so a failing step leaves the schema at the
+/// previous version. Steps should be idempotent (e.g. use [`add_column_if_missing`])
+/// because databases created before versioning already have part of the schema.
+pub struct Migration {
+    /// Schema version reached by this step, strictly increasing
+    pub version: u32,
+    /// Short description stored in the `schema_version` table
+    pub description: &'static str,
+    /// Applies the step
+    pub up: fn(&Connection) -> SqliteResult<()>,
+}
+
+impl Database {
+    /// Returns the current schema version, 0 for a database that was never migrated.
+    pub fn schema_version(&self) -> DatabaseResult<u32> {
+        self.execute(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER PRIMARY KEY,
+                description TEXT NOT NULL,
+                applied_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(self
+            .query_one("SELECT MAX(version) FROM schema_version", [], |row| {
+                row.get::<_, Option<u32>>(0)
+            })?
+            .flatten()
+            .unwrap_or(0))
+    }
+
+    /// Applies every migration newer than the current schema version.
+    ///
+    /// # Returns
+    ///
+    /// The schema version after migrating.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the migrations are not ordered by strictly increasing
+    /// version, or if a step fails. Steps applied before the failing one stay applied.
+    pub fn migrate(&self, migrations: &[Migration]) -> DatabaseResult<u32> {
+        if migrations.windows(2).any(|pair| pair[0].version >= pair[1].version) {
+            return Err(DatabaseError::from_msg(
+                "Migrations must be ordered by strictly increasing version",
+            ));
+        }
+
+        let mut version = self.schema_version()?;
+        for migration in migrations.iter().filter(|migration| migration.version > version) {
+            log::info!(
+                "Applying database migration {}: {}",
+                migration.version,
+                migration.description
+            );
+            self.transaction(|conn| {
+                (migration.up)(conn)?;
+                conn.execute(
+                    "INSERT INTO schema_version (version, description, applied_at)
+                     VALUES (?1, ?2, strftime('%s', 'now'))",
+                    rusqlite::params![migration.version, migration.description],
+                )?;
+                Ok(())
+            })
+            .map_err(|e| {
+                DatabaseError::from_msg(format!(
+                    "Migration {} ({}) failed: {}",
+                    migration.version, migration.description, e
+                ))
+            })?;
+            version = migration.version;
+        }
+
+        Ok(version)
+    }
+}
+
+/// Adds a column to a table unless it already exists, for idempotent migrations.
+///
+/// `definition` is the column definition following the name, e.g. `"INTEGER"` or
+/// `"BOOLEAN NOT NULL DEFAULT 0"`.
+pub fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> SqliteResult<()> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        rusqlite::params![table, column],
+        |row| row.get::<_, i64>(0).map(|count| count > 0),
+    )?;
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
 /// A builder pattern for constructing SQL queries with type safety.
 ///
 /// The `Query` struct helps in building parameterized SQL queries with type
@@ -773,4 +1027,169 @@ mod tests {
         // Clean up
         db.execute("DROP TABLE test_table", []).expect("Failed to drop test table");
     }
-}
\ No newline at end of file
+
+    fn scratch_database(name: &str) -> Database {
+        let path = std::env::temp_dir().join(format!("gwrs-{}-{}.sqlite", name, uuid::Uuid::new_v4()));
+        Database::open(path.to_string_lossy().to_string())
+    }
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            description: "create items",
+            up: |conn| conn.execute_batch("CREATE TABLE IF NOT EXISTS items (id TEXT PRIMARY KEY)"),
+        },
+        Migration {
+            version: 2,
+            description: "add items.name",
+            up: |conn| add_column_if_missing(conn, "items", "name", "TEXT"),
+        },
+    ];
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let db = scratch_database("migrate");
+        assert_eq!(db.schema_version().unwrap(), 0);
+        assert_eq!(db.migrate(MIGRATIONS).unwrap(), 2);
+        assert_eq!(db.migrate(MIGRATIONS).unwrap(), 2);
+        assert!(db.table_exists_with_columns("items", &["id", "name"]).unwrap());
+
+        // A database that already had the column before versioning
+        let db = scratch_database("migrate-existing");
+        db.execute("CREATE TABLE items (id TEXT PRIMARY KEY, name TEXT)", []).unwrap();
+        assert_eq!(db.migrate(MIGRATIONS).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_failed_migration_keeps_previous_version() {
+        let db = scratch_database("migrate-failure");
+        let failing = [
+            Migration { version: 1, description: "ok", up: MIGRATIONS[0].up },
+            Migration {
+                version: 2,
+                description: "broken",
+                up: |conn| conn.execute_batch("ALTER TABLE missing ADD COLUMN x TEXT"),
+            },
+        ];
+        assert!(db.migrate(&failing).is_err());
+        assert_eq!(db.schema_version().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_readers_run_alongside_writer() {
+        let db = Arc::new(scratch_database("concurrency"));
+        db.execute("CREATE TABLE counters (id INTEGER PRIMARY KEY, value INTEGER NOT NULL)", [])
+            .unwrap();
+        db.execute("INSERT INTO counters (id, value) VALUES (1, 0)", []).unwrap();
+
+        // A long-running read transaction holds its snapshot for the whole test.
+        // Without WAL its shared lock would keep the writer from committing.
+        let reader = db.connection().unwrap();
+        reader.execute_batch("BEGIN").unwrap();
+        let read = |conn: &Connection| {
+            conn.query_row("SELECT value FROM counters WHERE id = 1", [], |row| row.get::<_, i64>(0))
+                .unwrap()
+        };
+        assert_eq!(read(&reader), 0);
+
+        let writer = {
+            let db = Arc::clone(&db);
+            std::thread::spawn(move || {
+                for value in 1..=20 {
+                    db.execute("UPDATE counters SET value = ?1 WHERE id = 1", [value]).unwrap();
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = Arc::clone(&db);
+                std::thread::spawn(move || {
+                    for _ in 0..50 {
+                        let values = db
+                            .query("SELECT value FROM counters WHERE id = 1", [], |row| row.get::<_, i64>(0))
+                            .unwrap();
+                        assert_eq!(values.len(), 1);
+                    }
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        for handle in readers {
+            handle.join().unwrap();
+        }
+
+        // The open transaction still sees its snapshot, new reads see the writes
+        assert_eq!(read(&reader), 0);
+        reader.execute_batch("COMMIT").unwrap();
+        assert_eq!(read(&reader), 20);
+    }
+
+    #[test]
+    fn test_pool_reuses_returned_connections() {
+        let pool = Arc::new(Pool::new(
+            std::env::temp_dir()
+                .join(format!("gwrs-pool-{}.sqlite", uuid::Uuid::new_v4()))
+                .to_string_lossy()
+                .to_string(),
+            1,
+        ));
+
+        let first = pool.get().unwrap();
+        let waiter = {
+            let pool = Arc::clone(&pool);
+            std::thread::spawn(move || pool.get().map(|_| ()))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        drop(first);
+        assert!(waiter.join().unwrap().is_ok());
+
+        // A connection dropped mid-transaction is closed, not handed out again
+        let conn = pool.get().unwrap();
+        conn.execute_batch("BEGIN").unwrap();
+        drop(conn);
+        assert!(pool.get().unwrap().is_autocommit());
+        assert_eq!(pool.state().open, 1);
+    }
+
+    #[test]
+    fn test_pool_survives_a_panicking_borrower() {
+        let pool = Arc::new(Pool::new(
+            std::env::temp_dir()
+                .join(format!("gwrs-pool-{}.sqlite", uuid::Uuid::new_v4()))
+                .to_string_lossy()
+                .to_string(),
+            2,
+        ));
+
+        let panicked = {
+            let pool = Arc::clone(&pool);
+            std::thread::spawn(move || {
+                let _conn = pool.get().unwrap();
+                let _state = pool.state.lock().unwrap();
+                panic!("handler panicked while holding the pool");
+            })
+            .join()
+        };
+        assert!(panicked.is_err());
+        assert!(pool.state.is_poisoned());
+
+        // The panicking borrower's connection came back, and both slots still work
+        let first = pool.get().unwrap();
+        let second = pool.get().unwrap();
+        first.execute_batch("SELECT 1").unwrap();
+        second.execute_batch("SELECT 1").unwrap();
+        drop((first, second));
+        assert_eq!(pool.state().open, 2);
+    }
+
+    #[test]
+    fn test_migrations_must_be_ordered() {
+        let db = scratch_database("migrate-order");
+        let unordered = [
+            Migration { version: 2, description: "b", up: MIGRATIONS[1].up },
+            Migration { version: 1, description: "a", up: MIGRATIONS[0].up },
+        ];
+        assert!(db.migrate(&unordered).is_err());
+    }
+}
@@ -114,7 +114,7 @@ impl DatabaseLog {
             timestamp,
         };
 
-        let mut pool = self.log_pool.lock().unwrap();
+        let mut pool = self.log_pool.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
         pool.push(entry);
     }
 
@@ -173,7 +173,7 @@ impl DatabaseLog {
     /// Flush the log pool to the database
     #[allow(deprecated)]
     fn flush_to_db(&self) -> Result<usize, DatabaseError> {
-        let mut pool = self.log_pool.lock().unwrap();
+        let mut pool = self.log_pool.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
         if pool.is_empty() {
             return Ok(0);
         }
@@ -1,41 +1,203 @@
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::net::TcpStream;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::module::{netaddr, request_id};
+
+/// Default number of idle connections kept open to the core
+const DEFAULT_MAX_IDLE: usize = 8;
+
+/// Read/write timeout applied to every pooled connection
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Very simple HTTP client that only checks response status
 /// - Sends path + body via HTTP
 /// - Returns Ok(()) for 2xx status codes  
-/// - Returns Err(String) for non-2xx status codes
-/// - Ignores response body completely
+/// - Returns Err(String) for non-2xx status codes, including the response body if any
+/// - Ignores the body of successful responses, except for `post_with_response`
+///
+/// Connections to the core are kept in a small bounded pool. Each request
+/// checks out an idle connection (or opens a new one), and hands it back
+/// once the response has been fully read. The client is `Sync`, so it can
+/// be shared as `Arc<HttpC>` and used by several handlers at the same time.
+/// The pool lock is only held to take or return a connection, and a handler
+/// panicking meanwhile doesn't disable pooling for the others.
 pub struct HttpC {
     host: String,
     port: u16,
+    max_idle: usize,
+    idle: Mutex<Vec<TcpStream>>,
+    /// Bearer token of the core's protocol server, if it requires one
+    token: Option<String>,
 }
 
 impl HttpC {
     pub fn new(host: &str, port: u16) -> Self {
+        Self::with_pool_size(host, port, DEFAULT_MAX_IDLE)
+    }
+
+    /// Creates a client that keeps at most `max_idle` idle connections
+    pub fn with_pool_size(host: &str, port: u16, max_idle: usize) -> Self {
         Self {
             host: host.to_string(),
             port,
+            max_idle,
+            idle: Mutex::new(Vec::with_capacity(max_idle)),
+            token: None,
         }
     }
 
+    /// Sends `token` as bearer token with every request
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
     /// Send POST request with body - returns success/failure based on status
     pub fn post(&self, path: &str, body: &[u8]) -> Result<(), String> {
-        self.send_request("GWRX", path, body)
+        self.send_request("GWRX", path, body, true).map(|_| ())
+    }
+
+    /// Send POST request with body - returns the response body on success
+    pub fn post_with_response(&self, path: &str, body: &[u8]) -> Result<String, String> {
+        self.send_request("GWRX", path, body, true)
+    }
+
+    /// Like [`post_with_response`](Self::post_with_response) for requests that
+    /// must not reach the core twice, e.g. a shutdown or reload command
+    pub fn post_once_with_response(&self, path: &str, body: &[u8]) -> Result<String, String> {
+        self.send_request("GWRX", path, body, false)
+    }
+
+    /// Sends a request over a pooled connection and returns the response body
+    ///
+    /// A reused connection may have been closed by the core in the meantime,
+    /// so a failure on a pooled connection is retried once on a fresh one.
+    /// Requests that aren't `idempotent` are only retried if they were never
+    /// written, once sent the core may have acted on them without answering.
+    fn send_request(&self, method: &str, path: &str, body: &[u8], idempotent: bool) -> Result<String, String> {
+        if let Some(stream) = self.checkout() {
+            match self.round_trip(stream, method, path, body) {
+                Ok(result) => return result,
+                Err(ConnError::Sent(e)) if !idempotent => return Err(e),
+                Err(_) => {}
+            }
+        }
+
+        let stream = self.connect()?;
+        self.round_trip(stream, method, path, body)
+            .map_err(|(ConnError::Unsent(e) | ConnError::Sent(e))| e)?
+    }
+
+    /// Writes the request and reads the response on the given connection
+    ///
+    /// The outer error means the connection itself failed, the inner result
+    /// carries the outcome of the request (status check and body).
+    fn round_trip(
+        &self,
+        stream: TcpStream,
+        method: &str,
+        path: &str,
+        body: &[u8],
+    ) -> Result<Result<String, String>, ConnError> {
+        let mut stream = stream;
+        self.write_request(&mut stream, method, path, body)
+            .map_err(ConnError::Unsent)?;
+
+        let mut reader = BufReader::new(stream);
+        let (response, reusable) = Self::read_response(&mut reader).map_err(ConnError::Sent)?;
+
+        // Only hand the connection back if nothing is left unread on it
+        if reusable && reader.buffer().is_empty() {
+            self.checkin(reader.into_inner());
+        }
+
+        Ok(match Self::check_status(&response.head) {
+            Ok(()) => Ok(response.body),
+            // The core explains rejected payloads in the body
+            Err(e) if !response.body.trim().is_empty() => {
+                Err(format!("{}: {}", e, response.body.trim()))
+            }
+            Err(e) => Err(e),
+        })
+    }
+
+    /// Idle connections, recovered if a panicking thread poisoned the lock
+    ///
+    /// Every change to the list is a single push or pop, a panic can't leave it half updated.
+    fn idle(&self) -> MutexGuard<'_, Vec<TcpStream>> {
+        self.idle.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes an idle connection from the pool, dropping any the core has closed
+    fn checkout(&self) -> Option<TcpStream> {
+        let mut idle = self.idle();
+        while let Some(stream) = idle.pop() {
+            if Self::is_alive(&stream) {
+                return Some(stream);
+            }
+        }
+        None
+    }
+
+    /// Returns a connection to the pool, closing it if the pool is full
+    fn checkin(&self, stream: TcpStream) {
+        let mut idle = self.idle();
+        if idle.len() < self.max_idle {
+            idle.push(stream);
+        }
+    }
+
+    /// Health check for an idle connection
+    ///
+    /// An idle connection must have nothing to read: EOF means the core closed
+    /// it, and unexpected bytes mean the stream is out of sync.
+    fn is_alive(stream: &TcpStream) -> bool {
+        if stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let mut probe = [0u8; 1];
+        let alive = match stream.peek(&mut probe) {
+            Ok(_) => false,
+            Err(e) => e.kind() == ErrorKind::WouldBlock,
+        };
+        alive && stream.set_nonblocking(false).is_ok()
     }
 
-    /// Generic request sender - only checks status, ignores response body
-    fn send_request(&self, method: &str, path: &str, body: &[u8]) -> Result<(), String> {
-        // Connect to server
-        let mut stream = TcpStream::connect(format!("{}:{}", self.host, self.port))
+    /// Opens a new connection to the core
+    fn connect(&self) -> Result<TcpStream, String> {
+        let address = netaddr::join_host_port(&self.host, self.port);
+        let stream = TcpStream::connect(&address)
             .map_err(|e| format!("Connection failed: {}", e))?;
+        let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+        let _ = stream.set_nodelay(true);
+        Ok(stream)
+    }
+
+    /// Writes the request line, headers and body
+    fn write_request(&self, stream: &mut TcpStream, method: &str, path: &str, body: &[u8]) -> Result<(), String> {
+        let address = netaddr::join_host_port(&self.host, self.port);
+
+        // Forward the ID of the API request being handled, if any
+        let request_id = match request_id::current() {
+            Some(id) => format!("X-Request-Id: {}\r\n", id),
+            None => String::new(),
+        };
+        let authorization = match &self.token {
+            Some(token) => format!("Authorization: Bearer {}\r\n", token),
+            None => String::new(),
+        };
 
         // Build HTTP request
         let request = format!(
-            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n",
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: keep-alive\r\n{}{}Content-Length: {}\r\n\r\n",
             method,
             path,
-            self.host,
+            address,
+            request_id,
+            authorization,
             body.len()
         );
 
@@ -52,13 +214,67 @@ impl HttpC {
         stream.flush()
             .map_err(|e| format!("Failed to flush: {}", e))?;
 
-        // Read only the status line
-        let mut buffer = [0; 1024];
-        let bytes_read = stream.read(&mut buffer)
-            .map_err(|e| format!("Failed to read response: {}", e))?;
-        
-        let response = String::from_utf8_lossy(&buffer[..bytes_read]);
-        
+        Ok(())
+    }
+
+    /// Reads one response, using Content-Length to find the end of the body
+    ///
+    /// Returns the response and whether the connection can be reused. Without
+    /// a Content-Length, or when the core asks to close, the body runs to EOF.
+    fn read_response(reader: &mut BufReader<TcpStream>) -> Result<(Response, bool), String> {
+        let mut head = String::new();
+        let mut content_length = None;
+        let mut reusable = true;
+
+        loop {
+            let mut line = String::new();
+            let read = reader.read_line(&mut line)
+                .map_err(|e| format!("Failed to read response: {}", e))?;
+            if read == 0 {
+                if head.is_empty() {
+                    return Err("Connection closed before response".to_string());
+                }
+                break;
+            }
+            if line.trim().is_empty() {
+                break;
+            }
+
+            if let Some((key, value)) = line.split_once(':') {
+                let key = key.trim().to_ascii_lowercase();
+                let value = value.trim();
+                if key == "content-length" {
+                    content_length = value.parse::<usize>().ok();
+                } else if key == "connection" && value.eq_ignore_ascii_case("close") {
+                    reusable = false;
+                }
+            }
+            head.push_str(&line);
+        }
+
+        let mut body = Vec::new();
+        match content_length {
+            Some(len) => {
+                body.resize(len, 0);
+                reader.read_exact(&mut body)
+                    .map_err(|e| format!("Failed to read response: {}", e))?;
+            }
+            None => {
+                reusable = false;
+                reader.read_to_end(&mut body)
+                    .map_err(|e| format!("Failed to read response: {}", e))?;
+            }
+        }
+
+        let response = Response {
+            head,
+            body: String::from_utf8_lossy(&body).to_string(),
+        };
+        Ok((response, reusable))
+    }
+
+    /// Parses the status line and maps non-2xx codes to an error
+    fn check_status(response: &str) -> Result<(), String> {
         // Parse status line (first line)
         let status_line = response.lines().next()
             .ok_or("No status line found")?;
@@ -81,6 +297,18 @@ impl HttpC {
     }
 }
 
+/// Failure of a connection, before or after the request was fully written
+enum ConnError {
+    Unsent(String),
+    Sent(String),
+}
+
+/// Status line and headers, plus the decoded body of a core response
+struct Response {
+    head: String,
+    body: String,
+}
+
 // Helper functions for common data types
 impl HttpC {
     /// Send JSON data - returns success/failure only
@@ -97,4 +325,59 @@ impl HttpC {
     pub fn post_bytes(&self, path: &str, data: &[u8]) -> Result<(), String> {
         self.post(path, data)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_pool_survives_a_panicking_handler() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = Arc::new(HttpC::with_pool_size("127.0.0.1", port, 2));
+
+        let panicked = {
+            let client = Arc::clone(&client);
+            std::thread::spawn(move || {
+                let _idle = client.idle.lock().unwrap();
+                panic!("handler panicked while holding the pool");
+            })
+            .join()
+        };
+        assert!(panicked.is_err());
+        assert!(client.idle.is_poisoned());
+
+        // Connections are still handed back and reused
+        client.checkin(client.connect().unwrap());
+        let (_core_side, _) = listener.accept().unwrap();
+        assert!(client.checkout().is_some());
+        assert!(client.checkout().is_none());
+    }
+
+    #[test]
+    fn test_unanswered_command_is_not_resent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = HttpC::with_pool_size("127.0.0.1", port, 2);
+        client.checkin(client.connect().unwrap());
+        let (core_side, _) = listener.accept().unwrap();
+
+        // The core reads the request and drops the connection without answering
+        let core = std::thread::spawn(move || {
+            let mut reader = BufReader::new(core_side);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+        });
+        assert!(client.post_once_with_response("/shutdown", &[]).is_err());
+        core.join().unwrap();
+
+        // No second connection was opened to resend it
+        listener.set_nonblocking(true).unwrap();
+        assert!(listener.accept().is_err());
+    }
+}
@@ -0,0 +1,160 @@
+//! # Consumer Checkpoints
+//!
+//! The shared-memory rings are drained destructively, so once the API restarts it
+//! has no memory of what it already moved into the temporary log store. A
+//! checkpoint records, per ring, the newest entry timestamp that was flushed and
+//! the ring read index at that moment.
+//!
+//! ## Ordering Guarantees
+//!
+//! * Entries are delivered in ring (FIFO) order, which is the order the producer
+//!   enqueued them; timestamps are whole seconds and are not strictly increasing.
+//! * Delivery is at-most-once: an entry is removed from the ring before it is
+//!   flushed, so a crash between the two loses the unflushed batch.
+//! * After a restart, entries at the head of the ring older than the checkpoint
+//!   timestamp are dropped as already flushed. Entries from the same second are
+//!   kept, since they can't be told apart from new ones. Once the first entry is
+//!   accepted the filter is lifted, so producer threads racing on a timestamp
+//!   never lose entries during normal operation.
+//!
+//! ## Ring Resets
+//!
+//! Only the consumer advances the read index, so finding it somewhere other than
+//! the checkpointed slot on startup means the producer reset the ring
+//! (`fresh_start`). The consumer then resumes from the ring's new read head.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Position {
+    /// Timestamp (seconds) of the newest flushed entry
+    timestamp: u64,
+    /// Ring slot the next dequeue reads from
+    read_index: usize,
+}
+
+/// Persisted consume position of one shared-memory ring.
+#[derive(Debug)]
+pub struct ConsumerCheckpoint {
+    path: PathBuf,
+    saved: Option<Position>,
+    current: Position,
+    caught_up: bool,
+}
+
+impl ConsumerCheckpoint {
+    /// Loads the checkpoint of the ring named `logger_name` (e.g. `/gwrs-proxy`).
+    pub fn load(logger_name: &str) -> Self {
+        let file_name = format!("consumer_{}.json", logger_name.trim_start_matches('/'));
+        // Kept next to the segments it describes
+        Self::load_from(config::log_archive_dir().join(file_name))
+    }
+
+    fn load_from(path: PathBuf) -> Self {
+        let saved = fs::read(&path)
+            .ok()
+            .and_then(|raw| serde_json::from_slice::<Position>(&raw).ok());
+        Self {
+            path,
+            saved,
+            current: saved.unwrap_or_default(),
+            caught_up: false,
+        }
+    }
+
+    /// Raises the checkpoint timestamp to the newest entry already in the store.
+    ///
+    /// Covers batches that were flushed but whose checkpoint was never written.
+    pub fn reconcile(&mut self, stored_latest: Option<u64>) {
+        if let Some(latest) = stored_latest {
+            self.current.timestamp = self.current.timestamp.max(latest);
+        }
+    }
+
+    /// Whether the ring was reset since the checkpoint was written.
+    pub fn ring_was_reset(&self, read_index: usize) -> bool {
+        self.saved
+            .map(|saved| saved.read_index != read_index)
+            .unwrap_or(false)
+    }
+
+    /// Whether an entry with `timestamp` still has to be flushed.
+    pub fn accepts(&mut self, timestamp: u64) -> bool {
+        self.caught_up = self.caught_up || timestamp >= self.current.timestamp;
+        self.caught_up
+    }
+
+    /// Timestamp of the newest flushed entry.
+    #[cfg(test)]
+    pub fn timestamp(&self) -> u64 {
+        self.current.timestamp
+    }
+
+    /// Records a flushed batch and writes the checkpoint to disk.
+    pub fn commit(&mut self, newest_timestamp: u64, read_index: usize) -> io::Result<()> {
+        self.current = Position {
+            timestamp: self.current.timestamp.max(newest_timestamp),
+            read_index,
+        };
+        if self.saved == Some(self.current) {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&self.current)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        self.saved = Some(self.current);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "gwrs-checkpoint-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_commit_survives_reload() {
+        let path = temp_path("reload");
+        let mut checkpoint = ConsumerCheckpoint::load_from(path.clone());
+        assert!(!checkpoint.ring_was_reset(0));
+        checkpoint.commit(1_700_000_000, 42).unwrap();
+
+        let reloaded = ConsumerCheckpoint::load_from(path.clone());
+        assert_eq!(reloaded.timestamp(), 1_700_000_000);
+        assert!(!reloaded.ring_was_reset(42));
+        assert!(reloaded.ring_was_reset(0));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_older_entries_are_rejected() {
+        let mut checkpoint = ConsumerCheckpoint::load_from(temp_path("accept"));
+        checkpoint.reconcile(Some(100));
+        assert!(!checkpoint.accepts(99));
+        assert!(checkpoint.accepts(100));
+        // Once caught up, late entries from racing producers are kept
+        assert!(checkpoint.accepts(99));
+
+        // The store never lowers the checkpoint
+        checkpoint.reconcile(Some(50));
+        assert_eq!(checkpoint.timestamp(), 100);
+    }
+}
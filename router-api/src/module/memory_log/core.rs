@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::ffi::CString;
 use std::io::{self, Error, ErrorKind};
 use std::mem;
@@ -16,6 +17,11 @@ pub const ENTRY_MAX_SIZE: usize = 4096; // Maximum 4KB per entry
 pub const SHM_METADATA_SIZE: usize = 2048; // Space for metadata at the beginning (2KB)
 pub const PROXY_LOGGER_NAME: &str = "/gwrs-proxy";
 pub const GATEWAY_LOGGER_NAME: &str = "/gwrs-gateway";
+pub const LEVEL_TRACE: u8 = 0;
+pub const LEVEL_DEBUG: u8 = 1;
+pub const LEVEL_INFO: u8 = 2;
+pub const LEVEL_WARN: u8 = 3;
+pub const LEVEL_ERROR: u8 = 4;
 
 // Architecture-specific memory ordering helpers
 #[inline(always)]
@@ -121,8 +127,11 @@ impl QueueControl {
         // Memory fence to ensure index update is visible before count update
         memory_fence_release();
         
-        // Update count with Release ordering
-        self.count.fetch_sub(1, release_ordering());
+        // Update count with Release ordering, never wrapping below zero so a
+        // producer reset racing with this dequeue can't leave a huge count behind
+        let _ = self
+            .count
+            .fetch_update(release_ordering(), acquire_ordering(), |count| count.checked_sub(1));
     }
 }
 
@@ -229,6 +238,9 @@ impl SharedMemoryConsumer {
     }
 
     // Dequeue data from shared memory
+    //
+    // Read index and count are advanced only through `QueueControl::dequeue_item`,
+    // exactly once per entry (including skipped invalid entries).
     pub fn dequeue(&self) -> io::Result<Option<Vec<u8>>> {
         unsafe {
             // Lock the queue
@@ -364,6 +376,11 @@ impl SharedMemoryConsumer {
         unsafe { (*self.control).capacity.load(acquire_ordering()) }
     }
 
+    // Get the slot the next dequeue will read from
+    pub fn read_index(&self) -> usize {
+        unsafe { (*self.control).read_index.load(acquire_ordering()) }
+    }
+
     // Get overflow count (if available)
     pub fn overflow_count(&self) -> usize {
         unsafe { (*self.control).overflow_count.load(Ordering::Relaxed) }
@@ -408,6 +425,18 @@ pub struct LogEntry {
     // Message follows immediately after header
 }
 
+// Message of an entry; producers only write valid UTF-8, so replaced bytes
+// point at a corrupted entry and are flagged
+fn decode_message(bytes: &[u8]) -> String {
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(message) => message.to_string(),
+        Cow::Owned(message) => {
+            log::warn!("Log entry is not valid UTF-8, invalid bytes were replaced: {}", message);
+            message
+        }
+    }
+}
+
 // Log consumer implementation
 pub struct LogConsumer {
     shm: SharedMemoryConsumer,
@@ -446,8 +475,7 @@ impl LogConsumer {
                     let message_start = mem::size_of::<LogEntry>();
                     let message_bytes = &buffer[message_start..];
 
-                    // Convert to string
-                    let message = String::from_utf8_lossy(message_bytes).to_string();
+                    let message = decode_message(message_bytes);
 
                     Ok(Some((entry.timestamp, entry.level, message)))
                 }
@@ -477,8 +505,7 @@ impl LogConsumer {
                     let message_start = mem::size_of::<LogEntry>();
                     let message_bytes = &buffer[message_start..];
 
-                    // Convert to string
-                    let message = String::from_utf8_lossy(message_bytes).to_string();
+                    let message = decode_message(message_bytes);
 
                     Ok(Some((entry.timestamp, entry.level, message)))
                 }
@@ -491,12 +518,14 @@ impl LogConsumer {
         self.shm.queue_size()
     }
 
-    #[allow(dead_code)]
     pub fn capacity(&self) -> usize {
         self.shm.capacity()
     }
 
-    #[allow(dead_code)]
+    pub fn read_index(&self) -> usize {
+        self.shm.read_index()
+    }
+
     pub fn overflow_count(&self) -> usize {
         self.shm.overflow_count()
     }
@@ -506,3 +535,100 @@ impl LogConsumer {
         self.shm.cleanup()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal stand-in for the router-core producer, writing the same ring layout
+    struct TestProducer {
+        name: CString,
+        ptr: *mut u8,
+        size: usize,
+        fd: i32,
+    }
+
+    impl TestProducer {
+        fn create(name: &str, capacity: usize) -> Self {
+            let size = SHM_METADATA_SIZE + capacity * ENTRY_MAX_SIZE;
+            let c_name = CString::new(name).unwrap();
+            unsafe {
+                libc::shm_unlink(c_name.as_ptr());
+                let fd = libc::shm_open(c_name.as_ptr(), libc::O_CREAT | libc::O_RDWR, 0o600);
+                assert!(fd >= 0, "shm_open failed: {}", Error::last_os_error());
+                assert_eq!(libc::ftruncate(fd, size as libc::off_t), 0);
+                let ptr = libc::mmap(
+                    ptr::null_mut(),
+                    size,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    fd,
+                    0,
+                );
+                assert_ne!(ptr, libc::MAP_FAILED);
+                ptr::write(ptr as *mut QueueControl, QueueControl::new(capacity));
+                TestProducer { name: c_name, ptr: ptr as *mut u8, size, fd }
+            }
+        }
+
+        fn control(&self) -> &QueueControl {
+            unsafe { &*(self.ptr as *const QueueControl) }
+        }
+
+        fn enqueue(&self, data: &[u8]) {
+            let control = self.control();
+            let capacity = control.capacity.load(Ordering::Acquire);
+            let write_idx = control.write_index.load(Ordering::Acquire);
+            unsafe {
+                let entry = self.ptr.add(SHM_METADATA_SIZE + write_idx * ENTRY_MAX_SIZE);
+                ptr::write(entry as *mut usize, data.len());
+                ptr::copy_nonoverlapping(data.as_ptr(), entry.add(mem::size_of::<usize>()), data.len());
+            }
+            control.write_index.store((write_idx + 1) % capacity, Ordering::Release);
+            control.count.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    impl Drop for TestProducer {
+        fn drop(&mut self) {
+            unsafe {
+                libc::munmap(self.ptr as *mut libc::c_void, self.size);
+                libc::close(self.fd);
+                libc::shm_unlink(self.name.as_ptr());
+            }
+        }
+    }
+
+    #[test]
+    fn test_dequeue_returns_every_entry_in_order() {
+        let name = format!("/gwrs-test-dequeue-{}", std::process::id());
+        let producer = TestProducer::create(&name, 8);
+        let consumer = SharedMemoryConsumer::open(&name, producer.size).unwrap();
+
+        // Two rounds so the ring wraps around its capacity of 8
+        let mut next = 0;
+        for round in [5, 7] {
+            for i in next..next + round {
+                producer.enqueue(format!("entry-{}", i).as_bytes());
+            }
+            assert_eq!(consumer.queue_size(), round);
+
+            for i in next..next + round {
+                let data = consumer.dequeue().unwrap().expect("entry missing");
+                assert_eq!(String::from_utf8(data).unwrap(), format!("entry-{}", i));
+            }
+            next += round;
+
+            assert_eq!(consumer.queue_size(), 0);
+            assert!(consumer.dequeue().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_dequeue_item_does_not_underflow() {
+        let control = QueueControl::new(4);
+        control.dequeue_item(0, 4);
+        assert_eq!(control.count.load(Ordering::Acquire), 0);
+        assert_eq!(control.read_index.load(Ordering::Acquire), 1);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::module::{
     memory_log::core::{LogConsumer, GATEWAY_LOGGER_NAME, MAX_MEMORY_SIZE},
-    temporary_log::{tlog_gateway, TemporaryLog},
+    temporary_log::{ConnType, tlog_gateway, TemporaryLog, WsFrameCounts},
 };
 use std::time::{Duration, Instant};
 
@@ -11,6 +11,9 @@ pub fn listen() {
     let mut log_consumer = LogConsumer::new(GATEWAY_LOGGER_NAME, MAX_MEMORY_SIZE)
         .expect("Failed to open shared memory");
 
+    // No consumer checkpoint: while `process_batch` is disabled nothing is
+    // flushed, so there is no position worth resuming from
+
     // Pre-allocate batch with capacity
     let mut batch = Vec::with_capacity(BATCH_SIZE);
     const BATCH_SIZE: usize = 100;
@@ -43,7 +46,7 @@ pub fn listen() {
                 match LogConsumer::new(GATEWAY_LOGGER_NAME, MAX_MEMORY_SIZE) {
                     Ok(new_consumer) => {
                         log_consumer = new_consumer;
-                        consecutive_empty = 0;
+                                            consecutive_empty = 0;
                     }
                     Err(e) => {
                         log::error!("Failed to recreate log consumer during health check: {}", e);
@@ -104,7 +107,7 @@ pub fn listen() {
 // Extract batch processing to a separate function
 fn process_batch(batch: &Vec<(chrono::DateTime<chrono::Utc>, u8, String)>) {
     // Replace with actual batch processing logic
-    for (datetime, _level, message) in batch {
+    for (datetime, level, message) in batch {
         // Process each log entry (commented out to avoid unnecessary prints)
         // Uncomment if processing is actually needed
         // | ID:17936787362358910377, TYPE:REQ, CONN:HTTP, SIZE:0, STAT:N/A, SRC:127.0.0.1:42615, DST:127.0.0.1:3004 |
@@ -131,7 +134,6 @@ fn process_batch(batch: &Vec<(chrono::DateTime<chrono::Utc>, u8, String)>) {
         // Initialize variables to store extracted values
         let mut conn_id = String::new();
         let mut msg_type = "";
-        let mut conn_type = "";
         let mut size: u64 = 0;
         let mut status = "";
         let mut source = String::new();
@@ -149,7 +151,6 @@ fn process_batch(batch: &Vec<(chrono::DateTime<chrono::Utc>, u8, String)>) {
                 match *key {
                     "ID" => conn_id = value.to_string(),
                     "TYPE" => msg_type = value,
-                    "CONN" => conn_type = value,
                     "SIZE" => size = value.parse().unwrap_or(0),
                     "STAT" => status = value,
                     "SRC" => source = value.to_string(),
@@ -177,13 +178,15 @@ fn process_batch(batch: &Vec<(chrono::DateTime<chrono::Utc>, u8, String)>) {
         let log_entry = TemporaryLog {
             date_time: datetime.clone(),
             conn_id,
-            conn_type: conn_type.to_string(),
+            conn_type: ConnType::from_log_type(msg_type),
             peer: (source, destination),
             status_code,
             conn_req,
             conn_res,
             bytes_in: bytes_in as i32,
             bytes_out: bytes_out as i32,
+            level: *level,
+            ws_frames: WsFrameCounts::default(),
         };
 
         let _ = tlog_gateway::append_data(log_entry);
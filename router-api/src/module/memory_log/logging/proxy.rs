@@ -1,6 +1,7 @@
 use crate::module::{
+    memory_log::checkpoint::ConsumerCheckpoint,
     memory_log::core::{LogConsumer, MAX_MEMORY_SIZE, PROXY_LOGGER_NAME},
-    temporary_log::{tlog_proxy, TemporaryLog},
+    temporary_log::{ConnType, tlog_proxy, TemporaryLog, WsFrameCounts},
 };
 use std::time::{Duration, Instant};
 
@@ -12,6 +13,18 @@ pub fn listen() {
     let mut log_consumer =
         LogConsumer::new(PROXY_LOGGER_NAME, MAX_MEMORY_SIZE).expect("Failed to open shared memory");
 
+    // Resume from the last flushed position
+    let mut checkpoint = ConsumerCheckpoint::load(PROXY_LOGGER_NAME);
+    checkpoint.reconcile(
+        tlog_proxy::latest_timestamp()
+            .ok()
+            .flatten()
+            .map(|latest| latest.timestamp() as u64),
+    );
+    if checkpoint.ring_was_reset(log_consumer.read_index()) {
+        log::info!("Proxy log ring was reset, resuming from its start");
+    }
+
     // Pre-allocate batch with capacity
     let mut batch = Vec::with_capacity(BATCH_SIZE);
     const BATCH_SIZE: usize = 100;
@@ -39,6 +52,9 @@ pub fn listen() {
                 match LogConsumer::new(PROXY_LOGGER_NAME, MAX_MEMORY_SIZE) {
                     Ok(new_consumer) => {
                         log_consumer = new_consumer;
+                                            if checkpoint.ring_was_reset(log_consumer.read_index()) {
+                            log::info!("Proxy log ring was reset, resuming from its start");
+                        }
                         consecutive_empty = 0;
                     }
                     Err(e) => {
@@ -57,6 +73,11 @@ pub fn listen() {
             Ok(Some((timestamp, level, message))) => {
                 consecutive_empty = 0;
 
+                // Skip entries flushed before a restart
+                if !checkpoint.accepts(timestamp) {
+                    continue;
+                }
+
                 // Convert timestamp once
                 let datetime = chrono::DateTime::from_timestamp(timestamp as i64, 0)
                     .unwrap_or(chrono::DateTime::UNIX_EPOCH);
@@ -65,7 +86,8 @@ pub fn listen() {
 
                 // Process full batch
                 if batch.len() >= BATCH_SIZE {
-                    // process_batch(&batch);
+                    process_batch(&batch);
+                    commit_checkpoint(&mut checkpoint, &batch, &log_consumer);
                     batch.clear();
                 }
             }
@@ -73,7 +95,8 @@ pub fn listen() {
                 // Process any remaining logs first before incrementing consecutive_empty
                 if !batch.is_empty() {
                     consecutive_empty = 0; // Reset counter when we process logs
-                    // process_batch(&batch);
+                    process_batch(&batch);
+                    commit_checkpoint(&mut checkpoint, &batch, &log_consumer);
                     batch.clear();
                 }
 
@@ -95,84 +118,179 @@ pub fn listen() {
     }
 }
 
+/// Records a flushed batch in the consumer checkpoint.
+fn commit_checkpoint(
+    checkpoint: &mut ConsumerCheckpoint,
+    batch: &[(chrono::DateTime<chrono::Utc>, u8, String)],
+    consumer: &LogConsumer,
+) {
+    let newest = batch
+        .iter()
+        .map(|(datetime, _, _)| datetime.timestamp() as u64)
+        .max()
+        .unwrap_or(0);
+    if let Err(e) = checkpoint.commit(newest, consumer.read_index()) {
+        log::warn!("Failed to write proxy log checkpoint: {}", e);
+    }
+}
+
 // Extract batch processing to a separate function
-fn process_batch(batch: &Vec<(chrono::DateTime<chrono::Utc>, u8, String)>) {
-    // Replace with actual batch processing logic
-    for (datetime, _level, message) in batch {
-        // This log line is active - if you're not seeing this, there might be a log level issue
-        // or no logs are being produced for the proxy
-        // ID:14538016447660569718, TYPE:UPSTREAM, CONN:TCP, SIZE:195, STAT:UP@ON, SRC:0.0.0.0:3020, DST:127.0.0.1:3004
-        // log::info!("PXY : Processing: {} - {}: {}", datetime, level, message);
-
-        let message_inner = message.as_str();
-        let message_inner = message_inner.split('|').collect::<Vec<&str>>();
-        
-        let message_inner = {
-            if message_inner.len() > 1 {
-                message_inner[1]
-            } else {
-                continue; // Skip if the message format is not as expected
-            }
-        };
-
-        // Initialize variables to store extracted values
-        let mut conn_id = String::new();
-        let mut msg_type = "";
-        let mut conn_type = "";
-        let mut size: u64 = 0;
-        let mut status = "";
-        let mut source = String::new();
-        let mut destination = String::new();
-
-        // Direct field extraction
-        for field in message_inner.split(',') {
-            let field = field.trim();
-
-            if let Some(colon_idx) = field.find(':') {
-                let key = &field[..colon_idx].trim();
-                let value = &field[colon_idx + 1..].trim();
-
-                // Direct field matching without HashMap
-                match *key {
-                    "ID" => conn_id = value.to_string(),
-                    "TYPE" => msg_type = value,
-                    "CONN" => conn_type = value,
-                    "SIZE" => size = value.parse().unwrap_or(0),
-                    "STAT" => status = value,
-                    "SRC" => source = value.to_string(),
-                    "DST" => destination = value.to_string(),
-                    _ => {} // Ignore unknown fields
-                }
+fn process_batch(batch: &[(chrono::DateTime<chrono::Utc>, u8, String)]) {
+    for (datetime, level, message) in batch {
+        if let Some(log_entry) = parse_log_line(*datetime, *level, message) {
+            let _ = tlog_proxy::append_data(log_entry);
+        }
+    }
+}
+
+/// Converts one `[PXY]` line into a `TemporaryLog`.
+///
+/// Connection lifecycle lines carry the metrics: `OPEN` becomes `conn_req=1` and
+/// `CLOSE` becomes `conn_res=1` with the final `IN`/`OUT` byte counts, both under
+/// the same connection ID. Per-chunk `DOWNSTREAM`/`UPSTREAM` lines are kept for
+/// activity counts but carry no request/response or byte values, so totals are
+/// not counted twice. WebSocket CLOSE lines may add `WS_*` message and frame
+/// counts when the core counts frames.
+///
+/// # Example line
+///
+/// `[PXY] | ID:14538016447660569718, TYPE:CLOSE, CONN:TCP, SIZE:390, STAT:200, SRC:0.0.0.0:3020, DST:127.0.0.1:3004, IN:195, OUT:195, COMMENT:- |`
+fn parse_log_line(
+    datetime: chrono::DateTime<chrono::Utc>,
+    level: u8,
+    message: &str,
+) -> Option<TemporaryLog> {
+    let message_inner = message.split('|').nth(1)?;
+
+    // Initialize variables to store extracted values
+    let mut conn_id = String::new();
+    let mut msg_type = "";
+    let mut status = "";
+    let mut source = String::new();
+    let mut destination = String::new();
+    let mut bytes_in: i32 = 0;
+    let mut bytes_out: i32 = 0;
+    let mut ws_frames = WsFrameCounts::default();
+
+    // Direct field extraction
+    for field in message_inner.split(',') {
+        let field = field.trim();
+
+        if let Some(colon_idx) = field.find(':') {
+            let key = field[..colon_idx].trim();
+            let value = field[colon_idx + 1..].trim();
+
+            // Direct field matching without HashMap
+            match key {
+                "ID" => conn_id = value.to_string(),
+                "TYPE" => msg_type = value,
+                "STAT" => status = value,
+                "SRC" => source = value.to_string(),
+                "DST" => destination = value.to_string(),
+                "IN" => bytes_in = value.parse().unwrap_or(0),
+                "OUT" => bytes_out = value.parse().unwrap_or(0),
+                "WS_MSG_IN" => ws_frames.msg_in = value.parse().unwrap_or(0),
+                "WS_MSG_OUT" => ws_frames.msg_out = value.parse().unwrap_or(0),
+                "WS_TEXT" => ws_frames.text = value.parse().unwrap_or(0),
+                "WS_BINARY" => ws_frames.binary = value.parse().unwrap_or(0),
+                "WS_PING" => ws_frames.ping = value.parse().unwrap_or(0),
+                "WS_PONG" => ws_frames.pong = value.parse().unwrap_or(0),
+                "WS_CLOSE" => ws_frames.close = value.parse().unwrap_or(0),
+                _ => {} // Ignore unknown fields
             }
         }
+    }
+
+    // Only lifecycle lines mark a connection opened or closed
+    let (conn_req, conn_res, bytes_in, bytes_out) = match msg_type {
+        "OPEN" => (1, 0, 0, 0),
+        "CLOSE" => (0, 1, bytes_in, bytes_out),
+        _ => (0, 0, 0, 0),
+    };
+    if msg_type != "CLOSE" {
+        ws_frames = WsFrameCounts::default();
+    }
+
+    // Convert status to numeric code
+    let status_code = if status == "N/A" {
+        0
+    } else {
+        status.parse::<i32>().unwrap_or(0)
+    };
+
+    Some(TemporaryLog {
+        date_time: datetime,
+        conn_id,
+        conn_type: ConnType::from_log_type(msg_type),
+        peer: (source, destination),
+        status_code,
+        conn_req,
+        conn_res,
+        bytes_in,
+        bytes_out,
+        level,
+        ws_frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completed_connection_yields_matching_records() {
+        let now = chrono::Utc::now();
+        let open = parse_log_line(
+            now,
+            2,
+            "[PXY] | ID:42, TYPE:OPEN, CONN:TCP, SIZE:0, STAT:N/A, SRC:0.0.0.0:3020, DST:127.0.0.1:3004, COMMENT:- |",
+        )
+        .unwrap();
+        let chunk = parse_log_line(
+            now,
+            2,
+            "[PXY] | ID:42, TYPE:UPSTREAM[ON], CONN:TCP, SIZE:195, STAT:200, SRC:0.0.0.0:3020, DST:127.0.0.1:3004, COMMENT:- |",
+        )
+        .unwrap();
+        let close = parse_log_line(
+            now,
+            2,
+            "[PXY] | ID:42, TYPE:CLOSE, CONN:TCP, SIZE:390, STAT:200, SRC:0.0.0.0:3020, DST:127.0.0.1:3004, IN:150, OUT:240, COMMENT:- |",
+        )
+        .unwrap();
+
+        assert_eq!(open.conn_id, close.conn_id);
+        assert_eq!(open.peer, close.peer);
+        assert_eq!((open.conn_req, open.conn_res), (1, 0));
+        assert_eq!((close.conn_req, close.conn_res), (0, 1));
+        assert_eq!(
+            (open.conn_type, chunk.conn_type, close.conn_type),
+            (ConnType::Open, ConnType::UpstreamRes, ConnType::Close)
+        );
+        assert_eq!((close.bytes_in, close.bytes_out), (150, 240));
+        assert_eq!(close.status_code, 200);
+        assert_eq!(close.level, 2);
+
+        // Chunk lines don't add to the byte totals of the close record
+        assert_eq!((chunk.conn_req, chunk.conn_res, chunk.bytes_in, chunk.bytes_out), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_websocket_close_carries_frame_counts() {
+        let close = parse_log_line(
+            chrono::Utc::now(),
+            2,
+            "[PXY] | ID:7, TYPE:CLOSE, CONN:WS, SIZE:90, STAT:101, SRC:0.0.0.0:3020, DST:127.0.0.1:3004, IN:40, OUT:50, WS_MSG_IN:3, WS_MSG_OUT:2, WS_TEXT:4, WS_BINARY:1, WS_PING:1, WS_PONG:1, WS_CLOSE:2, COMMENT:- |",
+        )
+        .unwrap();
+        assert_eq!(
+            close.ws_frames,
+            WsFrameCounts { msg_in: 3, msg_out: 2, text: 4, binary: 1, ping: 1, pong: 1, close: 2 }
+        );
+    }
 
-        // Determine request vs response
-        let (conn_req, conn_res, bytes_in, bytes_out) = match msg_type {
-            "DOWNSTREAM" => (1, 0, size, 0),
-            "UPSTREAM" => (0, 1, 0, size),
-            _ => (0, 0, 0, 0),
-        };
-
-        // Convert status to numeric code
-        let status_code = if status == "N/A" {
-            0
-        } else {
-            status.parse::<i32>().unwrap_or(0)
-        };
-
-        // Create and append the TemporaryLog
-        let log_entry = TemporaryLog {
-            date_time: datetime.clone(),
-            conn_id,
-            conn_type: conn_type.to_string(),
-            peer: (source, destination),
-            status_code,
-            conn_req,
-            conn_res,
-            bytes_in: bytes_in as i32,
-            bytes_out: bytes_out as i32,
-        };
-
-        let _ = tlog_proxy::append_data(log_entry);
+    #[test]
+    fn test_malformed_line_is_skipped() {
+        assert!(parse_log_line(chrono::Utc::now(), 2, "no separators here").is_none());
     }
 }
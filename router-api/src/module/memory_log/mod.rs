@@ -1,5 +1,74 @@
 // -- lib.rs --
 // A raw implementation of shared memory in Rust using direct system calls
+mod checkpoint;
 mod core;
 mod logging;
 pub mod spawner;
+
+use serde::Serialize;
+
+use self::core::{
+    LogConsumer, GATEWAY_LOGGER_NAME, LEVEL_DEBUG, LEVEL_ERROR, LEVEL_INFO, LEVEL_TRACE,
+    LEVEL_WARN, MAX_MEMORY_SIZE, PROXY_LOGGER_NAME,
+};
+
+/// Parses a level name (`trace`, `debug`, `info`, `warn`, `error`) into the
+/// level byte carried by shared-memory log entries.
+pub fn parse_level(name: &str) -> Option<u8> {
+    match name.trim().to_lowercase().as_str() {
+        "trace" => Some(LEVEL_TRACE),
+        "debug" => Some(LEVEL_DEBUG),
+        "info" => Some(LEVEL_INFO),
+        "warn" | "warning" => Some(LEVEL_WARN),
+        "error" => Some(LEVEL_ERROR),
+        _ => None,
+    }
+}
+
+/// Name of a level byte, the inverse of [`parse_level`].
+pub fn level_name(level: u8) -> &'static str {
+    match level {
+        LEVEL_TRACE => "trace",
+        LEVEL_DEBUG => "debug",
+        LEVEL_INFO => "info",
+        LEVEL_WARN => "warn",
+        _ => "error",
+    }
+}
+
+/// Checks that the proxy and gateway shared-memory queues can be attached to.
+///
+/// A temporary consumer is opened for each queue and dropped right away, which
+/// only unmaps the segment; the queues themselves are left untouched.
+pub fn check_attachable() -> Result<(), String> {
+    for name in [PROXY_LOGGER_NAME, GATEWAY_LOGGER_NAME] {
+        if let Err(e) = LogConsumer::new(name, MAX_MEMORY_SIZE) {
+            return Err(format!("{}: {}", name, e));
+        }
+    }
+    Ok(())
+}
+
+/// Fill level of a shared-memory log queue and the entries it dropped.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RingStatus {
+    pub queued: usize,
+    pub capacity: usize,
+    /// Entries lost because the queue was full, since the queue was created
+    pub dropped: usize,
+}
+
+/// Reads the status of the proxy and gateway queues, `None` for a queue that
+/// can't be attached to.
+pub fn ring_status() -> (Option<RingStatus>, Option<RingStatus>) {
+    let status = |name: &str| {
+        LogConsumer::new(name, MAX_MEMORY_SIZE)
+            .ok()
+            .map(|consumer| RingStatus {
+                queued: consumer.queue_size(),
+                capacity: consumer.capacity(),
+                dropped: consumer.overflow_count(),
+            })
+    };
+    (status(PROXY_LOGGER_NAME), status(GATEWAY_LOGGER_NAME))
+}
@@ -0,0 +1,379 @@
+//! # Schema Migrations
+//!
+//! Ordered, versioned steps bringing `core.sqlite` to the current schema. They
+//! run once at startup through [`run`], before any request is served; the
+//! applied version is tracked in the `schema_version` table.
+//!
+//! Version 1 is the schema from before versioning, created with
+//! `CREATE TABLE IF NOT EXISTS` so existing databases keep their data. Every
+//! later step adds to it and must be idempotent, since databases that predate
+//! versioning may already contain columns added by the old ad-hoc upgrades.
+//!
+//! To change the schema, append a step with the next version number. Never edit
+//! or reorder steps that have been released.
+//!
+//! The migrations are the only code creating or altering tables. Query modules
+//! check the columns they use with [`ensure_table`], which never drops anything.
+
+use rusqlite::Connection;
+
+use crate::module::database::{
+    add_column_if_missing, get_connection, DatabaseError, DatabaseResult, Migration,
+};
+
+/// All migrations, ordered by version
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create users, proxies, proxy_domains, gateway_nodes and gateways",
+        up: create_base_tables,
+    },
+    Migration {
+        version: 2,
+        description: "add gateways.strip_prefix",
+        up: |conn| add_column_if_missing(conn, "gateways", "strip_prefix", "TEXT"),
+    },
+    Migration {
+        version: 3,
+        description: "add proxies.redirect_to_https and proxies.redirect_https_port",
+        up: |conn| {
+            add_column_if_missing(conn, "proxies", "redirect_to_https", "BOOLEAN NOT NULL DEFAULT 0")?;
+            add_column_if_missing(conn, "proxies", "redirect_https_port", "INTEGER")
+        },
+    },
+    Migration {
+        version: 4,
+        description: "add deleted_at to proxies and gateways",
+        up: |conn| {
+            add_column_if_missing(conn, "proxies", "deleted_at", "INTEGER")?;
+            add_column_if_missing(conn, "gateways", "deleted_at", "INTEGER")
+        },
+    },
+    Migration {
+        version: 5,
+        description: "add proxies.tcp_nodelay, proxies.keepalive_secs and proxies.keepalive_count",
+        up: |conn| {
+            add_column_if_missing(conn, "proxies", "tcp_nodelay", "BOOLEAN NOT NULL DEFAULT 1")?;
+            add_column_if_missing(conn, "proxies", "keepalive_secs", "INTEGER")?;
+            add_column_if_missing(conn, "proxies", "keepalive_count", "INTEGER")
+        },
+    },
+    Migration {
+        version: 6,
+        description: "add proxies.buffer_size",
+        up: |conn| add_column_if_missing(conn, "proxies", "buffer_size", "INTEGER"),
+    },
+    Migration {
+        version: 7,
+        description: "add gateway_nodes.compress",
+        up: |conn| add_column_if_missing(conn, "gateway_nodes", "compress", "BOOLEAN NOT NULL DEFAULT 0"),
+    },
+    Migration {
+        version: 8,
+        description: "add gateway_nodes.allow_cidrs and gateway_nodes.deny_cidrs",
+        up: |conn| {
+            add_column_if_missing(conn, "gateway_nodes", "allow_cidrs", "TEXT")?;
+            add_column_if_missing(conn, "gateway_nodes", "deny_cidrs", "TEXT")
+        },
+    },
+    Migration {
+        version: 9,
+        description: "add config_version with triggers bumping it on every config write",
+        up: create_config_version,
+    },
+    Migration {
+        version: 10,
+        description: "add upstream timeouts to gateway_nodes",
+        up: |conn| {
+            add_column_if_missing(conn, "gateway_nodes", "connect_timeout_secs", "INTEGER")?;
+            add_column_if_missing(conn, "gateway_nodes", "header_timeout_secs", "INTEGER")?;
+            add_column_if_missing(conn, "gateway_nodes", "total_timeout_secs", "INTEGER")
+        },
+    },
+    Migration {
+        version: 11,
+        description: "add maintenance to proxies and gateway_nodes",
+        up: |conn| {
+            add_column_if_missing(conn, "proxies", "maintenance", "BOOLEAN NOT NULL DEFAULT 0")?;
+            add_column_if_missing(conn, "gateway_nodes", "maintenance", "BOOLEAN NOT NULL DEFAULT 0")
+        },
+    },
+    Migration {
+        version: 12,
+        description: "add gateways.sticky",
+        up: |conn| add_column_if_missing(conn, "gateways", "sticky", "TEXT"),
+    },
+    Migration {
+        version: 13,
+        description: "add redaction to gateway_nodes",
+        up: |conn| {
+            add_column_if_missing(conn, "gateway_nodes", "redact_fields", "TEXT")?;
+            add_column_if_missing(conn, "gateway_nodes", "redact_mode", "TEXT")
+        },
+    },
+    Migration {
+        version: 14,
+        description: "add gateway_nodes.route_script",
+        up: |conn| add_column_if_missing(conn, "gateway_nodes", "route_script", "TEXT"),
+    },
+    Migration {
+        version: 15,
+        description: "add gateways.split",
+        up: |conn| add_column_if_missing(conn, "gateways", "split", "TEXT"),
+    },
+    Migration {
+        version: 16,
+        description: "add TLS policy to proxies",
+        up: |conn| {
+            add_column_if_missing(conn, "proxies", "tls_min_version", "TEXT")?;
+            add_column_if_missing(conn, "proxies", "tls_ciphers", "TEXT")?;
+            add_column_if_missing(conn, "proxies", "tls_require_sni", "BOOLEAN NOT NULL DEFAULT 0")
+        },
+    },
+    Migration {
+        version: 17,
+        description: "add CORS to gateway_nodes",
+        up: |conn| {
+            add_column_if_missing(conn, "gateway_nodes", "cors_origins", "TEXT")?;
+            add_column_if_missing(conn, "gateway_nodes", "cors_methods", "TEXT")?;
+            add_column_if_missing(conn, "gateway_nodes", "cors_headers", "TEXT")
+        },
+    },
+    Migration {
+        version: 18,
+        description: "add server header policy to gateway_nodes",
+        up: |conn| {
+            add_column_if_missing(conn, "gateway_nodes", "server_header", "TEXT")?;
+            add_column_if_missing(conn, "gateway_nodes", "strip_server_headers", "BOOLEAN NOT NULL DEFAULT 0")
+        },
+    },
+    Migration {
+        version: 19,
+        description: "add proxy_domains.acme and acme_accounts",
+        up: |conn| {
+            add_column_if_missing(conn, "proxy_domains", "acme", "BOOLEAN NOT NULL DEFAULT 0")?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS acme_accounts (
+                    directory TEXT PRIMARY KEY,
+                    credentials TEXT NOT NULL
+                )",
+            )
+        },
+    },
+    Migration {
+        version: 20,
+        description: "add gateways.mirror_target",
+        up: |conn| add_column_if_missing(conn, "gateways", "mirror_target", "TEXT"),
+    },
+    Migration {
+        version: 21,
+        description: "move TLS columns of pre-domain proxies to proxy_domains",
+        up: move_legacy_proxy_tls,
+    },
+    Migration {
+        version: 22,
+        description: "add proxies.fallback",
+        up: |conn| add_column_if_missing(conn, "proxies", "fallback", "TEXT"),
+    },
+    Migration {
+        version: 23,
+        description: "add proxies.tag and permissions",
+        up: |conn| {
+            add_column_if_missing(conn, "proxies", "tag", "TEXT")?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS permissions (
+                    user_id TEXT NOT NULL,
+                    tag TEXT NOT NULL,
+                    scope TEXT NOT NULL CHECK(scope IN ('read', 'write')),
+                    PRIMARY KEY (user_id, tag),
+                    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+                )",
+            )
+        },
+    },
+];
+
+fn create_base_tables(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS users (
+            id TEXT PRIMARY KEY,
+            username TEXT NOT NULL UNIQUE,
+            email TEXT NOT NULL UNIQUE,
+            password_hash TEXT NOT NULL,
+            role TEXT NOT NULL CHECK(role IN ('admin', 'staff', 'user')),
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS proxies (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            addr_listen TEXT NOT NULL,
+            addr_target TEXT NOT NULL,
+            high_speed BOOLEAN NOT NULL DEFAULT 0,
+            high_speed_addr TEXT,
+            high_speed_gwid TEXT
+        );
+        CREATE TABLE IF NOT EXISTS proxy_domains (
+            id TEXT PRIMARY KEY,
+            proxy_id TEXT NOT NULL,
+            tls BOOLEAN NOT NULL DEFAULT 0,
+            tls_pem TEXT,
+            tls_key TEXT,
+            sni TEXT
+        );
+        CREATE TABLE IF NOT EXISTS gateway_nodes (
+            id TEXT PRIMARY KEY,
+            proxy_id TEXT NOT NULL,
+            domain_id TEXT,
+            title TEXT NOT NULL,
+            alt_target TEXT NOT NULL,
+            priority INTEGER NOT NULL DEFAULT 100,
+            FOREIGN KEY(proxy_id) REFERENCES proxies(id),
+            FOREIGN KEY(domain_id) REFERENCES proxy_domains(id)
+        );
+        CREATE TABLE IF NOT EXISTS gateways (
+            id TEXT PRIMARY KEY,
+            gwnode_id TEXT NOT NULL,
+            pattern TEXT NOT NULL,
+            target TEXT NOT NULL,
+            priority INTEGER NOT NULL,
+            FOREIGN KEY(gwnode_id) REFERENCES gateway_nodes(id)
+        );",
+    )
+}
+
+fn column_exists(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        rusqlite::params![table, column],
+        |row| row.get::<_, i64>(0).map(|count| count > 0),
+    )
+}
+
+/// Proxies created before `proxy_domains` existed carried their certificate in
+/// `tls`, `tls_pem`, `tls_key` and `sni`. Those become a domain of the proxy.
+fn move_legacy_proxy_tls(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "proxies", "high_speed_gwid", "TEXT")?;
+    if !column_exists(conn, "proxies", "tls")? {
+        return Ok(());
+    }
+
+    conn.execute(
+        "INSERT INTO proxy_domains (id, proxy_id, tls, tls_pem, tls_key, sni)
+         SELECT hex(randomblob(16)), id, tls, tls_pem, tls_key, sni
+         FROM proxies WHERE tls = 1",
+        [],
+    )?;
+    for column in ["tls", "tls_pem", "tls_key", "sni"] {
+        if column_exists(conn, "proxies", column)? {
+            conn.execute(&format!("ALTER TABLE proxies DROP COLUMN {}", column), [])?;
+        }
+    }
+    Ok(())
+}
+
+/// Tables whose writes bump the config version
+const VERSIONED_TABLES: &[&str] = &["proxies", "proxy_domains", "gateway_nodes", "gateways"];
+
+/// Creates `config_version` and the triggers bumping it on any write to a config table.
+///
+/// Idempotent, the triggers are only created where missing.
+pub fn create_config_version(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS config_version (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            version INTEGER NOT NULL
+        );
+        INSERT OR IGNORE INTO config_version (id, version) VALUES (1, 0);",
+    )?;
+    for table in VERSIONED_TABLES {
+        for op in ["insert", "update", "delete"] {
+            conn.execute_batch(&format!(
+                "CREATE TRIGGER IF NOT EXISTS config_version_{table}_{op} AFTER {op} ON {table}
+                 BEGIN
+                     UPDATE config_version SET version = version + 1 WHERE id = 1;
+                 END;",
+                table = table,
+                op = op,
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Brings the core database to the latest schema version.
+pub fn run() -> DatabaseResult<u32> {
+    let version = get_connection()?.migrate(MIGRATIONS)?;
+    log::info!("Database schema is at version {}", version);
+    Ok(version)
+}
+
+/// Makes sure `table` exists with `columns`, applying pending migrations if it doesn't.
+///
+/// # Errors
+///
+/// Fails when the table still doesn't match after migrating; the table is left
+/// as it is so no rows are lost.
+pub fn ensure_table(table: &str, columns: &[&str]) -> DatabaseResult<()> {
+    let db = get_connection()?;
+    if db.table_exists_with_columns(table, columns)? {
+        return Ok(());
+    }
+
+    run()?;
+    if db.table_exists_with_columns(table, columns)? {
+        Ok(())
+    } else {
+        Err(DatabaseError::from_msg(format!(
+            "Table {} is missing columns of the current schema",
+            table
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_proxy_tls_moves_to_domains() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE proxies (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                addr_listen TEXT NOT NULL,
+                addr_target TEXT NOT NULL,
+                high_speed BOOLEAN NOT NULL DEFAULT 0,
+                high_speed_addr TEXT,
+                tls BOOLEAN NOT NULL DEFAULT 0,
+                tls_pem TEXT,
+                tls_key TEXT,
+                sni TEXT
+            );
+            INSERT INTO proxies (id, title, addr_listen, addr_target, tls, tls_pem, tls_key, sni)
+            VALUES ('secure', 'a', '0.0.0.0:443', '127.0.0.1:1', 1, 'pem', 'key', 'example.com'),
+                   ('plain', 'b', '0.0.0.0:80', '127.0.0.1:2', 0, NULL, NULL, NULL);",
+        )
+        .unwrap();
+        create_base_tables(&conn).unwrap();
+
+        move_legacy_proxy_tls(&conn).unwrap();
+        // Idempotent like every step
+        move_legacy_proxy_tls(&conn).unwrap();
+
+        let proxies: i64 = conn
+            .query_row("SELECT COUNT(*) FROM proxies", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(proxies, 2);
+        assert!(!column_exists(&conn, "proxies", "tls").unwrap());
+        assert!(column_exists(&conn, "proxies", "high_speed_gwid").unwrap());
+
+        let domain: (String, String, String) = conn
+            .query_row("SELECT proxy_id, tls_pem, sni FROM proxy_domains", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(domain, ("secure".to_string(), "pem".to_string(), "example.com".to_string()));
+    }
+}
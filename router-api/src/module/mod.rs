@@ -2,4 +2,14 @@ pub mod memory_log;
 pub mod database;
 pub mod database_log;
 pub mod temporary_log;
-pub mod httpc;
\ No newline at end of file
+pub mod httpc;
+pub mod netaddr;
+pub mod request_id;
+
+pub mod cert_expiry;
+pub mod acme;
+pub mod trash_purge;
+pub mod migrations;
+pub mod core_sync;
+pub mod config_version;
+pub mod api_tls;
\ No newline at end of file
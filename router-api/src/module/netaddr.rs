@@ -0,0 +1,237 @@
+//! Helpers for parsing `host:port` strings that may carry bracketed IPv6 literals.
+//!
+//! Proxy listen addresses and gateway node targets are stored as plain strings, so
+//! both `127.0.0.1:8080` and `[::1]:8080` need to round-trip through validation
+//! without being split on the wrong colon.
+//!
+//! Speed mode proxies may also listen on or forward to a Unix domain socket,
+//! written as `unix:/path/to.sock`.
+//!
+//! Gateway node access lists hold networks in CIDR notation (`10.0.0.0/8`).
+//!
+//! Gateway node targets may be written as `http://host[:port]` and are stored
+//! as the `host:port` the core connects to.
+
+use std::net::{IpAddr, Ipv6Addr};
+
+/// Splits an address into its host and port parts.
+///
+/// Bracketed IPv6 literals (`[::1]:8080`) are returned without the brackets.
+/// Bare IPv6 literals without brackets are rejected since the port boundary
+/// would be ambiguous.
+///
+/// # Returns
+///
+/// `Some((host, port))` when the address is well formed, `None` otherwise.
+pub fn split_host_port(addr: &str) -> Option<(&str, u16)> {
+    let (host, port) = if let Some(rest) = addr.strip_prefix('[') {
+        let (host, port) = rest.split_once("]:")?;
+        host.parse::<Ipv6Addr>().ok()?;
+        (host, port)
+    } else {
+        let (host, port) = addr.rsplit_once(':')?;
+        if host.contains(':') {
+            return None;
+        }
+        (host, port)
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+
+    let port = port.parse::<u16>().ok()?;
+    Some((host, port))
+}
+
+/// Returns the socket path of a `unix:/path/to.sock` address.
+///
+/// Only absolute paths are accepted, since the core binds them as given.
+pub fn unix_socket_path(addr: &str) -> Option<&str> {
+    addr.strip_prefix("unix:")
+        .filter(|path| path.len() > 1 && path.starts_with('/'))
+}
+
+/// Joins a host and port, wrapping IPv6 literals in brackets.
+pub fn join_host_port(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Whether `host` is an IP literal or a DNS name made of valid labels.
+fn is_valid_host(host: &str) -> bool {
+    if host.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+    host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Validates a gateway node target and returns it as the core expects it.
+///
+/// Accepts `host:port`, `http://host[:port][/]` (port 80 by default) and
+/// `unix:/path` sockets. Hostnames are lowercased. The core talks plain HTTP
+/// to its targets, so `https://` is rejected instead of silently never
+/// connecting, as are paths, credentials and other schemes.
+///
+/// # Returns
+///
+/// `host:port` with IPv6 hosts bracketed, or the unchanged `unix:` address.
+pub fn normalize_target(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if unix_socket_path(value).is_some() {
+        return Ok(value.to_string());
+    }
+
+    let (authority, default_port) = match value.split_once("://") {
+        Some((scheme, rest)) => match scheme.to_ascii_lowercase().as_str() {
+            "http" => (rest.strip_suffix('/').unwrap_or(rest), Some(80)),
+            "https" => {
+                return Err(format!(
+                    "'{}' uses https, targets are reached over plain HTTP, use http:// or host:port",
+                    value
+                ))
+            }
+            _ => return Err(format!("'{}' has an unsupported scheme, use http:// or host:port", value)),
+        },
+        None => (value, None),
+    };
+    if authority.contains(['/', '?', '#']) {
+        return Err(format!("'{}' must not contain a path, rewrite paths with the gateway rules", value));
+    }
+    if authority.contains('@') {
+        return Err(format!("'{}' must not contain credentials", value));
+    }
+
+    let (host, port) = match (split_host_port(authority), default_port) {
+        (Some((host, port)), _) => (host, port),
+        (None, Some(port)) => {
+            let host = authority
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+                .filter(|host| host.parse::<Ipv6Addr>().is_ok())
+                .unwrap_or(authority);
+            (host, port)
+        }
+        (None, None) => {
+            return Err(format!("'{}' is not a valid target, expected host:port or http://host:port", value))
+        }
+    };
+    if port == 0 {
+        return Err(format!("'{}' has port 0", value));
+    }
+    if !is_valid_host(host) {
+        return Err(format!("'{}' has an invalid host '{}'", value, host));
+    }
+    Ok(join_host_port(&host.to_ascii_lowercase(), port))
+}
+
+/// Validates a network in CIDR notation, as the core parses it.
+///
+/// Bare addresses are accepted as a single host and returned with `/32` or
+/// `/128`. Host bits below the prefix are rejected.
+pub fn normalize_cidr(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    let (addr, prefix_len) = match value.split_once('/') {
+        Some((addr, len)) => (addr, Some(len)),
+        None => (value, None),
+    };
+    let addr = addr
+        .parse::<IpAddr>()
+        .map_err(|_| format!("'{}' is not a valid CIDR", value))?;
+    let (bits, width) = match addr {
+        IpAddr::V4(v4) => (u32::from(v4) as u128, 32),
+        IpAddr::V6(v6) => (u128::from(v6), 128),
+    };
+    let prefix_len = match prefix_len {
+        Some(len) => len
+            .parse::<u32>()
+            .ok()
+            .filter(|len| *len <= width)
+            .ok_or_else(|| format!("'{}' has an invalid prefix length", value))?,
+        None => width,
+    };
+    let host_mask = if prefix_len == width { 0 } else { u128::MAX >> (128 - width + prefix_len) };
+    if bits & host_mask != 0 {
+        return Err(format!("'{}' has host bits set below the prefix", value));
+    }
+    Ok(format!("{}/{}", addr, prefix_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_ipv4_and_hostname() {
+        assert_eq!(split_host_port("127.0.0.1:8080"), Some(("127.0.0.1", 8080)));
+        assert_eq!(split_host_port("backend.local:443"), Some(("backend.local", 443)));
+        assert_eq!(split_host_port("127.0.0.1"), None);
+        assert_eq!(split_host_port("127.0.0.1:99999"), None);
+    }
+
+    #[test]
+    fn test_split_ipv6() {
+        assert_eq!(split_host_port("[::1]:0"), Some(("::1", 0)));
+        assert_eq!(split_host_port("[2001:db8::10]:8080"), Some(("2001:db8::10", 8080)));
+        assert_eq!(split_host_port("::1:8080"), None);
+        assert_eq!(split_host_port("[::1]"), None);
+        assert_eq!(split_host_port("[not-an-ip]:80"), None);
+    }
+
+    #[test]
+    fn test_unix_socket_path() {
+        assert_eq!(unix_socket_path("unix:/run/app.sock"), Some("/run/app.sock"));
+        assert_eq!(unix_socket_path("unix:run/app.sock"), None);
+        assert_eq!(unix_socket_path("unix:/"), None);
+        assert_eq!(unix_socket_path("/run/app.sock"), None);
+        assert_eq!(split_host_port("unix:/run/app.sock"), None);
+    }
+
+    #[test]
+    fn test_join_round_trip() {
+        assert_eq!(join_host_port("::1", 0), "[::1]:0");
+        assert_eq!(join_host_port("127.0.0.1", 30099), "127.0.0.1:30099");
+        let (host, port) = split_host_port("[::1]:30099").unwrap();
+        assert_eq!(join_host_port(host, port), "[::1]:30099");
+    }
+
+    #[test]
+    fn test_normalize_target() {
+        assert_eq!(normalize_target("127.0.0.1:8080"), Ok("127.0.0.1:8080".to_string()));
+        assert_eq!(normalize_target("http://Backup-Server.internal:8080/"), Ok("backup-server.internal:8080".to_string()));
+        assert_eq!(normalize_target("HTTP://backend"), Ok("backend:80".to_string()));
+        assert_eq!(normalize_target("http://[::1]"), Ok("[::1]:80".to_string()));
+        assert_eq!(normalize_target(" [::1]:9000 "), Ok("[::1]:9000".to_string()));
+        assert_eq!(normalize_target("unix:/run/app.sock"), Ok("unix:/run/app.sock".to_string()));
+        assert!(normalize_target("https://backend:8443").is_err());
+        assert!(normalize_target("ftp://backend:21").is_err());
+        assert!(normalize_target("http://backend:8080/api").is_err());
+        assert!(normalize_target("http://user:pw@backend:8080").is_err());
+        assert!(normalize_target("backend").is_err());
+        assert!(normalize_target("backend:0").is_err());
+        assert!(normalize_target("back_end:8080").is_err());
+        assert!(normalize_target("-backend:8080").is_err());
+        assert!(normalize_target("http://").is_err());
+    }
+
+    #[test]
+    fn test_normalize_cidr() {
+        assert_eq!(normalize_cidr(" 10.0.0.0/8 "), Ok("10.0.0.0/8".to_string()));
+        assert_eq!(normalize_cidr("192.168.1.10"), Ok("192.168.1.10/32".to_string()));
+        assert_eq!(normalize_cidr("2001:db8::/32"), Ok("2001:db8::/32".to_string()));
+        assert_eq!(normalize_cidr("0.0.0.0/0"), Ok("0.0.0.0/0".to_string()));
+        assert!(normalize_cidr("10.0.0.1/8").is_err());
+        assert!(normalize_cidr("10.0.0.0/33").is_err());
+        assert!(normalize_cidr("internal").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Request ID middleware for correlating API calls with core activity.
+//!
+//! Every request gets an ID, either the one supplied in an inbound `X-Request-Id`
+//! header or a freshly generated UUID. The ID is echoed back in the response,
+//! forwarded to the core by [`HttpC`](crate::module::httpc::HttpC), and ends up
+//! in the `COMMENT` field of the core's `[GWX]`/`[PXY]` log lines, so one ID can
+//! be grepped across the API, the core and the traffic logs.
+
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+use uuid::Uuid;
+
+/// Header carrying the request ID in both directions
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest inbound request ID that is accepted as-is
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: String;
+}
+
+/// Returns the ID of the request being handled on the current task, if any.
+///
+/// Startup code and background tasks run outside a request and get `None`.
+pub fn current() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Accepts an inbound ID only if it is short and made of visible ASCII,
+/// so it can be safely written into headers and log lines.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_graphic() && b != b',' && b != b'|')
+}
+
+/// Middleware that assigns a request ID and exposes it to handlers.
+pub struct RequestId;
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+
+        // Respect an inbound ID when it is well formed, otherwise generate one
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| is_valid(value))
+            .map(|value| value.to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        // Keep a handle on the request so errors from inner middleware
+        // (e.g. authentication failures) still carry the header
+        let http_req = req.request().clone();
+
+        Box::pin(async move {
+            let res = CURRENT_REQUEST_ID
+                .scope(request_id.clone(), srv.call(req))
+                .await;
+
+            let mut res = match res {
+                Ok(res) => res.map_into_left_body(),
+                Err(e) => {
+                    let response = e.error_response();
+                    ServiceResponse::new(http_req, response).map_into_right_body()
+                }
+            };
+
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[test]
+    fn test_inbound_id_validation() {
+        assert!(is_valid("3f0b6c1e-4a57-4f0e-9d1c-1a2b3c4d5e6f"));
+        assert!(is_valid("deploy-42"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("has space"));
+        assert!(!is_valid("a,b"));
+        assert!(!is_valid(&"x".repeat(MAX_REQUEST_ID_LEN + 1)));
+    }
+
+    #[actix_web::test]
+    async fn test_inbound_id_is_echoed_and_visible_to_handlers() {
+        let app = test::init_service(App::new().wrap(RequestId).route(
+            "/",
+            web::get().to(|| async { HttpResponse::Ok().body(current().unwrap_or_default()) }),
+        ))
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((REQUEST_ID_HEADER, "deploy-42"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "deploy-42");
+        assert_eq!(test::read_body(resp).await, "deploy-42");
+    }
+
+    #[actix_web::test]
+    async fn test_id_is_generated_when_missing() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RequestId)
+                .route("/", web::get().to(|| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        let id = resp.headers().get(REQUEST_ID_HEADER).unwrap().to_str().unwrap();
+        assert!(Uuid::parse_str(id).is_ok());
+    }
+}
@@ -5,11 +5,15 @@ use std::collections::{BTreeMap, HashMap, HashSet, VecDeque}; // Added HashSet t
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, Read, Write};
 // use std::os::unix::io::AsRawFd;
-use std::os::fd::IntoRawFd;
-use std::path::PathBuf;
+use std::os::fd::{AsRawFd, IntoRawFd};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::sync::mpsc;
 use thiserror::Error;
 
+use crate::config;
+
 #[derive(Error, Debug)]
 pub enum LogStoreError {
     #[error("IO error: {0}")]
@@ -24,26 +28,192 @@ pub enum LogStoreError {
 
 // Enum for selecting bytes metric type
 #[derive(Debug, Clone, Copy, PartialEq)]
-#[allow(dead_code)]
 pub enum BytesMetric {
     BytesIn,
     BytesOut,
     BytesTotal,
 }
 
+impl BytesMetric {
+    /// Parses the `metric` query parameter, `in`, `out` or `total`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "in" => Some(BytesMetric::BytesIn),
+            "out" => Some(BytesMetric::BytesOut),
+            "total" => Some(BytesMetric::BytesTotal),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)] // Added Debug for logging in append_data
 pub struct TemporaryLog {
     pub date_time: chrono::DateTime<chrono::Utc>,
     pub status_code: i32,
     pub peer: (String, String),
     pub conn_id: String,
-    pub conn_type: String,
+    pub conn_type: ConnType,
     pub conn_req: i8,   // 1 indicate connection in
     pub conn_res: i8,   // 1 indicate connection dirupted
     pub bytes_in: i32,  // bytes in
     pub bytes_out: i32, // bytes out
+    pub level: u8,      // level of the source log line (0 trace .. 4 error)
+    pub ws_frames: WsFrameCounts, // WebSocket counts of a closed proxy connection
+}
+
+/// Kind of log line a record was parsed from, the `TYPE` field of the line.
+///
+/// Stored as a one byte code, so a code must never be reused for another kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ConnType {
+    /// A line of an unknown type
+    #[default]
+    Other = 0,
+    /// A request from the client, `REQ` or `DOWNSTREAM[..]`
+    DownstreamReq = 1,
+    /// A response from the upstream, `RES` or `UPSTREAM[..]`
+    UpstreamRes = 2,
+    /// A proxy connection was accepted
+    Open = 3,
+    /// A proxy connection ended
+    Close = 4,
+    /// A gateway connection was upgraded to a WebSocket
+    Init = 5,
+    /// The gateway retried another target
+    Retry = 6,
+    /// The gateway refused a client by its access list
+    Deny = 7,
+    /// An upstream timed out
+    Timeout = 8,
+    /// A gateway request copied to its rule's mirror
+    Mirror = 9,
+    /// The routing decision of a traced gateway request
+    Trace = 10,
+    /// The gateway answered a request itself instead of forwarding it, e.g. at its limit
+    Reject = 11,
+}
+
+/// Set on stored codes, records written before the code existed start this
+/// field with the length of a short string instead
+const CONN_TYPE_TAG: u8 = 0x80;
+
+impl ConnType {
+    /// Maps the `TYPE` field of a log line, ignoring a `[..]` suffix.
+    pub fn from_log_type(value: &str) -> Self {
+        let name = value.split('[').next().unwrap_or_default();
+        match name {
+            "REQ" | "DOWNSTREAM" => ConnType::DownstreamReq,
+            "RES" | "UPSTREAM" => ConnType::UpstreamRes,
+            "OPEN" => ConnType::Open,
+            "CLOSE" => ConnType::Close,
+            "INIT" => ConnType::Init,
+            "RETRY" => ConnType::Retry,
+            "DENY" => ConnType::Deny,
+            "TIMEOUT" => ConnType::Timeout,
+            "MIRROR" => ConnType::Mirror,
+            "TRACE" => ConnType::Trace,
+            "REJECT" => ConnType::Reject,
+            _ => ConnType::Other,
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            1 => ConnType::DownstreamReq,
+            2 => ConnType::UpstreamRes,
+            3 => ConnType::Open,
+            4 => ConnType::Close,
+            5 => ConnType::Init,
+            6 => ConnType::Retry,
+            7 => ConnType::Deny,
+            8 => ConnType::Timeout,
+            9 => ConnType::Mirror,
+            10 => ConnType::Trace,
+            11 => ConnType::Reject,
+            _ => ConnType::Other,
+        }
+    }
+}
+
+impl bincode::enc::Encode for ConnType {
+    fn encode<E: bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> Result<(), bincode::error::EncodeError> {
+        (CONN_TYPE_TAG | self.code()).encode(encoder)
+    }
+}
+
+impl bincode::de::Decode<()> for ConnType {
+    fn decode<D: bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> Result<Self, bincode::error::DecodeError> {
+        let tag = u8::decode(decoder)?;
+        if tag & CONN_TYPE_TAG != 0 {
+            return Ok(ConnType::from_code(tag & !CONN_TYPE_TAG));
+        }
+        // Older records stored the protocol as a string, skip it
+        for _ in 0..tag {
+            u8::decode(decoder)?;
+        }
+        Ok(ConnType::Other)
+    }
 }
 
+/// WebSocket message and frame counts of a closed proxy connection.
+///
+/// Only filled when the core runs with `GWRS_WS_FRAME_COUNTS`, zero otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct WsFrameCounts {
+    pub msg_in: i32,  // complete messages from the client
+    pub msg_out: i32, // complete messages from the upstream
+    pub text: i32,
+    pub binary: i32,
+    pub ping: i32,
+    pub pong: i32,
+    pub close: i32,
+}
+
+impl bincode::enc::Encode for WsFrameCounts {
+    fn encode<E: bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> Result<(), bincode::error::EncodeError> {
+        self.msg_in.encode(encoder)?;
+        self.msg_out.encode(encoder)?;
+        self.text.encode(encoder)?;
+        self.binary.encode(encoder)?;
+        self.ping.encode(encoder)?;
+        self.pong.encode(encoder)?;
+        self.close.encode(encoder)?;
+        Ok(())
+    }
+}
+
+impl bincode::de::Decode<()> for WsFrameCounts {
+    fn decode<D: bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> Result<Self, bincode::error::DecodeError> {
+        Ok(WsFrameCounts {
+            msg_in: i32::decode(decoder)?,
+            msg_out: i32::decode(decoder)?,
+            text: i32::decode(decoder)?,
+            binary: i32::decode(decoder)?,
+            ping: i32::decode(decoder)?,
+            pong: i32::decode(decoder)?,
+            close: i32::decode(decoder)?,
+        })
+    }
+}
+
+/// Level assumed for records written before the level was stored
+const DEFAULT_RECORD_LEVEL: u8 = 2;
+
 impl bincode::enc::Encode for TemporaryLog {
     fn encode<E: bincode::enc::Encoder>(
         &self,
@@ -62,6 +232,8 @@ impl bincode::enc::Encode for TemporaryLog {
         self.conn_res.encode(encoder)?;
         self.bytes_in.encode(encoder)?;
         self.bytes_out.encode(encoder)?;
+        self.level.encode(encoder)?;
+        self.ws_frames.encode(encoder)?;
         Ok(())
     }
 }
@@ -85,15 +257,34 @@ impl bincode::de::Decode<()> for TemporaryLog {
             status_code: i32::decode(decoder)?,
             peer: (String::decode(decoder)?, String::decode(decoder)?),
             conn_id: String::decode(decoder)?,
-            conn_type: String::decode(decoder)?,
+            conn_type: ConnType::decode(decoder)?,
             conn_req: i8::decode(decoder)?,
             conn_res: i8::decode(decoder)?,
             bytes_in: i32::decode(decoder)?,
             bytes_out: i32::decode(decoder)?,
+            // Records are decoded from length-prefixed slices, so older
+            // records simply end before the level
+            level: u8::decode(decoder).unwrap_or(DEFAULT_RECORD_LEVEL),
+            // Likewise for records written before frame counts
+            ws_frames: WsFrameCounts::decode(decoder).unwrap_or_default(),
         })
     }
 }
 
+/// Response header with the number of corrupted regions a statistics response left out
+pub const SKIPPED_HEADER: &str = "x-log-entries-skipped";
+
+/// Result of a range query over the store.
+///
+/// `skipped` counts the corrupted regions of segments the query had to step
+/// over, each holding at least one record, and unreadable segments, so a
+/// non-zero value means `data` is incomplete.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Queried<T> {
+    pub data: T,
+    pub skipped: usize,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LogCaptureTimeframe {
     pub date_time: chrono::DateTime<chrono::Utc>,
@@ -102,6 +293,17 @@ pub struct LogCaptureTimeframe {
     pub low: i32,   // Now: req_count
 }
 
+/// Number of log records per level in one 15 second interval
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct LogLevelTimeframe {
+    pub date_time: chrono::DateTime<chrono::Utc>,
+    pub trace: i32,
+    pub debug: i32,
+    pub info: i32,
+    pub warn: i32,
+    pub error: i32,
+}
+
 impl Clone for TemporaryLog {
     fn clone(&self) -> Self {
         Self {
@@ -109,11 +311,13 @@ impl Clone for TemporaryLog {
             status_code: self.status_code,
             peer: self.peer.clone(),
             conn_id: self.conn_id.clone(),
-            conn_type: self.conn_type.clone(),
+            conn_type: self.conn_type,
             conn_req: self.conn_req,
             conn_res: self.conn_res,
             bytes_in: self.bytes_in,
             bytes_out: self.bytes_out,
+            level: self.level,
+            ws_frames: self.ws_frames,
         }
     }
 }
@@ -126,37 +330,155 @@ struct ActiveSegment {
     file_descriptor: i32,
     write_offset: usize,
     size: usize,
-    logs: VecDeque<TemporaryLog>, // In-memory cache of logs in this segment
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct ArchivedSegment {
     file_path: PathBuf, // Path to the file as found on disk (could be .bin or .lzma initially)
     start_time: DateTime<Utc>,
     end_time: DateTime<Utc>,
 }
 
+/// Records of one owner, the proxy or the gateway.
+///
+/// Each record is held in memory once, in `current_logs`, for the retention
+/// period. The active segment keeps no copy of its records, they are read back
+/// from its mapping, so a record costs its size in memory plus its encoding in
+/// the mapped file, which the kernel can page out.
 struct LogStore {
     owner: String,
-    current_logs: VecDeque<TemporaryLog>, // Global in-memory cache (up to retention)
+    current_logs: VecDeque<TemporaryLog>, // The in-memory cache of recent records (up to retention)
     active_segment: Option<ActiveSegment>,
     archived_segments: BTreeMap<DateTime<Utc>, ArchivedSegment>,
     base_dir: PathBuf,
     last_rotation_check: DateTime<Utc>,
     segment_duration: Duration,
     retention_period: Duration,
+    // Set while segments can't be written (e.g. disk full), records are only
+    // kept in memory until then
+    disk_retry_at: Option<DateTime<Utc>>,
+    // Length of the blocks sealed segments are merged into, None disables it
+    compaction_window: Option<Duration>,
+    // Result of the running compaction, at most one runs at a time
+    compaction: Option<mpsc::Receiver<CompactedBlock>>,
+    // Windows whose merge failed, not tried again
+    compaction_failed: HashSet<DateTime<Utc>>,
+}
+
+/// Identity of a record across the sources of a query, `(conn_id, seconds, nanos)`
+type LogKey = (String, i64, u32);
+
+/// Collects the records of a time frame from overlapping sources.
+///
+/// The in-memory cache holds the records of the retention window that are also
+/// in the active segment's file or the archived segments, so each record counts
+/// once. Log times have second precision and a connection logs several lines,
+/// so records sharing a key within one source are all kept; a later source only
+/// adds those beyond what an earlier one had.
+struct RangeCollector {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    logs: Vec<TemporaryLog>,
+    // Most records of a key any source had so far
+    kept: HashMap<LogKey, usize>,
+    // Records of a key in the current source
+    in_source: HashMap<LogKey, usize>,
+}
+
+impl RangeCollector {
+    fn new(start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self {
+            start,
+            end,
+            logs: Vec::new(),
+            kept: HashMap::new(),
+            in_source: HashMap::new(),
+        }
+    }
+
+    /// Starts reading the next source.
+    fn next_source(&mut self) {
+        self.in_source.clear();
+    }
+
+    /// Adds a record within the time frame unless an earlier source had it.
+    fn add(&mut self, log: &TemporaryLog) {
+        if log.date_time < self.start || log.date_time > self.end {
+            return;
+        }
+        let key = (log.conn_id.clone(), log.date_time.timestamp(), log.date_time.timestamp_subsec_nanos());
+        let seen = self.in_source.entry(key.clone()).or_insert(0);
+        *seen += 1;
+        let kept = self.kept.entry(key).or_insert(0);
+        if *seen > *kept {
+            *kept = *seen;
+            self.logs.push(log.clone());
+        }
+    }
+}
+
+/// Archived segments merged into one file by a background compaction
+struct CompactedBlock {
+    window_start: DateTime<Utc>,
+    // None when the merge failed
+    merged: Option<ArchivedSegment>,
+    replaced: Vec<ArchivedSegment>,
 }
 
 const SEGMENT_SIZE: usize = 100 * 1024 * 1024;
 
+/// How long writes to disk are paused after a segment couldn't be written
+const DISK_RETRY_SECONDS: i64 = 30;
+
+/// Creates the log archive directory if it is missing, readable only by its
+/// owner, and checks that segments can be written to it.
+///
+/// The permissions of an existing directory are left as the operator set them.
+pub fn prepare_archive_dir(dir: &Path) -> io::Result<()> {
+    if !dir.is_dir() {
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    let probe = dir.join(".write_probe");
+    File::create(&probe)?.write_all(b"gwrs")?;
+    fs::remove_file(&probe)
+}
+
+/// Sizes a segment file to `SEGMENT_SIZE` before it is mapped.
+///
+/// On Linux the blocks are reserved up front, writing to a hole of the mapping
+/// on a full disk raises SIGBUS instead of returning an error. Elsewhere the
+/// file is only extended, a full disk then surfaces when the segment is synced.
+#[cfg(target_os = "linux")]
+fn reserve_segment(file: &File) -> io::Result<()> {
+    let reserved = unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, SEGMENT_SIZE as libc::off_t) };
+    match reserved {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reserve_segment(file: &File) -> io::Result<()> {
+    if file.metadata()?.len() < SEGMENT_SIZE as u64 {
+        file.set_len(SEGMENT_SIZE as u64)?;
+    }
+    Ok(())
+}
+
 static mut PROXY_LOG_STORE: Option<LogStore> = None;
 static mut GATEWAY_LOG_STORE: Option<LogStore> = None;
 
 impl LogStore {
     #[allow(deprecated)]
-    fn new(owner: String) -> Self {
-        let base_dir = PathBuf::from("/tmp/gwrs/logment");
-        let _ = fs::create_dir_all(&base_dir);
+    fn new(owner: String, base_dir: PathBuf) -> Self {
+        if let Err(e) = prepare_archive_dir(&base_dir) {
+            log::error!(
+                "Log archive directory {} is not writable, {} logs are kept in memory only: {}",
+                base_dir.display(),
+                owner,
+                e
+            );
+        }
 
         let mut store = Self {
             owner: owner.clone(),
@@ -167,8 +489,16 @@ impl LogStore {
             last_rotation_check: Utc::now(),
             segment_duration: Duration::minutes(1),
             retention_period: Duration::minutes(35),
+            disk_retry_at: None,
+            compaction_window: Some(config::log_compaction_minutes())
+                .filter(|minutes| *minutes > 0)
+                .map(Duration::minutes),
+            compaction: None,
+            compaction_failed: HashSet::new(),
         };
 
+        let mut found_segments = Vec::new();
+
         if let Ok(entries) = fs::read_dir(&base_dir) {
             for entry in entries.filter_map(Result::ok) {
                 let path = entry.path();
@@ -178,6 +508,9 @@ impl LogStore {
                         && file_name.ends_with(".bin")
                     {
                         log::info!("Found active segment file: {}", file_name);
+                    } else if file_name.starts_with(&format!("merging_{}_", owner)) {
+                        // Left behind by a compaction that didn't finish
+                        let _ = fs::remove_file(&path);
                     } else if file_name.starts_with(&format!("segment_{}", owner))
                         && (file_name.ends_with(".bin") || file_name.ends_with(".lzma"))
                     {
@@ -205,14 +538,11 @@ impl LogStore {
                                     &format!("{} {}", date_part_of_start_str, end_time_of_day_str),
                                     "%Y%m%d %H%M%S",
                                 ) {
-                                    store.archived_segments.insert(
+                                    found_segments.push(ArchivedSegment {
+                                        file_path: path.clone(),
                                         start_time,
-                                        ArchivedSegment {
-                                            file_path: path.clone(),
-                                            start_time,
-                                            end_time,
-                                        },
-                                    );
+                                        end_time,
+                                    });
                                 }
                             }
                         }
@@ -220,6 +550,17 @@ impl LogStore {
                 }
             }
         }
+
+        // A compaction that stopped before removing the merged segments
+        // leaves them next to the block that covers them
+        let (kept_segments, covered_segments) = split_covered(found_segments);
+        for covered in covered_segments {
+            log::info!("Removing compacted segment {}", covered.file_path.display());
+            remove_segment_files(&covered);
+        }
+        for segment in kept_segments {
+            store.archived_segments.insert(segment.start_time, segment);
+        }
         store
     }
 
@@ -307,8 +648,12 @@ impl LogStore {
         let metadata = file.metadata()?;
         let on_disk_size_before_resize = metadata.len() as usize;
 
-        if on_disk_size_before_resize < SEGMENT_SIZE {
-            file.set_len(SEGMENT_SIZE as u64)?;
+        if let Err(err) = reserve_segment(&file) {
+            drop(file);
+            if existing_segment_parsed_start_time.is_none() {
+                let _ = fs::remove_file(&segment_file_path);
+            }
+            return Err(LogStoreError::IoError(err));
         }
 
         let fd = file.into_raw_fd();
@@ -345,7 +690,7 @@ impl LogStore {
             std::thread::sleep(std::time::Duration::from_millis(100));
         }
 
-        let mut loaded_logs_from_disk = VecDeque::new();
+        // Records of a reopened segment are only read to find where writing continues
         let mut current_write_offset = 0;
 
         if existing_segment_parsed_start_time.is_some() && on_disk_size_before_resize > 0 {
@@ -374,15 +719,12 @@ impl LogStore {
                 }
 
                 let entry_data = &content_slice[offset..offset + entry_size];
-                match bincode::decode_from_slice::<TemporaryLog, _>(
+                if let Err(e) = bincode::decode_from_slice::<TemporaryLog, _>(
                     entry_data,
                     bincode::config::standard(),
                 ) {
-                    Ok((log, _)) => loaded_logs_from_disk.push_back(log),
-                    Err(e) => {
-                        log::error!("Error decoding log entry from active segment: {}", e);
-                        break;
-                    }
+                    log::error!("Error decoding log entry from active segment: {}", e);
+                    break;
                 }
                 offset += entry_size;
             }
@@ -396,7 +738,6 @@ impl LogStore {
             file_descriptor: fd,
             write_offset: current_write_offset,
             size: SEGMENT_SIZE,
-            logs: loaded_logs_from_disk,
         });
         Ok(())
     }
@@ -413,9 +754,83 @@ impl LogStore {
                 self.rotate_segment(now)?;
             }
         }
+        self.poll_compaction(now);
         Ok(())
     }
 
+    /// Applies a finished compaction and starts the next one.
+    ///
+    /// The merge runs in a background thread and only writes the new block.
+    /// The block replaces the merged segments here, on the store's own thread,
+    /// so queries never see a window twice or not at all.
+    fn poll_compaction(&mut self, now: DateTime<Utc>) {
+        if let Some(receiver) = &self.compaction {
+            let block = match receiver.try_recv() {
+                Ok(block) => block,
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.compaction = None;
+                    return;
+                }
+            };
+            self.compaction = None;
+            match block.merged {
+                Some(merged) => {
+                    for replaced in &block.replaced {
+                        self.archived_segments.remove(&replaced.start_time);
+                    }
+                    self.archived_segments.insert(merged.start_time, merged);
+                    for replaced in &block.replaced {
+                        remove_segment_files(replaced);
+                    }
+                }
+                None => {
+                    self.compaction_failed.insert(block.window_start);
+                }
+            }
+        }
+
+        let Some(window) = self.compaction_window else {
+            return;
+        };
+        let cutoff = now - self.retention_period;
+        let Some((window_start, parts)) = compaction_candidates(
+            &self.archived_segments,
+            window,
+            now,
+            |segment| segment.end_time >= cutoff && is_sealed(segment),
+        ) else {
+            return;
+        };
+        if self.compaction_failed.contains(&window_start) {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.compaction = Some(receiver);
+        let owner = self.owner.clone();
+        let base_dir = self.base_dir.clone();
+        std::thread::spawn(move || {
+            let merged = match merge_segments(&owner, &base_dir, &parts) {
+                Ok(merged) => Some(merged),
+                Err(e) => {
+                    log::error!(
+                        "Failed to compact {} log segments from {}: {}",
+                        owner,
+                        window_start.format("%H:%M:%S"),
+                        e
+                    );
+                    None
+                }
+            };
+            let _ = sender.send(CompactedBlock {
+                window_start,
+                merged,
+                replaced: parts,
+            });
+        });
+    }
+
     fn rotate_segment(&mut self, rotation_time: DateTime<Utc>) -> Result<(), LogStoreError> {
         if let Some(segment_to_archive) = self.active_segment.take() {
             // Try to sync memory to disk with error handling
@@ -466,11 +881,11 @@ impl LogStore {
                     });
             }
 
-            let archived_file_name = format!(
-                "segment_{}_{}_{}.bin",
-                self.owner,
-                segment_to_archive.start_time.format("%Y%m%d_%H%M%S"),
-                rotation_time.format("%H%M%S")
+            let archived_file_name = segment_file_name(
+                &self.owner,
+                segment_to_archive.start_time,
+                rotation_time,
+                "bin",
             );
             let final_archived_file_path = self.base_dir.join(&archived_file_name);
 
@@ -567,6 +982,7 @@ impl LogStore {
                         log::error!("Error deleting file {}: {}", path_bin.display(), e);
                     }
                 }
+                let _ = fs::remove_file(SegmentIndex::path(&segment_to_delete.file_path));
             }
         }
 
@@ -580,8 +996,43 @@ impl LogStore {
         Ok(())
     }
 
-    // MODIFIED: Added logging before serialization
+    /// Writes a record to the active segment.
+    ///
+    /// When the disk can't take it the record is kept in memory only and
+    /// writes are paused for `DISK_RETRY_SECONDS`, so a full disk costs
+    /// history but never the process.
     fn append_data(&mut self, log: TemporaryLog) -> Result<(), LogStoreError> {
+        let now = Utc::now();
+        if self.disk_retry_at.is_some_and(|retry_at| now < retry_at) {
+            self.current_logs.push_back(log);
+            self.trim_memory();
+            return Ok(());
+        }
+        match self.write_record(log.clone()) {
+            Ok(()) => {
+                if self.disk_retry_at.take().is_some() {
+                    log::info!("Writing {} log segments to {} again", self.owner, self.base_dir.display());
+                }
+                Ok(())
+            }
+            Err(e @ LogStoreError::IoError(_)) => {
+                log::error!(
+                    "Failed to write {} log segment to {}, keeping logs in memory for {}s: {}",
+                    self.owner,
+                    self.base_dir.display(),
+                    DISK_RETRY_SECONDS,
+                    e
+                );
+                self.disk_retry_at = Some(now + Duration::seconds(DISK_RETRY_SECONDS));
+                self.current_logs.push_back(log);
+                self.trim_memory();
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_record(&mut self, log: TemporaryLog) -> Result<(), LogStoreError> {
         self.check_segment_rotation()?;
         self.ensure_active_segment()?;
 
@@ -631,7 +1082,6 @@ impl LogStore {
                 );
             }
             new_active_seg_ref.write_offset += total_space_needed_for_entry;
-            new_active_seg_ref.logs.push_back(log.clone());
             self.current_logs.push_back(log);
         } else {
             unsafe {
@@ -648,10 +1098,15 @@ impl LogStore {
                 );
             }
             active_seg_ref.write_offset += total_space_needed_for_entry;
-            active_seg_ref.logs.push_back(log.clone());
             self.current_logs.push_back(log);
         }
 
+        self.trim_memory();
+        Ok(())
+    }
+
+    /// Drops in-memory records older than the retention period.
+    fn trim_memory(&mut self) {
         if let Some(oldest_log_entry) = self.current_logs.front() {
             if Utc::now().signed_duration_since(oldest_log_entry.date_time) > self.retention_period
             {
@@ -663,46 +1118,21 @@ impl LogStore {
                 }
             }
         }
-        Ok(())
     }
 
     fn load_logs(
         &self,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-    ) -> Result<Vec<TemporaryLog>, LogStoreError> {
-        let mut result_logs_vec = Vec::new();
-        let mut unique_log_keys_set: HashSet<(String, i64, u32)> = HashSet::new();
-
-        let add_if_in_range =
-            |log: TemporaryLog,
-             _source_name: &str,
-             logs_container: &mut Vec<TemporaryLog>,
-             _keys_container: &mut HashSet<(String, i64, u32)>| {
-                if log.date_time >= start && log.date_time <= end {
-                    logs_container.push(log);
-                }
-            };
+    ) -> Result<Queried<Vec<TemporaryLog>>, LogStoreError> {
+        let mut skipped = 0;
+        let mut collector = RangeCollector::new(start, end);
 
         for log_entry in &self.current_logs {
-            add_if_in_range(
-                log_entry.clone(),
-                "current_logs_cache",
-                &mut result_logs_vec,
-                &mut unique_log_keys_set,
-            );
+            collector.add(log_entry);
         }
 
         if let Some(active_seg) = &self.active_segment {
-            for log_entry in &active_seg.logs {
-                add_if_in_range(
-                    log_entry.clone(),
-                    "active_segment_memory_cache",
-                    &mut result_logs_vec,
-                    &mut unique_log_keys_set,
-                );
-            }
-
             if active_seg.file_path.exists() && active_seg.write_offset > 0 {
                 let active_file_content_slice = unsafe {
                     std::slice::from_raw_parts(
@@ -710,55 +1140,30 @@ impl LogStore {
                         active_seg.write_offset,
                     )
                 };
-                let mut offset = 0;
-                while offset + 4 <= active_seg.write_offset {
-                    let size_bytes = &active_file_content_slice[offset..offset + 4];
-                    let entry_size = u32::from_ne_bytes([
-                        size_bytes[0],
-                        size_bytes[1],
-                        size_bytes[2],
-                        size_bytes[3],
-                    ]) as usize;
-                    offset += 4;
-
-                    if entry_size == 0 || offset + entry_size > active_seg.write_offset {
-                        break;
-                    }
-                    let entry_data = &active_file_content_slice[offset..offset + entry_size];
-                    match bincode::decode_from_slice::<TemporaryLog, _>(
-                        entry_data,
-                        bincode::config::standard(),
-                    ) {
-                        Ok((log_disk_entry, _)) => {
-                            add_if_in_range(
-                                log_disk_entry,
-                                "active_segment_disk_file",
-                                &mut result_logs_vec,
-                                &mut unique_log_keys_set,
-                            );
-                        }
-                        Err(e) => {
-                            log::error!("Error decoding log entry from active segment: {}", e);
-                            break;
-                        }
-                    }
-                    offset += entry_size;
-                }
+                collector.next_source();
+                skipped += decode_entries(active_file_content_slice, |log_disk_entry| {
+                    collector.add(&log_disk_entry);
+                });
             }
         }
 
+        // Archived segments don't overlap, together they are one source
+        collector.next_source();
         for (_, archived_segment_info) in self.archived_segments.iter() {
             if archived_segment_info.start_time <= end && archived_segment_info.end_time >= start {
                 match load_logs_from_segment(archived_segment_info, start, end) {
                     Ok(logs_from_one_archive) => {
-                        for log_entry_archived in logs_from_one_archive {
-                            add_if_in_range(
-                                log_entry_archived,
-                                "archived_segment_file",
-                                &mut result_logs_vec,
-                                &mut unique_log_keys_set,
+                        if logs_from_one_archive.skipped > 0 {
+                            log::warn!(
+                                "Skipped {} corrupted region(s) of archived segment {}",
+                                logs_from_one_archive.skipped,
+                                archived_segment_info.file_path.display()
                             );
                         }
+                        skipped += logs_from_one_archive.skipped;
+                        for log_entry_archived in &logs_from_one_archive.data {
+                            collector.add(log_entry_archived);
+                        }
                     }
                     Err(e) => {
                         log::error!(
@@ -766,14 +1171,41 @@ impl LogStore {
                             archived_segment_info.file_path.display(),
                             e
                         );
+                        skipped += 1;
                     }
                 }
             }
         }
 
+        let mut result_logs_vec = collector.logs;
         result_logs_vec.sort_by(|a, b| a.date_time.cmp(&b.date_time));
 
-        Ok(result_logs_vec)
+        Ok(Queried {
+            data: result_logs_vec,
+            skipped,
+        })
+    }
+
+    fn get_level_counts(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        min_level: u8,
+    ) -> Result<Queried<Vec<LogLevelTimeframe>>, LogStoreError> {
+        let Queried { data: logs, skipped } = self.load_logs(start, end)?;
+        Ok(Queried { data: count_levels(&logs, start, end, min_level), skipped })
+    }
+
+    /// Timestamp of the newest stored log within the retention window.
+    fn latest_timestamp(&self) -> Result<Option<DateTime<Utc>>, LogStoreError> {
+        let in_memory = self.current_logs.iter().map(|log| log.date_time).max();
+        if in_memory.is_some() {
+            return Ok(in_memory);
+        }
+
+        let now = Utc::now();
+        let logs = self.load_logs(now - self.retention_period, now)?.data;
+        Ok(logs.last().map(|log| log.date_time))
     }
 
     // MODIFIED: get_data_time_frame with enhanced logging
@@ -781,8 +1213,8 @@ impl LogStore {
         &self,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
-        let logs = self.load_logs(start, end)?;
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
+        let Queried { data: logs, skipped } = self.load_logs(start, end)?;
         // Your existing log::error!("Data: {:#?}", logs); // This is where you see the issue
 
         let mut result = Vec::new();
@@ -807,7 +1239,7 @@ impl LogStore {
                 });
             }
             result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
-            return Ok(result);
+            return Ok(Queried { data: result, skipped });
         }
 
         let mut time_groups: HashMap<i64, Vec<&TemporaryLog>> = HashMap::new();
@@ -876,7 +1308,7 @@ impl LogStore {
             result.push(timeframe);
         }
         result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
-        Ok(result)
+        Ok(Queried { data: result, skipped })
     }
 
     fn get_data_time_frame_by_status_code(
@@ -884,8 +1316,8 @@ impl LogStore {
         start: DateTime<Utc>,
         end: DateTime<Utc>,
         status_filter: i32,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
-        let logs = self.load_logs(start, end)?;
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
+        let Queried { data: logs, skipped } = self.load_logs(start, end)?;
         let mut result = Vec::new();
         let start_ts_interval = start.timestamp() / 15;
         let end_ts_interval = end.timestamp() / 15;
@@ -904,53 +1336,11 @@ impl LogStore {
                 });
             }
             result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
-            return Ok(result);
+            return Ok(Queried { data: result, skipped });
         }
 
-        let mut conn_logs_map: HashMap<String, Vec<&TemporaryLog>> = HashMap::new();
-        for log_ref in logs.iter() {
-            conn_logs_map
-                .entry(log_ref.conn_id.clone())
-                .or_default()
-                .push(log_ref);
-        }
-
-        let mut interval_response_times_map: HashMap<i64, Vec<i64>> = HashMap::new();
-        let mut interval_direct_status_counts_map: HashMap<i64, i32> = HashMap::new();
-
-        for (_conn_id_key, single_conn_logs_vec) in &conn_logs_map {
-            let mut sorted_logs_for_conn = single_conn_logs_vec.clone();
-            sorted_logs_for_conn.sort_by_key(|log_item| log_item.date_time);
-
-            let mut req_time_for_conn: Option<DateTime<Utc>> = None;
-            for current_log in sorted_logs_for_conn {
-                if current_log.conn_type == "REQ" || current_log.conn_type == "DOWNSTREAM" {
-                    req_time_for_conn = Some(current_log.date_time);
-                } else if (current_log.conn_type == "RES" || current_log.conn_type == "UPSTREAM")
-                    && current_log.status_code == status_filter
-                {
-                    if let Some(rt) = req_time_for_conn.take() {
-                        let resp_time_ms = current_log
-                            .date_time
-                            .signed_duration_since(rt)
-                            .num_milliseconds();
-                        if resp_time_ms >= 0 {
-                            let interval_ts_key = current_log.date_time.timestamp() / 15;
-                            interval_response_times_map
-                                .entry(interval_ts_key)
-                                .or_default()
-                                .push(resp_time_ms);
-                        }
-                    }
-                }
-                if current_log.status_code == status_filter {
-                    let interval_ts_key = current_log.date_time.timestamp() / 15;
-                    *interval_direct_status_counts_map
-                        .entry(interval_ts_key)
-                        .or_default() += 1;
-                }
-            }
-        }
+        let (interval_response_times_map, interval_direct_status_counts_map) =
+            response_times(&logs, status_filter);
 
         let mut final_interval_results_map = HashMap::new();
         for interval_block_ts_key in start_ts_interval..=end_ts_interval {
@@ -1009,15 +1399,15 @@ impl LogStore {
             );
         }
         result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
-        Ok(result)
+        Ok(Queried { data: result, skipped })
     }
 
     fn get_data_time_frame_by_conn_stall(
         &self,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
-        let logs = self.load_logs(start, end)?;
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
+        let Queried { data: logs, skipped } = self.load_logs(start, end)?;
         let mut result = Vec::new();
         let start_ts_interval = start.timestamp() / 15;
         let end_ts_interval = end.timestamp() / 15;
@@ -1036,11 +1426,20 @@ impl LogStore {
                 });
             }
             result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
-            return Ok(result);
+            return Ok(Queried { data: result, skipped });
         }
 
+        // A connection is stalled when it was opened but no close was recorded for it
+        let closed_conn_ids: std::collections::HashSet<&str> = logs
+            .iter()
+            .filter(|l| l.conn_res == 1)
+            .map(|l| l.conn_id.as_str())
+            .collect();
+
         let mut interval_stalled_log_groups: HashMap<i64, Vec<&TemporaryLog>> = HashMap::new();
-        for log_item in logs.iter().filter(|l| l.conn_req == 1 && l.conn_res == 0) {
+        for log_item in logs.iter().filter(|l| {
+            l.conn_req == 1 && l.conn_res == 0 && !closed_conn_ids.contains(l.conn_id.as_str())
+        }) {
             let interval_ts_key = log_item.date_time.timestamp() / 15;
             interval_stalled_log_groups
                 .entry(interval_ts_key)
@@ -1114,7 +1513,7 @@ impl LogStore {
             );
         }
         result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
-        Ok(result)
+        Ok(Queried { data: result, skipped })
     }
 
     fn get_bytes_io_frame(
@@ -1122,128 +1521,247 @@ impl LogStore {
         start: DateTime<Utc>,
         end: DateTime<Utc>,
         metric: BytesMetric,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
-        let logs = self.load_logs(start, end)?;
-        let mut result = Vec::new();
-        let start_ts_interval = start.timestamp() / 15;
-        let end_ts_interval = end.timestamp() / 15;
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
+        let Queried { data: logs, skipped } = self.load_logs(start, end)?;
+        Ok(Queried {
+            data: bytes_io_frames(&logs, start, end, metric),
+            skipped,
+        })
+    }
+}
 
-        if logs.is_empty() {
-            for interval_block_ts in start_ts_interval..=end_ts_interval {
-                let interval_datetime = Utc
-                    .timestamp_opt(interval_block_ts * 15, 0)
-                    .single()
-                    .unwrap_or(start);
-                result.push(LogCaptureTimeframe {
-                    date_time: interval_datetime,
-                    value: 0,
-                    high: 0,
-                    low: 0,
-                });
-            }
-            result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
-            return Ok(result);
+/// Buckets records into 15 second intervals and averages the bytes of `metric`.
+///
+/// `value` is the average per record of an interval, `high` and `low` the
+/// highest and lowest per second averages within it. Every interval between
+/// `start` and `end` is present, empty ones with zeros.
+fn bytes_io_frames(
+    logs: &[TemporaryLog],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    metric: BytesMetric,
+) -> Vec<LogCaptureTimeframe> {
+    let mut result = Vec::new();
+    let start_ts_interval = start.timestamp() / 15;
+    let end_ts_interval = end.timestamp() / 15;
+
+    if logs.is_empty() {
+        for interval_block_ts in start_ts_interval..=end_ts_interval {
+            let interval_datetime = Utc
+                .timestamp_opt(interval_block_ts * 15, 0)
+                .single()
+                .unwrap_or(start);
+            result.push(LogCaptureTimeframe {
+                date_time: interval_datetime,
+                value: 0,
+                high: 0,
+                low: 0,
+            });
         }
+        result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
+        return result;
+    }
 
-        let mut interval_log_groups: HashMap<i64, Vec<&TemporaryLog>> = HashMap::new();
-        for log_item in logs.iter() {
-            let interval_ts_key = log_item.date_time.timestamp() / 15;
-            interval_log_groups
-                .entry(interval_ts_key)
+    let mut interval_log_groups: HashMap<i64, Vec<&TemporaryLog>> = HashMap::new();
+    for log_item in logs.iter() {
+        let interval_ts_key = log_item.date_time.timestamp() / 15;
+        interval_log_groups
+            .entry(interval_ts_key)
+            .or_default()
+            .push(log_item);
+    }
+
+    let mut final_interval_results_map = HashMap::new();
+    let get_bytes_value_for_log = |log_entry: &&TemporaryLog| -> i32 {
+        match metric {
+            BytesMetric::BytesIn => log_entry.bytes_in,
+            BytesMetric::BytesOut => log_entry.bytes_out,
+            BytesMetric::BytesTotal => log_entry.bytes_in + log_entry.bytes_out,
+        }
+    };
+
+    for (interval_block_ts_key, logs_in_interval) in interval_log_groups {
+        let interval_dt = Utc
+            .timestamp_opt(interval_block_ts_key * 15, 0)
+            .single()
+            .unwrap_or(start);
+
+        let sum_bytes_in_interval: i64 = logs_in_interval
+            .iter()
+            .map(|l_val| get_bytes_value_for_log(l_val) as i64)
+            .sum();
+        let avg_bytes_for_15s_interval = if !logs_in_interval.is_empty() {
+            (sum_bytes_in_interval / logs_in_interval.len() as i64) as i32
+        } else {
+            0
+        };
+
+        let mut per_second_byte_values_map: HashMap<i64, Vec<i32>> = HashMap::new();
+        for log_entry_in_interval in &logs_in_interval {
+            let second_ts_key = log_entry_in_interval.date_time.timestamp();
+            per_second_byte_values_map
+                .entry(second_ts_key)
                 .or_default()
-                .push(log_item);
+                .push(get_bytes_value_for_log(log_entry_in_interval));
         }
 
-        let mut final_interval_results_map = HashMap::new();
-        let get_bytes_value_for_log = |log_entry: &&TemporaryLog| -> i32 {
-            match metric {
-                BytesMetric::BytesIn => log_entry.bytes_in,
-                BytesMetric::BytesOut => log_entry.bytes_out,
-                BytesMetric::BytesTotal => log_entry.bytes_in + log_entry.bytes_out,
+        let mut per_second_avg_bytes_vec: Vec<i32> = Vec::new();
+        for (_sec_ts_key, byte_values_for_second) in per_second_byte_values_map {
+            if !byte_values_for_second.is_empty() {
+                let sum_bytes_for_second: i64 = byte_values_for_second
+                    .iter()
+                    .map(|&b_val| b_val as i64)
+                    .sum();
+                per_second_avg_bytes_vec
+                    .push((sum_bytes_for_second / byte_values_for_second.len() as i64) as i32);
             }
-        };
+        }
+        let highest_one_sec_avg = per_second_avg_bytes_vec.iter().max().copied().unwrap_or(0);
+        let lowest_one_sec_avg = per_second_avg_bytes_vec.iter().min().copied().unwrap_or(0);
+
+        final_interval_results_map.insert(
+            interval_block_ts_key,
+            LogCaptureTimeframe {
+                date_time: interval_dt,
+                value: avg_bytes_for_15s_interval,
+                high: highest_one_sec_avg,
+                low: lowest_one_sec_avg,
+            },
+        );
+    }
 
-        for (interval_block_ts_key, logs_in_interval) in interval_log_groups {
-            let interval_dt = Utc
-                .timestamp_opt(interval_block_ts_key * 15, 0)
-                .single()
-                .unwrap_or(start);
+    for interval_block_ts_key in start_ts_interval..=end_ts_interval {
+        result.push(
+            final_interval_results_map
+                .remove(&interval_block_ts_key)
+                .unwrap_or_else(|| {
+                    let interval_dt_fallback = Utc
+                        .timestamp_opt(interval_block_ts_key * 15, 0)
+                        .single()
+                        .unwrap_or(start);
+                    LogCaptureTimeframe {
+                        date_time: interval_dt_fallback,
+                        value: 0,
+                        high: 0,
+                        low: 0,
+                    }
+                }),
+        );
+    }
+    result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
+    result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
+    result
+}
 
-            let sum_bytes_in_interval: i64 = logs_in_interval
-                .iter()
-                .map(|l_val| get_bytes_value_for_log(l_val) as i64)
-                .sum();
-            let avg_bytes_for_15s_interval = if !logs_in_interval.is_empty() {
-                (sum_bytes_in_interval / logs_in_interval.len() as i64) as i32
-            } else {
-                0
-            };
+/// Pairs every response with `status_filter` to the request before it on the
+/// same connection.
+///
+/// Returns the response times in milliseconds and the number of records with
+/// `status_filter`, both keyed by 15 second interval.
+fn response_times(
+    logs: &[TemporaryLog],
+    status_filter: i32,
+) -> (HashMap<i64, Vec<i64>>, HashMap<i64, i32>) {
+    let mut conn_logs_map: HashMap<&str, Vec<&TemporaryLog>> = HashMap::new();
+    for log_ref in logs.iter() {
+        conn_logs_map
+            .entry(log_ref.conn_id.as_str())
+            .or_default()
+            .push(log_ref);
+    }
 
-            let mut per_second_byte_values_map: HashMap<i64, Vec<i32>> = HashMap::new();
-            for log_entry_in_interval in &logs_in_interval {
-                let second_ts_key = log_entry_in_interval.date_time.timestamp();
-                per_second_byte_values_map
-                    .entry(second_ts_key)
-                    .or_default()
-                    .push(get_bytes_value_for_log(log_entry_in_interval));
-            }
+    let mut interval_response_times_map: HashMap<i64, Vec<i64>> = HashMap::new();
+    let mut interval_direct_status_counts_map: HashMap<i64, i32> = HashMap::new();
 
-            let mut per_second_avg_bytes_vec: Vec<i32> = Vec::new();
-            for (_sec_ts_key, byte_values_for_second) in per_second_byte_values_map {
-                if !byte_values_for_second.is_empty() {
-                    let sum_bytes_for_second: i64 = byte_values_for_second
-                        .iter()
-                        .map(|&b_val| b_val as i64)
-                        .sum();
-                    per_second_avg_bytes_vec
-                        .push((sum_bytes_for_second / byte_values_for_second.len() as i64) as i32);
+    for single_conn_logs_vec in conn_logs_map.values() {
+        let mut sorted_logs_for_conn = single_conn_logs_vec.clone();
+        sorted_logs_for_conn.sort_by_key(|log_item| log_item.date_time);
+
+        let mut req_time_for_conn: Option<DateTime<Utc>> = None;
+        for current_log in sorted_logs_for_conn {
+            if current_log.conn_type == ConnType::DownstreamReq {
+                req_time_for_conn = Some(current_log.date_time);
+            } else if current_log.conn_type == ConnType::UpstreamRes
+                && current_log.status_code == status_filter
+            {
+                if let Some(rt) = req_time_for_conn.take() {
+                    let resp_time_ms = current_log
+                        .date_time
+                        .signed_duration_since(rt)
+                        .num_milliseconds();
+                    if resp_time_ms >= 0 {
+                        let interval_ts_key = current_log.date_time.timestamp() / 15;
+                        interval_response_times_map
+                            .entry(interval_ts_key)
+                            .or_default()
+                            .push(resp_time_ms);
+                    }
                 }
             }
-            let highest_one_sec_avg = per_second_avg_bytes_vec.iter().max().copied().unwrap_or(0);
-            let lowest_one_sec_avg = per_second_avg_bytes_vec.iter().min().copied().unwrap_or(0);
-
-            final_interval_results_map.insert(
-                interval_block_ts_key,
-                LogCaptureTimeframe {
-                    date_time: interval_dt,
-                    value: avg_bytes_for_15s_interval,
-                    high: highest_one_sec_avg,
-                    low: lowest_one_sec_avg,
-                },
-            );
+            if current_log.status_code == status_filter {
+                let interval_ts_key = current_log.date_time.timestamp() / 15;
+                *interval_direct_status_counts_map
+                    .entry(interval_ts_key)
+                    .or_default() += 1;
+            }
         }
+    }
 
-        for interval_block_ts_key in start_ts_interval..=end_ts_interval {
-            result.push(
-                final_interval_results_map
-                    .remove(&interval_block_ts_key)
-                    .unwrap_or_else(|| {
-                        let interval_dt_fallback = Utc
-                            .timestamp_opt(interval_block_ts_key * 15, 0)
-                            .single()
-                            .unwrap_or(start);
-                        LogCaptureTimeframe {
-                            date_time: interval_dt_fallback,
-                            value: 0,
-                            high: 0,
-                            low: 0,
-                        }
-                    }),
-            );
+    (interval_response_times_map, interval_direct_status_counts_map)
+}
+
+/// Buckets records into 15 second intervals and counts them per level.
+///
+/// Every interval between `start` and `end` is present, empty ones with zeros.
+/// Counts records per level in 15 second intervals, records below `min_level` are left out.
+fn count_levels(
+    logs: &[TemporaryLog],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    min_level: u8,
+) -> Vec<LogLevelTimeframe> {
+    let start_ts_interval = start.timestamp() / 15;
+    let end_ts_interval = end.timestamp() / 15;
+
+    let mut intervals: BTreeMap<i64, LogLevelTimeframe> = (start_ts_interval..=end_ts_interval)
+        .map(|interval_block_ts| {
+            let date_time = Utc
+                .timestamp_opt(interval_block_ts * 15, 0)
+                .single()
+                .unwrap_or(start);
+            (
+                interval_block_ts,
+                LogLevelTimeframe {
+                    date_time,
+                    ..Default::default()
+                },
+            )
+        })
+        .collect();
+
+    for log in logs.iter().filter(|log| log.level >= min_level) {
+        if let Some(frame) = intervals.get_mut(&(log.date_time.timestamp() / 15)) {
+            match log.level {
+                0 => frame.trace += 1,
+                1 => frame.debug += 1,
+                2 => frame.info += 1,
+                3 => frame.warn += 1,
+                _ => frame.error += 1,
+            }
         }
-        result.sort_by(|a, b| a.date_time.cmp(&b.date_time));
-        Ok(result)
     }
+
+    intervals.into_values().collect()
 }
 
 fn load_logs_from_segment(
     segment_info: &ArchivedSegment,
     query_start_time: DateTime<Utc>,
     query_end_time: DateTime<Utc>,
-) -> Result<Vec<TemporaryLog>, LogStoreError> {
-    let mut loaded_logs_vec = Vec::new();
+) -> Result<Queried<Vec<TemporaryLog>>, LogStoreError> {
+    let mut loaded = Queried::default();
     if segment_info.end_time < query_start_time || segment_info.start_time > query_end_time {
-        return Ok(loaded_logs_vec);
+        return Ok(loaded);
     }
 
     let path_compressed = segment_info.file_path.with_extension("lzma");
@@ -1259,68 +1777,525 @@ fn load_logs_from_segment(
     } else if path_uncompressed.exists() {
         (path_uncompressed, false)
     } else {
-        return Ok(loaded_logs_vec);
+        return Ok(loaded);
     };
 
-    let mut file_bytes = Vec::new();
-    File::open(&path_to_load)?.read_to_end(&mut file_bytes)?;
+    let source_len = fs::metadata(&path_to_load)?.len();
+    let (start_secs, end_secs) = (query_start_time.timestamp(), query_end_time.timestamp());
+    let index = SegmentIndex::load(&path_to_load, source_len, is_compressed);
+    if index
+        .as_ref()
+        .is_some_and(|index| !index.overlaps(start_secs, end_secs))
+    {
+        return Ok(loaded);
+    }
+    let seek = index.as_ref().map_or(0, |index| index.seek(start_secs));
+    let mut decoder = RecordDecoder {
+        window: Some((start_secs, end_secs)),
+        consumed: seek,
+        index: index.is_none().then(IndexBuilder::default),
+        ..Default::default()
+    };
 
-    let data_to_process: &[u8];
-    let mut decompressed_data_holder;
+    let mut logs = Vec::new();
+    let mut keep = |log: TemporaryLog| {
+        if log.date_time >= query_start_time && log.date_time <= query_end_time {
+            logs.push(log);
+        }
+    };
 
+    let mut complete = true;
     if is_compressed {
-        decompressed_data_holder = Vec::new();
-        lzma_rs::lzma_decompress(
-            &mut io::Cursor::new(&file_bytes),
-            &mut decompressed_data_holder,
-        )
-        .map_err(|e| {
-            LogStoreError::CompressionError(format!(
-                "LZMA decompression for {:?}: {:?}",
-                path_to_load, e
-            ))
-        })?;
-        data_to_process = &decompressed_data_holder;
-    } else {
-        data_to_process = &file_bytes;
-    }
-
-    let data_len = data_to_process.len();
-    let mut offset = 0;
-    while offset + 4 <= data_len {
-        let size_bytes = &data_to_process[offset..offset + 4];
-        let entry_size =
-            u32::from_ne_bytes([size_bytes[0], size_bytes[1], size_bytes[2], size_bytes[3]])
-                as usize;
-        offset += 4;
-        if entry_size == 0 || offset + entry_size > data_len {
-            break;
-        }
-        let entry_data = &data_to_process[offset..offset + entry_size];
-        match bincode::decode_from_slice::<TemporaryLog, _>(entry_data, bincode::config::standard())
+        let mut input = io::BufReader::new(File::open(&path_to_load)?);
+        let mut sink = RecordSink {
+            decoder,
+            pending: Vec::new(),
+            skip: seek,
+            keep: &mut keep,
+        };
+        if let Err(e) = lzma_rs::lzma_decompress(&mut input, &mut sink) {
+            // Records decompressed before the error are still used, only the
+            // rest of the segment is lost
+            log::warn!("LZMA decompression of {} failed: {:?}", path_to_load.display(), e);
+            loaded.skipped += 1;
+            complete = false;
+        }
+        let RecordSink {
+            decoder: mut sink_decoder,
+            pending,
+            keep,
+            ..
+        } = sink;
+        sink_decoder.feed(&pending, true, keep);
+        decoder = sink_decoder;
+    } else if let Some(mapped) = MappedSegment::open(&path_to_load)? {
+        let records = mapped.as_slice();
+        let start = (seek as usize).min(records.len());
+        decoder.feed(&records[start..], true, &mut keep);
+    }
+
+    if let Some(builder) = decoder.index.take() {
+        if complete {
+            builder.finish(source_len, is_compressed).save(&path_to_load);
+        }
+    }
+    let corrupted = decoder.skipped;
+    loaded.data = logs;
+    loaded.skipped += corrupted;
+    Ok(loaded)
+}
+
+/// File name of an archived segment, parsed back by `LogStore::new`
+fn segment_file_name(
+    owner: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    extension: &str,
+) -> String {
+    format!(
+        "segment_{}_{}_{}.{}",
+        owner,
+        start.format("%Y%m%d_%H%M%S"),
+        end.format("%H%M%S"),
+        extension
+    )
+}
+
+/// Whether a segment was compressed, after which its file no longer changes
+fn is_sealed(segment: &ArchivedSegment) -> bool {
+    segment.file_path.with_extension("lzma").exists()
+        && !segment.file_path.with_extension("bin").exists()
+}
+
+fn remove_segment_files(segment: &ArchivedSegment) {
+    for path in [
+        segment.file_path.with_extension("lzma"),
+        segment.file_path.with_extension("bin"),
+        SegmentIndex::path(&segment.file_path),
+    ] {
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                log::error!("Error deleting file {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Picks the oldest window of `window` length whose segments can be merged.
+///
+/// Windows are aligned to multiples of their length and must have ended
+/// before `now`, hold at least two segments and only segments accepted by
+/// `mergeable`. A window never spans midnight, file names only carry the
+/// time of day of the end.
+fn compaction_candidates(
+    segments: &BTreeMap<DateTime<Utc>, ArchivedSegment>,
+    window: Duration,
+    now: DateTime<Utc>,
+    mergeable: impl Fn(&ArchivedSegment) -> bool,
+) -> Option<(DateTime<Utc>, Vec<ArchivedSegment>)> {
+    let window_secs = window.num_seconds().max(1);
+    let mut windows: BTreeMap<i64, Vec<&ArchivedSegment>> = BTreeMap::new();
+    for segment in segments.values() {
+        let key = segment.start_time.timestamp().div_euclid(window_secs) * window_secs;
+        windows.entry(key).or_default().push(segment);
+    }
+
+    windows.into_iter().find_map(|(key, parts)| {
+        let window_start = Utc.timestamp_opt(key, 0).single()?;
+        let window_end = window_start + Duration::seconds(window_secs);
+        let same_day = parts
+            .iter()
+            .all(|part| part.end_time.date_naive() == window_start.date_naive());
+        if parts.len() < 2
+            || window_end > now
+            || !same_day
+            || !parts.iter().all(|part| mergeable(part))
         {
-            Ok((log, _)) => {
-                if log.date_time >= query_start_time && log.date_time <= query_end_time {
-                    loaded_logs_vec.push(log);
+            return None;
+        }
+        Some((window_start, parts.into_iter().cloned().collect()))
+    })
+}
+
+/// Splits segments into the ones to keep and the ones inside the time range
+/// of a longer segment, which a compaction already merged.
+fn split_covered(
+    mut segments: Vec<ArchivedSegment>,
+) -> (Vec<ArchivedSegment>, Vec<ArchivedSegment>) {
+    segments.sort_by(|a, b| {
+        a.start_time
+            .cmp(&b.start_time)
+            .then(b.end_time.cmp(&a.end_time))
+    });
+    let mut kept: Vec<ArchivedSegment> = Vec::new();
+    let mut covered = Vec::new();
+    for segment in segments {
+        match kept.last() {
+            Some(cover) if segment.end_time <= cover.end_time => covered.push(segment),
+            _ => kept.push(segment),
+        }
+    }
+    (kept, covered)
+}
+
+/// Merges sealed segments, oldest first, into one compressed block.
+///
+/// The block is written under a temporary name and renamed once complete,
+/// the merged segments are left for the store to remove.
+fn merge_segments(
+    owner: &str,
+    base_dir: &Path,
+    parts: &[ArchivedSegment],
+) -> io::Result<ArchivedSegment> {
+    let (Some(first), Some(last)) = (parts.first(), parts.last()) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "nothing to merge"));
+    };
+    let start_time = first.start_time;
+    let end_time = parts
+        .iter()
+        .map(|part| part.end_time)
+        .max()
+        .unwrap_or(last.end_time);
+
+    let mut records = Vec::new();
+    for part in parts {
+        let compressed = fs::read(part.file_path.with_extension("lzma"))?;
+        lzma_rs::lzma_decompress(&mut io::Cursor::new(&compressed), &mut records).map_err(
+            |e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("decompressing {}: {:?}", part.file_path.display(), e),
+                )
+            },
+        )?;
+    }
+
+    let temp_path = base_dir.join(format!("merging_{}_{}.tmp", owner, start_time.timestamp()));
+    let written = File::create(&temp_path).and_then(|file| {
+        let mut writer = io::BufWriter::new(file);
+        lzma_rs::lzma_compress(&mut io::Cursor::new(&records), &mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    let file_path = base_dir.join(segment_file_name(owner, start_time, end_time, "lzma"));
+    fs::rename(&temp_path, &file_path)?;
+    Ok(ArchivedSegment {
+        file_path,
+        start_time,
+        end_time,
+    })
+}
+
+/// Largest record a length prefix may announce, longer ones are corruption
+const MAX_RECORD_SIZE: usize = 64 * 1024;
+
+/// Decodes the length-prefixed records of a segment.
+///
+/// A record that doesn't decode, or whose length runs past the data, starts a
+/// corrupted region: the scan moves forward a byte at a time until a length
+/// prefix is followed by a record that decodes to exactly that length, and
+/// continues from there. Zero bytes up to the end are the unwritten tail of a
+/// segment, not corruption.
+///
+/// # Returns
+///
+/// The number of corrupted regions that were skipped.
+fn decode_entries(data: &[u8], mut keep: impl FnMut(TemporaryLog)) -> usize {
+    let mut decoder = RecordDecoder::default();
+    decoder.feed(data, true, &mut keep);
+    decoder.skipped
+}
+
+/// Incremental form of `decode_entries` for data that arrives in chunks.
+#[derive(Debug, Default)]
+struct RecordDecoder {
+    in_corruption: bool,
+    skipped: usize,
+    // Seconds range of a query, records outside it are stepped over without
+    // decoding more than their timestamp
+    window: Option<(i64, i64)>,
+    // Position in the segment's records of the data passed to the next feed
+    consumed: u64,
+    // Collects the index of a segment that has none yet
+    index: Option<IndexBuilder>,
+}
+
+impl RecordDecoder {
+    /// Decodes the records at the start of `data` and returns how many bytes
+    /// were used. Unless `at_end`, a record that may still be completed by
+    /// the next chunk is left for it.
+    fn feed(
+        &mut self,
+        data: &[u8],
+        at_end: bool,
+        keep: &mut impl FnMut(TemporaryLog),
+    ) -> usize {
+        let mut offset = 0;
+        while offset + 4 <= data.len() {
+            let entry_size = u32::from_ne_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ]) as usize;
+            if entry_size == 0 && data[offset..].iter().all(|byte| *byte == 0) {
+                if at_end {
+                    return self.used(data.len());
                 }
+                return self.used(offset);
             }
-            Err(_e) => {
-                break;
+            let complete = offset + 4 + entry_size <= data.len();
+            if !complete && !at_end && (1..=MAX_RECORD_SIZE).contains(&entry_size) {
+                return self.used(offset);
+            }
+            let entry = &data[offset + 4..data.len().min(offset + 4 + entry_size)];
+            if complete && !self.in_corruption && entry_size <= MAX_RECORD_SIZE {
+                if let Some(timestamp) = self.outside_window(entry) {
+                    self.index_record(offset, timestamp);
+                    offset += 4 + entry_size;
+                    continue;
+                }
+            }
+            if entry_size <= MAX_RECORD_SIZE {
+                if let Some(log) = decode_entry(data, offset + 4, entry_size) {
+                    self.index_record(offset, log.date_time.timestamp());
+                    keep(log);
+                    self.in_corruption = false;
+                    offset += 4 + entry_size;
+                    continue;
+                }
             }
+            if !self.in_corruption {
+                self.in_corruption = true;
+                self.skipped += 1;
+            }
+            offset += 1;
+        }
+        if !at_end {
+            return self.used(offset);
+        }
+        if !self.in_corruption && data[offset..].iter().any(|byte| *byte != 0) {
+            // A partial record at the very end
+            self.skipped += 1;
+        }
+        self.used(data.len())
+    }
+
+    fn used(&mut self, bytes: usize) -> usize {
+        self.consumed += bytes as u64;
+        bytes
+    }
+
+    fn index_record(&mut self, offset: usize, timestamp: i64) {
+        if let Some(index) = &mut self.index {
+            index.record(self.consumed + offset as u64, timestamp);
+        }
+    }
+
+    /// Timestamp of the record in `entry` when it lies outside the window.
+    fn outside_window(&self, entry: &[u8]) -> Option<i64> {
+        let (start, end) = self.window?;
+        match bincode::decode_from_slice::<i64, _>(entry, bincode::config::standard()) {
+            Ok((timestamp, _)) if timestamp < start || timestamp > end => Some(timestamp),
+            _ => None,
+        }
+    }
+}
+
+/// Records between two checkpoints of a segment index
+const INDEX_INTERVAL: usize = 1000;
+
+/// Sidecar index of a sealed segment, kept next to it with an `.idx` extension.
+///
+/// Built the first time a query reads the segment, and again once the
+/// segment file it was built from changed (e.g. got compressed).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SegmentIndex {
+    /// Size of the segment file the index was built from
+    source_len: u64,
+    /// Whether that file was compressed
+    compressed: bool,
+    min_timestamp: i64,
+    max_timestamp: i64,
+    checkpoints: Vec<IndexCheckpoint>,
+}
+
+/// Position of every `INDEX_INTERVAL`th record of a segment
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct IndexCheckpoint {
+    /// Byte offset of the record's length prefix in the decompressed records
+    offset: u64,
+    /// Newest timestamp of all records before it
+    max_before: i64,
+}
+
+impl SegmentIndex {
+    fn path(segment_file: &Path) -> PathBuf {
+        segment_file.with_extension("idx")
+    }
+
+    /// Reads the index of a segment, `None` when it's missing or stale.
+    fn load(segment_file: &Path, source_len: u64, compressed: bool) -> Option<Self> {
+        let raw = fs::read(Self::path(segment_file)).ok()?;
+        serde_json::from_slice::<Self>(&raw)
+            .ok()
+            .filter(|index| index.source_len == source_len && index.compressed == compressed)
+    }
+
+    fn save(&self, segment_file: &Path) {
+        let path = Self::path(segment_file);
+        let written = serde_json::to_vec(self)
+            .map_err(io::Error::from)
+            .and_then(|raw| fs::write(&path, raw));
+        if let Err(e) = written {
+            log::warn!("Failed to write segment index {}: {}", path.display(), e);
+        }
+    }
+
+    /// Whether any record of the segment lies in `start..=end` (seconds).
+    fn overlaps(&self, start: i64, end: i64) -> bool {
+        self.min_timestamp <= end && self.max_timestamp >= start
+    }
+
+    /// Offset to start decoding at without missing a record from `start` on.
+    fn seek(&self, start: i64) -> u64 {
+        self.checkpoints
+            .iter()
+            .rev()
+            .find(|checkpoint| checkpoint.max_before < start)
+            .map_or(0, |checkpoint| checkpoint.offset)
+    }
+}
+
+/// Collects a `SegmentIndex` from the records a decoder frames.
+#[derive(Debug, Default)]
+struct IndexBuilder {
+    records: usize,
+    min_timestamp: Option<i64>,
+    max_timestamp: Option<i64>,
+    checkpoints: Vec<IndexCheckpoint>,
+}
+
+impl IndexBuilder {
+    fn record(&mut self, offset: u64, timestamp: i64) {
+        if let Some(max_before) = self.max_timestamp {
+            if self.records % INDEX_INTERVAL == 0 {
+                self.checkpoints.push(IndexCheckpoint { offset, max_before });
+            }
+        }
+        self.records += 1;
+        self.min_timestamp = Some(self.min_timestamp.map_or(timestamp, |min| min.min(timestamp)));
+        self.max_timestamp = Some(self.max_timestamp.map_or(timestamp, |max| max.max(timestamp)));
+    }
+
+    fn finish(self, source_len: u64, compressed: bool) -> SegmentIndex {
+        SegmentIndex {
+            source_len,
+            compressed,
+            // An empty segment overlaps no query
+            min_timestamp: self.min_timestamp.unwrap_or(i64::MAX),
+            max_timestamp: self.max_timestamp.unwrap_or(i64::MIN),
+            checkpoints: self.checkpoints,
+        }
+    }
+}
+
+/// Receives the output of the LZMA decoder and decodes records as they
+/// arrive, so a compressed segment is never held decompressed in full.
+struct RecordSink<'a, F: FnMut(TemporaryLog)> {
+    decoder: RecordDecoder,
+    pending: Vec<u8>,
+    // Leading bytes an index showed to be before the query
+    skip: u64,
+    keep: &'a mut F,
+}
+
+impl<F: FnMut(TemporaryLog)> Write for RecordSink<'_, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let skipped = (self.skip.min(buf.len() as u64)) as usize;
+        self.skip -= skipped as u64;
+        self.pending.extend_from_slice(&buf[skipped..]);
+        let used = self.decoder.feed(&self.pending, false, self.keep);
+        self.pending.drain(..used);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An archived segment mapped read-only into memory.
+struct MappedSegment {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl MappedSegment {
+    /// Maps `path`, `None` when the file is empty.
+    fn open(path: &Path) -> io::Result<Option<Self>> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(None);
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // The mapping stays valid after the file is closed
+        Ok(Some(Self { ptr, len }))
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for MappedSegment {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
         }
-        offset += entry_size;
     }
-    Ok(loaded_logs_vec)
+}
+
+/// Decodes the record of `size` bytes at `start`, if it is one.
+fn decode_entry(data: &[u8], start: usize, size: usize) -> Option<TemporaryLog> {
+    if size == 0 || start + size > data.len() {
+        return None;
+    }
+    match bincode::decode_from_slice::<TemporaryLog, _>(
+        &data[start..start + size],
+        bincode::config::standard(),
+    ) {
+        Ok((log, read)) if read == size => Some(log),
+        _ => None,
+    }
 }
 
 #[allow(static_mut_refs)]
 pub fn init() {
     unsafe {
         if PROXY_LOG_STORE.is_none() {
-            PROXY_LOG_STORE = Some(LogStore::new("proxy".to_string()));
+            PROXY_LOG_STORE = Some(LogStore::new("proxy".to_string(), config::log_archive_dir()));
         }
         if GATEWAY_LOG_STORE.is_none() {
-            GATEWAY_LOG_STORE = Some(LogStore::new("gateway".to_string()));
+            GATEWAY_LOG_STORE = Some(LogStore::new("gateway".to_string(), config::log_archive_dir()));
         }
     }
 }
@@ -1328,6 +2303,42 @@ pub fn init() {
 #[allow(static_mut_refs, dead_code)]
 pub mod tlog_proxy {
     use super::*;
+    pub fn get_level_counts(
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        min_level: u8,
+    ) -> Result<Queried<Vec<LogLevelTimeframe>>, LogStoreError> {
+        unsafe {
+            if PROXY_LOG_STORE.is_none() {
+                init();
+            }
+            PROXY_LOG_STORE
+                .as_ref()
+                .ok_or_else(|| {
+                    LogStoreError::IoError(io::Error::new(
+                        io::ErrorKind::Other,
+                        "Proxy log store not initialized",
+                    ))
+                })?
+                .get_level_counts(start, end, min_level)
+        }
+    }
+    pub fn latest_timestamp() -> Result<Option<DateTime<Utc>>, LogStoreError> {
+        unsafe {
+            if PROXY_LOG_STORE.is_none() {
+                init();
+            }
+            PROXY_LOG_STORE
+                .as_ref()
+                .ok_or_else(|| {
+                    LogStoreError::IoError(io::Error::new(
+                        io::ErrorKind::Other,
+                        "Proxy log store not initialized",
+                    ))
+                })?
+                .latest_timestamp()
+        }
+    }
     pub fn append_data(log: TemporaryLog) -> Result<(), LogStoreError> {
         unsafe {
             if PROXY_LOG_STORE.is_none() {
@@ -1347,7 +2358,7 @@ pub mod tlog_proxy {
     pub fn get_data_time_frame(
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
         unsafe {
             if PROXY_LOG_STORE.is_none() {
                 init();
@@ -1367,7 +2378,7 @@ pub mod tlog_proxy {
         start: DateTime<Utc>,
         end: DateTime<Utc>,
         status_filter: i32,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
         unsafe {
             if PROXY_LOG_STORE.is_none() {
                 init();
@@ -1386,7 +2397,7 @@ pub mod tlog_proxy {
     pub fn get_data_time_frame_by_conn_stall(
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
         unsafe {
             if PROXY_LOG_STORE.is_none() {
                 init();
@@ -1406,7 +2417,7 @@ pub mod tlog_proxy {
         start: DateTime<Utc>,
         end: DateTime<Utc>,
         metric: BytesMetric,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
         unsafe {
             if PROXY_LOG_STORE.is_none() {
                 init();
@@ -1427,6 +2438,26 @@ pub mod tlog_proxy {
 #[allow(static_mut_refs, dead_code)]
 pub mod tlog_gateway {
     use super::*;
+    pub fn get_level_counts(
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        min_level: u8,
+    ) -> Result<Queried<Vec<LogLevelTimeframe>>, LogStoreError> {
+        unsafe {
+            if GATEWAY_LOG_STORE.is_none() {
+                init();
+            }
+            GATEWAY_LOG_STORE
+                .as_ref()
+                .ok_or_else(|| {
+                    LogStoreError::IoError(io::Error::new(
+                        io::ErrorKind::Other,
+                        "Gateway log store not initialized",
+                    ))
+                })?
+                .get_level_counts(start, end, min_level)
+        }
+    }
     pub fn append_data(log: TemporaryLog) -> Result<(), LogStoreError> {
         unsafe {
             if GATEWAY_LOG_STORE.is_none() {
@@ -1446,7 +2477,7 @@ pub mod tlog_gateway {
     pub fn get_data_time_frame(
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
         unsafe {
             if GATEWAY_LOG_STORE.is_none() {
                 init();
@@ -1466,7 +2497,7 @@ pub mod tlog_gateway {
         start: DateTime<Utc>,
         end: DateTime<Utc>,
         status_filter: i32,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
         unsafe {
             if GATEWAY_LOG_STORE.is_none() {
                 init();
@@ -1485,7 +2516,7 @@ pub mod tlog_gateway {
     pub fn get_data_time_frame_by_conn_stall(
         start: DateTime<Utc>,
         end: DateTime<Utc>,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
         unsafe {
             if GATEWAY_LOG_STORE.is_none() {
                 init();
@@ -1505,7 +2536,7 @@ pub mod tlog_gateway {
         start: DateTime<Utc>,
         end: DateTime<Utc>,
         metric: BytesMetric,
-    ) -> Result<Vec<LogCaptureTimeframe>, LogStoreError> {
+    ) -> Result<Queried<Vec<LogCaptureTimeframe>>, LogStoreError> {
         unsafe {
             if GATEWAY_LOG_STORE.is_none() {
                 init();
@@ -1522,3 +2553,414 @@ pub mod tlog_gateway {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn record(date_time: DateTime<Utc>, level: u8) -> TemporaryLog {
+        TemporaryLog {
+            date_time,
+            status_code: 200,
+            peer: ("127.0.0.1:3000".to_string(), "127.0.0.1:3004".to_string()),
+            conn_id: "42".to_string(),
+            conn_type: ConnType::Close,
+            conn_req: 0,
+            conn_res: 1,
+            bytes_in: 10,
+            bytes_out: 20,
+            level,
+            ws_frames: WsFrameCounts::default(),
+        }
+    }
+
+    #[test]
+    fn test_level_survives_encoding() {
+        let log = record(Utc::now(), 4);
+        let bytes = bincode::encode_to_vec(&log, bincode::config::standard()).unwrap();
+        let (decoded, _): (TemporaryLog, _) =
+            bincode::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+        assert_eq!(decoded.level, 4);
+
+        // Records written before the level existed end right before it,
+        // frame counts of 0 take one byte each
+        let (decoded, _): (TemporaryLog, _) =
+            bincode::decode_from_slice(&bytes[..bytes.len() - 8], bincode::config::standard())
+                .unwrap();
+        assert_eq!(decoded.level, DEFAULT_RECORD_LEVEL);
+        assert_eq!(decoded.bytes_out, 20);
+    }
+
+    #[test]
+    fn test_prepare_archive_dir() {
+        let dir = std::env::temp_dir()
+            .join(format!("gwrs-archive-{}", std::process::id()))
+            .join("logment");
+        prepare_archive_dir(&dir).unwrap();
+        let mode = fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        // An existing directory keeps the permissions the operator gave it
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o750)).unwrap();
+        prepare_archive_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o750);
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_overlapping_sources_count_once() {
+        let dir = std::env::temp_dir().join(format!("gwrs-dedupe-{}", std::process::id()));
+        let mut store = LogStore::new("test".to_string(), dir.clone());
+        let now = Utc::now();
+        // Request and response of a connection logged in the same second share a key
+        let mut request = record(now, 2);
+        request.conn_type = ConnType::from_log_type("REQ");
+        store.append_data(request).unwrap();
+        store.append_data(record(now, 2)).unwrap();
+        store.append_data(record(now + Duration::seconds(1), 2)).unwrap();
+
+        // Every record is in the cache and the active segment's file
+        let logs = store
+            .load_logs(now - Duration::seconds(5), now + Duration::seconds(5))
+            .unwrap()
+            .data;
+        assert_eq!(logs.len(), 3);
+        assert_eq!(logs.iter().filter(|log| log.conn_type == ConnType::from_log_type("REQ")).count(), 1);
+        // The cache is the only copy in memory
+        assert_eq!(store.current_logs.len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_time_frame_counts_distinct_appended_logs() {
+        let dir = std::env::temp_dir().join(format!("gwrs-frames-{}", std::process::id()));
+        let mut store = LogStore::new("test".to_string(), dir.clone());
+        let now = Utc::now();
+        for i in 0..7 {
+            let mut log = record(now - Duration::seconds(i), 2);
+            log.conn_id = i.to_string();
+            if i < 4 {
+                log.conn_type = ConnType::from_log_type("REQ");
+                (log.conn_req, log.conn_res) = (1, 0);
+            }
+            store.append_data(log).unwrap();
+        }
+
+        let frames = store
+            .get_data_time_frame(now - Duration::seconds(30), now + Duration::seconds(1))
+            .unwrap()
+            .data;
+        assert_eq!(frames.iter().map(|frame| frame.low).sum::<i32>(), 4);
+        assert_eq!(frames.iter().map(|frame| frame.high).sum::<i32>(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_conn_type_survives_encoding() {
+        let mut log = record(Utc::now(), 2);
+        log.conn_type = ConnType::from_log_type("UPSTREAM[ON]");
+        assert_eq!(log.conn_type, ConnType::UpstreamRes);
+        let bytes = bincode::encode_to_vec(&log, bincode::config::standard()).unwrap();
+        let (decoded, _): (TemporaryLog, _) =
+            bincode::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+        assert_eq!(decoded.conn_type, ConnType::UpstreamRes);
+        assert_eq!(decoded.bytes_out, 20);
+        assert_eq!(ConnType::from_code(ConnType::from_log_type("REJECT").code()), ConnType::Reject);
+
+        // Records written when the type was a string keep the fields after it
+        let mut legacy = Vec::new();
+        let config = bincode::config::standard();
+        let now = Utc::now();
+        legacy.extend(bincode::encode_to_vec(now.timestamp(), config).unwrap());
+        legacy.extend(bincode::encode_to_vec(now.timestamp_subsec_nanos(), config).unwrap());
+        legacy.extend(bincode::encode_to_vec(200i32, config).unwrap());
+        legacy.extend(bincode::encode_to_vec("a", config).unwrap());
+        legacy.extend(bincode::encode_to_vec("b", config).unwrap());
+        legacy.extend(bincode::encode_to_vec("42", config).unwrap());
+        legacy.extend(bincode::encode_to_vec("HTTP", config).unwrap());
+        legacy.extend(bincode::encode_to_vec((1i8, 0i8, 7i32, 9i32), config).unwrap());
+        let (decoded, _): (TemporaryLog, _) = bincode::decode_from_slice(&legacy, config).unwrap();
+        assert_eq!(decoded.conn_type, ConnType::Other);
+        assert_eq!((decoded.conn_req, decoded.bytes_in, decoded.bytes_out), (1, 7, 9));
+    }
+
+    #[test]
+    fn test_decode_entries_resyncs_after_corruption() {
+        let start = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+        let mut segment = Vec::new();
+        let mut offsets = Vec::new();
+        for i in 0..3 {
+            let bytes = bincode::encode_to_vec(
+                record(start + Duration::seconds(i), 2),
+                bincode::config::standard(),
+            )
+            .unwrap();
+            offsets.push(segment.len());
+            segment.extend((bytes.len() as u32).to_ne_bytes());
+            segment.extend(bytes);
+        }
+        segment.extend([0u8; 64]);
+
+        let mut intact = Vec::new();
+        assert_eq!(decode_entries(&segment, |log| intact.push(log.date_time)), 0);
+        assert_eq!(intact.len(), 3);
+
+        // A record cut off at the end
+        let mut recovered = Vec::new();
+        let truncated = &segment[..offsets[2] + 6];
+        assert_eq!(decode_entries(truncated, |log| recovered.push(log.date_time)), 1);
+        assert_eq!(recovered, vec![start, start + Duration::seconds(1)]);
+
+        // Break the length prefix of the middle record
+        segment[offsets[1]..offsets[1] + 4].copy_from_slice(&u32::MAX.to_ne_bytes());
+        let mut recovered = Vec::new();
+        assert_eq!(decode_entries(&segment, |log| recovered.push(log.date_time)), 1);
+        assert_eq!(recovered, vec![start, start + Duration::seconds(2)]);
+    }
+
+    #[test]
+    fn test_streamed_records_match_whole_segment() {
+        let start = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+        let mut segment = Vec::new();
+        for i in 0..20 {
+            let bytes = bincode::encode_to_vec(
+                record(start + Duration::seconds(i), 2),
+                bincode::config::standard(),
+            )
+            .unwrap();
+            segment.extend((bytes.len() as u32).to_ne_bytes());
+            segment.extend(bytes);
+        }
+        segment[100] ^= 0xff;
+
+        let mut whole = Vec::new();
+        let skipped = decode_entries(&segment, |log| whole.push(log.date_time));
+
+        let mut streamed = Vec::new();
+        let mut keep = |log: TemporaryLog| streamed.push(log.date_time);
+        let mut sink = RecordSink {
+            decoder: RecordDecoder::default(),
+            pending: Vec::new(),
+            skip: 0,
+            keep: &mut keep,
+        };
+        for chunk in segment.chunks(7) {
+            sink.write_all(chunk).unwrap();
+        }
+        let RecordSink {
+            mut decoder,
+            pending,
+            keep,
+        } = sink;
+        decoder.feed(&pending, true, keep);
+        assert_eq!(decoder.skipped, skipped);
+        assert_eq!(streamed, whole);
+        assert!(whole.len() >= 17);
+
+        // Records outside the window aren't decoded at all
+        let mut decoder = RecordDecoder {
+            window: Some((start.timestamp() + 5, start.timestamp() + 9)),
+            ..Default::default()
+        };
+        let mut kept = Vec::new();
+        decoder.feed(&segment, true, &mut |log: TemporaryLog| kept.push(log.date_time));
+        assert_eq!(kept.len(), 5);
+    }
+
+    #[test]
+    fn test_segment_index_seeks_near_query_start() {
+        let start = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+        let mut segment = Vec::new();
+        for i in 0..2500 {
+            let bytes = bincode::encode_to_vec(
+                record(start + Duration::seconds(i), 2),
+                bincode::config::standard(),
+            )
+            .unwrap();
+            segment.extend((bytes.len() as u32).to_ne_bytes());
+            segment.extend(bytes);
+        }
+
+        let mut decoder = RecordDecoder {
+            index: Some(IndexBuilder::default()),
+            ..Default::default()
+        };
+        decoder.feed(&segment, true, &mut |_| {});
+        let index = decoder.index.take().unwrap().finish(segment.len() as u64, false);
+        assert_eq!(index.checkpoints.len(), 2);
+        assert_eq!(
+            (index.min_timestamp, index.max_timestamp),
+            (start.timestamp(), start.timestamp() + 2499)
+        );
+        assert!(!index.overlaps(start.timestamp() + 2500, start.timestamp() + 3000));
+
+        // Decoding from the seek position finds every record of the query
+        let query = (start.timestamp() + 2100, start.timestamp() + 2200);
+        let seek = index.seek(query.0);
+        assert_eq!(seek, index.checkpoints[1].offset);
+        let mut decoder = RecordDecoder {
+            window: Some(query),
+            consumed: seek,
+            ..Default::default()
+        };
+        let mut found = 0;
+        decoder.feed(&segment[seek as usize..], true, &mut |_| found += 1);
+        assert_eq!((found, decoder.skipped), (101, 0));
+        assert_eq!(index.seek(start.timestamp()), 0);
+    }
+
+    fn segment(start: DateTime<Utc>, minutes: i64) -> ArchivedSegment {
+        let end = start + Duration::minutes(minutes);
+        ArchivedSegment {
+            file_path: PathBuf::from(segment_file_name("gateway", start, end, "lzma")),
+            start_time: start,
+            end_time: end,
+        }
+    }
+
+    #[test]
+    fn test_compaction_picks_ended_windows() {
+        // 12:00 UTC
+        let noon = Utc.timestamp_opt(1_700_049_600, 0).unwrap();
+        let segments: BTreeMap<_, _> = (0..7)
+            .map(|i| {
+                let part = segment(noon + Duration::minutes(i), 1);
+                (part.start_time, part)
+            })
+            .collect();
+
+        let (window_start, parts) = compaction_candidates(
+            &segments,
+            Duration::minutes(5),
+            noon + Duration::minutes(7),
+            |_| true,
+        )
+        .unwrap();
+        assert_eq!(window_start, noon);
+        assert_eq!(parts.len(), 5);
+        assert_eq!(parts[4].start_time, noon + Duration::minutes(4));
+
+        // The window from 12:05 hasn't ended yet
+        let pending: BTreeMap<_, _> = segments.into_iter().skip(5).collect();
+        assert!(compaction_candidates(
+            &pending,
+            Duration::minutes(5),
+            noon + Duration::minutes(7),
+            |_| true
+        )
+        .is_none());
+
+        // Nor is a window merged while a segment is still being compressed
+        let segments: BTreeMap<_, _> = (0..5)
+            .map(|i| {
+                let part = segment(noon + Duration::minutes(i), 1);
+                (part.start_time, part)
+            })
+            .collect();
+        assert!(compaction_candidates(
+            &segments,
+            Duration::minutes(5),
+            noon + Duration::minutes(7),
+            |part| part.start_time != noon + Duration::minutes(3)
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_split_covered_drops_merged_segments() {
+        let noon = Utc.timestamp_opt(1_700_049_600, 0).unwrap();
+        let block = segment(noon, 5);
+        let found = vec![
+            segment(noon + Duration::minutes(1), 1),
+            segment(noon, 1),
+            block.clone(),
+            segment(noon + Duration::minutes(5), 1),
+        ];
+        let (kept, covered) = split_covered(found);
+        assert_eq!(
+            kept.iter().map(|part| (part.start_time, part.end_time)).collect::<Vec<_>>(),
+            vec![
+                (block.start_time, block.end_time),
+                (noon + Duration::minutes(5), noon + Duration::minutes(6)),
+            ]
+        );
+        assert_eq!(covered.len(), 2);
+    }
+
+    #[test]
+    fn test_bytes_io_frames_per_metric() {
+        let start = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+        let end = start + Duration::seconds(30);
+        let mut first = record(start, 2);
+        (first.bytes_in, first.bytes_out) = (100, 300);
+        let mut second = record(start + Duration::seconds(1), 2);
+        (second.bytes_in, second.bytes_out) = (200, 500);
+        let logs = vec![first, second];
+
+        for (metric, value, high, low) in [
+            (BytesMetric::BytesIn, 150, 200, 100),
+            (BytesMetric::BytesOut, 400, 500, 300),
+            (BytesMetric::BytesTotal, 550, 700, 400),
+        ] {
+            let frames = bytes_io_frames(&logs, start, end, metric);
+            assert_eq!(frames.len(), 3, "{:?}", metric);
+            assert_eq!(
+                (frames[0].value, frames[0].high, frames[0].low),
+                (value, high, low),
+                "{:?}",
+                metric
+            );
+            assert!(frames[1..].iter().all(|frame| frame.value == 0));
+        }
+        assert_eq!(BytesMetric::parse("out"), Some(BytesMetric::BytesOut));
+        assert_eq!(BytesMetric::parse("bytes"), None);
+    }
+
+    #[test]
+    fn test_response_times_pair_request_and_response() {
+        let start = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+        let mut request = record(start, 2);
+        request.conn_type = ConnType::from_log_type("REQ");
+        request.status_code = 0;
+        let mut response = record(start + Duration::milliseconds(250), 2);
+        response.conn_type = ConnType::from_log_type("RES");
+        let mut other = record(start + Duration::milliseconds(400), 2);
+        other.conn_type = ConnType::from_log_type("CLOSE");
+
+        let (times, counts) = response_times(&[request, response, other], 200);
+        let interval = start.timestamp() / 15;
+        assert_eq!(times.get(&interval), Some(&vec![250]));
+        assert_eq!(counts.get(&interval), Some(&2));
+    }
+
+    #[test]
+    fn test_count_levels_per_interval() {
+        let start = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+        let end = start + Duration::seconds(30);
+        let logs = vec![
+            record(start, 2),
+            record(start + Duration::seconds(1), 4),
+            record(start + Duration::seconds(20), 3),
+            record(start + Duration::seconds(21), 4),
+        ];
+
+        let frames = count_levels(&logs, start, end, 0);
+        assert_eq!(frames.len(), 3);
+        assert_eq!((frames[0].info, frames[0].error), (1, 1));
+        assert_eq!((frames[1].warn, frames[1].error), (1, 1));
+        assert_eq!(
+            frames[2],
+            LogLevelTimeframe {
+                date_time: frames[2].date_time,
+                ..Default::default()
+            }
+        );
+
+        // The stored records stay complete, the minimum only applies to the counts
+        let frames = count_levels(&logs, start, end, 3);
+        assert_eq!((frames[0].info, frames[0].error), (0, 1));
+        assert_eq!((frames[1].warn, frames[1].error), (1, 1));
+    }
+}
@@ -0,0 +1,77 @@
+//! # Trash Purging
+//!
+//! Deleted proxies and gateways are only moved to the trash so an accidental
+//! delete can be restored. A background thread permanently removes the items
+//! that have been in the trash for longer than `GWRS_TRASH_RETENTION_DAYS`
+//! (default 30, 0 keeps them forever).
+
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::api::settings::{gateway_queries, proxy_queries};
+use crate::config;
+
+/// Interval between two purges of the purger thread
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Items deleted at or before this timestamp have outlived the retention.
+///
+/// Saturates, so a huge retention keeps everything instead of wrapping to a
+/// cutoff in the future that would purge the whole trash.
+fn purge_cutoff(retention_days: i64, now: i64) -> i64 {
+    now.saturating_sub(retention_days.saturating_mul(SECONDS_PER_DAY))
+}
+
+/// Permanently deletes every trashed item older than `retention_days`.
+fn purge(retention_days: i64) {
+    let cutoff = purge_cutoff(retention_days, Utc::now().timestamp());
+
+    match gateway_queries::purge_trashed_gateways(cutoff) {
+        Ok(0) => {}
+        Ok(count) => log::info!("Purged {} gateways from the trash", count),
+        Err(e) => log::error!("Failed to purge deleted gateways: {}", e),
+    }
+    match proxy_queries::purge_trashed_proxies(cutoff) {
+        Ok(0) => {}
+        Ok(count) => log::info!("Purged {} proxies from the trash", count),
+        Err(e) => log::error!("Failed to purge deleted proxies: {}", e),
+    }
+}
+
+/// Starts the background thread purging the trash, unless retention is disabled.
+pub fn spawn_purger() {
+    let retention_days = config::trash_retention_days();
+    if retention_days == 0 {
+        log::info!("Trash retention is 0, deleted items are kept until restored");
+        return;
+    }
+
+    std::thread::spawn(move || {
+        log::info!(
+            "Trash purger started, keeping deleted items for {} days",
+            retention_days
+        );
+        loop {
+            purge(retention_days);
+            std::thread::sleep(PURGE_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_purge_cutoff() {
+        let now = 1_700_000_000;
+        assert_eq!(purge_cutoff(30, now), now - 30 * SECONDS_PER_DAY);
+        assert_eq!(purge_cutoff(1, now), now - SECONDS_PER_DAY);
+        // A huge retention keeps everything instead of overflowing
+        assert_eq!(purge_cutoff(i64::MAX, now), now - i64::MAX);
+        assert!(purge_cutoff(i64::MAX / SECONDS_PER_DAY + 1, now) < 0);
+    }
+}
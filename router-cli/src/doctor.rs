@@ -0,0 +1,432 @@
+//! `gwrs doctor` - checks step by step whether the CLI can work with the router.
+//!
+//! Every check prints one pass/fail line, and a failure is followed by a hint
+//! on how to fix it. Checks that build on a failed one are skipped, so the
+//! first failure is the one to look at. The output is meant to be pasted into
+//! a support request as is and never contains the password or the token.
+
+use anyhow::Result;
+use log::debug;
+use serde_json::Value;
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{authenticate, read_config, ConfigFormat, LoginResponse};
+
+/// Timeout of the TCP connect and of every request
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Settings for a doctor run
+pub struct DoctorOptions {
+    pub base_url: String,
+    /// Resolved credentials, or why they couldn't be resolved
+    pub credentials: Result<(String, String)>,
+    /// Local configuration file to compare with the router's
+    pub config: Option<PathBuf>,
+}
+
+/// Outcome of one check
+enum Status {
+    Pass(String),
+    Fail { detail: String, hint: String },
+    Skip(&'static str),
+}
+
+/// Collects and prints the checklist
+#[derive(Default)]
+struct Report {
+    failed: usize,
+}
+
+impl Report {
+    fn check(&mut self, name: &str, status: Status) {
+        match status {
+            Status::Pass(detail) => println!("[PASS] {}: {}", name, detail),
+            Status::Fail { detail, hint } => {
+                self.failed += 1;
+                println!("[FAIL] {}: {}", name, detail);
+                println!("       hint: {}", hint);
+            }
+            Status::Skip(reason) => println!("[SKIP] {}: {}", name, reason),
+        }
+    }
+}
+
+/// Runs every check, failing when at least one of them failed.
+pub fn run(options: &DoctorOptions) -> Result<()> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let mut report = Report::default();
+    println!("Checking the router API at {}\n", options.base_url);
+
+    let reachable = check_connect(&options.base_url);
+    let reachable_ok = matches!(reachable, Status::Pass(_));
+    report.check("Connect", reachable);
+
+    report.check(
+        "Health",
+        if reachable_ok {
+            check_health(&agent, &options.base_url)
+        } else {
+            Status::Skip("router API not reachable")
+        },
+    );
+
+    let credentials = match &options.credentials {
+        Ok((username, password)) => {
+            report.check("Credentials", Status::Pass(format!("user '{}'", username)));
+            Some((username.as_str(), password.as_str()))
+        }
+        Err(e) => {
+            report.check(
+                "Credentials",
+                Status::Fail {
+                    detail: format!("{:#}", e),
+                    hint: "pass --user and --pass, or --osenv with GWRS_USER and GWRS_PASS set".to_string(),
+                },
+            );
+            None
+        }
+    };
+
+    let token = match credentials {
+        Some((username, password)) if reachable_ok => {
+            let (status, token) = check_login(&options.base_url, username, password);
+            report.check("Login", status);
+            token
+        }
+        Some(_) => {
+            report.check("Login", Status::Skip("router API not reachable"));
+            None
+        }
+        None => {
+            report.check("Login", Status::Skip("no credentials"));
+            None
+        }
+    };
+
+    let version = match &token {
+        Some(token) => {
+            let (status, version) = check_token(&agent, &options.base_url, token);
+            report.check("Access", status);
+            version
+        }
+        None => {
+            report.check("Access", Status::Skip("not logged in"));
+            None
+        }
+    };
+
+    if let Some(config) = &options.config {
+        let status = match (&token, version) {
+            (Some(token), Some(version)) => check_config(&agent, &options.base_url, token, config, version),
+            _ => Status::Skip("no access to the router settings"),
+        };
+        report.check("Config", status);
+    }
+
+    println!();
+    if report.failed > 0 {
+        anyhow::bail!("{} check(s) failed", report.failed);
+    }
+    println!("All checks passed");
+    Ok(())
+}
+
+/// Opens a TCP connection to the host and port of the API URL
+fn check_connect(base_url: &str) -> Status {
+    let Some(address) = host_port(base_url) else {
+        return Status::Fail {
+            detail: format!("can't read a host from '{}'", base_url),
+            hint: "--url must look like http://host:port, e.g. http://localhost:24042".to_string(),
+        };
+    };
+
+    let addrs = match address.to_socket_addrs() {
+        Ok(addrs) => addrs.collect::<Vec<_>>(),
+        Err(e) => {
+            return Status::Fail {
+                detail: format!("can't resolve {}: {}", address, e),
+                hint: "check the host name in --url and the DNS of this machine".to_string(),
+            }
+        }
+    };
+    let mut last_error = None;
+    for addr in &addrs {
+        debug!("Connecting to {}", addr);
+        match TcpStream::connect_timeout(addr, TIMEOUT) {
+            Ok(_) => return Status::Pass(format!("{} accepts connections", addr)),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Status::Fail {
+        detail: match last_error {
+            Some(e) => format!("{}: {}", address, e),
+            None => format!("{} resolves to no address", address),
+        },
+        hint: "start router-api, or point --url at the host and port it listens on (default 24042); \
+               check firewalls between this machine and the router"
+            .to_string(),
+    }
+}
+
+/// `host:port` of a URL, with the default port of its scheme
+fn host_port(url: &str) -> Option<String> {
+    let (default_port, rest) = match url.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("https") => (443, rest),
+        Some((_, rest)) => (80, rest),
+        None => (80, url),
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    if authority.is_empty() {
+        return None;
+    }
+    // A colon after the closing bracket of an IPv6 literal, or in a plain host, starts the port
+    let has_port = match authority.rfind(']') {
+        Some(bracket) => authority[bracket..].contains(':'),
+        None => authority.contains(':'),
+    };
+    Some(if has_port {
+        authority.to_string()
+    } else {
+        format!("{}:{}", authority, default_port)
+    })
+}
+
+/// Asks the unauthenticated health endpoint how the router is doing
+fn check_health(agent: &ureq::Agent, base_url: &str) -> Status {
+    let url = format!("{}/api/v1/health", base_url);
+    debug!("GET {}", url);
+    match agent.get(&url).call() {
+        Ok(_) => Status::Pass("router healthy".to_string()),
+        Err(ureq::Error::Status(503, response)) => {
+            let failing = response
+                .into_json::<Value>()
+                .ok()
+                .and_then(|body| body.get("failing").cloned())
+                .and_then(|failing| serde_json::from_value::<Vec<String>>(failing).ok())
+                .unwrap_or_default();
+            Status::Fail {
+                detail: format!("router unhealthy, failing: {}", failing.join(", ")),
+                hint: "the API answers but some of its dependencies don't; a failing `core` means the \
+                       router core isn't running or can't be reached, see the router logs"
+                    .to_string(),
+            }
+        }
+        Err(ureq::Error::Status(status, _)) => Status::Fail {
+            detail: format!("{} answered {}", url, status),
+            hint: "--url may point at a proxy listener or another service instead of router-api".to_string(),
+        },
+        Err(e) => Status::Fail {
+            detail: e.to_string(),
+            hint: "the port accepts connections but doesn't answer HTTP; check that --url is router-api \
+                   and uses http:// or https:// as the API is served"
+                .to_string(),
+        },
+    }
+}
+
+/// Logs in, returning the token on success
+fn check_login(base_url: &str, username: &str, password: &str) -> (Status, Option<String>) {
+    match authenticate(base_url, username, password) {
+        Ok(token) => (Status::Pass(format!("logged in as '{}'", username)), Some(token)),
+        Err(e) => {
+            let (rejected, detail) = match e.downcast_ref::<ureq::Error>() {
+                Some(ureq::Error::Status(status, _)) => {
+                    let rejected = matches!(*status, 400 | 401 | 403);
+                    (rejected, login_message(e))
+                }
+                Some(_) => (false, format!("{:#}", e)),
+                // The router answered but refused the login
+                None => (true, format!("{:#}", e)),
+            };
+            let hint = if rejected {
+                "check the username and password; an administrator can reset the password in the router UI"
+            } else {
+                "the login endpoint failed, see the router-api logs"
+            };
+            (Status::Fail { detail, hint: hint.to_string() }, None)
+        }
+    }
+}
+
+/// The router's message in a failed login response, if it sent one
+fn login_message(e: anyhow::Error) -> String {
+    match e.downcast::<ureq::Error>() {
+        Ok(ureq::Error::Status(status, response)) => match response.into_json::<LoginResponse>() {
+            Ok(body) => format!("{} ({})", body.message, status),
+            Err(_) => format!("login answered {}", status),
+        },
+        Ok(e) => e.to_string(),
+        Err(e) => format!("{:#}", e),
+    }
+}
+
+/// Uses the token on an admin endpoint, returning the config version on success
+fn check_token(agent: &ureq::Agent, base_url: &str, token: &str) -> (Status, Option<u64>) {
+    let url = format!("{}/api/v1/settings/version", base_url);
+    debug!("GET {}", url);
+    let result = agent
+        .get(&url)
+        .set("Authorization", &format!("Bearer {}", token))
+        .call();
+    match result {
+        Ok(response) => {
+            let version = response
+                .into_json::<Value>()
+                .ok()
+                .and_then(|body| body.get("version").and_then(Value::as_u64));
+            match version {
+                Some(version) => (Status::Pass(format!("settings readable, config version {}", version)), Some(version)),
+                None => (
+                    Status::Fail {
+                        detail: "unexpected answer of the config version endpoint".to_string(),
+                        hint: "the CLI and router-api versions may not match, update the older one".to_string(),
+                    },
+                    None,
+                ),
+            }
+        }
+        Err(ureq::Error::Status(401, _)) => (
+            Status::Fail {
+                detail: "token rejected".to_string(),
+                hint: "the token expired right away or was signed with another secret; check the clock of the \
+                       router and restart router-api if its JWT secret changed"
+                    .to_string(),
+            },
+            None,
+        ),
+        Err(ureq::Error::Status(403, _)) => (
+            Status::Fail {
+                detail: "the user isn't allowed to read the settings".to_string(),
+                hint: "uploading and exporting configurations needs an admin user".to_string(),
+            },
+            None,
+        ),
+        Err(e) => (
+            Status::Fail {
+                detail: e.to_string(),
+                hint: "see the router-api logs".to_string(),
+            },
+            None,
+        ),
+    }
+}
+
+/// Compares a local configuration file with the router's current configuration
+fn check_config(agent: &ureq::Agent, base_url: &str, token: &str, config: &PathBuf, version: u64) -> Status {
+    let local = match read_config(config).and_then(|contents| parse_config(&contents, ConfigFormat::from_path(config))) {
+        Ok(local) => local,
+        Err(e) => {
+            return Status::Fail {
+                detail: format!("{}: {:#}", config.display(), e),
+                hint: "fix the file, 'gwrs init' writes a template to start from".to_string(),
+            }
+        }
+    };
+
+    let url = format!("{}/api/v1/settings/auto-config", base_url);
+    debug!("GET {}", url);
+    let remote = agent
+        .get(&url)
+        .set("Authorization", &format!("Bearer {}", token))
+        .call()
+        .map_err(anyhow::Error::from)
+        .and_then(|response| Ok(response.into_string()?))
+        .and_then(|contents| parse_config(&contents, ConfigFormat::Yaml));
+    let remote = match remote {
+        Ok(remote) => remote,
+        Err(e) => {
+            return Status::Fail {
+                detail: format!("can't export the router's configuration: {:#}", e),
+                hint: "see the router-api logs".to_string(),
+            }
+        }
+    };
+
+    let differences = compare_proxies(&local, &remote);
+    if differences.is_empty() {
+        return Status::Pass(format!("{} matches config version {}", config.display(), version));
+    }
+    Status::Fail {
+        detail: format!(
+            "{} differs from config version {}: {}",
+            config.display(),
+            version,
+            differences.join("; ")
+        ),
+        hint: format!(
+            "upload it with 'gwrs config {}', or 'gwrs export' the router's configuration to compare",
+            config.display()
+        ),
+    }
+}
+
+/// Parses a configuration file into a value with unset fields left out
+fn parse_config(contents: &str, format: ConfigFormat) -> Result<Value> {
+    format.validate(contents)?;
+    let mut value = match format {
+        ConfigFormat::Yaml => serde_yaml::from_str::<Value>(contents)?,
+        ConfigFormat::Json => serde_json::from_str::<Value>(contents)?,
+    };
+    prune(&mut value);
+    Ok(value)
+}
+
+/// Drops `null`, `false` and empty fields, the router leaves them out of exports
+fn prune(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.values_mut().for_each(prune);
+            object.retain(|_, field| match field {
+                Value::Null | Value::Bool(false) => false,
+                Value::Array(items) => !items.is_empty(),
+                Value::Object(fields) => !fields.is_empty(),
+                _ => true,
+            });
+        }
+        Value::Array(items) => items.iter_mut().for_each(prune),
+        _ => {}
+    }
+}
+
+/// Names the proxies that are missing on either side or configured differently
+fn compare_proxies(local: &Value, remote: &Value) -> Vec<String> {
+    let by_name = |config: &Value| -> Vec<(String, Value)> {
+        config
+            .get("proxy")
+            .and_then(Value::as_array)
+            .map(|proxies| {
+                proxies
+                    .iter()
+                    .map(|proxy| {
+                        let name = proxy.get("name").and_then(Value::as_str).unwrap_or_default();
+                        (name.to_string(), proxy.clone())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let (local, remote) = (by_name(local), by_name(remote));
+    let find = |proxies: &[(String, Value)], name: &str| {
+        proxies.iter().find(|(other, _)| other == name).map(|(_, proxy)| proxy.clone())
+    };
+
+    let mut differences = Vec::new();
+    for (name, proxy) in &local {
+        match find(&remote, name) {
+            None => differences.push(format!("proxy '{}' is not on the router", name)),
+            Some(other) if other != *proxy => differences.push(format!("proxy '{}' is configured differently", name)),
+            Some(_) => {}
+        }
+    }
+    for (name, _) in &remote {
+        if find(&local, name).is_none() {
+            differences.push(format!("proxy '{}' is only on the router", name));
+        }
+    }
+    differences
+}
@@ -0,0 +1,43 @@
+//! `gwrs lint` - lists problems of the router's configuration.
+//!
+//! Prints one line per warning of `/api/v1/settings/lint`, such as gateway
+//! nodes left behind by a deleted proxy or rules shadowed by a catch-all, and
+//! fails when there is at least one, so it can guard a deployment script.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::list::{fetch, print_json};
+
+#[derive(Deserialize, Debug)]
+struct LintReport {
+    warnings: Vec<LintWarning>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LintWarning {
+    kind: String,
+    message: String,
+}
+
+/// `gwrs lint`
+pub fn run(base_url: &str, token: &str, json: bool) -> Result<()> {
+    let url = format!("{}/api/v1/settings/lint", base_url);
+    let body = fetch(&url, token)?;
+    let report: LintReport =
+        serde_json::from_value(body.clone()).context("Failed to parse lint report")?;
+    if json {
+        print_json(&body)?;
+    } else if report.warnings.is_empty() {
+        println!("No problems found");
+    } else {
+        for warning in &report.warnings {
+            println!("[{}] {}", warning.kind, warning.message);
+        }
+    }
+
+    if !report.warnings.is_empty() {
+        anyhow::bail!("{} warning(s) found", report.warnings.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+//! Read-only `list` subcommands for proxies, gateway nodes and gateways.
+//!
+//! Output is a plain table by default, or the raw API response with `--json`.
+//! HTTP failures are returned as `ureq` errors so `main` can map them to exit codes.
+
+use anyhow::{Context, Result};
+use log::{debug, error};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize, Debug)]
+struct ProxyEntry {
+    proxy: Proxy,
+    #[serde(default)]
+    domains: Vec<ProxyDomain>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Proxy {
+    id: String,
+    title: String,
+    addr_listen: String,
+    #[serde(default)]
+    high_speed: bool,
+    high_speed_addr: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProxyDomain {
+    sni: Option<String>,
+    #[serde(default)]
+    tls: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct GatewayNode {
+    id: String,
+    proxy_id: String,
+    title: String,
+    alt_target: String,
+    priority: i32,
+    domain_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Gateway {
+    id: String,
+    gwnode_id: String,
+    pattern: String,
+    target: String,
+    priority: i32,
+}
+
+/// `gwrs proxy list`
+pub fn proxies(base_url: &str, token: &str, json: bool) -> Result<()> {
+    let url = format!("{}/api/v1/settings/proxies", base_url);
+    let body = fetch(&url, token)?;
+    if json {
+        return print_json(&body);
+    }
+
+    let entries: Vec<ProxyEntry> =
+        serde_json::from_value(body).context("Failed to parse proxy list")?;
+    let rows = entries
+        .into_iter()
+        .map(|entry| {
+            let domains = entry
+                .domains
+                .iter()
+                .filter_map(|d| {
+                    d.sni
+                        .as_ref()
+                        .map(|sni| if d.tls { format!("{} (tls)", sni) } else { sni.clone() })
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let high_speed = match (entry.proxy.high_speed, entry.proxy.high_speed_addr) {
+                (true, Some(addr)) => addr,
+                (true, None) => "on".to_string(),
+                (false, _) => "-".to_string(),
+            };
+            vec![
+                entry.proxy.id,
+                entry.proxy.title,
+                entry.proxy.addr_listen,
+                high_speed,
+                or_dash(domains),
+            ]
+        })
+        .collect();
+
+    print_table(&["ID", "TITLE", "LISTEN", "HIGH SPEED", "DOMAINS"], rows);
+    Ok(())
+}
+
+/// `gwrs gwnode list [--proxy <id>]`
+pub fn gwnodes(base_url: &str, token: &str, proxy_id: Option<&str>, json: bool) -> Result<()> {
+    let url = match proxy_id {
+        Some(id) => format!("{}/api/v1/settings/gwnode/list/{}", base_url, id),
+        None => format!("{}/api/v1/settings/gwnode/list", base_url),
+    };
+    let body = fetch(&url, token)?;
+    if json {
+        return print_json(&body);
+    }
+
+    let nodes: Vec<GatewayNode> =
+        serde_json::from_value(body).context("Failed to parse gateway node list")?;
+    let rows = nodes
+        .into_iter()
+        .map(|node| {
+            vec![
+                node.id,
+                node.proxy_id,
+                node.title,
+                node.alt_target,
+                node.priority.to_string(),
+                or_dash(node.domain_name.unwrap_or_default()),
+            ]
+        })
+        .collect();
+
+    print_table(&["ID", "PROXY", "TITLE", "TARGET", "PRIORITY", "DOMAIN"], rows);
+    Ok(())
+}
+
+/// `gwrs gateway list [--gwnode <id>]`
+pub fn gateways(base_url: &str, token: &str, gwnode_id: Option<&str>, json: bool) -> Result<()> {
+    let url = match gwnode_id {
+        Some(id) => format!("{}/api/v1/settings/gateway/list/{}", base_url, id),
+        None => format!("{}/api/v1/settings/gateway/list", base_url),
+    };
+    let body = fetch(&url, token)?;
+    if json {
+        return print_json(&body);
+    }
+
+    let gateways: Vec<Gateway> =
+        serde_json::from_value(body).context("Failed to parse gateway list")?;
+    let rows = gateways
+        .into_iter()
+        .map(|gateway| {
+            vec![
+                gateway.id,
+                gateway.gwnode_id,
+                gateway.priority.to_string(),
+                gateway.pattern,
+                gateway.target,
+            ]
+        })
+        .collect();
+
+    print_table(&["ID", "GWNODE", "PRIORITY", "PATTERN", "TARGET"], rows);
+    Ok(())
+}
+
+/// Performs an authenticated GET and parses the JSON body
+pub(crate) fn fetch(url: &str, token: &str) -> Result<Value> {
+    debug!("GET {}", url);
+    let response = ureq::get(url)
+        .set("Authorization", &format!("Bearer {}", token))
+        .call()
+        .map_err(|e| {
+            if let ureq::Error::Status(status, _) = &e {
+                error!("Request to {} failed with status {}", url, status);
+            }
+            e
+        })
+        .with_context(|| format!("Failed to fetch {}", url))?;
+
+    response
+        .into_json::<Value>()
+        .context("Failed to parse response")
+}
+
+pub(crate) fn print_json(body: &Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(body)?);
+    Ok(())
+}
+
+fn or_dash(value: String) -> String {
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+/// Prints rows as left-aligned columns sized to their widest cell
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    if rows.is_empty() {
+        println!("No entries found");
+        return;
+    }
+
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = *width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", format_row(headers.to_vec()));
+    for row in &rows {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
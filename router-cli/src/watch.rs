@@ -0,0 +1,271 @@
+//! `gwrs config watch` - re-upload a configuration file whenever it changes.
+//!
+//! The parent directory is watched rather than the file itself, since most
+//! editors and `git checkout` replace files instead of writing them in place.
+//! Bursts of events are debounced into a single upload, and every upload prints
+//! a line diff against the last version that was accepted by the router.
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use crate::{authenticate, read_config, upload_config_contents, ConfigFormat};
+
+/// Settings for a watch session
+pub struct WatchOptions {
+    pub base_url: String,
+    pub username: String,
+    pub password: String,
+    pub config: PathBuf,
+    pub debounce: Duration,
+}
+
+/// Uploads the file once, then keeps re-uploading it on every change.
+///
+/// Authentication happens once up front. When the router rejects the token
+/// (e.g. it expired) the session logs in again and retries the upload.
+/// Read, validation and upload errors are reported and the loop keeps going.
+pub fn run(options: &WatchOptions) -> Result<()> {
+    let config_path = options.config.canonicalize().with_context(|| {
+        format!("Failed to resolve configuration file {}", options.config.display())
+    })?;
+    let watch_dir = config_path
+        .parent()
+        .map(PathBuf::from)
+        .context("Configuration file has no parent directory")?;
+
+    let mut token = authenticate(&options.base_url, &options.username, &options.password)?;
+    debug!("Authentication successful, token received");
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())
+        .context("Failed to create filesystem watcher")?;
+    watcher
+        .watch(&watch_dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", watch_dir.display()))?;
+
+    println!("Watching {} (Ctrl+C to stop)", config_path.display());
+
+    // Last contents accepted by the router, used as the base for diffs
+    let mut applied: Option<String> = None;
+    sync_once(options, &config_path, &mut token, &mut applied);
+
+    loop {
+        // Block until something happens to the watched file
+        match rx.recv() {
+            Ok(Ok(event)) if touches(&event, &config_path) => {}
+            Ok(Ok(_)) => continue,
+            Ok(Err(e)) => {
+                println!("Watch error: {}", e);
+                continue;
+            }
+            Err(_) => anyhow::bail!("Filesystem watcher stopped unexpectedly"),
+        }
+
+        // Debounce: wait until the file has been quiet for the whole period
+        loop {
+            match rx.recv_timeout(options.debounce) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    anyhow::bail!("Filesystem watcher stopped unexpectedly")
+                }
+            }
+        }
+
+        sync_once(options, &config_path, &mut token, &mut applied);
+    }
+}
+
+/// Whether a filesystem event concerns the watched configuration file
+fn touches(event: &notify::Event, config_path: &PathBuf) -> bool {
+    if event.kind.is_access() {
+        return false;
+    }
+    event
+        .paths
+        .iter()
+        .any(|path| path.file_name() == config_path.file_name())
+}
+
+/// Reads, diffs and uploads the file, reporting failures instead of returning them
+fn sync_once(
+    options: &WatchOptions,
+    config_path: &PathBuf,
+    token: &mut String,
+    applied: &mut Option<String>,
+) {
+    let contents = match read_config(config_path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            // The file may be briefly missing while an editor replaces it
+            println!("Skipping change: {:#}", e);
+            return;
+        }
+    };
+
+    match applied {
+        Some(previous) if *previous == contents => {
+            debug!("Configuration unchanged, skipping upload");
+            return;
+        }
+        Some(previous) => {
+            println!("\nChange detected in {}:", config_path.display());
+            print_diff(previous, &contents);
+        }
+        None => println!("Uploading {}", config_path.display()),
+    }
+
+    match upload_with_refresh(options, token, &contents) {
+        Ok(()) => *applied = Some(contents),
+        Err(e) => {
+            println!("Upload rejected, keeping previous configuration: {:#}", e);
+            println!("Waiting for the next change...");
+        }
+    }
+}
+
+/// Uploads the contents, logging in again once if the token was rejected
+fn upload_with_refresh(options: &WatchOptions, token: &mut String, contents: &str) -> Result<()> {
+    let format = ConfigFormat::from_path(&options.config);
+    match upload_config_contents(&options.base_url, token, contents, format) {
+        Err(e) if is_unauthorized(&e) => {
+            info!("Token rejected, authenticating again");
+            *token = authenticate(&options.base_url, &options.username, &options.password)?;
+            upload_config_contents(&options.base_url, token, contents, format)
+        }
+        result => result,
+    }
+}
+
+/// Whether the error came from the router answering 401
+fn is_unauthorized(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<ureq::Error>(),
+        Some(ureq::Error::Status(401, _))
+    )
+}
+
+/// Prints a line diff between two versions of the file
+fn print_diff(old: &str, new: &str) {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    for line in diff_lines(&old, &new) {
+        match line {
+            DiffLine::Removed(text) => println!("- {}", text),
+            DiffLine::Added(text) => println!("+ {}", text),
+            DiffLine::Same => {}
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum DiffLine<'a> {
+    Same,
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Longest-common-subsequence line diff, good enough for config-sized files
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffLine<'a>> {
+    // lcs[i][j] = length of the LCS of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            out.push(DiffLine::Same);
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            out.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            out.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    out.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    out.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, AccessMode, CreateKind, DataChange, EventKind, ModifyKind};
+
+    fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffLine<'a>> {
+        diff_lines(old, new)
+            .into_iter()
+            .filter(|line| *line != DiffLine::Same)
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert!(diff(&["a", "b"], &["a", "b"]).is_empty());
+        // Insert
+        assert_eq!(
+            diff(&["a", "c"], &["a", "b", "c"]),
+            vec![DiffLine::Added("b")]
+        );
+        assert_eq!(diff(&[], &["a"]), vec![DiffLine::Added("a")]);
+        // Delete
+        assert_eq!(
+            diff(&["a", "b", "c"], &["a", "c"]),
+            vec![DiffLine::Removed("b")]
+        );
+        assert_eq!(diff(&["a"], &[]), vec![DiffLine::Removed("a")]);
+        // Replace
+        assert_eq!(
+            diff(&["a", "b", "c"], &["a", "x", "c"]),
+            vec![DiffLine::Removed("b"), DiffLine::Added("x")]
+        );
+        assert_eq!(diff_lines(&["a", "b"], &["a", "x"]).len(), 3);
+    }
+
+    fn event(kind: EventKind, path: &str) -> notify::Event {
+        notify::Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    fn modify(path: &str) -> notify::Event {
+        event(
+            EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+            path,
+        )
+    }
+
+    #[test]
+    fn test_touches() {
+        let config = PathBuf::from("/etc/gwrs/config.yaml");
+        assert!(touches(&modify("/etc/gwrs/config.yaml"), &config));
+        // Editors replace the file, so creating it counts
+        let create = event(EventKind::Create(CreateKind::File), "/etc/gwrs/config.yaml");
+        assert!(touches(&create, &config));
+        assert!(!touches(&modify("/etc/gwrs/other.yaml"), &config));
+        assert!(!touches(&modify("/etc/gwrs/.config.yaml.swp"), &config));
+        // Reading the file is no change
+        let read = event(
+            EventKind::Access(AccessKind::Close(AccessMode::Read)),
+            "/etc/gwrs/config.yaml",
+        );
+        assert!(!touches(&read, &config));
+    }
+}
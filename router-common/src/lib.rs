@@ -0,0 +1,34 @@
+//! # Router Common
+//!
+//! Helpers shared by the router binaries, kept free of dependencies so any of
+//! them can use it.
+
+/// Splits a comma separated environment variable into trimmed, non-empty values.
+///
+/// Returns an empty list when the variable is unset.
+pub fn env_list(key: &str) -> Vec<String> {
+    std::env::var(key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_list() {
+        std::env::set_var("GWRS_TEST_ENV_LIST", " a, b ,,c ");
+        assert_eq!(env_list("GWRS_TEST_ENV_LIST"), vec!["a", "b", "c"]);
+        std::env::set_var("GWRS_TEST_ENV_LIST", " , ");
+        assert!(env_list("GWRS_TEST_ENV_LIST").is_empty());
+        std::env::remove_var("GWRS_TEST_ENV_LIST");
+        assert!(env_list("GWRS_TEST_ENV_LIST").is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+//! # Gateway Routing Benchmarks
+//!
+//! Times the per request routing of the gateway against rule sets of 1, 100
+//! and 1000 rules. The request always matches the last rule, the worst case
+//! for a cache miss.
+//!
+//! * `gateway_route/cache_hit` - the route cache answers
+//! * `gateway_route/cache_miss` - plain `/svc/*` rules are matched in order
+//! * `gateway_route/captures` - regex rules with numeric and named captures
+//!   are matched and the target template expanded
+//! * `transform_path` - expanding a parsed target template from captures
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use regex::Regex;
+use router_core::app::gateway_fast::RouteResolver;
+use router_core::app::path_template::PathTemplate;
+use router_core::config::GatewayPath;
+
+const RULE_COUNTS: [usize; 3] = [1, 100, 1000];
+
+fn rule(index: usize, path_listen: String, path_target: String) -> GatewayPath {
+    GatewayPath {
+        id: format!("rule-{:04}", index),
+        priority: 1,
+        node_priority: 100,
+        sni: None,
+        tls: false,
+        addr_bind: "127.0.0.1:8080".to_string(),
+        addr_target: "127.0.0.1:3000".to_string(),
+        path_listen,
+        path_target,
+        strip_prefix: None,
+        compress: false,
+        allow_cidrs: Vec::new(),
+        deny_cidrs: Vec::new(),
+        connect_timeout_secs: None,
+        header_timeout_secs: None,
+        total_timeout_secs: None,
+        maintenance: false,
+        sticky: None,
+        redact_fields: Vec::new(),
+        redact_mode: None,
+        route_script: None,
+        split: Vec::new(),
+        mirror_target: None,
+        cors_origins: Vec::new(),
+        cors_methods: Vec::new(),
+        cors_headers: Vec::new(),
+        server_header: None,
+        strip_server_headers: false,
+    }
+}
+
+/// `/svc<i>/*` forwarded to `/backend/svc<i>`
+fn plain_rules(count: usize) -> RouteResolver {
+    let rules = (0..count)
+        .map(|i| rule(i, format!("/svc{}/*", i), format!("/backend/svc{}", i)))
+        .collect();
+    RouteResolver::new(rules).expect("benchmark rules must compile")
+}
+
+/// `/svc<i>/users/<id>/<rest>` forwarded to `/v2/svc<i>/<id>/<rest>`
+fn capture_rules(count: usize) -> RouteResolver {
+    let rules = (0..count)
+        .map(|i| {
+            rule(
+                i,
+                format!("^/svc{}/users/(?<id>[0-9]+)/(.*)$", i),
+                format!("/v2/svc{}/${{id}}/$2", i),
+            )
+        })
+        .collect();
+    RouteResolver::new(rules).expect("benchmark rules must compile")
+}
+
+fn bench_gateway_route(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gateway_route");
+    for count in RULE_COUNTS {
+        let last = count - 1;
+
+        let resolver = plain_rules(count);
+        let path = format!("/svc{}/orders?page=2", last);
+        assert!(resolver.resolve(&path).is_some(), "the path must match");
+        group.bench_with_input(BenchmarkId::new("cache_hit", count), &path, |b, path| {
+            b.iter(|| resolver.resolve(black_box(path)))
+        });
+        group.bench_with_input(BenchmarkId::new("cache_miss", count), &path, |b, path| {
+            b.iter(|| resolver.resolve_uncached(black_box(path)))
+        });
+
+        let resolver = capture_rules(count);
+        let path = format!("/svc{}/users/42/posts/7?page=2", last);
+        assert!(resolver.resolve_uncached(&path).is_some(), "the path must match");
+        group.bench_with_input(BenchmarkId::new("captures", count), &path, |b, path| {
+            b.iter(|| resolver.resolve_uncached(black_box(path)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_transform_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transform_path");
+    let pattern = Regex::new("^/api/(?<version>v[0-9]+)/users/([0-9]+)/(.*)$").unwrap();
+    let path = "/api/v1/users/42/posts/7";
+    for (name, template) in [
+        ("numeric", "/internal/$1/$2/$3"),
+        ("named", "/internal/${version}/accounts/$2/${3}"),
+    ] {
+        let template = PathTemplate::parse(template);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let captures = pattern.captures(black_box(path)).unwrap();
+                template.expand(&captures)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_gateway_route, bench_transform_path);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+//! # Proxy Relay Benchmarks
+//!
+//! Times the speed mode proxy relaying data between a client and an echo
+//! upstream over loopback TCP. Each iteration writes one payload through the
+//! proxy and reads it back, so both relay directions are covered.
+//!
+//! * `proxy_duplex/round_trip` - per payload size, with byte throughput
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pingora::apps::ServerApp;
+use pingora::protocols::l4::stream::Stream as L4Stream;
+use pingora::protocols::Stream;
+use pingora::upstreams::peer::BasicPeer;
+use router_core::app::proxy_fast::ProxyApp;
+use router_core::config;
+use router_core::system::sockopt::TcpOptions;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const PAYLOAD_SIZES: [usize; 3] = [1024, 16 * 1024, 256 * 1024];
+
+/// Starts an echo upstream and a proxy in front of it, returns a connected client.
+async fn connect_through_proxy() -> TcpStream {
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = upstream.accept().await.unwrap();
+        let (mut read, mut write) = stream.split();
+        let _ = tokio::io::copy(&mut read, &mut write).await;
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let listen_addr = listener.local_addr().unwrap();
+    let app = Arc::new(ProxyApp::new(
+        BasicPeer::new(&upstream_addr.to_string()),
+        listen_addr.to_string(),
+        TcpOptions::default(),
+        config::DEFAULT_PROXY_BUFFER_SIZE,
+        false,
+    ));
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (_tx, shutdown) = tokio::sync::watch::channel(false);
+        let io: Stream = Box::new(L4Stream::from(stream));
+        app.process_new(io, &shutdown).await;
+    });
+
+    let client = TcpStream::connect(listen_addr).await.unwrap();
+    client.set_nodelay(true).unwrap();
+    client
+}
+
+/// Writes `payload` through the proxy and reads the echo back.
+async fn round_trip(client: &mut TcpStream, payload: &[u8], echo: &mut [u8]) {
+    let (mut read, mut write) = client.split();
+    let (written, echoed) = tokio::join!(write.write_all(payload), read.read_exact(echo));
+    written.unwrap();
+    echoed.unwrap();
+}
+
+fn bench_proxy_duplex(c: &mut Criterion) {
+    config::RoutingData::ProxyRequestID.set("-");
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("proxy_duplex");
+    for size in PAYLOAD_SIZES {
+        // Not an HTTP request line, so the proxy relays it unchanged
+        let payload = vec![0x5a_u8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("round_trip", size), &payload, |b, payload| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let mut client = connect_through_proxy().await;
+                    let mut echo = vec![0u8; payload.len()];
+                    // Warm up the connection outside of the measurement
+                    round_trip(&mut client, payload, &mut echo).await;
+
+                    let started = Instant::now();
+                    for _ in 0..iters {
+                        round_trip(&mut client, payload, &mut echo).await;
+                    }
+                    let elapsed = started.elapsed();
+                    assert_eq!(echo, *payload, "the proxy must relay the payload unchanged");
+                    elapsed
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_proxy_duplex);
+criterion_main!(benches);
@@ -0,0 +1,110 @@
+//! # ACME Challenges
+//!
+//! Key authorizations of pending ACME HTTP-01 challenges. router-api obtains
+//! the certificates of proxy domains with `acme` set and publishes each
+//! challenge through the `acme-challenge` command before telling the CA it is
+//! ready. Gateway listeners and HTTPS redirect listeners then answer
+//! `GET /.well-known/acme-challenge/<token>` with the key authorization for any
+//! host, ahead of their rules and redirects.
+//!
+//! router-api removes a challenge once the order is decided. One that is never
+//! removed, e.g. because router-api stopped mid-order, expires after an hour.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+use std::time::{Duration, Instant};
+
+/// Path prefix of HTTP-01 challenge requests (RFC 8555, section 8.3)
+pub const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+/// Time a challenge is answered without being removed
+const CHALLENGE_TTL: Duration = Duration::from_secs(60 * 60);
+
+// Key authorization and publish time by token.
+static CHALLENGES: LazyLock<RwLock<HashMap<String, (String, Instant)>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Whether `token` is a valid challenge token, base64url without padding
+fn is_token(token: &str) -> bool {
+    !token.is_empty()
+        && token
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Publishes the key authorization of a challenge, replacing an earlier one of the token.
+pub fn publish(token: &str, key_authorization: &str) -> Result<(), String> {
+    if !is_token(token) {
+        return Err(format!("invalid challenge token '{}'", token.escape_debug()));
+    }
+    // `<token>.<thumbprint>`, both base64url
+    if !key_authorization
+        .split_once('.')
+        .is_some_and(|(prefix, thumbprint)| prefix == token && is_token(thumbprint))
+    {
+        return Err(format!("invalid key authorization for token '{}'", token));
+    }
+    let mut challenges = CHALLENGES.write().unwrap_or_else(|e| e.into_inner());
+    challenges.retain(|_, (_, published)| published.elapsed() < CHALLENGE_TTL);
+    challenges.insert(token.to_string(), (key_authorization.to_string(), Instant::now()));
+    Ok(())
+}
+
+/// Removes a challenge, returning whether it was published.
+pub fn remove(token: &str) -> bool {
+    CHALLENGES
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(token)
+        .is_some()
+}
+
+/// Number of challenges currently answered.
+pub fn pending() -> usize {
+    CHALLENGES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .filter(|(_, published)| published.elapsed() < CHALLENGE_TTL)
+        .count()
+}
+
+/// The key authorization answering a request for `path`, `None` for any other path.
+pub fn answer(path: &str) -> Option<String> {
+    let token = path.strip_prefix(CHALLENGE_PATH)?;
+    let challenges = CHALLENGES.read().unwrap_or_else(|e| e.into_inner());
+    challenges
+        .get(token)
+        .filter(|(_, published)| published.elapsed() < CHALLENGE_TTL)
+        .map(|(key_authorization, _)| key_authorization.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_and_answer() {
+        let token = "evaGxfADs6pSRb2LAv9IZf17Dt3juxGJ-PCt92wr-oA";
+        let key_authorization = format!("{}.9jg46WB3rR_AHD-EBXdN7cBkH1WOu0tA3M9fm21mqTI", token);
+        publish(token, &key_authorization).unwrap();
+
+        assert_eq!(answer(&format!("{}{}", CHALLENGE_PATH, token)), Some(key_authorization));
+        assert_eq!(answer(&format!("/{}", token)), None);
+        assert_eq!(answer(&format!("{}other", CHALLENGE_PATH)), None);
+        assert!(pending() >= 1);
+
+        assert!(remove(token));
+        assert!(!remove(token));
+        assert_eq!(answer(&format!("{}{}", CHALLENGE_PATH, token)), None);
+    }
+
+    #[test]
+    fn test_invalid_challenges_are_refused() {
+        assert!(publish("", "x.y").is_err());
+        assert!(publish("../etc", "../etc.y").is_err());
+        assert!(publish("tokenA", "tokenB.thumbprint").is_err());
+        assert!(publish("tokenA", "tokenA.").is_err());
+        assert!(publish("tokenA", "tokenA.thumb\r\nprint").is_err());
+    }
+}
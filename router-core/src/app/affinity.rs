@@ -0,0 +1,232 @@
+//! # Session Affinity
+//!
+//! A path configured on several gateway nodes of one listener (same pattern,
+//! `strip_prefix` and SNI) is served by several backends. Normally the first
+//! of them in rule order takes every request and the others only fail over.
+//! When the first matching rule is `sticky`, requests are spread over all of
+//! them instead and each client keeps going to the same one:
+//!
+//! * `cookie` - the response names the backend in the `GWRS_STICKY_COOKIE`
+//!   cookie (default `GWRS-Affinity`), valid for `GWRS_STICKY_TTL` seconds
+//!   (default 3600, `0` for a session cookie); requests carrying it return there
+//! * `ip` - the client address picks the backend, without any cookie
+//!
+//! Backends are ranked by rendezvous hashing of the client address, so a
+//! backend going down or coming back only moves its own clients. A pinned
+//! backend that is down or refuses the connection is replaced by the next one
+//! and the cookie is rewritten to name it. Sticky routes are never cached.
+
+use std::hash::Hasher;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::config;
+
+/// How a sticky rule pins clients to a backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sticky {
+    /// The backend is named by a cookie set on the first response
+    Cookie,
+    /// The backend is derived from the client address
+    IpHash,
+}
+
+impl Sticky {
+    /// Parses a rule's `sticky` value, `cookie` or `ip`, case insensitive.
+    ///
+    /// Returns `Ok(None)` for an empty value, the rule is not sticky then.
+    pub fn parse(value: &str) -> Result<Option<Self>, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" => Ok(None),
+            "cookie" => Ok(Some(Sticky::Cookie)),
+            "ip" => Ok(Some(Sticky::IpHash)),
+            _ => Err(format!("Invalid sticky '{}', expected cookie or ip", value)),
+        }
+    }
+}
+
+/// FNV-1a with a final mix. Unlike `DefaultHasher` its output is fixed, so
+/// cookies and client assignments survive restarts on a newer build.
+///
+/// Only feed it bytes with [`Hasher::write`], the `Hash` impls of std types
+/// may change their byte stream between Rust releases.
+#[derive(Debug, Clone, Copy)]
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        // FNV alone leaves the high bits poorly mixed for short inputs
+        let mut hash = self.0;
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^ (hash >> 31)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// Name of a backend in the affinity cookie, so the cookie doesn't reveal its address
+pub fn backend_key(address: &str) -> String {
+    let mut hasher = StableHasher::default();
+    hasher.write(address.as_bytes());
+    format!("{:016x}", hasher.finish())
+}
+
+/// Feeds a client address to `hasher`, a tag byte for its family then its octets
+pub fn write_client(hasher: &mut StableHasher, client: Option<IpAddr>) {
+    match client {
+        None => hasher.write(&[0]),
+        Some(IpAddr::V4(ip)) => {
+            hasher.write(&[4]);
+            hasher.write(&ip.octets());
+        }
+        Some(IpAddr::V6(ip)) => {
+            hasher.write(&[6]);
+            hasher.write(&ip.octets());
+        }
+    }
+}
+
+/// Index of the backend `client` is pinned to, `None` when there is none.
+///
+/// Highest random weight: every backend gets a score from the client and its
+/// address, the highest wins. Removing a backend only moves the clients it won.
+pub fn rendezvous(client: Option<IpAddr>, backends: &[String]) -> Option<usize> {
+    backends
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, backend)| {
+            let mut hasher = StableHasher::default();
+            write_client(&mut hasher, client);
+            hasher.write(backend.as_bytes());
+            hasher.finish()
+        })
+        .map(|(index, _)| index)
+}
+
+/// Name and lifetime of the affinity cookie, read once per gateway listener
+#[derive(Debug, Clone)]
+pub struct AffinityCookie {
+    name: String,
+    ttl: Option<Duration>,
+}
+
+impl AffinityCookie {
+    pub fn new(name: &str, ttl: Option<Duration>) -> Self {
+        Self {
+            name: name.to_string(),
+            ttl,
+        }
+    }
+
+    /// Cookie configured with `GWRS_STICKY_COOKIE` and `GWRS_STICKY_TTL`.
+    pub fn from_config() -> Self {
+        Self::new(&config::sticky_cookie(), config::sticky_ttl())
+    }
+
+    /// Backend key the client sent, looked up in its `Cookie` header values.
+    pub fn read<'a>(&self, headers: impl IntoIterator<Item = &'a str>) -> Option<String> {
+        headers
+            .into_iter()
+            .flat_map(|header| header.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == self.name)
+            .map(|(_, value)| value.trim_matches('"').to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// `Set-Cookie` value pinning the client to the backend `key`.
+    pub fn set_cookie(&self, key: &str) -> String {
+        let mut cookie = format!("{}={}; Path=/; HttpOnly; SameSite=Lax", self.name, key);
+        if let Some(ttl) = self.ttl {
+            cookie.push_str(&format!("; Max-Age={}", ttl.as_secs()));
+        }
+        cookie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backends(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("10.0.0.{}:8080", i + 1)).collect()
+    }
+
+    #[test]
+    fn test_parse_sticky() {
+        assert_eq!(Sticky::parse("cookie"), Ok(Some(Sticky::Cookie)));
+        assert_eq!(Sticky::parse(" IP "), Ok(Some(Sticky::IpHash)));
+        assert_eq!(Sticky::parse(""), Ok(None));
+        assert!(Sticky::parse("round-robin").is_err());
+    }
+
+    #[test]
+    fn test_rendezvous_moves_only_clients_of_a_removed_backend() {
+        let all = backends(4);
+        let client = Some(IpAddr::from([10, 1, 0, 1]));
+        assert_eq!(rendezvous(client, &[]), None);
+        assert_eq!(rendezvous(client, &all), rendezvous(client, &all));
+
+        let without_first = all[1..].to_vec();
+        let mut used = std::collections::HashSet::new();
+        for client in 0..200u8 {
+            let client = Some(IpAddr::from([192, 168, 0, client]));
+            let before = &all[rendezvous(client, &all).unwrap()];
+            let after = &without_first[rendezvous(client, &without_first).unwrap()];
+            if before != &all[0] {
+                assert_eq!(before, after, "client {:?} moved", client);
+            }
+            used.insert(before.clone());
+        }
+        assert_eq!(used.len(), 4, "clients are spread over every backend");
+    }
+
+    #[test]
+    fn test_stable_hasher_is_fixed() {
+        let mut hasher = StableHasher::default();
+        hasher.write(b"a");
+        let mut fnv = StableHasher(0xaf63_dc4c_8601_ec8c);
+        fnv.write(&[]);
+        assert_eq!(hasher.finish(), fnv.finish());
+        // Existing cookies name backends by this value, it must not change
+        assert_eq!(backend_key("10.0.0.1:8080"), "e6be330c3d44a2e2");
+        // Nor may the backend a client address is pinned to
+        let all = backends(4);
+        assert_eq!(rendezvous(Some(IpAddr::from([10, 1, 0, 1])), &all), Some(2));
+        assert_eq!(rendezvous(Some(IpAddr::from([10, 1, 0, 4])), &all), Some(3));
+        assert_eq!(rendezvous(None, &all), Some(2));
+    }
+
+    #[test]
+    fn test_affinity_cookie() {
+        let cookie = AffinityCookie::new("GWRS-Affinity", Some(Duration::from_secs(3600)));
+        let key = backend_key("10.0.0.1:8080");
+        assert_eq!(key.len(), 16);
+        assert_eq!(
+            cookie.set_cookie(&key),
+            format!("GWRS-Affinity={}; Path=/; HttpOnly; SameSite=Lax; Max-Age=3600", key)
+        );
+        assert_eq!(
+            AffinityCookie::new("sid", None).set_cookie("ab"),
+            "sid=ab; Path=/; HttpOnly; SameSite=Lax"
+        );
+
+        let header = format!("theme=dark; GWRS-Affinity={}; other=1", key);
+        assert_eq!(cookie.read([header.as_str()]), Some(key.clone()));
+        assert_eq!(cookie.read(["a=1", "GWRS-Affinity=\"ab\""]), Some("ab".to_string()));
+        assert_eq!(cookie.read(["GWRS-Affinity-Old=ab; GWRS-Affinity="]), None);
+        assert_eq!(cookie.read([]), None);
+    }
+}
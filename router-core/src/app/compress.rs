@@ -0,0 +1,302 @@
+//! # Response Compression
+//!
+//! Streaming gzip and brotli compression of upstream responses for gateway
+//! nodes with `compress` set. A response is compressed when the client accepts
+//! one of the encodings, its content type is textual, it isn't encoded yet and
+//! it is not known to be smaller than `GWRS_COMPRESS_MIN_SIZE` bytes.
+//!
+//! Bodies are compressed chunk by chunk as pingora relays them, so nothing is
+//! buffered beyond what the encoder holds internally.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use http::HeaderMap;
+
+/// Brotli quality, low enough to compress on the fly
+const BROTLI_QUALITY: u32 = 4;
+/// Brotli window size as a power of two
+const BROTLI_LG_WINDOW: u32 = 22;
+/// Internal buffer of the brotli encoder
+const BROTLI_BUFFER: usize = 4096;
+
+/// Content encoding produced by the gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    /// `Content-Encoding` value of this encoding
+    pub fn header_value(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+}
+
+/// Picks the encoding for an `Accept-Encoding` header, preferring brotli.
+///
+/// Encodings with `q=0` are refused and `*` stands for any encoding not listed.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut gzip = None;
+    let mut brotli = None;
+    let mut any = None;
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .filter_map(|q| q.trim().parse::<f32>().ok())
+            .next()
+            .unwrap_or(1.0);
+        match name.as_str() {
+            "gzip" | "x-gzip" => gzip = Some(quality),
+            "br" => brotli = Some(quality),
+            "*" => any = Some(quality),
+            _ => {}
+        }
+    }
+    let brotli = brotli.or(any).unwrap_or(0.0);
+    let gzip = gzip.or(any).unwrap_or(0.0);
+    if brotli > 0.0 && brotli >= gzip {
+        Some(Encoding::Brotli)
+    } else if gzip > 0.0 {
+        Some(Encoding::Gzip)
+    } else {
+        None
+    }
+}
+
+/// Whether a content type benefits from compression.
+///
+/// Images, video, archives and event streams are left alone, they are either
+/// compressed already or must reach the client without encoder buffering.
+pub fn compressible_type(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    if mime == "text/event-stream" {
+        return false;
+    }
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "application/x-javascript"
+                | "image/svg+xml"
+        )
+}
+
+/// Whether response headers allow compressing the body.
+///
+/// `min_size` only applies when the length is known, chunked responses are
+/// compressed regardless.
+pub fn should_compress(headers: &HeaderMap, min_size: usize) -> bool {
+    if headers.contains_key(http::header::CONTENT_ENCODING) {
+        return false;
+    }
+    let no_transform = headers
+        .get_all(http::header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.to_ascii_lowercase().contains("no-transform"));
+    if no_transform {
+        return false;
+    }
+    let textual = headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, compressible_type);
+    if !textual {
+        return false;
+    }
+    headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .map_or(true, |len| len >= min_size)
+}
+
+/// Buffer the encoders write into, drained after every chunk
+#[derive(Clone, Default)]
+struct Sink(Arc<Mutex<Vec<u8>>>);
+
+impl Sink {
+    fn take(&self) -> Bytes {
+        match self.0.lock() {
+            Ok(mut buf) => Bytes::from(std::mem::take(&mut *buf)),
+            Err(poisoned) => Bytes::from(std::mem::take(&mut *poisoned.into_inner())),
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self.0.lock() {
+            Ok(mut buf) => buf.extend_from_slice(data),
+            Err(poisoned) => poisoned.into_inner().extend_from_slice(data),
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Sink>),
+    Brotli(Box<brotli::CompressorWriter<Sink>>),
+}
+
+/// Streaming compressor of one response body
+pub struct Compressor {
+    encoder: Option<Encoder>,
+    sink: Sink,
+}
+
+impl Compressor {
+    pub fn new(encoding: Encoding) -> Self {
+        let sink = Sink::default();
+        let encoder = match encoding {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(sink.clone(), flate2::Compression::default())),
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                sink.clone(),
+                BROTLI_BUFFER,
+                BROTLI_QUALITY,
+                BROTLI_LG_WINDOW,
+            ))),
+        };
+        Self {
+            encoder: Some(encoder),
+            sink,
+        }
+    }
+
+    /// Compresses the next body chunk, finishing the stream at its end.
+    ///
+    /// Returns the compressed bytes ready so far, which may be empty.
+    pub fn compress(&mut self, chunk: &[u8], end_of_stream: bool) -> io::Result<Bytes> {
+        if let Some(encoder) = self.encoder.as_mut() {
+            match encoder {
+                Encoder::Gzip(gz) => gz.write_all(chunk)?,
+                Encoder::Brotli(br) => br.write_all(chunk)?,
+            }
+        }
+        if end_of_stream {
+            match self.encoder.take() {
+                Some(Encoder::Gzip(gz)) => {
+                    gz.finish()?;
+                }
+                // The brotli stream is finished when the writer is dropped
+                Some(Encoder::Brotli(br)) => drop(br),
+                None => {}
+            }
+        }
+        Ok(self.sink.take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(
+                http::header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        map
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, gzip;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0.2, gzip;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip;q=0, *;q=0"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn test_should_compress() {
+        let min = 1024;
+        assert!(should_compress(&headers(&[("content-type", "application/json")]), min));
+        assert!(should_compress(
+            &headers(&[("content-type", "text/html; charset=utf-8"), ("content-length", "4096")]),
+            min
+        ));
+        assert!(!should_compress(
+            &headers(&[("content-type", "text/html"), ("content-length", "100")]),
+            min
+        ));
+        assert!(!should_compress(
+            &headers(&[("content-type", "text/html"), ("content-encoding", "gzip")]),
+            min
+        ));
+        assert!(!should_compress(&headers(&[("content-type", "image/png")]), min));
+        assert!(!should_compress(&headers(&[("content-type", "text/event-stream")]), min));
+        assert!(!should_compress(
+            &headers(&[("content-type", "text/plain"), ("cache-control", "public, no-transform")]),
+            min
+        ));
+        assert!(!should_compress(&headers(&[]), min));
+    }
+
+    fn compress_in_chunks(encoding: Encoding, body: &[u8]) -> Vec<u8> {
+        let mut compressor = Compressor::new(encoding);
+        let mut out = Vec::new();
+        let chunks: Vec<&[u8]> = body.chunks(1000).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            out.extend_from_slice(&compressor.compress(chunk, i + 1 == chunks.len()).unwrap());
+        }
+        out
+    }
+
+    #[test]
+    fn test_streamed_bodies_decode() {
+        let body = "{\"message\":\"hello gateway\"}".repeat(500).into_bytes();
+
+        let gzip = compress_in_chunks(Encoding::Gzip, &body);
+        assert!(gzip.len() < body.len());
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(&gzip[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, body);
+
+        let br = compress_in_chunks(Encoding::Brotli, &body);
+        assert!(br.len() < body.len());
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(&br[..], 4096).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, body);
+    }
+
+    #[test]
+    fn test_end_without_final_chunk() {
+        let mut compressor = Compressor::new(Encoding::Gzip);
+        let mut out = compressor.compress(b"plain text body", false).unwrap().to_vec();
+        out.extend_from_slice(&compressor.compress(b"", true).unwrap());
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&out[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "plain text body");
+    }
+}
@@ -0,0 +1,158 @@
+//! # Concurrency Limits
+//!
+//! Overload protection per listener: every speed mode proxy caps its open
+//! connections at `GWRS_PROXY_MAX_CONNECTIONS` and every gateway listener its
+//! in-flight requests at `GWRS_GATEWAY_MAX_REQUESTS`. Both default to `0`,
+//! unlimited.
+//!
+//! Over the limit nothing is queued. A proxy closes the new connection before
+//! connecting upstream, a gateway answers 503. In-flight counts and rejections
+//! of every listener are reported under `limits` of prottp `/status`, along with
+//! the one limit of mirrored requests, `GWRS_GATEWAY_MIRROR_MAX_IN_FLIGHT`.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, RwLock, Weak};
+
+use serde::Serialize;
+
+/// What a limit counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitKind {
+    /// Open connections of a speed mode proxy
+    Proxy,
+    /// In-flight requests of a gateway listener
+    Gateway,
+    /// Mirrored copies in flight across all gateway listeners, see `mirror`
+    Mirror,
+}
+
+/// A counting semaphore over the connections or requests of one listener.
+#[derive(Debug)]
+pub struct ListenerLimit {
+    kind: LimitKind,
+    listener: String,
+    max: usize,
+    in_flight: AtomicUsize,
+    rejected: AtomicU64,
+}
+
+/// Reported state of one listener's limit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LimitStats {
+    pub kind: LimitKind,
+    pub listener: String,
+    /// `0` when unlimited
+    pub max: usize,
+    pub in_flight: usize,
+    pub rejected: u64,
+}
+
+// Limits of all live listeners, for `/status`.
+static LIMITS: LazyLock<RwLock<Vec<Weak<ListenerLimit>>>> = LazyLock::new(|| RwLock::new(Vec::new()));
+
+impl ListenerLimit {
+    /// Creates the limit of a listener and registers it for `/status`.
+    ///
+    /// `max` of `0` only counts, it never rejects.
+    pub fn register(kind: LimitKind, listener: &str, max: usize) -> Arc<Self> {
+        let limit = Arc::new(Self {
+            kind,
+            listener: listener.to_string(),
+            max,
+            in_flight: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+        });
+        if let Ok(mut limits) = LIMITS.write() {
+            limits.retain(|limit| limit.strong_count() > 0);
+            limits.push(Arc::downgrade(&limit));
+        }
+        limit
+    }
+
+    /// Takes a slot, released when the permit is dropped.
+    ///
+    /// Returns `None` and counts a rejection when every slot is taken.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        let admitted = self
+            .in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                (self.max == 0 || current < self.max).then_some(current + 1)
+            })
+            .is_ok();
+        if !admitted {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(Permit(Arc::clone(self)))
+    }
+
+    /// Configured maximum, `0` when unlimited
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    fn stats(&self) -> LimitStats {
+        LimitStats {
+            kind: self.kind,
+            listener: self.listener.clone(),
+            max: self.max,
+            in_flight: self.in_flight.load(Ordering::Acquire),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A taken slot of a [`ListenerLimit`]
+#[derive(Debug)]
+pub struct Permit(Arc<ListenerLimit>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// State of the limit of every live listener.
+pub fn stats() -> Vec<LimitStats> {
+    LIMITS
+        .read()
+        .map(|limits| {
+            limits
+                .iter()
+                .filter_map(Weak::upgrade)
+                .map(|limit| limit.stats())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_rejects_over_max_and_releases() {
+        let limit = ListenerLimit::register(LimitKind::Gateway, "127.0.0.1:61060", 2);
+        let first = limit.try_acquire().expect("first slot");
+        let _second = limit.try_acquire().expect("second slot");
+        assert!(limit.try_acquire().is_none());
+        assert_eq!(limit.stats().in_flight, 2);
+        assert_eq!(limit.stats().rejected, 1);
+
+        drop(first);
+        assert!(limit.try_acquire().is_some());
+        assert_eq!(limit.stats().in_flight, 1);
+        assert!(stats().iter().any(|s| s.listener == "127.0.0.1:61060" && s.max == 2));
+    }
+
+    #[test]
+    fn test_unlimited_only_counts() {
+        let limit = ListenerLimit::register(LimitKind::Proxy, "127.0.0.1:61061", 0);
+        let permits: Vec<Permit> = (0..100).filter_map(|_| limit.try_acquire()).collect();
+        assert_eq!(permits.len(), 100);
+        assert_eq!(limit.stats().in_flight, 100);
+        drop(permits);
+        assert_eq!(limit.stats().in_flight, 0);
+    }
+}
@@ -0,0 +1,189 @@
+//! # Active Connections
+//!
+//! Registry of the connections the speed mode proxies are relaying right now.
+//! A connection is registered when its duplex loop starts and removed when it
+//! ends, with the same ID as its `OPEN` and `CLOSE` log lines. Byte counters
+//! are updated on every read, so a listing shows long lived connections as
+//! they go. Reported under prottp `/connections`.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Instant;
+
+use serde::Serialize;
+
+/// What a connection carries, as far as the proxy can tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// Raw bytes on a plain listener
+    Tcp,
+    /// Raw bytes on a TLS listener
+    Tls,
+    /// An upgraded WebSocket on a plain listener
+    Ws,
+    /// An upgraded WebSocket on a TLS listener
+    Wss,
+}
+
+/// A relayed connection, shared between its duplex loop and the registry
+#[derive(Debug)]
+pub struct Connection {
+    id: String,
+    proxy: String,
+    target: String,
+    client: Option<IpAddr>,
+    tls: bool,
+    websocket: AtomicBool,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    opened: Instant,
+}
+
+/// Reported state of one connection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionInfo {
+    pub id: String,
+    /// Listen address of the proxy
+    pub proxy: String,
+    pub target: String,
+    /// `None` on Unix socket listeners
+    pub client: Option<String>,
+    pub protocol: Protocol,
+    /// Bytes from the client so far
+    pub bytes_in: u64,
+    /// Bytes to the client so far
+    pub bytes_out: u64,
+    pub age_secs: u64,
+}
+
+// Open connections by ID, written on open and close only.
+static CONNECTIONS: LazyLock<RwLock<HashMap<String, Arc<Connection>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+impl Connection {
+    /// Registers a connection, removed again when the handle is dropped.
+    pub fn open(id: &str, proxy: &str, target: &str, client: Option<IpAddr>, tls: bool) -> Tracked {
+        let connection = Arc::new(Self {
+            id: id.to_string(),
+            proxy: proxy.to_string(),
+            target: target.to_string(),
+            client,
+            tls,
+            websocket: AtomicBool::new(false),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            opened: Instant::now(),
+        });
+        if let Ok(mut connections) = CONNECTIONS.write() {
+            connections.insert(connection.id.clone(), Arc::clone(&connection));
+        }
+        Tracked(connection)
+    }
+
+    fn info(&self) -> ConnectionInfo {
+        let protocol = match (self.websocket.load(Ordering::Relaxed), self.tls) {
+            (false, false) => Protocol::Tcp,
+            (false, true) => Protocol::Tls,
+            (true, false) => Protocol::Ws,
+            (true, true) => Protocol::Wss,
+        };
+        ConnectionInfo {
+            id: self.id.clone(),
+            proxy: self.proxy.clone(),
+            target: self.target.clone(),
+            client: self.client.map(|client| client.to_string()),
+            protocol,
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            age_secs: self.opened.elapsed().as_secs(),
+        }
+    }
+}
+
+/// A registered [`Connection`], unregistered on drop
+#[derive(Debug)]
+pub struct Tracked(Arc<Connection>);
+
+impl Tracked {
+    /// Marks the connection as upgraded to a WebSocket.
+    pub fn upgraded(&self) {
+        self.0.websocket.store(true, Ordering::Relaxed);
+    }
+
+    /// Counts bytes relayed from the client.
+    pub fn add_in(&self, bytes: usize) {
+        self.0.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Counts bytes relayed to the client.
+    pub fn add_out(&self, bytes: usize) {
+        self.0.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = CONNECTIONS.write() {
+            connections.remove(&self.0.id);
+        }
+    }
+}
+
+/// State of every open connection, oldest first.
+pub fn list() -> Vec<ConnectionInfo> {
+    let mut connections: Vec<(Instant, ConnectionInfo)> = CONNECTIONS
+        .read()
+        .map(|connections| {
+            connections
+                .values()
+                .map(|connection| (connection.opened, connection.info()))
+                .collect()
+        })
+        .unwrap_or_default();
+    connections.sort_by_key(|(opened, _)| *opened);
+    connections.into_iter().map(|(_, info)| info).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(id: &str) -> Option<ConnectionInfo> {
+        list().into_iter().find(|info| info.id == id)
+    }
+
+    #[test]
+    fn test_connection_is_listed_until_dropped() {
+        let client = "10.0.0.7".parse().ok();
+        let tracked = Connection::open("conn-test-1", "0.0.0.0:61070", "127.0.0.1:8080", client, true);
+        tracked.add_in(120);
+        tracked.add_out(4096);
+        tracked.add_out(4);
+
+        let info = find("conn-test-1").expect("registered");
+        assert_eq!(info.proxy, "0.0.0.0:61070");
+        assert_eq!(info.target, "127.0.0.1:8080");
+        assert_eq!(info.client.as_deref(), Some("10.0.0.7"));
+        assert_eq!(info.protocol, Protocol::Tls);
+        assert_eq!((info.bytes_in, info.bytes_out), (120, 4100));
+
+        tracked.upgraded();
+        assert_eq!(find("conn-test-1").unwrap().protocol, Protocol::Wss);
+
+        drop(tracked);
+        assert!(find("conn-test-1").is_none());
+    }
+
+    #[test]
+    fn test_plain_websocket_protocol() {
+        let tracked = Connection::open("conn-test-2", "unix:/tmp/gw.sock", "127.0.0.1:8080", None, false);
+        assert_eq!(find("conn-test-2").unwrap().protocol, Protocol::Tcp);
+        tracked.upgraded();
+        let info = find("conn-test-2").unwrap();
+        assert_eq!(info.protocol, Protocol::Ws);
+        assert_eq!(info.client, None);
+    }
+}
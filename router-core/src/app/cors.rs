@@ -0,0 +1,297 @@
+//! # CORS
+//!
+//! Cross-origin settings of gateway nodes with `cors_origins` set. A preflight
+//! matching one of their rules, an `OPTIONS` request with `Origin` and
+//! `Access-Control-Request-Method`, is answered by the gateway with a `204`
+//! and never reaches the backend:
+//!
+//! * `cors_origins` - origins allowed, e.g. `https://app.example.com`, or `*` for any
+//! * `cors_methods` - methods allowed, empty allows [`DEFAULT_METHODS`]
+//! * `cors_headers` - request headers allowed, empty allows the ones the preflight asks for
+//!
+//! A preflight from an origin that isn't listed gets the `204` with `Allow` but
+//! without `Access-Control-*` headers, so the browser refuses the request.
+//! Other requests are forwarded, plain `OPTIONS` included, and responses to an
+//! allowed origin get `Access-Control-Allow-Origin` unless the backend set it.
+//! Without `cors_origins` preflights are forwarded like any other request.
+
+use http::header::{self, HeaderName};
+use http::Method;
+use pingora::http::{RequestHeader, ResponseHeader};
+use pingora::prelude::*;
+
+/// Methods allowed when a node lists none
+pub const DEFAULT_METHODS: &str = "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS";
+
+/// Seconds browsers may cache a preflight answer
+const PREFLIGHT_MAX_AGE: u64 = 600;
+
+/// The CORS settings of one gateway node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cors {
+    /// Allowed origins in lowercase, empty when any origin is allowed
+    origins: Vec<String>,
+    any_origin: bool,
+    /// `Access-Control-Allow-Methods` and `Allow` value
+    methods: String,
+    /// `Access-Control-Allow-Headers` value, `None` echoes the preflight's
+    headers: Option<String>,
+}
+
+impl Cors {
+    /// Compiles a node's `cors_origins`, `cors_methods` and `cors_headers`.
+    ///
+    /// Returns `Ok(None)` when no origin is listed, the node then has no CORS settings.
+    pub fn parse(
+        origins: &[String],
+        methods: &[String],
+        headers: &[String],
+    ) -> std::result::Result<Option<Self>, String> {
+        let mut cors = Self {
+            origins: Vec::new(),
+            any_origin: false,
+            methods: DEFAULT_METHODS.to_string(),
+            headers: None,
+        };
+        for origin in origins.iter().map(|origin| origin.trim()).filter(|origin| !origin.is_empty()) {
+            if origin == "*" {
+                cors.any_origin = true;
+            } else {
+                cors.origins.push(parse_origin(origin)?);
+            }
+        }
+        if !cors.any_origin && cors.origins.is_empty() {
+            return Ok(None);
+        }
+        if cors.any_origin {
+            cors.origins.clear();
+        }
+
+        let methods = methods
+            .iter()
+            .map(|method| method.trim())
+            .filter(|method| !method.is_empty())
+            .map(|method| {
+                Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .map(|method| method.to_string())
+                    .map_err(|_| format!("Invalid CORS method '{}'", method))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if !methods.is_empty() {
+            cors.methods = methods.join(", ");
+        }
+
+        let headers = headers
+            .iter()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                HeaderName::from_bytes(name.as_bytes())
+                    .map(|name| name.to_string())
+                    .map_err(|_| format!("Invalid CORS header '{}'", name))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if !headers.is_empty() {
+            cors.headers = Some(headers.join(", "));
+        }
+        Ok(Some(cors))
+    }
+
+    /// Whether `request` is a CORS preflight rather than a plain `OPTIONS` request.
+    pub fn is_preflight(request: &RequestHeader) -> bool {
+        request.method == Method::OPTIONS
+            && request.headers.contains_key(header::ORIGIN)
+            && request.headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+    }
+
+    /// `Access-Control-Allow-Origin` value for the request's origin, `None` when it isn't allowed.
+    pub fn allow_origin<'a>(&self, request: &'a RequestHeader) -> Option<&'a str> {
+        let origin = request.headers.get(header::ORIGIN)?.to_str().ok()?;
+        if self.any_origin {
+            return Some("*");
+        }
+        let normalized = origin.trim_end_matches('/');
+        self.origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(normalized))
+            .then_some(origin)
+    }
+
+    /// The answer to a preflight.
+    pub fn preflight(&self, request: &RequestHeader) -> Result<ResponseHeader> {
+        let mut response = ResponseHeader::build(204, Some(7))?;
+        response.insert_header(header::ALLOW, self.methods.as_str())?;
+        response.insert_header(header::CONTENT_LENGTH, "0")?;
+        let Some(origin) = self.allow_origin(request) else {
+            return Ok(response);
+        };
+        response.insert_header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin)?;
+        response.insert_header(header::ACCESS_CONTROL_ALLOW_METHODS, self.methods.as_str())?;
+        let requested = request.headers.get(header::ACCESS_CONTROL_REQUEST_HEADERS);
+        match (&self.headers, requested) {
+            (Some(headers), _) => {
+                response.insert_header(header::ACCESS_CONTROL_ALLOW_HEADERS, headers.as_str())?
+            }
+            (None, Some(requested)) => {
+                response.insert_header(header::ACCESS_CONTROL_ALLOW_HEADERS, requested.clone())?
+            }
+            (None, None) => {}
+        }
+        response.insert_header(header::ACCESS_CONTROL_MAX_AGE, PREFLIGHT_MAX_AGE.to_string())?;
+        if !self.any_origin {
+            response.insert_header(header::VARY, "Origin")?;
+        }
+        Ok(response)
+    }
+
+    /// Adds `Access-Control-Allow-Origin` to a forwarded response for an allowed origin.
+    ///
+    /// A backend answering CORS itself is left alone.
+    pub fn decorate(&self, request: &RequestHeader, response: &mut ResponseHeader) -> Result<()> {
+        if response.headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN) {
+            return Ok(());
+        }
+        let Some(origin) = self.allow_origin(request) else {
+            return Ok(());
+        };
+        response.insert_header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin)?;
+        if !self.any_origin {
+            response.append_header(header::VARY, "Origin")?;
+        }
+        Ok(())
+    }
+}
+
+/// Lowercases `scheme://host[:port]`, other forms can never match an `Origin` header.
+fn parse_origin(origin: &str) -> std::result::Result<String, String> {
+    let origin = origin.trim_end_matches('/');
+    let uri = origin
+        .parse::<http::Uri>()
+        .map_err(|_| format!("Invalid CORS origin '{}'", origin))?;
+    let plain = matches!(uri.scheme_str(), Some("http" | "https"))
+        && uri.authority().is_some_and(|authority| !authority.as_str().contains('@'))
+        && uri.path_and_query().map_or(true, |path| path.as_str() == "/");
+    if !plain {
+        return Err(format!("Invalid CORS origin '{}', expected scheme://host[:port]", origin));
+    }
+    Ok(origin.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn preflight(origin: &str, headers: Option<&str>) -> RequestHeader {
+        let mut request = RequestHeader::build("OPTIONS", b"/api/users", None).unwrap();
+        request.insert_header(header::ORIGIN, origin).unwrap();
+        request.insert_header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT").unwrap();
+        if let Some(headers) = headers {
+            request.insert_header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers).unwrap();
+        }
+        request
+    }
+
+    fn value_of(response: &ResponseHeader, name: HeaderName) -> Option<&str> {
+        response.headers.get(name).map(|value| value.to_str().unwrap())
+    }
+
+    #[test]
+    fn test_parse_cors() {
+        assert_eq!(Cors::parse(&[], &strings(&["GET"]), &[]), Ok(None));
+        assert_eq!(Cors::parse(&strings(&[" "]), &[], &[]), Ok(None));
+        assert!(Cors::parse(&strings(&["app.example.com"]), &[], &[]).is_err());
+        assert!(Cors::parse(&strings(&["https://app.example.com/login"]), &[], &[]).is_err());
+        assert!(Cors::parse(&strings(&["*"]), &strings(&["GE T"]), &[]).is_err());
+        assert!(Cors::parse(&strings(&["*"]), &[], &strings(&["x token"])).is_err());
+
+        let cors = Cors::parse(&strings(&["https://App.example.com/"]), &strings(&["get", "put"]), &[])
+            .unwrap()
+            .unwrap();
+        assert_eq!(cors.origins, vec!["https://app.example.com"]);
+        assert_eq!(cors.methods, "GET, PUT");
+        assert_eq!(cors.headers, None);
+    }
+
+    #[test]
+    fn test_preflight_detection() {
+        assert!(Cors::is_preflight(&preflight("https://app.example.com", None)));
+
+        let mut plain = RequestHeader::build("OPTIONS", b"/api/users", None).unwrap();
+        assert!(!Cors::is_preflight(&plain));
+        plain.insert_header(header::ORIGIN, "https://app.example.com").unwrap();
+        assert!(!Cors::is_preflight(&plain));
+
+        let mut get = preflight("https://app.example.com", None);
+        get.set_method(Method::GET);
+        assert!(!Cors::is_preflight(&get));
+    }
+
+    #[test]
+    fn test_preflight_gets_configured_headers() {
+        let cors = Cors::parse(
+            &strings(&["https://app.example.com"]),
+            &strings(&["GET", "PUT"]),
+            &strings(&["Authorization", "X-Request-Id"]),
+        )
+        .unwrap()
+        .unwrap();
+
+        let response = cors.preflight(&preflight("https://app.example.com", Some("x-other"))).unwrap();
+        assert_eq!(response.status.as_u16(), 204);
+        assert_eq!(value_of(&response, header::ALLOW), Some("GET, PUT"));
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some("https://app.example.com"));
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_METHODS), Some("GET, PUT"));
+        assert_eq!(
+            value_of(&response, header::ACCESS_CONTROL_ALLOW_HEADERS),
+            Some("authorization, x-request-id")
+        );
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_MAX_AGE), Some("600"));
+        assert_eq!(value_of(&response, header::VARY), Some("Origin"));
+
+        // Origins that aren't listed learn the methods, but get no CORS grant
+        let response = cors.preflight(&preflight("https://evil.example.com", None)).unwrap();
+        assert_eq!(response.status.as_u16(), 204);
+        assert_eq!(value_of(&response, header::ALLOW), Some("GET, PUT"));
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), None);
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_METHODS), None);
+    }
+
+    #[test]
+    fn test_preflight_defaults() {
+        let cors = Cors::parse(&strings(&["*"]), &[], &[]).unwrap().unwrap();
+        let response = cors
+            .preflight(&preflight("https://any.example.com", Some("content-type")))
+            .unwrap();
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some("*"));
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_METHODS), Some(DEFAULT_METHODS));
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_HEADERS), Some("content-type"));
+        assert_eq!(value_of(&response, header::VARY), None);
+    }
+
+    #[test]
+    fn test_decorate_forwarded_responses() {
+        let cors = Cors::parse(&strings(&["https://app.example.com"]), &[], &[]).unwrap().unwrap();
+        let mut request = RequestHeader::build("GET", b"/api/users", None).unwrap();
+        request.insert_header(header::ORIGIN, "https://app.example.com").unwrap();
+
+        let mut response = ResponseHeader::build(200, None).unwrap();
+        cors.decorate(&request, &mut response).unwrap();
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some("https://app.example.com"));
+        assert_eq!(value_of(&response, header::VARY), Some("Origin"));
+
+        // The backend's own answer wins
+        let mut response = ResponseHeader::build(200, None).unwrap();
+        response.insert_header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "https://other.example.com").unwrap();
+        cors.decorate(&request, &mut response).unwrap();
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some("https://other.example.com"));
+
+        request.insert_header(header::ORIGIN, "https://evil.example.com").unwrap();
+        let mut response = ResponseHeader::build(200, None).unwrap();
+        cors.decorate(&request, &mut response).unwrap();
+        assert_eq!(value_of(&response, header::ACCESS_CONTROL_ALLOW_ORIGIN), None);
+    }
+}
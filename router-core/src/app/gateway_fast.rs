@@ -7,12 +7,70 @@
 //! ## Features
 //!
 //! * **Pattern-based routing**: Uses regular expressions to match request paths
-//! * **Path transformation**: Efficiently rewrites URLs before forwarding using `Captures::expand`
-//! * **Priority-based rules**: Higher priority rules are evaluated first
+//! * **Path transformation**: Rewrites URLs before forwarding using precompiled `PathTemplate`s
+//! * **Prefix stripping**: `strip_prefix: "/api"` removes a literal prefix before matching,
+//!   so `/api/users` is matched and forwarded as `/users` without hand-written regexes
+//! * **Priority-based rules**: Rules are evaluated by `priority` (lower first), then by the
+//!   priority of their gateway node (higher first), then by rule id, so the order is the same
+//!   on every reload
+//! * **Gateway node failover**: The same path on several gateway nodes forms a failover chain in
+//!   that order. A target that refused a connection is skipped for `GWRS_GATEWAY_UNHEALTHY_TTL`
+//!   seconds (default 10), its requests go to the next matching rule whose target is up
 //! * **Query parameter preservation**: Maintains original query parameters during rewrites
-//! * **Default fallback**: Routes unmatched requests to a precomputed default service
+//! * **Default fallback**: Routes unmatched requests to the built-in 404 page, or to the 500 page,
+//!   a static response or a catch-all backend set on the listener's proxy or with
+//!   `GWRS_GATEWAY_FALLBACK`
+//! * **Connect retry**: When an upstream refuses the connection, the next matching rule with a
+//!   different target is tried, up to `GWRS_GATEWAY_CONNECT_RETRIES` times, before the 500 page
+//! * **Static responses**: Rules with a `static` target are answered by the gateway itself
 //! * **Sharded LRU Caching**: High-performance, contention-reduced caching using the `lru` crate.
-//! * **Dynamic Configuration Reloading**: Refreshes routing rules based on configuration changes.
+//!   Hit/miss stats are reported over prottp `/status` and caches can be flushed on demand.
+//!   Entries expire after `GWRS_GATEWAY_CACHE_TTL` seconds (default 24h) even without config changes,
+//!   upstream hostnames are only resolved again when the rules are rebuilt.
+//! * **Dynamic Configuration Reloading**: Rules are rebuilt on the first request after the protocol
+//!   server applies new gateway paths, detected with a single atomic load of the config version.
+//! * **`Expect: 100-continue`**: Once a proxied route is connected the gateway answers the
+//!   expectation itself and drops it from the upstream request, so uploads start without waiting
+//!   on upstreams that ignore it. Static and fallback pages answer without reading the body.
+//! * **Response compression**: Rules of gateway nodes with `compress` set gzip or brotli encode
+//!   textual upstream responses for clients that accept it, skipping encoded bodies and bodies
+//!   shorter than `GWRS_COMPRESS_MIN_SIZE` bytes (default 1024).
+//! * **IP access control**: Rules of gateway nodes with `allow_cidrs`/`deny_cidrs` answer 403 to
+//!   clients outside the allowed networks or inside a denied one, deny winning on overlap.
+//! * **Overload protection**: At most `GWRS_GATEWAY_MAX_REQUESTS` requests per listener are in
+//!   flight (default unlimited), further requests get a 503 until one finishes.
+//! * **Upstream timeouts**: Connect, header and total response timeouts per gateway node. A
+//!   request whose upstream doesn't connect or answer in time gets the 504 page and a `TIMEOUT`
+//!   log line; a response running past its total timeout is cut off.
+//! * **Sticky sessions**: When the first matching rule is `sticky`, the gateway nodes serving
+//!   its path share the requests and each client keeps its backend, by an affinity cookie or
+//!   by client IP. A pinned backend that is down fails over and the client is pinned anew.
+//! * **Response redaction**: Rules of gateway nodes with `redact_fields` mask or remove those
+//!   fields of JSON responses, buffering bodies up to `GWRS_REDACT_MAX_BODY` bytes (default 1 MiB).
+//! * **Routing scripts**: Rules of gateway nodes with a `route_script` run it on every match. The
+//!   script uses the rule, passes it over for the next matching rule or names another target, see
+//!   `route_script`. Routes decided by a script are never cached and connect retries or failover
+//!   to other rules don't run scripts.
+//! * **Traffic splitting**: Rules with a `split` send a percentage of their requests to other
+//!   targets for canaries and A/B tests, per request or per client when `sticky`. The variant
+//!   is logged as `VARIANT` on the `[GWX]` lines, see `split`.
+//! * **HEAD and OPTIONS**: `HEAD` requests are forwarded and answered with the upstream's
+//!   headers and no body, static and fallback pages included. CORS preflights of rules whose
+//!   gateway node sets `cors_origins` are answered by the gateway, see `cors`; other `OPTIONS`
+//!   requests are forwarded.
+//! * **Header hygiene**: Hop-by-hop headers are removed from proxied requests and responses and
+//!   both get a `Via` entry. Gateway nodes can replace the upstream `Server` header or strip it
+//!   together with `X-Powered-By`, see `header_policy`.
+//! * **ACME challenges**: `GET /.well-known/acme-challenge/<token>` is answered with the key
+//!   authorization router-api published for the token, on any listener and host, see
+//!   `acme_challenge`.
+//! * **Request mirroring**: Rules with a `mirror_target` send a copy of each proxied request to
+//!   it after the client got its response, discarding the mirror's answer. Copies are bounded
+//!   in body size and number in flight, failures are logged as `TYPE:MIRROR` lines, see `mirror`.
+//! * **Route traces**: With `GWRS_GATEWAY_TRACE_TOKEN` set, a request sending the token in
+//!   `X-GWRS-Trace` gets its routing decision (rules evaluated, cache hit or miss, rewritten path,
+//!   target) back in the `X-GWRS-Trace` response header and as a `TYPE:TRACE` line, see
+//!   `route_trace`.
 //!
 //! ## Architecture
 //!
@@ -28,7 +86,7 @@
 //! 3. Cache Miss:
 //!    a. Gateway checks if configuration needs reloading.
 //!    b. Matches the path `/api/users` against the `/api/(.*)` pattern.
-//!    c. Path is transformed to `/v2/api/users` using `Captures::expand`.
+//!    c. Path is transformed to `/v2/api/users` using the rule's `PathTemplate`.
 //!    d. Query `?page=2` is appended.
 //!    e. The result (`/v2/api/users?page=2`, target_peer) is stored in the cache.
 //! 4. Cache Hit (or after miss processing):
@@ -44,21 +102,44 @@ use bytes::Bytes;
 use log::{debug, error, info, warn};
 // Use log macros consistently
 use pingora::prelude::*; // Import commonly used items
+use pingora::http::{RequestHeader, ResponseHeader};
 use pingora::proxy::{ProxyHttp, Session};
 use pingora::upstreams::peer::BasicPeer;
 use regex::Regex;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroUsize;
-use std::sync::{Arc, LazyLock, RwLock};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, RwLock, Weak};
 use std::time::{Duration, Instant};
 // lazy_static is not used anymore
 use lru::LruCache; // Use the standard LRU crate
 use dns_lookup::{self, lookup_host};
 
 // Assuming these are correctly defined in your project structure
+use crate::app::acme_challenge;
+use crate::app::affinity::{self, AffinityCookie, Sticky};
+use crate::app::compress::{self, Compressor};
+use crate::app::concurrency::{LimitKind, ListenerLimit, Permit};
+use crate::app::cors::Cors;
+use crate::app::header_policy::{self, ServerIdentity};
+use crate::app::ip_acl::IpAcl;
+use crate::app::mirror::{self, Mirror, MirrorCopy};
+use crate::app::path_template::PathTemplate;
+use crate::app::peer_health::PEER_HEALTH;
+use crate::app::redact::{self, Redaction, Redactor};
+use crate::app::route_script::{self, Decision, RouteScript, ScriptRunner};
+use crate::app::route_trace::{self, RouteTrace, RuleStep, Verdict};
+use crate::app::split::{self, Split};
+use crate::app::trace;
+use crate::app::upstream_timeout::{TimeoutKind, TimeoutOverrides, UpstreamTimeouts};
 use crate::config::{self, GatewayPath, DEFAULT_PORT};
+use crate::system::default_page::p_static::StaticPage;
+use crate::system::sni;
+use crate::system::writer::access_log::{self, AccessEntry};
 use crate::system::writer::rawid::atomic_id;
 
 // Number of cache shards to reduce lock contention
@@ -74,6 +155,29 @@ pub struct ContextGw {
     pub size_in: usize,
     pub size_out: usize,
     pub src_addr: Option<String>,
+    pub request_id: Option<String>,
+    pub route_path: Option<String>, // Path before rewriting, used to pick a retry candidate
+    pub route_host: Option<String>, // Requested host, for SNI checks of retry candidates
+    pub connect_attempts: usize,    // Failed upstream connects so far
+    pub failed_peers: Vec<String>,  // Targets that refused the connection
+    pub continue_sent: bool,        // Interim 100 Continue already written downstream
+    pub compress: bool,             // Matched rule's gateway node compresses responses
+    pub compressor: Option<Compressor>, // Encoder of the response body, set by response_filter
+    pub redact: Option<Arc<Redaction>>, // JSON fields the matched rule's gateway node redacts
+    pub redactor: Option<Redactor>, // Buffer of the response body to redact, set by response_filter
+    pub timeouts: UpstreamTimeouts, // Upstream timeouts of the matched rule's gateway node
+    pub started: Option<Instant>,   // When the request arrived, start of the total timeout
+    pub timed_out: Option<TimeoutKind>, // Timeout that ended the request, logged once
+    pub connect_started: Option<Instant>, // When the upstream peer was handed out, with tracing on
+    pub permit: Option<Permit>,     // Slot of the listener's request limit, released with the context
+    pub affinity_cookie: bool,      // Matched rule pins clients by cookie, set by response_filter
+    pub affinity_key: Option<String>, // Backend key of the affinity cookie the client sent
+    pub variant: Option<String>,    // Split variant of the matched rule, logged as VARIANT
+    pub cors: Option<Arc<Cors>>,    // CORS settings of the matched rule's gateway node, applied by response_filter
+    pub identity: Option<Arc<ServerIdentity>>, // Server header handling of the matched rule's gateway node
+    pub mirror: Option<Arc<Mirror>>, // Mirror of the matched rule, taken when the request is copied
+    pub mirror_copy: Option<MirrorCopy>, // Copy of the proxied request, sent to the mirror by logging
+    pub trace: Option<RouteTrace>,  // Routing decision of a request that asked for a trace with the token
 }
 
 impl Default for ContextGw {
@@ -86,6 +190,29 @@ impl Default for ContextGw {
             size_in: 0,
             size_out: 0,
             src_addr: None,
+            request_id: None,
+            route_path: None,
+            route_host: None,
+            connect_attempts: 0,
+            failed_peers: Vec::new(),
+            continue_sent: false,
+            compress: false,
+            compressor: None,
+            redact: None,
+            redactor: None,
+            timeouts: UpstreamTimeouts::default(),
+            started: None,
+            timed_out: None,
+            connect_started: None,
+            permit: None,
+            affinity_cookie: false,
+            affinity_key: None,
+            variant: None,
+            cors: None,
+            identity: None,
+            mirror: None,
+            mirror_copy: None,
+            trace: None,
         }
     }
 }
@@ -94,11 +221,33 @@ impl Default for ContextGw {
 // Uses the `lru` crate for efficient O(1) operations.
 
 struct ShardedLruCache<K, V> {
-    shards: Vec<RwLock<LruCache<K, V>>>,
+    shards: Vec<RwLock<LruCache<K, (Instant, V)>>>, // Value is stored with its insert time
+    ttl_ms: AtomicU64, // Entry lifetime, changed in place when settings are reloaded
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Hit/miss counters and size of one or more route caches.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub hit_rate: f64,
+}
+
+impl CacheStats {
+    fn merge(&mut self, other: CacheStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.entries += other.entries;
+        let lookups = self.hits + self.misses;
+        self.hit_rate = if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 };
+    }
 }
 
 impl<K: Hash + Eq + Clone, V: Clone> ShardedLruCache<K, V> {
-    fn new(per_shard_capacity: usize) -> Self {
+    fn new(per_shard_capacity: usize, ttl: Duration) -> Self {
         let capacity = NonZeroUsize::new(per_shard_capacity).unwrap_or_else(|| {
             warn!(
                 "Invalid per_shard_capacity (0), using default: {}",
@@ -112,7 +261,12 @@ impl<K: Hash + Eq + Clone, V: Clone> ShardedLruCache<K, V> {
         for _ in 0..CACHE_SHARDS {
             shards.push(RwLock::new(LruCache::new(capacity)));
         }
-        Self { shards }
+        Self {
+            shards,
+            ttl_ms: AtomicU64::new(ttl.as_millis() as u64),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
     }
 
     #[inline] // Inline for potentially faster access
@@ -123,10 +277,21 @@ impl<K: Hash + Eq + Clone, V: Clone> ShardedLruCache<K, V> {
     }
 
     /// Gets a value from the cache without updating the LRU order.
+    ///
+    /// Entries older than the TTL count as misses. They are left in place and
+    /// get overwritten by the next insert for the same key or evicted by LRU.
     fn get(&self, key: &K) -> Option<V> {
         let shard_index = self.get_shard_index(key);
         match self.shards[shard_index].read() {
-            Ok(shard) => shard.peek(key).cloned(),
+            Ok(shard) => {
+                let value = shard
+                    .peek(key)
+                    .filter(|(inserted, _)| inserted.elapsed() < self.ttl())
+                    .map(|(_, value)| value.clone());
+                let counter = if value.is_some() { &self.hits } else { &self.misses };
+                counter.fetch_add(1, Ordering::Relaxed);
+                value
+            }
             Err(e) => {
                 error!(
                     "Failed to acquire read lock on cache shard {}: {}",
@@ -137,12 +302,21 @@ impl<K: Hash + Eq + Clone, V: Clone> ShardedLruCache<K, V> {
         }
     }
 
+    fn ttl(&self) -> Duration {
+        Duration::from_millis(self.ttl_ms.load(Ordering::Relaxed))
+    }
+
+    /// Changes the entry lifetime, applying to entries already cached as well.
+    fn set_ttl(&self, ttl: Duration) {
+        self.ttl_ms.store(ttl.as_millis() as u64, Ordering::Relaxed);
+    }
+
     /// Inserts a value into the cache, potentially evicting the least recently used item.
     fn insert(&self, key: K, value: V) {
         let shard_index = self.get_shard_index(&key);
         match self.shards[shard_index].write() {
             Ok(mut shard) => {
-                shard.put(key, value); // Discard return value
+                shard.put(key, (Instant::now(), value)); // Discard return value
             }
             Err(e) => {
                 error!(
@@ -170,6 +344,78 @@ impl<K: Hash + Eq + Clone, V: Clone> ShardedLruCache<K, V> {
         }
         debug!("Cleared entries from route cache (potentially skipping poisoned shards)");
     }
+
+    /// Returns hit/miss counters and the current number of entries.
+    fn stats(&self) -> CacheStats {
+        let entries = self
+            .shards
+            .iter()
+            .filter_map(|shard| shard.read().ok().map(|s| s.len()))
+            .sum();
+        let mut stats = CacheStats {
+            entries,
+            ..Default::default()
+        };
+        stats.merge(CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ..Default::default()
+        });
+        stats
+    }
+}
+
+// Route cache type shared by every GatewayApp: key=path+query, value=the route it resolved to
+type RouteCache = ShardedLruCache<String, CachedRoute>;
+
+// Route caches of all live GatewayApp instances, for stats and forced flushes.
+static ROUTE_CACHES: LazyLock<RwLock<Vec<Weak<RouteCache>>>> = LazyLock::new(|| RwLock::new(Vec::new()));
+
+/// Aggregated route cache statistics across all gateway listeners.
+pub fn route_cache_stats() -> CacheStats {
+    let mut total = CacheStats::default();
+    if let Ok(caches) = ROUTE_CACHES.read() {
+        for cache in caches.iter().filter_map(Weak::upgrade) {
+            total.merge(cache.stats());
+        }
+    }
+    total
+}
+
+/// Clears the route cache of every gateway listener.
+///
+/// # Returns
+///
+/// The number of caches that were flushed.
+pub fn flush_route_caches() -> usize {
+    let mut flushed = 0;
+    if let Ok(mut caches) = ROUTE_CACHES.write() {
+        // Drop entries of GatewayApps that no longer exist
+        caches.retain(|cache| cache.strong_count() > 0);
+        for cache in caches.iter().filter_map(Weak::upgrade) {
+            cache.clear();
+            flushed += 1;
+        }
+    }
+    info!("Flushed {} gateway route caches", flushed);
+    flushed
+}
+
+/// Sets the route cache TTL of every gateway listener, e.g. after a settings reload.
+///
+/// # Returns
+///
+/// The number of caches that were updated.
+pub fn set_route_cache_ttl(ttl: Duration) -> usize {
+    let mut updated = 0;
+    if let Ok(caches) = ROUTE_CACHES.read() {
+        for cache in caches.iter().filter_map(Weak::upgrade) {
+            cache.set_ttl(ttl);
+            updated += 1;
+        }
+    }
+    info!("Set the TTL of {} gateway route caches to {}s", updated, ttl.as_secs());
+    updated
 }
 
 // --- Redirect Rule Definition ---
@@ -178,13 +424,91 @@ impl<K: Hash + Eq + Clone, V: Clone> ShardedLruCache<K, V> {
 /// Defines a single routing rule.
 #[derive(Clone, Debug)]
 struct RedirectRule {
+    id: String,                 // Rule id, tie-break between equal priorities
     pattern: Regex,             // Compiled regex for matching
-    tls: bool,                  // Flag for TLS connections
+    _tls: bool,                 // Flag for TLS connections
     sni: Option<String>,        // Optional SNI for TLS connections
     target_template: String,    // Template string for path transformation (e.g., "/v2/api/$1")
+    target_plan: PathTemplate,  // Parsed form of target_template, supports `$1` and `${name}`
+    strip_prefix: Option<String>, // Literal prefix removed before `pattern` is matched
     _alt_listen: String,        // Listener address this rule applies to
     alt_target: Arc<BasicPeer>, // Target backend service (Arc for cheap cloning)
+    static_page: Option<Arc<StaticPage>>, // Inline response for `static` targets, no backend involved
+    compress: bool,             // Compress responses of this rule's gateway node
+    redact: Option<Arc<Redaction>>, // JSON response fields redacted for this rule's gateway node
+    script: Option<RouteScript>, // Script of this rule's gateway node deciding each matched request
+    acl: Option<Arc<IpAcl>>,    // Client networks of this rule's gateway node, `None` admits everyone
+    timeouts: TimeoutOverrides, // Upstream timeouts of this rule's gateway node
+    sticky: Option<Sticky>,     // Session affinity among the rules serving the same route, or the split variants
+    split: Option<Arc<Split>>,  // Percentage shares of this rule's requests sent to other targets
+    cors: Option<Arc<Cors>>,    // CORS preflights answered for this rule's gateway node
+    identity: Option<Arc<ServerIdentity>>, // Server header handling of this rule's gateway node
+    mirror: Option<Arc<Mirror>>, // Backend receiving copies of this rule's proxied requests
     priority: usize,            // Rule evaluation priority (lower value = higher priority)
+    node_priority: i32,         // Gateway node priority, higher wins between equal `priority`
+}
+
+/// Per-request settings of a rule's gateway node.
+///
+/// Copied into [`ContextGw`] as a whole, so a request failed over to another
+/// rule is handled by that rule's settings only.
+#[derive(Clone, Debug, Default)]
+struct RuleSettings {
+    compress: bool,
+    redact: Option<Arc<Redaction>>,
+    timeouts: TimeoutOverrides,
+    cors: Option<Arc<Cors>>,
+    identity: Option<Arc<ServerIdentity>>,
+    mirror: Option<Arc<Mirror>>,
+}
+
+impl RuleSettings {
+    /// Settings of `rule`'s gateway node.
+    fn of(rule: &RedirectRule) -> Self {
+        Self {
+            compress: rule.compress,
+            redact: rule.redact.clone(),
+            timeouts: rule.timeouts,
+            cors: rule.cors.clone(),
+            identity: rule.identity.clone(),
+            mirror: rule.mirror.clone(),
+        }
+    }
+
+    /// Hands the settings to the request, `defaults` fill the timeouts the node leaves unset.
+    fn apply(self, ctx: &mut ContextGw, defaults: &UpstreamTimeouts) {
+        ctx.compress = self.compress;
+        ctx.redact = self.redact;
+        ctx.timeouts = self.timeouts.resolve(defaults);
+        ctx.cors = self.cors;
+        ctx.identity = self.identity;
+        ctx.mirror = self.mirror;
+    }
+}
+
+/// A route resolved by a rule, cached by the request's path and query.
+#[derive(Clone, Debug)]
+struct CachedRoute {
+    path_query: String,                   // Rewritten path and query, empty for static pages
+    sni: Option<String>,                  // SNI the requested host must match
+    peer: Arc<BasicPeer>,                 // Target backend of the rule
+    static_page: Option<Arc<StaticPage>>, // Inline response served instead of a backend
+    acl: Option<Arc<IpAcl>>,              // Client networks of the rule's gateway node
+    settings: RuleSettings,               // Per-request settings of the rule's gateway node
+}
+
+impl CachedRoute {
+    /// Route of `rule`, rewritten to `path_query`.
+    fn of(rule: &RedirectRule, path_query: String) -> Self {
+        Self {
+            path_query,
+            sni: rule.sni.clone(),
+            peer: rule.alt_target.clone(),
+            static_page: rule.static_page.clone(),
+            acl: rule.acl.clone(),
+            settings: RuleSettings::of(rule),
+        }
+    }
 }
 
 // --- Static Global State ---
@@ -193,8 +517,12 @@ struct RedirectRule {
 static REDIRECT_RULES: LazyLock<RwLock<HashMap<String, Arc<Vec<RedirectRule>>>>> =
     LazyLock::new(|| RwLock::new(HashMap::new()));
 
-// Holds the ID of the currently loaded configuration to detect changes.
-static SAVED_CONFIG_ID: LazyLock<RwLock<String>> = LazyLock::new(|| RwLock::new(String::new()));
+// Holds the ID of the currently loaded configuration to detect changes, `None` until the first load.
+static SAVED_CONFIG_ID: LazyLock<RwLock<Option<String>>> = LazyLock::new(|| RwLock::new(None));
+// Held by the one GatewayApp rebuilding the rules of every listener.
+static POPULATE_LOCK: Mutex<()> = Mutex::new(());
+// Request ID of the API call that produced the currently loaded rules.
+static SAVED_REQUEST_ID: LazyLock<RwLock<String>> = LazyLock::new(|| RwLock::new("-".to_string()));
 
 // Precompute the default fallback peer.
 static DEFAULT_FALLBACK_PEER: LazyLock<Box<HttpPeer>> = LazyLock::new(|| {
@@ -208,233 +536,188 @@ static DEFAULT_FALLBACK_PEER: LazyLock<Box<HttpPeer>> = LazyLock::new(|| {
 
 static _DEFAULT_FALLBACK_PEER_PORT: &str = DEFAULT_PORT.p404;
 
+// Served when every connect retry failed.
+static ERROR_PEER_ADDR: &str = DEFAULT_PORT.p500;
+
+// Served when the upstream didn't connect or answer within its timeouts.
+static TIMEOUT_PEER_ADDR: &str = DEFAULT_PORT.p504;
+
+// Answered to clients refused by a rule's IP access list.
+static FORBIDDEN_PAGE: LazyLock<StaticPage> = LazyLock::new(StaticPage::forbidden);
+
+// Answered when the listener is at its in-flight request limit.
+static UNAVAILABLE_PAGE: LazyLock<StaticPage> = LazyLock::new(StaticPage::unavailable);
+
+// Answered when a rule rewrites the request into an invalid URI.
+static BAD_REQUEST_PAGE: LazyLock<StaticPage> = LazyLock::new(StaticPage::bad_request);
+
+// --- Fallback Target ---
+
+/// Where a listener sends requests no rule matches.
+///
+/// Set by the `fallback` of the listener's proxy, or `GWRS_GATEWAY_FALLBACK` for
+/// listeners without one: `404` (the default), `500`, `static`/`static:{..}` like
+/// a rule target, or the `host:port` of a catch-all backend.
+#[derive(Debug, Clone, PartialEq)]
+enum Fallback {
+    /// The built-in 404 page
+    NotFound,
+    /// A backend, including the built-in 500 page
+    Peer(String),
+    /// An inline response of the gateway
+    Page(Arc<StaticPage>),
+}
+
+impl Fallback {
+    /// Parses a fallback target, or explains why it can't be used.
+    fn parse(target: &str) -> std::result::Result<Self, String> {
+        match target {
+            "404" => return Ok(Fallback::NotFound),
+            "500" => return Ok(Fallback::Peer(ERROR_PEER_ADDR.to_string())),
+            _ => {}
+        }
+        if let Some(page) = StaticPage::from_target(target) {
+            return page.map(|page| Fallback::Page(Arc::new(page)));
+        }
+        resolve_target_addr(target)
+            .map(|addr| Fallback::Peer(addr.to_string()))
+            .ok_or_else(|| format!("Unable to resolve fallback target '{}'", target))
+    }
+
+    /// Fallback of the listener `source`, the 404 page when unset or invalid.
+    fn from_config(source: &str, target: Option<&str>) -> Self {
+        let target = target
+            .map(str::trim)
+            .filter(|target| !target.is_empty())
+            .map(str::to_string)
+            .or_else(config::gateway_fallback);
+        let Some(target) = target else {
+            return Fallback::NotFound;
+        };
+        match Fallback::parse(&target) {
+            Ok(fallback) => {
+                info!("Unmatched requests on {} go to {}", source, target);
+                fallback
+            }
+            Err(e) => {
+                warn!("{} for listener {}, using the 404 page", e, source);
+                Fallback::NotFound
+            }
+        }
+    }
+}
+
 // --- Gateway Application ---
 
 /// # Gateway Application
 /// The main application implementing HTTP proxy routing.
 pub struct GatewayApp {
     source: String,                   // Listener address (e.g., "0.0.0.0:8080")
-    last_check_time: RwLock<Instant>, // Last time config was checked
-    check_interval: Duration,         // How often to check for config changes
-    route_cache: Arc<ShardedLruCache<String, (String, Option<String>, bool, Arc<BasicPeer>)>>, // Cache: key=path+query, value=(rewritten_path+query, sni, tls, target_peer)
+    seen_config_version: AtomicU64,   // Gateway config version the rules were last checked against
+    connect_retries: usize,           // Upstream connect retries before serving the 500 page
+    compress_min_size: usize,         // Responses known to be shorter are never compressed
+    redact_max_body: usize,           // Largest JSON body buffered for redaction
+    scripts: ScriptRunner,            // Runs the routing scripts of rules within their limits
+    default_timeouts: UpstreamTimeouts, // Upstream timeouts of gateway nodes that don't set their own
+    fallback: Fallback,               // Target of requests no rule matches
+    limit: Arc<ListenerLimit>,        // In-flight request limit of this listener
+    affinity: AffinityCookie,         // Cookie pinning clients of `cookie` sticky rules
+    via: Option<String>,              // Pseudonym of the gateway in `Via`, none adds no entry
+    mirror_max_body: usize,           // Largest request body copied to a rule's mirror
+    trace_token: Option<String>,      // Token of `X-GWRS-Trace` requests to trace, none traces nothing
+    route_cache: Arc<RouteCache>, // Cache: key=path+query, value=the route it resolved to
 }
 
 impl GatewayApp {
     /// Creates a new GatewayApp instance for a specific listener source.
-    pub fn new(alt_source: &str) -> Self {
+    ///
+    /// `fallback` is the listener's target of unmatched requests, see [`Fallback`].
+    pub fn new(alt_source: &str, fallback: Option<&str>) -> Self {
         debug!("Creating GatewayApp for source: {}", alt_source);
         let app = GatewayApp {
             source: alt_source.to_string(),
-            last_check_time: RwLock::new(Instant::now()),
-            check_interval: Duration::from_secs(5), // Check config every 5 seconds
+            seen_config_version: AtomicU64::new(config::gateway_config_version()),
+            connect_retries: config::gateway_connect_retries(),
+            compress_min_size: config::compress_min_size(),
+            redact_max_body: config::redact_max_body(),
+            scripts: ScriptRunner::from_config(),
+            default_timeouts: UpstreamTimeouts::from_config(),
+            fallback: Fallback::from_config(alt_source, fallback),
+            limit: ListenerLimit::register(LimitKind::Gateway, alt_source, config::gateway_max_requests()),
+            affinity: AffinityCookie::from_config(),
+            via: config::gateway_via(),
+            mirror_max_body: config::mirror_max_body(),
+            trace_token: config::gateway_trace_token(),
             // Use NonZeroUsize for cache capacity
-            route_cache: Arc::new(ShardedLruCache::new(DEFAULT_PER_SHARD_CAPACITY)),
+            route_cache: Arc::new(ShardedLruCache::new(
+                DEFAULT_PER_SHARD_CAPACITY,
+                config::gateway_cache_ttl(),
+            )),
         };
+        // Register the cache so it can be inspected and flushed over prottp
+        if let Ok(mut caches) = ROUTE_CACHES.write() {
+            caches.retain(|cache| cache.strong_count() > 0);
+            caches.push(Arc::downgrade(&app.route_cache));
+        }
         // Initial population of rules
         app.populate_rules(true);
         app
     }
 
     /// Populates or refreshes the routing rules from the configuration source.
-    /// This is the main function responsible for loading and processing rules.
-    fn populate_rules(&self, init: bool) {
-        let current_config_id = config::RoutingData::GatewayID.get();
-        debug!(
-            "Checking configuration. Current ID: '{}'",
-            current_config_id
-        );
-        // Fast path: Check if config ID has changed using a read lock first.
-        let config_changed = {
-            match SAVED_CONFIG_ID.read() {
-                Ok(saved_id_guard) => {
-                    log::debug!(
-                        "Comparing saved config ID '{}' with current ID '{}'",
-                        *saved_id_guard,
-                        current_config_id
+    ///
+    /// Rules of every listener are rebuilt at once, single-flight: while one
+    /// instance rebuilds, interval checks of the others skip and newly created
+    /// instances wait for it, and the rebuild is dropped if the config version
+    /// was already loaded by the time the guard is held.
+    ///
+    /// Returns `false` when the rebuild was left to another instance.
+    fn populate_rules(&self, init: bool) -> bool {
+        if !init && !config_changed(&config::RoutingData::GatewayID.get()) {
+            debug!("Configuration ID unchanged. Skipping rule population.");
+            return true; // No change detected
+        }
+
+        let _guard = if init {
+            // A new listener needs its rules before it serves anything
+            POPULATE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+        } else {
+            match POPULATE_LOCK.try_lock() {
+                Ok(guard) => guard,
+                Err(std::sync::TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                Err(std::sync::TryLockError::WouldBlock) => {
+                    debug!(
+                        "Rules are being rebuilt by another listener, skipping for source: {}",
+                        self.source
                     );
-                    *saved_id_guard != current_config_id
-                }
-                Err(e) => {
-                    error!("Failed to acquire read lock on SAVED_CONFIG_ID: {}. Assuming config changed.", e);
-                    true // Assume change if we can't read
+                    return false;
                 }
             }
         };
 
-        if !init && !config_changed {
+        // Read the version under the guard, another instance may have loaded it meanwhile
+        let current_config_id = config::RoutingData::GatewayID.get();
+        if !config_changed(&current_config_id) {
             debug!(
-                "Configuration ID unchanged ('{}'). Skipping rule population.",
+                "Configuration '{}' already loaded by another listener.",
                 current_config_id
             );
-            return; // No change detected
+            return true;
         }
 
         // Config ID has changed (or lock failed), proceed with update.
         // Log the old ID safely
         let old_config_id_str = match SAVED_CONFIG_ID.read() {
-            Ok(guard) => guard.clone(),
+            Ok(guard) => guard.clone().unwrap_or_else(|| "<none>".to_string()),
             Err(_) => "<unknown: read lock failed>".to_string(),
         };
         info!(
-            "Configuration change detected ({} -> {}). Reloading rules for source: {}",
+            "Configuration change detected ({} -> {}). Reloading rules, triggered by source: {}",
             old_config_id_str, current_config_id, self.source
         );
 
-        // Clear the route cache as rules are changing.
-        self.route_cache.clear();
-
-        // Load raw rule data from the configuration source.
-        let gateway_nodes = match config::RoutingData::GatewayRouting.xget::<Vec<GatewayPath>>() {
-            Some(nodes) if !nodes.is_empty() => nodes,
-            _ => {
-                warn!(
-                    "No valid gateway routing rules found in configuration for source '{}'.",
-                    self.source
-                );
-                // Update state even if no rules are found
-                self.update_rules_and_config_id(Vec::new(), &current_config_id);
-                return;
-            }
-        };
-
-        // Process and compile rules relevant to *this* gateway instance's source.
-        let mut applicable_rules = Vec::new();
-        for node in gateway_nodes {
-            log::debug!(
-                "Processing node: addr_listen={}, addr_target={}, path_listen={}, path_target={}, targetd={}",
-                node.addr_bind, node.addr_target, node.path_listen, node.path_target, self.source
-            );
-            if node.addr_bind != self.source {
-                continue;
-            }
-            // Filter rules for the current listener source.
-            log::debug!(
-                "Processing rule for source: {}, target: {}",
-                node.addr_target,
-                self.source
-            );
-
-            log::debug!(
-                "Path listen: {}, path target: {}",
-                node.path_listen,
-                node.path_target
-            );
-
-            // Determine if this is a plain string path, a wildcard path, or a regex pattern.
-            // Process the pattern string to handle different formats
-            let processed_pattern = if is_regex_pattern(&node.path_listen) {
-                // Already a regex pattern (contains regex special chars other than * at the end)
-                debug!("Processing as regex pattern: '{}'", node.path_listen);
-                node.path_listen.clone()
-            } else if node.path_listen.ends_with("/*") {
-                // Wildcard pattern (e.g., "/api/*")
-                debug!("Processing as wildcard pattern: '{}'", node.path_listen);
-                // Convert "/api/*" to "^/api/.*$"
-                let base_path = &node.path_listen[..node.path_listen.len() - 1];
-                format!("^{}.*$", base_path)
-            } else {
-                // Plain string path (e.g., "/test")
-                debug!("Processing as exact match pattern: '{}'", node.path_listen);
-                // Convert "/test" to "^/test$"
-                format!("^{}$", node.path_listen)
-            };
-
-            // Compile the processed regex pattern.
-            let pattern = match Regex::new(&processed_pattern) {
-                Ok(re) => re,
-                Err(e) => {
-                    warn!(
-                        "Invalid regex pattern '{}' (from '{}') for source '{}': {}. Skipping rule.",
-                        processed_pattern, node.path_listen, self.source, e
-                    );
-                    continue;
-                }
-            };
-
-            // Create the target peer (use Arc for cheap sharing).
-            // BasicPeer::new takes &str, so clone addr_target if needed or pass reference
-            log::debug!("Creating target peer for address: {}", node.addr_target);
-            let mut addr_target = node.addr_target.clone();
-            let is_ip = node.addr_target.bytes().filter(|&b| b == b'.').count() == 4;
-            if !is_ip {
-                let ipx = lookup_host(&node.addr_target);
-                if let Ok(ipx) = ipx {
-                    if let Some(ip) = ipx.first() {
-                        addr_target = ip.to_string()
-                    }
-                }
-            }
-            let target_peer = Arc::new(BasicPeer::new(&addr_target));
-
-            applicable_rules.push(RedirectRule {
-                pattern,
-                tls: node.tls,                     // TLS flag
-                sni: node.sni.clone(),             // Optional SNI
-                target_template: node.path_target, // Store the template string
-                _alt_listen: node.addr_bind,       // Already checked, but store for completeness
-                alt_target: target_peer,
-                priority: node.priority as usize,
-            });
-        }
-        log::info!(
-            "Found {} applicable rules for source: {}",
-            applicable_rules.len(),
-            self.source
-        );
-        if applicable_rules.is_empty() {
-            info!(
-                "No applicable redirect rules found for source: {}",
-                self.source
-            );
-        } else {
-            // Sort rules by priority (lower number = higher priority).
-            // Use unstable sort as stability is not required.
-            applicable_rules.sort_unstable_by_key(|rule| rule.priority);
-            info!(
-                "Loaded and sorted {} rules for source: {}",
-                applicable_rules.len(),
-                self.source
-            );
-        }
-
-        // Update the shared state with the new rules and config ID.
-        self.update_rules_and_config_id(applicable_rules, &current_config_id);
-    }
-
-    /// Atomically updates the REDIRECT_RULES and SAVED_CONFIG_ID.
-    fn update_rules_and_config_id(&self, rules: Vec<RedirectRule>, new_config_id: &str) {
-        // Acquire write locks to update the shared data.
-        match REDIRECT_RULES.write() {
-            Ok(mut rules_map_guard) => {
-                // Store rules wrapped in Arc for efficient cloning on read.
-                rules_map_guard.insert(self.source.clone(), Arc::new(rules));
-            }
-            Err(e) => {
-                error!(
-                    "Failed to acquire write lock on REDIRECT_RULES: {}. Rules not updated.",
-                    e
-                );
-                // Decide if we should still try to update the config ID or return
-                return; // Let's return early to avoid inconsistent state
-            }
-        }
-
-        match SAVED_CONFIG_ID.write() {
-            Ok(mut saved_id_guard) => {
-                *saved_id_guard = new_config_id.to_string();
-                debug!(
-                    "Successfully updated rules and saved config ID: '{}'",
-                    new_config_id
-                );
-            }
-            Err(e) => {
-                error!(
-                    "Failed to acquire write lock on SAVED_CONFIG_ID: {}. Config ID not updated.",
-                    e
-                );
-                // Rules were updated, but ID wasn't. This might cause repeated reloads.
-            }
-        }
+        rebuild_rules(&current_config_id);
+        true
     }
 
     /// Gets a clone of the rules relevant to this gateway instance.
@@ -447,7 +730,8 @@ impl GatewayApp {
                     .get(&self.source)
                     .cloned()
                     .unwrap_or_else(|| {
-                        log::warn!(
+                        // Listeners without rules are not in the map
+                        log::debug!(
                             "No rules found for source '{}'. Returning empty ruleset.",
                             self.source
                         );
@@ -464,42 +748,686 @@ impl GatewayApp {
         }
     }
 
-    /// Checks if the configuration should be reloaded based on time interval and ID change.
+    /// Reloads the rules when gateway paths were applied since the last check.
     fn check_and_reload_config_if_needed(&self) {
+        let version = config::gateway_config_version();
+        if self.seen_config_version.load(Ordering::Acquire) == version {
+            return;
+        }
+        // Only mark the version seen once the rules are current, a skipped
+        // rebuild is retried on the next request
+        if self.populate_rules(false) {
+            self.seen_config_version.store(version, Ordering::Release);
+        }
+    }
+}
+
+impl GatewayApp {
+    /// Answers the request with a static page instead of proxying it.
+    ///
+    /// Returns `Ok(false)` so pingora skips the upstream; the `logging` hook
+    /// still records the response with `DST:static`.
+    async fn serve_static(
+        &self,
+        session: &mut Session,
+        ctx: &mut ContextGw,
+        page: &StaticPage,
+    ) -> Result<bool> {
+        ctx.peer = Some("static".into());
+        ctx.size_out = match session.req_header().method {
+            http::Method::HEAD => 0,
+            _ => page.body.len(),
+        };
+        match finish_trace(ctx).map(RouteTrace::header_value) {
+            Some(trace) => page.respond_with(session, &[(route_trace::TRACE_HEADER, trace)]).await?,
+            None => page.respond(session).await?,
+        }
+        Ok(false)
+    }
+
+    /// Answers 403 when the rule's access list refuses the client.
+    ///
+    /// Returns `None` when the client may proceed, otherwise the result for
+    /// `proxy_upstream_filter`.
+    async fn enforce_acl(
+        &self,
+        session: &mut Session,
+        ctx: &mut ContextGw,
+        acl: Option<&IpAcl>,
+    ) -> Option<Result<bool>> {
+        let acl = acl?;
+        let client = client_ip(session);
+        if client.map_or(false, |ip| acl.permits(ip)) {
+            return None;
+        }
+        warn!(
+            "[GWX] | ID:{}, TYPE:DENY, CONN:{}, SIZE:0, STAT:403, SRC:{}, DST:forbidden, COMMENT:{} client {} refused by access list |",
+            ctx.conn_id.clone().unwrap_or("-".into()),
+            ctx.conn_type.clone().unwrap_or("UNKNOWN".into()),
+            ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
+            ctx.request_id.clone().unwrap_or("-".into()),
+            client.map_or("unknown".to_string(), |ip| ip.to_string())
+        );
+        Some(self.serve_static(session, ctx, &FORBIDDEN_PAGE).await)
+    }
+
+    /// Answers a CORS preflight when the rule's gateway node sets `cors_origins`.
+    ///
+    /// Returns `None` when the request goes on to the backend, otherwise the
+    /// result for `proxy_upstream_filter`.
+    async fn answer_preflight(
+        &self,
+        session: &mut Session,
+        ctx: &mut ContextGw,
+        cors: Option<&Cors>,
+    ) -> Option<Result<bool>> {
+        let cors = cors.filter(|_| Cors::is_preflight(session.req_header()))?;
+        let response = match cors.preflight(session.req_header()) {
+            Ok(response) => response,
+            Err(e) => return Some(Err(e)),
+        };
+        ctx.peer = Some("preflight".into());
+        ctx.size_out = 0;
+        Some(
+            session
+                .write_response_header(Box::new(response), true)
+                .await
+                .map(|_| false),
+        )
+    }
+
+    /// Answers 503 when the listener is at its in-flight request limit.
+    async fn reject_overload(&self, session: &mut Session, ctx: &mut ContextGw) -> Result<bool> {
+        warn!(
+            "[GWX] | ID:{}, TYPE:REJECT, CONN:{}, SIZE:0, STAT:503, SRC:{}, DST:overload, COMMENT:{} listener at its limit of {} requests |",
+            ctx.conn_id.clone().unwrap_or("-".into()),
+            ctx.conn_type.clone().unwrap_or("UNKNOWN".into()),
+            ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
+            ctx.request_id.clone().unwrap_or("-".into()),
+            self.limit.max()
+        );
+        self.serve_static(session, ctx, &UNAVAILABLE_PAGE).await
+    }
+
+    /// Answers 400 when a rule rewrote the request into an invalid URI.
+    ///
+    /// Logs a `REJECT` line with the rewritten target, the request isn't forwarded.
+    async fn reject_rewrite(
+        &self,
+        session: &mut Session,
+        ctx: &mut ContextGw,
+        path_query: &str,
+        reason: &str,
+    ) -> Result<bool> {
+        warn!(
+            "[GWX] | ID:{}, TYPE:REJECT, CONN:{}, SIZE:0, STAT:400, SRC:{}, DST:invalid, COMMENT:{} rewritten target {:?} of {:?} is not a valid URI: {} |",
+            ctx.conn_id.clone().unwrap_or("-".into()),
+            ctx.conn_type.clone().unwrap_or("UNKNOWN".into()),
+            ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
+            ctx.request_id.clone().unwrap_or("-".into()),
+            path_query,
+            ctx.route_path.as_deref().unwrap_or("-"),
+            reason
+        );
+        self.serve_static(session, ctx, &BAD_REQUEST_PAGE).await
+    }
+
+    /// Logs a `TIMEOUT` line naming the timeout that hit `peer` and its length.
+    fn log_timeout(&self, ctx: &mut ContextGw, kind: TimeoutKind, peer: &str) {
+        ctx.timed_out = Some(kind);
+        let limit = ctx
+            .timeouts
+            .limit(kind)
+            .map_or("-".to_string(), |limit| format!("{}s", limit.as_secs()));
+        warn!(
+            "[GWX] | ID:{}, TYPE:TIMEOUT, CONN:{}, SIZE:{}, STAT:504, SRC:{}, DST:{}, COMMENT:{} {} timeout after {} |",
+            ctx.conn_id.clone().unwrap_or("-".into()),
+            ctx.conn_type.clone().unwrap_or("UNKNOWN".into()),
+            ctx.size_out,
+            ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
+            peer,
+            ctx.request_id.clone().unwrap_or("-".into()),
+            kind.name(),
+            limit
+        );
+    }
+
+    /// Moves a routed request to the next matching rule when its target is down.
+    ///
+    /// When every matching target is down the request stays on the chosen one.
+    fn failover_if_down(&self, session: &mut Session, ctx: &mut ContextGw) {
+        let Some(peer) = ctx.peer.clone() else {
+            return;
+        };
+        if !PEER_HEALTH.is_down(&peer, Instant::now()) {
+            return;
+        }
+        if let Some((address, settings)) = next_connect_candidate(&self.get_rules(), session, ctx) {
+            debug!("Target {} is down, failing over to {}", peer, address);
+            ctx.peer = Some(address);
+            settings.apply(ctx, &self.default_timeouts);
+        }
+    }
+
+    /// Picks the backend of a sticky rule for this request.
+    ///
+    /// Candidates are the rules serving the same route as `rule` whose target
+    /// is up and whose access list admits the client. A cookie naming one of
+    /// them wins, otherwise the client address ranks them. When every target
+    /// is down `rule` is kept, like `failover_if_down` does.
+    fn sticky_backend<'r>(
+        &self,
+        session: &Session,
+        ctx: &mut ContextGw,
+        rules: &'r [RedirectRule],
+        rule: &'r RedirectRule,
+        sticky: Sticky,
+    ) -> &'r RedirectRule {
+        let client = client_ip(session);
         let now = Instant::now();
-        let needs_check = {
-            match self.last_check_time.read() {
-                Ok(last_check_guard) => {
-                    now.duration_since(*last_check_guard) >= self.check_interval
+        let (candidates, addresses): (Vec<&RedirectRule>, Vec<String>) = rules
+            .iter()
+            .filter(|other| same_route(rule, other))
+            .filter(|other| match &other.acl {
+                Some(acl) => client.map_or(false, |ip| acl.permits(ip)),
+                None => true,
+            })
+            .map(|other| (other, other.alt_target._address.to_string()))
+            .filter(|(_, address)| !PEER_HEALTH.is_down(address, now))
+            .unzip();
+
+        if sticky == Sticky::Cookie {
+            self.read_affinity(session, ctx);
+            if let Some(key) = &ctx.affinity_key {
+                if let Some(index) = addresses.iter().position(|address| affinity::backend_key(address) == *key) {
+                    return candidates[index];
                 }
-                Err(e) => {
-                    error!("Failed to acquire read lock on last_check_time: {}. Assuming check needed.", e);
-                    true // Assume check needed if lock fails
+            }
+        }
+        match affinity::rendezvous(client, &addresses) {
+            Some(index) => candidates[index],
+            None => rule,
+        }
+    }
+
+    /// Picks the variant of a split rule for this request, `None` for the rule's own target.
+    ///
+    /// Without `sticky` the variant is drawn at random. With it a cookie naming
+    /// a variant or the rule's target wins, otherwise the client address picks
+    /// it. Clients without an address draw at random.
+    fn split_variant(
+        &self,
+        session: &Session,
+        ctx: &mut ContextGw,
+        rule: &RedirectRule,
+        split: &Split,
+        sticky: Option<Sticky>,
+    ) -> Option<String> {
+        let own_address = rule.alt_target._address.to_string();
+        let mut pinned = None;
+        if sticky == Some(Sticky::Cookie) {
+            self.read_affinity(session, ctx);
+            if let Some(key) = &ctx.affinity_key {
+                if *key == affinity::backend_key(&own_address) {
+                    pinned = Some(None);
+                } else if let Some(variant) = split.find(key) {
+                    pinned = Some(Some(variant));
                 }
             }
+        }
+        let variant = pinned.unwrap_or_else(|| {
+            let bucket = match (sticky, client_ip(session)) {
+                (Some(_), Some(client)) => split::client_bucket(client, &rule.id),
+                _ => split::random_bucket(),
+            };
+            split.pick(bucket)
+        });
+        ctx.variant = Some(variant.map_or(split::DEFAULT_VARIANT, |variant| variant.name.as_str()).to_string());
+        variant.map(|variant| variant.address.clone())
+    }
+
+    /// Reads the affinity cookie of the request, the response pins the backend that answers.
+    fn read_affinity(&self, session: &Session, ctx: &mut ContextGw) {
+        ctx.affinity_cookie = true;
+        ctx.affinity_key = self.affinity.read(
+            session
+                .req_header()
+                .headers
+                .get_all(http::header::COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok()),
+        );
+    }
+
+    /// Pins the client to the backend that answered, unless its cookie already names it.
+    ///
+    /// Error pages don't pin, the next request picks a backend again.
+    fn pin_affinity(&self, response: &mut ResponseHeader, ctx: &ContextGw) -> Result<()> {
+        let Some(peer) = ctx.peer.as_deref() else {
+            return Ok(());
         };
+        if [ERROR_PEER_ADDR, TIMEOUT_PEER_ADDR].contains(&peer) {
+            return Ok(());
+        }
+        let key = affinity::backend_key(peer);
+        if ctx.affinity_key.as_deref() != Some(key.as_str()) {
+            response.append_header(http::header::SET_COOKIE, self.affinity.set_cookie(&key))?;
+        }
+        Ok(())
+    }
 
-        if needs_check {
-            match self.last_check_time.write() {
-                Ok(mut last_check_guard) => {
-                    // Double-check in case another thread updated it between the read and write lock acquisition.
-                    if now.duration_since(*last_check_guard) >= self.check_interval {
-                        // Update last check time *before* potentially long-running populate_rules
-                        *last_check_guard = now;
-                        // Drop the lock before calling populate_rules to avoid holding it too long
-                        drop(last_check_guard);
-                        // Now perform the actual check and potential reload
-                        debug!("Checking rules due to interval check...");
-                        self.populate_rules(false);
-                    }
-                    // If the double-check fails, another thread already handled it.
-                }
-                Err(e) => {
-                    error!("Failed to acquire write lock on last_check_time: {}. Check interval not updated.", e);
-                    // Might lead to more frequent checks if this persists
-                }
+    /// Prepares the redaction of a JSON response of a rule with `redact_fields`.
+    ///
+    /// The body is buffered and goes out chunked, its length changes. Responses
+    /// that can't be redacted, encoded, partial or known to be too large, are
+    /// refused with 502 instead of being passed on.
+    fn start_redaction(
+        &self,
+        req: &RequestHeader,
+        response: &mut ResponseHeader,
+        ctx: &mut ContextGw,
+        redaction: Arc<Redaction>,
+    ) -> Result<()> {
+        if !has_body(req, response) || !redact::is_json(&response.headers) {
+            return Ok(());
+        }
+        let peer = ctx.peer.as_deref().unwrap_or("UNKNOWN");
+        if redact::is_encoded(&response.headers) || response.status.as_u16() == 206 {
+            warn!("Refusing the encoded or partial JSON response of {}, it can't be redacted", peer);
+            return Error::e_explain(HTTPStatus(502), "response can't be redacted");
+        }
+        if redact::content_length(&response.headers).map_or(false, |length| length > self.redact_max_body) {
+            warn!(
+                "Refusing the JSON response of {}, it is larger than the {} bytes buffered for redaction",
+                peer, self.redact_max_body
+            );
+            return Error::e_explain(HTTPStatus(502), "response too large to redact");
+        }
+        response.remove_header(&http::header::CONTENT_LENGTH);
+        response.insert_header(http::header::TRANSFER_ENCODING, "chunked")?;
+        weaken_etag(response)?;
+        ctx.redactor = Some(Redactor::new(redaction, self.redact_max_body));
+        Ok(())
+    }
+}
+
+/// Picks the next matching rule whose target hasn't failed yet and rewrites
+/// the request for it.
+///
+/// # Returns
+///
+/// The address of the new target and the settings of its rule, or `None` when
+/// no alternative is left.
+fn next_connect_candidate(
+    rules: &[RedirectRule],
+    session: &mut Session,
+    ctx: &ContextGw,
+) -> Option<(String, RuleSettings)> {
+    let path = ctx.route_path.as_deref()?;
+    let host = ctx.route_host.as_deref().unwrap_or("");
+    let query = session.req_header().uri.query().map(|q| q.to_string());
+
+    for rule in rules {
+        if rule.static_page.is_some() {
+            continue;
+        }
+        let address = rule.alt_target._address.to_string();
+        if ctx.failed_peers.contains(&address) || PEER_HEALTH.is_down(&address, Instant::now()) {
+            continue;
+        }
+        if let Some(sni) = &rule.sni {
+            if !sni_matches(sni, host) {
+                continue;
+            }
+        }
+        if let Some(acl) = &rule.acl {
+            if !client_ip(session).map_or(false, |ip| acl.permits(ip)) {
+                continue;
             }
         }
+        let subject = match &rule.strip_prefix {
+            Some(prefix) => match strip_path_prefix(path, prefix) {
+                Some(rest) => rest,
+                None => continue,
+            },
+            None => path,
+        };
+        let Some(captures) = rule.pattern.captures(subject) else {
+            continue;
+        };
+
+        let rewritten_path = rule.target_plan.expand(&captures);
+        let path_query = match &query {
+            Some(q) => format!("{}?{}", rewritten_path, q),
+            None => rewritten_path,
+        };
+        if let Err(e) = set_path_and_query(session, &path_query) {
+            error!("Error rewriting URI for retry target {}: {}", address, e);
+            continue;
+        }
+        return Some((address, RuleSettings::of(rule)));
+    }
+    None
+}
+
+/// Whether `config_id` differs from the loaded configuration.
+fn config_changed(config_id: &str) -> bool {
+    match SAVED_CONFIG_ID.read() {
+        Ok(saved_id_guard) => saved_id_guard.as_deref() != Some(config_id),
+        Err(e) => {
+            error!("Failed to acquire read lock on SAVED_CONFIG_ID: {}. Assuming config changed.", e);
+            true // Assume change if we can't read
+        }
+    }
+}
+
+/// Compiles the rules of every listener and swaps them in with `config_id`.
+///
+/// Route caches of all listeners are flushed after the swap, so no request
+/// caches a route of the old rules once the new ones are visible.
+fn rebuild_rules(config_id: &str) {
+    let gateway_nodes = config::RoutingData::GatewayRouting
+        .xget::<Vec<GatewayPath>>()
+        .unwrap_or_default();
+    if gateway_nodes.is_empty() {
+        warn!("No valid gateway routing rules found in configuration.");
+    }
+
+    // Group compiled rules by the listener they apply to
+    let mut rules_by_source: HashMap<String, Vec<RedirectRule>> = HashMap::new();
+    for node in gateway_nodes {
+        let source = node.addr_bind.clone();
+        match compile_rule(node) {
+            Ok(rule) => rules_by_source.entry(source).or_default().push(rule),
+            Err(e) => warn!("{} for source '{}'. Skipping rule.", e, source),
+        }
+    }
+    let rules_map: HashMap<String, Arc<Vec<RedirectRule>>> = rules_by_source
+        .into_iter()
+        .map(|(source, mut rules)| {
+            sort_rules(&mut rules);
+            info!("Loaded and sorted {} rules for source: {}", rules.len(), source);
+            (source, Arc::new(rules))
+        })
+        .collect();
+
+    match REDIRECT_RULES.write() {
+        Ok(mut rules_map_guard) => *rules_map_guard = rules_map,
+        Err(e) => {
+            error!(
+                "Failed to acquire write lock on REDIRECT_RULES: {}. Rules not updated.",
+                e
+            );
+            return; // Keep the old config ID so the next check retries
+        }
+    }
+
+    if let Ok(mut saved_request_guard) = SAVED_REQUEST_ID.write() {
+        *saved_request_guard = config::RoutingData::GatewayRequestID.get();
+    }
+
+    match SAVED_CONFIG_ID.write() {
+        Ok(mut saved_id_guard) => {
+            *saved_id_guard = Some(config_id.to_string());
+            debug!("Successfully updated rules and saved config ID: '{}'", config_id);
+        }
+        Err(e) => {
+            error!(
+                "Failed to acquire write lock on SAVED_CONFIG_ID: {}. Config ID not updated.",
+                e
+            );
+            // Rules were updated, but ID wasn't. This might cause repeated reloads.
+        }
+    }
+
+    flush_route_caches();
+}
+
+/// Puts rules in evaluation and failover order.
+///
+/// Lower `priority` first, then the higher gateway node priority, then rule id,
+/// so equal priorities route the same way across restarts. A path configured on
+/// several gateway nodes therefore fails over from the highest priority node down.
+fn sort_rules(rules: &mut [RedirectRule]) {
+    rules.sort_unstable_by(|a, b| {
+        a.priority
+            .cmp(&b.priority)
+            .then_with(|| b.node_priority.cmp(&a.node_priority))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+/// Whether `other` serves the same route as `rule`, making both backends of a sticky rule.
+///
+/// Static rules never are, they have no backend.
+fn same_route(rule: &RedirectRule, other: &RedirectRule) -> bool {
+    other.static_page.is_none()
+        && other.pattern.as_str() == rule.pattern.as_str()
+        && other.strip_prefix == rule.strip_prefix
+        && other.sni == rule.sni
+}
+
+/// Compiles one configured path rule, or explains why it can't be used.
+///
+/// Shared by the live reload, which skips failing rules, and the dry-run
+/// validation of the protocol server, which reports them.
+fn compile_rule(mut node: GatewayPath) -> std::result::Result<RedirectRule, String> {
+    // A strip_prefix is matched literally, `pattern` and `target` then apply to the rest.
+    // The API stores it normalized, only prefixes that can't match a path are refused.
+    let strip_prefix = node.strip_prefix.take().filter(|prefix| !prefix.is_empty());
+    if let Some(prefix) = strip_prefix.as_deref().filter(|prefix| !prefix.starts_with('/')) {
+        return Err(format!("Invalid strip_prefix '{}'", prefix));
+    }
+    // With a prefix, an empty pattern matches every stripped path and an
+    // empty target forwards the stripped path unchanged.
+    if strip_prefix.is_some() {
+        if node.path_listen.is_empty() {
+            node.path_listen = "^(.*)$".to_string();
+        }
+        if node.path_target.is_empty() {
+            node.path_target = "$0".to_string();
+        }
+    }
+
+    // Determine if this is a plain string path, a wildcard path, or a regex pattern.
+    // Process the pattern string to handle different formats
+    let processed_pattern = if is_regex_pattern(&node.path_listen) {
+        // Already a regex pattern (contains regex special chars other than * at the end)
+        debug!("Processing as regex pattern: '{}'", node.path_listen);
+        node.path_listen.clone()
+    } else if node.path_listen.ends_with("/*") {
+        // Wildcard pattern (e.g., "/api/*")
+        debug!("Processing as wildcard pattern: '{}'", node.path_listen);
+        // Convert "/api/*" to "^/api/.*$"
+        let base_path = &node.path_listen[..node.path_listen.len() - 1];
+        format!("^{}.*$", base_path)
+    } else {
+        // Plain string path (e.g., "/test")
+        debug!("Processing as exact match pattern: '{}'", node.path_listen);
+        // Convert "/test" to "^/test$"
+        format!("^{}$", node.path_listen)
+    };
+
+    // Compile the processed regex pattern.
+    let pattern = Regex::new(&processed_pattern).map_err(|e| {
+        format!(
+            "Invalid regex pattern '{}' (from '{}'): {}",
+            processed_pattern, node.path_listen, e
+        )
+    })?;
+
+    // Static targets are answered by the gateway, so there is no backend to resolve.
+    // Rules in maintenance are answered the same way, their target stays configured.
+    let static_page = match StaticPage::from_target(&node.path_target) {
+        _ if node.maintenance => Some(Arc::new(StaticPage::maintenance())),
+        Some(Ok(page)) => Some(Arc::new(page)),
+        Some(Err(e)) => {
+            return Err(format!(
+                "Static target for pattern '{}': {}",
+                node.path_listen, e
+            ))
+        }
+        None => None,
+    };
+
+    // Parse the target template once, flagging references the pattern can't provide.
+    let target_plan = PathTemplate::parse(&node.path_target);
+    let unknown = target_plan.unknown_references(&pattern);
+    if static_page.is_none() && !unknown.is_empty() {
+        warn!(
+            "Target '{}' references groups not in pattern '{}': {}. They will expand to empty.",
+            node.path_target, processed_pattern, unknown.join(", ")
+        );
+    }
+
+    // Create the target peer (use Arc for cheap sharing).
+    // Hostnames are resolved once here, IP literals (including bracketed IPv6) are used as is.
+    log::debug!("Creating target peer for address: {}", node.addr_target);
+    let addr_target = match resolve_target_addr(&node.addr_target) {
+        Some(addr) => addr,
+        // Static rules never connect, keep them even if the node's target is unreachable
+        None if static_page.is_some() => DEFAULT_PORT
+            .p404
+            .parse::<SocketAddr>()
+            .expect("Default 404 address must be a socket address"),
+        None => {
+            return Err(format!(
+                "Unable to resolve target address '{}'",
+                node.addr_target
+            ))
+        }
+    };
+    let target_peer = Arc::new(BasicPeer::new(&addr_target.to_string()));
+
+    let acl = IpAcl::parse(&node.allow_cidrs, &node.deny_cidrs)?.map(Arc::new);
+    let sticky = match node.sticky.as_deref() {
+        Some(value) => Sticky::parse(value)?,
+        None => None,
+    };
+    let redact = Redaction::parse(&node.redact_fields, node.redact_mode.as_deref())?.map(Arc::new);
+    let split = Split::parse(&node.split, resolve_target_addr)?.map(Arc::new);
+    let mirror = Mirror::parse(node.mirror_target.as_deref(), resolve_target_addr)?.map(Arc::new);
+    let cors = Cors::parse(&node.cors_origins, &node.cors_methods, &node.cors_headers)?.map(Arc::new);
+    let identity = ServerIdentity::parse(node.server_header.as_deref(), node.strip_server_headers)?.map(Arc::new);
+    let script = match node.route_script.as_deref() {
+        Some(source) => RouteScript::compile(source)?,
+        None => None,
+    };
+
+    Ok(RedirectRule {
+        id: node.id,
+        pattern,
+        _tls: node.tls,                    // TLS flag
+        sni: node.sni.clone(),             // Optional SNI
+        target_template: node.path_target, // Store the template string
+        target_plan,
+        strip_prefix,
+        _alt_listen: node.addr_bind,       // Already checked, but store for completeness
+        alt_target: target_peer,
+        static_page,
+        compress: node.compress,
+        redact,
+        script,
+        acl,
+        timeouts: TimeoutOverrides {
+            connect: node.connect_timeout_secs,
+            header: node.header_timeout_secs,
+            total: node.total_timeout_secs,
+        },
+        sticky,
+        split,
+        cors,
+        identity,
+        mirror,
+        priority: node.priority as usize,
+        node_priority: node.node_priority,
+    })
+}
+
+/// Checks path rules the way a reload compiles them, without applying anything.
+///
+/// Returns one message per rule that would be skipped.
+pub fn validate_rules(paths: &[GatewayPath]) -> Vec<String> {
+    paths
+        .iter()
+        .filter_map(|node| {
+            compile_rule(node.clone())
+                .err()
+                .map(|e| format!("Rule '{}': {}", node.id, e))
+        })
+        .collect()
+}
+
+/// Path routing of one listener without a pingora session.
+///
+/// Resolves a path and query the way `proxy_upstream_filter` does: the route
+/// cache first, the rules and the target template on a miss. Lets the
+/// benchmarks in `benches/` time the routing hot path in isolation. Routing
+/// scripts need a request and are not run, their rules are used as configured.
+pub struct RouteResolver {
+    rules: Vec<RedirectRule>,
+    route_cache: RouteCache,
+}
+
+impl RouteResolver {
+    /// Compiles and orders `paths` like a reload does.
+    ///
+    /// Fails on the first rule a reload would skip.
+    pub fn new(paths: Vec<GatewayPath>) -> std::result::Result<Self, String> {
+        let mut rules = paths
+            .into_iter()
+            .map(compile_rule)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        sort_rules(&mut rules);
+        Ok(Self {
+            rules,
+            route_cache: ShardedLruCache::new(DEFAULT_PER_SHARD_CAPACITY, config::gateway_cache_ttl()),
+        })
+    }
+
+    /// Rewritten path and query, from the route cache when possible.
+    ///
+    /// Returns `None` when no rule matches or the rule serves a static page.
+    /// Sticky routes depend on the client and are not cached.
+    pub fn resolve(&self, path_query: &str) -> Option<String> {
+        let key = path_query.to_string();
+        if let Some(route) = self.route_cache.get(&key) {
+            return Some(route.path_query);
+        }
+        let (path, query) = split_path_query(path_query);
+        let (rule, rewritten) = self.rewrite(path, query)?;
+        if rule.sticky.is_some() {
+            return Some(rewritten);
+        }
+        self.route_cache.insert(key, CachedRoute::of(rule, rewritten.clone()));
+        Some(rewritten)
+    }
+
+    /// Rewritten path and query, always matched against the rules.
+    pub fn resolve_uncached(&self, path_query: &str) -> Option<String> {
+        let (path, query) = split_path_query(path_query);
+        self.rewrite(path, query).map(|(_, rewritten)| rewritten)
+    }
+
+    fn rewrite(&self, path: &str, query: Option<&str>) -> Option<(&RedirectRule, String)> {
+        let (rule, captures) = match_rule(&self.rules, path)?;
+        if rule.static_page.is_some() {
+            return None;
+        }
+        let rewritten_path = rule.target_plan.expand(&captures);
+        let rewritten = match query {
+            Some(q) => format!("{}?{}", rewritten_path, q),
+            None => rewritten_path,
+        };
+        Some((rule, rewritten))
+    }
+}
+
+/// Splits `/path?query` at the first `?`.
+fn split_path_query(path_query: &str) -> (&str, Option<&str>) {
+    match path_query.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path_query, None),
     }
 }
 
@@ -522,20 +1450,279 @@ fn is_regex_pattern(pattern: &str) -> bool {
         '^', '$', '.', '+', '?', '(', ')', '[', ']', '{', '}', '|', '\\',
     ];
 
-    // Check if the pattern contains any regex special characters
-    for &c in &regex_special_chars {
-        if pattern.contains(c) {
-            return true;
-        }
+    // Check if the pattern contains any regex special characters
+    for &c in &regex_special_chars {
+        if pattern.contains(c) {
+            return true;
+        }
+    }
+
+    // If the pattern has a wildcard in the middle (not at the end), treat as regex
+    if pattern.contains('*') && !pattern.ends_with("/*") {
+        return true;
+    }
+
+    // Otherwise it's a plain string or a simple wildcard pattern
+    false
+}
+
+/// Finds the first rule matching `path`, in evaluation order.
+///
+/// Returns the rule with the captures its target template expands.
+fn match_rule<'r, 'p>(
+    rules: &'r [RedirectRule],
+    path: &'p str,
+) -> Option<(&'r RedirectRule, regex::Captures<'p>)> {
+    match_rules(rules, path).next()
+}
+
+/// Every rule matching `path`, in evaluation order, with its captures.
+fn match_rules<'r, 'p>(
+    rules: &'r [RedirectRule],
+    path: &'p str,
+) -> impl Iterator<Item = (&'r RedirectRule, regex::Captures<'p>)> {
+    rules
+        .iter()
+        .filter_map(move |rule| rule_captures(rule, path).map(|captures| (rule, captures)))
+}
+
+/// Captures of `rule` matching `path`, `None` when it doesn't match.
+fn rule_captures<'p>(rule: &RedirectRule, path: &'p str) -> Option<regex::Captures<'p>> {
+    debug!(
+        "Testing path '{}' against rule pattern: '{}' (priority: {})",
+        path, rule.pattern, rule.priority
+    );
+
+    // Match against the path part only, after removing the rule's prefix
+    let subject = match &rule.strip_prefix {
+        Some(prefix) => strip_path_prefix(path, prefix)?,
+        None => path,
+    };
+    rule.pattern.captures(subject)
+}
+
+/// The rules routing evaluates for `path`, in evaluation order up to the `used` one.
+///
+/// Matches like routing does, for route traces. Matching rules before the used
+/// one were passed over by their routing scripts. Without a used rule every
+/// rule was evaluated.
+fn explain(rules: &[RedirectRule], path: &str, used: Option<&str>) -> Vec<RuleStep> {
+    let mut steps = Vec::new();
+    for rule in rules {
+        let verdict = if used == Some(rule.id.as_str()) {
+            Verdict::Used
+        } else if rule_captures(rule, path).is_some() {
+            Verdict::Passed
+        } else {
+            Verdict::NoMatch
+        };
+        steps.push(RuleStep {
+            id: rule.id.clone(),
+            verdict,
+        });
+        if verdict == Verdict::Used {
+            break;
+        }
+    }
+    steps
+}
+
+/// [`explain`] for a route cache hit.
+///
+/// Routes are cached without their rule, and only routes no script decided are
+/// cached, so the used rule is the first one matching.
+fn explain_cached(rules: &[RedirectRule], path: &str) -> Vec<RuleStep> {
+    let used = match_rule(rules, path).map(|(rule, _)| rule.id.as_str());
+    explain(rules, path, used)
+}
+
+/// The `request` map routing scripts see, `host` without its port.
+fn script_request(session: &Session, host: &str) -> rhai::Map {
+    let req = session.req_header();
+    route_script::request_map(
+        req.method.as_str(),
+        req.uri.path(),
+        req.uri.query(),
+        host,
+        &req.headers,
+        client_ip(session),
+    )
+}
+
+/// Removes `prefix` from the start of `path`, only at a segment boundary.
+///
+/// `/api` strips `/api` and `/api/users` (leaving `/` and `/users`) but not `/apiv2`.
+fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    if rest.is_empty() {
+        Some("/")
+    } else if rest.starts_with('/') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/// Replaces the path and query of the request URI.
+fn set_path_and_query(session: &mut Session, path_query: &str) -> std::result::Result<(), String> {
+    let uri = with_path_and_query(&session.req_header().uri, path_query)?;
+    session.req_header_mut().set_uri(uri);
+    Ok(())
+}
+
+/// `uri` with its path and query replaced, or why `path_query` can't be used.
+///
+/// Rewritten targets can contain characters a URI doesn't allow, e.g. a space
+/// in a rule's `path_target`.
+fn with_path_and_query(uri: &http::Uri, path_query: &str) -> std::result::Result<http::Uri, String> {
+    let pq = http::uri::PathAndQuery::from_maybe_shared(path_query.to_string())
+        .map_err(|e| e.to_string())?;
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(pq);
+    http::Uri::from_parts(parts).map_err(|e| e.to_string())
+}
+
+/// Resolves a configured `host:port` target into a socket address.
+///
+/// IP literals such as `127.0.0.1:8080` or `[::1]:8080` are parsed directly,
+/// anything else is treated as a hostname and resolved through DNS.
+fn resolve_target_addr(addr_target: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = addr_target.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+
+    let (host, port) = addr_target.rsplit_once(':')?;
+    let port = port.parse::<u16>().ok()?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let ip = lookup_host(host).ok()?.into_iter().next()?;
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Strips the port from an authority or `Host` header value.
+///
+/// Bracketed IPv6 literals are returned without the brackets, so `[::1]:8080`
+/// becomes `::1`. Values without a port are returned unchanged.
+fn host_without_port(host: &str) -> &str {
+    if let Some(rest) = host.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') && port.parse::<u16>().is_ok() => name,
+        _ => host,
+    }
+}
+
+/// Checks whether the requested host matches the SNI configured on a rule.
+///
+/// The configured value may list several names, including leftmost-label
+/// wildcards. The comparison ignores ASCII case and IPv6 brackets on the
+/// configured value.
+fn sni_matches(sni: &str, host: &str) -> bool {
+    sni::matches(sni, host)
+}
+
+/// Address of the downstream client, checked against gateway node access lists.
+///
+/// This is the socket peer; clients behind another load balancer appear as that balancer.
+fn client_ip(session: &Session) -> Option<IpAddr> {
+    session.client_addr().and_then(|addr| addr.as_inet()).map(|addr| addr.ip())
+}
+
+/// `, VARIANT:<name>` on the `[GWX]` lines of split routes, empty for others.
+fn variant_field(ctx: &ContextGw) -> String {
+    ctx.variant
+        .as_ref()
+        .map_or_else(String::new, |variant| format!(", VARIANT:{}", variant))
+}
+
+/// The trace of a traced request with its final target, `None` for other requests.
+///
+/// A request without a peer went to the default 404 page.
+fn finish_trace(ctx: &mut ContextGw) -> Option<&RouteTrace> {
+    let trace = ctx.trace.as_mut()?;
+    trace.target = Some(ctx.peer.clone().unwrap_or_else(|| DEFAULT_PORT.p404.to_string()));
+    Some(trace)
+}
+
+/// Fields of the request's `[GWX]` lines, repeated on the lines of its mirrored copy.
+fn mirror_log_fields(ctx: &ContextGw) -> mirror::LogFields {
+    mirror::LogFields {
+        conn_id: ctx.conn_id.clone().unwrap_or("-".into()),
+        conn_type: ctx.conn_type.clone().unwrap_or("UNKNOWN".into()),
+        src_addr: ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
+        request_id: ctx.request_id.clone().unwrap_or("-".into()),
     }
+}
 
-    // If the pattern has a wildcard in the middle (not at the end), treat as regex
-    if pattern.contains('*') && !pattern.ends_with("/*") {
-        return true;
+/// Access log request line with the path the client asked for, before any rewrite.
+///
+/// Rewrites keep the query, so it is taken from the forwarded URI.
+fn request_line(req: &RequestHeader, route_path: Option<&str>) -> String {
+    let path = route_path.unwrap_or_else(|| req.uri.path());
+    match req.uri.query() {
+        Some(query) => format!("{} {}?{} {:?}", req.method, path, query, req.version),
+        None => format!("{} {} {:?}", req.method, path, req.version),
     }
+}
 
-    // Otherwise it's a plain string or a simple wildcard pattern
-    false
+/// Whether a response may carry a compressed body at all.
+///
+/// Bodiless responses, partial content and answers to `HEAD` are left as they are.
+fn compressible_response(req: &RequestHeader, resp: &ResponseHeader) -> bool {
+    let status = resp.status.as_u16();
+    req.method != http::Method::HEAD && (200..300).contains(&status) && status != 204 && status != 206
+}
+
+/// Whether a response carries a body the gateway may rewrite.
+fn has_body(req: &RequestHeader, resp: &ResponseHeader) -> bool {
+    let status = resp.status.as_u16();
+    req.method != http::Method::HEAD && status >= 200 && status != 204 && status != 304
+}
+
+/// Turns a strong ETag into a weak one, the rewritten body no longer matches it.
+fn weaken_etag(resp: &mut ResponseHeader) -> Result<()> {
+    let strong_etag = resp
+        .headers
+        .get(http::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .map(|etag| format!("W/{}", etag));
+    if let Some(etag) = strong_etag {
+        resp.insert_header(http::header::ETAG, etag)?;
+    }
+    Ok(())
+}
+
+/// Whether an HTTP/1.1 request waits for `100 Continue` before sending its body
+fn expects_continue(req: &RequestHeader) -> bool {
+    req.version == http::Version::HTTP_11
+        && req
+            .headers
+            .get(http::header::EXPECT)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| value.trim().eq_ignore_ascii_case("100-continue"))
+}
+
+/// Answers `Expect: 100-continue` for a request about to be forwarded.
+///
+/// The interim response is written once per downstream request, even when a
+/// refused connect is retried, and the expectation is removed from the upstream
+/// request so the client never sees a second 100 from the upstream.
+async fn answer_expect_continue(
+    session: &mut Session,
+    upstream_request: &mut RequestHeader,
+    continue_sent: &mut bool,
+) -> Result<()> {
+    if !expects_continue(session.req_header()) {
+        return Ok(());
+    }
+    upstream_request.remove_header(&http::header::EXPECT);
+    if !*continue_sent {
+        session.write_continue_response().await?;
+        *continue_sent = true;
+    }
+    Ok(())
 }
 
 #[async_trait]
@@ -555,6 +1742,7 @@ impl ProxyHttp for GatewayApp {
         _session: &mut Session,
         _ctx: &mut Self::CTX,
     ) -> Result<Box<HttpPeer>> {
+        let _stage = trace::stage("upstream_peer", _ctx.conn_id.as_deref());
         let peer = match &_ctx.peer {
             Some(peer) => peer,
             None => {
@@ -563,10 +1751,116 @@ impl ProxyHttp for GatewayApp {
             }
         };
 
-        let http_peer = HttpPeer::new(peer, false, String::new());
+        let mut http_peer = HttpPeer::new(peer, false, String::new());
+        // Error pages are always served, even once the total timeout ran out
+        let remaining = if _ctx.websocket || [ERROR_PEER_ADDR, TIMEOUT_PEER_ADDR].contains(&peer.as_str()) {
+            None
+        } else {
+            _ctx.timeouts.remaining(_ctx.started, Instant::now())
+        };
+        _ctx.timeouts.apply(&mut http_peer, _ctx.websocket, remaining);
+        if trace::enabled() {
+            _ctx.connect_started = Some(Instant::now());
+        }
         return Ok(Box::new(http_peer));
     }
 
+    /// Retries a refused upstream connect on the next matching target.
+    ///
+    /// Up to `connect_retries` alternatives are tried, each logged as a `RETRY`
+    /// line with the attempt number in COMMENT. When none is left the request is
+    /// sent to the default 500 page, or to the 504 page when the last target
+    /// timed out instead of refusing.
+    fn fail_to_connect(
+        &self,
+        session: &mut Session,
+        peer: &HttpPeer,
+        ctx: &mut Self::CTX,
+        mut e: Box<Error>,
+    ) -> Box<Error> {
+        let failed = peer._address.to_string();
+        if failed == ERROR_PEER_ADDR || failed == TIMEOUT_PEER_ADDR {
+            // The error page itself is down, nothing left to try
+            return e;
+        }
+        let timeout = (*e.etype() == ConnectTimedout)
+            .then(|| ctx.timeouts.connect_timeout_kind(ctx.started, Instant::now()));
+        if let Some(kind) = timeout {
+            self.log_timeout(ctx, kind, &failed);
+        }
+        let timed_out = timeout.is_some();
+        ctx.connect_attempts += 1;
+        ctx.failed_peers.push(failed.clone());
+        PEER_HEALTH.mark_down(&failed, Instant::now());
+
+        // Past the total timeout there is no time left for another attempt
+        let next = if ctx.connect_attempts <= self.connect_retries && timeout != Some(TimeoutKind::Total) {
+            next_connect_candidate(&self.get_rules(), session, ctx).map(|(address, settings)| {
+                settings.apply(ctx, &self.default_timeouts);
+                address
+            })
+        } else {
+            None
+        };
+        let comment = match &next {
+            Some(_) => format!("retry {}/{} after {}", ctx.connect_attempts, self.connect_retries, failed),
+            None => format!("retries exhausted after {}", failed),
+        };
+        let next = next.unwrap_or_else(|| {
+            if timed_out { TIMEOUT_PEER_ADDR } else { ERROR_PEER_ADDR }.to_string()
+        });
+
+        warn!(
+            "[GWX] | ID:{}, TYPE:RETRY, CONN:{}, SIZE:0, STAT:N/A, SRC:{}, DST:{}, COMMENT:{} {} |",
+            ctx.conn_id.clone().unwrap_or("-".into()),
+            ctx.conn_type.clone().unwrap_or("UNKNOWN".into()),
+            ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
+            next,
+            ctx.request_id.clone().unwrap_or("-".into()),
+            comment
+        );
+
+        ctx.peer = Some(next);
+        e.set_retry(true);
+        e
+    }
+
+    /// Sends a request whose upstream didn't answer within the header timeout,
+    /// or the part of it the total timeout left, to the 504 page.
+    ///
+    /// Once the response has started, or when the request body can no longer
+    /// be replayed, the timeout is only logged and pingora closes the
+    /// connection or answers 502 itself.
+    fn error_while_proxy(
+        &self,
+        peer: &HttpPeer,
+        session: &mut Session,
+        e: Box<Error>,
+        ctx: &mut Self::CTX,
+        client_reused: bool,
+    ) -> Box<Error> {
+        let mut e = e.more_context(format!("Peer: {}", peer));
+        let replayable = !session.as_ref().retry_buffer_truncated();
+        e.retry.decide_reuse(client_reused && replayable);
+
+        let failed = peer._address.to_string();
+        if *e.etype() != ReadTimedout
+            || ctx.timed_out == Some(TimeoutKind::Total)
+            || failed == TIMEOUT_PEER_ADDR
+        {
+            return e;
+        }
+        let response_started = session.response_written().is_some();
+        let kind = ctx.timeouts.read_timeout_kind(ctx.started, Instant::now(), response_started);
+        self.log_timeout(ctx, kind, &failed);
+        if response_started || !replayable {
+            return e;
+        }
+        ctx.peer = Some(TIMEOUT_PEER_ADDR.to_string());
+        e.set_retry(true);
+        e
+    }
+
     async fn proxy_upstream_filter(
         &self,
         session: &mut Session,
@@ -576,6 +1870,13 @@ impl ProxyHttp for GatewayApp {
         Self::CTX: Send + Sync,
     {
         _ctx.conn_id = Some(atomic_id());
+        _ctx.request_id = SAVED_REQUEST_ID.read().ok().map(|id| id.clone());
+        _ctx.started = Some(Instant::now());
+        // Overload protection, the slot is released when the request's context is dropped
+        match self.limit.try_acquire() {
+            Some(permit) => _ctx.permit = Some(permit),
+            None => return self.reject_overload(session, _ctx).await,
+        }
         //
         //
         // --- validate domain if using TLS ---
@@ -588,7 +1889,7 @@ impl ProxyHttp for GatewayApp {
 
         // Extract authority (host:port) from URI
         let authority = match session.req_header().uri.authority() {
-            Some(a) => a.as_str().split(':').next().unwrap_or(a.as_str()),
+            Some(a) => host_without_port(a.as_str()),
             None => {
                 error!("No authority found in URI. fallback to header");
                 let host = session.req_header().headers.get(http::header::HOST);
@@ -602,7 +1903,7 @@ impl ProxyHttp for GatewayApp {
                     },
                     None => "",
                 };
-                host.split(':').next().unwrap_or(host)
+                host_without_port(host)
             }
         };
 
@@ -634,17 +1935,30 @@ impl ProxyHttp for GatewayApp {
             _ctx.conn_type  = Some("WS".into());
 
             info!(
-                "[GWX] | ID:{}, TYPE:INIT, CONN:{}, SIZE:{}, STAT:101, SRC:{}, DST:{} |",
+                "[GWX] | ID:{}, TYPE:INIT, CONN:{}, SIZE:{}, STAT:101, SRC:{}, DST:{}, COMMENT:{} |",
                 _ctx.conn_id.clone().unwrap_or("-".into()),
                 "WS",
                 0,
                 _ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
-                _ctx.peer.clone().unwrap_or("UNKNOWN".into())
+                _ctx.peer.clone().unwrap_or("UNKNOWN".into()),
+                _ctx.request_id.clone().unwrap_or("-".into())
             );
         } else {
             _ctx.conn_type = Some("HTTP".into());
         }
 
+        if route_trace::requested(session.req_header(), self.trace_token.as_deref()) {
+            _ctx.trace = Some(RouteTrace::default());
+        }
+
+        // ACME HTTP-01 challenges are answered for any host, ahead of the rules
+        if matches!(session.req_header().method, http::Method::GET | http::Method::HEAD) {
+            if let Some(key_authorization) = acme_challenge::answer(session.req_header().uri.path()) {
+                let page = StaticPage::acme_challenge(key_authorization);
+                return self.serve_static(session, _ctx, &page).await;
+            }
+        }
+
         //
         //
         // -- Main routing logic starts here ---
@@ -655,22 +1969,37 @@ impl ProxyHttp for GatewayApp {
         self.check_and_reload_config_if_needed();
 
         // 2. Prepare cache key (full path + query)
-        // Avoid allocation if query is None
-        let path = session.req_header().uri.path();
-        let query = session.req_header().uri.query();
+        // The URI is cloned so the session stays free for the access list and
+        // static page answers while the rule captures borrow the path
+        let uri = session.req_header().uri.clone();
+        let path = uri.path();
+        let query = uri.query();
         // Use Cow for potential zero-allocation case when no query exists
         let cache_key = match query {
             Some(q) => format!("{}?{}", path, q), // Changed to String directly
             None => path.to_string(),             // Convert to String directly
         };
+        // Remember the original request in case the chosen upstream refuses the connection
+        _ctx.route_path = Some(path.to_string());
+        _ctx.route_host = Some(authority.to_string());
 
         // 3. Check cache using the String key
-        if let Some((rewritten_path_query, sni, _tls, peer_arc)) = self.route_cache.get(&cache_key)
-        {
+        let cached = {
+            let _stage = trace::stage("cache_lookup", _ctx.conn_id.as_deref());
+            self.route_cache.get(&cache_key)
+        };
+        if let Some(trace) = _ctx.trace.as_mut() {
+            trace.cache_hit = Some(cached.is_some());
+            // Routes are cached without their rule, a cached route was the first match
+            if cached.is_some() {
+                trace.rules = explain_cached(&self.get_rules(), path);
+            }
+        }
+        if let Some(route) = cached {
             // Cache Hit!
             debug!("Cache hit for key: {}", cache_key);
-            if let Some(sni) = sni {
-                if authority != sni {
+            if let Some(sni) = route.sni {
+                if !sni_matches(&sni, authority) {
                     error!(
                         "SNI mismatch: expected '{}', got '{}'. Using default fallback.",
                         sni, authority
@@ -678,35 +2007,27 @@ impl ProxyHttp for GatewayApp {
                     return Ok(true);
                 }
             }
+            if let Some(refused) = self.enforce_acl(session, _ctx, route.acl.as_deref()).await {
+                return refused;
+            }
+            if let Some(answered) = self.answer_preflight(session, _ctx, route.settings.cors.as_deref()).await {
+                return answered;
+            }
+            if let Some(page) = route.static_page {
+                return self.serve_static(session, _ctx, &page).await;
+            }
             // Update request URI using the cached rewritten path and query.
-            match http::uri::PathAndQuery::from_maybe_shared(rewritten_path_query.clone()) {
-                Ok(pq) => {
-                    let mut parts = session.req_header_mut().uri.clone().into_parts();
-                    parts.path_and_query = Some(pq);
-                    match http::Uri::from_parts(parts) {
-                        Ok(new_uri) => session.req_header_mut().set_uri(new_uri),
-                        Err(e) => {
-                            error!("Error rebuilding URI from cached parts: {}", e);
-                            // Fallback on error
-                            // return Ok(DEFAULT_FALLBACK_PEER.clone()); // Clone the precomputed Box<HttpPeer>
-                            return Ok(true); // Return true to indicate a successful match
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!(
-                        "Invalid PathAndQuery in cache: '{}', error: {}",
-                        rewritten_path_query, e
-                    );
-                    // Fallback on error
-                    // return Ok(DEFAULT_FALLBACK_PEER.clone());
-                    return Ok(true); // Return true to indicate a successful match
-                }
+            if let Err(e) = set_path_and_query(session, &route.path_query) {
+                return self.reject_rewrite(session, _ctx, &route.path_query, &e).await;
+            }
+            if let Some(trace) = _ctx.trace.as_mut() {
+                trace.rewritten = Some(route.path_query.clone());
             }
 
             // Return the cached peer. Cloning Arc is cheap.
-            let peer_address = &peer_arc._address.to_string(); // Get address string directly
-            _ctx.peer = Some(peer_address.clone());
+            _ctx.peer = Some(route.peer._address.to_string());
+            route.settings.apply(_ctx, &self.default_timeouts);
+            self.failover_if_down(session, _ctx);
             return Ok(true); // Return true to indicate a successful match
         }
 
@@ -715,100 +2036,165 @@ impl ProxyHttp for GatewayApp {
 
         let rules = self.get_rules(); // Gets an Arc<Vec<RedirectRule>>
 
-        for rule in rules.iter() {
-            // ADD THIS LINE FOR DEBUGGING:
-            debug!(
-                "Testing path '{}' against rule pattern: '{}' (priority: {})",
-                path, rule.pattern, rule.priority
-            );
-
-            // Match against the path part only
-            if let Some(captures) = rule.pattern.captures(path) {
-                // Rule matches!
-                debug!(
-                    "Rule matched: pattern='{}', target='{}'",
-                    rule.pattern, rule.target_template
-                );
-                if let Some(sni) = rule.sni.clone() {
-                    if authority != sni {
-                        error!(
-                            "SNI mismatch: expected '{}', got '{}'. Using default fallback.",
-                            sni, authority
-                        );
-                        return Ok(true);
+        // The first rule matching the path that its script doesn't pass over, with
+        // the target the script chose, timed apart from the awaits that follow
+        let mut scripted = false;
+        let matched = {
+            let _stage = trace::stage("rule_match", _ctx.conn_id.as_deref());
+            let mut request = None;
+            match_rules(&rules, path).find_map(|(rule, captures)| {
+                let Some(script) = &rule.script else {
+                    return Some((rule, captures, None));
+                };
+                scripted = true;
+                let request = request.get_or_insert_with(|| script_request(session, authority));
+                match self.scripts.decide(script, request.clone()) {
+                    Ok(Decision::Use) => Some((rule, captures, None)),
+                    Ok(Decision::Skip) => None,
+                    Ok(Decision::Target(target)) => Some((rule, captures, Some(target.to_string()))),
+                    Err(e) => {
+                        warn!("Route script of rule '{}' failed, using the rule as configured: {}", rule.id, e);
+                        Some((rule, captures, None))
                     }
                 }
+            })
+        };
 
-                // FIX: Use Captures::expand with a String buffer.
-                let mut rewritten_path_buf = String::new(); // Use String buffer
-                captures.expand(&rule.target_template, &mut rewritten_path_buf); // Pass &mut String
+        if let Some(trace) = _ctx.trace.as_mut() {
+            let used = matched.as_ref().map(|(rule, ..)| rule.id.as_str());
+            trace.rules = explain(&rules, path, used);
+        }
 
-                // FIX: rewritten_path_buf is already a String, no need for from_utf8_lossy
-                let rewritten_path = rewritten_path_buf; // Already a String
+        if let Some((rule, captures, script_target)) = matched {
+            // Rule matches!
+            debug!(
+                "Rule matched: pattern='{}', target='{}'",
+                rule.pattern, rule.target_template
+            );
+            if let Some(sni) = rule.sni.clone() {
+                if !sni_matches(&sni, authority) {
+                    error!(
+                        "SNI mismatch: expected '{}', got '{}'. Using default fallback.",
+                        sni, authority
+                    );
+                    return Ok(true);
+                }
+            }
 
-                // Combine rewritten path with original query string.
-                let final_path_query = match query {
-                    Some(q) => format!("{}?{}", rewritten_path, q),
-                    None => rewritten_path, // Already a String
-                };
+            if let Some(refused) = self.enforce_acl(session, _ctx, rule.acl.as_deref()).await {
+                return refused;
+            }
+            if let Some(answered) = self.answer_preflight(session, _ctx, rule.cors.as_deref()).await {
+                return answered;
+            }
 
-                // Update request URI
-                match http::uri::PathAndQuery::from_maybe_shared(final_path_query.clone()) {
-                    Ok(pq) => {
-                        let mut parts = session.req_header_mut().uri.clone().into_parts();
-                        parts.path_and_query = Some(pq);
-                        match http::Uri::from_parts(parts) {
-                            Ok(new_uri) => session.req_header_mut().set_uri(new_uri),
-                            Err(e) => {
-                                error!("Error rebuilding URI after rewrite: {}", e);
-                                // Fallback on error
-                                // return Ok(DEFAULT_FALLBACK_PEER.clone());
-                                return Ok(true);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Invalid PathAndQuery after rewrite: '{}', error: {}",
-                            final_path_query, e
-                        );
-                        // Fallback on error
-                        // return Ok(DEFAULT_FALLBACK_PEER.clone());
-                        return Ok(true);
-                    }
+            if let Some(page) = &rule.static_page {
+                if scripted {
+                    return self.serve_static(session, _ctx, page).await;
                 }
+                self.route_cache.insert(cache_key, CachedRoute::of(rule, String::new()));
+                return self.serve_static(session, _ctx, page).await;
+            }
 
-                // Cache the result (cloning Arc is cheap)
-                // Use into_owned() on cache_key if it was borrowed
-
-                self.route_cache.insert(
-                    cache_key.to_owned(),
-                    (
-                        final_path_query,
-                        rule.sni.clone(),
-                        rule.tls,
-                        rule.alt_target.clone(),
-                    ),
-                );
-                debug!("Cached result for key used in insertion"); // Key might have been owned now
-                                                                   // Return the target peer for this rule.
-                                                                   // Use the address string from BasicPeer directly
-                let peer_address = &rule.alt_target._address.to_string(); // Get address string
-                _ctx.peer = Some(peer_address.clone());
-                return Ok(true); // Return true to indicate a successful match
+            // Sticky rules pick their backend per client, the captures of the
+            // shared pattern expand the chosen rule's template. A target chosen
+            // by the rule's script takes precedence, split rules pick a variant.
+            let sticky = rule.sticky.filter(|_| script_target.is_none());
+            let split = rule.split.clone().filter(|_| script_target.is_none());
+            let rule = match (sticky, &split) {
+                (Some(sticky), None) => self.sticky_backend(session, _ctx, &rules, rule, sticky),
+                _ => rule,
+            };
+            let split_target = split.and_then(|split| self.split_variant(session, _ctx, rule, &split, sticky));
+
+            // Expand numeric and named capture references from the precompiled template.
+            let rewritten_path = rule.target_plan.expand(&captures);
+
+            // Combine rewritten path with original query string.
+            let final_path_query = match query {
+                Some(q) => format!("{}?{}", rewritten_path, q),
+                None => rewritten_path, // Already a String
+            };
+
+            // Update request URI, an invalid rewrite is answered with 400 and not cached
+            if let Err(e) = set_path_and_query(session, &final_path_query) {
+                return self.reject_rewrite(session, _ctx, &final_path_query, &e).await;
             }
+            if let Some(trace) = _ctx.trace.as_mut() {
+                trace.rewritten = Some(final_path_query.clone());
+            }
+
+            // Cache the result (cloning Arc is cheap), unless it depends on the
+            // client, on a script or on the split draw
+            if sticky.is_none() && !scripted && rule.split.is_none() {
+                self.route_cache.insert(cache_key.to_owned(), CachedRoute::of(rule, final_path_query));
+                debug!("Cached result for key used in insertion");
+            } // Key might have been owned now
+                                                               // Return the target peer for this rule.
+                                                               // Use the address string from BasicPeer directly
+            let peer_address = script_target
+                .or(split_target)
+                .unwrap_or_else(|| rule.alt_target._address.to_string());
+            _ctx.peer = Some(peer_address);
+            RuleSettings::of(rule).apply(_ctx, &self.default_timeouts);
+            self.failover_if_down(session, _ctx);
+            return Ok(true); // Return true to indicate a successful match
         }
 
-        // 5. No rules matched - use the precomputed default fallback
+        // 5. No rules matched - use the listener's fallback
         debug!(
-            "No matching rules for path '{}', using default fallback.",
-            path
+            "No matching rules for path '{}', using fallback {:?}.",
+            path, self.fallback
         );
-        // Clone the precomputed Box<HttpPeer>
-        // Ok(DEFAULT_FALLBACK_PEER.clone())
+        match &self.fallback {
+            // Without a peer `upstream_peer` picks the precomputed 404 peer
+            Fallback::NotFound => {}
+            Fallback::Peer(address) => {
+                _ctx.peer = Some(address.clone());
+                _ctx.timeouts = self.default_timeouts;
+            }
+            Fallback::Page(page) => return self.serve_static(session, _ctx, page).await,
+        }
         Ok(true)
     }
 
+    async fn upstream_request_filter(
+        &self,
+        session: &mut Session,
+        upstream_request: &mut RequestHeader,
+        ctx: &mut Self::CTX,
+    ) -> Result<()>
+    where
+        Self::CTX: Send + Sync,
+    {
+        // Runs once the upstream connection is ready
+        if let Some(started) = ctx.connect_started.take() {
+            trace::record("upstream_connect", ctx.conn_id.as_deref(), started.elapsed());
+        }
+        for name in header_policy::hop_by_hop(&upstream_request.headers, ctx.websocket) {
+            upstream_request.remove_header(&name);
+        }
+        if let Some(via) = &self.via {
+            let entry = header_policy::via_entry(session.req_header().version, via);
+            upstream_request.append_header(http::header::VIA, entry)?;
+        }
+        // Redacted fields must be readable, so the upstream may not encode the body
+        if ctx.redact.is_some() && !ctx.websocket {
+            upstream_request.insert_header(http::header::ACCEPT_ENCODING, "identity")?;
+        }
+        // The trace token stays with the gateway
+        if ctx.trace.is_some() {
+            upstream_request.remove_header(route_trace::TRACE_HEADER);
+        }
+        // Copied as the upstream gets it, taken so a retried connect doesn't copy it twice
+        if !ctx.websocket {
+            if let Some(mirror) = ctx.mirror.take() {
+                ctx.mirror_copy = Some(MirrorCopy::new(mirror, upstream_request));
+            }
+        }
+        answer_expect_continue(session, upstream_request, &mut ctx.continue_sent).await
+    }
+
     async fn request_body_filter(
         &self,
         _session: &mut Session,
@@ -821,6 +2207,15 @@ impl ProxyHttp for GatewayApp {
     {
         let size_in = _body.as_ref().map_or(0, |b| b.len());
         _ctx.size_in = size_in;
+        let oversized = _ctx.mirror_copy.as_mut().is_some_and(|copy| {
+            !copy.append(_body.as_deref().unwrap_or_default(), _end_of_stream, self.mirror_max_body)
+        });
+        if oversized {
+            if let Some(copy) = _ctx.mirror_copy.take() {
+                let reason = format!("request body over {} bytes, not mirrored", self.mirror_max_body);
+                mirror::discard(copy, &mirror_log_fields(_ctx), &reason);
+            }
+        }
         // eprintln!(
         //     "[GWX] | ID:{}, TYPE:REQ, CONN:{}, SIZE:{}, STAT:N/A, SRC:{}, DST:{} | Request",
         //     _ctx.conn_id.clone().unwrap_or("-".into()),
@@ -840,16 +2235,81 @@ impl ProxyHttp for GatewayApp {
 
         // println!("Request Header: {}", header_str);
         info!(
-            "[GWX] | ID:{}, TYPE:REQ, CONN:{}, SIZE:{}, STAT:N/A, SRC:{}, DST:{} |",
+            "[GWX] | ID:{}, TYPE:REQ, CONN:{}, SIZE:{}, STAT:N/A, SRC:{}, DST:{}{}, COMMENT:{} |",
             _ctx.conn_id.clone().unwrap_or("-".into()),
             _ctx.conn_type.clone().unwrap_or("UNKNOWN".into()),
             size_in,
             _ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
-            _ctx.peer.clone().unwrap_or("UNKNOWN".into())
+            _ctx.peer.clone().unwrap_or("UNKNOWN".into()),
+            variant_field(_ctx),
+            _ctx.request_id.clone().unwrap_or("-".into())
         );
         Ok(())
     }
 
+    /// Pins the client of a `cookie` sticky rule, prepares the redaction of JSON
+    /// responses, and switches the response to a compressed encoding when its
+    /// rule, the client and the response headers all allow it. Traced requests
+    /// get their route trace in `X-GWRS-Trace`.
+    async fn response_filter(
+        &self,
+        session: &mut Session,
+        upstream_response: &mut ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) -> Result<()>
+    where
+        Self::CTX: Send + Sync,
+    {
+        let upgraded = ctx.websocket && upstream_response.status == http::StatusCode::SWITCHING_PROTOCOLS;
+        for name in header_policy::hop_by_hop(&upstream_response.headers, upgraded) {
+            upstream_response.remove_header(&name);
+        }
+        if let Some(via) = &self.via {
+            let entry = header_policy::via_entry(upstream_response.version, via);
+            upstream_response.append_header(http::header::VIA, entry)?;
+        }
+        if let Some(identity) = ctx.identity.as_deref() {
+            identity.apply(upstream_response)?;
+        }
+        if let Some(trace) = finish_trace(ctx).map(RouteTrace::header_value) {
+            upstream_response.insert_header(route_trace::TRACE_HEADER, trace)?;
+        }
+        if ctx.affinity_cookie {
+            self.pin_affinity(upstream_response, ctx)?;
+        }
+        if let Some(cors) = ctx.cors.as_deref() {
+            cors.decorate(session.req_header(), upstream_response)?;
+        }
+        if let Some(redaction) = ctx.redact.clone().filter(|_| !ctx.websocket) {
+            self.start_redaction(session.req_header(), upstream_response, ctx, redaction)?;
+        }
+        if !ctx.compress || ctx.websocket || !compressible_response(session.req_header(), upstream_response) {
+            return Ok(());
+        }
+        if !compress::should_compress(&upstream_response.headers, self.compress_min_size) {
+            return Ok(());
+        }
+        let encoding = match session
+            .req_header()
+            .headers
+            .get(http::header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .and_then(compress::negotiate)
+        {
+            Some(encoding) => encoding,
+            None => return Ok(()),
+        };
+
+        // The compressed length is unknown up front, so the body goes out chunked
+        upstream_response.remove_header(&http::header::CONTENT_LENGTH);
+        upstream_response.insert_header(http::header::TRANSFER_ENCODING, "chunked")?;
+        upstream_response.insert_header(http::header::CONTENT_ENCODING, encoding.header_value())?;
+        upstream_response.append_header(http::header::VARY, "Accept-Encoding")?;
+        weaken_etag(upstream_response)?;
+        ctx.compressor = Some(Compressor::new(encoding));
+        Ok(())
+    }
+
     fn response_body_filter(
         &self,
         _session: &mut Session,
@@ -860,6 +2320,38 @@ impl ProxyHttp for GatewayApp {
     where
         Self::CTX: Send + Sync,
     {
+        if let Some(started) = _ctx.started.filter(|_| !_ctx.websocket) {
+            if _ctx.timeouts.total_exceeded(started, Instant::now()) {
+                let peer = _ctx.peer.clone().unwrap_or("UNKNOWN".into());
+                self.log_timeout(_ctx, TimeoutKind::Total, &peer);
+                return Error::e_explain(ReadTimedout, "total response timeout exceeded");
+            }
+        }
+        // The redacted body is only known once the whole body is in
+        if let Some(redactor) = _ctx.redactor.as_mut() {
+            if let Some(chunk) = _body.take() {
+                if let Err(e) = redactor.push(&chunk) {
+                    warn!(
+                        "Cutting off the response of request {}: {}",
+                        _ctx.conn_id.as_deref().unwrap_or("-"),
+                        e
+                    );
+                    return Error::e_explain(InternalError, "response too large to redact");
+                }
+            }
+            if _end_of_stream {
+                *_body = Some(redactor.finish());
+            }
+        }
+        if let Some(compressor) = _ctx.compressor.as_mut() {
+            let chunk = _body.take().unwrap_or_default();
+            let compressed = compressor
+                .compress(&chunk, _end_of_stream)
+                .map_err(|e| Error::because(InternalError, "Failed to compress response body", e))?;
+            if !compressed.is_empty() || _end_of_stream {
+                *_body = Some(compressed);
+            }
+        }
         _ctx.size_out = _body.as_ref().map_or(0, |b| b.len());
         Ok(None)
     }
@@ -878,14 +2370,49 @@ impl ProxyHttp for GatewayApp {
         //     _ctx.peer.clone().unwrap_or("UNKNOWN".into())
         // );
         info!(
-            "[GWX] | ID:{}, TYPE:RES, CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{} |",
+            "[GWX] | ID:{}, TYPE:RES, CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{}{}, COMMENT:{} |",
             _ctx.conn_id.clone().unwrap_or("-".into()),
             _ctx.conn_type.clone().unwrap_or("UNKNOWN".into()),
             _ctx.size_out,
             response_code,
             _ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
-            _ctx.peer.clone().unwrap_or("UNKNOWN".into())
+            _ctx.peer.clone().unwrap_or("UNKNOWN".into()),
+            variant_field(_ctx),
+            _ctx.request_id.clone().unwrap_or("-".into())
         );
+
+        if access_log::enabled() {
+            let req = _session.req_header();
+            let header = |name: http::header::HeaderName| {
+                req.headers.get(name).and_then(|value| value.to_str().ok())
+            };
+            access_log::record(&AccessEntry {
+                client: client_ip(_session),
+                request: &request_line(req, _ctx.route_path.as_deref()),
+                status: response_code,
+                bytes: _ctx.size_out,
+                referer: header(http::header::REFERER),
+                user_agent: header(http::header::USER_AGENT),
+            });
+        }
+
+        if let Some(trace) = finish_trace(_ctx).map(|trace| trace.render()) {
+            info!(
+                "[GWX] | ID:{}, TYPE:TRACE, CONN:{}, SIZE:0, STAT:{}, SRC:{}, DST:{}, COMMENT:{} {} |",
+                _ctx.conn_id.clone().unwrap_or("-".into()),
+                _ctx.conn_type.clone().unwrap_or("UNKNOWN".into()),
+                response_code,
+                _ctx.src_addr.clone().unwrap_or("UNKNOWN".into()),
+                _ctx.peer.clone().unwrap_or("UNKNOWN".into()),
+                _ctx.request_id.clone().unwrap_or("-".into()),
+                trace
+            );
+        }
+
+        // Only now, so the mirror never holds up the client's response
+        if let Some(copy) = _ctx.mirror_copy.take() {
+            mirror::dispatch(copy, mirror_log_fields(_ctx));
+        }
     }
 
     // fn request_cache_filter(&self, _session: &mut Session, _ctx: &mut Self::CTX) -> Result<()> {
@@ -940,3 +2467,540 @@ impl ProxyHttp for GatewayApp {
     //     Ok(RespCacheable::Uncacheable(NoCacheReason::Custom("default")))
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_without_port() {
+        assert_eq!(host_without_port("example.com:8080"), "example.com");
+        assert_eq!(host_without_port("example.com"), "example.com");
+        assert_eq!(host_without_port("[::1]:0"), "::1");
+        assert_eq!(host_without_port("[2001:db8::1]"), "2001:db8::1");
+        assert_eq!(host_without_port("::1"), "::1");
+    }
+
+    #[test]
+    fn test_sni_matches_ipv6_literal() {
+        assert!(sni_matches("[::1]", host_without_port("[::1]:8443")));
+        assert!(sni_matches("::1", host_without_port("[::1]:8443")));
+        assert!(sni_matches("Example.COM", host_without_port("example.com:443")));
+        assert!(!sni_matches("example.com", host_without_port("[::1]:443")));
+    }
+
+    #[test]
+    fn test_sni_matches_wildcards_and_lists() {
+        assert!(sni_matches("*.example.com", host_without_port("a.example.com:443")));
+        assert!(!sni_matches("*.example.com", "a.b.example.com"));
+        assert!(sni_matches("example.com, *.example.com", "example.com"));
+    }
+
+    #[test]
+    fn test_resolve_ipv6_target() {
+        let addr = resolve_target_addr("[::1]:0").expect("IPv6 literal should parse");
+        assert!(addr.is_ipv6());
+        assert_eq!(addr.port(), 0);
+        assert_eq!(addr.to_string(), "[::1]:0");
+
+        let addr = resolve_target_addr("127.0.0.1:8080").expect("IPv4 literal should parse");
+        assert_eq!(addr.to_string(), "127.0.0.1:8080");
+
+        assert!(resolve_target_addr("[::1]").is_none());
+    }
+
+    #[test]
+    fn test_strip_path_prefix() {
+        assert_eq!(strip_path_prefix("/api/users", "/api"), Some("/users"));
+        assert_eq!(strip_path_prefix("/api", "/api"), Some("/"));
+        assert_eq!(strip_path_prefix("/api/", "/api"), Some("/"));
+        assert_eq!(strip_path_prefix("/apiv2/users", "/api"), None);
+        assert_eq!(strip_path_prefix("/v1/api/users", "/api"), None);
+    }
+
+    #[test]
+    fn test_cache_entries_expire_after_ttl() {
+        let cache: ShardedLruCache<String, u32> = ShardedLruCache::new(4, Duration::from_millis(20));
+        cache.insert("/a".to_string(), 1);
+        assert_eq!(cache.get(&"/a".to_string()), Some(1));
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(cache.get(&"/a".to_string()), None);
+
+        // Re-inserting refreshes the entry
+        cache.insert("/a".to_string(), 2);
+        assert_eq!(cache.get(&"/a".to_string()), Some(2));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+    }
+
+    #[test]
+    fn test_cache_ttl_changes_in_place() {
+        let cache: ShardedLruCache<String, u32> = ShardedLruCache::new(4, Duration::from_millis(20));
+        cache.insert("/a".to_string(), 1);
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(cache.get(&"/a".to_string()), None);
+
+        // A longer TTL revives entries that are still cached
+        cache.set_ttl(Duration::from_secs(60));
+        assert_eq!(cache.get(&"/a".to_string()), Some(1));
+    }
+
+    fn path(id: &str, addr_bind: &str) -> GatewayPath {
+        GatewayPath {
+            id: id.to_string(),
+            priority: 1,
+            node_priority: 100,
+            sni: None,
+            tls: false,
+            addr_bind: addr_bind.to_string(),
+            addr_target: "127.0.0.1:3004".to_string(),
+            path_listen: "/api/*".to_string(),
+            path_target: "/$1".to_string(),
+            strip_prefix: None,
+            compress: false,
+            allow_cidrs: Vec::new(),
+            deny_cidrs: Vec::new(),
+            connect_timeout_secs: None,
+            header_timeout_secs: None,
+            total_timeout_secs: None,
+            maintenance: false,
+            sticky: None,
+            redact_fields: Vec::new(),
+            redact_mode: None,
+            route_script: None,
+            split: Vec::new(),
+            mirror_target: None,
+            cors_origins: Vec::new(),
+            cors_methods: Vec::new(),
+            cors_headers: Vec::new(),
+            server_header: None,
+            strip_server_headers: false,
+        }
+    }
+
+    #[test]
+    fn test_rules_fail_over_by_node_priority() {
+        let rule = |id: &str, priority: u8, node_priority: i32| {
+            let mut node = path(id, "127.0.0.1:61040");
+            node.priority = priority;
+            node.node_priority = node_priority;
+            compile_rule(node).unwrap()
+        };
+        let mut rules = vec![
+            rule("d", 1, 100),
+            rule("c", 1, 50),
+            rule("b", 1, 200),
+            rule("a", 1, 100),
+            rule("e", 0, 10),
+        ];
+        sort_rules(&mut rules);
+        let order: Vec<&str> = rules.iter().map(|r| r.id.as_str()).collect();
+        // Rule priority first, then the node priority, then the id
+        assert_eq!(order, vec!["e", "b", "a", "d", "c"]);
+    }
+
+    #[test]
+    fn test_route_resolver_matches_and_caches() {
+        let mut node = path("1", "127.0.0.1:61050");
+        node.path_listen = "^/users/(?<id>[0-9]+)/(.*)$".to_string();
+        node.path_target = "/v2/${id}/$2".to_string();
+        let resolver = RouteResolver::new(vec![path("0", "127.0.0.1:61050"), node]).unwrap();
+
+        assert_eq!(resolver.resolve_uncached("/users/7/posts?page=2").as_deref(), Some("/v2/7/posts?page=2"));
+        // The second lookup is answered by the route cache
+        assert_eq!(resolver.resolve("/users/7/posts").as_deref(), Some("/v2/7/posts"));
+        assert_eq!(resolver.resolve("/users/7/posts").as_deref(), Some("/v2/7/posts"));
+        assert_eq!(resolver.route_cache.stats().hits, 1);
+        assert_eq!(resolver.resolve("/users/x/posts"), None);
+    }
+
+    #[test]
+    fn test_invalid_rewritten_target_is_rejected() {
+        let mut node = path("1", "127.0.0.1:61051");
+        node.path_listen = "^/files/(.*)$".to_string();
+        node.path_target = "/store/$1 copy".to_string();
+        let rules = vec![compile_rule(node).unwrap()];
+        let (rule, captures) = match_rule(&rules, "/files/report").unwrap();
+        let target = rule.target_plan.expand(&captures);
+        assert_eq!(target, "/store/report copy");
+
+        let uri: http::Uri = "http://example.com/files/report".parse().unwrap();
+        assert!(with_path_and_query(&uri, &target).is_err());
+        assert!(with_path_and_query(&uri, "/store/\x07").is_err());
+        let rewritten = with_path_and_query(&uri, "/store/report?x=1").unwrap();
+        assert_eq!(rewritten.to_string(), "http://example.com/store/report?x=1");
+    }
+
+    #[test]
+    fn test_maintenance_rules_answer_503() {
+        let mut node = path("1", "127.0.0.1:61052");
+        node.maintenance = true;
+        // Unusable targets are kept, the rule never connects while in maintenance
+        node.addr_target = "backend-without-port".to_string();
+        let rule = compile_rule(node.clone()).unwrap();
+        let page = rule.static_page.as_deref().expect("maintenance page");
+        assert_eq!(page.status, 503);
+        assert_eq!(page.retry_after, Some(120));
+
+        node.maintenance = false;
+        assert!(compile_rule(node).is_err());
+        assert!(compile_rule(path("2", "127.0.0.1:61052")).unwrap().static_page.is_none());
+    }
+
+    #[test]
+    fn test_sticky_rules_group_by_route() {
+        let rule = |id: &str, path_listen: &str, sticky: Option<&str>| {
+            let mut node = path(id, "127.0.0.1:61053");
+            node.path_listen = path_listen.to_string();
+            node.sticky = sticky.map(str::to_string);
+            compile_rule(node)
+        };
+        let first = rule("1", "/api/*", Some("cookie")).unwrap();
+        assert_eq!(first.sticky, Some(Sticky::Cookie));
+        assert_eq!(rule("2", "/api/*", Some("")).unwrap().sticky, None);
+        assert!(rule("3", "/api/*", Some("weighted")).is_err());
+
+        // Backends of a sticky rule share its pattern, prefix and SNI
+        let mut other_node = rule("4", "/api/*", None).unwrap();
+        assert!(same_route(&first, &other_node));
+        other_node.sni = Some("example.com".to_string());
+        assert!(!same_route(&first, &other_node));
+        assert!(!same_route(&first, &rule("5", "/web/*", None).unwrap()));
+
+        // Sticky routes are resolved on every lookup
+        let mut node = path("6", "127.0.0.1:61053");
+        node.path_listen = "^/api/(.*)$".to_string();
+        node.sticky = Some("ip".to_string());
+        let resolver = RouteResolver::new(vec![node]).unwrap();
+        assert_eq!(resolver.resolve("/api/users").as_deref(), Some("/users"));
+        assert_eq!(resolver.resolve("/api/users").as_deref(), Some("/users"));
+        assert_eq!(resolver.route_cache.stats().hits, 0);
+    }
+
+    #[test]
+    fn test_fallback_targets() {
+        assert_eq!(Fallback::parse("404"), Ok(Fallback::NotFound));
+        assert_eq!(Fallback::parse("500"), Ok(Fallback::Peer(DEFAULT_PORT.p500.to_string())));
+        assert_eq!(
+            Fallback::parse("[::1]:9000"),
+            Ok(Fallback::Peer("[::1]:9000".to_string()))
+        );
+        match Fallback::parse(r#"static:{"status":503,"body":"maintenance"}"#) {
+            Ok(Fallback::Page(page)) => assert_eq!(page.status, 503),
+            other => panic!("expected a static page, got {:?}", other),
+        }
+        assert!(Fallback::parse(r#"static:{"status":42}"#).is_err());
+        assert!(Fallback::parse("not a target").is_err());
+
+        // The listener's own target wins, an invalid one keeps the 404 page
+        assert_eq!(
+            Fallback::from_config("127.0.0.1:61090", Some(" 500 ")),
+            Fallback::Peer(DEFAULT_PORT.p500.to_string())
+        );
+        assert_eq!(Fallback::from_config("127.0.0.1:61090", Some("not a target")), Fallback::NotFound);
+    }
+
+    #[test]
+    fn test_one_rebuild_serves_every_listener() {
+        let (a, b) = ("127.0.0.1:61031", "127.0.0.1:61032");
+        config::RoutingData::GatewayRouting.xset(&vec![path("1", a)]);
+        config::RoutingData::GatewayID.set("rebuild-test-1");
+        let app_a = GatewayApp::new(a, None);
+        let app_b = GatewayApp::new(b, None);
+        assert_eq!(app_a.get_rules().len(), 1);
+        assert!(app_b.get_rules().is_empty());
+
+        // A change noticed by one listener reloads the others as well
+        config::RoutingData::GatewayRouting.xset(&vec![path("1", a), path("2", b), path("3", b)]);
+        config::RoutingData::GatewayID.set("rebuild-test-2");
+        app_a.populate_rules(false);
+        assert_eq!(app_b.get_rules().len(), 2);
+        assert!(!config_changed("rebuild-test-2"));
+
+        // Concurrent checks of the same version rebuild at most once and all see it
+        config::RoutingData::GatewayRouting.xset(&vec![path("4", b)]);
+        config::RoutingData::GatewayID.set("rebuild-test-3");
+        let apps = Arc::new((app_a, app_b));
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let apps = apps.clone();
+                std::thread::spawn(move || {
+                    if i % 2 == 0 { apps.0.populate_rules(false) } else { apps.1.populate_rules(false) }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(apps.1.get_rules().len(), 1);
+        assert!(apps.0.get_rules().is_empty());
+
+        // Applied paths are picked up on the next request, without waiting for a poll
+        config::RoutingData::GatewayRouting.xset(&vec![path("5", a)]);
+        config::RoutingData::GatewayID.set("rebuild-test-4");
+        config::notify_gateway_config();
+        apps.1.check_and_reload_config_if_needed();
+        assert_eq!(apps.0.get_rules().len(), 1);
+        assert_eq!(
+            apps.1.seen_config_version.load(Ordering::Acquire),
+            config::gateway_config_version()
+        );
+    }
+
+    #[test]
+    fn test_expects_continue() {
+        let mut req = RequestHeader::build("PUT", b"/upload", None).unwrap();
+        assert!(!expects_continue(&req));
+        req.insert_header("Expect", "100-Continue").unwrap();
+        assert!(expects_continue(&req));
+        req.set_version(http::Version::HTTP_10);
+        assert!(!expects_continue(&req));
+    }
+
+    #[test]
+    fn test_compressible_response() {
+        let get = RequestHeader::build("GET", b"/", None).unwrap();
+        let head = RequestHeader::build("HEAD", b"/", None).unwrap();
+        let ok = ResponseHeader::build(200, None).unwrap();
+        assert!(compressible_response(&get, &ok));
+        assert!(!compressible_response(&head, &ok));
+        for status in [204, 206, 304, 404] {
+            let resp = ResponseHeader::build(status, None).unwrap();
+            assert!(!compressible_response(&get, &resp), "status {}", status);
+        }
+    }
+
+    #[test]
+    fn test_redacted_bodies() {
+        let get = RequestHeader::build("GET", b"/", None).unwrap();
+        let head = RequestHeader::build("HEAD", b"/", None).unwrap();
+        for status in [200, 206, 404, 500] {
+            let resp = ResponseHeader::build(status, None).unwrap();
+            assert!(has_body(&get, &resp), "status {}", status);
+            assert!(!has_body(&head, &resp), "status {}", status);
+        }
+        for status in [101, 204, 304] {
+            let resp = ResponseHeader::build(status, None).unwrap();
+            assert!(!has_body(&get, &resp), "status {}", status);
+        }
+
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header(http::header::ETAG, "\"v1\"").unwrap();
+        weaken_etag(&mut resp).unwrap();
+        weaken_etag(&mut resp).unwrap();
+        assert_eq!(resp.headers.get(http::header::ETAG).unwrap(), "W/\"v1\"");
+
+        let mut node = path("1", "127.0.0.1:61054");
+        assert!(compile_rule(node.clone()).unwrap().redact.is_none());
+        node.redact_fields = vec!["user.ssn".to_string()];
+        assert!(compile_rule(node.clone()).unwrap().redact.is_some());
+        node.redact_mode = Some("shuffle".to_string());
+        assert!(compile_rule(node).is_err());
+    }
+
+    #[test]
+    fn test_scripted_rules_match_in_order() {
+        let mut scripted = path("1", "127.0.0.1:61055");
+        scripted.route_script = Some("request.method == \"GET\"".to_string());
+        let rules = vec![compile_rule(scripted.clone()).unwrap(), compile_rule(path("2", "127.0.0.1:61055")).unwrap()];
+        assert!(rules[0].script.is_some() && rules[1].script.is_none());
+        // A script passing its rule over leaves the next matching one
+        let ids: Vec<&str> = match_rules(&rules, "/api/users").map(|(rule, _)| rule.id.as_str()).collect();
+        assert_eq!(ids, ["1", "2"]);
+
+        scripted.route_script = Some(" ".to_string());
+        assert!(compile_rule(scripted.clone()).unwrap().script.is_none());
+        scripted.route_script = Some("if (".to_string());
+        assert!(compile_rule(scripted).is_err());
+    }
+
+    #[test]
+    fn test_split_rules() {
+        let mut node = path("1", "127.0.0.1:61056");
+        assert!(compile_rule(node.clone()).unwrap().split.is_none());
+        node.split = vec![config::SplitTarget {
+            target: "127.0.0.1:3005".to_string(),
+            weight: 20,
+        }];
+        let split = compile_rule(node.clone()).unwrap().split.expect("split configured");
+        assert_eq!(split.pick(19).map(|variant| variant.address.as_str()), Some("127.0.0.1:3005"));
+        assert_eq!(split.pick(20), None);
+
+        node.split[0].weight = 101;
+        assert!(compile_rule(node.clone()).is_err());
+        node.split[0] = config::SplitTarget {
+            target: "backend-without-port".to_string(),
+            weight: 5,
+        };
+        assert!(compile_rule(node).is_err());
+    }
+
+    #[test]
+    fn test_cors_rules() {
+        let mut node = path("1", "127.0.0.1:61057");
+        assert!(compile_rule(node.clone()).unwrap().cors.is_none());
+        node.cors_origins = vec!["https://app.example.com".to_string()];
+        node.cors_methods = vec!["GET".to_string(), "PUT".to_string()];
+        let cors = compile_rule(node.clone()).unwrap().cors.expect("cors configured");
+
+        let mut preflight = RequestHeader::build("OPTIONS", b"/api/users", None).unwrap();
+        preflight.insert_header(http::header::ORIGIN, "https://app.example.com").unwrap();
+        preflight.insert_header(http::header::ACCESS_CONTROL_REQUEST_METHOD, "PUT").unwrap();
+        assert!(Cors::is_preflight(&preflight));
+        let response = cors.preflight(&preflight).unwrap();
+        assert_eq!(response.headers.get(http::header::ALLOW).unwrap(), "GET, PUT");
+
+        node.cors_origins = vec!["app.example.com".to_string()];
+        assert!(compile_rule(node).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failover_takes_the_next_rules_settings() {
+        use pingora::protocols::l4::stream::Stream as L4Stream;
+        use tokio::io::AsyncWriteExt;
+
+        let rule = |id: &str, target: &str, origin: &str| {
+            let mut node = path(id, "127.0.0.1:61060");
+            node.path_listen = "^/api/(.*)$".to_string();
+            node.addr_target = target.to_string();
+            node.cors_origins = vec![origin.to_string()];
+            compile_rule(node).unwrap()
+        };
+        let rules = vec![
+            rule("1", "127.0.0.1:61061", "https://first.example.com"),
+            rule("2", "127.0.0.1:61062", "https://second.example.com"),
+        ];
+
+        let (mut client, server) = tokio::net::UnixStream::pair().unwrap();
+        client
+            .write_all(b"GET /api/users?page=2 HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .await
+            .unwrap();
+        let mut session = Session::new_h1(Box::new(L4Stream::from(server)));
+        assert!(session.read_request().await.unwrap());
+
+        let mut ctx = ContextGw {
+            route_path: Some("/api/users".to_string()),
+            failed_peers: vec!["127.0.0.1:61061".to_string()],
+            ..Default::default()
+        };
+        RuleSettings::of(&rules[0]).apply(&mut ctx, &UpstreamTimeouts::default());
+
+        let (address, settings) =
+            next_connect_candidate(&rules, &mut session, &ctx).expect("second rule is up");
+        assert_eq!(address, "127.0.0.1:61062");
+        assert_eq!(session.req_header().uri.to_string(), "/users?page=2");
+        settings.apply(&mut ctx, &UpstreamTimeouts::default());
+        // The CORS settings of the rule failed over to answer the request
+        assert!(Arc::ptr_eq(ctx.cors.as_ref().unwrap(), rules[1].cors.as_ref().unwrap()));
+
+        ctx.failed_peers.push(address);
+        assert!(next_connect_candidate(&rules, &mut session, &ctx).is_none());
+    }
+
+    #[test]
+    fn test_server_identity_rules() {
+        let mut node = path("1", "127.0.0.1:61058");
+        assert!(compile_rule(node.clone()).unwrap().identity.is_none());
+        node.strip_server_headers = true;
+        assert!(compile_rule(node.clone()).unwrap().identity.is_some());
+        node.server_header = Some("bad\r\nvalue".to_string());
+        assert!(compile_rule(node).is_err());
+    }
+
+    #[test]
+    fn test_mirror_rules() {
+        let mut node = path("1", "127.0.0.1:61059");
+        assert!(compile_rule(node.clone()).unwrap().mirror.is_none());
+        node.mirror_target = Some(" 127.0.0.1:3006 ".to_string());
+        let mirror = compile_rule(node.clone()).unwrap().mirror.expect("mirror configured");
+        assert_eq!(mirror.name, "127.0.0.1:3006");
+        assert_eq!(mirror.address, "127.0.0.1:3006".parse::<SocketAddr>().unwrap());
+
+        node.mirror_target = Some("shadow-without-port".to_string());
+        assert!(compile_rule(node).is_err());
+    }
+
+    #[test]
+    fn test_route_trace_explains_rules() {
+        let mut users = path("1", "127.0.0.1:61060");
+        users.path_listen = "^/users/(.*)$".to_string();
+        let mut scripted = path("2", "127.0.0.1:61060");
+        scripted.route_script = Some("request.method == \"POST\"".to_string());
+        let rules: Vec<RedirectRule> = [users, scripted, path("3", "127.0.0.1:61060")]
+            .into_iter()
+            .map(|node| compile_rule(node).unwrap())
+            .collect();
+        let verdicts = |steps: Vec<RuleStep>| -> Vec<(String, Verdict)> {
+            steps.into_iter().map(|step| (step.id, step.verdict)).collect()
+        };
+
+        // A cache miss whose matching scripted rule was passed over for the next one
+        assert_eq!(
+            verdicts(explain(&rules, "/api/orders", Some("3"))),
+            [("1".to_string(), Verdict::NoMatch), ("2".to_string(), Verdict::Passed), ("3".to_string(), Verdict::Used)]
+        );
+        // Rules after the used one aren't evaluated, a path no rule matches lists every rule
+        assert_eq!(verdicts(explain(&rules, "/users/7", Some("1"))), [("1".to_string(), Verdict::Used)]);
+        assert!(explain(&rules, "/health", None).iter().all(|step| step.verdict == Verdict::NoMatch));
+
+        // A cache hit was the first matching rule
+        let mut cacheable = rules;
+        cacheable.remove(1);
+        assert_eq!(
+            verdicts(explain_cached(&cacheable, "/api/orders")),
+            [("1".to_string(), Verdict::NoMatch), ("3".to_string(), Verdict::Used)]
+        );
+        assert_eq!(explain_cached(&cacheable, "/health").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expect_continue_upload_flows() {
+        use pingora::protocols::l4::stream::Stream as L4Stream;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut client, server) = tokio::net::UnixStream::pair().unwrap();
+        let body = b"hello upstream";
+
+        // Like curl, send the headers and hold the body until the interim response
+        let uploader = tokio::spawn(async move {
+            let head = format!(
+                "PUT /upload HTTP/1.1\r\nHost: example.com\r\nContent-Length: {}\r\nExpect: 100-continue\r\n\r\n",
+                body.len()
+            );
+            client.write_all(head.as_bytes()).await.unwrap();
+            let mut interim = [0u8; 25];
+            client.read_exact(&mut interim).await.unwrap();
+            assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+            client.write_all(body).await.unwrap();
+            client
+        });
+
+        let mut session = Session::new_h1(Box::new(L4Stream::from(server)));
+        assert!(session.read_request().await.unwrap());
+        let mut upstream_request = session.req_header().clone();
+        let mut continue_sent = false;
+        answer_expect_continue(&mut session, &mut upstream_request, &mut continue_sent)
+            .await
+            .unwrap();
+        assert!(continue_sent);
+        assert!(upstream_request.headers.get(http::header::EXPECT).is_none());
+
+        // A retried connect must not write a second interim response
+        answer_expect_continue(&mut session, &mut upstream_request, &mut continue_sent)
+            .await
+            .unwrap();
+
+        let _client = tokio::time::timeout(Duration::from_secs(5), uploader)
+            .await
+            .expect("client never got 100 Continue")
+            .unwrap();
+        let mut received = Vec::new();
+        while let Some(chunk) = session.read_request_body().await.unwrap() {
+            received.extend_from_slice(&chunk);
+        }
+        assert_eq!(received, body);
+    }
+}
@@ -0,0 +1,204 @@
+//! # Header Policy
+//!
+//! Headers the gateway rewrites on proxied requests and responses:
+//!
+//! * Hop-by-hop headers are removed in both directions: `Connection` and the
+//!   headers it names, `Keep-Alive`, `Proxy-Connection`, `TE` (except
+//!   `TE: trailers`), `Trailer`, `Upgrade`, `Proxy-Authenticate` and
+//!   `Proxy-Authorization`. WebSocket upgrades keep `Connection` and `Upgrade`.
+//!   `Transfer-Encoding` is left to pingora, which frames each side from it, and
+//!   `Connection` can't name `Content-Length`, `Transfer-Encoding` or `Host` away.
+//! * `Via` gets `<version> <pseudonym>` appended in both directions, the
+//!   pseudonym is `GWRS_GATEWAY_VIA` (default `mini-gateway`, `off` adds none).
+//! * Per gateway node, `server_header` replaces the `Server` header of upstream
+//!   responses and `strip_server_headers` removes `Server` and `X-Powered-By`,
+//!   so backends can't be fingerprinted through the gateway.
+
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::Version;
+use pingora::http::ResponseHeader;
+use pingora::prelude::*;
+
+/// Longest `server_header` accepted
+pub const MAX_SERVER_HEADER_LEN: usize = 256;
+
+/// Hop-by-hop headers besides the ones `Connection` names
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "upgrade",
+    "proxy-authenticate",
+    "proxy-authorization",
+];
+
+/// Upstream headers identifying the backend software
+const IDENTITY_HEADERS: [&str; 2] = ["server", "x-powered-by"];
+
+/// Names of the hop-by-hop headers present in `headers`, to be removed before forwarding.
+///
+/// `upgrade` keeps `Connection` and `Upgrade` for a WebSocket handshake.
+pub fn hop_by_hop(headers: &HeaderMap, upgrade: bool) -> Vec<HeaderName> {
+    let framing = [header::CONTENT_LENGTH, header::TRANSFER_ENCODING, header::HOST];
+    let named = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|token| HeaderName::from_bytes(token.trim().as_bytes()).ok())
+        .filter(|name| !framing.contains(name));
+
+    let mut names: Vec<HeaderName> = Vec::new();
+    for name in HOP_BY_HOP.into_iter().map(HeaderName::from_static).chain(named) {
+        if upgrade && (name == header::CONNECTION || name == header::UPGRADE) {
+            continue;
+        }
+        // `TE: trailers` is the one value HTTP/2 and gRPC backends rely on
+        if name == header::TE
+            && headers
+                .get_all(header::TE)
+                .iter()
+                .all(|value| value.as_bytes().eq_ignore_ascii_case(b"trailers"))
+        {
+            continue;
+        }
+        if headers.contains_key(&name) && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// The `Via` entry of a hop received with `version`.
+pub fn via_entry(version: Version, pseudonym: &str) -> String {
+    let protocol = match version {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_2 => "2",
+        Version::HTTP_3 => "3",
+        _ => "1.1",
+    };
+    format!("{} {}", protocol, pseudonym)
+}
+
+/// The `Server` handling of one gateway node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerIdentity {
+    /// `Server` value sent instead of the upstream's
+    server: Option<String>,
+    /// Remove the upstream's `Server` and `X-Powered-By`
+    strip: bool,
+}
+
+impl ServerIdentity {
+    /// Compiles a node's `server_header` and `strip_server_headers`.
+    ///
+    /// Returns `Ok(None)` when the node sets neither, upstream headers pass unchanged.
+    pub fn parse(server: Option<&str>, strip: bool) -> std::result::Result<Option<Self>, String> {
+        let server = server.map(str::trim).filter(|server| !server.is_empty());
+        if let Some(server) = server {
+            if server.len() > MAX_SERVER_HEADER_LEN || HeaderValue::from_str(server).is_err() {
+                return Err(format!("Invalid server_header '{}'", server));
+            }
+        }
+        if server.is_none() && !strip {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            server: server.map(str::to_string),
+            strip,
+        }))
+    }
+
+    /// Rewrites the identity headers of an upstream response.
+    pub fn apply(&self, response: &mut ResponseHeader) -> Result<()> {
+        if self.strip {
+            for name in IDENTITY_HEADERS {
+                response.remove_header(name);
+            }
+        }
+        if let Some(server) = &self.server {
+            response.insert_header(header::SERVER, server.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(HeaderName::from_bytes(name.as_bytes()).unwrap(), value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn names(names: Vec<HeaderName>) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_hop_by_hop_headers() {
+        let request = headers(&[
+            ("connection", "keep-alive, X-Trace, Content-Length"),
+            ("keep-alive", "timeout=5"),
+            ("x-trace", "1"),
+            ("te", "gzip"),
+            ("transfer-encoding", "chunked"),
+            ("content-length", "10"),
+            ("proxy-authorization", "Basic Zm9vOmJhcg=="),
+            ("accept", "*/*"),
+        ]);
+        assert_eq!(
+            names(hop_by_hop(&request, false)),
+            vec!["connection", "keep-alive", "te", "proxy-authorization", "x-trace"]
+        );
+
+        // gRPC style `TE: trailers` is kept
+        assert!(hop_by_hop(&headers(&[("te", "trailers")]), false).is_empty());
+        assert!(hop_by_hop(&headers(&[("accept", "*/*")]), false).is_empty());
+    }
+
+    #[test]
+    fn test_websocket_upgrade_keeps_its_headers() {
+        let upgrade = headers(&[("connection", "Upgrade"), ("upgrade", "websocket"), ("keep-alive", "300")]);
+        assert_eq!(names(hop_by_hop(&upgrade, true)), vec!["keep-alive"]);
+        assert_eq!(names(hop_by_hop(&upgrade, false)), vec!["connection", "keep-alive", "upgrade"]);
+    }
+
+    #[test]
+    fn test_via_entry() {
+        assert_eq!(via_entry(Version::HTTP_11, "mini-gateway"), "1.1 mini-gateway");
+        assert_eq!(via_entry(Version::HTTP_10, "edge"), "1.0 edge");
+        assert_eq!(via_entry(Version::HTTP_2, "edge"), "2 edge");
+    }
+
+    #[test]
+    fn test_server_identity() {
+        assert_eq!(ServerIdentity::parse(None, false), Ok(None));
+        assert_eq!(ServerIdentity::parse(Some("  "), false), Ok(None));
+        assert!(ServerIdentity::parse(Some("bad\nvalue"), false).is_err());
+        assert!(ServerIdentity::parse(Some(&"x".repeat(MAX_SERVER_HEADER_LEN + 1)), false).is_err());
+
+        let mut response = ResponseHeader::build(200, None).unwrap();
+        response.insert_header(header::SERVER, "nginx/1.25.3").unwrap();
+        response.insert_header("X-Powered-By", "PHP/8.2").unwrap();
+
+        let stripped = ServerIdentity::parse(None, true).unwrap().unwrap();
+        let mut bare = response.clone();
+        stripped.apply(&mut bare).unwrap();
+        assert!(bare.headers.get(header::SERVER).is_none());
+        assert!(bare.headers.get("x-powered-by").is_none());
+
+        // Replacing `Server` alone keeps the other headers
+        let renamed = ServerIdentity::parse(Some(" gateway "), false).unwrap().unwrap();
+        renamed.apply(&mut response).unwrap();
+        assert_eq!(response.headers.get(header::SERVER).unwrap(), "gateway");
+        assert_eq!(response.headers.get("x-powered-by").unwrap(), "PHP/8.2");
+    }
+}
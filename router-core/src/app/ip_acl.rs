@@ -0,0 +1,174 @@
+//! # Client IP Access Control
+//!
+//! Gateway nodes may restrict their rules to clients from given networks with
+//! `allow_cidrs` and `deny_cidrs`. Entries are parsed when the rules are
+//! compiled, so a bad entry rejects the rule instead of failing open at
+//! request time.
+//!
+//! A client is refused when any deny entry matches it, deny wins over an
+//! overlapping allow. Otherwise an empty allow list admits everyone and a
+//! non-empty one only the clients it matches. Single addresses without a
+//! prefix length are accepted as `/32` or `/128`.
+
+use std::net::IpAddr;
+
+/// An IPv4 or IPv6 network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Parses `10.0.0.0/8`, `2001:db8::/32` or a bare address.
+    ///
+    /// Host bits set below the prefix are rejected, they usually hide a typo.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let (addr, prefix_len) = match value.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (value, None),
+        };
+        let network = addr
+            .parse::<IpAddr>()
+            .map_err(|_| format!("Invalid CIDR '{}': bad address", value))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("Invalid CIDR '{}': bad prefix length", value))?,
+            None => max_len,
+        };
+        let cidr = Self { network, prefix_len };
+        if bits(network) & !cidr.mask() != 0 {
+            return Err(format!("Invalid CIDR '{}': host bits set", value));
+        }
+        Ok(cidr)
+    }
+
+    /// Whether `ip` lies within this network, IPv4-mapped IPv6 clients match IPv4 networks.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        if ip.is_ipv4() != self.network.is_ipv4() {
+            return false;
+        }
+        bits(ip) & self.mask() == bits(self.network)
+    }
+
+    fn mask(&self) -> u128 {
+        let width = if self.network.is_ipv4() { 32 } else { 128 };
+        if self.prefix_len == 0 {
+            return 0;
+        }
+        let mask = u128::MAX << (128 - self.prefix_len as u32);
+        mask >> (128 - width)
+    }
+}
+
+/// Address bits right-aligned in a `u128`
+fn bits(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4) as u128,
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+/// Unwraps IPv4-mapped IPv6 addresses, as seen on dual-stack listeners
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// Allow and deny networks of one gateway node
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpAcl {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl IpAcl {
+    /// Parses both lists.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` when both lists are empty, so unrestricted rules skip the check.
+    pub fn parse(allow: &[String], deny: &[String]) -> Result<Option<Self>, String> {
+        if allow.is_empty() && deny.is_empty() {
+            return Ok(None);
+        }
+        let parse_all = |list: &[String]| list.iter().map(|c| Cidr::parse(c)).collect::<Result<Vec<_>, _>>();
+        Ok(Some(Self {
+            allow: parse_all(allow)?,
+            deny: parse_all(deny)?,
+        }))
+    }
+
+    /// Whether a client may use the rule. Deny entries win over allow entries.
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|cidr| cidr.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn list(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_cidr() {
+        assert!(Cidr::parse("10.0.0.0/8").is_ok());
+        assert!(Cidr::parse(" 192.168.1.10 ").is_ok());
+        assert!(Cidr::parse("0.0.0.0/0").is_ok());
+        assert!(Cidr::parse("2001:db8::/32").is_ok());
+        assert!(Cidr::parse("::/0").is_ok());
+        assert!(Cidr::parse("10.0.0.1/8").is_err());
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("10.0.0/8").is_err());
+        assert!(Cidr::parse("internal").is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let net = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(net.contains(ip("10.1.200.3")));
+        assert!(!net.contains(ip("10.2.0.1")));
+        assert!(net.contains(ip("::ffff:10.1.0.9")));
+        assert!(!net.contains(ip("2001:db8::1")));
+
+        let v6 = Cidr::parse("2001:db8::/32").unwrap();
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn test_acl_deny_wins() {
+        assert_eq!(IpAcl::parse(&[], &[]), Ok(None));
+        assert!(IpAcl::parse(&list(&["10.0.0.0/8", "bogus"]), &[]).is_err());
+
+        let acl = IpAcl::parse(&list(&["10.0.0.0/8"]), &list(&["10.0.5.0/24"]))
+            .unwrap()
+            .unwrap();
+        assert!(acl.permits(ip("10.1.2.3")));
+        assert!(!acl.permits(ip("10.0.5.20")));
+        assert!(!acl.permits(ip("192.168.1.1")));
+
+        let deny_only = IpAcl::parse(&[], &list(&["203.0.113.0/24"])).unwrap().unwrap();
+        assert!(deny_only.permits(ip("198.51.100.1")));
+        assert!(!deny_only.permits(ip("203.0.113.9")));
+    }
+}
@@ -0,0 +1,322 @@
+//! # Request Mirroring
+//!
+//! Shadow traffic for gateway rules with a `mirror_target`: a copy of every
+//! request the rule proxies, as rewritten for its upstream, is sent to the
+//! mirror once the client has its response. The mirror can neither delay nor
+//! change what the client sees, its response is read up to the status line and
+//! discarded.
+//!
+//! Mirroring is bounded so a slow or dead mirror can't hold up the gateway:
+//!
+//! * The request body is buffered while it streams to the primary, up to
+//!   `GWRS_GATEWAY_MIRROR_MAX_BODY` bytes (default 256 KiB). Larger requests
+//!   aren't mirrored.
+//! * At most `GWRS_GATEWAY_MIRROR_MAX_IN_FLIGHT` copies (default 64, `0` turns
+//!   mirroring off) are on their way across all listeners, further ones are
+//!   dropped. The count is reported under `limits` of prottp `/status`.
+//! * A copy has 5 seconds to connect, be written and be answered.
+//!
+//! WebSocket upgrades are never mirrored. Failed and dropped copies are logged
+//! as `[GWX]` lines of `TYPE:MIRROR` with the mirror as `DST`, apart from the
+//! request's own `REQ` and `RES` lines.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use http::header::{self, HeaderName};
+use log::{debug, warn};
+use pingora::http::RequestHeader;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::app::concurrency::{LimitKind, ListenerLimit};
+use crate::config;
+
+/// Time a copy has to connect, be written and be answered
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest status line read from the mirror
+const MAX_STATUS_LINE: u64 = 1024;
+
+/// Request headers not copied, the copy is framed by its buffered body and closes after it
+const SKIPPED_HEADERS: [HeaderName; 4] = [
+    header::CONTENT_LENGTH,
+    header::TRANSFER_ENCODING,
+    header::CONNECTION,
+    header::EXPECT,
+];
+
+// Copies in flight across all gateway listeners.
+static IN_FLIGHT: LazyLock<Arc<ListenerLimit>> =
+    LazyLock::new(|| ListenerLimit::register(LimitKind::Mirror, "mirror", config::mirror_max_in_flight()));
+
+/// The mirror of one gateway rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mirror {
+    /// Target as configured, logged as `DST`
+    pub name: String,
+    /// Resolved address copies are sent to
+    pub address: SocketAddr,
+}
+
+impl Mirror {
+    /// Resolves a rule's `mirror_target` once, like its target.
+    ///
+    /// Returns `Ok(None)` when the rule has no mirror.
+    pub fn parse(
+        target: Option<&str>,
+        resolve: impl Fn(&str) -> Option<SocketAddr>,
+    ) -> Result<Option<Self>, String> {
+        let Some(name) = target.map(str::trim).filter(|target| !target.is_empty()) else {
+            return Ok(None);
+        };
+        let address = resolve(name).ok_or_else(|| format!("Unable to resolve mirror target '{}'", name))?;
+        Ok(Some(Self {
+            name: name.to_string(),
+            address,
+        }))
+    }
+}
+
+/// Fields of the request's `[GWX]` lines repeated on its `MIRROR` lines
+#[derive(Debug, Clone, Default)]
+pub struct LogFields {
+    pub conn_id: String,
+    pub conn_type: String,
+    pub src_addr: String,
+    pub request_id: String,
+}
+
+/// Copy of one proxied request, collected while it is sent to the primary
+#[derive(Debug)]
+pub struct MirrorCopy {
+    mirror: Arc<Mirror>,
+    head: Vec<u8>,  // Request line and headers, without the final CRLF
+    body: Vec<u8>,  // Buffered request body
+    complete: bool, // Whole body buffered, or the request has none
+}
+
+impl MirrorCopy {
+    /// Starts the copy of a request as it goes upstream.
+    pub fn new(mirror: Arc<Mirror>, request: &RequestHeader) -> Self {
+        let path = request.uri.path_and_query().map_or("/", |path| path.as_str());
+        let mut head = format!("{} {} HTTP/1.1\r\n", request.method, path).into_bytes();
+        // HTTP/2 clients send the host as the authority only
+        if !request.headers.contains_key(header::HOST) {
+            if let Some(authority) = request.uri.authority() {
+                head.extend_from_slice(format!("host: {}\r\n", authority).as_bytes());
+            }
+        }
+        for (name, value) in request.headers.iter() {
+            if SKIPPED_HEADERS.contains(name) {
+                continue;
+            }
+            head.extend_from_slice(name.as_str().as_bytes());
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(value.as_bytes());
+            head.extend_from_slice(b"\r\n");
+        }
+        // Without framing headers the request has no body to wait for
+        let has_body = request.headers.contains_key(header::TRANSFER_ENCODING)
+            || request
+                .headers
+                .get(header::CONTENT_LENGTH)
+                .is_some_and(|length| length.as_bytes() != b"0");
+        Self {
+            mirror,
+            head,
+            body: Vec::new(),
+            complete: !has_body,
+        }
+    }
+
+    /// Adds a chunk of the request body.
+    ///
+    /// Returns `false` once the body outgrew `max_body`, the copy is to be dropped.
+    pub fn append(&mut self, chunk: &[u8], end_of_stream: bool, max_body: usize) -> bool {
+        if self.body.len() + chunk.len() > max_body {
+            return false;
+        }
+        self.body.extend_from_slice(chunk);
+        self.complete |= end_of_stream;
+        true
+    }
+
+    /// Mirror the copy goes to
+    pub fn mirror(&self) -> &Mirror {
+        &self.mirror
+    }
+
+    /// Buffered body size
+    pub fn size(&self) -> usize {
+        self.body.len()
+    }
+
+    /// The copy as sent to the mirror, one request on its own connection
+    fn into_request(self) -> Vec<u8> {
+        let mut request = self.head;
+        if !self.body.is_empty() {
+            request.extend_from_slice(format!("content-length: {}\r\n", self.body.len()).as_bytes());
+        }
+        request.extend_from_slice(b"connection: close\r\n\r\n");
+        request.extend_from_slice(&self.body);
+        request
+    }
+}
+
+/// Sends a copy in the background, the caller never waits for the mirror.
+///
+/// Copies with an incomplete body, e.g. of a request that failed mid-upload,
+/// and copies over the in-flight limit are dropped and logged.
+pub fn dispatch(copy: MirrorCopy, log: LogFields) {
+    if !copy.complete {
+        discard(copy, &log, "request body incomplete, not mirrored");
+        return;
+    }
+    if IN_FLIGHT.max() == 0 {
+        return;
+    }
+    let Some(permit) = IN_FLIGHT.try_acquire() else {
+        discard(copy, &log, "too many mirrored requests in flight, dropped");
+        return;
+    };
+    tokio::spawn(async move {
+        let _permit = permit;
+        let mirror = copy.mirror.clone();
+        let size = copy.size();
+        match tokio::time::timeout(MIRROR_TIMEOUT, deliver(mirror.address, copy.into_request())).await {
+            Ok(Ok(status)) => debug!(
+                "[GWX] | ID:{}, TYPE:MIRROR, CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{}, COMMENT:{} |",
+                log.conn_id, log.conn_type, size, status, log.src_addr, mirror.name, log.request_id
+            ),
+            Ok(Err(e)) => log_failure(&log, &mirror, size, &e),
+            Err(_) => log_failure(
+                &log,
+                &mirror,
+                size,
+                &format!("no response within {}s", MIRROR_TIMEOUT.as_secs()),
+            ),
+        }
+    });
+}
+
+/// Drops a copy that won't be sent, logging why.
+pub fn discard(copy: MirrorCopy, log: &LogFields, reason: &str) {
+    log_failure(log, copy.mirror(), copy.size(), reason);
+}
+
+/// Logs a copy that didn't reach the mirror.
+fn log_failure(log: &LogFields, mirror: &Mirror, size: usize, comment: &str) {
+    warn!(
+        "[GWX] | ID:{}, TYPE:MIRROR, CONN:{}, SIZE:{}, STAT:N/A, SRC:{}, DST:{}, COMMENT:{} {} |",
+        log.conn_id, log.conn_type, size, log.src_addr, mirror.name, log.request_id, comment
+    );
+}
+
+/// Writes one request to the mirror and returns the status it answered with.
+async fn deliver(address: SocketAddr, request: Vec<u8>) -> Result<u16, String> {
+    let mut stream = TcpStream::connect(address)
+        .await
+        .map_err(|e| format!("connect failed: {}", e))?;
+    stream
+        .write_all(&request)
+        .await
+        .map_err(|e| format!("write failed: {}", e))?;
+    let mut line = String::new();
+    BufReader::new((&mut stream).take(MAX_STATUS_LINE))
+        .read_line(&mut line)
+        .await
+        .map_err(|e| format!("read failed: {}", e))?;
+    line.strip_prefix("HTTP/")
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| "no HTTP status line in the response".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn mirror(address: &str) -> Arc<Mirror> {
+        Arc::new(Mirror {
+            name: address.to_string(),
+            address: address.parse().unwrap(),
+        })
+    }
+
+    #[test]
+    fn test_parse_mirror() {
+        let resolve = |target: &str| target.parse::<SocketAddr>().ok();
+        assert_eq!(Mirror::parse(None, resolve), Ok(None));
+        assert_eq!(Mirror::parse(Some("  "), resolve), Ok(None));
+        assert!(Mirror::parse(Some("shadow.invalid:80"), resolve).is_err());
+
+        let parsed = Mirror::parse(Some(" 127.0.0.1:9090 "), resolve).unwrap().unwrap();
+        assert_eq!(parsed.name, "127.0.0.1:9090");
+        assert_eq!(parsed.address, "127.0.0.1:9090".parse().unwrap());
+    }
+
+    #[test]
+    fn test_copy_reframes_the_request() {
+        let mut request = RequestHeader::build("POST", b"/v2/users?page=2", None).unwrap();
+        request.insert_header("Host", "api.example.com").unwrap();
+        request.insert_header("Content-Length", "5").unwrap();
+        request.insert_header("Expect", "100-continue").unwrap();
+        request.insert_header("X-Trace", "abc").unwrap();
+
+        let mut copy = MirrorCopy::new(mirror("127.0.0.1:9090"), &request);
+        assert!(!copy.complete);
+        assert!(copy.append(b"hel", false, 16));
+        assert!(copy.append(b"lo", true, 16));
+
+        let sent = String::from_utf8(copy.into_request()).unwrap();
+        assert!(sent.starts_with("POST /v2/users?page=2 HTTP/1.1\r\n"));
+        assert!(sent.contains("host: api.example.com\r\n"));
+        assert!(sent.contains("x-trace: abc\r\n"));
+        assert!(!sent.contains("expect"));
+        assert!(sent.ends_with("content-length: 5\r\nconnection: close\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn test_copy_over_limit_is_refused() {
+        let mut request = RequestHeader::build("PUT", b"/upload", None).unwrap();
+        request.insert_header("Transfer-Encoding", "chunked").unwrap();
+        let mut copy = MirrorCopy::new(mirror("127.0.0.1:9090"), &request);
+        assert!(copy.append(b"1234", false, 8));
+        assert!(!copy.append(b"56789", false, 8));
+
+        // Bodiless requests are complete from the start
+        let get = RequestHeader::build("GET", b"/", None).unwrap();
+        assert!(MirrorCopy::new(mirror("127.0.0.1:9090"), &get).complete);
+    }
+
+    #[tokio::test]
+    async fn test_deliver_reads_the_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !received.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                assert!(n > 0, "copy ended early");
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket.write_all(b"HTTP/1.1 202 Accepted\r\n\r\n").await.unwrap();
+            received
+        });
+
+        let request = RequestHeader::build("GET", b"/health", None).unwrap();
+        let copy = MirrorCopy::new(mirror(&address.to_string()), &request);
+        let status = tokio::time::timeout(MIRROR_TIMEOUT, deliver(address, copy.into_request()))
+            .await
+            .expect("mirror answered");
+        assert_eq!(status, Ok(202));
+        let received = server.await.unwrap();
+        assert!(received.starts_with(b"GET /health HTTP/1.1\r\n"));
+        assert!(received.ends_with(b"connection: close\r\n\r\n"));
+    }
+}
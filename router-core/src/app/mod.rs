@@ -8,6 +8,24 @@
 //! 
 //! * `proxy`: Implements proxying functionality for TCP/TLS connections
 //! * `gateway`: Implements HTTP gateway functionality with path-based routing
+//! * `path_template`: Compiles gateway `path_target` templates with numeric and named captures
+//! * `ws_frame`: Counts WebSocket frames relayed by the proxy for per-connection metrics
+//! * `compress`: Streaming gzip/brotli compression of gateway responses
+//! * `ip_acl`: Client IP allow/deny lists of gateway nodes
+//! * `peer_health`: Gateway targets that refused a connection recently, skipped on failover
+//! * `upstream_timeout`: Connect, header and total response timeouts of gateway nodes
+//! * `trace`: Timed tracing spans around the stages of a gateway request
+//! * `concurrency`: Connection and in-flight request limits per listener
+//! * `affinity`: Sticky sessions pinning gateway clients to one backend of a path
+//! * `connections`: Registry of the connections the proxies are relaying
+//! * `redact`: JSON field redaction of gateway responses
+//! * `route_script`: Sandboxed Rhai scripts deciding the routes of gateway nodes
+//! * `split`: Percentage traffic splits of gateway rules for canaries and A/B tests
+//! * `cors`: CORS preflights answered per gateway node
+//! * `header_policy`: Hop-by-hop, `Via` and `Server` headers of proxied gateway traffic
+//! * `acme_challenge`: ACME HTTP-01 challenges answered while router-api obtains certificates
+//! * `mirror`: Copies of gateway rule requests sent to a shadow target, responses discarded
+//! * `route_trace`: Routing decision of single gateway requests asking for it with the trace token
 //! 
 //! ## Responsibility
 //! 
@@ -20,4 +38,22 @@
 //! The implementations in this module build on the lower-level system components
 //! to provide the actual gateway and proxy behavior defined by user configuration.
 pub mod proxy_fast;
-pub mod gateway_fast;
\ No newline at end of file
+pub mod gateway_fast;
+pub mod path_template;
+pub mod ws_frame;
+pub mod compress;
+pub mod ip_acl;
+pub mod peer_health;
+pub mod upstream_timeout;
+pub mod trace;
+pub mod concurrency;
+pub mod affinity;
+pub mod connections;
+pub mod redact;
+pub mod route_script;
+pub mod split;
+pub mod cors;
+pub mod header_policy;
+pub mod acme_challenge;
+pub mod mirror;
+pub mod route_trace;
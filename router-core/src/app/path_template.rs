@@ -0,0 +1,358 @@
+//! # Path Template
+//!
+//! Compiled form of a gateway rule's `path_target`, used to build the rewritten
+//! path from the regex captures of `path_listen`.
+//!
+//! Supported references:
+//! - `$1`, `${1}` - numeric capture groups (`$0` is the whole match)
+//! - `$name`, `${name}` - named capture groups, e.g. `(?P<id>[0-9]+)`
+//! - `$$` - a literal `$`
+//!
+//! A numeric reference only consumes digits, so `/$1abc` is group 1 followed
+//! by `abc`. Use braces when a name must be followed by word characters:
+//! `${id}_v2`. Groups that did not participate in the match expand to "".
+//!
+//! ## Captured Values
+//!
+//! Captures come from the request, so they are cleaned before substitution,
+//! the template's own text is used as written:
+//!
+//! - `%XX` escapes are decoded first, so `%2e`, `%2f` and `%5c` can't hide a
+//!   dot segment or a separator from the next step
+//! - `.` and `..` segments are resolved within the capture, with `/` and `\`
+//!   both separating segments, and never climb above it, so `/files/$1` with
+//!   `$1 = ../admin` or `..%2fadmin` becomes `/files/admin`
+//! - characters a URI path can't hold (spaces, `"`, `<`, `#`, `%`, non-ASCII, ..)
+//!   are percent-encoded again
+//!
+//! `GWRS_GATEWAY_ALLOW_DOT_SEGMENTS` skips the first two steps and keeps
+//! captures as sent, existing escapes included.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use regex::{Captures, Regex};
+
+// Whether dot segments of captures are kept, `GWRS_GATEWAY_ALLOW_DOT_SEGMENTS`
+static KEEP_DOT_SEGMENTS: AtomicBool = AtomicBool::new(false);
+
+/// Keeps or resolves `.` and `..` segments of captured values from now on.
+pub fn set_keep_dot_segments(keep: bool) {
+    KEEP_DOT_SEGMENTS.store(keep, Ordering::Relaxed);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Index(usize),
+    Name(String),
+}
+
+/// A `path_target` parsed once at rule load time.
+#[derive(Clone, Debug)]
+pub struct PathTemplate {
+    parts: Vec<Part>,
+}
+
+impl PathTemplate {
+    /// Parses a target template into literal and capture reference parts.
+    pub fn parse(template: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+
+        while let Some(pos) = rest.find('$') {
+            literal.push_str(&rest[..pos]);
+            let after = &rest[pos + 1..];
+
+            let (reference, consumed) = if after.starts_with('$') {
+                literal.push('$');
+                (None, 1)
+            } else if let Some(inner) = after.strip_prefix('{') {
+                match inner.find('}') {
+                    Some(end) if end > 0 => (Some(Self::reference(&inner[..end])), end + 2),
+                    _ => (None, 0),
+                }
+            } else {
+                let len = Self::reference_len(after);
+                if len > 0 {
+                    (Some(Self::reference(&after[..len])), len)
+                } else {
+                    (None, 0)
+                }
+            };
+
+            match reference {
+                Some(part) => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                }
+                // `$$` already pushed its `$`, a lone `$` is kept as is
+                None if consumed == 0 => literal.push('$'),
+                None => {}
+            }
+            rest = &after[consumed..];
+        }
+
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Self { parts }
+    }
+
+    /// Length of an unbraced reference: a run of digits, or an identifier.
+    fn reference_len(s: &str) -> usize {
+        let bytes = s.as_bytes();
+        match bytes.first() {
+            Some(b) if b.is_ascii_digit() => bytes.iter().take_while(|b| b.is_ascii_digit()).count(),
+            Some(b) if b.is_ascii_alphabetic() || *b == b'_' => bytes
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+                .count(),
+            _ => 0,
+        }
+    }
+
+    fn reference(name: &str) -> Part {
+        match name.parse::<usize>() {
+            Ok(index) => Part::Index(index),
+            Err(_) => Part::Name(name.to_string()),
+        }
+    }
+
+    /// Lists references that the given pattern cannot satisfy.
+    ///
+    /// Such references always expand to an empty string, which is almost
+    /// certainly a typo in the rule, so callers log them as warnings.
+    pub fn unknown_references(&self, pattern: &Regex) -> Vec<String> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Index(index) if *index >= pattern.captures_len() => Some(format!("${}", index)),
+                Part::Name(name) if !pattern.capture_names().flatten().any(|n| n == name) => {
+                    Some(format!("${{{}}}", name))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Builds the rewritten path from the captures of a matched rule.
+    pub fn expand(&self, captures: &Captures) -> String {
+        self.expand_with(captures, KEEP_DOT_SEGMENTS.load(Ordering::Relaxed))
+    }
+
+    fn expand_with(&self, captures: &Captures, keep_dot_segments: bool) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            let value = match part {
+                Part::Literal(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Part::Index(index) => captures.get(*index).map(|m| m.as_str()),
+                Part::Name(name) => captures.name(name).map(|m| m.as_str()),
+            };
+            if let Some(value) = value {
+                if keep_dot_segments {
+                    push_encoded(&mut out, value.as_bytes(), true);
+                } else {
+                    let decoded = percent_decode(value);
+                    push_encoded(&mut out, &remove_dot_segments(&decoded), false);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Decodes the `%XX` escapes of a captured value, invalid escapes are kept as is.
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' {
+            bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Resolves `.` and `..` segments of a decoded captured value.
+///
+/// Both `/` and `\` separate segments, since some backends accept either. A
+/// `..` only removes a segment of the value itself, any surplus is dropped, so
+/// the result never reaches above where the value is substituted.
+fn remove_dot_segments(value: &[u8]) -> Vec<u8> {
+    let mut segments: Vec<&[u8]> = Vec::new();
+    let mut ends_in_dot = false;
+    for segment in value.split(|b| *b == b'/' || *b == b'\\') {
+        ends_in_dot = true;
+        match segment {
+            b"." => {}
+            b".." => {
+                // Never pop the empty segment of a leading `/`
+                if segments.last().map_or(false, |last| !last.is_empty()) {
+                    segments.pop();
+                }
+            }
+            _ => {
+                ends_in_dot = false;
+                segments.push(segment);
+            }
+        }
+    }
+    // `a/..` and `a/.` name a directory, keep the trailing `/`
+    if ends_in_dot && !segments.is_empty() {
+        segments.push(b"");
+    }
+    segments.join(&b'/')
+}
+
+/// Appends `value`, percent-encoding what a URI path can't hold.
+///
+/// Unreserved characters, sub-delims, `:`, `@` and `/` are kept. `%` is only
+/// kept with `keep_escapes`, for values that weren't decoded.
+fn push_encoded(out: &mut String, value: &[u8], keep_escapes: bool) {
+    for &byte in value {
+        let allowed = byte.is_ascii_alphanumeric()
+            || matches!(
+                byte,
+                b'-' | b'.' | b'_' | b'~' | b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+'
+                    | b',' | b';' | b'=' | b':' | b'@' | b'/'
+            )
+            || (keep_escapes && byte == b'%');
+        if allowed {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(pattern: &str, template: &str, path: &str) -> String {
+        let re = Regex::new(pattern).unwrap();
+        let captures = re.captures(path).unwrap();
+        PathTemplate::parse(template).expand(&captures)
+    }
+
+    #[test]
+    fn test_numeric_references() {
+        assert_eq!(rewrite("^/api/(.*)$", "/$1", "/api/users"), "/users");
+        assert_eq!(rewrite("^(.*)$", "/v2$1", "/users"), "/v2/users");
+        assert_eq!(rewrite("^/a/(\\d+)/(\\w+)$", "/$2/${1}x", "/a/7/b"), "/b/7x");
+        assert_eq!(rewrite("^/a/(\\d+)$", "/$1abc", "/a/7"), "/7abc");
+    }
+
+    #[test]
+    fn test_named_references() {
+        assert_eq!(
+            rewrite("^/users/(?P<id>[0-9]+)$", "/v2/users/$id", "/users/42"),
+            "/v2/users/42"
+        );
+        assert_eq!(
+            rewrite("^/users/(?P<id>[0-9]+)$", "/v2/${id}_profile", "/users/42"),
+            "/v2/42_profile"
+        );
+    }
+
+    #[test]
+    fn test_mixed_named_and_numeric() {
+        let pattern = "^/(?P<tenant>[a-z]+)/(items)/(?P<id>[0-9]+)$";
+        assert_eq!(
+            rewrite(pattern, "/$2/${id}?tenant=$tenant&all=$0", "/acme/items/9"),
+            "/items/9?tenant=acme&all=/acme/items/9"
+        );
+        // Named groups are numbered too
+        assert_eq!(rewrite(pattern, "/$1/$3", "/acme/items/9"), "/acme/9");
+    }
+
+    #[test]
+    fn test_literal_dollars_and_missing_groups() {
+        assert_eq!(rewrite("^/(.*)$", "/cost/$$1", "/x"), "/cost/$1");
+        assert_eq!(rewrite("^/(.*)$", "/a$", "/x"), "/a$");
+        assert_eq!(rewrite("^/(.*)$", "/a/${", "/x"), "/a/${");
+        assert_eq!(rewrite("^/(a)?(.*)$", "/$1-$2", "/x"), "/-x");
+        assert_eq!(rewrite("^/(.*)$", "/$nope/$9", "/x"), "//");
+    }
+
+    #[test]
+    fn test_dot_segments_stay_below_the_prefix() {
+        assert_eq!(rewrite("^/files/(.*)$", "/store/$1", "/files/../admin"), "/store/admin");
+        assert_eq!(rewrite("^/files/(.*)$", "/store/$1", "/files/%2e%2E/admin"), "/store/admin");
+        assert_eq!(rewrite("^/files/(.*)$", "/store/$1", "/files/a/../../b/./c"), "/store/b/c");
+        assert_eq!(rewrite("^/files/(.*)$", "/store/$1", "/files/a/.."), "/store/");
+        assert_eq!(rewrite("^(.*)$", "/v2$1", "/../etc"), "/v2/etc");
+        // Dots inside a segment are no dot segments
+        assert_eq!(rewrite("^/files/(.*)$", "/store/$1", "/files/..a/b.."), "/store/..a/b..");
+        // The template's own text is used as written
+        assert_eq!(rewrite("^/files/(.*)$", "/store/../$1", "/files/x"), "/store/../x");
+
+        let re = Regex::new("^/files/(.*)$").unwrap();
+        let captures = re.captures("/files/../admin").unwrap();
+        assert_eq!(PathTemplate::parse("/store/$1").expand_with(&captures, true), "/store/../admin");
+    }
+
+    #[test]
+    fn test_encoded_separators_and_dots_are_resolved() {
+        let re = Regex::new("^/files/(.*)$").unwrap();
+        let template = PathTemplate::parse("/store/$1");
+        let expand = |path: &str| template.expand(&re.captures(path).unwrap());
+        assert_eq!(expand("/files/..%2fadmin"), "/store/admin");
+        assert_eq!(expand("/files/..%2Fadmin"), "/store/admin");
+        assert_eq!(expand("/files/..%5cadmin"), "/store/admin");
+        assert_eq!(expand("/files/..\\admin"), "/store/admin");
+        assert_eq!(expand("/files/%2e%2e%2f%2e%2e%2fetc"), "/store/etc");
+        assert_eq!(expand("/files/a%2fb"), "/store/a/b");
+        // Decoded once, what is left of a double encoding stays literal
+        assert_eq!(expand("/files/%252e%252e/admin"), "/store/%252e%252e/admin");
+        // Kept as sent when dot segments are allowed
+        let captures = re.captures("/files/..%2fadmin").unwrap();
+        assert_eq!(template.expand_with(&captures, true), "/store/..%2fadmin");
+    }
+
+    #[test]
+    fn test_captures_are_percent_encoded() {
+        let re = Regex::new("^/files/(.*)$").unwrap();
+        let template = PathTemplate::parse("/store/$1");
+        let expand = |path: &str| template.expand(&re.captures(path).unwrap());
+        assert_eq!(expand("/files/a b\"<c>"), "/store/a%20b%22%3Cc%3E");
+        assert_eq!(expand("/files/caf\u{e9}#x"), "/store/caf%C3%A9%23x");
+        // Escapes come out the same, path characters are kept
+        assert_eq!(expand("/files/a%20b/c;v=1@x:y"), "/store/a%20b/c;v=1@x:y");
+        assert_eq!(expand("/files/100%"), "/store/100%25");
+        assert!(http::uri::PathAndQuery::try_from(expand("/files/\x07 ^{}|`").as_str()).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_references() {
+        let re = Regex::new("^/(?P<id>[0-9]+)/(.*)$").unwrap();
+        assert!(PathTemplate::parse("/$id/$2").unknown_references(&re).is_empty());
+        assert_eq!(
+            PathTemplate::parse("/$ids/$3").unknown_references(&re),
+            vec!["${ids}".to_string(), "$3".to_string()]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! # Passive Target Health
+//!
+//! A gateway target that refused a connection is marked down for
+//! `GWRS_GATEWAY_UNHEALTHY_TTL` seconds (default 10, `0` disables the
+//! marking). While a target is down, requests routed to it move straight to
+//! the next matching rule in failover order instead of paying a refused
+//! connect first. When every matching target is down the first one is still
+//! tried, so a recovered target is noticed without waiting for the TTL.
+//!
+//! The state is shared by all gateway listeners, a target refusing one
+//! listener's connections is down for all of them.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::config;
+
+/// Targets that refused a connection, with the time they are tried again
+pub struct PeerHealth {
+    ttl_ms: AtomicU64,
+    down_until: RwLock<HashMap<String, Instant>>,
+}
+
+impl PeerHealth {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl_ms: AtomicU64::new(ttl.as_millis() as u64),
+            down_until: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// How long a refusing target stays down
+    pub fn ttl(&self) -> Duration {
+        Duration::from_millis(self.ttl_ms.load(Ordering::Relaxed))
+    }
+
+    /// Changes the TTL of targets marked from now on.
+    pub fn set_ttl(&self, ttl: Duration) {
+        self.ttl_ms.store(ttl.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Marks `addr` down after it refused a connection at `now`.
+    pub fn mark_down(&self, addr: &str, now: Instant) {
+        let ttl = self.ttl();
+        if ttl.is_zero() {
+            return;
+        }
+        if let Ok(mut down) = self.down_until.write() {
+            down.retain(|_, until| *until > now);
+            down.insert(addr.to_string(), now + ttl);
+        }
+    }
+
+    /// Whether `addr` refused a connection within the TTL before `now`.
+    pub fn is_down(&self, addr: &str, now: Instant) -> bool {
+        self.down_until
+            .read()
+            .ok()
+            .and_then(|down| down.get(addr).map(|until| *until > now))
+            .unwrap_or(false)
+    }
+}
+
+/// Health of every gateway target, shared by all listeners
+pub static PEER_HEALTH: LazyLock<PeerHealth> =
+    LazyLock::new(|| PeerHealth::new(config::gateway_unhealthy_ttl()));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_down_until_ttl() {
+        let health = PeerHealth::new(Duration::from_secs(10));
+        let now = Instant::now();
+        assert!(!health.is_down("127.0.0.1:3004", now));
+
+        health.mark_down("127.0.0.1:3004", now);
+        assert!(health.is_down("127.0.0.1:3004", now + Duration::from_secs(9)));
+        assert!(!health.is_down("127.0.0.1:3005", now));
+        assert!(!health.is_down("127.0.0.1:3004", now + Duration::from_secs(10)));
+
+        // A later refusal extends the window
+        health.mark_down("127.0.0.1:3004", now + Duration::from_secs(5));
+        assert!(health.is_down("127.0.0.1:3004", now + Duration::from_secs(12)));
+    }
+
+    #[test]
+    fn test_zero_ttl_disables() {
+        let health = PeerHealth::new(Duration::ZERO);
+        let now = Instant::now();
+        health.mark_down("127.0.0.1:3004", now);
+        assert!(!health.is_down("127.0.0.1:3004", now));
+    }
+}
@@ -15,13 +15,16 @@
 use async_trait::async_trait;
 use log::{debug, error, warn};
 
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::select;
+use tokio::time::Instant;
 
 use pingora::apps::ServerApp;
 use pingora::connectors::TransportConnector;
-use pingora::protocols::Stream;
+use pingora::protocols::{GetSocketDigest, Stream, UniqueID};
 use pingora::server::ShutdownWatch;
 use pingora::upstreams::peer::BasicPeer;
 use regex_automata::meta::Regex;
@@ -31,7 +34,12 @@ use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use lru::LruCache;
 
+use crate::app::concurrency::{LimitKind, ListenerLimit};
+use crate::app::connections::{Connection, Tracked};
 use crate::config::{self, GatewayPath};
+use crate::app::ws_frame::{self, FrameParser};
+use crate::system::sockopt::TcpOptions;
+use crate::system::writer::access_log::{self, AccessEntry};
 use crate::system::writer::rawid::atomic_id;
 
 // Number of cache shards to reduce lock contention
@@ -122,32 +130,64 @@ pub struct ProxyApp {
     client_connector: TransportConnector,
     proxy_to: BasicPeer,
     proxy_source: String,
+    // Request ID of the API call that produced this proxy's routing
+    request_id: String,
+    // Whether the listener terminates TLS, reported with the active connections
+    tls: bool,
+    // Nagle and keepalive settings for the downstream and upstream sockets
+    tcp_options: TcpOptions,
+    // Relay buffer per direction, allocated per connection
+    buffer_size: usize,
+    // Count WebSocket frames per connection (GWRS_WS_FRAME_COUNTS)
+    ws_frame_counts: bool,
+    // Silence after which a connection is closed, once upgraded the WebSocket one applies
+    idle_timeout: Option<Duration>,
+    ws_idle_timeout: Option<Duration>,
+    // Silence after which a WebSocket client is pinged (GWRS_WS_PING_INTERVAL)
+    ws_ping_interval: Option<Duration>,
     path_rewrites: Arc<RwLock<Vec<RewriteRule>>>,
     // Cache for rewritten requests: key = original request line, value = rewritten request
     rewrite_cache: Arc<ShardedLruCache<String, String>>,
-    // Last time config was checked
-    last_check_time: RwLock<std::time::Instant>,
-    // Recheck interval
-    check_interval: std::time::Duration,
+    // Gateway config version the rewrite rules were loaded from
+    seen_config_version: AtomicU64,
+    // Open connection limit of this proxy (GWRS_PROXY_MAX_CONNECTIONS)
+    limit: Arc<ListenerLimit>,
 }
 
 enum DuplexEvent {
     DownstreamRead(usize),
     UpstreamRead(usize),
+    PingDue,
 }
 
 impl ProxyApp {
-    pub fn new(proxy_to: BasicPeer, proxy_source: String) -> Self {
+    pub fn new(
+        proxy_to: BasicPeer,
+        proxy_source: String,
+        tcp_options: TcpOptions,
+        buffer_size: usize,
+        tls: bool,
+    ) -> Self {
+        let seen_config_version = AtomicU64::new(config::gateway_config_version());
         let path_rewrites = Self::fetch_config(proxy_to.clone());
+        let limit = ListenerLimit::register(LimitKind::Proxy, &proxy_source, config::proxy_max_connections());
 
         ProxyApp {
             client_connector: TransportConnector::new(None),
             proxy_to,
             proxy_source,
+            request_id: config::RoutingData::ProxyRequestID.get(),
+            tls,
+            tcp_options,
+            buffer_size,
+            ws_frame_counts: config::ws_frame_counts(),
+            idle_timeout: config::proxy_idle_timeout(),
+            ws_idle_timeout: config::ws_idle_timeout(),
+            ws_ping_interval: config::ws_ping_interval(),
             path_rewrites: Arc::new(RwLock::new(path_rewrites)),
             rewrite_cache: Arc::new(ShardedLruCache::new(DEFAULT_PER_SHARD_CAPACITY)),
-            last_check_time: RwLock::new(std::time::Instant::now()),
-            check_interval: std::time::Duration::from_secs(5), // Check config every 5 seconds
+            seen_config_version,
+            limit,
         }
     }
 
@@ -158,8 +198,19 @@ impl ProxyApp {
         let mut new_rewrites = Vec::new();
         if let Some(cfg) = config {
             for node in cfg {
+                // normalize the configured target so "[::1]:8080" and friends compare
+                // against the peer address the same way it is displayed, Unix socket
+                // peers display as their bare path
+                let node_target = match config::unix_socket_path(&node.addr_target) {
+                    Some(path) => path.to_string(),
+                    None => node
+                        .addr_target
+                        .parse::<std::net::SocketAddr>()
+                        .map(|addr| addr.to_string())
+                        .unwrap_or_else(|_| node.addr_target.clone()),
+                };
                 // high speed only
-                if node.addr_target == current_addr {
+                if node_target == current_addr {
                     // Determine if this is a plain string path, a wildcard path, or a regex pattern
                     let processed_pattern = if Self::is_regex_pattern(&node.path_listen) {
                         // Already a regex pattern (contains regex special chars other than * at the end)
@@ -457,122 +508,169 @@ impl ProxyApp {
         (0, is_websocket, extracted_id)
     }
     
-    /// Checks if the configuration should be reloaded based on time interval.
+    /// Reloads the rewrite rules when gateway paths were applied since the last check.
     fn check_and_reload_config_if_needed(&self) {
-        let now = std::time::Instant::now();
-        let needs_check = {
-            // Scoped read lock
-            match self.last_check_time.read() {
-                Ok(last_check_guard) => now.duration_since(*last_check_guard) >= self.check_interval,
-                Err(e) => {
-                    error!("Failed to acquire read lock on last_check_time: {}", e);
-                    false // Don't check if lock is poisoned
-                }
-            }
-            // Read lock is dropped here
-        };
-
-        if needs_check {
-            // Acquire write lock only if the time interval has passed.
-            match self.last_check_time.write() {
-                Ok(mut last_check_guard) => {
-                    // Double-check in case another thread updated it between the read and write lock acquisition.
-                    if now.duration_since(*last_check_guard) >= self.check_interval {
-                        // Update last check time *before* potentially long-running fetch_config
-                        *last_check_guard = now;
-                        // Drop the lock before calling fetch_config to avoid holding it too long
-                        drop(last_check_guard);
-
-                        // Now perform the actual check and potential reload
-                        debug!("Checking rules due to interval check...");
-                        let new_rewrites = Self::fetch_config(self.proxy_to.clone());
-
-                        // Compare current rules count with new rules count
-                        let current_rules_count = match self.path_rewrites.read() {
-                             Ok(rules_guard) => rules_guard.len(),
-                             Err(e) => {
-                                 error!("Failed to acquire read lock on path_rewrites for count: {}", e);
-                                 // Cannot compare if lock is poisoned, assume no change needed for safety
-                                 // Or potentially return a sentinel value like usize::MAX
-                                 return; // Exit the check function
-                             }
-                        };
-
-                        // Only update if the rules have changed
-                        if current_rules_count != new_rewrites.len() {
-                            log::info!(
-                                "Configuration changed. Reloading rules for proxy: {} (old: {}, new: {})",
-                                self.proxy_to._address,
-                                current_rules_count,
-                                new_rewrites.len()
-                            );
+        let version = config::gateway_config_version();
+        // Claim the version so concurrent connections reload once
+        let seen = self.seen_config_version.swap(version, Ordering::AcqRel);
+        if seen == version {
+            return;
+        }
 
-                            // Clear the rewrite cache as rules are changing
-                            self.rewrite_cache.clear();
+        debug!("Gateway config version {} -> {}, reloading rules...", seen, version);
+        let new_rewrites = Self::fetch_config(self.proxy_to.clone());
+        log::info!(
+            "Configuration changed. Reloading rules for proxy: {} ({} rules)",
+            self.proxy_to._address,
+            new_rewrites.len()
+        );
 
-                            // Update the rules with write lock
-                            match self.path_rewrites.write() {
-                                Ok(mut rules_guard) => *rules_guard = new_rewrites,
-                                Err(e) => {
-                                    error!("Failed to acquire write lock on path_rewrites for update: {}", e);
-                                    // Failed to update rules, cache might be inconsistent now.
-                                    // Consider logging a more severe warning or taking other action.
-                                }
-                            }
-                        }
-                    }
-                },
-                Err(e) => {
-                     error!("Failed to acquire write lock on last_check_time: {}", e);
-                     // Cannot update last check time if lock is poisoned
-                }
+        match self.path_rewrites.write() {
+            Ok(mut rules_guard) => *rules_guard = new_rewrites,
+            Err(e) => {
+                error!("Failed to acquire write lock on path_rewrites for update: {}", e);
+                return;
             }
-            // Write lock is dropped here
         }
+        // Clear after the swap so no rewrite of the old rules is cached again
+        self.rewrite_cache.clear();
     }
 
-    async fn duplex(&self, mut server_session: Stream, mut client_session: Stream) {
-        let mut upstream_buf = [0; 4096]; // Increased buffer size for HTTP headers
-        let mut downstream_buf = [0; 4096];
-        let timeout_duration = std::time::Duration::from_secs(60);
-        // (websocket, upstream_len, downstream_len, status)
+    /// Relays a connection and logs its lifecycle.
+    ///
+    /// An `OPEN` line is written when the connection starts and a `CLOSE` line
+    /// with the final byte counts (`IN`/`OUT`) and last status when it ends, on
+    /// every exit path. The API turns them into `conn_req`/`conn_res` records.
+    /// With frame counting on, WebSocket CLOSE lines also carry message and frame counts.
+    ///
+    /// A connection silent in both directions for the idle timeout is closed,
+    /// WebSockets use their own, longer one. With a ping interval, a WebSocket
+    /// silent for that long gets a ping written to the client; its pong is
+    /// relayed upstream as an unsolicited pong, which RFC 6455 allows, and
+    /// keeps the connection from idling out.
+    async fn duplex(&self, server_session: Stream, client_session: Stream) {
         let id = atomic_id();
-        let mut temp_record = (id, None, 0, 0, "N/A");
+        log::info!("[PXY] | ID:{}, TYPE:OPEN, CONN:TCP, SIZE:0, STAT:N/A, SRC:{}, DST:{}, COMMENT:{} |",
+            id,
+            self.proxy_source,
+            self.proxy_to._address,
+            self.request_id
+        );
+
+        // (id, websocket, upstream_len, downstream_len, status)
+        let mut temp_record = (id.clone(), None, 0, 0, "N/A");
+        // (bytes_in, bytes_out) over the whole connection
+        let mut totals = (0usize, 0usize);
+        // (client to upstream, upstream to client) frame parsers of a WebSocket connection
+        let mut frames: Option<(FrameParser, FrameParser)> = None;
+        let client = server_session
+            .get_socket_digest()
+            .and_then(|digest| digest.peer_addr().and_then(|addr| addr.as_inet()).map(|addr| addr.ip()));
+        let tracked = Connection::open(&id, &self.proxy_source, &self.proxy_to._address.to_string(), client, self.tls);
+        self.pump(server_session, client_session, &tracked, &mut temp_record, &mut totals, &mut frames).await;
+
+        let ws_fields = frames
+            .filter(|_| self.ws_frame_counts)
+            .map(|(client, upstream)| ws_frame::log_fields(&client.counts(), &upstream.counts()))
+            .unwrap_or_default();
+        log::info!("[PXY] | ID:{}, TYPE:CLOSE, CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{}, IN:{}, OUT:{}, {}COMMENT:{} |",
+            id,
+            if temp_record.1 == Some(true) { "WS" } else { "TCP" },
+            totals.0 + totals.1,
+            temp_record.4,
+            self.proxy_source,
+            self.proxy_to._address,
+            totals.0,
+            totals.1,
+            ws_fields,
+            self.request_id
+        );
+
+        if access_log::enabled() {
+            let conn = if temp_record.1 == Some(true) { "WS" } else { "TCP" };
+            access_log::record(&AccessEntry {
+                client,
+                request: &format!("{} {} {}", conn, self.proxy_source, self.proxy_to._address),
+                status: temp_record.4.parse().unwrap_or(0),
+                bytes: totals.1,
+                referer: None,
+                user_agent: None,
+            });
+        }
+    }
+
+    async fn pump(
+        &self,
+        mut server_session: Stream,
+        mut client_session: Stream,
+        tracked: &Tracked,
+        temp_record: &mut (String, Option<bool>, usize, usize, &'static str),
+        totals: &mut (usize, usize),
+        frames: &mut Option<(FrameParser, FrameParser)>,
+    ) {
+        let mut upstream_buf = vec![0; self.buffer_size];
+        let mut downstream_buf = vec![0; self.buffer_size];
+        // Last read from either side, and last read or ping for the ping interval
+        let mut last_read = Instant::now();
+        let mut last_ping = last_read;
 
         loop {
-            let downstream_read =
-                tokio::time::timeout(timeout_duration, server_session.read(&mut upstream_buf));
-            let upstream_read =
-                tokio::time::timeout(timeout_duration, client_session.read(&mut downstream_buf));
+            let websocket = temp_record.1 == Some(true);
+            let idle = if websocket { self.ws_idle_timeout } else { self.idle_timeout };
+            let idle_deadline = idle.map(|idle| last_read + idle);
+            let ping_deadline = self
+                .ws_ping_interval
+                .filter(|_| websocket)
+                .map(|interval| last_read.max(last_ping) + interval);
             let event: DuplexEvent;
 
             select! {
-                result = downstream_read => match result {
-                    Ok(Ok(n)) => event = DuplexEvent::DownstreamRead(n),
-                    Ok(Err(e)) => {
+                result = server_session.read(&mut upstream_buf) => match result {
+                    Ok(n) => event = DuplexEvent::DownstreamRead(n),
+                    Err(e) => {
                         log::debug!("Error reading from downstream: {}", e);
                         return;
                     },
-                    Err(_) => {
-                        log::debug!("Timeout reading from downstream");
-                        return;
-                    }
                 },
-                result = upstream_read => match result {
-                    Ok(Ok(n)) => event = DuplexEvent::UpstreamRead(n),
-                    Ok(Err(e)) => {
+                result = client_session.read(&mut downstream_buf) => match result {
+                    Ok(n) => event = DuplexEvent::UpstreamRead(n),
+                    Err(e) => {
                         log::debug!("Error reading from upstream: {}", e);
                         return;
                     },
-                    Err(_) => {
-                        log::debug!("Timeout reading from upstream");
-                        return;
-                    }
                 },
+                _ = sleep_until(idle_deadline) => {
+                    log::debug!(
+                        "{} connection idle for {}s, closing",
+                        if websocket { "WebSocket" } else { "TCP" },
+                        idle.map_or(0, |idle| idle.as_secs())
+                    );
+                    return;
+                },
+                _ = sleep_until(ping_deadline) => event = DuplexEvent::PingDue,
+            }
+            if !matches!(event, DuplexEvent::PingDue) {
+                last_read = Instant::now();
             }
             match event {
+                DuplexEvent::PingDue => {
+                    last_ping = Instant::now();
+                    // Only between two upstream frames, a ping inside one would corrupt it
+                    if !frames.as_ref().map_or(false, |(_, upstream)| upstream.at_frame_boundary()) {
+                        continue;
+                    }
+                    debug!("Pinging idle WebSocket client of connection {}", temp_record.0);
+                    if let Err(e) = server_session.write_all(&ws_frame::PING_FRAME).await {
+                        debug!("Error writing ping to downstream server: {}", e);
+                        return;
+                    }
+                    if let Err(e) = server_session.flush().await {
+                        debug!("Error flushing downstream server: {}", e);
+                        return;
+                    }
+                }
                 DuplexEvent::DownstreamRead(0) => {
-                    log::info!("[PXY] | ID:{}, TYPE:DOWNSTREAM[OFF], CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{} |", 
+                    log::info!("[PXY] | ID:{}, TYPE:DOWNSTREAM[OFF], CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{}, COMMENT:{} |", 
                         temp_record.0, 
                         {
                             if let Some(data) = temp_record.1 {
@@ -588,12 +686,13 @@ impl ProxyApp {
                         temp_record.3, 
                         temp_record.4,
                         self.proxy_source,
-                        self.proxy_to._address
+                        self.proxy_to._address,
+                        self.request_id
                     );
                     return;
                 }
                 DuplexEvent::UpstreamRead(0) => {
-                    log::info!("[PXY] | ID:{}, TYPE:UPSTREAM[OFF], CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{} |", 
+                    log::info!("[PXY] | ID:{}, TYPE:UPSTREAM[OFF], CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{}, COMMENT:{} |", 
                         temp_record.0, 
                         {
                             if let Some(data) = temp_record.1 {
@@ -609,7 +708,8 @@ impl ProxyApp {
                         temp_record.2, 
                         temp_record.4,
                         self.proxy_source,
-                        self.proxy_to._address
+                        self.proxy_to._address,
+                        self.request_id
                     );
                     return;
                 }
@@ -618,7 +718,9 @@ impl ProxyApp {
                     let (write_len, websocket, id) = self.rewrite_http_request(&mut upstream_buf, n);
 
                     temp_record.3 = write_len;
-                    log::info!("[PXY] | ID:{}, TYPE:DOWNSTREAM[ON], CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{} |", 
+                    totals.0 += write_len;
+                    tracked.add_in(write_len);
+                    log::info!("[PXY] | ID:{}, TYPE:DOWNSTREAM[ON], CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{}, COMMENT:{} |", 
                         {
                             if let Some(id) = id {
                                 if websocket {
@@ -651,7 +753,8 @@ impl ProxyApp {
                             }
                         }, 
                         self.proxy_source,
-                        self.proxy_to._address
+                        self.proxy_to._address,
+                        self.request_id
                     );
                     temp_record.1 = {
                         if let None = temp_record.1 {
@@ -660,10 +763,20 @@ impl ProxyApp {
                             temp_record.1
                         }
                     };
+                    if temp_record.1 == Some(true) {
+                        tracked.upgraded();
+                    }
                     if write_len == 0 {
                         debug!("Request rewrite failed, closing connection");
                         return; // Close connection on rewrite failure
                     }
+                    let parse_frames = self.ws_frame_counts || self.ws_ping_interval.is_some();
+                    if parse_frames && temp_record.1 == Some(true) {
+                        frames
+                            .get_or_insert_with(|| (FrameParser::client(), FrameParser::upstream()))
+                            .0
+                            .feed(&upstream_buf[..write_len]);
+                    }
                     if let Err(e) = client_session
                         .write_all(&upstream_buf[0..write_len])
                         .await {
@@ -677,7 +790,9 @@ impl ProxyApp {
                 }
                 DuplexEvent::UpstreamRead(n) => {
                     temp_record.2 = n;
-                    log::info!("[PXY] | ID:{}, TYPE:UPSTREAM[ON], CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{} |", 
+                    totals.1 += n;
+                    tracked.add_out(n);
+                    log::info!("[PXY] | ID:{}, TYPE:UPSTREAM[ON], CONN:{}, SIZE:{}, STAT:{}, SRC:{}, DST:{}, COMMENT:{} |", 
                         temp_record.0, 
                         {
                             if let Some(data) = temp_record.1 {
@@ -693,10 +808,14 @@ impl ProxyApp {
                         temp_record.2, 
                         temp_record.4,
                         self.proxy_source,
-                        self.proxy_to._address
+                        self.proxy_to._address,
+                        self.request_id
                     );
 
                     log::debug!("Incoming data from upstream: {}", n);
+                    if let Some((_, upstream)) = frames.as_mut() {
+                        upstream.feed(&downstream_buf[..n]);
+                    }
                      if let Err(e) = server_session
                         .write_all(&downstream_buf[0..n])
                         .await {
@@ -713,6 +832,14 @@ impl ProxyApp {
     }
 }
 
+/// Sleeps until `deadline`, forever without one.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
 #[async_trait]
 impl ServerApp for ProxyApp {
     async fn process_new(
@@ -720,10 +847,31 @@ impl ServerApp for ProxyApp {
         io: Stream,
         _shutdown: &ShutdownWatch,
     ) -> Option<Stream> {
+        // Over the limit the connection is closed before anything is connected upstream
+        let Some(_permit) = self.limit.try_acquire() else {
+            warn!(
+                "Proxy {} is at its limit of {} connections, closing a new connection",
+                self.proxy_source,
+                self.limit.max()
+            );
+            return None;
+        };
         let client_session = self.client_connector.new_stream(&self.proxy_to).await;
 
         match client_session {
             Ok(client_session) => {
+                // TCP options do not apply to Unix domain socket ends
+                let downstream_tcp = config::unix_socket_path(&self.proxy_source).is_none();
+                let upstream_tcp = self.proxy_to._address.as_inet().is_some();
+                let sockets = [
+                    ("downstream", io.id(), downstream_tcp),
+                    ("upstream", client_session.id(), upstream_tcp),
+                ];
+                for (side, fd, _) in sockets.into_iter().filter(|(_, _, tcp)| *tcp) {
+                    if let Err(e) = self.tcp_options.apply(fd) {
+                        warn!("Failed to set TCP options on {} socket: {}", side, e);
+                    }
+                }
                 self.duplex(io, client_session).await;
                 None
             }
@@ -734,3 +882,173 @@ impl ServerApp for ProxyApp {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pingora::protocols::l4::stream::Stream as L4Stream;
+    use tokio::net::{UnixListener, UnixStream};
+
+    #[tokio::test]
+    async fn test_round_trip_over_unix_sockets() {
+        config::RoutingData::ProxyRequestID.set("-");
+        let dir = std::env::temp_dir().join(format!("gwrs-uds-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let upstream_path = dir.join("upstream.sock");
+        let listen_path = dir.join("listen.sock");
+
+        // Echo upstream
+        let upstream = UnixListener::bind(&upstream_path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 64];
+            let n = stream.read(&mut buf).await.unwrap();
+            stream.write_all(&buf[..n]).await.unwrap();
+        });
+
+        let peer = BasicPeer::new_uds(&upstream_path).unwrap();
+        let listen = format!("unix:{}", listen_path.display());
+        let app = Arc::new(ProxyApp::new(
+            peer,
+            listen,
+            TcpOptions::default(),
+            config::DEFAULT_PROXY_BUFFER_SIZE,
+            false,
+        ));
+
+        let listener = UnixListener::bind(&listen_path).unwrap();
+        let proxy = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (_tx, shutdown) = tokio::sync::watch::channel(false);
+            let io: Stream = Box::new(L4Stream::from(stream));
+            app.process_new(io, &shutdown).await;
+        });
+
+        let mut client = UnixStream::connect(&listen_path).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        tokio::time::timeout(std::time::Duration::from_secs(5), client.read_exact(&mut buf))
+            .await
+            .expect("no reply through the proxy")
+            .unwrap();
+        assert_eq!(&buf, b"ping");
+
+        drop(client);
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(5), proxy).await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    const UPGRADE: &[u8] = b"GET /ws HTTP/1.1\r\nHost: x\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n";
+    const SWITCHED: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n";
+
+    /// Proxies one connection to an upstream that answers the upgrade and then
+    /// echoes, with a 100ms TCP idle timeout. Returns the client end.
+    async fn websocket_through_proxy(
+        name: &str,
+        ws_ping_interval: Option<Duration>,
+    ) -> (UnixStream, std::path::PathBuf) {
+        config::RoutingData::ProxyRequestID.set("-");
+        let dir = std::env::temp_dir().join(format!("gwrs-ws-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let upstream_path = dir.join("upstream.sock");
+        let listen_path = dir.join("listen.sock");
+
+        let upstream = UnixListener::bind(&upstream_path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 256];
+            stream.read(&mut buf).await.unwrap();
+            stream.write_all(SWITCHED).await.unwrap();
+            loop {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => stream.write_all(&buf[..n]).await.unwrap(),
+                }
+            }
+        });
+
+        let peer = BasicPeer::new_uds(&upstream_path).unwrap();
+        let mut app = ProxyApp::new(
+            peer,
+            format!("unix:{}", listen_path.display()),
+            TcpOptions::default(),
+            config::DEFAULT_PROXY_BUFFER_SIZE,
+            false,
+        );
+        app.idle_timeout = Some(Duration::from_millis(100));
+        app.ws_idle_timeout = Some(Duration::from_secs(5));
+        app.ws_ping_interval = ws_ping_interval;
+        // The upgrade request must match a rule to be forwarded
+        app.seen_config_version.store(config::gateway_config_version(), Ordering::Release);
+        app.path_rewrites.write().unwrap().push(RewriteRule {
+            pattern: Regex::new("^/ws$").unwrap(),
+            replacement: "/ws".to_string(),
+        });
+        let app = Arc::new(app);
+
+        let listener = UnixListener::bind(&listen_path).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (_tx, shutdown) = tokio::sync::watch::channel(false);
+            let io: Stream = Box::new(L4Stream::from(stream));
+            app.process_new(io, &shutdown).await;
+        });
+
+        let mut client = UnixStream::connect(&listen_path).await.unwrap();
+        client.write_all(UPGRADE).await.unwrap();
+        let mut switched = vec![0u8; SWITCHED.len()];
+        tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut switched))
+            .await
+            .expect("no upgrade response through the proxy")
+            .unwrap();
+        assert_eq!(switched, SWITCHED);
+        (client, dir)
+    }
+
+    #[tokio::test]
+    async fn test_idle_websocket_outlives_tcp_idle_timeout() {
+        let (mut client, dir) = websocket_through_proxy("idle", None).await;
+
+        // Well past the TCP idle timeout, the WebSocket one still has seconds left
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let text = [0x81, 0x82, 1, 2, 3, 4, b'h' ^ 1, b'i' ^ 2];
+        client.write_all(&text).await.unwrap();
+        let mut echoed = [0u8; 8];
+        tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut echoed))
+            .await
+            .expect("WebSocket was closed while idle")
+            .unwrap();
+        assert_eq!(echoed, text);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_idle_websocket_client_is_pinged() {
+        let (mut client, dir) = websocket_through_proxy("ping", Some(Duration::from_millis(50))).await;
+
+        let mut ping = [0u8; 2];
+        tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut ping))
+            .await
+            .expect("no ping while idle")
+            .unwrap();
+        assert_eq!(ping, ws_frame::PING_FRAME);
+
+        // The pong is relayed upstream, which echoes it back
+        let pong = [0x8A, 0x80, 1, 2, 3, 4];
+        client.write_all(&pong).await.unwrap();
+        let mut frame = [0u8; 2];
+        loop {
+            tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut frame))
+                .await
+                .expect("connection closed")
+                .unwrap();
+            if frame != ws_frame::PING_FRAME {
+                break;
+            }
+        }
+        assert_eq!(frame, pong[..2]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,318 @@
+//! # Response Redaction
+//!
+//! JSON field redaction of upstream responses for gateway nodes with
+//! `redact_fields` set. Each field is a dotted path from the document root,
+//! like `user.ssn` or `auth.*.token`: `*` matches every key of an object or
+//! every element of an array. Other segments descend into arrays, so
+//! `items.secret` and `items.*.secret` both cover `secret` of every element
+//! of `items`. Matching fields are masked with `"[REDACTED]"`, or
+//! removed when the node's `redact_mode` is `remove`.
+//!
+//! Only `application/json` and `+json` responses are redacted. Their body is
+//! buffered up to `GWRS_REDACT_MAX_BODY` bytes (default 1 MiB) and rewritten
+//! once complete, the key order of objects may change. Responses are never
+//! passed on unredacted: one known to be larger, or still encoded although
+//! the upstream was asked for `identity`, is answered with 502, and one that
+//! outgrows the buffer is cut off. A body that is not valid JSON has no
+//! fields to redact and is passed on unchanged.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http::HeaderMap;
+use serde_json::Value;
+
+/// Replacement of masked values
+pub const MASK: &str = "[REDACTED]";
+
+/// What happens to a matching field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactMode {
+    /// The value is replaced with [`MASK`]
+    Mask,
+    /// The field is removed from its object
+    Remove,
+}
+
+impl RedactMode {
+    /// Parses a node's `redact_mode`, `mask` or `remove`, case insensitive.
+    ///
+    /// An empty value masks.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "mask" => Ok(RedactMode::Mask),
+            "remove" => Ok(RedactMode::Remove),
+            _ => Err(format!("Invalid redact_mode '{}', expected mask or remove", value)),
+        }
+    }
+}
+
+/// The compiled field paths of one gateway node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redaction {
+    paths: Vec<Vec<String>>,
+    mode: RedactMode,
+}
+
+impl Redaction {
+    /// Compiles the fields and mode of a gateway node.
+    ///
+    /// Returns `Ok(None)` without fields, nothing is redacted then.
+    pub fn parse(fields: &[String], mode: Option<&str>) -> Result<Option<Self>, String> {
+        let mode = RedactMode::parse(mode.unwrap_or_default())?;
+        let paths = fields
+            .iter()
+            .map(|field| field.trim())
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                let path: Vec<String> = field.split('.').map(str::to_string).collect();
+                if path.iter().any(String::is_empty) {
+                    return Err(format!("Invalid redact field '{}', empty path segment", field));
+                }
+                Ok(path)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((!paths.is_empty()).then_some(Self { paths, mode }))
+    }
+
+    /// Redacts every configured field of `value` in place.
+    pub fn apply(&self, value: &mut Value) {
+        for path in &self.paths {
+            redact_path(value, path, self.mode);
+        }
+    }
+
+    /// Redacted form of a JSON body, `None` when it isn't valid JSON.
+    pub fn redact_body(&self, body: &[u8]) -> Option<Vec<u8>> {
+        let mut value = serde_json::from_slice::<Value>(body).ok()?;
+        self.apply(&mut value);
+        serde_json::to_vec(&value).ok()
+    }
+}
+
+fn redact_path(value: &mut Value, path: &[String], mode: RedactMode) {
+    let Some((segment, rest)) = path.split_first() else {
+        return;
+    };
+    match value {
+        // `*` takes the place of the element, `items.*` redacts the elements themselves
+        Value::Array(items) if segment == "*" => {
+            if !rest.is_empty() {
+                for item in items {
+                    redact_path(item, rest, mode);
+                }
+                return;
+            }
+            match mode {
+                RedactMode::Mask => items.fill(Value::String(MASK.to_string())),
+                RedactMode::Remove => items.clear(),
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact_path(item, path, mode);
+            }
+        }
+        Value::Object(object) if rest.is_empty() => {
+            let keys: Vec<String> = object
+                .keys()
+                .filter(|key| segment == "*" || *key == segment)
+                .cloned()
+                .collect();
+            for key in keys {
+                match mode {
+                    RedactMode::Mask => {
+                        object.insert(key, Value::String(MASK.to_string()));
+                    }
+                    RedactMode::Remove => {
+                        object.remove(&key);
+                    }
+                }
+            }
+        }
+        Value::Object(object) => {
+            for (_, child) in object.iter_mut().filter(|(key, _)| segment == "*" || *key == segment) {
+                redact_path(child, rest, mode);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether a response carries a JSON body by its `Content-Type`.
+pub fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    mime == "application/json" || mime.ends_with("+json")
+}
+
+/// Whether the body is encoded, so its fields can't be read.
+pub fn is_encoded(headers: &HeaderMap) -> bool {
+    headers
+        .get(http::header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |encoding| !encoding.trim().eq_ignore_ascii_case("identity"))
+}
+
+/// Body length announced by the upstream, if any.
+pub fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Collects a response body until it is complete, then redacts it
+#[derive(Debug)]
+pub struct Redactor {
+    redaction: Arc<Redaction>,
+    body: Vec<u8>,
+    max: usize,
+}
+
+impl Redactor {
+    pub fn new(redaction: Arc<Redaction>, max: usize) -> Self {
+        Self {
+            redaction,
+            body: Vec::new(),
+            max,
+        }
+    }
+
+    /// Buffers a chunk, failing once the body outgrows the limit.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), String> {
+        if self.body.len() + chunk.len() > self.max {
+            return Err(format!("response body exceeds {} bytes", self.max));
+        }
+        self.body.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    /// The redacted body, or the buffered one unchanged when it isn't JSON.
+    pub fn finish(&mut self) -> Bytes {
+        let body = std::mem::take(&mut self.body);
+        match self.redaction.redact_body(&body) {
+            Some(redacted) => Bytes::from(redacted),
+            None => {
+                log::warn!("Response body of a redacted route is not valid JSON, passing it on unchanged");
+                Bytes::from(body)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn redaction(fields: &[&str], mode: Option<&str>) -> Redaction {
+        let fields: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+        Redaction::parse(&fields, mode).unwrap().expect("fields configured")
+    }
+
+    #[test]
+    fn test_parse_redaction() {
+        assert_eq!(Redaction::parse(&[], None), Ok(None));
+        assert_eq!(Redaction::parse(&[" ".to_string()], Some("remove")), Ok(None));
+        assert!(Redaction::parse(&["user..ssn".to_string()], None).is_err());
+        assert!(Redaction::parse(&["ssn".to_string()], Some("hash")).is_err());
+        assert_eq!(RedactMode::parse("REMOVE"), Ok(RedactMode::Remove));
+        assert_eq!(RedactMode::parse(""), Ok(RedactMode::Mask));
+    }
+
+    #[test]
+    fn test_mask_nested_fields_and_arrays() {
+        let mut value = json!({
+            "user": {"name": "ann", "ssn": "123-45-6789"},
+            "items": [{"id": 1, "token": "a"}, {"id": 2}, {"id": 3, "token": "c"}],
+            "auth": {"github": {"token": "x", "scope": "repo"}, "gitlab": {"token": "y"}},
+            "ssn": 42
+        });
+        redaction(&["user.ssn", "items.token", "auth.*.token", "missing.field"], None).apply(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "user": {"name": "ann", "ssn": MASK},
+                "items": [{"id": 1, "token": MASK}, {"id": 2}, {"id": 3, "token": MASK}],
+                "auth": {"github": {"token": MASK, "scope": "repo"}, "gitlab": {"token": MASK}},
+                "ssn": 42
+            })
+        );
+    }
+
+    #[test]
+    fn test_wildcard_matches_array_elements() {
+        // The example of the API docs
+        let mut value = json!({"items": [{"id": 1, "token": "a"}, {"id": 2, "meta": {"token": "b"}}]});
+        redaction(&["items.*.token"], None).apply(&mut value);
+        assert_eq!(
+            value,
+            json!({"items": [{"id": 1, "token": MASK}, {"id": 2, "meta": {"token": "b"}}]})
+        );
+
+        let mut value = json!({"keys": ["k1", "k2"], "nested": [["a"], ["b"]]});
+        redaction(&["keys.*"], None).apply(&mut value);
+        redaction(&["nested.*"], Some("remove")).apply(&mut value);
+        assert_eq!(value, json!({"keys": [MASK, MASK], "nested": []}));
+    }
+
+    #[test]
+    fn test_remove_fields_of_a_top_level_array() {
+        let mut value = json!([{"ssn": "1", "name": "a"}, {"ssn": "2"}, "plain"]);
+        redaction(&["ssn"], Some("remove")).apply(&mut value);
+        assert_eq!(value, json!([{"name": "a"}, {}, "plain"]));
+
+        let mut value = json!({"secrets": {"a": 1, "b": 2}, "keep": true});
+        redaction(&["secrets.*"], Some("remove")).apply(&mut value);
+        assert_eq!(value, json!({"secrets": {}, "keep": true}));
+    }
+
+    #[test]
+    fn test_redactor_buffers_up_to_the_limit() {
+        let redaction = Arc::new(redaction(&["token"], None));
+        let mut redactor = Redactor::new(Arc::clone(&redaction), 64);
+        redactor.push(br#"{"token":"#).unwrap();
+        redactor.push(br#""abc","n":1}"#).unwrap();
+        let redacted: Value = serde_json::from_slice(&redactor.finish()).unwrap();
+        assert_eq!(redacted, json!({"token": MASK, "n": 1}));
+
+        let mut redactor = Redactor::new(Arc::clone(&redaction), 64);
+        redactor.push(b"not json").unwrap();
+        assert_eq!(&redactor.finish()[..], b"not json");
+
+        let mut redactor = Redactor::new(redaction, 8);
+        assert!(redactor.push(b"0123456789").is_err());
+    }
+
+    #[test]
+    fn test_json_content_types() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_json(&headers));
+        headers.insert(http::header::CONTENT_TYPE, "application/json; charset=utf-8".parse().unwrap());
+        assert!(is_json(&headers));
+        headers.insert(http::header::CONTENT_TYPE, "application/problem+json".parse().unwrap());
+        assert!(is_json(&headers));
+        headers.insert(http::header::CONTENT_TYPE, "text/html".parse().unwrap());
+        assert!(!is_json(&headers));
+
+        assert!(!is_encoded(&headers));
+        headers.insert(http::header::CONTENT_ENCODING, "identity".parse().unwrap());
+        assert!(!is_encoded(&headers));
+        headers.insert(http::header::CONTENT_ENCODING, "gzip".parse().unwrap());
+        assert!(is_encoded(&headers));
+
+        headers.insert(http::header::CONTENT_LENGTH, "512".parse().unwrap());
+        assert_eq!(content_length(&headers), Some(512));
+    }
+}
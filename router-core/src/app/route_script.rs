@@ -0,0 +1,256 @@
+//! # Routing Scripts
+//!
+//! Rhai scripts of gateway nodes with `route_script` set. The script of a rule
+//! runs for every request the rule matches and sees it as the `request` map:
+//! `method`, `path`, `query` and `host` strings, `client_ip` (`()` when the
+//! peer has no IP) and `headers`, a map of lowercase names to values, repeated
+//! headers joined with `, `. The value of the script decides:
+//!
+//! * `()` or `true` - the rule is used as configured
+//! * `false` - the rule is passed over, the next matching rule is tried
+//! * `"ip:port"` - the request is forwarded to that target instead of the
+//!   rule's, with the rule's path rewrite
+//!
+//! ```text
+//! if request.headers["x-canary"] == "1" { "10.0.0.7:8080" }
+//! else if request.method == "DELETE" { false }
+//! ```
+//!
+//! Scripts run sandboxed: there is no file, network or module access, `eval`
+//! is disabled and `print`/`debug` go to the debug log. A run is limited to
+//! `GWRS_ROUTE_SCRIPT_MAX_OPERATIONS` operations (default 100000) and
+//! `GWRS_ROUTE_SCRIPT_TIMEOUT_MS` milliseconds (default 10), strings, arrays,
+//! maps and call depth to fixed sizes. A script that fails, runs out of budget
+//! or returns anything else is logged and the rule is used as configured.
+
+use std::cell::Cell;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use http::HeaderMap;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::config;
+
+/// Longest string a script may build, in bytes
+const MAX_STRING_SIZE: usize = 64 * 1024;
+/// Most elements of an array a script may build
+const MAX_ARRAY_SIZE: usize = 1024;
+/// Most entries of a map a script may build
+const MAX_MAP_SIZE: usize = 1024;
+/// Deepest function call nesting of a script
+const MAX_CALL_LEVELS: usize = 16;
+/// Operations between two checks of the deadline
+const DEADLINE_CHECK_INTERVAL: u64 = 256;
+
+thread_local! {
+    // Deadline of the script running on this thread, scripts never yield
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Engine with the sandbox of every routing script, without run limits.
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.set_max_map_size(MAX_MAP_SIZE);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_expr_depths(64, 32);
+    engine.on_print(|text| log::debug!("Route script: {}", text));
+    engine.on_debug(|text, _, pos| log::debug!("Route script {}: {}", pos, text));
+    engine
+}
+
+/// A compiled routing script of one gateway node
+#[derive(Debug, Clone)]
+pub struct RouteScript {
+    ast: Arc<AST>,
+}
+
+impl RouteScript {
+    /// Compiles a node's `route_script`.
+    ///
+    /// Returns `Ok(None)` for a blank script, the rule then has none.
+    pub fn compile(source: &str) -> Result<Option<Self>, String> {
+        if source.trim().is_empty() {
+            return Ok(None);
+        }
+        sandboxed_engine()
+            .compile(source)
+            .map(|ast| Some(Self { ast: Arc::new(ast) }))
+            .map_err(|e| format!("Invalid route script: {}", e))
+    }
+}
+
+/// What a script decided for its rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The rule routes the request as configured
+    Use,
+    /// The rule is passed over
+    Skip,
+    /// The rule routes the request to this target instead of its own
+    Target(SocketAddr),
+}
+
+impl Decision {
+    fn from_value(value: Dynamic) -> Result<Self, String> {
+        if value.is_unit() {
+            return Ok(Decision::Use);
+        }
+        if let Ok(use_rule) = value.as_bool() {
+            return Ok(if use_rule { Decision::Use } else { Decision::Skip });
+        }
+        match value.into_string() {
+            Ok(target) => target
+                .trim()
+                .parse::<SocketAddr>()
+                .map(Decision::Target)
+                .map_err(|_| format!("target '{}' is not an ip:port address", target)),
+            Err(kind) => Err(format!("unexpected {} result", kind)),
+        }
+    }
+}
+
+/// Runs routing scripts under the limits of one gateway listener
+pub struct ScriptRunner {
+    engine: Engine,
+    timeout: Duration,
+}
+
+impl ScriptRunner {
+    pub fn new(max_operations: u64, timeout: Duration) -> Self {
+        let mut engine = sandboxed_engine();
+        engine.set_max_operations(max_operations);
+        engine.on_progress(|operations| {
+            if operations % DEADLINE_CHECK_INTERVAL != 0 {
+                return None;
+            }
+            let expired = DEADLINE.with(|deadline| deadline.get().map_or(false, |at| Instant::now() >= at));
+            expired.then(|| Dynamic::from("timeout"))
+        });
+        Self { engine, timeout }
+    }
+
+    /// Runner with the limits set in the environment.
+    pub fn from_config() -> Self {
+        Self::new(config::route_script_max_operations(), config::route_script_timeout())
+    }
+
+    /// Runs `script` for a request, see [`request_map`].
+    pub fn decide(&self, script: &RouteScript, request: Map) -> Result<Decision, String> {
+        let mut scope = Scope::new();
+        scope.push_constant("request", request);
+        DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + self.timeout)));
+        let result = self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &script.ast);
+        DEADLINE.with(|deadline| deadline.set(None));
+        match result {
+            Ok(value) => Decision::from_value(value),
+            Err(e) if matches!(*e, EvalAltResult::ErrorTerminated(..)) => {
+                Err(format!("ran longer than {}ms", self.timeout.as_millis()))
+            }
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// The `request` map a script sees.
+pub fn request_map(
+    method: &str,
+    path: &str,
+    query: Option<&str>,
+    host: &str,
+    headers: &HeaderMap,
+    client_ip: Option<IpAddr>,
+) -> Map {
+    let mut header_map = Map::new();
+    for name in headers.keys() {
+        let value = headers
+            .get_all(name)
+            .iter()
+            .map(|value| String::from_utf8_lossy(value.as_bytes()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        header_map.insert(name.as_str().into(), value.into());
+    }
+
+    let mut request = Map::new();
+    request.insert("method".into(), method.to_string().into());
+    request.insert("path".into(), path.to_string().into());
+    request.insert("query".into(), query.unwrap_or_default().to_string().into());
+    request.insert("host".into(), host.to_string().into());
+    request.insert(
+        "client_ip".into(),
+        client_ip.map_or(Dynamic::UNIT, |ip| ip.to_string().into()),
+    );
+    request.insert("headers".into(), Dynamic::from_map(header_map));
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str) -> Map {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-canary", "1".parse().unwrap());
+        headers.append("accept", "text/html".parse().unwrap());
+        headers.append("accept", "application/json".parse().unwrap());
+        request_map(method, path, Some("page=2"), "example.com", &headers, "10.1.2.3".parse().ok())
+    }
+
+    fn decide(source: &str, request: Map) -> Result<Decision, String> {
+        let script = RouteScript::compile(source).unwrap().expect("script configured");
+        ScriptRunner::new(10_000, Duration::from_millis(50)).decide(&script, request)
+    }
+
+    #[test]
+    fn test_compile_route_script() {
+        assert!(RouteScript::compile("  ").unwrap().is_none());
+        assert!(RouteScript::compile("request.path == \"/\"").unwrap().is_some());
+        assert!(RouteScript::compile("if (").is_err());
+        assert!(RouteScript::compile("eval(\"1\")").is_err());
+    }
+
+    #[test]
+    fn test_script_decisions() {
+        assert_eq!(decide("()", request("GET", "/")), Ok(Decision::Use));
+        assert_eq!(decide("request.method != \"DELETE\"", request("DELETE", "/")), Ok(Decision::Skip));
+        assert_eq!(
+            decide("if request.headers[\"x-canary\"] == \"1\" { \"10.0.0.7:8080\" }", request("GET", "/")),
+            Ok(Decision::Target("10.0.0.7:8080".parse().unwrap()))
+        );
+        assert_eq!(
+            decide(
+                "request.client_ip == \"10.1.2.3\" && request.query == \"page=2\" && request.host == \"example.com\"",
+                request("GET", "/users")
+            ),
+            Ok(Decision::Use)
+        );
+        assert_eq!(
+            decide("request.headers.accept == \"text/html, application/json\"", request("GET", "/")),
+            Ok(Decision::Use)
+        );
+        assert!(decide("\"backend:80\"", request("GET", "/")).is_err());
+        assert!(decide("42", request("GET", "/")).is_err());
+    }
+
+    #[test]
+    fn test_script_limits() {
+        let endless = "let n = 0; loop { n += 1; }";
+        // The operation budget ends the loop first
+        assert!(decide(endless, request("GET", "/")).is_err());
+
+        let script = RouteScript::compile(endless).unwrap().unwrap();
+        let runner = ScriptRunner::new(u64::MAX, Duration::from_millis(5));
+        let started = Instant::now();
+        assert_eq!(runner.decide(&script, request("GET", "/")), Err("ran longer than 5ms".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        assert!(decide("let s = \"x\"; loop { s += s; }", request("GET", "/")).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! # Route Trace
+//!
+//! The routing decision of a single gateway request, for troubleshooting in
+//! production without raising the log levels. With `GWRS_GATEWAY_TRACE_TOKEN`
+//! set, a request carrying `X-GWRS-Trace: <token>` is traced: the rules
+//! evaluated in order and what became of each, the route cache lookup, the
+//! rewritten path and the chosen target. The trace is returned in the
+//! `X-GWRS-Trace` response header and logged as a `[GWX]` line of `TYPE:TRACE`
+//! with the request's `ID`.
+//!
+//! Without the token nothing is traced, so arbitrary clients can't turn it on,
+//! and the header of a traced request is never forwarded upstream.
+//!
+//! A trace reads like
+//! `cache=miss; rules=g1:no-match g2:passed g3:used; path=/v2/users?page=2; target=10.0.0.5:8080`,
+//! where `passed` is a matching rule its routing script passed over. Rules after
+//! the used one aren't evaluated and aren't listed.
+
+use http::header::HeaderValue;
+use pingora::http::RequestHeader;
+
+use crate::system::prottp::token_matches;
+
+/// Request header asking for a trace and response header carrying it
+pub const TRACE_HEADER: &str = "x-gwrs-trace";
+
+/// Longest trace returned in the response header, the log line has all of it
+const MAX_HEADER_LEN: usize = 4096;
+
+/// Whether `request` asks for a trace with the configured `token`.
+pub fn requested(request: &RequestHeader, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return false;
+    };
+    request
+        .headers
+        .get(TRACE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|given| token_matches(given.trim(), token))
+}
+
+/// What became of one rule while routing a traced request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The pattern didn't match the path
+    NoMatch,
+    /// The pattern matched, the rule's routing script passed it over
+    Passed,
+    /// The rule routed the request
+    Used,
+}
+
+impl Verdict {
+    /// Name of the verdict in a trace
+    pub fn name(self) -> &'static str {
+        match self {
+            Verdict::NoMatch => "no-match",
+            Verdict::Passed => "passed",
+            Verdict::Used => "used",
+        }
+    }
+}
+
+/// One rule evaluated for a traced request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleStep {
+    /// Id of the rule, the gateway it was configured as
+    pub id: String,
+    pub verdict: Verdict,
+}
+
+/// Routing decision of one traced request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteTrace {
+    /// Route cache lookup, `None` when routing ended before it
+    pub cache_hit: Option<bool>,
+    /// Rules evaluated, in evaluation order up to the one used
+    pub rules: Vec<RuleStep>,
+    /// Path and query forwarded upstream, `None` when nothing was rewritten
+    pub rewritten: Option<String>,
+    /// Where the request went, a target address or `static`
+    pub target: Option<String>,
+}
+
+impl RouteTrace {
+    /// The trace as one line, `-` for steps that didn't happen.
+    ///
+    /// `|` is escaped, it separates the fields of the log line.
+    pub fn render(&self) -> String {
+        let cache = match self.cache_hit {
+            Some(true) => "hit",
+            Some(false) => "miss",
+            None => "-",
+        };
+        let rules = if self.rules.is_empty() {
+            "-".to_string()
+        } else {
+            self.rules
+                .iter()
+                .map(|step| format!("{}:{}", step.id, step.verdict.name()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        format!(
+            "cache={}; rules={}; path={}; target={}",
+            cache,
+            rules,
+            self.rewritten.as_deref().unwrap_or("-"),
+            self.target.as_deref().unwrap_or("-")
+        )
+        .replace('|', "%7C")
+    }
+
+    /// The trace as a response header value.
+    ///
+    /// Characters a header can't carry are replaced by `?` and a trace longer
+    /// than 4 KiB is cut, ending in `...`.
+    pub fn header_value(&self) -> HeaderValue {
+        let mut value: String = self
+            .render()
+            .chars()
+            .map(|c| if c == ' ' || c.is_ascii_graphic() { c } else { '?' })
+            .collect();
+        if value.len() > MAX_HEADER_LEN {
+            value.truncate(MAX_HEADER_LEN - 3);
+            value.push_str("...");
+        }
+        HeaderValue::from_str(&value).unwrap_or_else(|_| HeaderValue::from_static("-"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traced(value: &str) -> RequestHeader {
+        let mut request = RequestHeader::build("GET", b"/api/users", None).unwrap();
+        request.insert_header(TRACE_HEADER, value).unwrap();
+        request
+    }
+
+    #[test]
+    fn test_trace_needs_the_token() {
+        assert!(requested(&traced("s3cret"), Some("s3cret")));
+        assert!(requested(&traced(" s3cret "), Some("s3cret")));
+        assert!(!requested(&traced("1"), Some("s3cret")));
+        // Without a configured token no request is traced
+        assert!(!requested(&traced("1"), None));
+        let plain = RequestHeader::build("GET", b"/api/users", None).unwrap();
+        assert!(!requested(&plain, Some("s3cret")));
+    }
+
+    #[test]
+    fn test_render() {
+        let trace = RouteTrace {
+            cache_hit: Some(false),
+            rules: vec![
+                RuleStep { id: "g1".to_string(), verdict: Verdict::NoMatch },
+                RuleStep { id: "g2".to_string(), verdict: Verdict::Used },
+            ],
+            rewritten: Some("/v2/users?page=2".to_string()),
+            target: Some("10.0.0.5:8080".to_string()),
+        };
+        assert_eq!(
+            trace.render(),
+            "cache=miss; rules=g1:no-match g2:used; path=/v2/users?page=2; target=10.0.0.5:8080"
+        );
+        assert_eq!(RouteTrace::default().render(), "cache=-; rules=-; path=-; target=-");
+    }
+
+    #[test]
+    fn test_header_value_is_bounded() {
+        let trace = RouteTrace {
+            rewritten: Some("/caf\u{e9}|x".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(trace.header_value(), "cache=-; rules=-; path=/caf?%7Cx; target=-");
+
+        let long = RouteTrace {
+            rules: (0..1000)
+                .map(|i| RuleStep { id: format!("rule-{}", i), verdict: Verdict::NoMatch })
+                .collect(),
+            ..Default::default()
+        };
+        let value = long.header_value();
+        assert_eq!(value.len(), MAX_HEADER_LEN);
+        assert!(value.to_str().unwrap().ends_with("..."));
+    }
+}
@@ -0,0 +1,172 @@
+//! # Traffic Splitting
+//!
+//! Percentage based canary and A/B routing of gateway rules with `split` set.
+//! Each entry sends `weight` percent of the rule's requests to its `target`,
+//! the rest of 100 keeps going to the target of the rule's gateway node, so
+//! `split: [{target: "10.0.0.9:8080", weight: 5}]` is a 5% canary.
+//!
+//! Without `sticky` every request draws its variant anew. With it a client
+//! always sees the same variant: `ip` hashes the client address together with
+//! the rule id, `cookie` also names the variant in the affinity cookie so a
+//! client keeps it when its address changes. On a split rule `sticky` applies
+//! to the variants, the path is not spread over other gateway nodes.
+//!
+//! The chosen variant is logged as `VARIANT:<target>` on the `[GWX]` request
+//! and response lines, `VARIANT:default` for the node's own target, so error
+//! rates can be compared per variant. Split routes are never cached.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::app::affinity;
+use crate::config::SplitTarget;
+
+/// Variant name of the gateway node's own target
+pub const DEFAULT_VARIANT: &str = "default";
+
+/// Shares are percentages, buckets run from 0 to 99
+const BUCKETS: u64 = 100;
+
+/// One target of a split rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    /// Target as configured, logged as the variant's name
+    pub name: String,
+    /// Resolved address requests of the variant are sent to
+    pub address: String,
+    /// Percent of the rule's requests
+    pub weight: u32,
+}
+
+/// The variants of one rule, their weights add up to at most 100
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Split {
+    variants: Vec<Variant>,
+}
+
+impl Split {
+    /// Compiles a rule's `split`, resolving each target with `resolve`.
+    ///
+    /// Entries weighing `0` are paused and left out. Returns `Ok(None)` when
+    /// no entry is left, every request goes to the node's target then.
+    pub fn parse(
+        targets: &[SplitTarget],
+        resolve: impl Fn(&str) -> Option<SocketAddr>,
+    ) -> Result<Option<Self>, String> {
+        let mut variants: Vec<Variant> = Vec::new();
+        for target in targets.iter().filter(|target| target.weight > 0) {
+            let name = target.target.trim();
+            let address = resolve(name)
+                .ok_or_else(|| format!("Unable to resolve split target '{}'", name))?
+                .to_string();
+            if variants.iter().any(|variant| variant.address == address) {
+                return Err(format!("Split target '{}' is listed twice", name));
+            }
+            variants.push(Variant {
+                name: name.to_string(),
+                address,
+                weight: target.weight,
+            });
+        }
+        let total: u64 = variants.iter().map(|variant| u64::from(variant.weight)).sum();
+        if total > BUCKETS {
+            return Err(format!("Split weights add up to {}, at most 100 percent", total));
+        }
+        Ok((!variants.is_empty()).then_some(Self { variants }))
+    }
+
+    /// Variant owning `bucket` (0 to 99), `None` for the node's own target.
+    pub fn pick(&self, bucket: u64) -> Option<&Variant> {
+        let mut upper = 0;
+        self.variants.iter().find(|variant| {
+            upper += u64::from(variant.weight);
+            bucket % BUCKETS < upper
+        })
+    }
+
+    /// Variant named by an affinity cookie key, see [`affinity::backend_key`].
+    pub fn find(&self, key: &str) -> Option<&Variant> {
+        self.variants
+            .iter()
+            .find(|variant| affinity::backend_key(&variant.address) == key)
+    }
+}
+
+/// Bucket of a client on a rule, the same on every request, restart and upgrade.
+///
+/// The rule id is hashed in, so a client isn't in the canary of every rule at once.
+pub fn client_bucket(client: IpAddr, rule_id: &str) -> u64 {
+    let mut hasher = affinity::StableHasher::default();
+    affinity::write_client(&mut hasher, Some(client));
+    hasher.write(rule_id.as_bytes());
+    hasher.finish() % BUCKETS
+}
+
+/// Bucket of a request drawn at random.
+pub fn random_bucket() -> u64 {
+    static DRAWS: AtomicU64 = AtomicU64::new(0);
+    RandomState::new().hash_one(DRAWS.fetch_add(1, Ordering::Relaxed)) % BUCKETS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(target: &str, weight: u32) -> SplitTarget {
+        SplitTarget {
+            target: target.to_string(),
+            weight,
+        }
+    }
+
+    fn resolve(target: &str) -> Option<SocketAddr> {
+        target.parse().ok()
+    }
+
+    #[test]
+    fn test_parse_split() {
+        assert_eq!(Split::parse(&[], resolve), Ok(None));
+        assert_eq!(Split::parse(&[target("10.0.0.9:8080", 0)], resolve), Ok(None));
+        assert!(Split::parse(&[target("canary", 5)], resolve).is_err());
+        assert!(Split::parse(&[target("10.0.0.9:8080", 60), target("10.0.0.10:8080", 41)], resolve).is_err());
+        assert!(Split::parse(&[target("10.0.0.9:8080", 5), target(" 10.0.0.9:8080", 5)], resolve).is_err());
+
+        let split = Split::parse(&[target(" 10.0.0.9:8080 ", 5)], resolve).unwrap().unwrap();
+        assert_eq!(split.variants[0].name, "10.0.0.9:8080");
+        assert_eq!(split.find(&affinity::backend_key("10.0.0.9:8080")), Some(&split.variants[0]));
+        assert_eq!(split.find(&affinity::backend_key("10.0.0.1:8080")), None);
+    }
+
+    #[test]
+    fn test_pick_by_percentage() {
+        let split = Split::parse(&[target("10.0.0.9:8080", 10), target("10.0.0.10:8080", 30)], resolve)
+            .unwrap()
+            .unwrap();
+        let mut counts = [0; 3];
+        for bucket in 0..BUCKETS {
+            match split.pick(bucket).map(|variant| variant.name.as_str()) {
+                Some("10.0.0.9:8080") => counts[0] += 1,
+                Some(_) => counts[1] += 1,
+                None => counts[2] += 1,
+            }
+        }
+        assert_eq!(counts, [10, 30, 60]);
+    }
+
+    #[test]
+    fn test_buckets() {
+        let client: IpAddr = "192.168.1.20".parse().unwrap();
+        assert_eq!(client_bucket(client, "rule-1"), client_bucket(client, "rule-1"));
+        assert!(client_bucket(client, "rule-1") < BUCKETS);
+        // Sticky clients keep their variant across upgrades, the bucket must not change
+        assert_eq!(client_bucket(IpAddr::from([10, 1, 0, 1]), "r1"), 91);
+        assert_eq!(client_bucket(IpAddr::from([10, 1, 0, 1]), "r2"), 96);
+
+        let spread: std::collections::HashSet<u64> =
+            (0..200u8).map(|i| client_bucket(IpAddr::from([10, 0, 0, i]), "rule-1")).collect();
+        assert!(spread.len() > 50, "clients are spread over the buckets");
+        assert!((0..100).all(|_| random_bucket() < BUCKETS));
+    }
+}
@@ -0,0 +1,275 @@
+//! # Upstream Timeouts
+//!
+//! Every gateway node bounds its upstream with three timeouts, so a hung
+//! backend can't hold a client connection forever while a slow but
+//! progressing download still finishes:
+//!
+//! * **connect** - establishing the upstream connection
+//! * **header** - how long the upstream may stay silent: before the response
+//!   header arrives, and between two reads while the body streams
+//! * **total** - the whole response, counted from the request's arrival
+//!
+//! The total timeout is a hard ceiling: the connect and header timeouts are
+//! cut short to the time it leaves, so connect retries and a slow upstream
+//! together can't outlast it either.
+//!
+//! Node settings override the listener defaults read from
+//! `GWRS_GATEWAY_CONNECT_TIMEOUT`, `GWRS_GATEWAY_HEADER_TIMEOUT` and
+//! `GWRS_GATEWAY_TOTAL_TIMEOUT` (10s, 60s and 1h); `0` disables a timeout.
+//! Upgraded (WebSocket) connections only get the connect timeout, they are
+//! long lived and may be idle.
+//!
+//! A request ended by a timeout gets the 504 page, or is cut off once its
+//! response started, and logs a `TIMEOUT` line naming the phase: `connect`,
+//! `header`, `body` (the body stopped flowing) or `total`.
+
+use std::time::{Duration, Instant};
+
+use pingora::prelude::*;
+
+use crate::config;
+
+/// Timeouts in seconds of one gateway node, `None` keeps the listener default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeoutOverrides {
+    pub connect: Option<u64>,
+    pub header: Option<u64>,
+    pub total: Option<u64>,
+}
+
+/// Effective upstream timeouts of a request, `None` when disabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpstreamTimeouts {
+    pub connect: Option<Duration>,
+    pub header: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+/// Which timeout ended a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// The upstream connection wasn't established in time
+    Connect,
+    /// No response header arrived in time
+    Header,
+    /// The body stopped flowing for longer than the header timeout
+    Body,
+    /// The whole response took too long
+    Total,
+}
+
+impl TimeoutKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimeoutKind::Connect => "connect",
+            TimeoutKind::Header => "header",
+            TimeoutKind::Body => "body",
+            TimeoutKind::Total => "total",
+        }
+    }
+}
+
+impl TimeoutOverrides {
+    /// Resolves the node's settings against the listener defaults.
+    pub fn resolve(&self, defaults: &UpstreamTimeouts) -> UpstreamTimeouts {
+        let pick = |node: Option<u64>, default: Option<Duration>| match node {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => default,
+        };
+        UpstreamTimeouts {
+            connect: pick(self.connect, defaults.connect),
+            header: pick(self.header, defaults.header),
+            total: pick(self.total, defaults.total),
+        }
+    }
+}
+
+impl UpstreamTimeouts {
+    /// Listener defaults from the `GWRS_GATEWAY_*_TIMEOUT` settings
+    pub fn from_config() -> Self {
+        Self {
+            connect: config::gateway_connect_timeout(),
+            header: config::gateway_header_timeout(),
+            total: config::gateway_total_timeout(),
+        }
+    }
+
+    /// Sets the connect timeout of `peer`, and the read timeout unless the connection is upgraded.
+    ///
+    /// Both are capped to `remaining`, the time the total timeout leaves.
+    pub fn apply(&self, peer: &mut HttpPeer, upgraded: bool, remaining: Option<Duration>) {
+        peer.options.connection_timeout = cap(self.connect, remaining);
+        if !upgraded {
+            peer.options.read_timeout = cap(self.header, remaining);
+        }
+    }
+
+    /// Whether a response started at `started` ran past the total timeout at `now`
+    pub fn total_exceeded(&self, started: Instant, now: Instant) -> bool {
+        self.total
+            .is_some_and(|total| now.saturating_duration_since(started) >= total)
+    }
+
+    /// Time the total timeout leaves a request started at `started`, `None` without one.
+    pub fn remaining(&self, started: Option<Instant>, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(started?);
+        self.total.map(|total| total.saturating_sub(elapsed))
+    }
+
+    /// Phase a connect timeout ended, `Total` when the total ran out meanwhile.
+    pub fn connect_timeout_kind(&self, started: Option<Instant>, now: Instant) -> TimeoutKind {
+        match started {
+            Some(started) if self.total_exceeded(started, now) => TimeoutKind::Total,
+            _ => TimeoutKind::Connect,
+        }
+    }
+
+    /// Phase a read timeout ended, `Body` once the response header was received.
+    pub fn read_timeout_kind(
+        &self,
+        started: Option<Instant>,
+        now: Instant,
+        response_started: bool,
+    ) -> TimeoutKind {
+        match started {
+            Some(started) if self.total_exceeded(started, now) => TimeoutKind::Total,
+            _ if response_started => TimeoutKind::Body,
+            _ => TimeoutKind::Header,
+        }
+    }
+
+    /// The configured length of a timeout, for log lines
+    pub fn limit(&self, kind: TimeoutKind) -> Option<Duration> {
+        match kind {
+            TimeoutKind::Connect => self.connect,
+            TimeoutKind::Header | TimeoutKind::Body => self.header,
+            TimeoutKind::Total => self.total,
+        }
+    }
+}
+
+/// The shorter of a timeout and the time left, either may be unset.
+fn cap(timeout: Option<Duration>, remaining: Option<Duration>) -> Option<Duration> {
+    match (timeout, remaining) {
+        (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+        (timeout, remaining) => timeout.or(remaining),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_overrides() {
+        let defaults = UpstreamTimeouts {
+            connect: Some(Duration::from_secs(10)),
+            header: Some(Duration::from_secs(60)),
+            total: Some(Duration::from_secs(3600)),
+        };
+        assert_eq!(TimeoutOverrides::default().resolve(&defaults), defaults);
+
+        let node = TimeoutOverrides {
+            connect: Some(2),
+            header: None,
+            total: Some(0),
+        };
+        let resolved = node.resolve(&defaults);
+        assert_eq!(resolved.connect, Some(Duration::from_secs(2)));
+        assert_eq!(resolved.header, Some(Duration::from_secs(60)));
+        assert_eq!(resolved.total, None);
+    }
+
+    #[test]
+    fn test_total_exceeded() {
+        let timeouts = UpstreamTimeouts {
+            total: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        assert!(!timeouts.total_exceeded(started, started + Duration::from_secs(29)));
+        assert!(timeouts.total_exceeded(started, started + Duration::from_secs(30)));
+        assert!(!UpstreamTimeouts::default().total_exceeded(started, started + Duration::from_secs(86400)));
+    }
+
+    fn timeouts() -> UpstreamTimeouts {
+        UpstreamTimeouts {
+            connect: Some(Duration::from_secs(10)),
+            header: Some(Duration::from_secs(60)),
+            total: Some(Duration::from_secs(30)),
+        }
+    }
+
+    #[test]
+    fn test_total_caps_connect_and_header() {
+        let started = Instant::now();
+        let timeouts = timeouts();
+        assert_eq!(timeouts.remaining(Some(started), started + Duration::from_secs(25)), Some(Duration::from_secs(5)));
+        assert_eq!(timeouts.remaining(Some(started), started + Duration::from_secs(45)), Some(Duration::ZERO));
+        assert_eq!(timeouts.remaining(None, started), None);
+        assert_eq!(UpstreamTimeouts::default().remaining(Some(started), started), None);
+
+        let mut peer = HttpPeer::new("127.0.0.1:3004", false, String::new());
+        timeouts.apply(&mut peer, false, Some(Duration::from_secs(5)));
+        assert_eq!(peer.options.connection_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(peer.options.read_timeout, Some(Duration::from_secs(5)));
+
+        let mut peer = HttpPeer::new("127.0.0.1:3004", false, String::new());
+        timeouts.apply(&mut peer, false, Some(Duration::from_secs(20)));
+        assert_eq!(peer.options.connection_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(peer.options.read_timeout, Some(Duration::from_secs(20)));
+
+        // Upgraded connections keep no read timeout, a disabled timeout is only bounded by the total
+        let mut peer = HttpPeer::new("127.0.0.1:3004", false, String::new());
+        let only_total = UpstreamTimeouts {
+            total: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        only_total.apply(&mut peer, true, Some(Duration::from_secs(7)));
+        assert_eq!(peer.options.connection_timeout, Some(Duration::from_secs(7)));
+        assert_eq!(peer.options.read_timeout, None);
+    }
+
+    #[test]
+    fn test_connect_timeout_phase() {
+        let started = Instant::now();
+        let timeouts = timeouts();
+        let kind = timeouts.connect_timeout_kind(Some(started), started + Duration::from_secs(10));
+        assert_eq!(kind, TimeoutKind::Connect);
+        assert_eq!(timeouts.limit(kind), Some(Duration::from_secs(10)));
+        // Retries ran the request into its total timeout
+        let kind = timeouts.connect_timeout_kind(Some(started), started + Duration::from_secs(30));
+        assert_eq!(kind, TimeoutKind::Total);
+        assert_eq!(timeouts.limit(kind), Some(Duration::from_secs(30)));
+        assert_eq!(timeouts.connect_timeout_kind(None, started), TimeoutKind::Connect);
+    }
+
+    #[test]
+    fn test_header_timeout_phase() {
+        let started = Instant::now();
+        let kind = timeouts().read_timeout_kind(Some(started), started + Duration::from_secs(12), false);
+        assert_eq!(kind, TimeoutKind::Header);
+        assert_eq!(kind.name(), "header");
+        assert_eq!(timeouts().limit(kind), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_body_timeout_phase() {
+        let started = Instant::now();
+        let kind = timeouts().read_timeout_kind(Some(started), started + Duration::from_secs(12), true);
+        assert_eq!(kind, TimeoutKind::Body);
+        assert_eq!(kind.name(), "body");
+        assert_eq!(timeouts().limit(kind), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_total_timeout_phase() {
+        let started = Instant::now();
+        for response_started in [false, true] {
+            let kind = timeouts().read_timeout_kind(Some(started), started + Duration::from_secs(30), response_started);
+            assert_eq!(kind, TimeoutKind::Total);
+            assert_eq!(kind.name(), "total");
+        }
+    }
+}
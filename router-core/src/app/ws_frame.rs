@@ -0,0 +1,293 @@
+//! # WebSocket Frame Counting
+//!
+//! Lightweight RFC 6455 frame header parsing for per-connection metrics of
+//! WebSocket traffic relayed by the speed mode proxy. Only the opcode and the
+//! payload length of each frame are read; payloads are skipped without being
+//! unmasked, reassembled or decompressed.
+//!
+//! Parsing starts after the HTTP upgrade head of each direction and stops for
+//! good on anything that does not look like a frame, so a misdetected
+//! connection only loses its counts and is still relayed untouched. It is off
+//! unless `GWRS_WS_FRAME_COUNTS` is set, since it adds work to every read.
+//!
+//! With `GWRS_WS_PING_INTERVAL` set the upstream direction is parsed as well,
+//! so keepalive pings are only inserted between two frames.
+
+/// Frames seen in one direction of a WebSocket connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameCounts {
+    /// Complete data messages, a fragmented message counts once
+    pub messages: u64,
+    pub text: u64,
+    pub binary: u64,
+    pub continuation: u64,
+    pub ping: u64,
+    pub pong: u64,
+    pub close: u64,
+}
+
+impl FrameCounts {
+    fn record(&mut self, fin: bool, opcode: u8) {
+        match opcode {
+            0x0 => self.continuation += 1,
+            0x1 => self.text += 1,
+            0x2 => self.binary += 1,
+            0x8 => self.close += 1,
+            0x9 => self.ping += 1,
+            0xA => self.pong += 1,
+            _ => {}
+        }
+        if fin && matches!(opcode, 0x0..=0x2) {
+            self.messages += 1;
+        }
+    }
+}
+
+/// Ping with an empty payload, unmasked as a server sends it
+pub const PING_FRAME: [u8; 2] = [0x89, 0x00];
+
+/// Longest frame header: 2 bytes, 8 bytes of extended length and a 4 byte mask
+const MAX_HEADER_LEN: usize = 14;
+
+#[derive(Debug)]
+enum State {
+    /// Scanning for the blank line ending the HTTP head, with the number of
+    /// `\r\n\r\n` bytes matched so far
+    Head(usize),
+    /// Collecting a frame header that may be split across reads
+    Header { buf: [u8; MAX_HEADER_LEN], len: usize },
+    /// Skipping the payload of the current frame
+    Payload(u64),
+    /// Stopped after something that was not a frame
+    Stopped,
+}
+
+/// Incremental frame parser for one direction of a connection
+#[derive(Debug)]
+pub struct FrameParser {
+    state: State,
+    /// Whether the HTTP head must be a `101 Switching Protocols` response
+    expect_switch: bool,
+    head_checked: bool,
+    counts: FrameCounts,
+}
+
+impl FrameParser {
+    /// Parser for client to upstream traffic, starting with the upgrade request.
+    pub fn client() -> Self {
+        Self::new(false)
+    }
+
+    /// Parser for upstream to client traffic, starting with the `101` response.
+    pub fn upstream() -> Self {
+        Self::new(true)
+    }
+
+    fn new(expect_switch: bool) -> Self {
+        Self {
+            state: State::Head(0),
+            expect_switch,
+            head_checked: false,
+            counts: FrameCounts::default(),
+        }
+    }
+
+    pub fn counts(&self) -> FrameCounts {
+        self.counts
+    }
+
+    /// Whether the bytes fed so far end with a complete frame, so another
+    /// frame can be written into the stream here.
+    ///
+    /// `false` before the HTTP head is through and after parsing stopped.
+    pub fn at_frame_boundary(&self) -> bool {
+        matches!(self.state, State::Header { len: 0, .. })
+    }
+
+    /// Feeds the next chunk of relayed bytes.
+    pub fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            match &mut self.state {
+                State::Stopped => return,
+                State::Head(matched) => {
+                    if self.expect_switch && !self.head_checked {
+                        self.head_checked = true;
+                        if !data.starts_with(b"HTTP/1.1 101") {
+                            self.state = State::Stopped;
+                            return;
+                        }
+                    }
+                    let mut consumed = data.len();
+                    for (i, byte) in data.iter().enumerate() {
+                        let expected = b"\r\n\r\n"[*matched];
+                        *matched = if *byte == expected {
+                            *matched + 1
+                        } else if *byte == b'\r' {
+                            1
+                        } else {
+                            0
+                        };
+                        if *matched == 4 {
+                            consumed = i + 1;
+                            break;
+                        }
+                    }
+                    if *matched == 4 {
+                        self.state = State::Header { buf: [0; MAX_HEADER_LEN], len: 0 };
+                    }
+                    data = &data[consumed..];
+                }
+                State::Header { buf, len } => {
+                    buf[*len] = data[0];
+                    *len += 1;
+                    data = &data[1..];
+                    if header_len(&buf[..*len]) == Some(*len) {
+                        let fin = buf[0] & 0x80 != 0;
+                        let opcode = buf[0] & 0x0F;
+                        let payload = payload_len(&buf[..*len]);
+                        // Control frames are never fragmented and carry at most 125 bytes
+                        if !valid_opcode(opcode) || (opcode >= 0x8 && (!fin || payload > 125)) {
+                            self.state = State::Stopped;
+                            return;
+                        }
+                        self.counts.record(fin, opcode);
+                        self.state = State::Payload(payload);
+                    }
+                }
+                State::Payload(remaining) => {
+                    let skip = (*remaining).min(data.len() as u64);
+                    *remaining -= skip;
+                    data = &data[skip as usize..];
+                    if *remaining == 0 {
+                        self.state = State::Header { buf: [0; MAX_HEADER_LEN], len: 0 };
+                    }
+                }
+            }
+        }
+        // A frame with an empty payload is complete once its header is
+        if let State::Payload(0) = self.state {
+            self.state = State::Header { buf: [0; MAX_HEADER_LEN], len: 0 };
+        }
+    }
+}
+
+/// `[PXY]` CLOSE line fields of a connection's frame counts, each followed by `, `.
+///
+/// `WS_MSG_IN`/`WS_MSG_OUT` follow the `IN`/`OUT` byte counts (client to upstream
+/// and back), the per-kind frame counts cover both directions.
+pub fn log_fields(client: &FrameCounts, upstream: &FrameCounts) -> String {
+    format!(
+        "WS_MSG_IN:{}, WS_MSG_OUT:{}, WS_TEXT:{}, WS_BINARY:{}, WS_PING:{}, WS_PONG:{}, WS_CLOSE:{}, ",
+        client.messages,
+        upstream.messages,
+        client.text + upstream.text,
+        client.binary + upstream.binary,
+        client.ping + upstream.ping,
+        client.pong + upstream.pong,
+        client.close + upstream.close
+    )
+}
+
+fn valid_opcode(opcode: u8) -> bool {
+    matches!(opcode, 0x0..=0x2 | 0x8..=0xA)
+}
+
+/// Full header length once enough of it is known to tell
+fn header_len(header: &[u8]) -> Option<usize> {
+    if header.len() < 2 {
+        return None;
+    }
+    let mask = if header[1] & 0x80 != 0 { 4 } else { 0 };
+    let extended = match header[1] & 0x7F {
+        126 => 2,
+        127 => 8,
+        _ => 0,
+    };
+    Some(2 + extended + mask)
+}
+
+fn payload_len(header: &[u8]) -> u64 {
+    match header[1] & 0x7F {
+        126 => u16::from_be_bytes([header[2], header[3]]) as u64,
+        127 => u64::from_be_bytes(header[2..10].try_into().unwrap_or([0; 8])),
+        len => len as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(fin: bool, opcode: u8, masked: bool, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![((fin as u8) << 7) | opcode];
+        let mask_bit = (masked as u8) << 7;
+        match payload.len() {
+            len if len < 126 => out.push(mask_bit | len as u8),
+            len if len <= u16::MAX as usize => {
+                out.push(mask_bit | 126);
+                out.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                out.push(mask_bit | 127);
+                out.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        if masked {
+            out.extend_from_slice(&[1, 2, 3, 4]);
+        }
+        out.extend_from_slice(payload);
+        out
+    }
+
+    #[test]
+    fn test_counts_frames_split_across_reads() {
+        let mut stream = b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\n\r\n".to_vec();
+        stream.extend(frame(true, 0x1, true, b"hello"));
+        stream.extend(frame(false, 0x2, true, &[0; 300]));
+        stream.extend(frame(true, 0x0, true, &[0; 70_000]));
+        stream.extend(frame(true, 0x9, true, b""));
+        stream.extend(frame(true, 0x8, true, &[3, 232]));
+
+        // Feed in awkward chunk sizes so headers and the head end straddle reads
+        let mut parser = FrameParser::client();
+        for chunk in stream.chunks(3) {
+            parser.feed(chunk);
+        }
+        let counts = parser.counts();
+        assert_eq!(counts.text, 1);
+        assert_eq!(counts.binary, 1);
+        assert_eq!(counts.continuation, 1);
+        assert_eq!(counts.ping, 1);
+        assert_eq!(counts.close, 1);
+        assert_eq!(counts.messages, 2);
+    }
+
+    #[test]
+    fn test_upstream_needs_switching_protocols() {
+        let mut parser = FrameParser::upstream();
+        let mut stream = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n".to_vec();
+        stream.extend(frame(true, 0xA, false, b""));
+        stream.extend(frame(true, 0x1, false, b"hi"));
+        parser.feed(&stream);
+        assert_eq!(parser.counts().pong, 1);
+        assert_eq!(parser.counts().messages, 1);
+        assert!(parser.at_frame_boundary());
+        parser.feed(&frame(true, 0x2, false, &[0; 10])[..5]);
+        assert!(!parser.at_frame_boundary());
+
+        let mut parser = FrameParser::upstream();
+        parser.feed(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+        parser.feed(&frame(true, 0x1, false, b"hi"));
+        assert_eq!(parser.counts(), FrameCounts::default());
+        assert!(!parser.at_frame_boundary());
+    }
+
+    #[test]
+    fn test_stops_on_invalid_frame() {
+        let mut parser = FrameParser::client();
+        parser.feed(b"GET / HTTP/1.1\r\n\r\n");
+        parser.feed(&[0x83, 0x00]); // reserved opcode
+        parser.feed(&frame(true, 0x1, true, b"late"));
+        assert_eq!(parser.counts(), FrameCounts::default());
+    }
+}
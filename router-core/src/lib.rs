@@ -0,0 +1,9 @@
+//! # Router Core Library
+//!
+//! The modules of the router core, shared by the `router-core` binary in
+//! `main.rs` and the benchmarks in `benches/`. See `main.rs` for the
+//! architecture and lifecycle of the router.
+pub mod app;
+pub mod config;
+pub mod service;
+pub mod system;
@@ -20,13 +20,9 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Duration;
-use system::memory_log;
 use tokio::{self};
 
-mod app;
-mod config;
-mod service;
-mod system;
+use router_core::{config, system};
 
 /// Main entry point for the router core application.
 ///
@@ -34,13 +30,24 @@ mod system;
 /// 1. Sets up logging configuration
 /// 2. Initializes the service registry for inter-service communication
 /// 3. Starts the custom protocol server for control messages
-/// 4. Sets up signal handlers for graceful shutdown
+/// 4. Sets up signal handlers for graceful shutdown and settings reload
 /// 5. Starts the main server in a separate thread
 /// 6. Enters a control loop for monitoring and management
 ///
 /// The application can be terminated by:
-/// - SIGINT (Ctrl+C) signal
 /// - Ctrl+X keyboard shortcut via the terminator CLI
+/// - A remote shutdown request over the protocol server (`GWRX /shutdown`)
+///
+/// `--print-config` prints the effective configuration as JSON and exits without
+/// starting anything (see `system::effective_config`). A normal start logs it once.
+///
+/// SIGINT (Ctrl+C) restarts the servers instead of exiting. SIGHUP re-reads the
+/// settings file and applies what can change without a restart (see `system::reload`).
+///
+/// A listener that can't be bound at startup (address in use, permission denied)
+/// is reported and the process exits with status 1. This includes the protocol
+/// server, which listens on `GWRS_PROTTP_ADDRESS` (`127.0.0.1:30099` by default).
+/// Restarts after a config push leave such a listener out and keep the others.
 ///
 /// # Lifecycle
 ///
@@ -50,8 +57,13 @@ mod system;
 async fn main() {
     // Configure file-based logging
     config::init();
+    if std::env::args().skip(1).any(|arg| arg == "--print-config") {
+        system::effective_config::print();
+        return;
+    }
     // std::env::set_var("RUST_LOG", "info");
     // env_logger::init();
+    eprintln!("[----] Starting router-core {}...", env!("CARGO_PKG_VERSION"));
     eprintln!("[----] Starting proxy server...");
 
     // Create atomic flag to track server active state
@@ -59,8 +71,10 @@ async fn main() {
 
     eprintln!("[----] Starting protocol server...");
     // Initialize custom protocol server for control and management interface
-    {
-        system::prottp::init();
+    if let Err(e) = system::prottp::init() {
+        log::error!("{}", e);
+        eprintln!("[----] {}", e);
+        std::process::exit(1);
     }
     
     eprintln!("[----] Starting service registry...");
@@ -68,6 +82,7 @@ async fn main() {
     {
         system::writer::writer_start();
     }
+    system::effective_config::log();
 
     eprintln!("[----] Starting CTRL+C Listener...");
     // Set up interrupt handler for graceful shutdown on SIGINT (Ctrl+C)
@@ -82,31 +97,55 @@ async fn main() {
         .expect("Error setting Ctrl-C handler");
     }
 
+    eprintln!("[----] Starting SIGHUP Listener...");
+    // Re-read the settings file on SIGHUP without touching the servers
+    tokio::spawn(system::reload::listen());
+
     eprintln!("[----] Starting Main Loop...");
 
+    // Handle of the running server thread, checked for startup failures
+    let mut server: Option<std::thread::JoinHandle<Result<(), Vec<system::startup::StartupError>>>> = None;
+    // Only the first start exits on a listener that can't be bound
+    let mut first_start = true;
+
     // Main application loop - continues until termination signal
     loop {
 
         // Check for Ctrl+X termination signal via CLI interface
         if system::terminator::cli::init(Duration::from_millis(0)) {
-            let _ = memory_log::log_cleanup();
             eprintln!("[----] Ctrl+X received, exiting...");
-            eprintln!("[----] Cleaning up memory log...");
-            let _ = memory_log::log_cleanup();
-            eprintln!("[----] Cleaning up memory log done.");
-            eprintln!("[----] Finish...\n\n");
+            system::terminator::cleanup();
+            break;
+        }
+
+        // Check for a remote shutdown request received over the protocol server
+        if system::terminator::service::is_shutdown_requested() {
+            eprintln!("[----] Remote shutdown requested, exiting...");
+            system::terminator::cleanup();
             break;
         }
 
+        // Exit when the servers could not start the first time, retrying would fail the same way
+        if server.as_ref().is_some_and(|handle| handle.is_finished()) {
+            if let Some(Ok(Err(errors))) = server.take().map(|handle| handle.join()) {
+                for e in &errors {
+                    log::error!("{}", e);
+                    eprintln!("[----] {}", e);
+                }
+                eprintln!("[----] Failed to start the servers, exiting...");
+                system::terminator::cleanup();
+                std::process::exit(1);
+            }
+        }
+
         // Start server if not already active
         if !active_state.load(std::sync::atomic::Ordering::Relaxed) {
             // Set active state flag
             active_state.store(true, std::sync::atomic::Ordering::Relaxed);
 
             // Launch server in separate thread to avoid blocking the control loop
-            std::thread::spawn(|| {
-                system::server::init();
-            });
+            let first = std::mem::replace(&mut first_start, false);
+            server = Some(std::thread::spawn(move || system::server::init(first)));
 
             continue;
         }
@@ -1,18 +1,43 @@
 use crate::app::proxy_fast;
-use pingora::listeners::Listeners;
+use crate::config;
+use crate::system::sockopt::TcpOptions;
+use crate::system::tls_policy::{self, TlsPolicy};
+use pingora::listeners::{tls::TlsSettings, Listeners};
+use std::ops::DerefMut;
 use pingora::services::listening::Service;
 use pingora::upstreams::peer::BasicPeer;
 
+/// Upstream peer of an address, a Unix domain socket for `unix:/path` targets
+fn peer(addr_to: &str) -> Result<BasicPeer, String> {
+    match config::unix_socket_path(addr_to) {
+        Some(path) => BasicPeer::new_uds(path)
+            .map_err(|e| format!("Invalid Unix socket target {}: {}", addr_to, e)),
+        None => Ok(BasicPeer::new(addr_to)),
+    }
+}
+
+/// Plain listeners of an address, a Unix domain socket for `unix:/path` addresses
+fn listeners(addr: &str) -> Listeners {
+    match config::unix_socket_path(addr) {
+        Some(path) => Listeners::uds(path, None),
+        None => Listeners::tcp(addr),
+    }
+}
 
-pub fn proxy_service_fast(addr: &str, addr_to: &str) -> Service<proxy_fast::ProxyApp> {
+pub fn proxy_service_fast(
+    addr: &str,
+    addr_to: &str,
+    tcp_options: TcpOptions,
+    buffer_size: usize,
+) -> Result<Service<proxy_fast::ProxyApp>, String> {
 
-    let peer = BasicPeer::new(addr_to);
+    let peer = peer(addr_to)?;
 
-    Service::with_listeners(
+    Ok(Service::with_listeners(
         "Proxy Service".to_string(),
-        Listeners::tcp(addr),
-        proxy_fast::ProxyApp::new(peer, String::from(addr)),
-    )
+        listeners(addr),
+        proxy_fast::ProxyApp::new(peer, String::from(addr), tcp_options, buffer_size, false),
+    ))
 }
 
 pub fn proxy_service_tls_fast(
@@ -21,9 +46,17 @@ pub fn proxy_service_tls_fast(
     _addr_sni: &str,
     cert_path: &str,
     key_path: &str,
-) -> Service<proxy_fast::ProxyApp> {
+    tcp_options: TcpOptions,
+    buffer_size: usize,
+    tls_policy: &TlsPolicy,
+) -> Result<Service<proxy_fast::ProxyApp>, String> {
+
+    // TLS is terminated on TCP listeners only, the API refuses such proxies
+    if config::unix_socket_path(addr).is_some() {
+        return Err(format!("TLS is not supported on Unix socket {}", addr));
+    }
 
-    let peer = BasicPeer::new(addr_to);
+    let peer = peer(addr_to)?;
     
     // Check if certificate and key files exist
     if !std::path::Path::new(cert_path).exists() {
@@ -34,18 +67,30 @@ pub fn proxy_service_tls_fast(
         log::error!("TLS key file not found: {}", key_path);
     }
     
-    let listeners = match Listeners::tls(addr, cert_path, key_path) {
-        Ok(l) => l,
+    let mut tls_settings = match TlsSettings::intermediate(cert_path, key_path) {
+        Ok(settings) => settings,
         Err(e) => {
             log::error!("Failed to create TLS listener: {}. Check that your certificate is valid and not expired.", e);
             log::error!("Certificate path: {}, Key path: {}", cert_path, key_path);
             panic!("TLS setup failed: {}", e);
         }
     };
+    if let Err(e) = tls_policy.apply(tls_settings.deref_mut().deref_mut()) {
+        log::error!("Proxy service {} TLS policy not applied, using the default: {}", addr, e);
+        TlsPolicy::default()
+            .apply(tls_settings.deref_mut().deref_mut())
+            .expect("default TLS policy applies");
+    }
+    if tls_policy.require_sni {
+        tls_policy::refuse_without_sni(tls_settings.deref_mut().deref_mut(), addr);
+    }
+
+    let mut listeners = Listeners::new();
+    listeners.add_tls_with_settings(addr, None, tls_settings);
     
-    Service::with_listeners(
+    Ok(Service::with_listeners(
         "Proxy Service TLS".to_string(),
         listeners,
-        proxy_fast::ProxyApp::new(peer,String::from(addr)),
-    )
+        proxy_fast::ProxyApp::new(peer, String::from(addr), tcp_options, buffer_size, true),
+    ))
 }
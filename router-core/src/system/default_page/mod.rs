@@ -9,6 +9,10 @@
 //! * `p_base`: Common base functionality for default page handlers
 //! * `p404`: Handler for 404 Not Found responses
 //! * `p500`: Handler for 500 Internal Server Error responses
+//! * `p504`: Handler for 504 Gateway Timeout responses, served when an upstream times out
+//! * `p503`: Handler for 503 Service Unavailable responses of speed mode proxies in maintenance
+//! * `p_static`: Inline responses served by gateway rules with a `static` target
+//! * `p_redirect`: HTTP to HTTPS redirects for proxies with `redirect_to_https`
 //! * `tls_honeypot`: Security monitoring endpoint that logs suspicious TLS connections
 //! 
 //! ## Usage
@@ -20,10 +24,17 @@
 //! 2. An internal error occurs during request processing (500 handler)
 //! 3. Suspicious TLS connection attempts are detected (TLS honeypot)
 //! 
+//! Static pages are different: they are answered directly from the gateway without
+//! any listener, e.g. to take an endpoint offline for maintenance via config.
+//!
 //! Each handler logs relevant information about the request and returns an appropriate
 //! response to the client.
 
 pub mod p_base;
 pub mod p404;
 pub mod p500;
+pub mod p504;
+pub mod p503;
+pub mod p_redirect;
+pub mod p_static;
 pub mod tls_honeypot;
\ No newline at end of file
@@ -0,0 +1,21 @@
+//! # 503 Service Unavailable Page Handler
+//!
+//! Speed mode proxies in maintenance relay their connections here instead of
+//! to their target, so clients get a 503 until the proxy is switched back.
+//! Gateway rules in maintenance are answered inline with a `Retry-After`
+//! header instead, see `p_static`.
+//!
+//! The handler uses the address and port defined in `DEFAULT_PORT.p503`.
+
+use crate::config::DEFAULT_PORT;
+use super::p_base::run_error_page_server;
+
+/// Initialize the 503 Service Unavailable page handler.
+pub fn init() {
+    run_error_page_server(
+        DEFAULT_PORT.p503,
+        503,
+        "Service Unavailable",
+        "Default 503 page"
+    );
+}
@@ -0,0 +1,20 @@
+//! # 504 Gateway Timeout Page Handler
+//!
+//! Requests whose upstream didn't connect or answer within the timeouts of
+//! their gateway node are sent here, the same way exhausted connect retries
+//! are sent to the 500 page.
+//!
+//! The handler uses the address and port defined in `DEFAULT_PORT.p504`.
+
+use crate::config::DEFAULT_PORT;
+use super::p_base::run_error_page_server;
+
+/// Initialize the 504 Gateway Timeout page handler.
+pub fn init() {
+    run_error_page_server(
+        DEFAULT_PORT.p504,
+        504,
+        "Gateway Timeout",
+        "Default 504 page"
+    );
+}
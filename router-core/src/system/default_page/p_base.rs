@@ -10,6 +10,17 @@
 use std::io::Write;
 use std::net::TcpListener;
 
+/// HTML body served by the default pages, also the fallback body of static gateway rules.
+pub const DEFAULT_PAGE_HTML: &str = "<!DOCTYPE html>\
+                         <html>\
+                         <head><title>Mini Router</title></head>\
+                         <body>\
+                         <center><h1>Gateway.rs</h1></center>\
+                         <hr>\
+                         <center>mini-gateway</center>\
+                         </body>\
+                         </html>";
+
 /// Runs a simple HTTP server that serves a generic error page with the specified status code.
 ///
 /// This function creates a TCP listener on the provided address and continuously accepts
@@ -94,15 +105,7 @@ pub fn run_error_page_server(
     log::debug!("{} server listening on {}", server_type, bind_addr);
 
     // HTML content for the error page
-    let html_content = "<!DOCTYPE html>\
-                         <html>\
-                         <head><title>Mini Router</title></head>\
-                         <body>\
-                         <center><h1>Gateway.rs</h1></center>\
-                         <hr>\
-                         <center>mini-gateway</center>\
-                         </body>\
-                         </html>";
+    let html_content = DEFAULT_PAGE_HTML;
 
     // Calculate content length dynamically
     let content_length = html_content.len();
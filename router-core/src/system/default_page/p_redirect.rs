@@ -0,0 +1,214 @@
+//! # HTTP to HTTPS Redirect Handler
+//!
+//! Serves proxies configured with `redirect_to_https`. Instead of forwarding, the
+//! listener answers every plain HTTP request with a `301 Moved Permanently` to the
+//! same host and path on HTTPS.
+//!
+//! ## Target Port
+//!
+//! The redirect points at port 443 unless the proxy sets `redirect_https_port`, in
+//! which case the port is added to the `Location` host (e.g. `https://example.com:8443/`).
+//!
+//! ## ACME Challenges
+//!
+//! `GET /.well-known/acme-challenge/<token>` requests of a challenge router-api
+//! published are answered with its key authorization instead of a redirect, so
+//! certificates of the proxy's domains can be obtained over plain HTTP.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use crate::app::acme_challenge;
+
+/// Port assumed by browsers for `https://` URLs
+pub const DEFAULT_HTTPS_PORT: u16 = 443;
+
+/// Upper bound for the request head read before answering
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// Time a client gets to send its request head
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Extracts the request target and `Host` header from a request head.
+fn parse_request_head(head: &str) -> Option<(&str, &str)> {
+    let mut lines = head.split("\r\n");
+    let target = lines.next()?.split_whitespace().nth(1)?;
+    let host = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.trim())
+        .filter(|value| !value.is_empty())?;
+    Some((target, host))
+}
+
+/// Builds the `Location` of the redirect for a request.
+///
+/// The port of the original `Host` is replaced by `https_port`, which is left
+/// out when it is the default 443.
+pub fn https_location(host: &str, target: &str, https_port: u16) -> String {
+    let host_name = if host.starts_with('[') {
+        // Bracketed IPv6 literal, keep the brackets
+        host.split_inclusive(']').next().unwrap_or(host)
+    } else {
+        host.split(':').next().unwrap_or(host)
+    };
+
+    // Absolute-form targets (`GET http://host/path`) keep only their path
+    let path = match target.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|idx| &rest[idx..]).unwrap_or("/"),
+        None if target.starts_with('/') => target,
+        None => "/",
+    };
+
+    if https_port == DEFAULT_HTTPS_PORT {
+        format!("https://{}{}", host_name, path)
+    } else {
+        format!("https://{}:{}{}", host_name, https_port, path)
+    }
+}
+
+/// The key authorization answering a `GET` of an ACME challenge, `None` for other requests.
+fn challenge_answer(head: &str, target: &str) -> Option<String> {
+    if !head.starts_with("GET ") {
+        return None;
+    }
+    let path = target.split('?').next().unwrap_or(target);
+    acme_challenge::answer(path)
+}
+
+/// Reads the request head and answers with a challenge or a redirect, or 400 without a `Host`.
+fn handle_connection(mut stream: TcpStream, https_port: u16) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let mut head = Vec::with_capacity(1024);
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < MAX_HEAD_SIZE {
+        let n = stream.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..n]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let response = match parse_request_head(&head) {
+        Some((target, host)) => match challenge_answer(&head, target) {
+            Some(key_authorization) => {
+                log::info!("Answering ACME challenge {} for {}", target, host);
+                format!(
+                    "HTTP/1.1 200 OK\r\n\
+                     Content-Type: application/octet-stream\r\n\
+                     Content-Length: {}\r\n\
+                     Connection: close\r\n\
+                     \r\n\
+                     {}",
+                    key_authorization.len(),
+                    key_authorization
+                )
+            }
+            None => {
+                let location = https_location(host, target, https_port);
+                log::debug!("Redirecting http://{}{} to {}", host, target, location);
+                format!(
+                    "HTTP/1.1 301 Moved Permanently\r\n\
+                     Location: {}\r\n\
+                     Content-Length: 0\r\n\
+                     Connection: close\r\n\
+                     \r\n",
+                    location
+                )
+            }
+        },
+        None => "HTTP/1.1 400 Bad Request\r\n\
+                 Content-Length: 0\r\n\
+                 Connection: close\r\n\
+                 \r\n"
+            .to_string(),
+    };
+
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// Runs the redirect listener on `bind_addr` until the process exits.
+///
+/// Each connection is handled in its own thread, like the other default pages.
+pub fn init(bind_addr: &str, https_port: u16) {
+    let listener = match TcpListener::bind(bind_addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Failed to bind HTTPS redirect on {}: {}", bind_addr, e);
+            return;
+        }
+    };
+
+    log::debug!(
+        "HTTPS redirect server listening on {} (target port {})",
+        bind_addr,
+        https_port
+    );
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_connection(stream, https_port) {
+                        log::debug!("HTTPS redirect connection failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                log::error!("Connection failed: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_head() {
+        let head = "GET /login?next=/ HTTP/1.1\r\nUser-Agent: curl\r\nHOST: example.com:80\r\n\r\n";
+        assert_eq!(parse_request_head(head), Some(("/login?next=/", "example.com:80")));
+        assert_eq!(parse_request_head("GET / HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(parse_request_head(""), None);
+    }
+
+    #[test]
+    fn test_challenges_are_answered_before_redirecting() {
+        let token = "redirectToken";
+        acme_challenge::publish(token, "redirectToken.thumbprint").unwrap();
+        let target = format!("{}{}", acme_challenge::CHALLENGE_PATH, token);
+
+        let get = format!("GET {} HTTP/1.1\r\nHost: example.com\r\n\r\n", target);
+        assert_eq!(challenge_answer(&get, &target).as_deref(), Some("redirectToken.thumbprint"));
+        let post = format!("POST {} HTTP/1.1\r\nHost: example.com\r\n\r\n", target);
+        assert_eq!(challenge_answer(&post, &target), None);
+        assert_eq!(challenge_answer("GET / HTTP/1.1\r\n\r\n", "/"), None);
+        acme_challenge::remove(token);
+    }
+
+    #[test]
+    fn test_location_uses_default_port() {
+        assert_eq!(
+            https_location("example.com:80", "/a/b?c=1", 443),
+            "https://example.com/a/b?c=1"
+        );
+        assert_eq!(https_location("example.com", "*", 443), "https://example.com/");
+        assert_eq!(
+            https_location("example.com", "http://example.com/x", 443),
+            "https://example.com/x"
+        );
+    }
+
+    #[test]
+    fn test_location_with_custom_port() {
+        assert_eq!(https_location("example.com:8080", "/", 8443), "https://example.com:8443/");
+        assert_eq!(https_location("[::1]:8080", "/x", 8443), "https://[::1]:8443/x");
+        assert_eq!(https_location("[::1]", "/x", 443), "https://[::1]/x");
+    }
+}
@@ -0,0 +1,232 @@
+//! # Static Gateway Responses
+//!
+//! Gateway rules whose `path_target` is `static` don't forward to a backend. The
+//! gateway answers them itself with a fixed status, content type and body, which
+//! lets operators take an endpoint offline (e.g. for maintenance) through config.
+//!
+//! ## Target Format
+//!
+//! * `static` - 503 with the default HTML page
+//! * `static:{"status":503,"content_type":"application/json","body":"{\"status\":\"maintenance\"}"}`
+//!
+//! Every field of the JSON spec is optional and falls back to the values above.
+
+use bytes::Bytes;
+use http::header::HeaderValue;
+use pingora::http::ResponseHeader;
+use pingora::prelude::*;
+use pingora::proxy::Session;
+use serde::Deserialize;
+
+use super::p_base::DEFAULT_PAGE_HTML;
+
+/// `path_target` value (or prefix, followed by `:` and a JSON spec) marking a static rule
+pub const STATIC_TARGET: &str = "static";
+
+const DEFAULT_STATUS: u16 = 503;
+const DEFAULT_CONTENT_TYPE: &str = "text/html";
+
+/// Seconds clients are told to wait before retrying a rule in maintenance
+const MAINTENANCE_RETRY_AFTER: u64 = 120;
+
+#[derive(Debug, Default, Deserialize)]
+struct StaticSpec {
+    status: Option<u16>,
+    content_type: Option<String>,
+    body: Option<String>,
+}
+
+/// A response served inline by the gateway.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaticPage {
+    pub status: u16,
+    pub content_type: String,
+    pub body: Bytes,
+    /// Seconds sent in `Retry-After`, no header when unset
+    pub retry_after: Option<u64>,
+}
+
+impl StaticPage {
+    /// Parses a rule target.
+    ///
+    /// # Returns
+    ///
+    /// * `None` if the target is a regular path template
+    /// * `Some(Err(..))` if it is a static target with an invalid spec
+    /// * `Some(Ok(page))` otherwise
+    pub fn from_target(target: &str) -> Option<Result<Self, String>> {
+        let spec = if target == STATIC_TARGET {
+            Ok(StaticSpec::default())
+        } else {
+            let json = target.strip_prefix(STATIC_TARGET)?.strip_prefix(':')?;
+            serde_json::from_str::<StaticSpec>(json)
+                .map_err(|e| format!("invalid static response spec: {}", e))
+        };
+
+        Some(spec.and_then(|spec| {
+            let status = spec.status.unwrap_or(DEFAULT_STATUS);
+            if !(100..=599).contains(&status) {
+                return Err(format!("invalid static response status {}", status));
+            }
+            Ok(Self {
+                status,
+                content_type: spec
+                    .content_type
+                    .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string()),
+                body: Bytes::from(spec.body.unwrap_or_else(|| DEFAULT_PAGE_HTML.to_string())),
+                retry_after: None,
+            })
+        }))
+    }
+
+    /// The 403 answered to clients refused by a gateway node's IP access list.
+    pub fn forbidden() -> Self {
+        Self {
+            status: 403,
+            content_type: DEFAULT_CONTENT_TYPE.to_string(),
+            body: Bytes::from(DEFAULT_PAGE_HTML),
+            retry_after: None,
+        }
+    }
+
+    /// The 503 answered when a gateway listener is at its in-flight request limit.
+    pub fn unavailable() -> Self {
+        Self {
+            status: 503,
+            content_type: DEFAULT_CONTENT_TYPE.to_string(),
+            body: Bytes::from(DEFAULT_PAGE_HTML),
+            retry_after: None,
+        }
+    }
+
+    /// The 400 answered when a gateway rule rewrites a request into an invalid URI.
+    pub fn bad_request() -> Self {
+        Self {
+            status: 400,
+            content_type: DEFAULT_CONTENT_TYPE.to_string(),
+            body: Bytes::from(DEFAULT_PAGE_HTML),
+            retry_after: None,
+        }
+    }
+
+    /// The 503 answered for rules of a proxy or gateway node in maintenance mode.
+    pub fn maintenance() -> Self {
+        Self {
+            status: 503,
+            content_type: DEFAULT_CONTENT_TYPE.to_string(),
+            body: Bytes::from(DEFAULT_PAGE_HTML),
+            retry_after: Some(MAINTENANCE_RETRY_AFTER),
+        }
+    }
+
+    /// The 200 answering an ACME HTTP-01 challenge with its key authorization.
+    pub fn acme_challenge(key_authorization: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/octet-stream".to_string(),
+            body: Bytes::from(key_authorization),
+            retry_after: None,
+        }
+    }
+
+    /// Writes the full response to the downstream session.
+    ///
+    /// `HEAD` requests get the same headers, `Content-Length` included, without the body.
+    pub async fn respond(&self, session: &mut Session) -> Result<()> {
+        self.respond_with(session, &[]).await
+    }
+
+    /// [`respond`](Self::respond) with extra response headers, e.g. a route trace.
+    pub async fn respond_with(
+        &self,
+        session: &mut Session,
+        headers: &[(&'static str, HeaderValue)],
+    ) -> Result<()> {
+        let send_body = !self.body.is_empty() && session.req_header().method != http::Method::HEAD;
+        let mut header = ResponseHeader::build(self.status, Some(3))?;
+        header.insert_header(http::header::CONTENT_TYPE, self.content_type.as_str())?;
+        header.insert_header(http::header::CONTENT_LENGTH, self.body.len().to_string())?;
+        header.insert_header(http::header::CACHE_CONTROL, "no-store")?;
+        if let Some(secs) = self.retry_after {
+            header.insert_header(http::header::RETRY_AFTER, secs.to_string())?;
+        }
+        for (name, value) in headers {
+            header.insert_header(*name, value.clone())?;
+        }
+        session
+            .write_response_header(Box::new(header), !send_body)
+            .await?;
+        if send_body {
+            session
+                .write_response_body(Some(self.body.clone()), true)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regular_targets_are_not_static() {
+        assert!(StaticPage::from_target("/$1").is_none());
+        assert!(StaticPage::from_target("/static/$1").is_none());
+        assert!(StaticPage::from_target("statics").is_none());
+    }
+
+    #[test]
+    fn test_bare_static_target_uses_defaults() {
+        let page = StaticPage::from_target("static").unwrap().unwrap();
+        assert_eq!(page.status, 503);
+        assert_eq!(page.content_type, "text/html");
+        assert_eq!(page.body, Bytes::from(DEFAULT_PAGE_HTML));
+    }
+
+    #[test]
+    fn test_static_target_with_spec() {
+        let target = r#"static:{"status":200,"content_type":"application/json","body":"{\"status\":\"maintenance\"}"}"#;
+        let page = StaticPage::from_target(target).unwrap().unwrap();
+        assert_eq!(page.status, 200);
+        assert_eq!(page.content_type, "application/json");
+        assert_eq!(page.body, Bytes::from(r#"{"status":"maintenance"}"#));
+    }
+
+    #[test]
+    fn test_invalid_static_spec() {
+        assert!(StaticPage::from_target("static:{not json").unwrap().is_err());
+        assert!(StaticPage::from_target(r#"static:{"status":42}"#).unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_head_gets_headers_without_body() {
+        use pingora::protocols::l4::stream::Stream as L4Stream;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut client, server) = tokio::net::UnixStream::pair().unwrap();
+        client
+            .write_all(b"HEAD /health HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut session = Session::new_h1(Box::new(L4Stream::from(server)));
+        assert!(session.read_request().await.unwrap());
+        let page = StaticPage::from_target(r#"static:{"status":200,"content_type":"application/json","body":"{\"up\":true}"}"#)
+            .unwrap()
+            .unwrap();
+        page.respond(&mut session).await.unwrap();
+        drop(session);
+
+        let mut response = Vec::new();
+        tokio::time::timeout(std::time::Duration::from_secs(5), client.read_to_end(&mut response))
+            .await
+            .expect("response never ended")
+            .unwrap();
+        let response = String::from_utf8(response).unwrap().to_ascii_lowercase();
+        assert!(response.starts_with("http/1.1 200"), "{}", response);
+        assert!(response.contains("content-type: application/json\r\n"), "{}", response);
+        assert!(response.contains("content-length: 11\r\n"), "{}", response);
+        assert!(response.ends_with("\r\n\r\n"), "HEAD response carried a body: {}", response);
+    }
+}
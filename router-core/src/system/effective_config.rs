@@ -0,0 +1,134 @@
+//! # Effective Configuration
+//!
+//! The settings the core actually runs with, after the environment, the
+//! settings file and the defaults were merged, as one JSON document. It is
+//! logged once at startup and printed by `router-core --print-config`, which
+//! exits right after, so "which settings is it running with?" has one answer.
+//!
+//! Durations are in seconds (`route_script.timeout_ms` in milliseconds), `null`
+//! where `0` disabled a timeout. Secrets are never included, the protocol and
+//! trace tokens only show whether one is set. Proxy and gateway nodes aren't
+//! part of it, they arrive from router-api at runtime.
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::config::{self, DEFAULT_PORT};
+use crate::system::memory_log::level;
+
+/// Seconds of an optional duration, `null` when it is off
+fn secs(duration: Option<Duration>) -> Value {
+    duration.map_or(Value::Null, |duration| json!(duration.as_secs()))
+}
+
+/// The effective configuration of the core.
+pub fn snapshot() -> Value {
+    let sinks = config::log_sinks();
+    let sinks: Vec<&str> = [("shm", sinks.shm), ("file", sinks.file)]
+        .into_iter()
+        .filter_map(|(name, on)| on.then_some(name))
+        .collect();
+    json!({
+        "service": "router-core",
+        "version": env!("CARGO_PKG_VERSION"),
+        "settings_file": std::env::var(config::ENV_CONFIG_FILE).ok().filter(|path| !path.trim().is_empty()),
+        "protocol": {
+            "address": config::prottp_address().unwrap_or_else(|e| e),
+            "token_set": config::prottp_token().is_some(),
+            "buffer_size": config::prottp_buffer_size(),
+            "max_body": config::prottp_max_body(),
+        },
+        "gateway": {
+            "cache_ttl": config::gateway_cache_ttl().as_secs(),
+            "unhealthy_ttl": config::gateway_unhealthy_ttl().as_secs(),
+            "connect_retries": config::gateway_connect_retries(),
+            "connect_timeout": secs(config::gateway_connect_timeout()),
+            "header_timeout": secs(config::gateway_header_timeout()),
+            "total_timeout": secs(config::gateway_total_timeout()),
+            "max_requests": config::gateway_max_requests(),
+            "allow_dot_segments": config::gateway_allow_dot_segments(),
+            "fallback": config::setting(config::ENV_GATEWAY_FALLBACK),
+            "compress_min_size": config::compress_min_size(),
+            "redact_max_body": config::redact_max_body(),
+            "sticky_cookie": config::sticky_cookie(),
+            "sticky_ttl": secs(config::sticky_ttl()),
+            "via": config::gateway_via(),
+            "trace_token_set": config::gateway_trace_token().is_some(),
+            "mirror": {
+                "max_body": config::mirror_max_body(),
+                "max_in_flight": config::mirror_max_in_flight(),
+            },
+            "route_script": {
+                "max_operations": config::route_script_max_operations(),
+                "timeout_ms": config::route_script_timeout().as_millis() as u64,
+            },
+        },
+        "proxy": {
+            "max_connections": config::proxy_max_connections(),
+            "idle_timeout": secs(config::proxy_idle_timeout()),
+            "ws_idle_timeout": secs(config::ws_idle_timeout()),
+            "ws_ping_interval": secs(config::ws_ping_interval()),
+            "ws_frame_counts": config::ws_frame_counts(),
+        },
+        "log": {
+            "levels": level::current(),
+            "sinks": sinks,
+            "overflow_warn_interval": config::log_overflow_warn_interval(),
+            "access_log": {
+                "file": config::access_log_file(),
+                "format": config::access_log_format(),
+                "max_size": config::access_log_max_size(),
+                "rotate_interval": secs(config::access_log_rotate_interval()),
+                "keep": config::access_log_keep(),
+            },
+        },
+        "default_pages": {
+            "not_found": DEFAULT_PORT.p404,
+            "error": DEFAULT_PORT.p500,
+            "timeout": DEFAULT_PORT.p504,
+            "maintenance": DEFAULT_PORT.p503,
+            "tls_honeypot": DEFAULT_PORT.tls_honeypot,
+        },
+    })
+}
+
+/// Prints the effective configuration as pretty JSON to stdout.
+///
+/// Reads the log level settings first, nothing else has at this point.
+pub fn print() {
+    level::init_from_env();
+    match serde_json::to_string_pretty(&snapshot()) {
+        Ok(dump) => println!("{}", dump),
+        Err(e) => eprintln!("[----] Failed to serialize the configuration: {}", e),
+    }
+}
+
+/// Logs the effective configuration as one line.
+pub fn log() {
+    log::info!("Effective configuration: {}", snapshot());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_sections() {
+        let snapshot = snapshot();
+        for section in ["protocol", "gateway", "proxy", "log", "default_pages"] {
+            assert!(snapshot[section].is_object(), "{} is missing", section);
+        }
+        assert_eq!(snapshot["service"], "router-core");
+        assert!(snapshot["protocol"]["token_set"].is_boolean());
+        assert!(snapshot["protocol"].get("token").is_none());
+        assert!(snapshot["gateway"]["trace_token_set"].is_boolean());
+        assert_eq!(snapshot["default_pages"]["timeout"], DEFAULT_PORT.p504);
+    }
+
+    #[test]
+    fn test_secs() {
+        assert_eq!(secs(Some(Duration::from_secs(90))), json!(90));
+        assert_eq!(secs(None), Value::Null);
+    }
+}
@@ -0,0 +1,248 @@
+//! # Runtime Log Levels
+//!
+//! Per-component thresholds deciding which log lines reach the shared-memory
+//! queues. Entries below a component's threshold are dropped by the producer
+//! before they are enqueued.
+//!
+//! ## Components
+//!
+//! * `proxy` - `[PXY]` lines
+//! * `gateway` - `[GWX]` lines
+//! * `protocol` - `[NET]` lines and everything untagged
+//!
+//! Thresholds start from `RUST_LOG` (when it names a plain level), are refined
+//! by `GWRS_LOG_LEVEL_<COMPONENT>` and can be changed at runtime through the
+//! `/log/level` control route. Both are also read from the settings file and
+//! re-applied when it changes on SIGHUP.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+
+use super::{LEVEL_DEBUG, LEVEL_ERROR, LEVEL_INFO, LEVEL_TRACE, LEVEL_WARN};
+use crate::config::{self, ENV_LOG_LEVEL_PREFIX};
+
+static PROXY_LEVEL: AtomicU8 = AtomicU8::new(LEVEL_INFO);
+static GATEWAY_LEVEL: AtomicU8 = AtomicU8::new(LEVEL_INFO);
+static PROTOCOL_LEVEL: AtomicU8 = AtomicU8::new(LEVEL_INFO);
+
+/// A component with its own log threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Proxy,
+    Gateway,
+    Protocol,
+}
+
+impl Component {
+    pub const ALL: [Component; 3] = [Component::Proxy, Component::Gateway, Component::Protocol];
+
+    /// Maps a log tag (`[PXY]`, `[GWX]`, ...) to its component.
+    pub fn from_tag(tag: &str) -> Self {
+        match tag {
+            "[PXY]" => Component::Proxy,
+            "[GWX]" => Component::Gateway,
+            _ => Component::Protocol,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Proxy => "proxy",
+            Component::Gateway => "gateway",
+            Component::Protocol => "protocol",
+        }
+    }
+
+    fn slot(self) -> &'static AtomicU8 {
+        match self {
+            Component::Proxy => &PROXY_LEVEL,
+            Component::Gateway => &GATEWAY_LEVEL,
+            Component::Protocol => &PROTOCOL_LEVEL,
+        }
+    }
+
+    /// Current threshold as a `LEVEL_*` value.
+    pub fn threshold(self) -> u8 {
+        self.slot().load(Ordering::Relaxed)
+    }
+
+    /// Whether an entry of `level` passes this component's threshold.
+    pub fn enabled(self, level: u8) -> bool {
+        level >= self.threshold()
+    }
+
+    fn set_threshold(self, level: u8) {
+        self.slot().store(level, Ordering::Relaxed);
+    }
+}
+
+/// Component levels by name, as exchanged with the API.
+///
+/// Missing fields leave the component unchanged when applied.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogLevels {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+}
+
+impl LogLevels {
+    fn get(&self, component: Component) -> Option<&str> {
+        match component {
+            Component::Proxy => self.proxy.as_deref(),
+            Component::Gateway => self.gateway.as_deref(),
+            Component::Protocol => self.protocol.as_deref(),
+        }
+    }
+}
+
+/// Parses a level name (`trace`, `debug`, `info`, `warn`, `error`) into `LEVEL_*`.
+pub fn parse_level(name: &str) -> Option<u8> {
+    match name.trim().to_lowercase().as_str() {
+        "trace" => Some(LEVEL_TRACE),
+        "debug" => Some(LEVEL_DEBUG),
+        "info" => Some(LEVEL_INFO),
+        "warn" | "warning" => Some(LEVEL_WARN),
+        "error" => Some(LEVEL_ERROR),
+        _ => None,
+    }
+}
+
+pub fn level_name(level: u8) -> &'static str {
+    match level {
+        LEVEL_TRACE => "trace",
+        LEVEL_DEBUG => "debug",
+        LEVEL_INFO => "info",
+        LEVEL_WARN => "warn",
+        _ => "error",
+    }
+}
+
+/// Converts a `log::Level` into its `LEVEL_*` byte.
+pub fn from_log_level(level: log::Level) -> u8 {
+    match level {
+        log::Level::Error => LEVEL_ERROR,
+        log::Level::Warn => LEVEL_WARN,
+        log::Level::Info => LEVEL_INFO,
+        log::Level::Debug => LEVEL_DEBUG,
+        log::Level::Trace => LEVEL_TRACE,
+    }
+}
+
+/// The most verbose filter any component needs, for `log::set_max_level`.
+pub fn max_filter() -> LevelFilter {
+    let lowest = Component::ALL
+        .iter()
+        .map(|component| component.threshold())
+        .min()
+        .unwrap_or(LEVEL_INFO);
+    match lowest {
+        LEVEL_TRACE => LevelFilter::Trace,
+        LEVEL_DEBUG => LevelFilter::Debug,
+        LEVEL_INFO => LevelFilter::Info,
+        LEVEL_WARN => LevelFilter::Warn,
+        _ => LevelFilter::Error,
+    }
+}
+
+/// Returns the current level of every component.
+pub fn current() -> LogLevels {
+    LogLevels {
+        proxy: Some(level_name(Component::Proxy.threshold()).to_string()),
+        gateway: Some(level_name(Component::Gateway.threshold()).to_string()),
+        protocol: Some(level_name(Component::Protocol.threshold()).to_string()),
+    }
+}
+
+/// Applies the given levels, all or nothing.
+///
+/// # Errors
+///
+/// Returns a message naming the first unknown level; no component is changed then.
+pub fn apply(levels: &LogLevels) -> Result<(), String> {
+    let mut parsed = Vec::new();
+    for component in Component::ALL {
+        if let Some(name) = levels.get(component) {
+            let level = parse_level(name)
+                .ok_or_else(|| format!("unknown {} log level '{}'", component.name(), name))?;
+            parsed.push((component, level));
+        }
+    }
+    for (component, level) in parsed {
+        component.set_threshold(level);
+    }
+    log::set_max_level(max_filter());
+    Ok(())
+}
+
+/// Initializes the thresholds from `RUST_LOG` and `GWRS_LOG_LEVEL_<COMPONENT>`,
+/// in the environment or the settings file.
+///
+/// Runs before the logger exists, so invalid values are reported on stderr.
+pub fn init_from_env() {
+    let base = config::setting("RUST_LOG")
+        .and_then(|value| parse_level(&value))
+        .unwrap_or(LEVEL_INFO);
+
+    for component in Component::ALL {
+        let var = format!("{}{}", ENV_LOG_LEVEL_PREFIX, component.name().to_uppercase());
+        let level = match config::setting(&var) {
+            Some(value) => parse_level(&value).unwrap_or_else(|| {
+                eprintln!("[----] Invalid {}='{}', using {}", var, value, level_name(base));
+                base
+            }),
+            None => base,
+        };
+        component.set_threshold(level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_map_to_components() {
+        assert_eq!(Component::from_tag("[PXY]"), Component::Proxy);
+        assert_eq!(Component::from_tag("[GWX]"), Component::Gateway);
+        assert_eq!(Component::from_tag("[NET]"), Component::Protocol);
+        assert_eq!(Component::from_tag("-"), Component::Protocol);
+    }
+
+    #[test]
+    fn test_parse_level_round_trips() {
+        for level in [LEVEL_TRACE, LEVEL_DEBUG, LEVEL_INFO, LEVEL_WARN, LEVEL_ERROR] {
+            assert_eq!(parse_level(level_name(level)), Some(level));
+        }
+        assert_eq!(parse_level(" DEBUG "), Some(LEVEL_DEBUG));
+        assert_eq!(parse_level("verbose"), None);
+    }
+
+    #[test]
+    fn test_apply_is_all_or_nothing() {
+        let before = current();
+        let invalid = LogLevels {
+            proxy: Some("debug".to_string()),
+            gateway: Some("loud".to_string()),
+            protocol: None,
+        };
+        assert!(apply(&invalid).is_err());
+        assert_eq!(current(), before);
+
+        let valid = LogLevels {
+            proxy: Some("debug".to_string()),
+            ..LogLevels::default()
+        };
+        assert!(apply(&valid).is_ok());
+        assert!(Component::Proxy.enabled(LEVEL_DEBUG));
+        assert!(!Component::Proxy.enabled(LEVEL_TRACE));
+        assert_eq!(current().gateway, before.gateway);
+
+        apply(&before).unwrap();
+    }
+}
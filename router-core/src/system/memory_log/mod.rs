@@ -1,13 +1,16 @@
 // A raw implementation of shared memory in Rust using direct system calls
 // Now with support for both x86_64 and ARM64 (aarch64) architectures
+pub mod level;
 pub mod sender;
 
+use std::borrow::Cow;
 use std::ffi::CString;
 use std::io::{self, Error, ErrorKind};
 use std::mem;
 use std::ptr;
 use std::slice;
-use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
 
 // Architecture detection
 #[cfg(target_arch = "x86_64")]
@@ -225,9 +228,10 @@ pub struct SharedMemoryProducer {
     overflow_policy: OverflowPolicy,
 }
 
-// Safety: SharedMemoryProducer operations are not thread-safe by default
-// Users must ensure proper synchronization when sharing between threads
+// Safety: the pointers are fixed after creation, and the queue they point to is
+// only changed while holding the lock in its `QueueControl`
 unsafe impl Send for SharedMemoryProducer {}
+unsafe impl Sync for SharedMemoryProducer {}
 
 // Implementing producer
 impl SharedMemoryProducer {
@@ -515,8 +519,8 @@ impl SharedMemoryProducer {
                                     .overflow_count
                                     .fetch_add(1, Ordering::Relaxed);
 
-                                // Unlock and return error
-                                (*self.control).unlock();
+                                // The guard unlocks, unlocking here as well could
+                                // release the lock of the next writer
                                 return Err(Error::new(ErrorKind::Other, "Queue is full"));
                             }
                             OverflowPolicy::Overwrite => {
@@ -640,18 +644,34 @@ pub struct LogEntry {
 // Log producer implementation
 pub struct LogProducer {
     shm: SharedMemoryProducer,
+    // Overflow count when the last warning was printed
+    reported_overflow: AtomicUsize,
+    // Unix time of the last overflow warning
+    last_warning: AtomicU64,
 }
 
-// Safety: LogProducer operations are not thread-safe by default
-// Users must ensure proper synchronization when sharing between threads
-unsafe impl Send for LogProducer {}
+// Minimum seconds between two overflow warnings of a logger, 0 disables them
+static OVERFLOW_WARN_INTERVAL: AtomicU64 = AtomicU64::new(60);
+
+/// Sets the minimum seconds between two log ring overflow warnings, `0` disables them.
+pub fn set_overflow_warn_interval(seconds: u64) {
+    OVERFLOW_WARN_INTERVAL.store(seconds, Ordering::Relaxed);
+}
 
 impl LogProducer {
+    fn from_shm(shm: SharedMemoryProducer) -> Self {
+        LogProducer {
+            shm,
+            reported_overflow: AtomicUsize::new(0),
+            last_warning: AtomicU64::new(0),
+        }
+    }
+
     // Standard constructor - creates with default options
     #[allow(dead_code)]
     pub fn new(name: &str, size: usize) -> io::Result<Self> {
         let shm = SharedMemoryProducer::create(name, size)?;
-        Ok(LogProducer { shm })
+        Ok(LogProducer::from_shm(shm))
     }
 
     // Constructor with fresh start option - removes existing memory
@@ -663,7 +683,7 @@ impl LogProducer {
             true, // Fresh start
             OverflowPolicy::Block,
         )?;
-        Ok(LogProducer { shm })
+        Ok(LogProducer::from_shm(shm))
     }
 
     // Constructor with all options - enhanced with better error handling
@@ -704,7 +724,7 @@ impl LogProducer {
             }
         };
 
-        Ok(LogProducer { shm })
+        Ok(LogProducer::from_shm(shm))
     }
 
     // New constructor with capacity requirement
@@ -734,12 +754,11 @@ impl LogProducer {
             }
         };
 
-        Ok(LogProducer { shm })
+        Ok(LogProducer::from_shm(shm))
     }
 
     pub fn log(&self, level: u8, message: &str) -> io::Result<()> {
         let header_size = mem::size_of::<LogEntry>();
-        let total_size_of_payload = header_size + message.len();
 
         if message.is_empty() {
             println!("Empty message received");
@@ -748,51 +767,9 @@ impl LogProducer {
         
         // Maximum size an entry can hold for its payload (LogEntry + message)
         let max_payload_in_shm_entry = ENTRY_MAX_SIZE - mem::size_of::<usize>();
-
-        if total_size_of_payload > max_payload_in_shm_entry {
-            let suffix = "...";
-            let suffix_len = suffix.len();
-
-            // Calculate available space for the message text itself
-            let available_for_message_text = max_payload_in_shm_entry
-                .saturating_sub(header_size)
-                .saturating_sub(suffix_len);
-
-            let final_message_to_log: String;
-
-            if available_for_message_text == 0 {
-                if header_size + suffix_len <= max_payload_in_shm_entry {
-                    final_message_to_log = suffix.to_string();
-                } else {
-                    return Err(Error::new(
-                        ErrorKind::InvalidInput,
-                        "Log entry too small for header and truncation suffix.",
-                    ));
-                }
-            } else {
-                // Ensure cut_at does not exceed message length and respects char boundaries
-                let mut cut_at = std::cmp::min(message.len(), available_for_message_text);
-                while !message.is_char_boundary(cut_at) && cut_at > 0 {
-                    cut_at -= 1;
-                }
-
-                if cut_at == 0 && !message.is_empty() {
-                    if header_size + suffix_len <= max_payload_in_shm_entry {
-                        final_message_to_log = suffix.to_string();
-                    } else {
-                        return Err(Error::new(
-                            ErrorKind::InvalidInput,
-                            "Log entry too small for even one char and truncation suffix.",
-                        ));
-                    }
-                } else {
-                    final_message_to_log = format!("{}{}", &message[..cut_at], suffix);
-                }
-            }
-
-            // Call self recursively with the truncated message
-            return self.log(level, &final_message_to_log);
-        }
+        let message = truncate_message(message, max_payload_in_shm_entry - header_size);
+        let message = message.as_ref();
+        let total_size_of_payload = header_size + message.len();
 
         // Prepare the buffer
         let mut buffer = Vec::with_capacity(total_size_of_payload);
@@ -820,12 +797,46 @@ impl LogProducer {
         buffer.extend_from_slice(message.as_bytes());
 
         // Send to shared memory with better error reporting
-        match self.shm.enqueue(&buffer) {
-            Ok(_) => {
-                Ok(())
+        let result = self.shm.enqueue(&buffer);
+        self.report_overflow(timestamp);
+        result
+    }
+
+    /// Prints a warning to stderr when entries were dropped since the last one,
+    /// at most once per overflow warning interval.
+    ///
+    /// Goes to stderr rather than through `log`, which would feed the full ring.
+    fn report_overflow(&self, now: u64) {
+        let dropped = self.shm.overflow_count();
+        let reported = self.reported_overflow.load(Ordering::Relaxed);
+        if dropped <= reported {
+            if dropped < reported {
+                // The ring was reset
+                self.reported_overflow.store(dropped, Ordering::Relaxed);
             }
-            Err(e) => Err(e),
+            return;
+        }
+
+        let interval = OVERFLOW_WARN_INTERVAL.load(Ordering::Relaxed);
+        let last = self.last_warning.load(Ordering::Relaxed);
+        if interval == 0 || now < last.saturating_add(interval) {
+            return;
         }
+        // Only the thread that wins the update prints
+        if self
+            .last_warning
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        self.reported_overflow.store(dropped, Ordering::Relaxed);
+        eprintln!(
+            "[-LO-] WARN log ring {} overflowing, {} entries dropped ({} in total), statistics are incomplete",
+            self.shm.shm_name.to_string_lossy(),
+            dropped - reported,
+            dropped
+        );
     }
 
     #[allow(dead_code)]
@@ -852,163 +863,243 @@ impl LogProducer {
     }
 }
 
+/// Cuts `message` to at most `max_len` bytes, ending it with `...` when cut.
+///
+/// The cut is made at a character boundary, so a multi-byte character is never
+/// split and the entry stays valid UTF-8 for the consumer.
+fn truncate_message(message: &str, max_len: usize) -> Cow<'_, str> {
+    const SUFFIX: &str = "...";
+    if message.len() <= max_len {
+        return Cow::Borrowed(message);
+    }
+    if max_len < SUFFIX.len() {
+        return Cow::Borrowed(&SUFFIX[..max_len]);
+    }
+    let mut cut_at = max_len - SUFFIX.len();
+    while !message.is_char_boundary(cut_at) {
+        cut_at -= 1;
+    }
+    Cow::Owned(format!("{}{}", &message[..cut_at], SUFFIX))
+}
+
 // Default configuration for global logger
 const PROXY_LOGGER_NAME: &str = "/gwrs-proxy";
 const GATEWAY_LOGGER_NAME: &str = "/gwrs-gateway";
 
-// Global logger instances
-static mut GLOBAL_LOG_PROXY: Option<LogProducer> = None;
-static mut GLOBAL_LOG_GATEWAY: Option<LogProducer> = None;
-
-/// Gets or initializes the proxy logger instance
-///
-/// # Safety
-/// Not thread-safe. Should only be called from a single thread.
-#[allow(static_mut_refs)]
-pub unsafe fn proxy_logger() -> io::Result<&'static LogProducer> {
-    if GLOBAL_LOG_PROXY.is_none() {
-        eprintln!("[-LO-] Initializing proxy logger on {}...", ARCH_NAME);
-        // Request 10 million entries with smaller size
-        let desired_capacity = 10_000_000; // 10 million entries
-
-        // Create with capacity-based approach
-        match LogProducer::new_with_capacity(
-            PROXY_LOGGER_NAME,
-            desired_capacity,
+// Global logger instances, created by the first thread that logs
+static GLOBAL_LOG_PROXY: OnceLock<LogProducer> = OnceLock::new();
+static GLOBAL_LOG_GATEWAY: OnceLock<LogProducer> = OnceLock::new();
+
+// Serializes logger creation, so a shared memory region is only opened once
+static LOGGER_INIT: Mutex<()> = Mutex::new(());
+
+/// Creates a logger with room for 10 million entries, or `MAX_MEMORY_SIZE` bytes
+/// when the capacity-based approach fails
+fn create_logger(name: &str, label: &str) -> io::Result<LogProducer> {
+    eprintln!("[-LO-] Initializing {} logger on {}...", label, ARCH_NAME);
+    // Request 10 million entries with smaller size
+    let desired_capacity = 10_000_000; // 10 million entries
+
+    // Create with capacity-based approach
+    match LogProducer::new_with_capacity(
+        name,
+        desired_capacity,
+        true,                      // Force fresh start to clear memory
+        OverflowPolicy::Overwrite, // Overwrite when full
+    ) {
+        Ok(logger) => Ok(logger),
+        // Fall back to using default approach if capacity-based approach fails
+        Err(_) => LogProducer::new_with_options(
+            name,
+            MAX_MEMORY_SIZE,
             true,                      // Force fresh start to clear memory
             OverflowPolicy::Overwrite, // Overwrite when full
-        ) {
-            Ok(logger) => {
-                GLOBAL_LOG_PROXY = Some(logger);
-            }
-            Err(_) => {
-                // Fall back to using default approach if capacity-based approach fails
-                let logger_size = MAX_MEMORY_SIZE; // Use 1GB for more capacity
-
-                match LogProducer::new_with_options(
-                    PROXY_LOGGER_NAME,
-                    logger_size,
-                    true,                      // Force fresh start to clear memory
-                    OverflowPolicy::Overwrite, // Overwrite when full
-                ) {
-                    Ok(logger) => {
-                        GLOBAL_LOG_PROXY = Some(logger);
-                    }
-                    Err(e) => {
-                        return Err(e);
-                    }
-                }
-            }
-        }
-    }
-
-    match &GLOBAL_LOG_PROXY {
-        Some(logger) => Ok(logger),
-        None => Err(Error::new(
-            ErrorKind::Other,
-            "Failed to initialize proxy logger",
-        )),
+        ),
     }
 }
 
-/// Gets or initializes the gateway logger instance
+/// Gets the logger in `cell`, creating it on first use.
 ///
-/// # Safety
-/// Not thread-safe. Should only be called from a single thread.
-#[allow(static_mut_refs)]
-pub unsafe fn gateway_logger() -> io::Result<&'static LogProducer> {
-    if GLOBAL_LOG_GATEWAY.is_none() {
-        eprintln!("[-LO-] Initializing gateway logger on {}...", ARCH_NAME);
-        // Request 10 million entries with smaller size
-        let desired_capacity = 10_000_000; // 10 million entries
-
-        // Create with capacity-based approach
-        match LogProducer::new_with_capacity(
-            GATEWAY_LOGGER_NAME,
-            desired_capacity,
-            true,                      // Force fresh start to clear memory
-            OverflowPolicy::Overwrite, // Overwrite when full
-        ) {
-            Ok(logger) => {
-                GLOBAL_LOG_GATEWAY = Some(logger);
-            }
-            Err(_) => {
-                // Fall back to using default approach if capacity-based approach fails
-                let logger_size = MAX_MEMORY_SIZE; // Use 1GB for more capacity
-
-                match LogProducer::new_with_options(
-                    GATEWAY_LOGGER_NAME,
-                    logger_size,
-                    true,                      // Force fresh start to clear memory
-                    OverflowPolicy::Overwrite, // Overwrite when full
-                ) {
-                    Ok(logger) => {
-                        GLOBAL_LOG_GATEWAY = Some(logger);
-                    }
-                    Err(e) => {
-                        return Err(e);
-                    }
-                }
-            }
-        }
+/// Safe to call from any thread; when several threads race on the first
+/// call exactly one creates the logger. A failed creation is retried on the
+/// next call.
+fn logger_in(
+    cell: &'static OnceLock<LogProducer>,
+    create: impl FnOnce() -> io::Result<LogProducer>,
+) -> io::Result<&'static LogProducer> {
+    if let Some(logger) = cell.get() {
+        return Ok(logger);
     }
-
-    match &GLOBAL_LOG_GATEWAY {
-        Some(logger) => Ok(logger),
-        None => Err(Error::new(
-            ErrorKind::Other,
-            "Failed to initialize gateway logger",
-        )),
+    let _init = LOGGER_INIT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(logger) = cell.get() {
+        return Ok(logger);
     }
+    let logger = create()?;
+    Ok(cell.get_or_init(|| logger))
+}
+
+/// Gets or initializes the proxy logger instance
+pub fn proxy_logger() -> io::Result<&'static LogProducer> {
+    logger_in(&GLOBAL_LOG_PROXY, || create_logger(PROXY_LOGGER_NAME, "proxy"))
+}
+
+/// Gets or initializes the gateway logger instance
+pub fn gateway_logger() -> io::Result<&'static LogProducer> {
+    logger_in(&GLOBAL_LOG_GATEWAY, || create_logger(GATEWAY_LOGGER_NAME, "gateway"))
 }
 
 /// Log a message using the proxy logger
-///
-/// # Safety
-/// Not thread-safe. Should only be called from a single thread.
-pub unsafe fn log_proxy(level: u8, message: &str) -> io::Result<()> {
-    match proxy_logger() {
-        Ok(logger) => match logger.log(level, message) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
-        },
-        Err(e) => Err(e),
-    }
+pub fn log_proxy(level: u8, message: &str) -> io::Result<()> {
+    proxy_logger()?.log(level, message)
 }
 
 /// Log a message using the gateway logger
+pub fn log_gateway(level: u8, message: &str) -> io::Result<()> {
+    gateway_logger()?.log(level, message)
+}
+
+/// Returns the number of entries still queued in the proxy and gateway loggers.
 ///
-/// # Safety
-/// Not thread-safe. Should only be called from a single thread.
-pub unsafe fn log_gateway(level: u8, message: &str) -> io::Result<()> {
-    match gateway_logger() {
-        Ok(logger) => match logger.log(level, message) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
-        },
-        Err(e) => Err(e),
-    }
+/// Loggers that were never initialized report zero pending entries.
+pub fn log_pending() -> (usize, usize) {
+    let proxy = GLOBAL_LOG_PROXY.get().map_or(0, |logger| logger.queue_size());
+    let gateway = GLOBAL_LOG_GATEWAY.get().map_or(0, |logger| logger.queue_size());
+    (proxy, gateway)
 }
 
-#[allow(static_mut_refs)]
+/// Unlinks the shared memory of both loggers.
+///
+/// The mappings stay valid, so threads still logging during shutdown write
+/// into memory that is released when the process exits.
 pub fn log_cleanup() -> io::Result<()> {
     let mut result = Ok(());
 
-    unsafe {
-        if let Some(logger) = GLOBAL_LOG_PROXY.take() {
-            if let Err(e) = logger.cleanup() {
+    if let Some(logger) = GLOBAL_LOG_PROXY.get() {
+        if let Err(e) = logger.cleanup() {
+            result = Err(e);
+        }
+    }
+
+    if let Some(logger) = GLOBAL_LOG_GATEWAY.get() {
+        if let Err(e) = logger.cleanup() {
+            if result.is_ok() {
                 result = Err(e);
             }
         }
+    }
 
-        if let Some(logger) = GLOBAL_LOG_GATEWAY.take() {
-            if let Err(e) = logger.cleanup() {
-                if result.is_ok() {
-                    result = Err(e);
-                }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_logging_initializes_once() {
+        const THREADS: usize = 8;
+        const PER_THREAD: usize = 500;
+        static LOGGER: OnceLock<LogProducer> = OnceLock::new();
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+        let name = format!("/gwrs-test-{}", std::process::id());
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|thread_no| {
+                let name = name.clone();
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    for i in 0..PER_THREAD {
+                        let logger = logger_in(&LOGGER, || {
+                            CREATED.fetch_add(1, Ordering::SeqCst);
+                            LogProducer::new_with_capacity(&name, 5_000, true, OverflowPolicy::Block)
+                        })
+                        .unwrap();
+                        logger
+                            .log(LEVEL_INFO, &format!("thread {} message {}", thread_no, i))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let logger = LOGGER.get().unwrap();
+        assert_eq!(CREATED.load(Ordering::SeqCst), 1);
+        assert_eq!(logger.queue_size(), THREADS * PER_THREAD);
+        assert_eq!(logger.overflow_count(), 0);
+        logger.cleanup().unwrap();
+    }
+
+    #[test]
+    fn test_truncate_message_keeps_char_boundaries() {
+        assert_eq!(truncate_message("short", 16), "short");
+        assert_eq!(truncate_message("abcdefgh", 6), "abc...");
+
+        // 2, 3 and 4 byte characters, cut at every offset around the limit
+        for ch in ['é', '€', '🦀'] {
+            let message: String = std::iter::repeat(ch).take(20).collect();
+            for max_len in 3..message.len() {
+                let truncated = truncate_message(&message, max_len);
+                assert!(truncated.len() <= max_len, "{} within {}", truncated, max_len);
+                assert!(truncated.len() + ch.len_utf8() > max_len);
+                let kept = truncated.strip_suffix("...").unwrap();
+                assert!(message.starts_with(kept));
+                assert!(kept.chars().all(|c| c == ch));
             }
         }
     }
 
-    result
-}
\ No newline at end of file
+    #[test]
+    fn test_multibyte_message_at_entry_limit_is_valid_utf8() {
+        let name = format!("/gwrs-test-utf8-{}", std::process::id());
+        let logger = LogProducer::new_with_capacity(&name, 1_000, true, OverflowPolicy::Block).unwrap();
+        let max_len = ENTRY_MAX_SIZE - mem::size_of::<usize>() - mem::size_of::<LogEntry>();
+        // Shifting by one ASCII byte moves the limit into each byte of a 3 byte character
+        for (index, prefix) in ["", "a", "ab"].into_iter().enumerate() {
+            let message = format!("{}{}", prefix, "€".repeat(max_len / 3 + 1));
+            logger.log(LEVEL_INFO, &message).unwrap();
+
+            let entry = unsafe {
+                logger
+                    .shm
+                    .data_start
+                    .add(index * ENTRY_MAX_SIZE + mem::size_of::<usize>())
+            };
+            let header = unsafe { ptr::read_unaligned(entry as *const LogEntry) };
+            let stored = unsafe {
+                slice::from_raw_parts(entry.add(mem::size_of::<LogEntry>()), header.message_len as usize)
+            };
+            let stored = std::str::from_utf8(stored).unwrap();
+            assert!(stored.len() <= max_len);
+            assert!(stored.starts_with(prefix) && stored.ends_with("€..."));
+        }
+        assert_eq!(logger.queue_size(), 3);
+        logger.cleanup().unwrap();
+    }
+
+    #[test]
+    fn test_overflow_is_reported_once_per_interval() {
+        let name = format!("/gwrs-test-overflow-{}", std::process::id());
+        let logger = LogProducer::new_with_capacity(&name, 1_000, true, OverflowPolicy::Block).unwrap();
+        let capacity = logger.capacity();
+        for i in 0..capacity + 5 {
+            let _ = logger.log(LEVEL_INFO, &format!("message {}", i));
+        }
+        // The first dropped entry is reported right away, the following ones
+        // fall within the interval of that warning
+        assert_eq!(logger.overflow_count(), 5);
+        assert_eq!(logger.reported_overflow.load(Ordering::Relaxed), 1);
+
+        logger.last_warning.store(0, Ordering::Relaxed);
+        let _ = logger.log(LEVEL_INFO, "one more");
+        assert_eq!(logger.overflow_count(), 6);
+        assert_eq!(logger.reported_overflow.load(Ordering::Relaxed), 6);
+        logger.cleanup().unwrap();
+    }
+}
@@ -1,30 +1,28 @@
+use super::level::{from_log_level, Component};
 use super::{log_gateway, log_proxy};
 
 
 
 pub fn switcher(marker: &str, level:log::Level, message: &str) {
     
-    let level = match level {
-        log::Level::Error => super::LEVEL_ERROR,
-        log::Level::Warn => super::LEVEL_WARN,
-        log::Level::Info => super::LEVEL_INFO,
-        log::Level::Debug => super::LEVEL_DEBUG,
-        log::Level::Trace => super::LEVEL_TRACE,
-    };
+    let level = from_log_level(level);
+
+    // Drop entries below the component threshold before they hit the queue
+    if !Component::from_tag(marker).enabled(level) {
+        return;
+    }
     
     match marker {
-        "[PXY]" => unsafe {
-            let res = log_proxy(level, message);
-            if let Err(e) = res {
+        "[PXY]" => {
+            if let Err(e) = log_proxy(level, message) {
                 eprintln!("[MEMLOG::PX] {}", e);
             }
-        },
-        "[GWX]" => unsafe {
-            let res = log_gateway(level, message);
-            if let Err(e) = res {
+        }
+        "[GWX]" => {
+            if let Err(e) = log_gateway(level, message) {
                 eprintln!("[MEMLOG::GW] {}", e);
             }
-        },
+        }
         _ => (),
     }
-}
\ No newline at end of file
+}
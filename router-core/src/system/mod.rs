@@ -7,8 +7,14 @@
 //! ## Module Structure
 //! 
 //! * `default_page`: Handlers for serving default content for error conditions and security monitoring
+//! * `effective_config`: The merged settings as JSON, logged at startup and printed by `--print-config`
 //! * `protocol`: Implementation of the custom protocol for inter-service communication
+//! * `reload`: Re-reads the settings file on SIGHUP and applies the reloadable settings
 //! * `server`: Core server initialization and management functionality
+//! * `sni`: Exact and wildcard host name matching for TLS certificates and gateway rules
+//! * `sockopt`: Per-proxy TCP_NODELAY and keepalive settings for proxied sockets
+//! * `startup`: Listen address checks run before the servers start, with their error types
+//! * `tls_policy`: Per-proxy minimum TLS version, ciphers and SNI requirement of TLS listeners
 //! * `terminator`: Signal handling and graceful shutdown mechanisms
 //! * `listeners`: Module for managing network listeners
 //! 
@@ -18,11 +24,17 @@
 //! managing network connections, server lifecycle, and system-level protocols.
 
 pub mod default_page;
+pub mod effective_config;
 pub mod server;
+pub mod sni;
+pub mod sockopt;
+pub mod startup;
 pub mod terminator;
+pub mod tls_policy;
 pub mod writer;
 pub mod memory_log;
 pub mod prottp;
+pub mod reload;
 
 // unused
 // pub mod netlisten;
\ No newline at end of file
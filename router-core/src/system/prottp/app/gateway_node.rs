@@ -1,8 +1,64 @@
 use crate::config::{self, GatewayNode, GatewayNodeSNI};
 use crate::system::prottp::app::tls_tools::AppTlsTools;
-use crate::system::terminator;
+use crate::system::tls_policy::TlsPolicy;
+use crate::system::{server, terminator};
 
-pub fn init(payload: String) -> Result<(), serde_json::Error> {
+/// Parses and checks a payload like [`init`] and the server start would, without applying it
+pub fn validate(payload: String, _request_id: &str) -> Result<(), Vec<String>> {
+    let nodes = serde_json::from_str::<Vec<GatewayNode>>(&payload)
+        .map_err(|e| vec![format!("Invalid gateway node data: {}", e)])?;
+
+    let mut errors = Vec::new();
+    for node in &nodes {
+        let owner = format!("Gateway node '{}'", node.addr_listen);
+        errors.extend(super::listen_addr_error(&owner, &node.addr_listen));
+        for tls in node.tls.iter().filter(|tls| tls.tls) {
+            if super::missing_tls_material(&tls.tls_pem, &tls.tls_key) {
+                errors.push(format!(
+                    "{}: TLS for {:?} needs a certificate and a key",
+                    owner, tls.sni
+                ));
+            }
+        }
+        if node.tls.iter().any(|tls| tls.tls) {
+            if let Err(e) = TlsPolicy::of_gateway(node).and_then(|policy| policy.check()) {
+                errors.push(format!("{}: {}", owner, e));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Whether `old` and `new` differ in nothing but their certificate and key files.
+///
+/// Such a change, an ACME renewal for one, is swapped into the running listeners.
+pub fn certificates_only(old: &[GatewayNode], new: &[GatewayNode]) -> bool {
+    let without_files = |nodes: &[GatewayNode]| -> Vec<GatewayNode> {
+        nodes
+            .iter()
+            .cloned()
+            .map(|node| GatewayNode {
+                tls: node
+                    .tls
+                    .into_iter()
+                    .map(|tls| GatewayNodeSNI {
+                        tls_pem: None,
+                        tls_key: None,
+                        ..tls
+                    })
+                    .collect(),
+                ..node
+            })
+            .collect()
+    };
+    without_files(old) == without_files(new)
+}
+
+pub fn init(payload: String, request_id: &str) -> Result<(), serde_json::Error> {
     let checksum = {
         use sha2::{Digest, Sha256};
         let mut hasher = Sha256::new();
@@ -53,9 +109,18 @@ pub fn init(payload: String) -> Result<(), serde_json::Error> {
         }
     };
 
+    let current = config::RoutingData::GatewayNodeListen
+        .xget::<Vec<GatewayNode>>()
+        .unwrap_or(vec![]);
+
+    config::RoutingData::GatewayRequestID.set(request_id);
     config::RoutingData::GatewayNodeID.set(&checksum);
     config::RoutingData::GatewayNodeListen.xset(&gwnode_data);
 
+    // New certificates are loaded in place, other changes restart the listeners
+    if certificates_only(&current, &gwnode_data) && server::reload_gateway_certs(&gwnode_data) {
+        return Ok(());
+    }
     terminator::service::init();
     Ok(())
 }
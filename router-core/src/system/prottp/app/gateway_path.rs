@@ -1,7 +1,20 @@
+use crate::app::gateway_fast;
 use crate::config::{self, GatewayPath};
 
+/// Parses and checks a payload like [`init`] and the gateway reload would, without applying it
+pub fn validate(payload: String, _request_id: &str) -> Result<(), Vec<String>> {
+    let paths = serde_json::from_str::<Vec<GatewayPath>>(&payload)
+        .map_err(|e| vec![format!("Invalid gateway path data: {}", e)])?;
+    let errors = gateway_fast::validate_rules(&paths);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
 // path stay as it is
-pub fn init(payload: String) -> Result<(), serde_json::Error> {
+pub fn init(payload: String, request_id: &str) -> Result<(), serde_json::Error> {
     let checksum = {
         use sha2::{Digest, Sha256};
         let mut hasher = Sha256::new();
@@ -65,8 +78,10 @@ pub fn init(payload: String) -> Result<(), serde_json::Error> {
     );
     eprintln!("[-TC-]   Addresses to add: {:?}", addresses_to_add.len());
 
-    config::RoutingData::GatewayID.set(&checksum);
+    config::RoutingData::GatewayRequestID.set(request_id);
     config::RoutingData::GatewayRouting.xset(&gateway_data);
+    config::RoutingData::GatewayID.set(&checksum);
+    config::notify_gateway_config();
 
     Ok(())
 }
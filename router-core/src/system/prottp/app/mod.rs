@@ -1,4 +1,19 @@
 pub mod gateway_node;
 pub mod gateway_path;
 pub mod proxy_node;
-pub mod tls_tools;
\ No newline at end of file
+pub mod tls_tools;
+
+use std::net::ToSocketAddrs;
+
+/// Checks that a listen address can be bound, returning why not
+fn listen_addr_error(owner: &str, addr: &str) -> Option<String> {
+    match addr.to_socket_addrs() {
+        Ok(mut addrs) if addrs.next().is_some() => None,
+        _ => Some(format!("{}: invalid listen address '{}'", owner, addr)),
+    }
+}
+
+/// Whether a TLS entry lacks the certificate or key it needs
+fn missing_tls_material(pem: &Option<String>, key: &Option<String>) -> bool {
+    pem.as_deref().map_or(true, str::is_empty) || key.as_deref().map_or(true, str::is_empty)
+}
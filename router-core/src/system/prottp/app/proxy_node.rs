@@ -1,9 +1,79 @@
 use crate::config::{self, ProxyNode};
 use crate::system::prottp::app::tls_tools::AppTlsTools;
 use crate::system::terminator;
+use crate::system::tls_policy::TlsPolicy;
+
+/// Parses and checks a payload like [`init`] and the server start would, without applying it
+pub fn validate(payload: String, _request_id: &str) -> Result<(), Vec<String>> {
+    let nodes = serde_json::from_str::<Vec<ProxyNode>>(&payload)
+        .map_err(|e| vec![format!("Invalid proxy node data: {}", e)])?;
+
+    let mut errors = Vec::new();
+    // Only speed mode and redirect proxies are started by the core
+    for node in nodes.iter().filter(|node| node.high_speed || node.redirect_to_https) {
+        let owner = format!("Proxy '{}'", node.addr_listen);
+        // Only the speed mode stream proxy listens on Unix sockets
+        if !(node.high_speed && config::unix_socket_path(&node.addr_listen).is_some()) {
+            errors.extend(super::listen_addr_error(&owner, &node.addr_listen));
+        }
+        if node.redirect_to_https {
+            if node.redirect_https_port == Some(0) {
+                errors.push(format!("{}: redirect HTTPS port must not be 0", owner));
+            }
+            continue;
+        }
+        // The upstream peer is created from a socket address literal
+        let target = node.high_speed_addr.as_ref().unwrap_or(&node.addr_target);
+        if config::unix_socket_path(target).is_none() && target.parse::<std::net::SocketAddr>().is_err() {
+            errors.push(format!("{}: invalid target address '{}'", owner, target));
+        }
+        if let Some(size) = node.buffer_size {
+            if !(config::MIN_PROXY_BUFFER_SIZE..=config::MAX_PROXY_BUFFER_SIZE).contains(&size) {
+                errors.push(format!(
+                    "{}: buffer size {} is outside {}..={} bytes",
+                    owner, size, config::MIN_PROXY_BUFFER_SIZE, config::MAX_PROXY_BUFFER_SIZE
+                ));
+            }
+        }
+        if node.tls {
+            if let Err(e) = TlsPolicy::of_proxy(node).and_then(|policy| policy.check()) {
+                errors.push(format!("{}: {}", owner, e));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Whether `old` and `new` differ in nothing but certificate and key files no listener of the core serves.
+///
+/// Only speed mode proxies terminate TLS on their own, the certificates of the
+/// other proxies reach the core with the gateway nodes.
+pub fn unused_certificates_only(old: &[ProxyNode], new: &[ProxyNode]) -> bool {
+    let served = |nodes: &[ProxyNode]| -> Vec<ProxyNode> {
+        nodes
+            .iter()
+            .cloned()
+            .map(|node| {
+                if node.high_speed && !node.redirect_to_https {
+                    return node;
+                }
+                ProxyNode {
+                    tls_pem: None,
+                    tls_key: None,
+                    ..node
+                }
+            })
+            .collect()
+    };
+    served(old) == served(new)
+}
 
 /// now proxy data always accept high speed.
-pub fn init(payload: String) -> Result<(), serde_json::Error> {
+pub fn init(payload: String, request_id: &str) -> Result<(), serde_json::Error> {
     let checksum = {
         use sha2::{Digest, Sha256};
         let mut hasher = Sha256::new();
@@ -45,10 +115,17 @@ pub fn init(payload: String) -> Result<(), serde_json::Error> {
             return Err(e);
         }
     };
+    let current = config::RoutingData::ProxyRouting
+        .xget::<Vec<ProxyNode>>()
+        .unwrap_or(vec![]);
+    let restart = !unused_certificates_only(&current, &proxy_data);
+    config::RoutingData::ProxyRequestID.set(request_id);
     config::RoutingData::ProxyID.xset(checksum);
     config::RoutingData::ProxyRouting.xset(proxy_data);
-    // restart services
 
-    terminator::service::init();
+    // restart services, unless only certificates the gateway listeners load changed
+    if restart {
+        terminator::service::init();
+    }
     Ok(())
 }
@@ -0,0 +1,134 @@
+//! # Runtime Commands
+//!
+//! Operational commands router-api sends to a running core as
+//! `GWRX /command/<action>`. Every command answers with a JSON body.
+//!
+//! | Action           | Body                         | Answer                                   |
+//! |------------------|------------------------------|------------------------------------------|
+//! | `flush-cache`    | -                            | `{"flushed": n}`, gateway caches cleared |
+//! | `reload`         | -                            | `{"changed": [..]}`, like a SIGHUP       |
+//! | `log-level`      | `LogLevels`, opt.            | current levels, an empty body only reads |
+//! | `shutdown`       | -                            | drain summary, the core exits afterwards |
+//! | `acme-challenge` | `token`, `key_authorization` | `{"pending": n}`, see `acme_challenge`   |
+//!
+//! `acme-challenge` without a `key_authorization` removes the token's challenge.
+//!
+//! The older `/gateway/cache/flush`, `/log/level` and `/shutdown` paths run
+//! the same commands.
+//!
+//! Like every protocol server request, commands need the
+//! `GWRS_PROTTP_TOKEN` bearer token when one is configured.
+
+use serde::Deserialize;
+
+use crate::app::{acme_challenge, gateway_fast};
+use crate::system::memory_log::level;
+use crate::system::{reload, terminator};
+
+/// An operational command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    FlushCache,
+    Reload,
+    LogLevel,
+    Shutdown,
+    AcmeChallenge,
+}
+
+/// Body of `acme-challenge`, without a key authorization the challenge is removed
+#[derive(Debug, Deserialize)]
+struct AcmeChallengeBody {
+    token: String,
+    #[serde(default)]
+    key_authorization: Option<String>,
+}
+
+impl Command {
+    /// The command behind a request path, `None` for any other path
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path {
+            "/command/flush-cache" | "/gateway/cache/flush" => Some(Command::FlushCache),
+            "/command/reload" => Some(Command::Reload),
+            "/command/log-level" | "/log/level" => Some(Command::LogLevel),
+            "/command/shutdown" | "/shutdown" => Some(Command::Shutdown),
+            "/command/acme-challenge" => Some(Command::AcmeChallenge),
+            _ => None,
+        }
+    }
+
+    /// Runs the command.
+    ///
+    /// # Returns
+    ///
+    /// The JSON answer, or the reason an invalid body was rejected.
+    pub fn run(self, body: &str) -> Result<serde_json::Value, String> {
+        match self {
+            Command::FlushCache => Ok(serde_json::json!({
+                "flushed": gateway_fast::flush_route_caches()
+            })),
+            Command::Reload => Ok(serde_json::json!({ "changed": reload::reload() })),
+            Command::LogLevel => {
+                // An empty body only reads the current levels
+                if !body.trim().is_empty() {
+                    serde_json::from_str::<level::LogLevels>(body)
+                        .map_err(|e| format!("invalid log level body: {}", e))
+                        .and_then(|levels| level::apply(&levels))?;
+                }
+                serde_json::to_value(level::current()).map_err(|e| e.to_string())
+            }
+            Command::Shutdown => {
+                serde_json::to_value(terminator::service::shutdown()).map_err(|e| e.to_string())
+            }
+            Command::AcmeChallenge => {
+                let challenge = serde_json::from_str::<AcmeChallengeBody>(body)
+                    .map_err(|e| format!("invalid ACME challenge body: {}", e))?;
+                match challenge.key_authorization {
+                    Some(key_authorization) => {
+                        acme_challenge::publish(&challenge.token, &key_authorization)?
+                    }
+                    None => {
+                        acme_challenge::remove(&challenge.token);
+                    }
+                }
+                Ok(serde_json::json!({ "pending": acme_challenge::pending() }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_paths() {
+        assert_eq!(Command::from_path("/command/reload"), Some(Command::Reload));
+        assert_eq!(Command::from_path("/command/flush-cache"), Some(Command::FlushCache));
+        assert_eq!(Command::from_path("/gateway/cache/flush"), Some(Command::FlushCache));
+        assert_eq!(Command::from_path("/log/level"), Some(Command::LogLevel));
+        assert_eq!(Command::from_path("/command/acme-challenge"), Some(Command::AcmeChallenge));
+        assert_eq!(Command::from_path("/command/drain"), None);
+        assert_eq!(Command::from_path("/gateway/node"), None);
+    }
+
+    #[test]
+    fn test_log_level_rejects_invalid_body() {
+        assert!(Command::LogLevel.run("{\"proxy\": \"loud\"}").is_err());
+        assert!(Command::LogLevel.run("not json").is_err());
+    }
+
+    #[test]
+    fn test_acme_challenge_body() {
+        let publish = r#"{"token": "cmdToken", "key_authorization": "cmdToken.thumbprint"}"#;
+        assert!(Command::AcmeChallenge.run(publish).unwrap()["pending"].as_u64() >= Some(1));
+        assert_eq!(
+            acme_challenge::answer("/.well-known/acme-challenge/cmdToken").as_deref(),
+            Some("cmdToken.thumbprint")
+        );
+
+        Command::AcmeChallenge.run(r#"{"token": "cmdToken"}"#).unwrap();
+        assert_eq!(acme_challenge::answer("/.well-known/acme-challenge/cmdToken"), None);
+        assert!(Command::AcmeChallenge.run(r#"{"token": "a/b", "key_authorization": "a/b.c"}"#).is_err());
+        assert!(Command::AcmeChallenge.run("").is_err());
+    }
+}
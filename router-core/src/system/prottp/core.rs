@@ -1,9 +1,12 @@
 use std::io::{BufRead, BufReader, Write};
 use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
 use std::thread;
 // use serde_json::Value;
 use std::io::Read;
 
+use crate::config;
+
 #[derive(Debug)]
 pub struct HttpRequest {
     pub method: String,
@@ -11,35 +14,72 @@ pub struct HttpRequest {
     // pub headers: std::collections::HashMap<String, String>,
     pub body: Vec<u8>,
     // pub json: Option<Value>,
+    /// Correlation ID from the `X-Request-Id` header, "-" when absent
+    pub request_id: String,
     pub stream: TcpStream,
 }
 
 pub struct HttpServer {
     address: String,
+    limits: Limits,
+    /// Bearer token every request must carry, `None` accepts any request
+    token: Option<Arc<str>>,
+}
+
+/// Read buffer and body limit of every connection
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    buffer_size: usize,
+    max_body_size: usize,
 }
 
 impl HttpServer {
+    /// Creates a server using the buffer size, body limit and token from the environment
     pub fn new(address: &str) -> Self {
         Self {
             address: address.to_string(),
+            limits: Limits {
+                buffer_size: config::prottp_buffer_size(),
+                max_body_size: config::prottp_max_body(),
+            },
+            token: config::prottp_token().map(Arc::from),
         }
     }
 
-    pub fn start<F>(&self, handler: F) -> std::io::Result<()>
+    /// Overrides the read buffer size and the largest accepted body, in bytes
+    pub fn with_limits(mut self, buffer_size: usize, max_body_size: usize) -> Self {
+        self.limits = Limits {
+            buffer_size: buffer_size.max(1),
+            max_body_size,
+        };
+        self
+    }
+
+    /// Overrides the bearer token requests must carry
+    pub fn with_token(mut self, token: Option<&str>) -> Self {
+        self.token = token.map(Arc::from);
+        self
+    }
+
+    /// Accepts connections on an already bound listener
+    pub fn serve<F>(&self, listener: TcpListener, handler: F) -> std::io::Result<()>
     where
         F: Fn(HttpRequest) + Send + Sync + 'static,
     {
-        let listener = TcpListener::bind(&self.address)?;
-        println!("[-PT-] HTTP Server listening on {}", self.address);
-
-        let handler = std::sync::Arc::new(handler);
+        println!(
+            "[-PT-] HTTP Server listening on {} (buffer {} bytes, max body {} bytes)",
+            self.address, self.limits.buffer_size, self.limits.max_body_size
+        );
+        let handler = Arc::new(handler);
+        let limits = self.limits;
 
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
                     let handler = handler.clone();
+                    let token = self.token.clone();
                     thread::spawn(move || {
-                        if let Err(e) = handle_connection(stream, handler) {
+                        if let Err(e) = handle_connection(stream, handler, limits, token.as_deref()) {
                             eprintln!("Error handling connection: {}", e);
                         }
                     });
@@ -52,80 +92,170 @@ impl HttpServer {
     }
 }
 
-fn handle_connection<F>(stream: TcpStream, handler: std::sync::Arc<F>) -> std::io::Result<()>
+/// Serves requests on one connection until the client closes it.
+///
+/// Connections are kept alive so the API can reuse pooled connections.
+/// A `Connection: close` header ends the loop after the current response.
+///
+/// Bodies larger than the limit, or with an unreadable `Content-Length`, are
+/// answered with a 400 and the connection is closed, since the unread body
+/// can't be told apart from the next request. For the same reason a request
+/// without the configured token gets a 401 and the connection is closed.
+fn handle_connection<F>(
+    stream: TcpStream,
+    handler: Arc<F>,
+    limits: Limits,
+    token: Option<&str>,
+) -> std::io::Result<()>
 where
     F: Fn(HttpRequest) + Send + Sync,
 {
-    let mut reader = BufReader::new(&stream);
-    
-    // Read request line
-    let mut request_line = String::new();
-    reader.read_line(&mut request_line)?;
-    
-    // Parse method and path
-    let parts: Vec<&str> = request_line.trim().split_whitespace().collect();
-    if parts.len() < 2 {
-        return Ok(()); // Invalid request, just close
-    }
-    
-    let method = parts[0].to_string();
-    let path = parts[1].to_string();
-    
-    // Read headers
-    let mut headers = std::collections::HashMap::new();
-    let mut content_length = 0;
-    
+    let mut reader = BufReader::with_capacity(limits.buffer_size, &stream);
+
     loop {
-        let mut line = String::new();
-        reader.read_line(&mut line)?;
-        let line = line.trim();
+        // Read request line, zero bytes means the client closed the connection
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line)? == 0 {
+            return Ok(());
+        }
         
-        if line.is_empty() {
-            break; // End of headers
+        // Parse method and path
+        let parts: Vec<&str> = request_line.trim().split_whitespace().collect();
+        if parts.len() < 2 {
+            return Ok(()); // Invalid request, just close
         }
         
-        if let Some(pos) = line.find(':') {
-            let key = line[..pos].trim().to_lowercase();
-            let value = line[pos + 1..].trim().to_string();
+        let method = parts[0].to_string();
+        let path = parts[1].to_string();
+        
+        // Read headers
+        let mut headers = std::collections::HashMap::new();
+        let mut content_length: Result<usize, String> = Ok(0);
+        
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim();
             
-            if key == "content-length" {
-                content_length = value.parse().unwrap_or(0);
+            if line.is_empty() {
+                break; // End of headers
             }
             
-            headers.insert(key, value);
+            if let Some(pos) = line.find(':') {
+                let key = line[..pos].trim().to_lowercase();
+                let value = line[pos + 1..].trim().to_string();
+                
+                if key == "content-length" {
+                    content_length = value
+                        .parse()
+                        .map_err(|_| format!("Invalid Content-Length '{}'", value));
+                }
+                
+                headers.insert(key, value);
+            }
+        }
+        
+        let content_length = match content_length {
+            Ok(length) if length > limits.max_body_size => {
+                return reject(
+                    &stream,
+                    "400 Bad Request",
+                    &format!(
+                        "Request body of {} bytes exceeds the limit of {} bytes ({})",
+                        length,
+                        limits.max_body_size,
+                        config::ENV_PROTTP_MAX_BODY
+                    ),
+                );
+            }
+            Ok(length) => length,
+            Err(e) => return reject(&stream, "400 Bad Request", &e),
+        };
+
+        if let Some(token) = token {
+            let given = headers
+                .get("authorization")
+                .and_then(|value| value.strip_prefix("Bearer "));
+            if !given.is_some_and(|given| token_matches(given, token)) {
+                return reject(
+                    &stream,
+                    "401 Unauthorized",
+                    &format!("Missing or invalid {} bearer token", config::ENV_PROTTP_TOKEN),
+                );
+            }
+        }
+
+        // Read body if present
+        let mut body = Vec::new();
+        if content_length > 0 {
+            body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+        }
+        
+        // // Parse body as JSON
+        // let json = if !body.is_empty() {
+        //     match serde_json::from_slice::<Value>(&body) {
+        //         Ok(value) => Some(value),
+        //         Err(_) => None, // Invalid JSON, keep as None
+        //     }
+        // } else {
+        //     None
+        // };
+
+        let request_id = headers
+            .get("x-request-id")
+            .filter(|value| !value.is_empty())
+            .cloned()
+            .unwrap_or_else(|| "-".to_string());
+
+        let close = headers
+            .get("connection")
+            .map(|value| value.eq_ignore_ascii_case("close"))
+            .unwrap_or(false);
+        
+        // Create request and pass to handler, the handler writes through
+        // its own handle so the connection stays open for the next request
+        let request = HttpRequest {
+            method,
+            path,
+            // headers,
+            body,
+            // json,
+            request_id,
+            stream: stream.try_clone()?,
+        };
+        
+        handler(request);
+
+        if close {
+            return Ok(());
         }
     }
-    
-    // Read body if present
-    let mut body = Vec::new();
-    if content_length > 0 {
-        body = vec![0; content_length];
-        reader.read_exact(&mut body)?;
-    }
-    
-    // // Parse body as JSON
-    // let json = if !body.is_empty() {
-    //     match serde_json::from_slice::<Value>(&body) {
-    //         Ok(value) => Some(value),
-    //         Err(_) => None, // Invalid JSON, keep as None
-    //     }
-    // } else {
-    //     None
-    // };
-    
-    // Create request and pass to handler
-    let request = HttpRequest {
-        method,
-        path,
-        // headers,
-        body,
-        // json,
-        stream,
-    };
-    
-    handler(request);
-    
-    Ok(())
+}
+
+/// Answers with a 400 and closes the connection
+fn reject(mut stream: &TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    eprintln!("[-PT-] Rejected request: {}", body);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// Compares tokens in time independent of where they differ
+pub(crate) fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
 }
 
 // Helper functions for sending standard responses
@@ -141,6 +271,17 @@ impl HttpRequest {
         Ok(())
     }
 
+    pub fn send_json_200(&mut self, body: &str) -> std::io::Result<()> {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nContent-Type: application/json\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        self.stream.write_all(response.as_bytes())?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
     pub fn send_400(&mut self, body: &str) -> std::io::Result<()> {
         let response = format!(
             "HTTP/1.1 400 Bad Request\r\nContent-Length: {}\r\nContent-Type: text/plain\r\n\r\n{}",
@@ -152,6 +293,17 @@ impl HttpRequest {
         Ok(())
     }
 
+    pub fn send_json_400(&mut self, body: &str) -> std::io::Result<()> {
+        let response = format!(
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: {}\r\nContent-Type: application/json\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        self.stream.write_all(response.as_bytes())?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
     pub fn send_404(&mut self, body: &str) -> std::io::Result<()> {
         let response = format!(
             "HTTP/1.1 404 Not Found\r\nContent-Length: {}\r\nContent-Type: text/plain\r\n\r\n{}",
@@ -163,3 +315,101 @@ impl HttpRequest {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    const MAX_BODY: usize = 64 * 1024;
+
+    /// Starts a server answering with the length of the body it received
+    fn start_server() -> SocketAddr {
+        start_server_with_token(None)
+    }
+
+    fn start_server_with_token(token: Option<&str>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = HttpServer::new("127.0.0.1:0")
+            .with_limits(1024, MAX_BODY)
+            .with_token(token);
+        thread::spawn(move || {
+            server.serve(listener, |mut request| {
+                let received = request.body.len().to_string();
+                let _ = request.send_200(&received);
+            })
+        });
+        addr
+    }
+
+    fn send(addr: SocketAddr, head: &str, body: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    /// A `/proxy/node` JSON body of exactly `size` bytes
+    fn proxy_body(size: usize) -> Vec<u8> {
+        let prefix = r#"[{"addr_listen":"0.0.0.0:80","tls_pem":""#;
+        let suffix = r#""}]"#;
+        let mut body = prefix.as_bytes().to_vec();
+        body.resize(size - suffix.len(), b'A');
+        body.extend_from_slice(suffix.as_bytes());
+        body
+    }
+
+    #[test]
+    fn test_body_up_to_the_limit_is_read_whole() {
+        let addr = start_server();
+        let body = proxy_body(MAX_BODY);
+        let head = format!(
+            "GWRX /proxy/node HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+
+        let response = send(addr, &head, &body);
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with(&MAX_BODY.to_string()), "{}", response);
+    }
+
+    #[test]
+    fn test_body_over_the_limit_is_rejected() {
+        let addr = start_server();
+        // Only the head is sent, the server answers before reading any body
+        let head = format!(
+            "GWRX /proxy/node HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+
+        let response = send(addr, &head, &[]);
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        assert!(response.contains(&format!("limit of {} bytes", MAX_BODY)), "{}", response);
+    }
+
+    #[test]
+    fn test_invalid_content_length_is_rejected() {
+        let addr = start_server();
+        let head = "GWRX /proxy/node HTTP/1.1\r\nContent-Length: lots\r\n\r\n";
+
+        let response = send(addr, head, &[]);
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        assert!(response.contains("Invalid Content-Length"), "{}", response);
+    }
+
+    #[test]
+    fn test_token_is_required_when_configured() {
+        let addr = start_server_with_token(Some("s3cret"));
+        let head = "GWRX /status HTTP/1.1\r\nContent-Length: 0\r\nConnection: close\r\n";
+
+        let response = send(addr, &format!("{}\r\n", head), &[]);
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        let response = send(addr, &format!("{}Authorization: Bearer s3cre7\r\n\r\n", head), &[]);
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        let response = send(addr, &format!("{}Authorization: Bearer s3cret\r\n\r\n", head), &[]);
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    }
+}
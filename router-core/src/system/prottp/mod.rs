@@ -1,50 +1,105 @@
 mod app;
+mod command;
 mod core;
 
-pub fn init() {
-    std::thread::spawn(|| {
-        let server = core::HttpServer::new("127.0.0.1:30099");
+use crate::app::{concurrency, connections, gateway_fast};
+use crate::config;
+use crate::system::memory_log::level;
+use crate::system::writer::access_log;
 
-        println!("[-PT-] Starting HTTP server on 30099");
+use self::command::Command;
+pub(crate) use self::core::token_matches;
+
+/// Binds the protocol server on `GWRS_PROTTP_ADDRESS` and serves it on its own thread.
+///
+/// # Errors
+///
+/// An invalid address or a failed bind; router-api can't reach the core without the server.
+pub fn init() -> Result<(), String> {
+    let address = config::prottp_address()?;
+    let listener = std::net::TcpListener::bind(&address)
+        .map_err(|e| format!("Protocol server can't listen on {}: {}", address, e))?;
+
+    std::thread::spawn(move || {
+        let server = core::HttpServer::new(&address);
+
+        println!("[-PT-] Starting HTTP server on {}", address);
         
-        if let Err(e) = server.start(|mut request| {
+        if let Err(e) = server.serve(listener, |mut request| {
             let body_string = {
                 let string = String::from_utf8_lossy(&request.body); // Returns Cow<str>
                 let string = string.to_string(); // Convert to owned String
                 string
             };
 
-            println!("[-PT-] Received request: {} {}", request.method, request.path);
+            println!(
+                "[-PT-] Received request: {} {} (request id: {})",
+                request.method, request.path, request.request_id
+            );
 
-            match (request.method.as_str(), request.path.as_str()) {
-                ("GWRX", "/gateway/node") => {
-                    let res = match app::gateway_node::init(body_string) {
-                        Ok(_) => request.send_200("Gateway node data updated successfully"),
+            // `?dryrun=1` on a config path only validates the body
+            let (path, query) = match request.path.split_once('?') {
+                Some((path, query)) => (path.to_string(), query.to_string()),
+                None => (request.path.clone(), String::new()),
+            };
+            let dry_run = is_dry_run(&query);
+
+            if request.method == "GWRX" {
+                if let Some(command) = Command::from_path(&path) {
+                    let res = match command.run(&body_string) {
+                        Ok(answer) => request.send_json_200(&answer.to_string()),
                         Err(e) => {
-                            log::error!("Failed to update gateway node data: {}", e);
-                            request.send_400("Failed to update gateway node data")
+                            log::error!("Command {:?} failed: {}", command, e);
+                            request.send_400(&e)
                         }
                     };
                     let _ = res;
+                    return;
+                }
+            }
+
+            match (request.method.as_str(), path.as_str()) {
+                ("GWRX", "/gateway/node") if dry_run => {
+                    let res = validate_config(&mut request, body_string, "Gateway node", app::gateway_node::validate);
+                    let _ = res;
+                }
+                ("GWRX", "/gateway/path") if dry_run => {
+                    let res = validate_config(&mut request, body_string, "Gateway path", app::gateway_path::validate);
+                    let _ = res;
+                }
+                ("GWRX", "/proxy/node") if dry_run => {
+                    let res = validate_config(&mut request, body_string, "Proxy node", app::proxy_node::validate);
+                    let _ = res;
+                }
+                ("GWRX", "/gateway/node") => {
+                    let res = apply_config(&mut request, body_string, "Gateway node", app::gateway_node::init);
+                    let _ = res;
                 }
                 ("GWRX", "/gateway/path") => {
-                    let res = match app::gateway_path::init(body_string) {
-                        Ok(_) => request.send_200("Gateway path data updated successfully"),
-                        Err(e) => {
-                            log::error!("Failed to update gateway path data: {}", e);
-                            request.send_400("Failed to update gateway path data")
-                        }
-                    };
+                    let res = apply_config(&mut request, body_string, "Gateway path", app::gateway_path::init);
                     let _ = res;
                 }
                 ("GWRX", "/proxy/node") => {
-                    let res = match app::proxy_node::init(body_string) {
-                        Ok(_) => request.send_200("Proxy node data updated successfully"),
-                        Err(e) => {
-                            log::error!("Failed to update proxy node data: {}", e);
-                            request.send_400("Failed to update proxy node data")
-                        }
-                    };
+                    let res = apply_config(&mut request, body_string, "Proxy node", app::proxy_node::init);
+                    let _ = res;
+                }
+                ("GWRX", "/status") => {
+                    let body = serde_json::json!({
+                        "status": "ok",
+                        "gateway_cache": gateway_fast::route_cache_stats(),
+                        "limits": concurrency::stats(),
+                        "log_levels": level::current(),
+                        "access_log": {
+                            "enabled": access_log::enabled(),
+                            "dropped": access_log::dropped(),
+                        },
+                    });
+                    let res = request.send_json_200(&body.to_string());
+                    let _ = res;
+                }
+                ("GWRX", "/connections") => {
+                    let body = serde_json::json!({ "connections": connections::list() });
+                    let res = request.send_json_200(&body.to_string());
                     let _ = res;
                 }
                 _ => {
@@ -55,4 +110,192 @@ pub fn init() {
             log::error!("HTTP server error: {}", e);
         }
     });
+    Ok(())
+}
+
+/// Applies a config payload from the API and answers with the outcome.
+///
+/// Empty bodies are rejected before parsing. Parse errors are returned in the
+/// 400 body as reported by serde (field, line and column), so the API can log
+/// exactly what it sent wrong.
+fn apply_config<E: std::fmt::Display>(
+    request: &mut core::HttpRequest,
+    body: String,
+    label: &str,
+    apply: fn(String, &str) -> Result<(), E>,
+) -> std::io::Result<()> {
+    let what = label.to_lowercase();
+    if body.trim().is_empty() {
+        log::error!("Rejected {} data: empty body", what);
+        return request.send_400(&format!("Empty body, expected {} data as a JSON array", what));
+    }
+
+    let request_id = request.request_id.clone();
+    match apply(body, &request_id) {
+        Ok(_) => request.send_200(&format!("{} data updated successfully", label)),
+        Err(e) => {
+            log::error!("Failed to update {} data: {}", what, e);
+            request.send_400(&format!("Failed to update {} data: {}", what, e))
+        }
+    }
+}
+
+/// Whether the query string asks for a dry run (`dryrun`, `dryrun=1` or `dryrun=true`)
+fn is_dry_run(query: &str) -> bool {
+    query.split('&').any(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        key == "dryrun" && matches!(value, "" | "1" | "true")
+    })
+}
+
+/// Validates a config payload like its apply would, without changing the live config.
+///
+/// Answers `{"valid": true, "errors": []}`, or a 400 with `valid` false and one
+/// message per problem found.
+fn validate_config(
+    request: &mut core::HttpRequest,
+    body: String,
+    label: &str,
+    validate: fn(String, &str) -> Result<(), Vec<String>>,
+) -> std::io::Result<()> {
+    let errors = if body.trim().is_empty() {
+        vec![format!("Empty body, expected {} data as a JSON array", label.to_lowercase())]
+    } else {
+        let request_id = request.request_id.clone();
+        validate(body, &request_id).err().unwrap_or_default()
+    };
+
+    let result = serde_json::json!({
+        "valid": errors.is_empty(),
+        "errors": errors,
+    });
+    if errors.is_empty() {
+        request.send_json_200(&result.to_string())
+    } else {
+        log::warn!("Dry run of {} data found {} problem(s)", label.to_lowercase(), errors.len());
+        request.send_json_400(&result.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+
+    /// Parses like the real apply functions without touching the live config
+    fn parse_nodes(body: String, _request_id: &str) -> Result<(), serde_json::Error> {
+        serde_json::from_str::<Vec<crate::config::ProxyNode>>(&body).map(|_| ())
+    }
+
+    fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            super::core::HttpServer::new("127.0.0.1:0").serve(listener, |mut request| {
+                let body = String::from_utf8_lossy(&request.body).to_string();
+                let _ = apply_config(&mut request, body, "Proxy node", parse_nodes);
+            })
+        });
+        addr
+    }
+
+    fn send(addr: SocketAddr, body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = format!(
+            "GWRX /proxy/node HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_parse_error_detail_is_returned() {
+        let addr = start_server();
+
+        let response = send(addr, "[{\"tls\": false}]");
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        assert!(response.contains("missing field `addr_listen`"), "{}", response);
+        assert!(response.contains("line 1 column"), "{}", response);
+
+        let response = send(addr, "[{\"tls\": fals");
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        assert!(response.contains("Failed to update proxy node data"), "{}", response);
+    }
+
+    #[test]
+    fn test_empty_body_is_rejected() {
+        let addr = start_server();
+
+        let response = send(addr, "  ");
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        assert!(response.contains("Empty body"), "{}", response);
+
+        let response = send(addr, "[]");
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    }
+
+    #[test]
+    fn test_dry_run_query() {
+        assert!(is_dry_run("dryrun=1"));
+        assert!(is_dry_run("a=b&dryrun"));
+        assert!(!is_dry_run("dryrun=0"));
+        assert!(!is_dry_run(""));
+    }
+
+    #[test]
+    fn test_dry_run_reports_invalid_rules() {
+        let body = r#"[
+            {"id": "ok", "priority": 1, "sni": null, "tls": false, "addr_bind": "127.0.0.1:30001",
+             "addr_target": "127.0.0.1:8080", "path_listen": "/api/*", "path_target": "/$1"},
+            {"id": "bad", "priority": 2, "sni": null, "tls": false, "addr_bind": "127.0.0.1:30001",
+             "addr_target": "127.0.0.1:8080", "path_listen": "^/(unclosed", "path_target": "/"}
+        ]"#;
+        let errors = app::gateway_path::validate(body.to_string(), "-").unwrap_err();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("Rule 'bad'"), "{:?}", errors);
+
+        let proxies = r#"[{"tls": false, "addr_listen": "0.0.0.0:8443", "addr_target": "127.0.0.1:40001",
+            "high_speed": true, "high_speed_addr": "not-an-address"}]"#;
+        let errors = app::proxy_node::validate(proxies.to_string(), "-").unwrap_err();
+        assert!(errors[0].contains("invalid target address"), "{:?}", errors);
+
+        let nodes = r#"[{"priority": 1, "addr_target": "127.0.0.1:8080", "addr_listen": "0.0.0.0:443",
+            "addr_bind": "127.0.0.1:30001", "tls": [{"tls": true, "sni": "example.com", "tls_pem": null, "tls_key": null}]}]"#;
+        let errors = app::gateway_node::validate(nodes.to_string(), "-").unwrap_err();
+        assert!(errors[0].contains("needs a certificate and a key"), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_certificate_only_changes() {
+        let gateway = |pem: &str, target: &str| {
+            let nodes = format!(
+                r#"[{{"priority": 1, "addr_target": "{}", "addr_listen": "0.0.0.0:443", "addr_bind": "127.0.0.1:30001",
+                "tls": [{{"tls": true, "sni": "example.com", "tls_pem": "{}", "tls_key": "/tmp/key.pem"}}]}}]"#,
+                target, pem
+            );
+            serde_json::from_str::<Vec<crate::config::GatewayNode>>(&nodes).unwrap()
+        };
+        let current = gateway("/tmp/old.pem", "127.0.0.1:8080");
+        assert!(app::gateway_node::certificates_only(&current, &gateway("/tmp/renewed.pem", "127.0.0.1:8080")));
+        assert!(!app::gateway_node::certificates_only(&current, &gateway("/tmp/renewed.pem", "127.0.0.1:8081")));
+        assert!(!app::gateway_node::certificates_only(&current, &[]));
+
+        let proxy = |pem: &str, high_speed: bool| {
+            let nodes = format!(
+                r#"[{{"tls": true, "sni": "example.com", "tls_pem": "{}", "tls_key": "/tmp/key.pem",
+                "addr_listen": "0.0.0.0:443", "addr_target": "127.0.0.1:30001", "high_speed": {}}}]"#,
+                pem, high_speed
+            );
+            serde_json::from_str::<Vec<crate::config::ProxyNode>>(&nodes).unwrap()
+        };
+        // Gateway listeners load the certificates of proxies outside speed mode
+        assert!(app::proxy_node::unused_certificates_only(&proxy("/tmp/old.pem", false), &proxy("/tmp/renewed.pem", false)));
+        assert!(!app::proxy_node::unused_certificates_only(&proxy("/tmp/old.pem", true), &proxy("/tmp/renewed.pem", true)));
+        assert!(!app::proxy_node::unused_certificates_only(&proxy("/tmp/old.pem", false), &proxy("/tmp/old.pem", true)));
+    }
 }
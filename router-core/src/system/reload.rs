@@ -0,0 +1,219 @@
+//! # Settings Reload
+//!
+//! On SIGHUP the settings file named by `GWRS_CONFIG_FILE` is read again and
+//! the settings that can change under live traffic are applied in place, no
+//! listener is restarted and no connection is dropped. Every changed setting
+//! is logged together with whether it was applied.
+//!
+//! ## Reloadable
+//!
+//! * `RUST_LOG`, `GWRS_LOG_LEVEL_<COMPONENT>` - log thresholds; this replaces
+//!   levels set through the `/log/level` route
+//! * `GWRS_GATEWAY_CACHE_TTL` - gateway route cache TTL, also for cached entries
+//! * `GWRS_GATEWAY_UNHEALTHY_TTL` - how long a refusing target is skipped, for targets marked afterwards
+//! * `GWRS_LOG_OVERFLOW_WARN_INTERVAL` - seconds between log ring overflow warnings
+//! * `GWRS_GATEWAY_ALLOW_DOT_SEGMENTS` - whether rewrites keep `..` in captures, cached routes are dropped
+//!
+//! ## Applied when the servers restart (SIGINT)
+//!
+//! * `GWRS_GATEWAY_CONNECT_RETRIES`, `GWRS_COMPRESS_MIN_SIZE`, `GWRS_GATEWAY_CONNECT_TIMEOUT`,
+//!   `GWRS_GATEWAY_HEADER_TIMEOUT`, `GWRS_GATEWAY_TOTAL_TIMEOUT` - read by each gateway listener
+//! * `GWRS_GATEWAY_FALLBACK` - read by each gateway listener without its own fallback
+//! * `GWRS_WS_FRAME_COUNTS`, `GWRS_PROXY_MAX_CONNECTIONS`, `GWRS_PROXY_IDLE_TIMEOUT`,
+//!   `GWRS_WS_IDLE_TIMEOUT`, `GWRS_WS_PING_INTERVAL` - read by each speed mode proxy
+//! * `GWRS_GATEWAY_MAX_REQUESTS`, `GWRS_STICKY_COOKIE`, `GWRS_STICKY_TTL`, `GWRS_REDACT_MAX_BODY`,
+//!   `GWRS_ROUTE_SCRIPT_MAX_OPERATIONS`, `GWRS_ROUTE_SCRIPT_TIMEOUT_MS`, `GWRS_GATEWAY_MIRROR_MAX_BODY`,
+//!   `GWRS_GATEWAY_TRACE_TOKEN` - read by each gateway listener
+//!
+//! ## Applied on a full process restart
+//!
+//! * `GWRS_PROTTP_ADDRESS`, `GWRS_PROTTP_TOKEN`, `GWRS_PROTTP_BUFFER_SIZE`, `GWRS_PROTTP_MAX_BODY` -
+//!   read once by the protocol server
+//! * `GWRS_LOG_SINKS`, `GWRS_ACCESS_LOG_FILE`, `GWRS_ACCESS_LOG_FORMAT`, `GWRS_ACCESS_LOG_MAX_SIZE`,
+//!   `GWRS_ACCESS_LOG_ROTATE_SECS`, `GWRS_ACCESS_LOG_KEEP` - read once when logging starts
+//! * `GWRS_GATEWAY_MIRROR_MAX_IN_FLIGHT` - read once by the first mirrored request
+//!
+//! Environment variables take precedence over the file, so a setting that is
+//! also exported in the environment never changes on reload. `GWRS_CONFIG_FILE`
+//! itself is only read from the environment.
+
+use crate::app::{gateway_fast, path_template, peer_health};
+use crate::config::{self, ENV_LOG_LEVEL_PREFIX};
+use crate::system::memory_log::{self, level::{self, Component}};
+
+/// When a changed setting takes effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Effect {
+    /// Applied by the reload itself
+    Now,
+    /// Applied when the proxy and gateway servers restart
+    ServerRestart,
+    /// Applied when the process restarts
+    ProcessRestart,
+}
+
+/// Settings of the gateway and protocol server with the time they take effect
+const SETTINGS: &[(&str, Effect)] = &[
+    (config::ENV_GATEWAY_CACHE_TTL, Effect::Now),
+    (config::ENV_GATEWAY_UNHEALTHY_TTL, Effect::Now),
+    (config::ENV_LOG_OVERFLOW_WARN_INTERVAL, Effect::Now),
+    (config::ENV_GATEWAY_ALLOW_DOT_SEGMENTS, Effect::Now),
+    (config::ENV_GATEWAY_CONNECT_RETRIES, Effect::ServerRestart),
+    (config::ENV_COMPRESS_MIN_SIZE, Effect::ServerRestart),
+    (config::ENV_GATEWAY_CONNECT_TIMEOUT, Effect::ServerRestart),
+    (config::ENV_GATEWAY_HEADER_TIMEOUT, Effect::ServerRestart),
+    (config::ENV_GATEWAY_TOTAL_TIMEOUT, Effect::ServerRestart),
+    (config::ENV_GATEWAY_FALLBACK, Effect::ServerRestart),
+    (config::ENV_WS_FRAME_COUNTS, Effect::ServerRestart),
+    (config::ENV_PROXY_MAX_CONNECTIONS, Effect::ServerRestart),
+    (config::ENV_PROXY_IDLE_TIMEOUT, Effect::ServerRestart),
+    (config::ENV_WS_IDLE_TIMEOUT, Effect::ServerRestart),
+    (config::ENV_WS_PING_INTERVAL, Effect::ServerRestart),
+    (config::ENV_GATEWAY_MAX_REQUESTS, Effect::ServerRestart),
+    (config::ENV_STICKY_COOKIE, Effect::ServerRestart),
+    (config::ENV_STICKY_TTL, Effect::ServerRestart),
+    (config::ENV_REDACT_MAX_BODY, Effect::ServerRestart),
+    (config::ENV_ROUTE_SCRIPT_MAX_OPERATIONS, Effect::ServerRestart),
+    (config::ENV_ROUTE_SCRIPT_TIMEOUT_MS, Effect::ServerRestart),
+    (config::ENV_MIRROR_MAX_BODY, Effect::ServerRestart),
+    (config::ENV_GATEWAY_TRACE_TOKEN, Effect::ServerRestart),
+    (config::ENV_PROTTP_ADDRESS, Effect::ProcessRestart),
+    (config::ENV_PROTTP_TOKEN, Effect::ProcessRestart),
+    (config::ENV_PROTTP_BUFFER_SIZE, Effect::ProcessRestart),
+    (config::ENV_PROTTP_MAX_BODY, Effect::ProcessRestart),
+    (config::ENV_LOG_SINKS, Effect::ProcessRestart),
+    (config::ENV_ACCESS_LOG_FILE, Effect::ProcessRestart),
+    (config::ENV_ACCESS_LOG_FORMAT, Effect::ProcessRestart),
+    (config::ENV_ACCESS_LOG_MAX_SIZE, Effect::ProcessRestart),
+    (config::ENV_ACCESS_LOG_ROTATE_SECS, Effect::ProcessRestart),
+    (config::ENV_ACCESS_LOG_KEEP, Effect::ProcessRestart),
+    (config::ENV_MIRROR_MAX_IN_FLIGHT, Effect::ProcessRestart),
+];
+
+/// Names of the log level settings, all applied by the reload
+fn log_level_settings() -> Vec<String> {
+    let mut names = vec!["RUST_LOG".to_string()];
+    names.extend(
+        Component::ALL
+            .iter()
+            .map(|component| format!("{}{}", ENV_LOG_LEVEL_PREFIX, component.name().to_uppercase())),
+    );
+    names
+}
+
+/// Effective value of every known setting, in a fixed order
+fn snapshot() -> Vec<(String, Effect, Option<String>)> {
+    log_level_settings()
+        .into_iter()
+        .map(|name| (name, Effect::Now))
+        .chain(SETTINGS.iter().map(|(name, effect)| (name.to_string(), *effect)))
+        .map(|(name, effect)| {
+            let value = config::setting(&name);
+            (name, effect, value)
+        })
+        .collect()
+}
+
+/// Re-reads the settings file and applies the reloadable settings.
+///
+/// # Returns
+///
+/// The names of the settings whose value changed.
+pub fn reload() -> Vec<String> {
+    let before = snapshot();
+    match config::load_settings_file() {
+        Ok(Some(path)) => log::info!("Reloading settings from {}", path),
+        Ok(None) => {
+            log::warn!("SIGHUP received but {} is not set, nothing to reload", config::ENV_CONFIG_FILE);
+            return Vec::new();
+        }
+        Err(e) => {
+            log::error!("Failed to reload settings, keeping the current ones: {}", e);
+            return Vec::new();
+        }
+    }
+    let after = snapshot();
+
+    let mut changed = Vec::new();
+    let mut levels_changed = false;
+    let mut ttl_changed = false;
+    let mut unhealthy_ttl_changed = false;
+    let mut overflow_warn_changed = false;
+    let mut dot_segments_changed = false;
+    for ((name, effect, old), (_, _, new)) in before.iter().zip(after.iter()) {
+        if old == new {
+            continue;
+        }
+        let when = match effect {
+            Effect::Now => "applied",
+            Effect::ServerRestart => "takes effect when the servers restart",
+            Effect::ProcessRestart => "takes effect after a full restart",
+        };
+        // Secrets are never written to the log
+        let shown = |value: &Option<String>| match value {
+            None => "<unset>".to_string(),
+            Some(_) if [config::ENV_PROTTP_TOKEN, config::ENV_GATEWAY_TRACE_TOKEN].contains(&name.as_str()) => {
+                "<hidden>".to_string()
+            }
+            Some(value) => value.clone(),
+        };
+        log::info!(
+            "Setting {} changed from {} to {}, {}",
+            name,
+            shown(old),
+            shown(new),
+            when
+        );
+        if name == config::ENV_GATEWAY_CACHE_TTL {
+            ttl_changed = true;
+        } else if name == config::ENV_GATEWAY_UNHEALTHY_TTL {
+            unhealthy_ttl_changed = true;
+        } else if name == config::ENV_LOG_OVERFLOW_WARN_INTERVAL {
+            overflow_warn_changed = true;
+        } else if name == config::ENV_GATEWAY_ALLOW_DOT_SEGMENTS {
+            dot_segments_changed = true;
+        } else if *effect == Effect::Now {
+            levels_changed = true;
+        }
+        changed.push(name.clone());
+    }
+
+    if levels_changed {
+        level::init_from_env();
+        log::set_max_level(level::max_filter());
+        log::info!("Log levels are now {:?}", level::current());
+    }
+    if ttl_changed {
+        gateway_fast::set_route_cache_ttl(config::gateway_cache_ttl());
+    }
+    if unhealthy_ttl_changed {
+        peer_health::PEER_HEALTH.set_ttl(config::gateway_unhealthy_ttl());
+    }
+    if overflow_warn_changed {
+        memory_log::set_overflow_warn_interval(config::log_overflow_warn_interval());
+    }
+    if dot_segments_changed {
+        path_template::set_keep_dot_segments(config::gateway_allow_dot_segments());
+        // Cached routes were rewritten under the old setting
+        gateway_fast::flush_route_caches();
+    }
+    if changed.is_empty() {
+        log::info!("Settings reloaded, nothing changed");
+    }
+    changed
+}
+
+/// Reloads the settings on every SIGHUP, for the lifetime of the process.
+pub async fn listen() {
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => signal,
+        Err(e) => {
+            log::error!("Failed to install the SIGHUP handler, settings can't be reloaded: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        eprintln!("[----] SIGHUP received, reloading settings...");
+        reload();
+    }
+}
@@ -15,9 +15,12 @@
 //! Each component runs in its own thread to provide isolation and parallel processing.
 
 use super::default_page;
+use super::sockopt::TcpOptions;
+use super::startup::{self, StartupError};
+use super::tls_policy::TlsPolicy;
 use crate::{
-    app::gateway_fast::GatewayApp,
-    config::{self, GatewayNode, ProxyNode},
+    app::{gateway_fast::GatewayApp, path_template},
+    config::{self, GatewayNode, ProxyNode, DEFAULT_PORT},
     service,
 };
 use pingora::{
@@ -26,37 +29,81 @@ use pingora::{
     server::{RunArgs, Server},
     services::Service,
 };
+use std::collections::HashMap;
 use std::ops::DerefMut;
+use std::sync::{Arc, LazyLock, Mutex, Weak};
 use std::thread;
 
 /// Module for handling dynamic TLS certificate selection based on SNI (Server Name Indication).
 ///
 /// This module provides functionality to dynamically select TLS certificates based on
 /// the hostname requested by clients through SNI. It supports both exact matches and
-/// wildcard certificates.
+/// wildcard certificates (see `system::sni`). The certificates of a running listener
+/// can be replaced in place, see [`super::reload_gateway_certs`].
 mod boringssl_openssl {
+    use crate::config::GatewayNodeSNI;
+    use crate::system::sni::SniMatcher;
     use async_trait::async_trait;
     use pingora::tls::pkey::{PKey, Private};
     use pingora::tls::ssl::{NameType, SslRef};
     use pingora::tls::x509::X509;
     use std::collections::HashMap;
-    use std::sync::{Arc, Mutex};
+    use std::sync::{Arc, Mutex, RwLock};
+
+    /// Certificates of one listener, replaced as a whole
+    #[derive(Default)]
+    struct Certs {
+        list: Vec<(Option<String>, X509, PKey<Private>)>,
+        // Index into `list` for every configured name, exact and wildcard
+        names: SniMatcher<usize>,
+    }
+
+    impl Certs {
+        fn add(
+            &mut self,
+            domain: String,
+            cert: &str,
+            key: &str,
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            let cert_bytes = std::fs::read(cert)?;
+            let cert = X509::from_pem(&cert_bytes)?;
+
+            let key_bytes = std::fs::read(key)?;
+            let key = PKey::private_key_from_pem(&key_bytes)?;
+
+            // One domain config may list several names, all served by this cert
+            self.names.insert(&domain, self.list.len());
+            self.list.push((Some(domain), cert, key));
+            Ok(())
+        }
+    }
 
     pub(super) struct DynamicCert {
-        certs: Vec<(Option<String>, X509, PKey<Private>)>,
+        certs: RwLock<Certs>,
         // Thread-safe cache for hostname lookups
         cache: Mutex<HashMap<String, (Arc<X509>, Arc<PKey<Private>>)>>,
         // Maximum number of entries to prevent unbounded growth
         max_cache_size: usize,
+        // Listen address of a listener refusing clients without SNI
+        require_sni: Option<String>,
     }
 
+    /// Handshake callbacks of a listener, sharing its [`DynamicCert`] with the reloads
+    pub(super) struct SharedCert(pub(super) Arc<DynamicCert>);
+
     impl DynamicCert {
-        pub(super) fn new() -> Box<Self> {
-            Box::new(DynamicCert {
-                certs: Vec::new(),
+        pub(super) fn new() -> Self {
+            DynamicCert {
+                certs: RwLock::new(Certs::default()),
                 cache: Mutex::new(HashMap::new()),
                 max_cache_size: 1000, // Default size, can be adjusted based on expected traffic patterns
-            })
+                require_sni: None,
+            }
+        }
+
+        /// Refuse handshakes without SNI on the listener `listen` instead of serving the default cert
+        pub(super) fn require_sni(&mut self, listen: &str) {
+            self.require_sni = Some(listen.to_string());
         }
 
         // Optional: Allow configuring the cache size
@@ -72,25 +119,38 @@ mod boringssl_openssl {
             cert: &str,
             key: &str,
         ) -> Result<(), Box<dyn std::error::Error>> {
-            let cert_bytes = std::fs::read(cert)?;
-            let cert = X509::from_pem(&cert_bytes)?;
-
-            let key_bytes = std::fs::read(key)?;
-            let key = PKey::private_key_from_pem(&key_bytes)?;
-
-            self.certs.push((Some(domain), cert, key));
-            Ok(())
+            self.certs
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner())
+                .add(domain, cert, key)
         }
 
-        fn domain_matches(pattern: &str, domain: &str) -> bool {
-            // Existing implementation
-            if pattern.starts_with("*.") {
-                let suffix = &pattern[1..];
-                domain.ends_with(suffix)
-                    && domain[..domain.len() - suffix.len()].matches('.').count() == 0
-            } else {
-                pattern == domain
+        /// Replaces the certificates with the TLS entries of `tls`, for new handshakes.
+        ///
+        /// Either every certificate is loaded or the current ones are kept.
+        ///
+        /// # Returns
+        ///
+        /// The number of certificates now served.
+        pub(super) fn replace(&self, tls: &[GatewayNodeSNI]) -> Result<usize, String> {
+            let mut certs = Certs::default();
+            for entry in tls.iter().filter(|entry| entry.tls) {
+                let (Some(cert), Some(key)) = (&entry.tls_pem, &entry.tls_key) else {
+                    return Err(format!("{:?} has no certificate file", entry.sni));
+                };
+                let domain = entry.sni.clone().unwrap_or_else(|| "localhost".to_string());
+                certs
+                    .add(domain, cert, key)
+                    .map_err(|e| format!("{:?} could not be loaded: {}", entry.sni, e))?;
+            }
+            if certs.list.is_empty() {
+                return Err("no TLS certificate left".to_string());
             }
+            let count = certs.list.len();
+            *self.certs.write().unwrap_or_else(|e| e.into_inner()) = certs;
+            // Cached lookups point at the replaced certificates
+            self.cache.lock().unwrap_or_else(|e| e.into_inner()).clear();
+            Ok(count)
         }
 
         // Find certificate for a hostname and cache the result
@@ -138,27 +198,15 @@ mod boringssl_openssl {
 
         // Search for matching certificate (exact or wildcard)
         fn find_matching_cert(&self, hostname: &str) -> Option<(Arc<X509>, Arc<PKey<Private>>)> {
-            // First try exact matches
-            for (domain, cert, key) in &self.certs {
-                if let Some(domain_str) = domain {
-                    if domain_str == hostname {
-                        return Some((Arc::new(cert.clone()), Arc::new(key.clone())));
-                    }
-                }
-            }
-
-            // Then try wildcard matches
-            for (domain, cert, key) in &self.certs {
-                if let Some(domain_str) = domain {
-                    if Self::domain_matches(domain_str, hostname) {
-                        return Some((Arc::new(cert.clone()), Arc::new(key.clone())));
-                    }
-                }
+            let certs = self.certs.read().unwrap_or_else(|e| e.into_inner());
+            // Exact names first, then wildcards
+            if let Some(&index) = certs.names.find(hostname) {
+                let (_, cert, key) = &certs.list[index];
+                return Some((Arc::new(cert.clone()), Arc::new(key.clone())));
             }
 
             // No match found, return the default if available
-            if !self.certs.is_empty() {
-                let (_, default_cert, default_key) = &self.certs[0];
+            if let Some((_, default_cert, default_key)) = certs.list.first() {
                 return Some((
                     Arc::new(default_cert.clone()),
                     Arc::new(default_key.clone()),
@@ -170,17 +218,26 @@ mod boringssl_openssl {
     }
 
     #[async_trait]
-    impl pingora::listeners::TlsAccept for DynamicCert {
+    impl pingora::listeners::TlsAccept for SharedCert {
         async fn certificate_callback(&self, ssl: &mut SslRef) {
             use pingora::tls::ext;
+            let this = &self.0;
 
-            if self.certs.is_empty() {
+            let empty = this.certs.read().unwrap_or_else(|e| e.into_inner()).list.is_empty();
+            if empty {
                 panic!("No certificates configured for TLS!");
             }
 
-            if let Some(server_name) = ssl.servername(NameType::HOST_NAME) {
+            let server_name = ssl.servername(NameType::HOST_NAME);
+            if let (None, Some(listen)) = (server_name, &this.require_sni) {
+                // Without a certificate the handshake fails
+                log::warn!("TLS handshake on {} refused, the client sent no server name", listen);
+                return;
+            }
+
+            if let Some(server_name) = server_name {
                 // Use the cache to efficiently look up certificates
-                if let Some((cert, key)) = self.find_cert_for_hostname(server_name) {
+                if let Some((cert, key)) = this.find_cert_for_hostname(server_name) {
                     ext::ssl_use_certificate(ssl, &cert).unwrap();
                     ext::ssl_use_private_key(ssl, &key).unwrap();
                     return;
@@ -188,13 +245,53 @@ mod boringssl_openssl {
             }
 
             // No SNI or no matching certificate found, use default (index 0)
-            let (_, default_cert, default_key) = &self.certs[0];
+            let certs = this.certs.read().unwrap_or_else(|e| e.into_inner());
+            let (_, default_cert, default_key) = &certs.list[0];
             ext::ssl_use_certificate(ssl, default_cert).unwrap();
             ext::ssl_use_private_key(ssl, default_key).unwrap();
         }
     }
 }
 
+/// Certificate resolvers of the running TLS gateway listeners, by listen address
+static GATEWAY_CERTS: LazyLock<Mutex<HashMap<String, Weak<boringssl_openssl::DynamicCert>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Loads renewed certificates into the running gateway listeners of `nodes`.
+///
+/// Each TLS listener swaps all its certificates at once, handshakes that already
+/// started keep the old ones and no connection is dropped. Listeners that aren't
+/// running load theirs when they start.
+///
+/// # Returns
+///
+/// `false` when a listener couldn't load its certificates, it keeps the old ones then.
+pub fn reload_gateway_certs(nodes: &[GatewayNode]) -> bool {
+    let resolvers = GATEWAY_CERTS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut reloaded = true;
+    for node in nodes {
+        let Some(resolver) = resolvers.get(&node.addr_listen).and_then(Weak::upgrade) else {
+            continue;
+        };
+        match resolver.replace(&node.tls) {
+            Ok(count) => log::info!(
+                "Gateway service {} now serves {} renewed TLS certificate(s)",
+                node.addr_listen,
+                count
+            ),
+            Err(e) => {
+                log::error!(
+                    "Gateway service {} keeps its TLS certificates: {}",
+                    node.addr_listen,
+                    e
+                );
+                reloaded = false;
+            }
+        }
+    }
+    reloaded
+}
+
 /// Initialize and run all server components.
 ///
 /// This function launches multiple server instances in separate threads:
@@ -220,14 +317,38 @@ mod boringssl_openssl {
 /// Each server is created using the Pingora framework with default options.
 /// The servers are bootstrapped individually and configured with appropriate
 /// services before being launched with default run arguments.
-pub fn init() {
+///
+/// # Errors
+///
+/// Every gateway and proxy listen address is bound once before any server
+/// starts (see `system::startup`). On the `first_start` nothing is started
+/// when one can't be bound and all failing listeners are returned. On a
+/// restart after a config push the failing listeners are logged and left
+/// out, the others start as usual.
+pub fn init(first_start: bool) -> Result<(), Vec<StartupError>> {
+    let skipped: Vec<String> = if first_start {
+        startup::check_binds(&listen_addrs())?;
+        Vec::new()
+    } else {
+        startup::unbindable(&listen_addrs())
+            .into_iter()
+            .map(|e| {
+                log::error!("{}, leaving it out until the next restart", e);
+                eprintln!("[----] {}, leaving it out until the next restart", e);
+                e.addr().to_string()
+            })
+            .collect()
+    };
+    path_template::set_keep_dot_segments(config::gateway_allow_dot_segments());
+
     // Vector to store thread handles for later joining
     let mut server_threads: Vec<thread::JoinHandle<()>> = Vec::new();
 
     // Gateway Service Thread - Handles HTTP routing based on path patterns
     // cases are, if the proxy have a multiple address, it converted to normal gateway, either it's HTTPS or HTTP
     {
-        let handle = thread::spawn(|| {
+        let skipped = skipped.clone();
+        let handle = thread::spawn(move || {
             //
             // 3010 -> x
             //      -> y
@@ -252,6 +373,9 @@ pub fn init() {
 
             for gw in gateway {
                 let listen_addr = gw.addr_listen.clone();
+                if skipped.contains(&listen_addr) {
+                    continue;
+                }
 
                 // check if the listen address is already listened
                 if already_listened.contains(&listen_addr) {
@@ -266,12 +390,19 @@ pub fn init() {
                 // setup the gateway service
                 let mut my_gateway_service = pingora::proxy::http_proxy_service(
                     &my_server.configuration,
-                    GatewayApp::new(&gw.addr_bind),
+                    GatewayApp::new(&gw.addr_bind, gw.fallback.as_deref()),
                 );
 
                 eprintln!("[----] Gateway Added: {:#?}", &gw.addr_listen);
 
+                let tls_policy = TlsPolicy::of_gateway(&gw).unwrap_or_else(|e| {
+                    log::error!("Gateway service {} has an invalid TLS policy, using the default: {}", &gw.addr_listen, e);
+                    TlsPolicy::default()
+                });
                 let mut dynamic_cert = boringssl_openssl::DynamicCert::new();
+                if tls_policy.require_sni {
+                    dynamic_cert.require_sni(&gw.addr_listen);
+                }
                 let mut is_tls = false;
                 for tls in gw.tls.clone() {
                     let proxy_sni = tls.sni;
@@ -313,12 +444,25 @@ pub fn init() {
                     my_gateway_service.add_tcp(&gw.addr_listen);
                 } else {
                     // TLS settings are present, add TLS service
-                    let mut tls_settings = TlsSettings::with_callbacks(dynamic_cert).unwrap();
+                    // Registered so renewed certificates are swapped in without a restart
+                    let dynamic_cert = Arc::new(dynamic_cert);
+                    if let Ok(mut resolvers) = GATEWAY_CERTS.lock() {
+                        resolvers.retain(|_, resolver| resolver.strong_count() > 0);
+                        resolvers.insert(gw.addr_listen.clone(), Arc::downgrade(&dynamic_cert));
+                    }
+                    let mut tls_settings =
+                        TlsSettings::with_callbacks(Box::new(boringssl_openssl::SharedCert(dynamic_cert))).unwrap();
                     tls_settings
                         .deref_mut()
                         .deref_mut()
                         .set_max_proto_version(Some(pingora::tls::ssl::SslVersion::TLS1_3))
                         .unwrap();
+                    if let Err(e) = tls_policy.apply(tls_settings.deref_mut().deref_mut()) {
+                        log::error!("Gateway service {} TLS policy not applied, using the default: {}", &gw.addr_listen, e);
+                        TlsPolicy::default()
+                            .apply(tls_settings.deref_mut().deref_mut())
+                            .expect("default TLS policy applies");
+                    }
 
                     tls_settings.enable_h2();
 
@@ -336,7 +480,7 @@ pub fn init() {
 
     // TLS and non-TLS proxy server thread - Handles TLS and non-TLS traffic
     {
-        let handle = thread::spawn(|| {
+        let handle = thread::spawn(move || {
             let opt = Some(Opt::default());
             let mut my_server = Server::new(opt).expect("Failed to create server");
             my_server.bootstrap();
@@ -344,7 +488,8 @@ pub fn init() {
                 .xget::<Vec<ProxyNode>>()
                 .unwrap_or(vec![])
                 .into_iter()
-                .filter(|px| px.high_speed)
+                .filter(|px| px.high_speed || px.redirect_to_https)
+                .filter(|px| !skipped.contains(&px.addr_listen))
                 .collect::<Vec<_>>();
 
             eprintln!("[----] Proxy Loaded: {:#?}", &proxy);
@@ -352,7 +497,31 @@ pub fn init() {
             let mut proxies: Vec<Box<dyn Service>> = vec![];
 
             for px in proxy {
-                let addr_target = px.high_speed_addr.unwrap_or(px.addr_target);
+                if px.redirect_to_https {
+                    let addr_listen = px.addr_listen.clone();
+                    let https_port = px
+                        .redirect_https_port
+                        .unwrap_or(default_page::p_redirect::DEFAULT_HTTPS_PORT);
+                    eprintln!(
+                        "[----] Adding HTTPS redirect on {} to port {}",
+                        &addr_listen, https_port
+                    );
+                    thread::spawn(move || default_page::p_redirect::init(&addr_listen, https_port));
+                    continue;
+                }
+
+                let tcp_options = TcpOptions::from_node(&px);
+                let buffer_size = px.relay_buffer_size();
+                let tls_policy = TlsPolicy::of_proxy(&px).unwrap_or_else(|e| {
+                    log::error!("Proxy service {} has an invalid TLS policy, using the default: {}", &px.addr_listen, e);
+                    TlsPolicy::default()
+                });
+                // Maintenance relays to the 503 page, the target stays configured
+                let addr_target = if px.maintenance {
+                    DEFAULT_PORT.p503.to_string()
+                } else {
+                    px.high_speed_addr.unwrap_or(px.addr_target)
+                };
                 eprintln!("[----] Proxy Added: {}", &px.addr_listen);
 
                 if px.tls && px.sni.is_some() && px.tls_pem.is_some() && px.tls_key.is_some() {
@@ -362,16 +531,26 @@ pub fn init() {
                         &px.sni.as_ref().unwrap_or(&"localhost".to_string()),
                         &px.tls_pem.as_ref().unwrap(),
                         &px.tls_key.as_ref().unwrap(),
+                        tcp_options,
+                        buffer_size,
+                        &tls_policy,
                     );
 
-                    eprintln!("[----] Adding proxy TLS service");
-                    proxies.push(Box::new(proxy_tls));
+                    match proxy_tls {
+                        Ok(proxy_tls) => {
+                            eprintln!("[----] Adding proxy TLS service");
+                            proxies.push(Box::new(proxy_tls));
+                        }
+                        Err(e) => log::error!("Proxy service {} not started: {}", &px.addr_listen, e),
+                    }
                     continue;
                 }
 
                 eprintln!("[----] Adding proxy fast service: {:?}", px.addr_listen);
-                let proxy_set = service::proxy::proxy_service_fast(&px.addr_listen, &addr_target);
-                proxies.push(Box::new(proxy_set));
+                match service::proxy::proxy_service_fast(&px.addr_listen, &addr_target, tcp_options, buffer_size) {
+                    Ok(proxy_set) => proxies.push(Box::new(proxy_set)),
+                    Err(e) => log::error!("Proxy service {} not started: {}", &px.addr_listen, e),
+                }
             }
 
             // Add all proxy services to the server
@@ -397,6 +576,17 @@ pub fn init() {
             default_page::p500::init();
         });
 
+        // 504 Gateway Timeout page server
+        let handle504: thread::JoinHandle<()> = thread::spawn(|| {
+            // Create a TCP listener for the default 504 page
+            default_page::p504::init();
+        });
+
+        // 503 Service Unavailable page server for speed mode proxies in maintenance
+        let handle503: thread::JoinHandle<()> = thread::spawn(|| {
+            default_page::p503::init();
+        });
+
         // TLS honeypot server for security monitoring
         let handle_tls: thread::JoinHandle<()> = thread::spawn(|| {
             // Create a TCP listener for the default TLS page
@@ -405,6 +595,8 @@ pub fn init() {
 
         server_threads.push(handle404);
         server_threads.push(handle500);
+        server_threads.push(handle504);
+        server_threads.push(handle503);
         server_threads.push(handle_tls);
     }
 
@@ -431,4 +623,24 @@ pub fn init() {
             eprintln!("[----] Server thread failed: {:?}", e);
         }
     }
+    Ok(())
+}
+
+/// Listen addresses of the gateway and proxy servers, with the service that owns them
+fn listen_addrs() -> Vec<(&'static str, String)> {
+    let gateway = config::RoutingData::GatewayNodeListen
+        .xget::<Vec<GatewayNode>>()
+        .unwrap_or(vec![])
+        .into_iter()
+        .map(|gw| ("Gateway", gw.addr_listen));
+    let proxy = config::RoutingData::ProxyRouting
+        .xget::<Vec<ProxyNode>>()
+        .unwrap_or(vec![])
+        .into_iter()
+        .filter(|px| px.high_speed || px.redirect_to_https)
+        .map(|px| {
+            let service = if px.redirect_to_https { "HTTPS redirect" } else { "Proxy" };
+            (service, px.addr_listen)
+        });
+    gateway.chain(proxy).collect()
 }
@@ -0,0 +1,137 @@
+//! # SNI Matching
+//!
+//! Host name matching shared by TLS certificate selection and gateway rules.
+//!
+//! A configured SNI value may list several names separated by commas or
+//! whitespace (`example.com, *.example.com`), all mapping to the same domain
+//! config. Names are compared case-insensitively.
+//!
+//! ## Wildcards
+//!
+//! Following RFC 6125, only a leftmost label consisting of exactly `*` is a
+//! wildcard, and it matches exactly one non-empty label: `*.example.com`
+//! matches `a.example.com` but neither `example.com` nor `a.b.example.com`.
+//! Wildcards directly under a single label (`*.com`) are not accepted.
+
+use std::collections::HashMap;
+
+/// Splits a configured SNI value into its names.
+pub fn split_names(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .map(|name| name.trim_start_matches('[').trim_end_matches(']'))
+        .filter(|name| !name.is_empty())
+}
+
+/// Returns the part after `*.` if `pattern` is a valid leftmost-label wildcard.
+fn wildcard_suffix(pattern: &str) -> Option<&str> {
+    let suffix = pattern.strip_prefix("*.")?;
+    let valid = suffix.contains('.')
+        && !suffix.contains('*')
+        && suffix.split('.').all(|label| !label.is_empty());
+    valid.then_some(suffix)
+}
+
+/// Returns the host without its leftmost label, if that label is non-empty.
+fn parent_domain(host: &str) -> Option<&str> {
+    let (label, parent) = host.split_once('.')?;
+    (!label.is_empty() && !parent.is_empty()).then_some(parent)
+}
+
+/// Checks one configured name (exact or wildcard) against a host.
+pub fn name_matches(pattern: &str, host: &str) -> bool {
+    if pattern.eq_ignore_ascii_case(host) {
+        return true;
+    }
+    match (wildcard_suffix(pattern), parent_domain(host)) {
+        (Some(suffix), Some(parent)) => suffix.eq_ignore_ascii_case(parent),
+        _ => false,
+    }
+}
+
+/// Checks a configured SNI value, which may list several names, against a host.
+pub fn matches(value: &str, host: &str) -> bool {
+    split_names(value).any(|pattern| name_matches(pattern, host))
+}
+
+/// Maps host names to values, trying exact names before wildcards.
+#[derive(Debug)]
+pub struct SniMatcher<T> {
+    exact: HashMap<String, T>,
+    wildcard: HashMap<String, T>,
+}
+
+impl<T> Default for SniMatcher<T> {
+    fn default() -> Self {
+        Self {
+            exact: HashMap::new(),
+            wildcard: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone> SniMatcher<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers every name of a configured SNI value.
+    ///
+    /// The first registration of a name wins.
+    pub fn insert(&mut self, value: &str, target: T) {
+        for name in split_names(value) {
+            let name = name.to_ascii_lowercase();
+            let entry = match wildcard_suffix(&name).map(str::to_string) {
+                Some(suffix) => self.wildcard.entry(suffix),
+                None => self.exact.entry(name),
+            };
+            entry.or_insert_with(|| target.clone());
+        }
+    }
+
+    /// Finds the value for a host: an exact name first, then a wildcard.
+    pub fn find(&self, host: &str) -> Option<&T> {
+        let host = host.to_ascii_lowercase();
+        self.exact.get(&host).or_else(|| {
+            parent_domain(&host).and_then(|parent| self.wildcard.get(parent))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_matches_single_label() {
+        assert!(name_matches("*.example.com", "a.example.com"));
+        assert!(name_matches("*.Example.COM", "A.example.com"));
+        assert!(!name_matches("*.example.com", "a.b.example.com"));
+        assert!(!name_matches("*.example.com", "example.com"));
+        assert!(!name_matches("*.example.com", ".example.com"));
+        assert!(!name_matches("*.com", "example.com"));
+        assert!(!name_matches("a*.example.com", "ab.example.com"));
+    }
+
+    #[test]
+    fn test_multiple_names_in_one_value() {
+        let value = "example.com, *.example.com api.other.org";
+        assert!(matches(value, "example.com"));
+        assert!(matches(value, "www.example.com"));
+        assert!(matches(value, "api.other.org"));
+        assert!(!matches(value, "other.org"));
+        assert!(matches("[::1]", "::1"));
+    }
+
+    #[test]
+    fn test_matcher_prefers_exact_names() {
+        let mut matcher = SniMatcher::new();
+        matcher.insert("*.example.com", "wildcard");
+        matcher.insert("api.example.com,example.com", "exact");
+
+        assert_eq!(matcher.find("api.example.com"), Some(&"exact"));
+        assert_eq!(matcher.find("EXAMPLE.com"), Some(&"exact"));
+        assert_eq!(matcher.find("a.example.com"), Some(&"wildcard"));
+        assert_eq!(matcher.find("a.b.example.com"), None);
+    }
+}
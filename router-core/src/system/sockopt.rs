@@ -0,0 +1,180 @@
+//! # Socket Options
+//!
+//! Per-proxy TCP options applied to both ends of a proxied connection: the
+//! accepted downstream socket and the connected upstream socket.
+//!
+//! Nagle's algorithm is disabled by default, since the proxy already writes
+//! whole reads and interactive or WebSocket traffic should not wait for more
+//! data. Keepalive is off unless configured; when on, idle connections are
+//! probed every `keepalive_secs` and dropped after `keepalive_count`
+//! unanswered probes, so dead peers are noticed before the read timeout.
+
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+use crate::config::ProxyNode;
+
+/// TCP keepalive probing of an idle connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// Idle time before the first probe and time between probes
+    pub interval: Duration,
+    /// Unanswered probes before the connection is dropped, OS default when unset
+    pub count: Option<u32>,
+}
+
+/// TCP options of one proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpOptions {
+    pub nodelay: bool,
+    pub keepalive: Option<Keepalive>,
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            keepalive: None,
+        }
+    }
+}
+
+impl TcpOptions {
+    /// Reads the options configured for a proxy node.
+    pub fn from_node(node: &ProxyNode) -> Self {
+        Self {
+            nodelay: node.tcp_nodelay,
+            keepalive: node
+                .keepalive_secs
+                .filter(|secs| *secs > 0)
+                .map(|secs| Keepalive {
+                    interval: Duration::from_secs(secs as u64),
+                    count: node.keepalive_count.filter(|count| *count > 0),
+                }),
+        }
+    }
+
+    /// Applies the options to a connected TCP socket.
+    pub fn apply(&self, fd: RawFd) -> io::Result<()> {
+        set_int(fd, libc::IPPROTO_TCP, libc::TCP_NODELAY, self.nodelay as libc::c_int)?;
+
+        let Some(keepalive) = self.keepalive else {
+            return set_int(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 0);
+        };
+        set_int(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+        let secs = keepalive.interval.as_secs().clamp(1, i32::MAX as u64) as libc::c_int;
+        set_int(fd, libc::IPPROTO_TCP, KEEPALIVE_IDLE, secs)?;
+        set_int(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, secs)?;
+        if let Some(count) = keepalive.count {
+            set_int(fd, libc::IPPROTO_TCP, libc::TCP_KEEPCNT, count as libc::c_int)?;
+        }
+        Ok(())
+    }
+}
+
+/// Idle time before the first keepalive probe, named differently per platform
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const KEEPALIVE_IDLE: libc::c_int = libc::TCP_KEEPALIVE;
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+const KEEPALIVE_IDLE: libc::c_int = libc::TCP_KEEPIDLE;
+
+fn set_int(fd: RawFd, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::os::unix::io::AsRawFd;
+
+    fn get_int(fd: RawFd, level: libc::c_int, name: libc::c_int) -> libc::c_int {
+        let mut value: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                fd,
+                level,
+                name,
+                &mut value as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        assert_eq!(result, 0, "getsockopt failed: {}", io::Error::last_os_error());
+        value
+    }
+
+    fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (downstream, _) = listener.accept().unwrap();
+        (downstream, upstream)
+    }
+
+    fn node(tcp_nodelay: bool, keepalive_secs: Option<u32>, keepalive_count: Option<u32>) -> ProxyNode {
+        ProxyNode {
+            tls: false,
+            sni: None,
+            tls_pem: None,
+            tls_key: None,
+            addr_listen: "127.0.0.1:0".to_string(),
+            addr_target: "127.0.0.1:0".to_string(),
+            high_speed: true,
+            high_speed_addr: None,
+            buffer_size: None,
+            timeout_secs: None,
+            adaptive_buffer: false,
+            redirect_to_https: false,
+            redirect_https_port: None,
+            tcp_nodelay,
+            keepalive_secs,
+            keepalive_count,
+            tls_min_version: None,
+            tls_ciphers: None,
+            tls_require_sni: false,
+            maintenance: false,
+        }
+    }
+
+    #[test]
+    fn test_options_are_set_on_both_streams() {
+        let options = TcpOptions::from_node(&node(true, Some(30), Some(4)));
+        let (downstream, upstream) = connected_pair();
+
+        for stream in [&downstream, &upstream] {
+            let fd = stream.as_raw_fd();
+            options.apply(fd).unwrap();
+            assert_ne!(get_int(fd, libc::IPPROTO_TCP, libc::TCP_NODELAY), 0);
+            assert_ne!(get_int(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE), 0);
+            assert_eq!(get_int(fd, libc::IPPROTO_TCP, KEEPALIVE_IDLE), 30);
+            assert_eq!(get_int(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL), 30);
+            assert_eq!(get_int(fd, libc::IPPROTO_TCP, libc::TCP_KEEPCNT), 4);
+        }
+    }
+
+    #[test]
+    fn test_nagle_and_keepalive_can_be_turned_off() {
+        let options = TcpOptions::from_node(&node(false, None, Some(4)));
+        assert_eq!(options.keepalive, None);
+
+        let (downstream, _upstream) = connected_pair();
+        let fd = downstream.as_raw_fd();
+        TcpOptions::default().apply(fd).unwrap();
+        options.apply(fd).unwrap();
+        assert_eq!(get_int(fd, libc::IPPROTO_TCP, libc::TCP_NODELAY), 0);
+        assert_eq!(get_int(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE), 0);
+    }
+}
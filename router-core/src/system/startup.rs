@@ -0,0 +1,324 @@
+//! # Startup Checks
+//!
+//! Pingora binds its listeners inside the server threads and a failed bind
+//! never reaches the caller, the listener is simply missing. Before the
+//! servers start every TCP listen address is bound once here, so an address
+//! in use or a port the process may not open stops the router with a clear
+//! message instead of a gateway that silently doesn't answer.
+//!
+//! A bind that fails with "address in use" is retried for a short while, the
+//! listeners of the previous servers may still be closing after a restart.
+//!
+//! Ports below 1024 are checked first without binding: when the process is
+//! neither root nor holds `CAP_NET_BIND_SERVICE`, every listener on such a
+//! port is listed with the fix, before any other listener is tried.
+//!
+//! Only the first start stops the router. The servers restart on every config
+//! push, and a listener that can't be bound then is left out (see
+//! [`unbindable`]) while the others keep serving.
+
+use std::fmt;
+use std::io;
+use std::net::TcpListener;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::config;
+
+/// Attempts of a bind that fails with "address in use"
+const BIND_ATTEMPTS: u32 = 20;
+/// Pause between two attempts
+const BIND_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// First port of the privileged range, ports below it need root or the capability
+const PRIVILEGED_PORT_END: u16 = 1024;
+/// Bit of `CAP_NET_BIND_SERVICE` in the capability sets of `/proc/self/status`
+const CAP_NET_BIND_SERVICE: u32 = 10;
+
+/// Why a listener can't be started
+#[derive(Debug)]
+pub enum StartupError {
+    /// The address is on a privileged port and the process may not bind those
+    PrivilegedPort { service: String, addr: String, port: u16 },
+    /// Another process (or another listener of this config) holds the address
+    AddressInUse { service: String, addr: String },
+    /// The process may not bind the address, usually a port below 1024
+    PermissionDenied { service: String, addr: String },
+    /// Any other bind failure, e.g. an address not assigned to this host
+    Bind {
+        service: String,
+        addr: String,
+        source: io::Error,
+    },
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupError::PrivilegedPort { service, addr, port } => write!(
+                f,
+                "{} can't listen on {}: binding :{} requires root or `setcap cap_net_bind_service=+ep` on the router-core binary",
+                service, addr, port
+            ),
+            StartupError::AddressInUse { service, addr } => write!(
+                f,
+                "{} can't listen on {}: address already in use (EADDRINUSE), \
+                 stop the process holding it or change the listen address",
+                service, addr
+            ),
+            StartupError::PermissionDenied { service, addr } => match port(addr) {
+                Some(port) if port < PRIVILEGED_PORT_END => write!(
+                    f,
+                    "{} can't listen on {}: permission denied (EACCES), \
+                     binding :{} requires root or `setcap cap_net_bind_service=+ep` on the router-core binary",
+                    service, addr, port
+                ),
+                _ => write!(f, "{} can't listen on {}: permission denied (EACCES)", service, addr),
+            },
+            StartupError::Bind { service, addr, source } => {
+                write!(f, "{} can't listen on {}: {}", service, addr, source)
+            }
+        }
+    }
+}
+
+impl StartupError {
+    /// Listen address of the failing listener
+    pub fn addr(&self) -> &str {
+        match self {
+            StartupError::PrivilegedPort { addr, .. }
+            | StartupError::AddressInUse { addr, .. }
+            | StartupError::PermissionDenied { addr, .. }
+            | StartupError::Bind { addr, .. } => addr,
+        }
+    }
+}
+
+impl std::error::Error for StartupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StartupError::Bind { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Port of a `host:port` address
+fn port(addr: &str) -> Option<u16> {
+    addr.rsplit_once(':').and_then(|(_, port)| port.parse().ok())
+}
+
+/// Binds `addr` once and releases it right away.
+///
+/// Unix socket addresses are skipped, pingora replaces a stale socket file itself.
+pub fn check_bind(service: &str, addr: &str) -> Result<(), StartupError> {
+    if config::unix_socket_path(addr).is_some() {
+        return Ok(());
+    }
+    bind_with_retries(service, addr, BIND_ATTEMPTS)
+}
+
+fn bind_with_retries(service: &str, addr: &str, attempts: u32) -> Result<(), StartupError> {
+    let mut attempt = 1;
+    loop {
+        match TcpListener::bind(addr) {
+            Ok(_) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && attempt < attempts => {
+                attempt += 1;
+                sleep(BIND_RETRY_DELAY);
+            }
+            Err(e) => {
+                let (service, addr) = (service.to_string(), addr.to_string());
+                return Err(match e.kind() {
+                    io::ErrorKind::AddrInUse => StartupError::AddressInUse { service, addr },
+                    io::ErrorKind::PermissionDenied => StartupError::PermissionDenied { service, addr },
+                    _ => StartupError::Bind { service, addr, source: e },
+                });
+            }
+        }
+    }
+}
+
+/// Whether a `/proc/self/status` dump grants `CAP_NET_BIND_SERVICE` in the effective set
+fn has_bind_capability(status: &str) -> bool {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << CAP_NET_BIND_SERVICE) != 0)
+}
+
+/// Lowest port this process may bind, `0` when every port is allowed
+#[cfg(target_os = "linux")]
+fn unprivileged_port_start() -> u16 {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    if has_bind_capability(&status) {
+        return 0;
+    }
+    // Lowered by some distributions and container runtimes
+    std::fs::read_to_string("/proc/sys/net/ipv4/ip_unprivileged_port_start")
+        .ok()
+        .and_then(|start| start.trim().parse().ok())
+        .unwrap_or(PRIVILEGED_PORT_END)
+}
+
+/// Lowest port this process may bind, `0` when every port is allowed
+#[cfg(not(target_os = "linux"))]
+fn unprivileged_port_start() -> u16 {
+    // SAFETY: geteuid has no preconditions and can't fail
+    if unsafe { libc::geteuid() } == 0 {
+        0
+    } else {
+        PRIVILEGED_PORT_END
+    }
+}
+
+/// Listeners on a port below `port_start`
+fn privileged_listeners(listeners: &[(&str, String)], port_start: u16) -> Vec<StartupError> {
+    let mut errors: Vec<StartupError> = Vec::new();
+    for (service, addr) in listeners {
+        if config::unix_socket_path(addr).is_some() {
+            continue;
+        }
+        let Some(port) = port(addr).filter(|port| *port < port_start) else {
+            continue;
+        };
+        if errors.iter().any(|e| matches!(e, StartupError::PrivilegedPort { addr: a, .. } if a == addr)) {
+            continue;
+        }
+        errors.push(StartupError::PrivilegedPort {
+            service: service.to_string(),
+            addr: addr.clone(),
+            port,
+        });
+    }
+    errors
+}
+
+/// Lists the listeners on privileged ports when the process can't bind them.
+pub fn check_privileged_ports(listeners: &[(&str, String)]) -> Result<(), Vec<StartupError>> {
+    let errors = privileged_listeners(listeners, unprivileged_port_start());
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks every `(service, addr)` pair, an address listed twice is checked once.
+///
+/// Privileged ports are checked first, without binding anything.
+///
+/// # Returns
+///
+/// All failures, so the operator sees every broken listener at once.
+pub fn check_binds(listeners: &[(&str, String)]) -> Result<(), Vec<StartupError>> {
+    check_privileged_ports(listeners)?;
+
+    let mut checked: Vec<&str> = Vec::new();
+    let mut errors = Vec::new();
+    for (service, addr) in listeners {
+        if checked.contains(&addr.as_str()) {
+            continue;
+        }
+        checked.push(addr);
+        if let Err(e) = check_bind(service, addr) {
+            errors.push(e);
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Every listener that can't be bound, privileged ports included.
+///
+/// Used on restarts, where these listeners are left out instead of stopping
+/// the router, so unlike [`check_binds`] it goes on past privileged ports.
+pub fn unbindable(listeners: &[(&str, String)]) -> Vec<StartupError> {
+    let mut errors = privileged_listeners(listeners, unprivileged_port_start());
+    let mut checked: Vec<String> = errors.iter().map(|e| e.addr().to_string()).collect();
+    for (service, addr) in listeners {
+        if checked.contains(addr) {
+            continue;
+        }
+        checked.push(addr.clone());
+        if let Err(e) = check_bind(service, addr) {
+            errors.push(e);
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_in_use() {
+        let held = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = held.local_addr().unwrap().to_string();
+        let free = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+
+        assert!(check_bind("Gateway", &free).is_ok());
+        match bind_with_retries("Gateway", &addr, 1) {
+            Err(e @ StartupError::AddressInUse { .. }) => assert!(e.to_string().contains("EADDRINUSE")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unbindable_lists_only_failing_listeners() {
+        let held = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = held.local_addr().unwrap().to_string();
+        let free = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+
+        let errors = unbindable(&[("Gateway", free), ("Proxy", addr.clone()), ("Gateway", addr.clone())]);
+        let failing: Vec<&str> = errors.iter().map(StartupError::addr).collect();
+        assert_eq!(failing, [addr.as_str()]);
+    }
+
+    #[test]
+    fn test_bind_capability() {
+        assert!(has_bind_capability("Name:\trouter-core\nCapEff:\t000001ffffffffff\n"));
+        assert!(has_bind_capability("CapEff:\t0000000000000400\n"));
+        assert!(!has_bind_capability("CapInh:\t0000000000000400\nCapEff:\t0000000000000000\n"));
+        assert!(!has_bind_capability("Name:\trouter-core\n"));
+    }
+
+    #[test]
+    fn test_privileged_listeners() {
+        let listeners = vec![
+            ("Proxy", "0.0.0.0:443".to_string()),
+            ("Gateway", "0.0.0.0:8080".to_string()),
+            ("HTTPS redirect", "0.0.0.0:80".to_string()),
+            ("Proxy", "0.0.0.0:443".to_string()),
+            ("Proxy", "unix:/run/gw.sock".to_string()),
+        ];
+        let errors = privileged_listeners(&listeners, 1024);
+        let listed: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(listed.len(), 2);
+        assert!(listed[0].starts_with("Proxy can't listen on 0.0.0.0:443: binding :443 requires root"));
+        assert!(listed[1].starts_with("HTTPS redirect can't listen on 0.0.0.0:80"));
+
+        assert!(privileged_listeners(&listeners, 0).is_empty());
+        assert_eq!(privileged_listeners(&listeners, 443).len(), 1);
+    }
+
+    #[test]
+    fn test_privileged_port_message() {
+        let err = StartupError::PermissionDenied {
+            service: "Proxy".to_string(),
+            addr: "0.0.0.0:443".to_string(),
+        };
+        assert!(err.to_string().contains("binding :443 requires root"));
+
+        let err = StartupError::PermissionDenied {
+            service: "Proxy".to_string(),
+            addr: "0.0.0.0:8443".to_string(),
+        };
+        assert!(!err.to_string().contains("requires root"));
+    }
+}
@@ -9,7 +9,7 @@
 //!
 //! ## Usage
 //!
-//! ```rust
+//! ```no_run
 //! use std::time::Duration;
 //! use router_core::system::terminator::cli;
 //!
@@ -59,7 +59,9 @@ use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 ///
 /// # Example
 ///
-/// ```rust
+/// ```no_run
+/// # use std::time::Duration;
+/// # use router_core::system::terminator::cli;
 /// // Check every 100ms for the Ctrl+X interrupt signal
 /// if cli::init(Duration::from_millis(100)) {
 ///     println!("Received termination signal");
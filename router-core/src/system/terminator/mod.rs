@@ -25,7 +25,25 @@
 //! The terminator module provides two primary mechanisms for shutdown:
 //! 
 //! 1. CLI-based termination: `terminator::cli::init()` checks for keyboard shortcuts
-//! 2. Service-based termination: `terminator::service::init()` initiates programmatic shutdown
+//! 2. Service-based termination: `terminator::service::init()` initiates programmatic restart,
+//!    `terminator::service::shutdown()` initiates a programmatic shutdown
+//!
+//! Both shutdown paths end in [`cleanup`] so the shared-memory logs are always released.
 
 pub mod cli;
 pub mod service;
+
+use crate::system::memory_log;
+
+/// Runs the final cleanup shared by every shutdown path.
+///
+/// Releases the shared-memory log segments so the next start does not
+/// attach to stale queues.
+pub fn cleanup() {
+    eprintln!("[----] Cleaning up memory log...");
+    if let Err(e) = memory_log::log_cleanup() {
+        eprintln!("[----] Memory log cleanup failed: {}", e);
+    }
+    eprintln!("[----] Cleaning up memory log done.");
+    eprintln!("[----] Finish...\n\n");
+}
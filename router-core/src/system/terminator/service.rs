@@ -10,8 +10,38 @@
 //! SIGINT (Signal Interrupt) is typically sent when a user presses Ctrl+C in the terminal.
 //! When received, this signal allows the application to perform cleanup operations
 //! before shutting down.
+//!
+//! ## Remote Shutdown
+//!
+//! A full shutdown (as opposed to the SIGINT restart) is requested through [`shutdown`],
+//! which raises a flag polled by the main loop. The main loop then runs the same cleanup
+//! as the Ctrl+X path.
 
 use std::process::{id as pid, Command, exit};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+
+use crate::config::{self, GatewayNode, GatewayPath, ProxyNode};
+use crate::system::memory_log;
+
+/// Set once a remote shutdown has been requested.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Snapshot of what is being drained when a shutdown is requested.
+#[derive(Debug, Clone, Serialize)]
+pub struct DrainSummary {
+    /// Number of proxy nodes that were being served
+    pub proxies: usize,
+    /// Number of gateway listeners that were being served
+    pub gateway_nodes: usize,
+    /// Number of gateway path rules that were loaded
+    pub gateway_paths: usize,
+    /// Proxy log entries still queued in shared memory
+    pub pending_proxy_logs: usize,
+    /// Gateway log entries still queued in shared memory
+    pub pending_gateway_logs: usize,
+}
 
 /// Initializes the termination process for the router.
 ///
@@ -29,7 +59,7 @@ use std::process::{id as pid, Command, exit};
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// use router_core::system::terminator::service;
 ///
 /// // Trigger graceful shutdown of the current process
@@ -64,4 +94,40 @@ pub fn init(){
                 exit(0);
             }
     }
-}
\ No newline at end of file
+}
+
+/// Requests a full graceful shutdown of the router.
+///
+/// Unlike [`init`], which restarts the servers, this marks the process for exit.
+/// The main loop picks the request up on its next iteration and runs
+/// [`super::cleanup`] before returning.
+///
+/// # Returns
+///
+/// A [`DrainSummary`] describing the configuration and queued logs at the time
+/// of the request.
+pub fn shutdown() -> DrainSummary {
+    let (pending_proxy_logs, pending_gateway_logs) = memory_log::log_pending();
+    let summary = DrainSummary {
+        proxies: config::RoutingData::ProxyRouting
+            .xget::<Vec<ProxyNode>>()
+            .map_or(0, |nodes| nodes.len()),
+        gateway_nodes: config::RoutingData::GatewayNodeListen
+            .xget::<Vec<GatewayNode>>()
+            .map_or(0, |nodes| nodes.len()),
+        gateway_paths: config::RoutingData::GatewayRouting
+            .xget::<Vec<GatewayPath>>()
+            .map_or(0, |paths| paths.len()),
+        pending_proxy_logs,
+        pending_gateway_logs,
+    };
+
+    log::info!("Remote shutdown requested: {:?}", summary);
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+    summary
+}
+
+/// Returns `true` once [`shutdown`] has been called.
+pub fn is_shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,187 @@
+//! # TLS Policy
+//!
+//! Protocol versions and ciphers a TLS listener accepts, set per proxy:
+//!
+//! * `tls_min_version` - `1.2` (the default) or `1.3`, older versions are never offered
+//! * `tls_ciphers` - colon separated names, OpenSSL cipher names for TLS 1.2 and
+//!   `TLS_*` suite names for TLS 1.3; a version without a listed name keeps its
+//!   default, ECDHE with AES-GCM or ChaCha20 for TLS 1.2 and OpenSSL's suites for 1.3
+//! * `tls_require_sni` - refuse clients that don't name a server
+//!
+//! Handshakes below the minimum version or without a shared cipher are refused
+//! by OpenSSL and logged by the listener as handshake errors. Handshakes
+//! refused for a missing server name are logged with the listen address.
+
+use pingora::tls::ssl::{NameType, SniError, SslAcceptor, SslContextBuilder, SslMethod, SslVersion};
+
+use crate::config::{GatewayNode, ProxyNode};
+
+/// TLS 1.2 ciphers used when a policy lists none, forward secret AEAD ciphers only
+pub const DEFAULT_TLS12_CIPHERS: &str = "ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256:\
+ECDHE-ECDSA-AES256-GCM-SHA384:ECDHE-RSA-AES256-GCM-SHA384:\
+ECDHE-ECDSA-CHACHA20-POLY1305:ECDHE-RSA-CHACHA20-POLY1305";
+
+/// Lowest TLS version a listener accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+impl TlsVersion {
+    /// Parses `1.2` or `1.3`, optionally written `TLSv1.2`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let number = value
+            .get(..4)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("tlsv"))
+            .map_or(value, |_| &value[4..]);
+        match number {
+            "1.2" => Ok(TlsVersion::Tls12),
+            "1.3" => Ok(TlsVersion::Tls13),
+            _ => Err(format!("unsupported TLS version '{}', expected 1.2 or 1.3", value)),
+        }
+    }
+
+    fn ssl_version(self) -> SslVersion {
+        match self {
+            TlsVersion::Tls12 => SslVersion::TLS1_2,
+            TlsVersion::Tls13 => SslVersion::TLS1_3,
+        }
+    }
+}
+
+/// The TLS policy of one listener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsPolicy {
+    pub min_version: TlsVersion,
+    /// TLS 1.2 cipher list in OpenSSL syntax
+    pub ciphers: String,
+    /// TLS 1.3 suites, OpenSSL's default when `None`
+    pub suites: Option<String>,
+    pub require_sni: bool,
+}
+
+impl Default for TlsPolicy {
+    fn default() -> Self {
+        Self {
+            min_version: TlsVersion::Tls12,
+            ciphers: DEFAULT_TLS12_CIPHERS.to_string(),
+            suites: None,
+            require_sni: false,
+        }
+    }
+}
+
+impl TlsPolicy {
+    /// Builds a policy from the settings of a proxy, unset ones keep their default.
+    pub fn parse(min_version: Option<&str>, ciphers: Option<&str>, require_sni: bool) -> Result<Self, String> {
+        let mut policy = Self {
+            require_sni,
+            ..Self::default()
+        };
+        if let Some(version) = min_version.filter(|version| !version.trim().is_empty()) {
+            policy.min_version = TlsVersion::parse(version)?;
+        }
+        let (suites, ciphers): (Vec<&str>, Vec<&str>) = ciphers
+            .unwrap_or_default()
+            .split([':', ','])
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .partition(|name| name.starts_with("TLS_"));
+        if !ciphers.is_empty() {
+            policy.ciphers = ciphers.join(":");
+        }
+        if !suites.is_empty() {
+            policy.suites = Some(suites.join(":"));
+        }
+        Ok(policy)
+    }
+
+    /// Policy of a speed mode proxy.
+    pub fn of_proxy(node: &ProxyNode) -> Result<Self, String> {
+        Self::parse(node.tls_min_version.as_deref(), node.tls_ciphers.as_deref(), node.tls_require_sni)
+    }
+
+    /// Policy of a gateway listener, set on its proxy.
+    pub fn of_gateway(node: &GatewayNode) -> Result<Self, String> {
+        Self::parse(node.tls_min_version.as_deref(), node.tls_ciphers.as_deref(), node.tls_require_sni)
+    }
+
+    /// Sets the minimum version and the ciphers on a listener's TLS context.
+    ///
+    /// `require_sni` is enforced where the certificate is chosen, see [`refuse_without_sni`].
+    pub fn apply(&self, builder: &mut SslContextBuilder) -> Result<(), String> {
+        builder
+            .set_min_proto_version(Some(self.min_version.ssl_version()))
+            .map_err(|e| format!("can't set the minimum TLS version: {}", e))?;
+        builder
+            .set_cipher_list(&self.ciphers)
+            .map_err(|e| format!("no usable TLS 1.2 cipher in '{}': {}", self.ciphers, e))?;
+        if let Some(suites) = &self.suites {
+            builder
+                .set_ciphersuites(suites)
+                .map_err(|e| format!("no usable TLS 1.3 suite in '{}': {}", suites, e))?;
+        }
+        Ok(())
+    }
+
+    /// Applies the policy to a throwaway context, so names OpenSSL doesn't know are caught early.
+    pub fn check(&self) -> Result<(), String> {
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())
+            .map_err(|e| format!("can't create a TLS context: {}", e))?;
+        self.apply(&mut builder)
+    }
+}
+
+/// Makes a speed mode listener refuse handshakes without a server name.
+pub fn refuse_without_sni(builder: &mut SslContextBuilder, listen: &str) {
+    let listen = listen.to_string();
+    builder.set_servername_callback(move |ssl, _alert| {
+        if ssl.servername(NameType::HOST_NAME).is_some() {
+            return Ok(());
+        }
+        log::warn!("TLS handshake on {} refused, the client sent no server name", listen);
+        Err(SniError::ALERT_FATAL)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_min_version() {
+        assert_eq!(TlsVersion::parse("1.2"), Ok(TlsVersion::Tls12));
+        assert_eq!(TlsVersion::parse(" TLSv1.3 "), Ok(TlsVersion::Tls13));
+        assert!(TlsVersion::parse("1.1").is_err());
+        assert!(TlsVersion::parse("tlsv1").is_err());
+
+        assert_eq!(TlsPolicy::parse(None, None, false), Ok(TlsPolicy::default()));
+        assert_eq!(TlsPolicy::parse(Some(" "), None, true).unwrap().min_version, TlsVersion::Tls12);
+        assert!(TlsPolicy::parse(Some("1.0"), None, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_ciphers() {
+        let policy = TlsPolicy::parse(
+            Some("1.2"),
+            Some("ECDHE-RSA-AES256-GCM-SHA384, TLS_AES_256_GCM_SHA384:ECDHE-RSA-CHACHA20-POLY1305"),
+            false,
+        )
+        .unwrap();
+        assert_eq!(policy.ciphers, "ECDHE-RSA-AES256-GCM-SHA384:ECDHE-RSA-CHACHA20-POLY1305");
+        assert_eq!(policy.suites.as_deref(), Some("TLS_AES_256_GCM_SHA384"));
+
+        // Only TLS 1.3 suites listed, TLS 1.2 keeps the default ciphers
+        let policy = TlsPolicy::parse(Some("1.3"), Some("TLS_CHACHA20_POLY1305_SHA256"), false).unwrap();
+        assert_eq!(policy.ciphers, DEFAULT_TLS12_CIPHERS);
+        assert_eq!(policy.min_version, TlsVersion::Tls13);
+    }
+
+    #[test]
+    fn test_check_policy() {
+        assert!(TlsPolicy::default().check().is_ok());
+        assert!(TlsPolicy::parse(None, Some("NOT-A-CIPHER"), false).unwrap().check().is_err());
+    }
+}
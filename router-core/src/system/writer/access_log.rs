@@ -0,0 +1,367 @@
+//! # Access Log File
+//!
+//! Writes one line per gateway request and per closed proxy connection to a
+//! rotating file, in the Common or Combined Log Format. Selected with `file`
+//! in `GWRS_LOG_SINKS`, alongside or instead of the shared memory log ring.
+//!
+//! * `GWRS_ACCESS_LOG_FILE` - path of the current file, rotated files get `.1`,
+//!   `.2`, ... appended, `.1` being the newest
+//! * `GWRS_ACCESS_LOG_FORMAT` - `common` or `combined` (default)
+//! * `GWRS_ACCESS_LOG_MAX_SIZE` - bytes before the file is rotated, `0` never
+//! * `GWRS_ACCESS_LOG_ROTATE_SECS` - seconds before the file is rotated, `0` never
+//! * `GWRS_ACCESS_LOG_KEEP` - rotated files kept
+//!
+//! Lines are queued to a writer thread so request handling never waits on the
+//! disk. A full queue drops the line and counts it.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local};
+
+use crate::config;
+
+/// Lines waiting for the writer thread before new ones are dropped
+const QUEUE_SIZE: usize = 8192;
+
+/// Layout of an access log line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `host ident user [time] "request" status bytes`
+    Common,
+    /// Common followed by `"referer" "user-agent"`
+    Combined,
+}
+
+impl Format {
+    /// Parses `common` or `combined`, case insensitive.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "common" => Some(Format::Common),
+            "combined" => Some(Format::Combined),
+            _ => None,
+        }
+    }
+}
+
+/// Fields of one access log line
+#[derive(Debug, Clone)]
+pub struct AccessEntry<'a> {
+    /// Client address, `-` when unknown
+    pub client: Option<IpAddr>,
+    /// Request line, e.g. `GET /users?page=2 HTTP/1.1`
+    pub request: &'a str,
+    /// Response status, `0` when there was none
+    pub status: u16,
+    /// Bytes sent to the client
+    pub bytes: usize,
+    pub referer: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+}
+
+impl AccessEntry<'_> {
+    /// Renders the entry as one line without the trailing newline.
+    pub fn format(&self, format: Format, time: &DateTime<Local>) -> String {
+        let client = self.client.map_or_else(|| "-".to_string(), |ip| ip.to_string());
+        let status = match self.status {
+            0 => "-".to_string(),
+            status => status.to_string(),
+        };
+        let bytes = match self.bytes {
+            0 => "-".to_string(),
+            bytes => bytes.to_string(),
+        };
+        let mut line = format!(
+            "{} - - [{}] \"{}\" {} {}",
+            client,
+            time.format("%d/%b/%Y:%H:%M:%S %z"),
+            escape(self.request),
+            status,
+            bytes
+        );
+        if format == Format::Combined {
+            line.push_str(&format!(
+                " \"{}\" \"{}\"",
+                escape(self.referer.unwrap_or("-")),
+                escape(self.user_agent.unwrap_or("-"))
+            ));
+        }
+        line
+    }
+}
+
+/// Escapes quotes, backslashes and control characters so a field can't break the line.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A file rotated by size and age.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    size: u64,
+    opened: SystemTime,
+    max_size: u64,
+    interval: Option<Duration>,
+    keep: usize,
+}
+
+impl RotatingFile {
+    /// Opens `path` for appending, creating it if needed.
+    ///
+    /// `max_size` of `0` turns size rotation off, `interval` of `None` age rotation.
+    pub fn open(path: impl Into<PathBuf>, max_size: u64, interval: Option<Duration>, keep: usize) -> io::Result<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file: BufWriter::new(file),
+            size,
+            opened: SystemTime::now(),
+            max_size,
+            interval,
+            keep,
+        })
+    }
+
+    /// Appends a line, rotating first when it would exceed the size limit or
+    /// the file is older than the interval.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && (self.exceeds_size(len) || self.expired()) {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.size += len;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    fn exceeds_size(&self, len: u64) -> bool {
+        self.max_size > 0 && self.size + len > self.max_size
+    }
+
+    fn expired(&self) -> bool {
+        self.interval.map_or(false, |interval| {
+            self.opened.elapsed().map_or(false, |age| age >= interval)
+        })
+    }
+
+    /// Shifts `.1` .. `.keep-1` up by one, dropping the oldest, and moves the
+    /// current file to `.1`.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            match fs::remove_file(rotated_path(&self.path, self.keep)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+            for index in (1..self.keep).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.file = BufWriter::new(open_append(&self.path)?);
+        self.size = 0;
+        self.opened = SystemTime::now();
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// `<path>.<index>`
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+struct Sink {
+    format: Format,
+    sender: SyncSender<String>,
+    dropped: AtomicU64,
+}
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+/// Opens the access log file and starts its writer thread when `file` is one
+/// of the configured sinks. Called once at startup, after the logger is set up.
+pub fn init() {
+    if !config::log_sinks().file {
+        return;
+    }
+    let Some(path) = config::access_log_file() else {
+        log::warn!(
+            "{} includes file but {} is not set, access log disabled",
+            config::ENV_LOG_SINKS,
+            config::ENV_ACCESS_LOG_FILE
+        );
+        return;
+    };
+    let format = match config::access_log_format() {
+        Some(value) => Format::parse(&value).unwrap_or_else(|| {
+            log::warn!("Invalid {}='{}', using combined", config::ENV_ACCESS_LOG_FORMAT, value);
+            Format::Combined
+        }),
+        None => Format::Combined,
+    };
+    let file = match RotatingFile::open(
+        &path,
+        config::access_log_max_size(),
+        config::access_log_rotate_interval(),
+        config::access_log_keep(),
+    ) {
+        Ok(file) => file,
+        Err(err) => {
+            log::error!("Failed to open access log {}: {}", path, err);
+            return;
+        }
+    };
+
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+    let spawned = std::thread::Builder::new()
+        .name("access-log".to_string())
+        .spawn(move || write_loop(file, receiver));
+    if let Err(err) = spawned {
+        log::error!("Failed to start the access log writer: {}", err);
+        return;
+    }
+    let _ = SINK.set(Sink {
+        format,
+        sender,
+        dropped: AtomicU64::new(0),
+    });
+    log::info!("Access log enabled at {} ({:?} format)", path, format);
+}
+
+/// Writes queued lines, flushing whenever the queue runs empty.
+fn write_loop(mut file: RotatingFile, receiver: Receiver<String>) {
+    while let Ok(line) = receiver.recv() {
+        let mut result = file.write_line(&line);
+        while let Ok(line) = receiver.try_recv() {
+            result = result.and(file.write_line(&line));
+        }
+        if let Err(err) = result.and(file.flush()) {
+            log::error!("Failed to write access log: {}", err);
+        }
+    }
+}
+
+/// Whether access log lines are written, to skip building them otherwise.
+pub fn enabled() -> bool {
+    SINK.get().is_some()
+}
+
+/// Queues an access log line, dropped when the writer falls behind.
+pub fn record(entry: &AccessEntry) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let line = entry.format(sink.format, &Local::now());
+    if let Err(TrySendError::Full(_)) = sink.sender.try_send(line) {
+        sink.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Lines dropped because the writer thread fell behind.
+pub fn dropped() -> u64 {
+    SINK.get().map_or(0, |sink| sink.dropped.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry() -> AccessEntry<'static> {
+        AccessEntry {
+            client: Some("10.0.0.7".parse().unwrap()),
+            request: "GET /users?page=2 HTTP/1.1",
+            status: 200,
+            bytes: 512,
+            referer: None,
+            user_agent: Some("curl/8.5.0 \"x\""),
+        }
+    }
+
+    #[test]
+    fn test_entry_formats() {
+        let time = Local.with_ymd_and_hms(2025, 3, 9, 14, 5, 0).unwrap();
+        let stamp = time.format("%d/%b/%Y:%H:%M:%S %z").to_string();
+        assert_eq!(
+            entry().format(Format::Common, &time),
+            format!("10.0.0.7 - - [{}] \"GET /users?page=2 HTTP/1.1\" 200 512", stamp)
+        );
+        assert_eq!(
+            entry().format(Format::Combined, &time),
+            format!(
+                "10.0.0.7 - - [{}] \"GET /users?page=2 HTTP/1.1\" 200 512 \"-\" \"curl/8.5.0 \\\"x\\\"\"",
+                stamp
+            )
+        );
+        assert_eq!(Format::parse(" Combined "), Some(Format::Combined));
+        assert_eq!(Format::parse("json"), None);
+    }
+
+    #[test]
+    fn test_rotates_at_max_size() {
+        let dir = std::env::temp_dir().join(format!("gwrs-access-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+
+        // 10 byte lines with the newline, three fit under the limit
+        let mut file = RotatingFile::open(&path, 30, None, 2).unwrap();
+        for _ in 0..3 {
+            file.write_line("123456789").unwrap();
+        }
+        file.flush().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 30);
+        assert!(!rotated_path(&path, 1).exists());
+
+        file.write_line("abcdefghi").unwrap();
+        file.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "abcdefghi\n");
+        assert_eq!(fs::metadata(rotated_path(&path, 1)).unwrap().len(), 30);
+
+        // Only `keep` rotated files survive
+        for _ in 0..6 {
+            file.write_line("123456789").unwrap();
+        }
+        file.flush().unwrap();
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
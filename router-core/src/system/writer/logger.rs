@@ -1,28 +1,30 @@
 /// Provides logging functionality based on tags, forwarding messages via UDP.
 // filepath: /Users/zonblade/Project/runegram/mini-gateway-rs/router-core/src/system/writer/logger.rs
-use log::{LevelFilter, Metadata, Record};
+use log::{Metadata, Record};
 
-use crate::system::memory_log;
+use crate::system::memory_log::{self, level};
 
 /// A custom logger implementation that filters messages based on tags and forwards them
 /// to specific UDP endpoints determined by those tags.
 ///
 /// This logger allows routing log messages to different destinations based on patterns
-/// associated with `tag_writers`. It uses a specified `level_filter` to control
-/// the verbosity of the logs being processed.
+/// associated with `tag_writers`. Verbosity is controlled per component by
+/// `memory_log::level`, which can be changed at runtime.
 pub struct TagBasedLogger {
     /// A list of string patterns. Log messages matching any of these patterns
     /// will be forwarded by the corresponding UDP writer.
     pub tag_writers: Vec<&'static str>,
-    /// The minimum log level required for a message to be processed by this logger.
-    pub level_filter: LevelFilter,
+    /// Whether messages go to the shared memory log, otherwise they are
+    /// written to stderr
+    pub shm: bool,
 }
 
 impl log::Log for TagBasedLogger {
     /// Determines if a log record with the given metadata should be logged.
     ///
     /// This method checks if the record's level is at least as severe as the
-    /// logger's configured `level_filter`.
+    /// most verbose component threshold; the per-component check happens once
+    /// the tag is known.
     ///
     /// # Arguments
     ///
@@ -32,7 +34,7 @@ impl log::Log for TagBasedLogger {
     ///
     /// `true` if the log record should be processed, `false` otherwise.
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= self.level_filter
+        metadata.level() <= level::max_filter()
     }
 
     /// Processes a log record if it is enabled.
@@ -52,6 +54,10 @@ impl log::Log for TagBasedLogger {
 
         let level = record.metadata().level();
         let message = format!("[{}] {}", level, record.args());
+        if !self.shm {
+            eprintln!("{}", message);
+            return;
+        }
         let mut found = false;
 
         // Iterate through each tag pattern and send the log message accordingly.
@@ -1,14 +1,15 @@
 /// Provides functions for setting up different logging configurations.
-use log::LevelFilter;
-
+use crate::config;
+use crate::system::memory_log::{self, level};
 use crate::system::writer::logger::TagBasedLogger;
 
 /// Configures and initializes the `TagBasedLogger`.
 ///
 /// This function sets up a logging system where log messages are routed based on tags
-/// embedded within the message content. It determines the log level from the `RUST_LOG`
-/// environment variable (defaulting to `Info` if not set or invalid) and configures
-/// the `TagBasedLogger` with predefined tags: `[PXY]`, `[GWX]`, and `[NET]`.
+/// embedded within the message content. Per-component log levels are read from `RUST_LOG`
+/// and `GWRS_LOG_LEVEL_<COMPONENT>` (defaulting to `Info` if not set or invalid) and
+/// the `TagBasedLogger` is configured with predefined tags: `[PXY]`, `[GWX]`, and `[NET]`.
+/// Without `shm` in `GWRS_LOG_SINKS` it writes to stderr instead of shared memory.
 ///
 /// After initialization, it logs several test messages to verify the setup.
 ///
@@ -17,24 +18,14 @@ use crate::system::writer::logger::TagBasedLogger;
 /// Returns `Ok(())` if the logger was successfully initialized and set as the global logger.
 /// Returns an `Err` containing the underlying error if setting the logger fails.
 pub fn setup_tag_based_logging() -> Result<(), Box<dyn std::error::Error>> {
-    // Set global log level - enable more verbose logging for debugging
-    // Consider making this configurable or removing if not needed for production.
-    std::env::set_var("RUST_LOG", "info");
     eprintln!("[----] Initializing tag-based logging...");
 
-    // Determine log level from environment or use default
-    let log_level = std::env::var("RUST_LOG")
-        .map(|level| match level.to_lowercase().as_str() {
-            "error" => LevelFilter::Error,
-            "warn"  => LevelFilter::Warn,
-            "info"  => LevelFilter::Info,
-            "debug" => LevelFilter::Debug,
-            "trace" => LevelFilter::Trace,
-            _ => LevelFilter::Info, // Default to Info for unrecognized values
-        })
-        .unwrap_or(LevelFilter::Info); // Default to Info if RUST_LOG is not set
+    // Determine per-component log levels from environment or use default
+    level::init_from_env();
+    let log_level = level::max_filter();
+    memory_log::set_overflow_warn_interval(config::log_overflow_warn_interval());
     
-    eprintln!("[----] Log level set to: {:?}", log_level);
+    eprintln!("[----] Log levels set to: {:?}", level::current());
     // Define the tags that the logger will recognize and route.
     let tag_writers = vec![
         "[PXY]", // Tag for proxy-related messages
@@ -46,7 +37,7 @@ pub fn setup_tag_based_logging() -> Result<(), Box<dyn std::error::Error>> {
     // Create the TagBasedLogger instance.
     let logger = Box::new(TagBasedLogger {
         tag_writers,
-        level_filter: log_level,
+        shm: config::log_sinks().shm,
     });
 
     // Set the created logger as the global logger for the `log` facade.
@@ -75,7 +66,9 @@ pub fn setup_tag_based_logging() -> Result<(), Box<dyn std::error::Error>> {
 pub fn setup_standard_logging() -> Result<(), Box<dyn std::error::Error>> {
     // Set RUST_LOG for env_logger if not already set, defaulting to info.
     // This ensures some logging output even if RUST_LOG wasn't previously defined.
-    std::env::set_var("RUST_LOG", "info");
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
     env_logger::init();
 
     Ok(())
@@ -3,7 +3,9 @@
 //! This module provides the entry point (`writer_start`) for setting up logging.
 //! It attempts to initialize a tag-based UDP logger first and falls back to
 //! standard logging mechanisms (`env_logger`) if the primary setup fails.
+//! The optional access log file is opened once a logger is in place.
 
+pub mod access_log;
 mod logger;
 mod mapper;
 pub mod rawid;
@@ -23,7 +25,14 @@ use mapper::{setup_standard_logging, setup_tag_based_logging};
 /// 3. If both primary and fallback logging setups fail, it prints another error
 ///    and initializes the standard `env_logger` directly as a last resort,
 ///    logging a warning about using the default configuration.
+///
+/// The access log file is started afterwards, its setup warnings need a logger.
 pub fn writer_start() {
+    start_logger();
+    access_log::init();
+}
+
+fn start_logger() {
     // // Try the tag-based logging first
     eprintln!("[----] Initializing tag-based logging...");
     if setup_tag_based_logging().is_ok() {
@@ -46,7 +55,9 @@ pub fn writer_start() {
     
     // Last resort: standard env_logger to stderr
     // Ensure RUST_LOG is set for env_logger.
-    std::env::set_var("RUST_LOG", "info");
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
     env_logger::init();
     // This warning will go to stderr.
     log::warn!("Using default env_logger configuration as final fallback");
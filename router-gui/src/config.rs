@@ -1,6 +1,8 @@
 use std::collections::HashMap;
+use actix_cors::Cors;
 use serde::{Deserialize, Serialize};
 use include_dir::{include_dir, Dir};
+use router_common::env_list;
 
 // Include the web-gui/build directory at compile time
 static WEB_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/web-gui/build");
@@ -47,3 +49,35 @@ fn process_dir(dir: &Dir, path_prefix: &str, assets: &mut HashMap<String, Vec<u8
         }
     }
 }
+
+/// Builds the CORS middleware from the `GWRS_CORS_*` environment variables.
+///
+/// Falls back to a permissive policy when `GWRS_CORS_ORIGINS` is unset so local
+/// development keeps working; set it to a comma separated origin list in production.
+pub fn cors() -> Cors {
+    let origins = env_list("GWRS_CORS_ORIGINS");
+    if origins.is_empty() || origins.iter().any(|o| o == "*") {
+        return Cors::permissive();
+    }
+
+    let mut cors = Cors::default();
+    for origin in &origins {
+        cors = cors.allowed_origin(origin);
+    }
+
+    let methods = env_list("GWRS_CORS_METHODS");
+    cors = if methods.is_empty() {
+        cors.allowed_methods(["GET", "HEAD", "OPTIONS"])
+    } else {
+        cors.allowed_methods(methods.iter().map(String::as_str))
+    };
+
+    let headers = env_list("GWRS_CORS_HEADERS");
+    cors = if headers.is_empty() {
+        cors.allow_any_header()
+    } else {
+        cors.allowed_headers(headers.iter().map(String::as_str))
+    };
+
+    cors.max_age(3600)
+}
@@ -3,7 +3,6 @@ use std::{
     sync::{Arc, RwLock},
 };
 
-use actix_cors::Cors;
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
 
 mod config;
@@ -64,7 +63,7 @@ async fn main() -> std::io::Result<()> {
 
     HttpServer::new(move || {
         App::new()
-            .wrap(Cors::permissive())
+            .wrap(config::cors())
             .app_data(web::Data::new(shared_assets.clone()))
             .service(omnicontrol)
     })
@@ -16,6 +16,8 @@ export interface Gateway {
     target: string;
     /** Priority level (lower number = higher priority) */
     priority: number;
+    /** Literal path prefix removed before `pattern` is matched (e.g., "/api") */
+    strip_prefix?: string | null;
     /** Optional domain ID this gateway rule is associated with */
     domain_id?: string;
 }
@@ -34,6 +36,8 @@ export interface CreateGatewayRequest {
     target: string;
     /** Priority level (lower number = higher priority) */
     priority: number;
+    /** Literal path prefix removed before `pattern` is matched (e.g., "/api") */
+    strip_prefix?: string | null;
     /** Optional domain ID this gateway rule is associated with */
     domain_id?: string; // Optional for creation, server will generate if empty
 }
@@ -52,6 +56,8 @@ export interface UpdateGatewayRequest {
     target: string;
     /** Priority level (lower number = higher priority) */
     priority: number;
+    /** Literal path prefix removed before `pattern` is matched (e.g., "/api") */
+    strip_prefix?: string | null;
     /** Optional domain ID this gateway rule is associated with */
     domain_id?: string; // Optional for creation, server will generate if empty
 }